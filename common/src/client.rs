@@ -0,0 +1,266 @@
+//! Typed client for calls between Mirage services
+//!
+//! A `ServiceClient` points at one downstream service. It sends JSON, decodes
+//! JSON responses into the caller's type and turns error statuses into the
+//! matching `Error` variant, so a 404 from the callee surfaces as
+//! `Error::NotFound`:
+//!
+//! ```ignore
+//! let storage = ServiceClient::new(&config.data_storage.url)?;
+//! let entity: Entity = storage.get_json(&format!("/api/v1/data/{}", id)).await?;
+//! ```
+//!
+//! Inside a handler, `for_request` gives a client that forwards the caller's
+//! bearer token and request id, so the callee authorizes the original user
+//! and logs under the same id. Trace headers for the current span are added
+//! to every request.
+
+use crate::error::{Error, Result};
+use crate::http::HttpClientOptions;
+use crate::telemetry::{self, RequestId, REQUEST_ID_HEADER};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{HttpMessage, HttpRequest};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Longest part of an error response quoted in the resulting `Error`
+const MAX_ERROR_BODY_LEN: usize = 512;
+
+#[derive(Clone)]
+pub struct ServiceClient {
+    client: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+    request_id: Option<String>,
+}
+
+impl ServiceClient {
+    /// Client for the service at `base_url` with the default timeout and TLS
+    /// policy
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_options(base_url, &HttpClientOptions::default())
+    }
+
+    pub fn with_options(base_url: &str, options: &HttpClientOptions) -> Result<Self> {
+        Ok(Self::with_client(options.build_client()?, base_url))
+    }
+
+    /// Wraps an existing client, sharing its connection pool
+    pub fn with_client(client: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+            request_id: None,
+        }
+    }
+
+    /// Copy of the client that authenticates with `token`
+    pub fn with_token(&self, token: &str) -> Self {
+        Self {
+            token: Some(token.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of the client acting for `req`: its bearer token and request id
+    /// are passed on with every call
+    pub fn for_request(&self, req: &HttpRequest) -> Self {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string);
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|RequestId(id)| id.clone());
+
+        Self {
+            token: token.or_else(|| self.token.clone()),
+            request_id: request_id.or_else(|| self.request_id.clone()),
+            ..self.clone()
+        }
+    }
+
+    /// Request for `path` on the service, with auth, request id and trace
+    /// headers set. Send it with `send_json`.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(request_id) = &self.request_id {
+            request = request.header(REQUEST_ID_HEADER, request_id);
+        }
+        for (name, value) in telemetry::trace_headers() {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send_json(self.request(Method::GET, path)).await
+    }
+
+    pub async fn post_json<B, T>(&self, path: &str, body: &B) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        self.send_json(self.request(Method::POST, path).json(body))
+            .await
+    }
+
+    /// Sends `request` and decodes a successful response as JSON
+    pub async fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = request.send().await?;
+        let url = response.url().clone();
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let mut body = body.trim().to_string();
+            if body.len() > MAX_ERROR_BODY_LEN {
+                let mut end = MAX_ERROR_BODY_LEN;
+                while !body.is_char_boundary(end) {
+                    end -= 1;
+                }
+                body.truncate(end);
+            }
+            return Err(status_error(
+                status,
+                format!("{} returned {}: {}", url, status, body),
+            ));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| Error::Serialization(format!("Invalid response from {}: {}", url, e)))
+    }
+}
+
+/// Error for a call to another Mirage service that answered `status`. A
+/// failure inside the callee is an internal error of the platform rather
+/// than of an external API.
+fn status_error(status: StatusCode, message: String) -> Error {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Error::Validation(message),
+        StatusCode::UNAUTHORIZED => Error::Unauthorized(message),
+        StatusCode::FORBIDDEN => Error::Forbidden(message),
+        StatusCode::NOT_FOUND => Error::NotFound(message),
+        StatusCode::CONFLICT => Error::Conflict(message),
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited(message),
+        StatusCode::GATEWAY_TIMEOUT => Error::Timeout(message),
+        _ => Error::Internal(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Entity {
+        id: u32,
+        value: String,
+    }
+
+    async fn entity(req: HttpRequest, id: web::Path<u32>) -> HttpResponse {
+        let auth = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        match id.into_inner() {
+            404 => HttpResponse::NotFound().body("no such entity"),
+            500 => HttpResponse::InternalServerError().body("database unavailable"),
+            id => HttpResponse::Ok().json(Entity { id, value: auth }),
+        }
+    }
+
+    async fn create(entity: web::Json<Entity>) -> HttpResponse {
+        HttpResponse::Created().json(entity.into_inner())
+    }
+
+    /// Stand-in for a downstream service
+    fn mock_service() -> (String, ServerHandle) {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/entities/{id}", web::get().to(entity))
+                .route("/entities", web::post().to(create))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        (url, handle)
+    }
+
+    #[actix_web::test]
+    async fn test_success_is_decoded_and_carries_token() {
+        let (url, server) = mock_service();
+        let client = ServiceClient::new(&format!("{}/", url)).unwrap();
+
+        let entity: Entity = client
+            .with_token("caller-token")
+            .get_json("/entities/7")
+            .await
+            .unwrap();
+        assert_eq!(entity.id, 7);
+        assert_eq!(entity.value, "Bearer caller-token");
+
+        let sent = Entity {
+            id: 8,
+            value: "example.com".to_string(),
+        };
+        let created: Entity = client.post_json("/entities", &sent).await.unwrap();
+        assert_eq!(created, sent);
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_not_found_maps_to_not_found() {
+        let (url, server) = mock_service();
+        let client = ServiceClient::new(&url).unwrap();
+
+        let err = client
+            .get_json::<Entity>("/entities/404")
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, Error::NotFound(message) if message.contains("no such entity")));
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_server_error_maps_to_internal() {
+        let (url, server) = mock_service();
+        let client = ServiceClient::new(&url).unwrap();
+
+        let err = client
+            .get_json::<Entity>("/entities/500")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Internal(message) if message.contains("database unavailable"))
+        );
+
+        server.stop(false).await;
+    }
+}
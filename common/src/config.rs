@@ -4,10 +4,27 @@ use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::env;
 
+pub const DEFAULT_ACQUIRE_TIMEOUT_SECONDS: u64 = 10;
+pub const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 600;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
+    /// How long a query waits for a free connection before it fails
+    #[serde(default = "default_acquire_timeout_seconds")]
+    pub acquire_timeout_seconds: u64,
+    /// Connections idle for this long are closed; 0 keeps them open
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: u64,
+}
+
+fn default_acquire_timeout_seconds() -> u64 {
+    DEFAULT_ACQUIRE_TIMEOUT_SECONDS
+}
+
+fn default_idle_timeout_seconds() -> u64 {
+    DEFAULT_IDLE_TIMEOUT_SECONDS
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,11 +62,23 @@ impl ServiceConfig {
             .parse()
             .map_err(|_| Error::Config("Invalid DB_MAX_CONNECTIONS value".to_string()))?;
 
+        let acquire_timeout_seconds = env::var("DB_ACQUIRE_TIMEOUT_SECONDS")
+            .map(|value| value.parse())
+            .unwrap_or(Ok(DEFAULT_ACQUIRE_TIMEOUT_SECONDS))
+            .map_err(|_| Error::Config("Invalid DB_ACQUIRE_TIMEOUT_SECONDS value".to_string()))?;
+
+        let idle_timeout_seconds = env::var("DB_IDLE_TIMEOUT_SECONDS")
+            .map(|value| value.parse())
+            .unwrap_or(Ok(DEFAULT_IDLE_TIMEOUT_SECONDS))
+            .map_err(|_| Error::Config("Invalid DB_IDLE_TIMEOUT_SECONDS value".to_string()))?;
+
         Ok(ServiceConfig {
             server: ServerConfig { host, port },
             database: DatabaseConfig {
                 url: database_url,
                 max_connections,
+                acquire_timeout_seconds,
+                idle_timeout_seconds,
             },
             jwt_secret,
             log_level,
@@ -0,0 +1,342 @@
+//! Hot-reloading values from the configuration service
+//!
+//! The configuration service publishes a `ConfigChange` on `CHANGES_CHANNEL`
+//! each time a value is created, updated or rolled back. A `ConfigWatcher`
+//! holds one key's value for a service and swaps in each change as it
+//! arrives, so the service picks it up without a restart:
+//!
+//! ```ignore
+//! let max_depth = ConfigWatcher::new("scan", "max_depth", 3u32);
+//! let config = ServiceClient::new(&config.configuration_service_url)?;
+//! tokio::spawn(max_depth.clone().run(redis_client, config, shutdown.subscribe()));
+//! ...
+//! let depth = *max_depth.get();
+//! ```
+//!
+//! Pub/sub only reaches subscribers connected at the time, so the watcher
+//! loads the current value over HTTP each time it (re)subscribes. Changes
+//! carry the item's id and version as an etag; one that is not newer than
+//! the held value is ignored, whichever order they arrive in.
+//!
+//! Secret items are not watchable: the service serves them masked and does
+//! not publish their changes, so a watcher refuses them and keeps its value.
+
+use crate::client::ServiceClient;
+use crate::error::{Error, Result};
+use crate::shutdown::ShutdownSignal;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Redis channel configuration changes are published on
+pub const CHANGES_CHANNEL: &str = "mirage:config:changes";
+
+/// Wait before resubscribing after the Redis connection drops
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A configuration value as of one version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub id: Uuid,
+    pub namespace: String,
+    pub key: String,
+    pub version: i32,
+    pub value: Value,
+    /// Set when `value` is masked rather than the real one
+    #[serde(default)]
+    pub is_secret: bool,
+}
+
+impl ConfigChange {
+    /// Etag naming this version of the item
+    pub fn etag(&self) -> String {
+        format!("{}-{}", self.id, self.version)
+    }
+}
+
+struct Held<T> {
+    value: Arc<T>,
+    // None until the first change is applied
+    etag: Option<(Uuid, i32)>,
+}
+
+/// One configuration value, kept current from the configuration service
+pub struct ConfigWatcher<T> {
+    namespace: String,
+    key: String,
+    held: Arc<RwLock<Held<T>>>,
+}
+
+impl<T> Clone for ConfigWatcher<T> {
+    fn clone(&self) -> Self {
+        Self {
+            namespace: self.namespace.clone(),
+            key: self.key.clone(),
+            held: self.held.clone(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> ConfigWatcher<T> {
+    /// Watcher holding `initial` until the first change arrives
+    pub fn new(namespace: &str, key: &str, initial: T) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+            held: Arc::new(RwLock::new(Held {
+                value: Arc::new(initial),
+                etag: None,
+            })),
+        }
+    }
+
+    /// The current value. A caller keeps the snapshot it got even if a
+    /// change is swapped in meanwhile.
+    pub fn get(&self) -> Arc<T> {
+        self.held.read().unwrap().value.clone()
+    }
+
+    /// Etag of the held value, if one has been applied
+    pub fn etag(&self) -> Option<String> {
+        self.held
+            .read()
+            .unwrap()
+            .etag
+            .map(|(id, version)| format!("{}-{}", id, version))
+    }
+
+    /// Swaps in `change` if it is for this key and newer than the held
+    /// value. Returns whether it was applied.
+    pub fn apply(&self, change: &ConfigChange) -> Result<bool> {
+        if change.namespace != self.namespace || change.key != self.key {
+            return Ok(false);
+        }
+        if change.is_secret {
+            return Err(Error::Config(format!(
+                "Configuration '{}' in namespace '{}' is secret and cannot be watched",
+                self.key, self.namespace
+            )));
+        }
+
+        let mut held = self.held.write().unwrap();
+        // A recreated key starts its versions over under a new id
+        if let Some((id, version)) = held.etag {
+            if id == change.id && version >= change.version {
+                return Ok(false);
+            }
+        }
+
+        let value = T::deserialize(&change.value).map_err(|e| {
+            Error::Serialization(format!(
+                "Configuration '{}' in namespace '{}' has an unexpected value: {}",
+                self.key, self.namespace, e
+            ))
+        })?;
+        held.value = Arc::new(value);
+        held.etag = Some((change.id, change.version));
+
+        Ok(true)
+    }
+
+    /// Applies a message published on `CHANGES_CHANNEL`
+    pub fn apply_message(&self, payload: &str) -> Result<bool> {
+        let change: ConfigChange = serde_json::from_str(payload)
+            .map_err(|e| Error::Serialization(format!("Invalid configuration change: {}", e)))?;
+        self.apply(&change)
+    }
+
+    /// Loads the current value from the configuration service
+    pub async fn load(&self, config_service: &ServiceClient) -> Result<bool> {
+        let change: ConfigChange = config_service
+            .get_json(&format!(
+                "/api/v1/config/namespaces/{}/items/{}",
+                self.namespace, self.key
+            ))
+            .await?;
+        self.apply(&change)
+    }
+
+    /// Follows changes until `shutdown`, resubscribing if Redis goes away
+    pub async fn run(
+        self,
+        redis: redis::Client,
+        config_service: ServiceClient,
+        mut shutdown: ShutdownSignal,
+    ) {
+        while !shutdown.is_shutting_down() {
+            tokio::select! {
+                result = self.follow(&redis, &config_service) => {
+                    if let Err(e) = result {
+                        tracing::warn!(
+                            "Watching configuration '{}' in namespace '{}' failed: {}",
+                            self.key,
+                            self.namespace,
+                            e
+                        );
+                    }
+                }
+                _ = shutdown.recv() => break,
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                _ = shutdown.recv() => break,
+            }
+        }
+    }
+
+    // Subscribes, catches up with the configuration service, then applies
+    // changes until the subscription ends
+    async fn follow(&self, redis: &redis::Client, config_service: &ServiceClient) -> Result<()> {
+        let mut pubsub = redis
+            .get_async_connection()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to connect to Redis: {}", e)))?
+            .into_pubsub();
+        pubsub
+            .subscribe(CHANGES_CHANNEL)
+            .await
+            .map_err(|e| Error::Network(format!("Failed to subscribe to changes: {}", e)))?;
+
+        // Subscribed first, so nothing published after this read is missed
+        match self.load(config_service).await {
+            Ok(_) | Err(Error::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::warn!("Unreadable configuration change: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.apply_message(&payload) {
+                tracing::warn!("Ignoring configuration change: {}", e);
+            }
+        }
+
+        Err(Error::Network("Redis subscription closed".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    fn change(id: Uuid, version: i32, value: Value) -> ConfigChange {
+        ConfigChange {
+            id,
+            namespace: "scan".to_string(),
+            key: "max_depth".to_string(),
+            version,
+            value,
+            is_secret: false,
+        }
+    }
+
+    #[test]
+    fn test_published_change_updates_the_held_value() {
+        let watcher = ConfigWatcher::new("scan", "max_depth", 3u32);
+        let before = watcher.get();
+        let id = Uuid::new_v4();
+
+        let payload = serde_json::to_string(&change(id, 2, json!(5))).unwrap();
+        assert!(watcher.apply_message(&payload).unwrap());
+        assert_eq!(*watcher.get(), 5);
+        assert_eq!(watcher.etag(), Some(format!("{}-2", id)));
+
+        // The earlier snapshot is unaffected by the swap
+        assert_eq!(*before, 3);
+    }
+
+    #[test]
+    fn test_stale_and_unrelated_changes_are_ignored() {
+        let watcher = ConfigWatcher::new("scan", "max_depth", 3u32);
+        let id = Uuid::new_v4();
+        watcher.apply(&change(id, 4, json!(5))).unwrap();
+
+        assert!(!watcher.apply(&change(id, 3, json!(7))).unwrap());
+        assert!(!watcher.apply(&change(id, 4, json!(7))).unwrap());
+        let mut other_key = change(id, 9, json!(7));
+        other_key.key = "timeout".to_string();
+        assert!(!watcher.apply(&other_key).unwrap());
+        assert_eq!(*watcher.get(), 5);
+
+        // Unless the key was recreated
+        assert!(watcher.apply(&change(Uuid::new_v4(), 1, json!(7))).unwrap());
+        assert_eq!(*watcher.get(), 7);
+    }
+
+    #[test]
+    fn test_mistyped_value_keeps_the_held_one() {
+        let watcher = ConfigWatcher::new("scan", "max_depth", 3u32);
+
+        let err = watcher
+            .apply(&change(Uuid::new_v4(), 2, json!("deep")))
+            .unwrap_err();
+        assert!(matches!(err, Error::Serialization(_)));
+        assert_eq!(*watcher.get(), 3);
+        assert_eq!(watcher.etag(), None);
+    }
+
+    #[test]
+    fn test_secret_value_keeps_the_held_one() {
+        let watcher = ConfigWatcher::new("scan", "max_depth", 3u32);
+        let mut secret = change(Uuid::new_v4(), 2, json!("*****"));
+        secret.is_secret = true;
+
+        let err = watcher.apply(&secret).unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+        assert_eq!(*watcher.get(), 3);
+        assert_eq!(watcher.etag(), None);
+    }
+
+    #[actix_web::test]
+    async fn test_late_subscriber_loads_the_current_value() {
+        let id = Uuid::new_v4();
+        let server = HttpServer::new(move || {
+            App::new().route(
+                "/api/v1/config/namespaces/scan/items/max_depth",
+                web::get().to(move || async move {
+                    // The configuration service's full response
+                    HttpResponse::Ok().json(json!({
+                        "id": id,
+                        "key": "max_depth",
+                        "namespace": "scan",
+                        "value": 6,
+                        "value_type": "integer",
+                        "version": 3,
+                        "is_secret": false
+                    }))
+                }),
+            )
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        let watcher = ConfigWatcher::new("scan", "max_depth", 3u32);
+        assert!(watcher
+            .load(&ServiceClient::new(&url).unwrap())
+            .await
+            .unwrap());
+        assert_eq!(*watcher.get(), 6);
+        assert_eq!(watcher.etag(), Some(format!("{}-3", id)));
+
+        handle.stop(false).await;
+    }
+}
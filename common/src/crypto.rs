@@ -0,0 +1,138 @@
+//! Encryption for secrets kept at rest
+//!
+//! Values are sealed with AES-256-GCM under a base64-encoded 32-byte master
+//! key. Every value gets a fresh random nonce; the stored form is base64 of
+//! the nonce followed by the ciphertext.
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
+use thiserror::Error;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct CryptoError(String);
+
+#[derive(Clone)]
+pub struct SecretCipher {
+    key: [u8; 32],
+}
+
+impl SecretCipher {
+    /// Takes the base64-encoded master key
+    pub fn new(encoded_key: &str) -> Result<Self, CryptoError> {
+        let bytes = general_purpose::STANDARD
+            .decode(encoded_key.trim())
+            .map_err(|e| CryptoError(format!("Encryption key is not valid base64: {}", e)))?;
+
+        let key: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            CryptoError(format!(
+                "Encryption key must be 32 bytes, got {}",
+                bytes.len()
+            ))
+        })?;
+
+        Ok(Self { key })
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+
+    /// Encrypts `data` under a fresh nonce, returned alongside the
+    /// ciphertext for callers that store the two apart
+    pub fn seal_bytes(&self, data: &str) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut rand::thread_rng());
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, data.as_bytes())
+            .map_err(|e| CryptoError(format!("Encryption failed: {}", e)))?;
+
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    pub fn open_bytes(&self, nonce: &[u8], ciphertext: &[u8]) -> Result<String, CryptoError> {
+        if nonce.len() != NONCE_LEN {
+            return Err(CryptoError("Invalid encrypted data format".to_string()));
+        }
+
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| CryptoError(format!("Decryption failed: {}", e)))?;
+
+        String::from_utf8(plaintext)
+            .map_err(|e| CryptoError(format!("UTF-8 decoding failed: {}", e)))
+    }
+
+    /// Encrypts `data` into base64 of the nonce followed by the ciphertext
+    pub fn encrypt(&self, data: &str) -> Result<String, CryptoError> {
+        let (mut combined, ciphertext) = self.seal_bytes(data)?;
+        combined.extend_from_slice(&ciphertext);
+
+        Ok(general_purpose::STANDARD.encode(combined))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, CryptoError> {
+        let combined = general_purpose::STANDARD
+            .decode(encrypted.trim())
+            .map_err(|e| CryptoError(format!("Base64 decoding failed: {}", e)))?;
+
+        if combined.len() < NONCE_LEN {
+            return Err(CryptoError("Invalid encrypted data format".to_string()));
+        }
+
+        let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+        self.open_bytes(nonce, ciphertext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=";
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let cipher = SecretCipher::new(KEY).unwrap();
+
+        let sealed = cipher.encrypt("JBSWY3DPEHPK3PXP").unwrap();
+        assert!(!sealed.contains("JBSWY3DPEHPK3PXP"));
+        // A fresh nonce for every value
+        assert_ne!(sealed, cipher.encrypt("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(
+            cipher.decrypt(&format!("{}\n", sealed)).unwrap(),
+            "JBSWY3DPEHPK3PXP"
+        );
+
+        let (nonce, ciphertext) = cipher.seal_bytes("s3cret").unwrap();
+        assert_eq!(cipher.open_bytes(&nonce, &ciphertext).unwrap(), "s3cret");
+    }
+
+    #[test]
+    fn test_wrong_key_or_tampering_fails_to_decrypt() {
+        let sealed = SecretCipher::new(KEY).unwrap().encrypt("abc").unwrap();
+
+        let other = SecretCipher::new(&general_purpose::STANDARD.encode([7u8; 32])).unwrap();
+        assert!(other.decrypt(&sealed).is_err());
+
+        let mut combined = general_purpose::STANDARD.decode(&sealed).unwrap();
+        combined[NONCE_LEN] ^= 1;
+        let tampered = general_purpose::STANDARD.encode(combined);
+        assert!(SecretCipher::new(KEY).unwrap().decrypt(&tampered).is_err());
+        assert!(SecretCipher::new(KEY).unwrap().decrypt("c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn test_master_key_must_be_32_base64_bytes() {
+        assert!(SecretCipher::new("not-a-32-byte-key").is_err());
+        assert!(SecretCipher::new(&general_purpose::STANDARD.encode([1u8; 16])).is_err());
+        assert!(SecretCipher::new(&format!(" {}\n", KEY)).is_ok());
+    }
+}
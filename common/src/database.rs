@@ -1,24 +1,131 @@
-//! Database utilities and connections
+//! PostgreSQL connection pools
+//!
+//! Services build their pool with `create_pool`, which sizes it from the
+//! service's `DatabaseConfig` and checks the database answers before the
+//! service starts, so a wrong URL fails at startup rather than on the first
+//! request. Migrations stay with each service, since `sqlx::migrate!` embeds
+//! the service's own `migrations/` directory:
+//!
+//! ```ignore
+//! let pool = database::create_pool(&config.database).await?;
+//! sqlx::migrate!("./migrations").run(&pool).await?;
+//! ```
+//!
+//! `HealthChecker::database` probes the pool and reports its `PoolStats`.
 
+use crate::config::DatabaseConfig;
 use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::time::Duration;
 
-// Type alias for database pool - actual implementation depends on the database driver
-pub type DatabasePool = ();
+pub type DatabasePool = PgPool;
 
-pub async fn create_pool(database_url: &str, max_connections: u32) -> Result<DatabasePool> {
-    // Placeholder implementation
-    // In real implementation, this would use sqlx::postgres::PgPoolOptions
-    Ok(())
+/// Pool options for `config`, without connecting
+pub fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    let idle_timeout = match config.idle_timeout_seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_seconds))
+        .idle_timeout(idle_timeout)
 }
 
-pub async fn run_migrations(pool: &DatabasePool, migration_path: &str) -> Result<()> {
-    // Placeholder for running migrations
-    // In real implementation, this would use sqlx::migrate!
-    Ok(())
+/// Builds the pool for `config` and pings the database through it
+pub async fn create_pool(config: &DatabaseConfig) -> Result<DatabasePool> {
+    let pool = pool_options(config)
+        .connect_lazy(&config.url)
+        .map_err(|e| Error::Config(format!("Invalid database URL: {}", e)))?;
+
+    ping(&pool).await?;
+    Ok(pool)
 }
 
-pub async fn health_check(pool: &DatabasePool) -> Result<()> {
-    // Placeholder for health check
-    // In real implementation, this would execute a simple query
+/// Checks the database answers a trivial query
+pub async fn ping(pool: &DatabasePool) -> Result<()> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(format!("Database ping failed: {}", e)))?;
     Ok(())
 }
+
+/// Connections held by a pool at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+}
+
+impl PoolStats {
+    pub fn of(pool: &DatabasePool) -> Self {
+        Self {
+            size: pool.size(),
+            idle: pool.num_idle() as u32,
+            max_connections: pool.options().get_max_connections(),
+        }
+    }
+
+    pub fn in_use(&self) -> u32 {
+        self.size.saturating_sub(self.idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn config(url: &str, max_connections: u32) -> DatabaseConfig {
+        DatabaseConfig {
+            url: url.to_string(),
+            max_connections,
+            acquire_timeout_seconds: 1,
+            idle_timeout_seconds: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_fails_at_startup() {
+        // Nothing listens on port 1
+        let config = config("postgres://mirage@127.0.0.1:1/mirage", 2);
+
+        let started = Instant::now();
+        let err = create_pool(&config).await.unwrap_err();
+        assert!(matches!(err, Error::Database(_)), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let err = create_pool(&self::config("postgres://127.0.0.1:port/mirage", 2))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Config(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_exhausted_pool_times_out_acquire() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = create_pool(&config(&url, 1)).await.unwrap();
+
+        let held = pool.acquire().await.unwrap();
+        let stats = PoolStats::of(&pool);
+        assert_eq!(
+            (stats.size, stats.in_use(), stats.max_connections),
+            (1, 1, 1)
+        );
+
+        let started = Instant::now();
+        let err = pool.acquire().await.unwrap_err();
+        assert!(matches!(err, sqlx::Error::PoolTimedOut), "{}", err);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+
+        // The connection is handed to the next caller once released
+        drop(held);
+        ping(&pool).await.unwrap();
+    }
+}
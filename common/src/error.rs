@@ -1,3 +1,6 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -52,6 +55,113 @@ pub enum Error {
 
     #[error("Timeout error: {0}")]
     Timeout(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+}
+
+/// JSON body of an error response, the same for every service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// Stable, machine-readable kind such as `not_found`
+    pub error: String,
+    pub message: String,
+}
+
+impl Error {
+    /// HTTP status a handler answers with when it fails with this error
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Error::Validation(_) => 400,
+            Error::Auth(_) | Error::Unauthorized(_) => 401,
+            Error::Forbidden(_) | Error::Authorization(_) => 403,
+            Error::NotFound(_) | Error::ResourceNotFound(_) => 404,
+            Error::Conflict(_) => 409,
+            Error::RateLimited(_) => 429,
+            Error::Network(_) | Error::ExternalApi(_) => 502,
+            Error::ServiceUnavailable(_) => 503,
+            Error::Timeout(_) => 504,
+            Error::Database(_)
+            | Error::Config(_)
+            | Error::Serialization(_)
+            | Error::Internal(_)
+            | Error::ModuleExecution(_) => 500,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Validation(_) => "validation_error",
+            Error::Auth(_) | Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) | Error::Authorization(_) => "forbidden",
+            Error::NotFound(_) | Error::ResourceNotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::RateLimited(_) => "rate_limited",
+            Error::Network(_) | Error::ExternalApi(_) => "upstream_error",
+            Error::ServiceUnavailable(_) => "service_unavailable",
+            Error::Timeout(_) => "timeout",
+            Error::Database(_)
+            | Error::Config(_)
+            | Error::Serialization(_)
+            | Error::Internal(_)
+            | Error::ModuleExecution(_) => "internal_error",
+        }
+    }
+
+    /// Response body for this error. Server-side failures get a generic
+    /// message so database and upstream details don't reach the client.
+    pub fn body(&self) -> ErrorBody {
+        let message = match self {
+            _ if self.http_status() < 500 => self.detail().to_string(),
+            Error::ServiceUnavailable(detail) => detail.clone(),
+            Error::Network(_) | Error::ExternalApi(_) => "A dependency failed".to_string(),
+            Error::Timeout(_) => "A dependency timed out".to_string(),
+            _ => "Internal server error".to_string(),
+        };
+
+        ErrorBody {
+            error: self.kind().to_string(),
+            message,
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Error::Database(detail)
+            | Error::Auth(detail)
+            | Error::Validation(detail)
+            | Error::Config(detail)
+            | Error::Network(detail)
+            | Error::Serialization(detail)
+            | Error::Internal(detail)
+            | Error::NotFound(detail)
+            | Error::Unauthorized(detail)
+            | Error::Forbidden(detail)
+            | Error::Authorization(detail)
+            | Error::ResourceNotFound(detail)
+            | Error::Conflict(detail)
+            | Error::ExternalApi(detail)
+            | Error::ModuleExecution(detail)
+            | Error::RateLimited(detail)
+            | Error::Timeout(detail)
+            | Error::ServiceUnavailable(detail) => detail,
+        }
+    }
+}
+
+/// Lets actix handlers return `mirage_common::Result` and have failures
+/// answered with `Error::http_status` and an `ErrorBody`
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.http_status() >= 500 {
+            tracing::error!("Request failed: {}", self);
+        }
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
 }
 
 impl From<serde_json::Error> for Error {
@@ -78,6 +188,12 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl From<prometheus::Error> for Error {
+    fn from(err: prometheus::Error) -> Self {
+        Error::Internal(format!("Metrics error: {}", err))
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 // Helper function to map status codes to error types
@@ -93,3 +209,66 @@ pub fn map_status_error(status: reqwest::StatusCode, message: &str) -> Error {
         _ => Error::Internal(format!("Unexpected status code: {}", status)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    async fn respond(error: Error) -> (u16, ErrorBody) {
+        let response = error.error_response();
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_client_errors_keep_their_message() {
+        let cases = [
+            (Error::Validation("bad".into()), 400, "validation_error"),
+            (Error::Auth("bad".into()), 401, "unauthorized"),
+            (Error::Unauthorized("bad".into()), 401, "unauthorized"),
+            (Error::Forbidden("bad".into()), 403, "forbidden"),
+            (Error::Authorization("bad".into()), 403, "forbidden"),
+            (Error::NotFound("bad".into()), 404, "not_found"),
+            (Error::ResourceNotFound("bad".into()), 404, "not_found"),
+            (Error::Conflict("bad".into()), 409, "conflict"),
+            (Error::RateLimited("bad".into()), 429, "rate_limited"),
+        ];
+
+        for (error, status, kind) in cases {
+            let expected = ErrorBody {
+                error: kind.to_string(),
+                message: "bad".to_string(),
+            };
+            assert_eq!(respond(error).await, (status, expected));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_server_errors_hide_their_detail() {
+        let secret = "password authentication failed for user mirage";
+        let cases = [
+            (Error::Database(secret.into()), 500, "internal_error"),
+            (Error::Config(secret.into()), 500, "internal_error"),
+            (Error::Serialization(secret.into()), 500, "internal_error"),
+            (Error::Internal(secret.into()), 500, "internal_error"),
+            (Error::ModuleExecution(secret.into()), 500, "internal_error"),
+            (Error::Network(secret.into()), 502, "upstream_error"),
+            (Error::ExternalApi(secret.into()), 502, "upstream_error"),
+            (Error::Timeout(secret.into()), 504, "timeout"),
+        ];
+
+        for (error, status, kind) in cases {
+            let (actual, body) = respond(error).await;
+            assert_eq!(actual, status);
+            assert_eq!(body.error, kind);
+            assert!(!body.message.contains(secret), "{}", body.message);
+        }
+
+        let (status, body) = respond(Error::ServiceUnavailable("Queue is full".into())).await;
+        assert_eq!(status, 503);
+        assert_eq!(body.error, "service_unavailable");
+        assert_eq!(body.message, "Queue is full");
+    }
+}
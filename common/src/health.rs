@@ -1,11 +1,52 @@
 //! Health check utilities
+//!
+//! Each service builds one `HealthChecker` at startup and registers a probe
+//! for every dependency it talks to. `/health/live` only says the process is
+//! up and answering requests, so an orchestrator restarts it when it isn't.
+//! `/health/ready` runs the probes and reports each of them, so traffic is
+//! held back while a dependency is down:
+//!
+//! ```ignore
+//! let health = web::Data::new(
+//!     HealthChecker::new("scanner-coordinator", env!("CARGO_PKG_VERSION"))
+//!         .probe("postgres", move || repositories::ping(pool.clone())),
+//! );
+//! App::new()
+//!     .app_data(health.clone())
+//!     .route("/health/live", web::get().to(health::live))
+//!     .route("/health/ready", web::get().to(health::ready))
+//! ```
+//!
+//! A failing `probe` makes the service unhealthy. A failing `optional_probe`
+//! only degrades it: the service still reports ready, with the failure listed.
+//! `database` registers a PostgreSQL pool, whose check also reports the pool's
+//! connection counts.
 
+use crate::database::{self, DatabasePool, PoolStats};
+use crate::error::{Error, Result};
+use actix_web::{web, HttpResponse};
 use chrono::{DateTime, Utc};
+use futures::future::{join_all, BoxFuture, FutureExt};
 use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Time a probe gets before it counts as failed
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ordered from best to worst, so the overall status is the worst of the
+/// checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthStatus {
-    pub status: String,
+    pub status: Status,
     pub service: String,
     pub timestamp: DateTime<Utc>,
     pub version: String,
@@ -15,15 +56,19 @@ pub struct HealthStatus {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthCheck {
     pub name: String,
-    pub status: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
-    pub duration_ms: Option<u64>,
+    pub latency_ms: u64,
+    /// Probe specific state, such as a pool's connection counts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
 impl HealthStatus {
     pub fn new(service_name: &str, version: &str) -> Self {
         Self {
-            status: "healthy".to_string(),
+            status: Status::Healthy,
             service: service_name.to_string(),
             timestamp: Utc::now(),
             version: version.to_string(),
@@ -31,27 +76,317 @@ impl HealthStatus {
         }
     }
 
-    pub fn add_check(
-        &mut self,
+    pub fn add_check(&mut self, check: HealthCheck) {
+        // Overall status is the worst of the checks
+        self.status = self.status.max(check.status);
+        self.checks.push(check);
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.status == Status::Healthy
+    }
+}
+
+type ProbeFn = Box<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+type DetailsFn = Box<dyn Fn() -> serde_json::Value + Send + Sync>;
+
+struct Probe {
+    name: String,
+    /// Status reported when the probe fails
+    failure: Status,
+    check: ProbeFn,
+    details: Option<DetailsFn>,
+}
+
+pub struct HealthChecker {
+    service: String,
+    version: String,
+    timeout: Duration,
+    probes: Vec<Probe>,
+}
+
+impl HealthChecker {
+    pub fn new(service_name: &str, version: &str) -> Self {
+        Self {
+            service: service_name.to_string(),
+            version: version.to_string(),
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            probes: Vec::new(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers a dependency the service can't work without
+    pub fn probe<F, Fut>(self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.add_probe(name, Status::Unhealthy, check, None)
+    }
+
+    /// Registers a dependency the service can run without, at reduced
+    /// functionality
+    pub fn optional_probe<F, Fut>(self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.add_probe(name, Status::Degraded, check, None)
+    }
+
+    /// Registers the service's PostgreSQL pool. The check pings the database
+    /// and reports the pool's `PoolStats`, so an exhausted pool shows up.
+    pub fn database(self, name: &str, pool: DatabasePool) -> Self {
+        let stats_pool = pool.clone();
+        self.add_probe(
+            name,
+            Status::Unhealthy,
+            move || {
+                let pool = pool.clone();
+                async move { database::ping(&pool).await }
+            },
+            Some(Box::new(move || {
+                serde_json::json!(PoolStats::of(&stats_pool))
+            })),
+        )
+    }
+
+    fn add_probe<F, Fut>(
+        mut self,
         name: &str,
-        status: &str,
-        message: Option<String>,
-        duration_ms: Option<u64>,
-    ) {
-        self.checks.push(HealthCheck {
+        failure: Status,
+        check: F,
+        details: Option<DetailsFn>,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.probes.push(Probe {
             name: name.to_string(),
-            status: status.to_string(),
-            message,
-            duration_ms,
+            failure,
+            check: Box::new(move || check().boxed()),
+            details,
         });
+        self
+    }
 
-        // Update overall status based on checks
-        if self.checks.iter().any(|check| check.status != "healthy") {
-            self.status = "unhealthy".to_string();
+    /// Liveness: the process is running, no probes are run
+    pub fn live(&self) -> HealthStatus {
+        HealthStatus::new(&self.service, &self.version)
+    }
+
+    /// Readiness: runs every probe concurrently and aggregates the results
+    pub async fn check(&self) -> HealthStatus {
+        let checks = join_all(self.probes.iter().map(|probe| self.run(probe))).await;
+
+        let mut status = HealthStatus::new(&self.service, &self.version);
+        for check in checks {
+            status.add_check(check);
         }
+        status
     }
 
-    pub fn is_healthy(&self) -> bool {
-        self.status == "healthy"
+    async fn run(&self, probe: &Probe) -> HealthCheck {
+        let started = Instant::now();
+        let result = match tokio::time::timeout(self.timeout, (probe.check)()).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout(format!(
+                "No response within {}ms",
+                self.timeout.as_millis()
+            ))),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let details = probe.details.as_ref().map(|details| details());
+
+        match result {
+            Ok(()) => HealthCheck {
+                name: probe.name.clone(),
+                status: Status::Healthy,
+                message: None,
+                latency_ms,
+                details,
+            },
+            Err(e) => {
+                tracing::warn!("Health probe {} failed: {}", probe.name, e);
+                HealthCheck {
+                    name: probe.name.clone(),
+                    status: probe.failure,
+                    message: Some(e.to_string()),
+                    latency_ms,
+                    details,
+                }
+            }
+        }
+    }
+}
+
+/// Probe for a downstream service, which passes when `url` answers with a
+/// success status
+pub fn http_probe(
+    client: reqwest::Client,
+    url: &str,
+) -> impl Fn() -> BoxFuture<'static, Result<()>> + Send + Sync + 'static {
+    let url = url.to_string();
+    move || {
+        let client = client.clone();
+        let url = url.clone();
+        async move {
+            let response = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| Error::Network(format!("Failed to reach {}: {}", url, e)))?;
+
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(Error::ExternalApi(format!(
+                    "{} returned {}",
+                    url,
+                    response.status()
+                )))
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Handler for `GET /health/live`
+pub async fn live(checker: web::Data<HealthChecker>) -> HttpResponse {
+    HttpResponse::Ok().json(checker.live())
+}
+
+/// Handler for `GET /health/ready`. A degraded service is still ready; only
+/// an unhealthy one answers 503.
+pub async fn ready(checker: web::Data<HealthChecker>) -> HttpResponse {
+    let status = checker.check().await;
+    if status.status == Status::Unhealthy {
+        HttpResponse::ServiceUnavailable().json(status)
+    } else {
+        HttpResponse::Ok().json(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::App;
+
+    async fn up() -> Result<()> {
+        Ok(())
+    }
+
+    async fn down() -> Result<()> {
+        Err(Error::Database("connection refused".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_failing_probe_makes_service_unhealthy() {
+        let checker = HealthChecker::new("test-service", "0.1.0")
+            .probe("postgres", up)
+            .probe("redis", down);
+
+        let status = checker.check().await;
+        assert_eq!(status.status, Status::Unhealthy);
+        assert_eq!(status.checks.len(), 2);
+        assert_eq!(status.checks[0].name, "postgres");
+        assert_eq!(status.checks[0].status, Status::Healthy);
+        assert_eq!(status.checks[1].status, Status::Unhealthy);
+        assert_eq!(
+            status.checks[1].message.as_deref(),
+            Some("Database error: connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failing_optional_probe_degrades_service() {
+        let checker = HealthChecker::new("test-service", "0.1.0")
+            .probe("postgres", up)
+            .optional_probe("reporting-service", down);
+
+        let status = checker.check().await;
+        assert_eq!(status.status, Status::Degraded);
+        assert!(!status.is_healthy());
+        assert!(checker.live().is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_slow_probe_times_out() {
+        let checker = HealthChecker::new("test-service", "0.1.0")
+            .with_timeout(Duration::from_millis(20))
+            .probe("mongodb", || async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok(())
+            });
+
+        let status = checker.check().await;
+        assert_eq!(status.status, Status::Unhealthy);
+        assert!(status.checks[0].latency_ms < 10_000);
+        assert_eq!(
+            status.checks[0].message.as_deref(),
+            Some("Timeout error: No response within 20ms")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_database_check_reports_pool_stats() {
+        let config = crate::config::DatabaseConfig {
+            url: "postgres://mirage@127.0.0.1:1/mirage".to_string(),
+            max_connections: 4,
+            acquire_timeout_seconds: 1,
+            idle_timeout_seconds: 0,
+        };
+        let pool = database::pool_options(&config)
+            .connect_lazy(&config.url)
+            .unwrap();
+        let checker = HealthChecker::new("test-service", "0.1.0").database("postgres", pool);
+
+        let status = checker.check().await;
+        assert_eq!(status.status, Status::Unhealthy);
+        assert_eq!(
+            status.checks[0].details,
+            Some(serde_json::json!({ "size": 0, "idle": 0, "max_connections": 4 }))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_ready_reports_each_check_and_live_ignores_them() {
+        let health = web::Data::new(
+            HealthChecker::new("test-service", "0.1.0")
+                .probe("postgres", up)
+                .probe("redis", down),
+        );
+        let app = init_service(
+            App::new()
+                .app_data(health)
+                .route("/health/live", web::get().to(live))
+                .route("/health/ready", web::get().to(ready)),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/health/ready").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = read_body_json(resp).await;
+        assert_eq!(body["status"], "unhealthy");
+        assert_eq!(body["checks"][0]["name"], "postgres");
+        assert_eq!(body["checks"][0]["status"], "healthy");
+        assert!(body["checks"][0]["latency_ms"].is_u64());
+        assert_eq!(body["checks"][1]["status"], "unhealthy");
+
+        let req = TestRequest::get().uri("/health/live").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = read_body_json(resp).await;
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["checks"], serde_json::json!([]));
     }
 }
@@ -1,14 +1,22 @@
 //! Common functionality for Mirage OSINT platform
 
 pub mod auth;
+pub mod client;
 pub mod config;
+pub mod config_watch;
+pub mod crypto;
 pub mod database;
 pub mod error;
 pub mod event;
 pub mod health;
+pub mod http;
+pub mod metrics;
 pub mod models;
+pub mod shutdown;
 pub mod target;
+pub mod telemetry;
 pub mod utils;
+pub mod validation;
 
 // Re-exports
 pub use error::{Error, Result};
@@ -0,0 +1,210 @@
+//! Prometheus metrics
+//!
+//! Each service keeps its metrics in one `Metrics` registry. The
+//! `track_requests` middleware counts and times every HTTP request by route
+//! and status, and `render` serves the registry in the Prometheus text
+//! exposition format:
+//!
+//! ```ignore
+//! let metrics = web::Data::new(Metrics::new("data-collection")?);
+//! App::new()
+//!     .app_data(metrics.clone())
+//!     .wrap(from_fn(metrics::track_requests))
+//!     .route("/metrics", web::get().to(metrics::render))
+//! ```
+//!
+//! Service-specific values such as queue depth are exported through gauges
+//! created with `Metrics::gauge`, which land in the same registry.
+
+use crate::error::Result;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub use prometheus::IntGauge;
+
+/// Prefix of every metric name
+pub const NAMESPACE: &str = "mirage";
+
+/// Path label for requests that didn't match a route, so scanners probing
+/// random URLs can't create a series per path
+const UNMATCHED_PATH: &str = "unmatched";
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+}
+
+impl Metrics {
+    /// Creates the registry for `service`, which is attached to every metric
+    /// as the `service` label
+    pub fn new(service: &str) -> Result<Self> {
+        let labels = HashMap::from([("service".to_string(), service.to_string())]);
+        let registry = Registry::new_custom(Some(NAMESPACE.to_string()), Some(labels))?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "path", "status"],
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "path"],
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            latency,
+        })
+    }
+
+    /// Registers a gauge for a service-specific value
+    pub fn gauge(&self, name: &str, help: &str) -> Result<IntGauge> {
+        let gauge = IntGauge::new(name, help)?;
+        self.registry.register(Box::new(gauge.clone()))?;
+        Ok(gauge)
+    }
+
+    /// Records one handled request. `path` should be the route pattern
+    /// rather than the concrete path, to keep the number of series bounded.
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        self.requests
+            .with_label_values(&[method, path, &status.to_string()])
+            .inc();
+        self.latency
+            .with_label_values(&[method, path])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// The registry in the Prometheus text exposition format
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Middleware recording every request in the app's `web::Data<Metrics>`.
+/// Use with `actix_web::middleware::from_fn`.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let started = Instant::now();
+
+    let response = next.call(req).await?;
+
+    if let Some(metrics) = metrics {
+        let path = response
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| UNMATCHED_PATH.to_string());
+        metrics.observe_request(
+            &method,
+            &path,
+            response.status().as_u16(),
+            started.elapsed(),
+        );
+    }
+
+    Ok(response)
+}
+
+/// Handler for `GET /metrics`
+pub async fn render(metrics: web::Data<Metrics>) -> HttpResponse {
+    match metrics.encode() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(body),
+        Err(e) => {
+            tracing::error!("Failed to encode metrics: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::App;
+
+    async fn scan(path: web::Path<String>) -> HttpResponse {
+        HttpResponse::Ok().body(path.into_inner())
+    }
+
+    async fn exposition<B: MessageBody>(resp: ServiceResponse<B>) -> String {
+        assert!(resp.status().is_success());
+        String::from_utf8(read_body(resp).await.to_vec()).unwrap()
+    }
+
+    /// Value of the series whose line starts with `prefix`, or 0 if absent
+    fn sample(exposition: &str, prefix: &str) -> u64 {
+        exposition
+            .lines()
+            .find(|line| line.starts_with(prefix))
+            .and_then(|line| line.rsplit(' ').next())
+            .map_or(0, |value| value.parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_endpoint_counts_requests() {
+        let metrics = web::Data::new(Metrics::new("test-service").unwrap());
+        let app = init_service(
+            App::new()
+                .app_data(metrics.clone())
+                .wrap(from_fn(track_requests))
+                .route("/scans/{id}", web::get().to(scan))
+                .route("/metrics", web::get().to(render)),
+        )
+        .await;
+        let series = r#"mirage_http_requests_total{method="GET",path="/scans/{id}",status="200",service="test-service"}"#;
+
+        let req = TestRequest::get().uri("/metrics").to_request();
+        let before = exposition(call_service(&app, req).await).await;
+        assert_eq!(sample(&before, series), 0);
+
+        for id in ["1", "2"] {
+            let req = TestRequest::get()
+                .uri(&format!("/scans/{}", id))
+                .to_request();
+            call_service(&app, req).await;
+        }
+        let req = TestRequest::get().uri("/nowhere").to_request();
+        call_service(&app, req).await;
+
+        let req = TestRequest::get().uri("/metrics").to_request();
+        let after = exposition(call_service(&app, req).await).await;
+        assert!(after.contains("# TYPE mirage_http_requests_total counter"));
+        assert_eq!(sample(&after, series), 2);
+        assert!(after.contains(r#"path="unmatched""#));
+        assert!(after.contains("mirage_http_request_duration_seconds_bucket"));
+    }
+
+    #[test]
+    fn test_gauges_share_the_registry() {
+        let metrics = Metrics::new("test-service").unwrap();
+        let depth = metrics.gauge("queue_depth", "Tasks waiting").unwrap();
+        depth.set(7);
+
+        let exposition = metrics.encode().unwrap();
+        assert!(exposition.contains(r#"mirage_queue_depth{service="test-service"} 7"#));
+        assert!(metrics.gauge("queue_depth", "Tasks waiting").is_err());
+    }
+}
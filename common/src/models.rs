@@ -27,6 +27,8 @@ pub struct Module {
     pub author: String,
     pub dependencies: Vec<String>,
     pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub configuration: serde_json::Value,
 }
 
@@ -109,6 +111,75 @@ impl<T> PaginatedResponse<T> {
     }
 }
 
+// Offset-based list parameters, e.g. `?limit=50&offset=100`
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+pub const MAX_PAGE_LIMIT: u32 = 500;
+
+/// Paging query of a list endpoint. The fields are signed so a negative value
+/// is reported as a validation error rather than a parse failure.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct PageRequest {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl PageRequest {
+    /// Resolved `(limit, offset)`; limits above `MAX_PAGE_LIMIT` are capped
+    pub fn resolve(&self) -> crate::Result<(u32, u32)> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT.into());
+        if limit <= 0 {
+            return Err(crate::Error::Validation(
+                "limit must be greater than zero".to_string(),
+            ));
+        }
+
+        let offset = self.offset.unwrap_or(0);
+        let offset = u32::try_from(offset).map_err(|_| {
+            crate::Error::Validation(format!("offset must be between 0 and {}", u32::MAX))
+        })?;
+
+        let limit = limit.min(MAX_PAGE_LIMIT.into()) as u32;
+        Ok((limit, offset))
+    }
+}
+
+/// One page of a list response, the same shape for every service
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items across all pages
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: u64, limit: u32, offset: u32) -> Self {
+        Self {
+            items,
+            total,
+            limit,
+            offset,
+        }
+    }
+
+    /// Page of a list held in memory: `limit` items of `all` from `offset`,
+    /// with every item counted in `total`
+    pub fn from_slice(all: &[T], limit: u32, offset: u32) -> Self
+    where
+        T: Clone,
+    {
+        let items = all
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect();
+
+        Self::new(items, all.len() as u64, limit, offset)
+    }
+}
+
 // Module-related models
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModuleConfig {
@@ -208,3 +279,47 @@ pub struct TokenClaims {
     pub iat: u64,           // Issued at
     pub roles: Vec<String>, // User roles
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_request_defaults_and_cap() {
+        assert_eq!(
+            PageRequest::default().resolve().unwrap(),
+            (DEFAULT_PAGE_LIMIT, 0)
+        );
+
+        let request = PageRequest {
+            limit: Some(10_000),
+            offset: Some(20),
+        };
+        assert_eq!(request.resolve().unwrap(), (MAX_PAGE_LIMIT, 20));
+    }
+
+    #[test]
+    fn test_page_request_rejects_out_of_range_values() {
+        for (limit, offset) in [(Some(0), None), (Some(-5), None), (None, Some(-1))] {
+            let request = PageRequest { limit, offset };
+            assert!(matches!(
+                request.resolve(),
+                Err(crate::Error::Validation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_page_from_slice_counts_every_item() {
+        let all: Vec<u32> = (0..25).collect();
+
+        let page = Page::from_slice(&all, 10, 20);
+        assert_eq!(page.items, vec![20, 21, 22, 23, 24]);
+        assert_eq!(page.total, 25);
+        assert_eq!((page.limit, page.offset), (10, 20));
+
+        let page = Page::from_slice(&all, 10, 30);
+        assert!(page.items.is_empty());
+        assert_eq!(page.total, 25);
+    }
+}
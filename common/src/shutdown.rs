@@ -0,0 +1,228 @@
+//! Graceful shutdown
+//!
+//! `Shutdown` runs a service's HTTP server until SIGTERM or SIGINT. It then
+//! stops accepting connections and lets in-flight requests finish. Background
+//! workers are told to wrap up through their `ShutdownSignal`, and it waits for
+//! them:
+//!
+//! ```ignore
+//! let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+//! let worker = tokio::spawn(run_worker(shutdown.subscribe()));
+//! let server = HttpServer::new(...)
+//!     .disable_signals()
+//!     .shutdown_timeout(shutdown.timeout().as_secs())
+//!     .bind(addr)?
+//!     .run();
+//! shutdown.run(server, vec![worker]).await
+//! ```
+//!
+//! Servers are built with `disable_signals()` so actix leaves the signals to
+//! us. Their `shutdown_timeout` bounds the time given to in-flight requests,
+//! and the same timeout bounds the workers; a worker still running after that
+//! is aborted.
+
+use futures::future::join_all;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+pub use actix_web::dev::Server;
+
+/// Time given to in-flight requests, and then to workers, to finish
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tells a background worker when the service is stopping
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    pub fn is_shutting_down(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Completes once shutdown has started. Workers `select!` on this next to
+    /// their poll interval.
+    pub async fn recv(&mut self) {
+        // The sender is only dropped once the service is going away anyway
+        let _ = self.rx.wait_for(|stopping| *stopping).await;
+    }
+}
+
+pub struct Shutdown {
+    tx: watch::Sender<bool>,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(timeout: Duration) -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { tx, timeout }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn subscribe(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// Runs `server` until the process receives SIGTERM or SIGINT, then shuts
+    /// it and `workers` down
+    pub async fn run(self, server: Server, workers: Vec<JoinHandle<()>>) -> io::Result<()> {
+        self.run_until(server, workers, terminated()).await
+    }
+
+    /// Like `run`, but shuts down when `signal` completes
+    pub async fn run_until(
+        self,
+        server: Server,
+        mut workers: Vec<JoinHandle<()>>,
+        signal: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        // The server only handles the stop command while it is being polled
+        let handle = server.handle();
+        let mut server = tokio::spawn(server);
+
+        let result = tokio::select! {
+            result = &mut server => result,
+            _ = signal => {
+                tracing::info!("Shutdown requested, draining in-flight requests");
+                handle.stop(true).await;
+                server.await
+            }
+        };
+        let result = result.unwrap_or_else(|e| Err(io::Error::other(e)));
+
+        self.tx.send_replace(true);
+        if !workers.is_empty() {
+            tracing::info!("Waiting for {} background workers to finish", workers.len());
+            let finished = join_all(workers.iter_mut());
+            if tokio::time::timeout(self.timeout, finished).await.is_err() {
+                tracing::warn!(
+                    "Background workers still running after {}s, aborting them",
+                    self.timeout.as_secs()
+                );
+                for worker in &workers {
+                    worker.abort();
+                }
+            }
+        }
+
+        tracing::info!("Shutdown complete");
+        result
+    }
+}
+
+/// Completes when the process receives SIGTERM or SIGINT
+pub async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::error!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        // Without a signal to wait for, never shut down rather than at once
+        tracing::error!("Failed to listen for SIGINT: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpServer};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::{oneshot, Notify};
+
+    async fn slow(started: web::Data<Notify>) -> &'static str {
+        started.notify_one();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "done"
+    }
+
+    fn server(shutdown: &Shutdown, started: web::Data<Notify>) -> (Server, SocketAddr) {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(started.clone())
+                .route("/slow", web::get().to(slow))
+        })
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(shutdown.timeout().as_secs())
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        (server.run(), addr)
+    }
+
+    #[actix_web::test]
+    async fn test_shutdown_drains_requests_and_stops_workers() {
+        let shutdown = Shutdown::new(Duration::from_secs(5));
+        let started = web::Data::new(Notify::new());
+        let (server, addr) = server(&shutdown, started.clone());
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let worker = tokio::spawn({
+            let finished = finished.clone();
+            let mut signal = shutdown.subscribe();
+            async move {
+                signal.recv().await;
+                finished.store(true, Ordering::SeqCst);
+            }
+        });
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let stopped = tokio::spawn(shutdown.run_until(server, vec![worker], async {
+            let _ = stop_rx.await;
+        }));
+
+        let url = format!("http://{}/slow", addr);
+        let request = tokio::spawn(reqwest::get(url.clone()));
+        // Only stop once the handler is running, so the request is in flight
+        started.notified().await;
+        stop_tx.send(()).unwrap();
+
+        // The request that was in flight still gets its answer
+        let response = request.await.unwrap().unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.text().await.unwrap(), "done");
+
+        stopped.await.unwrap().unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(reqwest::get(url).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_worker_that_ignores_shutdown_is_aborted() {
+        let shutdown = Shutdown::new(Duration::from_millis(100));
+        let (server, _) = server(&shutdown, web::Data::new(Notify::new()));
+        let stuck = tokio::spawn(std::future::pending::<()>());
+
+        let started = std::time::Instant::now();
+        shutdown
+            .run_until(server, vec![stuck], async {})
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
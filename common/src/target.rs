@@ -1,6 +1,8 @@
 //! Functionality for handling various target types in the OSINT platform
 
 use crate::models::TargetType;
+use crate::utils::normalize_url;
+use crate::validation::{is_valid_domain, is_valid_email};
 use crate::{Error, Result};
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -9,13 +11,6 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use uuid::Uuid;
 
-static DOMAIN_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^([a-zA-Z0-9]([a-zA-Z0-9\-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]{2,}$").unwrap()
-});
-
-static EMAIL_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap());
-
 static IPV4_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap()
 });
@@ -41,7 +36,7 @@ impl TargetInput {
             // If type is specified, validate against that type
             match target_type {
                 TargetType::Domain => {
-                    if !DOMAIN_REGEX.is_match(&self.value) {
+                    if !is_valid_domain(&self.value) {
                         return Err(Error::Validation(format!("Invalid domain: {}", self.value)));
                     }
                 }
@@ -54,7 +49,7 @@ impl TargetInput {
                     }
                 }
                 TargetType::Email => {
-                    if !EMAIL_REGEX.is_match(&self.value) {
+                    if !is_valid_email(&self.value) {
                         return Err(Error::Validation(format!("Invalid email: {}", self.value)));
                     }
                 }
@@ -75,11 +70,11 @@ impl TargetInput {
 
 /// Attempts to infer the target type from the provided value
 pub fn infer_target_type(value: &str) -> Result<TargetType> {
-    if DOMAIN_REGEX.is_match(value) {
+    if is_valid_domain(value) {
         return Ok(TargetType::Domain);
     }
 
-    if EMAIL_REGEX.is_match(value) {
+    if is_valid_email(value) {
         return Ok(TargetType::Email);
     }
 
@@ -97,6 +92,19 @@ pub fn infer_target_type(value: &str) -> Result<TargetType> {
     )))
 }
 
+/// Returns the canonical form of a target value so equivalent inputs compare
+/// equal, e.g. `Example.COM.` and `example.com`
+pub fn normalize_target_value(target_type: &TargetType, value: &str) -> String {
+    let value = value.trim();
+
+    match target_type {
+        TargetType::Domain => value.trim_end_matches('.').to_lowercase(),
+        TargetType::Email => value.to_lowercase(),
+        TargetType::Url => normalize_url(value),
+        _ => value.to_string(),
+    }
+}
+
 /// Parse a string representation of target type
 impl FromStr for TargetType {
     type Err = Error;
@@ -164,6 +172,27 @@ impl TargetManager {
         self.targets.push(target);
     }
 
+    /// Returns the stored target equivalent to `input`, creating it if needed.
+    /// The flag is `true` when a new target was created, so repeated imports
+    /// of overlapping lists don't produce duplicates.
+    pub fn ensure_target(&mut self, input: &TargetInput) -> Result<(&Target, bool)> {
+        let target_type =
+            TargetInput::new(input.value.trim().to_string(), input.target_type.clone())
+                .validate()?;
+        let value = normalize_target_value(&target_type, &input.value);
+
+        if let Some(index) = self
+            .targets
+            .iter()
+            .position(|t| t.target_type == target_type && t.value == value)
+        {
+            return Ok((&self.targets[index], false));
+        }
+
+        self.targets.push(Target::new(target_type, &value));
+        Ok((self.targets.last().expect("target was just added"), true))
+    }
+
     pub fn get_target(&self, id: &Uuid) -> Option<&Target> {
         self.targets.iter().find(|t| t.id == *id)
     }
@@ -179,3 +208,52 @@ impl TargetManager {
         &self.targets
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_target_is_idempotent() {
+        let mut manager = TargetManager::new();
+
+        let (first, created) = manager
+            .ensure_target(&TargetInput::new("Example.COM".to_string(), None))
+            .unwrap();
+        let first_id = first.id;
+        assert!(created);
+        assert_eq!(first.value, "example.com");
+        assert_eq!(first.target_type, TargetType::Domain);
+
+        let (second, created) = manager
+            .ensure_target(&TargetInput::new(
+                " example.com ".to_string(),
+                Some(TargetType::Domain),
+            ))
+            .unwrap();
+        assert!(!created);
+        assert_eq!(second.id, first_id);
+        assert_eq!(manager.list_all_targets().len(), 1);
+    }
+
+    #[test]
+    fn test_ensure_target_distinguishes_types_and_values() {
+        let mut manager = TargetManager::new();
+
+        let inputs = [
+            TargetInput::new("example.com".to_string(), None),
+            TargetInput::new("https://Example.com/".to_string(), None),
+            TargetInput::new("https://example.com".to_string(), None),
+            TargetInput::new("Admin@Example.com".to_string(), None),
+            TargetInput::new("admin@example.com".to_string(), None),
+        ];
+        for input in &inputs {
+            manager.ensure_target(input).unwrap();
+        }
+
+        assert_eq!(manager.list_all_targets().len(), 3);
+        assert!(manager
+            .ensure_target(&TargetInput::new("not a target".to_string(), None))
+            .is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! Logging and request correlation
+//!
+//! Every service calls `init` first thing in `main`. `RUST_LOG` filters what
+//! is logged, using `EnvFilter` directives such as `info,sqlx=warn`, and
+//! `LOG_FORMAT=json` switches to one JSON object per line for log shippers.
+//! Records from crates that still log through `log` are forwarded as well.
+//!
+//! Built with the `otel` feature, spans are also exported over OTLP/HTTP to
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` when it is set. Each request span joins the
+//! trace of the W3C `traceparent` header the caller sent, and `trace_headers`
+//! gives the headers that carry the current trace on to the next service.
+//!
+//! The `trace_requests` middleware opens a `request` span around every
+//! request, so everything logged while handling it carries the same
+//! `request_id`. The id is taken from an inbound `X-Request-Id` header when
+//! there is one and echoed on the response. Handlers find it as a
+//! `RequestId` in the request extensions, to pass on to the services they
+//! call, which lets one id follow a request from service to service:
+//!
+//! ```ignore
+//! let _telemetry = telemetry::init("data-collection");
+//! App::new()
+//!     .wrap(Logger::default())
+//!     .wrap(from_fn(telemetry::trace_requests))
+//! ```
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::HttpMessage;
+use tracing::{Instrument, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use uuid::Uuid;
+
+/// Filter used when `RUST_LOG` is unset or can't be parsed
+pub const DEFAULT_FILTER: &str = "info";
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest inbound request id that is propagated as-is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Spans are exported here over OTLP/HTTP, with the `otel` feature
+pub const OTLP_ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Id of the request being handled, set by `trace_requests`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// Reads `LOG_FORMAT`, the same variable the access log middleware uses
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.trim().eq_ignore_ascii_case("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// Flushes exported spans when dropped, so hold it until `main` returns
+#[must_use = "exported spans are flushed when the guard is dropped"]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush exported spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber, configured from `RUST_LOG` and
+/// `LOG_FORMAT`. `service` names the spans' source when they are exported.
+pub fn init(service: &str) -> Telemetry {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+
+    #[cfg(feature = "otel")]
+    let provider = otel::provider(service);
+    #[cfg(feature = "otel")]
+    let export = provider
+        .as_ref()
+        .map(|provider| otel::layer(provider, service));
+    #[cfg(not(feature = "otel"))]
+    let export = {
+        let _ = service;
+        None::<tracing_subscriber::layer::Identity>
+    };
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(LogFormat::from_env(), std::io::stdout))
+        .with(export)
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("Logging was already initialized: {}", e);
+    }
+
+    Telemetry {
+        #[cfg(feature = "otel")]
+        provider,
+    }
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Reuses the caller's request id when it is sane, otherwise generates one
+fn request_id_for(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Middleware running each request inside a span tagged with its id. Use
+/// with `actix_web::middleware::from_fn`, wrapped last so the access log is
+/// inside the span too.
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = request_id_for(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    #[cfg(feature = "otel")]
+    otel::set_parent(&span, req.headers());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut response = next.call(req).instrument(span).await?;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+/// Headers carrying the current trace to a service being called, such as
+/// `traceparent`. Empty unless spans are being exported.
+pub fn trace_headers() -> Vec<(String, String)> {
+    #[cfg(feature = "otel")]
+    return otel::trace_headers();
+    #[cfg(not(feature = "otel"))]
+    Vec::new()
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::OTLP_ENDPOINT_VAR;
+    use actix_web::http::header::HeaderMap;
+    use opentelemetry::propagation::Extractor;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::SpanExporter;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use std::collections::HashMap;
+    use tracing::Subscriber;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    /// Sets up export when `OTEL_EXPORTER_OTLP_ENDPOINT` is set. The
+    /// exporter reads the endpoint, and the other `OTEL_EXPORTER_OTLP_*`
+    /// settings, itself.
+    pub fn provider(service: &str) -> Option<SdkTracerProvider> {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        std::env::var_os(OTLP_ENDPOINT_VAR)?;
+
+        let exporter = match SpanExporter::builder().with_http().build() {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("Failed to set up span export: {}", e);
+                return None;
+            }
+        };
+        Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(
+                    Resource::builder()
+                        .with_service_name(service.to_string())
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    pub fn layer<S>(provider: &SdkTracerProvider, service: &str) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(service.to_string()))
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|name| name.as_str()).collect()
+        }
+    }
+
+    /// Makes `span` part of the trace the caller's headers carry, if any
+    pub fn set_parent(span: &tracing::Span, headers: &HeaderMap) {
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        // Fails only when spans aren't being exported
+        let _ = span.set_parent(parent);
+    }
+
+    pub fn trace_headers() -> Vec<(String, String)> {
+        let mut headers = HashMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&tracing::Span::current().context(), &mut headers)
+        });
+        headers.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App, HttpRequest, HttpResponse};
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Log output kept in memory
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn lines(&self) -> Vec<serde_json::Value> {
+            let output = self.0.lock().unwrap();
+            String::from_utf8_lossy(&output)
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    async fn lookup(req: HttpRequest) -> HttpResponse {
+        tracing::info!("looking up scan");
+        match req.extensions().get::<RequestId>() {
+            Some(RequestId(id)) => HttpResponse::Ok().body(id.clone()),
+            None => HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_request_span_carries_request_id() {
+        let captured = Captured::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(
+            fmt_layer(LogFormat::Json, {
+                let captured = captured.clone();
+                move || captured.clone()
+            }),
+        ));
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(trace_requests))
+                .route("/scans", web::get().to(lookup)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/scans")
+            .insert_header(("X-Request-Id", "upstream-abc-123"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "upstream-abc-123"
+        );
+
+        let req = TestRequest::get().uri("/scans").to_request();
+        let resp = call_service(&app, req).await;
+        let generated = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(Uuid::parse_str(&generated).is_ok());
+        assert_eq!(read_body(resp).await, generated.as_bytes());
+
+        let lines = captured.lines();
+        assert_eq!(lines.len(), 2);
+        for (line, request_id) in lines.iter().zip(["upstream-abc-123", generated.as_str()]) {
+            assert_eq!(line["fields"]["message"], "looking up scan");
+            assert_eq!(line["span"]["name"], "request");
+            assert_eq!(line["span"]["request_id"], request_id);
+            assert_eq!(line["span"]["method"], "GET");
+            assert_eq!(line["span"]["path"], "/scans");
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    #[actix_web::test]
+    async fn test_request_span_joins_callers_trace() {
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+        use opentelemetry_sdk::trace::SdkTracerProvider;
+
+        async fn call_downstream() -> HttpResponse {
+            let headers = trace_headers();
+            match headers.iter().find(|(name, _)| name == "traceparent") {
+                Some((_, value)) => HttpResponse::Ok().body(value.clone()),
+                None => HttpResponse::InternalServerError().finish(),
+            }
+        }
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(otel::layer(&provider, "test-service")),
+        );
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(trace_requests))
+                .route("/scans", web::get().to(call_downstream)),
+        )
+        .await;
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let req = TestRequest::get()
+            .uri("/scans")
+            .insert_header((
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-01", trace_id),
+            ))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        // Same trace, with our span as the parent
+        let body = read_body(resp).await;
+        let traceparent: Vec<&str> = std::str::from_utf8(&body).unwrap().split('-').collect();
+        assert_eq!(traceparent[1], trace_id);
+        assert_ne!(traceparent[2], "00f067aa0ba902b7");
+    }
+}
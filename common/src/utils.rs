@@ -10,58 +10,57 @@ pub mod ip {
     use std::net::IpAddr;
     use std::str::FromStr;
 
-    pub fn is_valid_ip(ip: &str) -> bool {
-        IpAddr::from_str(ip).is_ok()
-    }
+    pub use crate::validation::is_valid_ip;
 
     pub fn is_internal_ip(ip: &str) -> bool {
-        if let Ok(ip) = IpAddr::from_str(ip) {
-            match ip {
-                IpAddr::V4(ipv4) => {
-                    ipv4.is_private()
-                        || ipv4.is_loopback()
-                        || ipv4.is_link_local()
-                        || ipv4.is_documentation()
-                }
-                IpAddr::V6(ipv6) => ipv6.is_loopback() || ipv6.is_unspecified(),
+        IpAddr::from_str(ip)
+            .map(|ip| is_internal_addr(&ip))
+            .unwrap_or(false)
+    }
+
+    /// Whether `ip` is not publicly routable: private, loopback, link-local
+    /// (which holds cloud metadata endpoints), shared, unspecified and
+    /// similar ranges, including IPv4 addresses mapped into IPv6
+    pub fn is_internal_addr(ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ipv4) => {
+                let [a, b, ..] = ipv4.octets();
+                ipv4.is_private()
+                    || ipv4.is_loopback()
+                    || ipv4.is_link_local()
+                    || ipv4.is_documentation()
+                    || ipv4.is_unspecified()
+                    || ipv4.is_broadcast()
+                    || ipv4.is_multicast()
+                    // Carrier-grade NAT, 100.64.0.0/10
+                    || (a == 100 && (64..128).contains(&b))
+                    || a == 0
             }
-        } else {
-            false
+            IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+                Some(ipv4) => is_internal_addr(&IpAddr::V4(ipv4)),
+                None => {
+                    let first = ipv6.segments()[0];
+                    ipv6.is_loopback()
+                        || ipv6.is_unspecified()
+                        || ipv6.is_multicast()
+                        // Unique local, fc00::/7
+                        || (first & 0xfe00) == 0xfc00
+                        // Link-local, fe80::/10
+                        || (first & 0xffc0) == 0xfe80
+                }
+            },
         }
     }
 }
 
 /// Utility functions for domain operations
 pub mod domain {
-    use lazy_static::lazy_static;
-    use regex::Regex;
-
-    lazy_static! {
-        static ref DOMAIN_REGEX: Regex = Regex::new(
-            r"^(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z0-9][a-z0-9-]{0,61}[a-z0-9]$"
-        )
-        .unwrap();
-    }
-
-    pub fn is_valid_domain(domain: &str) -> bool {
-        DOMAIN_REGEX.is_match(domain)
-    }
+    pub use crate::validation::is_valid_domain;
 }
 
 /// Utility functions for email operations
 pub mod email {
-    use lazy_static::lazy_static;
-    use regex::Regex;
-
-    lazy_static! {
-        static ref EMAIL_REGEX: Regex = Regex::new(
-            r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"
-        ).unwrap();
-    }
-
-    pub fn is_valid_email(email: &str) -> bool {
-        EMAIL_REGEX.is_match(email)
-    }
+    pub use crate::validation::is_valid_email;
 }
 
 /// Timing utilities
@@ -0,0 +1,144 @@
+//! Shared input validators
+//!
+//! These are the single source of truth for target validation across the
+//! platform; `core::helpers` and `modules/common` re-export them so every
+//! service judges the same input the same way.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::net::IpAddr;
+use url::Url;
+
+// Dot-atom local part (no leading, trailing, or consecutive dots) followed by
+// a domain of valid labels ending in an alphabetic TLD.
+static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+)*@(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]{2,63}$",
+    )
+    .unwrap()
+});
+
+static DOMAIN_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]{2,63}$").unwrap()
+});
+
+/// Validates if the given string is a valid email address.
+pub fn is_valid_email(email: &str) -> bool {
+    if email.len() > 254 {
+        return false;
+    }
+    match email.rsplit_once('@') {
+        Some((local, _)) if local.len() <= 64 => EMAIL_REGEX.is_match(email),
+        _ => false,
+    }
+}
+
+/// Validates if the given string is an absolute HTTP or HTTPS URL.
+pub fn is_valid_url(url_str: &str) -> bool {
+    match Url::parse(url_str) {
+        Ok(url) => (url.scheme() == "http" || url.scheme() == "https") && url.has_host(),
+        Err(_) => false,
+    }
+}
+
+/// Validates if the given string is a valid IP address (IPv4 or IPv6).
+pub fn is_valid_ip(ip_str: &str) -> bool {
+    ip_str.parse::<IpAddr>().is_ok()
+}
+
+/// Validates if the given string is a valid domain name.
+///
+/// Requires at least two labels and an alphabetic TLD, so bare IP addresses
+/// and single-label hosts such as `localhost` are rejected.
+pub fn is_valid_domain(domain: &str) -> bool {
+    domain.len() <= 253 && DOMAIN_REGEX.is_match(domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_url() {
+        assert!(is_valid_url("https://example.com"));
+        assert!(is_valid_url("http://example.com:8080/path?q=1"));
+        assert!(!is_valid_url("ftp://example.com"));
+        assert!(!is_valid_url("http://"));
+        assert!(!is_valid_url("example.com"));
+
+        // The old modules pattern only looked for a lowercase `http(s)://` prefix
+        assert!(is_valid_url("HTTPS://example.com"));
+        assert!(!is_valid_url("http://exa mple.com"));
+        assert!(!is_valid_url("mailto:user@example.com"));
+        assert!(!is_valid_url("file:///etc/passwd"));
+    }
+
+    #[test]
+    fn test_regexes_compiled_once() {
+        use std::time::{Duration, Instant};
+
+        assert!(is_valid_email("user@example.com"));
+        assert!(is_valid_domain("example.com"));
+        let email_regex = Lazy::get(&EMAIL_REGEX).expect("email regex initialised");
+        let domain_regex = Lazy::get(&DOMAIN_REGEX).expect("domain regex initialised");
+
+        // Compiling the email pattern on every call takes about a minute for
+        // this many in a debug build; matching the compiled statics, well
+        // under a second
+        let started = Instant::now();
+        for i in 0..100_000 {
+            let email = if i % 2 == 0 {
+                "user@example.com"
+            } else {
+                "a@b..c"
+            };
+            assert_eq!(is_valid_email(email), i % 2 == 0);
+            assert!(is_valid_domain("example.com"));
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        // And the same compiled patterns served every call
+        assert!(std::ptr::eq(email_regex, Lazy::get(&EMAIL_REGEX).unwrap()));
+        assert!(std::ptr::eq(
+            domain_regex,
+            Lazy::get(&DOMAIN_REGEX).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_is_valid_email() {
+        assert!(is_valid_email("user@example.com"));
+        assert!(is_valid_email("user+tag@sub.example.co.uk"));
+        // The old target pattern only allowed `._%+-` in the local part
+        assert!(is_valid_email("o'brien@example.ie"));
+
+        // Both old patterns let dots fall anywhere in the local part, and the
+        // target one in the domain too
+        assert!(!is_valid_email(".user@example.com"));
+        assert!(!is_valid_email("user.@example.com"));
+        assert!(!is_valid_email("user@example..com"));
+        // The old utils pattern didn't require a TLD
+        assert!(!is_valid_email("user@example"));
+        assert!(!is_valid_email("user@localhost"));
+        // Neither limited the length of the local part
+        assert!(!is_valid_email(&format!("{}@example.com", "a".repeat(65))));
+    }
+
+    #[test]
+    fn test_is_valid_domain() {
+        assert!(is_valid_domain("example.com"));
+        assert!(is_valid_domain("xn--bcher-kva.example"));
+        // The old utils pattern was lowercase only
+        assert!(is_valid_domain("Example.COM"));
+
+        // The old utils pattern took a numeric last label as a TLD
+        assert!(!is_valid_domain("10.0.0.10"));
+        assert!(!is_valid_domain("example.123"));
+        assert!(!is_valid_domain("localhost"));
+        assert!(!is_valid_domain("-example.com"));
+        assert!(!is_valid_domain(&format!("{}.com", "a".repeat(64))));
+        // Neither limited the length of the whole name
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert!(!is_valid_domain(&format!("{}.com", long)));
+    }
+}
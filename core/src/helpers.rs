@@ -1,28 +1,44 @@
-use regex::Regex;
 use std::net::IpAddr;
 use url::Url;
-use lazy_static::lazy_static;
 
-lazy_static! {
-    static ref EMAIL_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap();
-}
+// Validators live in mirage_common so every service agrees on what is valid.
+pub use mirage_common::validation::{is_valid_domain, is_valid_email, is_valid_ip, is_valid_url};
 
-/// Validates if the given string is a valid email address.
-pub fn is_valid_email(email: &str) -> bool {
-    EMAIL_REGEX.is_match(email)
+/// The kind of target a raw input string most likely refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetType {
+    Email,
+    Ipv4,
+    Ipv6,
+    Url,
+    Domain,
+    Unknown,
 }
 
-/// Validates if the given string is a valid URL.
-pub fn is_valid_url(url_str: &str) -> bool {
-    match Url::parse(url_str) {
-        Ok(url) => url.scheme() == "http" || url.scheme() == "https",
-        Err(_) => false,
+/// Classifies an arbitrary input string by trying each validator in
+/// precedence order: email, IPv4, IPv6, URL, then domain.
+pub fn infer_target_type(input: &str) -> TargetType {
+    let input = input.trim();
+
+    if is_valid_email(input) {
+        return TargetType::Email;
     }
-}
 
-/// Validates if the given string is a valid IP address (IPv4 or IPv6).
-pub fn is_valid_ip(ip_str: &str) -> bool {
-    ip_str.parse::<IpAddr>().is_ok()
+    match input.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => return TargetType::Ipv4,
+        Ok(IpAddr::V6(_)) => return TargetType::Ipv6,
+        Err(_) => {}
+    }
+
+    if is_valid_url(input) {
+        return TargetType::Url;
+    }
+
+    if is_valid_domain(input) {
+        return TargetType::Domain;
+    }
+
+    TargetType::Unknown
 }
 
 /// Extracts domain name from a URL.
@@ -48,3 +64,103 @@ pub fn normalize_url(url_str: &str) -> Option<String> {
 pub fn is_valid_port(port: u16) -> bool {
     port > 0 && port < 65536
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_email() {
+        let cases = [
+            ("user@example.com", true),
+            ("user+tag@sub.example.co.uk", true),
+            ("first.last@example.org", true),
+            ("o'brien@example.ie", true),
+            ("user_name-1@example-mail.com", true),
+            ("x@example.io", true),
+            ("!#$%&'*+/=?^_`{|}~-@example.com", true),
+            ("user@xn--bcher-kva.example", true),
+            ("a@b..c", false),
+            ("user..name@example.com", false),
+            (".user@example.com", false),
+            ("user.@example.com", false),
+            ("user@example", false),
+            ("user@-example.com", false),
+            ("user@example-.com", false),
+            ("user@.example.com", false),
+            ("user@example.com.", false),
+            ("user@example.c", false),
+            ("user@@example.com", false),
+            ("user name@example.com", false),
+            ("@example.com", false),
+            ("user@", false),
+            ("user@192.168.0.1", false),
+            ("plainaddress", false),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(is_valid_email(input), expected, "input: {:?}", input);
+        }
+
+        let long_local = format!("{}@example.com", "a".repeat(65));
+        assert!(!is_valid_email(&long_local));
+    }
+
+    #[test]
+    fn test_helpers_agree_with_common_utils() {
+        let corpus = [
+            "user@example.com",
+            "a@b..c",
+            "example.com",
+            "localhost",
+            "192.168.1.1",
+            "https://example.com",
+        ];
+
+        for input in corpus {
+            assert_eq!(is_valid_email(input), mirage_common::utils::email::is_valid_email(input));
+            assert_eq!(is_valid_domain(input), mirage_common::utils::domain::is_valid_domain(input));
+            assert_eq!(is_valid_ip(input), mirage_common::utils::ip::is_valid_ip(input));
+        }
+    }
+
+    #[test]
+    fn test_is_valid_domain() {
+        assert!(is_valid_domain("example.com"));
+        assert!(is_valid_domain("sub.example.co.uk"));
+        assert!(is_valid_domain("xn--bcher-kva.example"));
+        assert!(!is_valid_domain("192.168.1.1"));
+        assert!(!is_valid_domain("localhost"));
+        assert!(!is_valid_domain("-bad.example.com"));
+        assert!(!is_valid_domain("bad-.example.com"));
+        assert!(!is_valid_domain("example..com"));
+        assert!(!is_valid_domain("example.c0m"));
+    }
+
+    #[test]
+    fn test_infer_target_type() {
+        assert_eq!(infer_target_type("user@example.com"), TargetType::Email);
+        assert_eq!(infer_target_type("10.0.0.1"), TargetType::Ipv4);
+        assert_eq!(infer_target_type("2001:db8::1"), TargetType::Ipv6);
+        assert_eq!(infer_target_type("https://example.com/path"), TargetType::Url);
+        assert_eq!(infer_target_type("example.com"), TargetType::Domain);
+        assert_eq!(infer_target_type("not a target"), TargetType::Unknown);
+        assert_eq!(infer_target_type(""), TargetType::Unknown);
+    }
+
+    #[test]
+    fn test_infer_target_type_ambiguous_inputs() {
+        // Dotted quads look like domains but must be classified as IPs.
+        assert_eq!(infer_target_type("8.8.8.8"), TargetType::Ipv4);
+        // An out-of-range octet is neither an IP nor a domain.
+        assert_eq!(infer_target_type("256.1.1.1"), TargetType::Unknown);
+        // A URL containing an email-like userinfo is still a URL.
+        assert_eq!(infer_target_type("http://user@example.com"), TargetType::Url);
+        // Non-HTTP schemes are not routed as URLs.
+        assert_eq!(infer_target_type("ftp://example.com"), TargetType::Unknown);
+        // Surrounding whitespace is ignored.
+        assert_eq!(infer_target_type("  example.org  "), TargetType::Domain);
+        // IPv4-mapped IPv6 addresses stay IPv6.
+        assert_eq!(infer_target_type("::ffff:192.0.2.1"), TargetType::Ipv6);
+    }
+}
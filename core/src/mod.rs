@@ -5,4 +5,4 @@ pub mod helpers;
 // Re-exports for convenience
 pub use event::{Event, EventHandler};
 pub use target::{Target, TargetManager};
-pub use helpers::{is_valid_email, is_valid_url, is_valid_ip};
+pub use helpers::{infer_target_type, is_valid_domain, is_valid_email, is_valid_ip, is_valid_url, TargetType};
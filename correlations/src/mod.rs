@@ -1,19 +1,50 @@
 pub mod cloud_bucket_open;
 pub mod dns_zone_transfer;
 pub mod email_breach;
+pub mod subdomain_takeover;
+pub mod sources;
 
 // Common types and traits for correlation rules
 use crate::core::event::Event;
 use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashSet};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Ordered from least to most severe, so `Critical > High > Medium > Low`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Severity {
-    Low,
-    Medium,
-    High,
-    Critical,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+    Critical = 4,
 }
 
+impl Severity {
+    /// The same lowercase name used when serializing
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// Base score of an alert with this severity
+    pub fn weight(&self) -> u32 {
+        match self {
+            Severity::Low => 10,
+            Severity::Medium => 40,
+            Severity::High => 70,
+            Severity::Critical => 100,
+        }
+    }
+}
+
+/// Extra events add to an alert's score up to this many
+const MAX_SCORED_EXTRA_EVENTS: u32 = 10;
+const SCORE_PER_EXTRA_EVENT: u32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
     title: String,
@@ -33,6 +64,50 @@ impl Alert {
             timestamp,
         }
     }
+
+    /// Get the alert title.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Get the alert description.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Get the alert severity.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Get the events that triggered the alert.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Get the time the alert refers to, in seconds since the epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Priority of the alert: its severity's weight plus a bonus for each
+    /// correlated event beyond the first. The bonus is capped below the gap
+    /// between severities, so event count only orders alerts of equal
+    /// severity.
+    pub fn score(&self) -> u32 {
+        let extra_events = (self.events.len() as u32).saturating_sub(1);
+        self.severity.weight() + extra_events.min(MAX_SCORED_EXTRA_EVENTS) * SCORE_PER_EXTRA_EVENT
+    }
+}
+
+/// Sorts alerts by descending score, newest first among equal scores
+pub fn rank_alerts(mut alerts: Vec<Alert>) -> Vec<Alert> {
+    alerts.sort_by(|a, b| {
+        b.score()
+            .cmp(&a.score())
+            .then_with(|| b.timestamp.cmp(&a.timestamp))
+    });
+    alerts
 }
 
 pub trait CorrelationRule {
@@ -40,3 +115,347 @@ pub trait CorrelationRule {
     fn description(&self) -> &str;
     fn analyze(&self, events: &[Event]) -> Vec<Alert>;
 }
+
+/// Correlation rules keyed by `name()`, so callers can choose per run which
+/// ones apply
+#[derive(Default)]
+pub struct RuleRegistry {
+    rules: BTreeMap<String, Box<dyn CorrelationRule>>,
+}
+
+impl RuleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding every built-in rule
+    pub fn with_default_rules() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(cloud_bucket_open::CloudBucketOpenRule::new()));
+        registry.register(Box::new(dns_zone_transfer::DnsZoneTransferRule::new()));
+        registry.register(Box::new(email_breach::EmailBreachRule::new()));
+        registry.register(Box::new(subdomain_takeover::SubdomainTakeoverRule::new()));
+        registry
+    }
+
+    /// Adds a rule, returning any rule previously registered under its name
+    pub fn register(&mut self, rule: Box<dyn CorrelationRule>) -> Option<Box<dyn CorrelationRule>> {
+        self.rules.insert(rule.name().to_string(), rule)
+    }
+
+    /// Names of the registered rules, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rules.keys().map(String::as_str)
+    }
+
+    /// Runs the rules named in `enabled` over `events`. Unknown names are
+    /// ignored.
+    pub fn run_enabled(&self, events: &[Event], enabled: &HashSet<String>) -> Vec<Alert> {
+        self.rules
+            .iter()
+            .filter(|(name, _)| enabled.contains(name.as_str()))
+            .flat_map(|(_, rule)| rule.analyze(events))
+            .collect()
+    }
+}
+
+/// What a rule concluded about one bucket of events
+pub struct Finding {
+    pub title: String,
+    pub description: String,
+    pub severity: Severity,
+}
+
+/// Events about one target that fell in the same time window
+pub struct EventBucket<'a> {
+    pub target: String,
+    /// Start of the window, inclusive
+    pub window_start: u64,
+    /// Events in timestamp order
+    pub events: Vec<&'a Event>,
+}
+
+impl EventBucket<'_> {
+    /// Timestamp of the newest event in the bucket
+    pub fn last_seen(&self) -> u64 {
+        self.events.last().map_or(self.window_start, |event| event.timestamp())
+    }
+}
+
+/// Groups events by target and fixed time window so a rule only has to
+/// decide what each bucket means. Events about the same target in different
+/// windows are never correlated with each other.
+pub struct WindowedCorrelator<F> {
+    window_secs: u64,
+    target: F,
+}
+
+impl<F> WindowedCorrelator<F>
+where
+    F: Fn(&Event) -> Option<String>,
+{
+    /// `target` picks the key events are grouped by; events it returns
+    /// `None` for are ignored
+    pub fn new(window_secs: u64, target: F) -> Self {
+        WindowedCorrelator {
+            window_secs: window_secs.max(1),
+            target,
+        }
+    }
+
+    /// Buckets ordered by target, then window
+    pub fn buckets<'a>(&self, events: &'a [Event]) -> Vec<EventBucket<'a>> {
+        let mut buckets: BTreeMap<(String, u64), Vec<&'a Event>> = BTreeMap::new();
+
+        for event in events {
+            if let Some(target) = (self.target)(event) {
+                let window_start = event.timestamp() - event.timestamp() % self.window_secs;
+                buckets.entry((target, window_start)).or_default().push(event);
+            }
+        }
+
+        buckets
+            .into_iter()
+            .map(|((target, window_start), mut events)| {
+                events.sort_by_key(|event| event.timestamp());
+                EventBucket {
+                    target,
+                    window_start,
+                    events,
+                }
+            })
+            .collect()
+    }
+
+    /// Runs `rule` on every bucket, turning each finding into an alert that
+    /// carries the bucket's events and the time of its newest event
+    pub fn correlate<R>(&self, events: &[Event], mut rule: R) -> Vec<Alert>
+    where
+        R: FnMut(&EventBucket) -> Option<Finding>,
+    {
+        self.buckets(events)
+            .iter()
+            .filter_map(|bucket| {
+                let finding = rule(bucket)?;
+                Some(Alert::new(
+                    &finding.title,
+                    &finding.description,
+                    finding.severity,
+                    bucket.events.iter().map(|event| (*event).clone()).collect(),
+                    bucket.last_seen(),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3600;
+
+    fn event(data: &str, timestamp: u64) -> Event {
+        Event::new("PORT_OPEN", data, None, timestamp)
+    }
+
+    fn by_data(event: &Event) -> Option<String> {
+        Some(event.data().to_string())
+    }
+
+    #[test]
+    fn test_events_in_different_windows_do_not_correlate() {
+        let correlator = WindowedCorrelator::new(HOUR, by_data);
+        let events = vec![
+            event("10.0.0.1", 10 * HOUR + 5),
+            event("10.0.0.1", 10 * HOUR + 50),
+            // Same target, next window
+            event("10.0.0.1", 11 * HOUR + 1),
+            event("10.0.0.2", 10 * HOUR + 20),
+        ];
+
+        let buckets = correlator.buckets(&events);
+        let shape: Vec<(&str, u64, usize)> = buckets
+            .iter()
+            .map(|b| (b.target.as_str(), b.window_start, b.events.len()))
+            .collect();
+        assert_eq!(
+            shape,
+            vec![
+                ("10.0.0.1", 10 * HOUR, 2),
+                ("10.0.0.1", 11 * HOUR, 1),
+                ("10.0.0.2", 10 * HOUR, 1),
+            ]
+        );
+
+        // A rule wanting two sightings only fires for the first window
+        let alerts = correlator.correlate(&events, |bucket| {
+            (bucket.events.len() >= 2).then(|| Finding {
+                title: format!("Repeated: {}", bucket.target),
+                description: String::new(),
+                severity: Severity::Low,
+            })
+        });
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].title(), "Repeated: 10.0.0.1");
+        assert_eq!(alerts[0].events().len(), 2);
+        assert_eq!(alerts[0].timestamp(), 10 * HOUR + 50);
+    }
+
+    #[test]
+    fn test_alert_round_trips_through_json() {
+        let alert = Alert::new(
+            "Open Port: 10.0.0.1",
+            "Port 22 is reachable",
+            Severity::Critical,
+            vec![Event::new("PORT_OPEN", "10.0.0.1:22", Some("portscan"), 42)],
+            42,
+        );
+
+        let json = serde_json::to_value(&alert).unwrap();
+        assert_eq!(json["severity"], "critical");
+
+        let decoded: Alert = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.title(), alert.title());
+        assert_eq!(decoded.description(), alert.description());
+        assert_eq!(decoded.severity(), Severity::Critical);
+        assert_eq!(decoded.timestamp(), 42);
+        assert_eq!(decoded.events().len(), 1);
+        assert_eq!(decoded.events()[0].data(), "10.0.0.1:22");
+        assert_eq!(decoded.events()[0].source(), Some("portscan"));
+    }
+
+    #[test]
+    fn test_severity_names_are_lowercase() {
+        for severity in [
+            Severity::Low,
+            Severity::Medium,
+            Severity::High,
+            Severity::Critical,
+        ] {
+            let json = serde_json::to_string(&severity).unwrap();
+            assert_eq!(json, format!("\"{}\"", severity.as_str()));
+            assert_eq!(serde_json::from_str::<Severity>(&json).unwrap(), severity);
+        }
+    }
+
+    /// Raises one alert per event of its type
+    struct EchoRule(&'static str);
+
+    impl CorrelationRule for EchoRule {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn description(&self) -> &str {
+            "Echoes events of its own type"
+        }
+
+        fn analyze(&self, events: &[Event]) -> Vec<Alert> {
+            events
+                .iter()
+                .filter(|event| event.event_type() == self.0)
+                .map(|event| {
+                    Alert::new(self.0, "", Severity::Low, vec![event.clone()], event.timestamp())
+                })
+                .collect()
+        }
+    }
+
+    fn registry() -> RuleRegistry {
+        let mut registry = RuleRegistry::new();
+        registry.register(Box::new(EchoRule("PORT_OPEN")));
+        registry.register(Box::new(EchoRule("DNS_RECORD")));
+        registry
+    }
+
+    fn enabled(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_disabled_rule_produces_no_alerts() {
+        let events = vec![
+            Event::new("PORT_OPEN", "10.0.0.1:22", None, 1),
+            Event::new("DNS_RECORD", "example.com A 10.0.0.1", None, 2),
+        ];
+
+        let alerts = registry().run_enabled(&events, &enabled(&["DNS_RECORD"]));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].title(), "DNS_RECORD");
+
+        let alerts = registry().run_enabled(&events, &enabled(&["DNS_RECORD", "PORT_OPEN"]));
+        assert_eq!(alerts.len(), 2);
+
+        assert!(registry().run_enabled(&events, &enabled(&[])).is_empty());
+        assert!(registry()
+            .run_enabled(&events, &enabled(&["NOT_A_RULE"]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_registering_a_name_twice_replaces_the_rule() {
+        let mut registry = registry();
+        assert!(registry.register(Box::new(EchoRule("PORT_OPEN"))).is_some());
+        assert_eq!(registry.names().collect::<Vec<_>>(), vec!["DNS_RECORD", "PORT_OPEN"]);
+    }
+
+    fn alert(title: &str, severity: Severity, events: usize, timestamp: u64) -> Alert {
+        let events = (0..events)
+            .map(|i| Event::new("PORT_OPEN", &i.to_string(), None, timestamp))
+            .collect();
+        Alert::new(title, "", severity, events, timestamp)
+    }
+
+    #[test]
+    fn test_alerts_rank_by_severity_then_event_count() {
+        let alerts = vec![
+            alert("lone low", Severity::Low, 1, 5),
+            alert("busy medium", Severity::Medium, 50, 5),
+            alert("lone critical", Severity::Critical, 1, 5),
+            alert("busy critical", Severity::Critical, 8, 5),
+            alert("high", Severity::High, 3, 5),
+            alert("newer high", Severity::High, 3, 9),
+        ];
+
+        let ranked = rank_alerts(alerts);
+        let titles: Vec<&str> = ranked.iter().map(|a| a.title()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "busy critical",
+                "lone critical",
+                "newer high",
+                "high",
+                "busy medium",
+                "lone low",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_severity_order_is_explicit() {
+        assert!(Severity::Critical > Severity::High);
+        assert!(Severity::High > Severity::Medium);
+        assert!(Severity::Medium > Severity::Low);
+        // Many events never lift an alert past the next severity
+        assert!(
+            alert("", Severity::Low, 1000, 0).score() < alert("", Severity::Medium, 1, 0).score()
+        );
+    }
+
+    #[test]
+    fn test_untargeted_events_are_ignored() {
+        let correlator = WindowedCorrelator::new(HOUR, |event: &Event| {
+            event.source().map(str::to_string)
+        });
+        let events = vec![
+            Event::new("DNS_RECORD", "a", Some("example.com"), 1),
+            Event::new("DNS_RECORD", "b", None, 2),
+        ];
+
+        let buckets = correlator.buckets(&events);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].target, "example.com");
+    }
+}
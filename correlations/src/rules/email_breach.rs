@@ -1,6 +1,9 @@
 use crate::core::event::Event;
-use crate::correlations::{CorrelationRule, Alert, Severity};
-use std::collections::{HashMap, HashSet};
+use crate::correlations::{CorrelationRule, Alert, Finding, Severity, WindowedCorrelator};
+
+/// Breach sightings of an address within this window count towards the
+/// same alert
+const BREACH_WINDOW_SECS: u64 = 24 * 60 * 60;
 
 /// Correlation rule for detecting compromised email addresses
 pub struct EmailBreachRule;
@@ -9,6 +12,14 @@ impl EmailBreachRule {
     pub fn new() -> Self {
         EmailBreachRule
     }
+
+    fn breached_email(event: &Event) -> Option<String> {
+        let is_breach = event.event_type() == "EMAIL_BREACH" ||
+            (event.event_type() == "BREACH_DATA" && event.data().contains("@"));
+
+        // In a real implementation, we would extract the email more carefully
+        is_breach.then(|| event.data().to_string())
+    }
 }
 
 impl CorrelationRule for EmailBreachRule {
@@ -21,48 +32,52 @@ impl CorrelationRule for EmailBreachRule {
     }
     
     fn analyze(&self, events: &[Event]) -> Vec<Alert> {
-        let mut alerts = Vec::new();
-        let mut breach_count: HashMap<String, Vec<&Event>> = HashMap::new();
-        let mut seen_emails = HashSet::new();
-        
-        // Find all email breach events and group by email
-        for event in events {
-            if event.event_type() == "EMAIL_BREACH" || 
-               (event.event_type() == "BREACH_DATA" && event.data().contains("@")) {
-                
-                // In a real implementation, we would extract the email more carefully
-                let email = event.data().to_string();
-                
-                if !seen_emails.contains(&email) {
-                    seen_emails.insert(email.clone());
-                    breach_count.entry(email).or_insert_with(Vec::new).push(event);
-                }
-            }
-        }
-        
-        // Create alerts for each breached email
-        for (email, events) in breach_count {
+        WindowedCorrelator::new(BREACH_WINDOW_SECS, Self::breached_email).correlate(events, |bucket| {
+            let email = &bucket.target;
+
             // Determine severity based on number of breach events
-            let severity = match events.len() {
+            let severity = match bucket.events.len() {
                 1 => Severity::Low,
                 2..=3 => Severity::Medium,
                 4..=10 => Severity::High,
                 _ => Severity::Critical,
             };
-            
-            let alert = Alert::new(
-                &format!("Email Breach: {}", email),
-                &format!("Email address {} has been found in {} data breach(es)", email, events.len()),
+
+            Some(Finding {
+                title: format!("Email Breach: {}", email),
+                description: format!("Email address {} has been found in {} data breach(es)", email, bucket.events.len()),
                 severity,
-                events.into_iter().cloned().collect(),
-                std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-            );
-            alerts.push(alert);
-        }
-        
-        alerts
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn breach(email: &str, timestamp: u64) -> Event {
+        Event::new("EMAIL_BREACH", email, Some("haveibeenpwned"), timestamp)
+    }
+
+    #[test]
+    fn test_breaches_are_counted_per_window() {
+        let events = vec![
+            breach("alice@example.com", 100 * DAY + 10),
+            breach("alice@example.com", 100 * DAY + 20),
+            breach("alice@example.com", 100 * DAY + 30),
+            breach("alice@example.com", 103 * DAY),
+            Event::new("DNS_RECORD", "alice@example.com", None, 100 * DAY),
+        ];
+
+        let alerts = EmailBreachRule::new().analyze(&events);
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].severity(), Severity::Medium);
+        assert_eq!(alerts[0].events().len(), 3);
+        assert_eq!(alerts[1].severity(), Severity::Low);
+        assert_eq!(alerts[1].timestamp(), 103 * DAY);
     }
 }
@@ -0,0 +1,280 @@
+use crate::core::event::Event;
+use crate::correlations::{CorrelationRule, Alert, Severity};
+use mirage_common::target::{normalize_target_value, TargetType};
+use std::collections::{BTreeMap, HashMap};
+
+/// What a dangling resource looks like from the outside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TakeoverSignal {
+    /// The provider answers, but has no content for the host
+    Http404,
+    /// The CNAME target no longer resolves
+    NxDomain,
+}
+
+/// A hosting provider whose resources can be claimed by anyone once the
+/// owner releases them
+#[derive(Debug, Clone)]
+pub struct TakeoverFingerprint {
+    pub service: &'static str,
+    pub cname_suffixes: &'static [&'static str],
+    pub signals: &'static [TakeoverSignal],
+}
+
+impl TakeoverFingerprint {
+    fn matches(&self, target: &str) -> bool {
+        self.cname_suffixes
+            .iter()
+            .any(|suffix| target == *suffix || target.ends_with(&format!(".{}", suffix)))
+    }
+}
+
+/// Providers known to be vulnerable to subdomain takeover. Add an entry here
+/// to teach the rule about a new one.
+pub const DEFAULT_FINGERPRINTS: &[TakeoverFingerprint] = &[
+    TakeoverFingerprint {
+        service: "GitHub Pages",
+        cname_suffixes: &["github.io"],
+        signals: &[TakeoverSignal::Http404],
+    },
+    TakeoverFingerprint {
+        service: "AWS S3",
+        cname_suffixes: &[
+            "s3.amazonaws.com",
+            "s3-website-us-east-1.amazonaws.com",
+            "s3-website-us-west-2.amazonaws.com",
+            "s3-website-eu-west-1.amazonaws.com",
+        ],
+        signals: &[TakeoverSignal::Http404],
+    },
+    TakeoverFingerprint {
+        service: "Heroku",
+        cname_suffixes: &["herokuapp.com", "herokudns.com"],
+        signals: &[TakeoverSignal::Http404, TakeoverSignal::NxDomain],
+    },
+    TakeoverFingerprint {
+        service: "Azure",
+        cname_suffixes: &[
+            "azurewebsites.net",
+            "cloudapp.net",
+            "trafficmanager.net",
+            "blob.core.windows.net",
+        ],
+        signals: &[TakeoverSignal::NxDomain],
+    },
+    TakeoverFingerprint {
+        service: "Shopify",
+        cname_suffixes: &["myshopify.com"],
+        signals: &[TakeoverSignal::Http404],
+    },
+    TakeoverFingerprint {
+        service: "Surge.sh",
+        cname_suffixes: &["surge.sh"],
+        signals: &[TakeoverSignal::Http404],
+    },
+    TakeoverFingerprint {
+        service: "Pantheon",
+        cname_suffixes: &["pantheonsite.io"],
+        signals: &[TakeoverSignal::Http404],
+    },
+];
+
+/// Correlation rule for detecting subdomains that point at unclaimed
+/// third-party resources
+///
+/// A `DNS_CNAME` event (`"<host> <target>"`, optionally `"<host> CNAME
+/// <target>"`) whose target belongs to a known provider is reported once the
+/// same scan saw the provider disown it: an `HTTP_STATUS` event
+/// (`"<host> 404"`) for the host, or a `DNS_NXDOMAIN` event for the host or
+/// the target.
+pub struct SubdomainTakeoverRule {
+    fingerprints: Vec<TakeoverFingerprint>,
+}
+
+impl SubdomainTakeoverRule {
+    pub fn new() -> Self {
+        Self::with_fingerprints(DEFAULT_FINGERPRINTS.to_vec())
+    }
+
+    pub fn with_fingerprints(fingerprints: Vec<TakeoverFingerprint>) -> Self {
+        SubdomainTakeoverRule { fingerprints }
+    }
+
+    fn fingerprint_for(&self, target: &str) -> Option<&TakeoverFingerprint> {
+        self.fingerprints.iter().find(|f| f.matches(target))
+    }
+
+    /// `(host, target)` from a CNAME event
+    fn parse_cname(event: &Event) -> Option<(String, String)> {
+        let fields: Vec<&str> = event.data().split_whitespace().collect();
+        match fields.as_slice() {
+            [host, target] | [host, _, target] => Some((
+                normalize_target_value(&TargetType::Domain, host),
+                normalize_target_value(&TargetType::Domain, target),
+            )),
+            _ => None,
+        }
+    }
+
+    /// `(name, signal)` from an HTTP status or NXDOMAIN event
+    fn parse_signal(event: &Event) -> Option<(String, TakeoverSignal)> {
+        let mut fields = event.data().split_whitespace();
+        match event.event_type() {
+            "HTTP_STATUS" => {
+                let host = fields.next()?;
+                (fields.next()? == "404").then(|| {
+                    (
+                        normalize_target_value(&TargetType::Domain, host),
+                        TakeoverSignal::Http404,
+                    )
+                })
+            }
+            "DNS_NXDOMAIN" => Some((
+                normalize_target_value(&TargetType::Domain, fields.next()?),
+                TakeoverSignal::NxDomain,
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl CorrelationRule for SubdomainTakeoverRule {
+    fn name(&self) -> &str {
+        "Subdomain Takeover"
+    }
+
+    fn description(&self) -> &str {
+        "Detects subdomains whose CNAME points at an unclaimed resource on a third-party service"
+    }
+
+    fn analyze(&self, events: &[Event]) -> Vec<Alert> {
+        // Sorted by host so alerts come out in a stable order
+        let mut cnames: BTreeMap<String, (String, &Event)> = BTreeMap::new();
+        let mut signals: HashMap<String, Vec<(TakeoverSignal, &Event)>> = HashMap::new();
+
+        for event in events {
+            if event.event_type() == "DNS_CNAME" {
+                if let Some((host, target)) = Self::parse_cname(event) {
+                    cnames.entry(host).or_insert((target, event));
+                }
+            } else if let Some((name, signal)) = Self::parse_signal(event) {
+                signals.entry(name).or_default().push((signal, event));
+            }
+        }
+
+        let mut alerts = Vec::new();
+        for (host, (target, cname_event)) in cnames {
+            let Some(fingerprint) = self.fingerprint_for(&target) else {
+                continue;
+            };
+
+            let evidence: Vec<(TakeoverSignal, &Event)> = [&host, &target]
+                .iter()
+                .filter_map(|name| signals.get(name.as_str()))
+                .flatten()
+                .filter(|(signal, _)| fingerprint.signals.contains(signal))
+                .cloned()
+                .collect();
+            if evidence.is_empty() {
+                continue;
+            }
+
+            let symptom = if evidence.iter().any(|(s, _)| *s == TakeoverSignal::NxDomain) {
+                "no longer resolves"
+            } else {
+                "returns 404"
+            };
+
+            let mut alert_events = vec![cname_event.clone()];
+            alert_events.extend(evidence.into_iter().map(|(_, event)| event.clone()));
+
+            alerts.push(Alert::new(
+                &format!("Possible Subdomain Takeover: {}", host),
+                &format!(
+                    "{} is a CNAME for {} on {}, which {}; anyone who claims that resource can serve content on {}",
+                    host, target, fingerprint.service, symptom, host
+                ),
+                Severity::High,
+                alert_events,
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            ));
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: &str, data: &str) -> Event {
+        Event::new(event_type, data, Some("dns_resolver"), 1_700_000_000)
+    }
+
+    #[test]
+    fn test_cname_to_github_pages_with_404_triggers() {
+        let events = vec![
+            event("DNS_CNAME", "docs.example.com CNAME example.github.io."),
+            event("HTTP_STATUS", "docs.example.com 404"),
+            event("HTTP_STATUS", "www.example.com 404"),
+        ];
+
+        let alerts = SubdomainTakeoverRule::new().analyze(&events);
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity(), Severity::High);
+        assert_eq!(alerts[0].title(), "Possible Subdomain Takeover: docs.example.com");
+        assert_eq!(alerts[0].events().len(), 2);
+    }
+
+    #[test]
+    fn test_nxdomain_target_triggers_for_azure() {
+        let events = vec![
+            event("DNS_CNAME", "app.example.com app-prod.azurewebsites.net"),
+            event("DNS_NXDOMAIN", "app-prod.azurewebsites.net"),
+        ];
+
+        let alerts = SubdomainTakeoverRule::new().analyze(&events);
+
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].description().contains("no longer resolves"));
+    }
+
+    #[test]
+    fn test_unmatched_events_do_not_trigger() {
+        let events = vec![
+            // Known provider, but the site is still served
+            event("DNS_CNAME", "blog.example.com example.github.io"),
+            event("HTTP_STATUS", "blog.example.com 200"),
+            // 404 on a host that isn't behind a known provider
+            event("DNS_CNAME", "shop.example.com lb.example.net"),
+            event("HTTP_STATUS", "shop.example.com 404"),
+            // Azure only counts NXDOMAIN, not a 404 from the app
+            event("DNS_CNAME", "api.example.com api.azurewebsites.net"),
+            event("HTTP_STATUS", "api.example.com 404"),
+        ];
+
+        assert!(SubdomainTakeoverRule::new().analyze(&events).is_empty());
+    }
+
+    #[test]
+    fn test_custom_fingerprints_extend_the_rule() {
+        const INTERNAL: &[TakeoverFingerprint] = &[TakeoverFingerprint {
+            service: "Example PaaS",
+            cname_suffixes: &["paas.example.net"],
+            signals: &[TakeoverSignal::Http404],
+        }];
+        let events = vec![
+            event("DNS_CNAME", "old.example.com old.paas.example.net"),
+            event("HTTP_STATUS", "old.example.com 404"),
+        ];
+
+        assert!(SubdomainTakeoverRule::new().analyze(&events).is_empty());
+        let rule = SubdomainTakeoverRule::with_fingerprints(INTERNAL.to_vec());
+        assert_eq!(rule.analyze(&events).len(), 1);
+    }
+}
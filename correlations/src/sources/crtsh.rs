@@ -0,0 +1,263 @@
+use crate::core::event::Event;
+use crate::core::helpers::is_valid_domain;
+use mirage_common::target::{normalize_target_value, TargetType};
+use reqwest::blocking::Client;
+use reqwest::{StatusCode, Url};
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::BufReader;
+use std::time::Duration;
+
+const DEFAULT_BASE_URL: &str = "https://crt.sh";
+
+/// crt.sh builds the whole answer before sending it, which takes a while for
+/// domains with many certificates
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+const SOURCE: &str = "crt.sh";
+
+/// The parts of a crt.sh certificate entry that name hosts
+#[derive(Deserialize)]
+struct Certificate {
+    #[serde(default)]
+    common_name: Option<String>,
+    /// Subject alternative names, one per line
+    #[serde(default)]
+    name_value: String,
+}
+
+/// Finds subdomains in the certificate transparency logs indexed by crt.sh.
+pub struct CrtShSource {
+    client: Client,
+    base_url: String,
+}
+
+impl CrtShSource {
+    pub fn new() -> Self {
+        CrtShSource {
+            client: Client::builder()
+                .timeout(DEFAULT_TIMEOUT)
+                .build()
+                .unwrap_or_else(|_| Client::new()),
+            base_url: DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Use a different crt.sh instance, such as a mock in tests.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Subdomains of `domain` named in certificates logged for it, lowercase
+    /// and sorted. Wildcard names and `domain` itself are left out.
+    ///
+    /// crt.sh doesn't paginate, so the response is read one certificate at a
+    /// time and only the names found are kept in memory.
+    pub fn subdomains(&self, domain: &str) -> Result<BTreeSet<String>, String> {
+        let domain = normalize_target_value(&TargetType::Domain, domain);
+        let mut url = Url::parse(&self.base_url).map_err(|e| e.to_string())?;
+        url.query_pairs_mut()
+            .append_pair("q", &format!("%.{}", domain))
+            .append_pair("output", "json");
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .map_err(|e| format!("crt.sh request failed: {}", e))?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err("crt.sh rate limit exceeded".to_string());
+            }
+            status => return Err(format!("crt.sh returned {}", status)),
+        }
+
+        let mut subdomains = BTreeSet::new();
+        let mut reader = serde_json::Deserializer::from_reader(BufReader::new(response));
+        reader
+            .deserialize_seq(SubdomainCollector {
+                domain: &domain,
+                subdomains: &mut subdomains,
+            })
+            .map_err(|e| format!("Invalid crt.sh response: {}", e))?;
+
+        Ok(subdomains)
+    }
+
+    /// One `DOMAIN_NAME` event per subdomain of `domain`, for DNS scanning to
+    /// resolve. `timestamp` is when the search was made.
+    pub fn events(&self, domain: &str, timestamp: u64) -> Result<Vec<Event>, String> {
+        Ok(self
+            .subdomains(domain)?
+            .iter()
+            .map(|name| Event::new("DOMAIN_NAME", name, Some(SOURCE), timestamp))
+            .collect())
+    }
+}
+
+impl Default for CrtShSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `name` if it is a proper subdomain of `domain`
+fn subdomain(name: &str, domain: &str) -> Option<String> {
+    let name = normalize_target_value(&TargetType::Domain, name);
+    let parent = name.strip_suffix(domain)?.strip_suffix('.')?;
+
+    // Wildcards and names that aren't hosts, such as email addresses
+    if parent.is_empty() || !is_valid_domain(&name) {
+        return None;
+    }
+    Some(name)
+}
+
+/// Collects subdomains from a JSON list of certificates as it is parsed
+struct SubdomainCollector<'a> {
+    domain: &'a str,
+    subdomains: &'a mut BTreeSet<String>,
+}
+
+impl<'de> Visitor<'de> for SubdomainCollector<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of certificates")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(certificate) = seq.next_element::<Certificate>()? {
+            let names = certificate
+                .name_value
+                .lines()
+                .chain(certificate.common_name.as_deref());
+            for name in names {
+                if let Some(name) = subdomain(name, self.domain) {
+                    self.subdomains.insert(name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Answers one request with `body` and passes on its request line
+    fn mock_crtsh(body: String) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            tx.send(request_line).unwrap();
+        });
+
+        (format!("http://{}", addr), rx)
+    }
+
+    fn certificate(common_name: &str, names: &[&str]) -> serde_json::Value {
+        serde_json::json!({
+            "issuer_name": "C=US, O=Let's Encrypt, CN=R3",
+            "common_name": common_name,
+            "name_value": names.join("\n"),
+            "id": 1,
+        })
+    }
+
+    #[test]
+    fn test_subdomains_are_extracted_and_deduplicated() {
+        let body = serde_json::json!([
+            certificate("www.example.com", &["www.example.com", "example.com"]),
+            certificate("*.example.com", &["*.example.com", "API.Example.com"]),
+            certificate("api.example.com", &["api.example.com.", "mail.example.com"]),
+            certificate("example.com", &["admin@example.com", "*.dev.example.com"]),
+            certificate(
+                "vpn.example.com",
+                &["vpn.example.com", "www.notexample.com"]
+            ),
+        ]);
+        let (base_url, requests) = mock_crtsh(body.to_string());
+
+        let subdomains = CrtShSource::new()
+            .with_base_url(&base_url)
+            .subdomains("Example.com")
+            .unwrap();
+
+        assert_eq!(
+            subdomains.into_iter().collect::<Vec<_>>(),
+            vec![
+                "api.example.com",
+                "mail.example.com",
+                "vpn.example.com",
+                "www.example.com",
+            ]
+        );
+        let request_line = requests.recv().unwrap();
+        assert!(
+            request_line.starts_with("GET /?q=%25.example.com&output=json "),
+            "{}",
+            request_line
+        );
+    }
+
+    #[test]
+    fn test_subdomains_become_domain_name_events() {
+        // Many certificates renewing the same few names
+        let certificates: Vec<_> = (0..2_000)
+            .map(|i| certificate(&format!("host{}.example.com", i % 3), &[]))
+            .collect();
+        let (base_url, _requests) = mock_crtsh(serde_json::to_string(&certificates).unwrap());
+
+        let events = CrtShSource::new()
+            .with_base_url(&base_url)
+            .events("example.com", 1_000)
+            .unwrap();
+
+        let names: Vec<&str> = events.iter().map(|event| event.data()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "host0.example.com",
+                "host1.example.com",
+                "host2.example.com"
+            ]
+        );
+        for event in &events {
+            assert_eq!(event.event_type(), "DOMAIN_NAME");
+            assert_eq!(event.source(), Some("crt.sh"));
+            assert_eq!(event.timestamp(), 1_000);
+        }
+    }
+}
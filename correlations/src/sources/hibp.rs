@@ -0,0 +1,252 @@
+use crate::core::event::Event;
+use reqwest::blocking::Client;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_BASE_URL: &str = "https://haveibeenpwned.com/api/v3";
+
+/// The lowest HIBP subscription allows 10 requests a minute
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(6);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait after a 429 that doesn't say how long to back off
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Source name on the events, as the email breach rule expects
+const SOURCE: &str = "haveibeenpwned";
+
+/// A breach an account was found in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Breach {
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// `YYYY-MM-DD`, as published by HIBP
+    #[serde(rename = "BreachDate")]
+    pub breach_date: String,
+}
+
+/// Looks up email addresses in HaveIBeenPwned's breached-account API.
+///
+/// Requests are spaced at least `interval` apart, which should match the
+/// rate limit of the API key's subscription.
+pub struct HibpSource {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    user_agent: String,
+    interval: Duration,
+    /// Earliest time the next request may be sent
+    next_request: Mutex<Instant>,
+}
+
+impl HibpSource {
+    /// HIBP rejects requests without a user agent naming the consuming
+    /// application.
+    pub fn new(api_key: &str, user_agent: &str) -> Self {
+        HibpSource {
+            client: Client::builder()
+                .timeout(DEFAULT_TIMEOUT)
+                .build()
+                .unwrap_or_else(|_| Client::new()),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_key: api_key.to_string(),
+            user_agent: user_agent.to_string(),
+            interval: DEFAULT_INTERVAL,
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Use a different API root, such as a mock in tests.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set the least time between two requests.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Breaches `email` appears in. An address HIBP doesn't know is not
+    /// breached and yields no breaches.
+    pub fn breaches(&self, email: &str) -> Result<Vec<Breach>, String> {
+        let mut url = Url::parse(&self.base_url).map_err(|e| e.to_string())?;
+        url.path_segments_mut()
+            .map_err(|_| format!("Invalid HIBP base URL: {}", self.base_url))?
+            .push("breachedaccount")
+            .push(email);
+        url.query_pairs_mut()
+            .append_pair("truncateResponse", "false");
+
+        self.wait_turn();
+        let response = self
+            .client
+            .get(url)
+            .header("hibp-api-key", &self.api_key)
+            .header("user-agent", &self.user_agent)
+            .send()
+            .map_err(|e| format!("HIBP request failed: {}", e))?;
+
+        match response.status() {
+            StatusCode::OK => response
+                .json()
+                .map_err(|e| format!("Invalid HIBP response: {}", e)),
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            StatusCode::UNAUTHORIZED => Err("HIBP rejected the API key".to_string()),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_RETRY_AFTER);
+                self.back_off(retry_after);
+                Err(format!(
+                    "HIBP rate limit exceeded, retry after {} seconds",
+                    retry_after.as_secs()
+                ))
+            }
+            status => Err(format!("HIBP returned {}", status)),
+        }
+    }
+
+    /// One `EMAIL_BREACH` event per breach `email` appears in, for the email
+    /// breach rule. `timestamp` is when the lookup was made.
+    pub fn events(&self, email: &str, timestamp: u64) -> Result<Vec<Event>, String> {
+        Ok(self
+            .breaches(email)?
+            .iter()
+            .map(|_| Event::new("EMAIL_BREACH", email, Some(SOURCE), timestamp))
+            .collect())
+    }
+
+    /// Blocks until the next request is allowed and claims that slot
+    fn wait_turn(&self) {
+        let mut next_request = self.next_request.lock().unwrap();
+        let now = Instant::now();
+        if *next_request > now {
+            thread::sleep(*next_request - now);
+        }
+        *next_request = Instant::now() + self.interval;
+    }
+
+    fn back_off(&self, retry_after: Duration) {
+        let mut next_request = self.next_request.lock().unwrap();
+        *next_request = (*next_request).max(Instant::now() + retry_after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::correlations::email_breach::EmailBreachRule;
+    use crate::correlations::{CorrelationRule, Severity};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Serves `requests` requests like the breached-account endpoint, which
+    /// only knows alice, and passes on each request head
+    fn mock_hibp(requests: usize) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+
+                let (status, body) = if head.starts_with("GET /api/v3/breachedaccount/alice") {
+                    (
+                        "200 OK",
+                        r#"[{"Name":"Adobe","Title":"Adobe","BreachDate":"2013-10-04"},
+                            {"Name":"LinkedIn","Title":"LinkedIn","BreachDate":"2012-05-05"}]"#,
+                    )
+                } else {
+                    ("404 Not Found", "")
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+                tx.send(head).unwrap();
+            }
+        });
+
+        (format!("http://{}/api/v3", addr), rx)
+    }
+
+    #[test]
+    fn test_breached_email_feeds_the_breach_rule() {
+        let (base_url, requests) = mock_hibp(2);
+        let source = HibpSource::new("test-key", "mirage-tests")
+            .with_base_url(&base_url)
+            .with_interval(Duration::ZERO);
+
+        let breaches = source.breaches("alice@example.com").unwrap();
+        assert_eq!(
+            breaches,
+            vec![
+                Breach {
+                    name: "Adobe".to_string(),
+                    breach_date: "2013-10-04".to_string(),
+                },
+                Breach {
+                    name: "LinkedIn".to_string(),
+                    breach_date: "2012-05-05".to_string(),
+                },
+            ]
+        );
+
+        let head = requests.recv().unwrap().to_lowercase();
+        assert!(head.contains("truncateresponse=false"), "{}", head);
+        assert!(head.contains("\r\nhibp-api-key: test-key\r\n"), "{}", head);
+        assert!(
+            head.contains("\r\nuser-agent: mirage-tests\r\n"),
+            "{}",
+            head
+        );
+
+        let events = source.events("alice@example.com", 1_000).unwrap();
+        assert_eq!(events.len(), 2);
+        let alerts = EmailBreachRule::new().analyze(&events);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].title(), "Email Breach: alice@example.com");
+        assert_eq!(alerts[0].severity(), Severity::Medium);
+    }
+
+    #[test]
+    fn test_clean_email_is_not_breached_and_requests_are_spaced() {
+        let (base_url, _requests) = mock_hibp(2);
+        let source = HibpSource::new("test-key", "mirage-tests")
+            .with_base_url(&base_url)
+            .with_interval(Duration::from_millis(300));
+
+        let started = Instant::now();
+        assert_eq!(source.breaches("bob@example.com").unwrap(), Vec::new());
+        let events = source.events("bob@example.com", 1_000).unwrap();
+
+        assert!(events.is_empty());
+        assert!(EmailBreachRule::new().analyze(&events).is_empty());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,7 @@
+//! External lookups that produce events for the correlation rules
+
+pub mod crtsh;
+pub mod hibp;
+
+pub use crtsh::CrtShSource;
+pub use hibp::{Breach, HibpSource};
@@ -1,11 +1,59 @@
-use regex::Regex;
+// Validators are shared with core::helpers through mirage_common so both agree.
+pub use mirage_common::validation::is_valid_email as validate_email;
+pub use mirage_common::validation::is_valid_url as validate_url;
 
-pub fn validate_url(url: &str) -> bool {
-    let url_regex = Regex::new(r"^(http|https)://").unwrap();
-    url_regex.is_match(url)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_the_old_validators_disagreed_on() {
+        // The old URL check only looked for a lowercase `http(s)://` prefix
+        assert!(validate_url("HTTPS://example.com"));
+        assert!(!validate_url("http://"));
+        assert!(!validate_url("http://exa mple.com"));
+
+        assert!(!validate_url("mailto:user@example.com"));
+
+        // Target validation let dots fall anywhere and didn't limit the local
+        // part, but only allowed `._%+-` in it
+        assert!(!validate_email("user@example..com"));
+        assert!(!validate_email("user.@example.com"));
+        assert!(!validate_email(&format!("{}@example.com", "a".repeat(65))));
+        assert!(validate_email("o'brien@example.ie"));
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://example.com"));
+        assert!(validate_url("http://example.com/path"));
+        assert!(!validate_url("ftp://example.com"));
+        assert!(!validate_url("example.com"));
+    }
+
+    #[test]
+    fn test_validate_email() {
+        let cases = [
+            ("user@example.com", true),
+            ("user+tag@sub.example.co.uk", true),
+            ("first.last@example.org", true),
+            ("o'brien@example.ie", true),
+            ("user_name-1@example-mail.com", true),
+            ("a@b..c", false),
+            ("user..name@example.com", false),
+            (".user@example.com", false),
+            ("user.@example.com", false),
+            ("user@example", false),
+            ("user@-example.com", false),
+            ("user@example-.com", false),
+            ("user@.example.com", false),
+            ("user@@example.com", false),
+            ("user name@example.com", false),
+            ("@example.com", false),
+        ];
 
-pub fn validate_email(email: &str) -> bool {
-    let email_regex = Regex::new(r"^[\w\.-]+@[\w\.-]+\.\w+$").unwrap();
-    email_regex.is_match(email)
+        for (input, expected) in cases {
+            assert_eq!(validate_email(input), expected, "input: {:?}", input);
+        }
+    }
 }
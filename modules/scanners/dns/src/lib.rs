@@ -1,31 +1,117 @@
-use std::net::ToSocketAddrs;
-use std::net::UdpSocket;
-use std::time::Duration;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
 
-pub struct DnsScanner;
+const DEFAULT_SERVER: &str = "8.8.8.8:53";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct DnsScanner {
+    server: String,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+}
 
 impl DnsScanner {
     pub fn new() -> Self {
-        DnsScanner
+        DnsScanner {
+            server: DEFAULT_SERVER.to_string(),
+            connect_timeout: DEFAULT_TIMEOUT,
+            read_timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Use a different resolver, given as `host:port`.
+    pub fn with_server(mut self, server: &str) -> Self {
+        self.server = server.to_string();
+        self
+    }
+
+    /// Set how long to wait for a TCP connection to the resolver.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set how long to wait for a response on either transport.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
     }
 
     pub fn perform_dns_lookup(&self, domain: &str) -> Result<Vec<String>, String> {
-        let server = "8.8.8.8:53";
         let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
         socket
-            .set_read_timeout(Some(Duration::from_secs(5)))
+            .set_read_timeout(Some(self.read_timeout))
             .map_err(|e| e.to_string())?;
 
         let mut buf = [0u8; 512];
         let len = self.build_query(domain, &mut buf)?;
         socket
-            .send_to(&buf[..len], server)
+            .send_to(&buf[..len], &self.server)
             .map_err(|e| e.to_string())?;
 
-        let (amt, _) = socket.recv_from(&mut buf).map_err(|e| e.to_string())?;
+        let (amt, _) = socket
+            .recv_from(&mut buf)
+            .map_err(|e| self.map_io_error(e))?;
+
+        // The TC flag means the answer didn't fit in a UDP datagram; retry over TCP
+        if amt > 2 && buf[2] & 0x02 != 0 {
+            return self.perform_tcp_dns_lookup(domain);
+        }
+
         self.parse_response(&buf[..amt])
     }
 
+    /// Performs the lookup over TCP, bounded by the connect and read timeouts
+    /// so an unresponsive server can't hang the caller. The read timeout
+    /// covers the whole response, so a server trickling it out byte by byte
+    /// can't either.
+    pub fn perform_tcp_dns_lookup(&self, domain: &str) -> Result<Vec<String>, String> {
+        let addr = self
+            .server
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("Could not resolve DNS server address: {}", self.server))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, self.connect_timeout)
+            .map_err(|e| self.map_io_error(e))?;
+        stream
+            .set_write_timeout(Some(self.read_timeout))
+            .map_err(|e| e.to_string())?;
+
+        let mut query = [0u8; 512];
+        let len = self.build_query(domain, &mut query)?;
+
+        // TCP messages are prefixed with a two-byte length
+        let mut message = Vec::with_capacity(len + 2);
+        message.extend_from_slice(&(len as u16).to_be_bytes());
+        message.extend_from_slice(&query[..len]);
+        stream
+            .write_all(&message)
+            .map_err(|e| self.map_io_error(e))?;
+
+        let deadline = Instant::now() + self.read_timeout;
+
+        let mut len_buf = [0u8; 2];
+        read_exact_by(&mut stream, &mut len_buf, deadline).map_err(|e| self.map_io_error(e))?;
+        let response_len = u16::from_be_bytes(len_buf) as usize;
+
+        let mut response = vec![0u8; response_len];
+        read_exact_by(&mut stream, &mut response, deadline).map_err(|e| self.map_io_error(e))?;
+
+        self.parse_response(&response)
+    }
+
+    fn map_io_error(&self, e: io::Error) -> String {
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                format!("DNS query to {} timed out", self.server)
+            }
+            _ => e.to_string(),
+        }
+    }
+
     fn build_query(&self, domain: &str, buf: &mut [u8]) -> Result<usize, String> {
         // Build a DNS query for the given domain
         // This is a simplified example and may not cover all cases
@@ -88,3 +174,104 @@ impl DnsScanner {
         Ok(ips)
     }
 }
+
+impl Default for DnsScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fills `buf` from `stream`, failing with `TimedOut` once `deadline` passes
+/// however the data is split across reads
+fn read_exact_by(stream: &mut TcpStream, buf: &mut [u8], deadline: Instant) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(remaining))?;
+
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_tcp_lookup_times_out_on_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Accept the connection and hold it open without ever replying
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(2));
+            drop(stream);
+        });
+
+        let scanner = DnsScanner::new()
+            .with_server(&addr.to_string())
+            .with_connect_timeout(Duration::from_millis(500))
+            .with_read_timeout(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let result = scanner.perform_tcp_dns_lookup("example.com");
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let err = result.unwrap_err();
+        assert!(err.contains("timed out"), "unexpected error: {}", err);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_lookup_times_out_on_trickling_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Promise a long answer, then send it a byte at a time, each well
+        // within the read timeout
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.write_all(&[0x00, 0x40]);
+            for _ in 0..20 {
+                thread::sleep(Duration::from_millis(100));
+                if stream.write_all(&[0x00]).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let scanner = DnsScanner::new()
+            .with_server(&addr.to_string())
+            .with_connect_timeout(Duration::from_millis(500))
+            .with_read_timeout(Duration::from_millis(300));
+
+        let started = Instant::now();
+        let result = scanner.perform_tcp_dns_lookup("example.com");
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let err = result.unwrap_err();
+        assert!(err.contains("timed out"), "unexpected error: {}", err);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_default_timeouts_match_udp() {
+        let scanner = DnsScanner::new();
+        assert_eq!(scanner.connect_timeout, Duration::from_secs(5));
+        assert_eq!(scanner.read_timeout, Duration::from_secs(5));
+    }
+}
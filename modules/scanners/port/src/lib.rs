@@ -0,0 +1,201 @@
+use futures::stream::{self, StreamExt};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpStream};
+
+/// Ports most often found open on internet-facing hosts
+pub const COMMON_PORTS: &[u16] = &[
+    21, 22, 23, 25, 53, 80, 110, 111, 135, 139, 143, 443, 445, 993, 995, 1723, 3306, 3389, 5900,
+    8080,
+];
+
+/// HTTP(S) servers, admin panels and dev servers
+pub const WEB_PORTS: &[u16] = &[80, 443, 3000, 5000, 8000, 8008, 8080, 8443, 8888, 9443];
+
+/// Databases and caches that shouldn't be reachable from outside
+pub const DATABASE_PORTS: &[u16] = &[1433, 1521, 3306, 5432, 5984, 6379, 9042, 9200, 11211, 27017];
+
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// Upper bound on connection attempts in flight, whatever the caller asks
+/// for, so a scan can't flood the target or exhaust local sockets
+pub const MAX_CONCURRENCY: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// The connection was accepted
+    Open,
+    /// The host refused the connection
+    Closed,
+    /// No answer within the timeout, or the host couldn't be reached
+    Filtered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortStatus {
+    pub port: u16,
+    pub state: PortState,
+}
+
+pub struct PortScanner {
+    concurrency: usize,
+}
+
+impl PortScanner {
+    pub fn new() -> Self {
+        PortScanner {
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Set how many ports are probed at once, between 1 and `MAX_CONCURRENCY`.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.clamp(1, MAX_CONCURRENCY);
+        self
+    }
+
+    /// Probes each of `ports` on `host` with a TCP connect, waiting up to
+    /// `timeout` per port. Results are in the order of `ports`.
+    ///
+    /// A host that doesn't resolve has every port reported filtered.
+    pub async fn scan_ports(
+        &self,
+        host: &str,
+        ports: &[u16],
+        timeout: Duration,
+    ) -> Vec<PortStatus> {
+        let ip = match resolve(host).await {
+            Ok(ip) => ip,
+            Err(_) => {
+                return ports
+                    .iter()
+                    .map(|&port| PortStatus {
+                        port,
+                        state: PortState::Filtered,
+                    })
+                    .collect();
+            }
+        };
+
+        let mut results: Vec<(usize, PortStatus)> = stream::iter(ports.iter().copied().enumerate())
+            .map(|(i, port)| async move {
+                let state = probe(SocketAddr::new(ip, port), timeout).await;
+                (i, PortStatus { port, state })
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, status)| status).collect()
+    }
+}
+
+impl Default for PortScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn resolve(host: &str) -> io::Result<IpAddr> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+
+    lookup_host((host, 0))
+        .await?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No address for {}", host)))
+}
+
+async fn probe(addr: SocketAddr, timeout: Duration) -> PortState {
+    match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => PortState::Open,
+        Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => PortState::Closed,
+        // Unreachable networks and hosts as well as silent drops
+        Ok(Err(_)) | Err(_) => PortState::Filtered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// A port nothing listens on: bound to find a free one, then released
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn test_open_and_closed_ports_are_told_apart() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = closed_port();
+
+        let results = PortScanner::new()
+            .scan_ports("127.0.0.1", &[closed, open], Duration::from_secs(2))
+            .await;
+
+        assert_eq!(
+            results,
+            vec![
+                PortStatus {
+                    port: closed,
+                    state: PortState::Closed,
+                },
+                PortStatus {
+                    port: open,
+                    state: PortState::Open,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_results_keep_port_order_with_a_small_cap() {
+        let listeners: Vec<TcpListener> = (0..5)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().port())
+            .collect();
+
+        let results = PortScanner::new()
+            .with_concurrency(2)
+            .scan_ports("127.0.0.1", &ports, Duration::from_secs(2))
+            .await;
+
+        let scanned: Vec<u16> = results.iter().map(|status| status.port).collect();
+        assert_eq!(scanned, ports);
+        assert!(results.iter().all(|status| status.state == PortState::Open));
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_host_is_filtered() {
+        let results = PortScanner::new()
+            .scan_ports("host.invalid", WEB_PORTS, Duration::from_millis(200))
+            .await;
+
+        assert_eq!(results.len(), WEB_PORTS.len());
+        assert!(results
+            .iter()
+            .all(|status| status.state == PortState::Filtered));
+    }
+
+    #[test]
+    fn test_concurrency_is_capped() {
+        assert_eq!(
+            PortScanner::new().with_concurrency(100_000).concurrency,
+            MAX_CONCURRENCY
+        );
+        assert_eq!(PortScanner::new().with_concurrency(0).concurrency, 1);
+    }
+}
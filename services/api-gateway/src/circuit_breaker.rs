@@ -0,0 +1,260 @@
+//! Per-service circuit breakers
+//!
+//! A breaker opens after `failure_threshold` consecutive failed requests to a
+//! service. While open, requests are rejected immediately instead of waiting
+//! on the downstream timeout. After `cooldown` the breaker half-opens and lets
+//! a single probe request through: success closes it again, failure re-opens
+//! it for another cooldown.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BreakerConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            failure_threshold: env::var("CIRCUIT_BREAKER_FAILURE_THRESHOLD")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.failure_threshold),
+            cooldown: env::var("CIRCUIT_BREAKER_COOLDOWN_SECONDS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.cooldown),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    pub service: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Seconds until an open breaker lets a probe through
+    pub retry_after_seconds: Option<u64>,
+}
+
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+        }
+    }
+}
+
+pub struct CircuitBreakers {
+    config: BreakerConfig,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a request to `service` may be sent. An open breaker whose
+    /// cooldown has elapsed half-opens and admits exactly one probe.
+    pub fn try_acquire(&self, service: &str, now: Instant) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(service.to_string())
+            .or_insert_with(Breaker::new);
+
+        match breaker.state {
+            BreakerState::Closed => true,
+            // Another cooldown without an answer means the probe was
+            // abandoned (e.g. the client went away), so admit a new one
+            BreakerState::Open | BreakerState::HalfOpen => {
+                let cooled_down = breaker
+                    .opened_at
+                    .is_some_and(|opened_at| now.duration_since(opened_at) >= self.config.cooldown);
+                if cooled_down {
+                    breaker.state = BreakerState::HalfOpen;
+                    breaker.opened_at = Some(now);
+                }
+                cooled_down
+            }
+        }
+    }
+
+    pub fn record_success(&self, service: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if let Some(breaker) = breakers.get_mut(service) {
+            if breaker.state != BreakerState::Closed {
+                tracing::info!("Circuit breaker for {} closed", service);
+            }
+            *breaker = Breaker::new();
+        }
+    }
+
+    pub fn record_failure(&self, service: &str, now: Instant) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(service.to_string())
+            .or_insert_with(Breaker::new);
+
+        breaker.consecutive_failures += 1;
+        let trips = breaker.state == BreakerState::HalfOpen
+            || breaker.consecutive_failures >= self.config.failure_threshold;
+
+        if trips && breaker.state != BreakerState::Open {
+            tracing::warn!(
+                "Circuit breaker for {} opened after {} consecutive failures",
+                service,
+                breaker.consecutive_failures
+            );
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = Some(now);
+        }
+    }
+
+    pub fn statuses(&self, now: Instant) -> Vec<BreakerStatus> {
+        let breakers = self.breakers.lock().unwrap();
+        let mut statuses: Vec<BreakerStatus> = breakers
+            .iter()
+            .map(|(service, breaker)| BreakerStatus {
+                service: service.clone(),
+                state: breaker.state,
+                consecutive_failures: breaker.consecutive_failures,
+                retry_after_seconds: match (breaker.state, breaker.opened_at) {
+                    (BreakerState::Open, Some(opened_at)) => Some(
+                        self.config
+                            .cooldown
+                            .saturating_sub(now.duration_since(opened_at))
+                            .as_secs(),
+                    ),
+                    _ => None,
+                },
+            })
+            .collect();
+
+        statuses.sort_by(|a, b| a.service.cmp(&b.service));
+        statuses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl CircuitBreakers {
+        fn state(&self, service: &str) -> BreakerState {
+            self.breakers
+                .lock()
+                .unwrap()
+                .get(service)
+                .map_or(BreakerState::Closed, |breaker| breaker.state)
+        }
+    }
+
+    fn breakers() -> CircuitBreakers {
+        CircuitBreakers::new(BreakerConfig {
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn test_breaker_cycles_through_every_state() {
+        let breakers = breakers();
+        let start = Instant::now();
+
+        // Closed: failures below the threshold still forward
+        for _ in 0..2 {
+            assert!(breakers.try_acquire("reporting", start));
+            breakers.record_failure("reporting", start);
+        }
+        assert_eq!(breakers.state("reporting"), BreakerState::Closed);
+
+        // Open: the third failure trips the breaker
+        assert!(breakers.try_acquire("reporting", start));
+        breakers.record_failure("reporting", start);
+        assert_eq!(breakers.state("reporting"), BreakerState::Open);
+        assert!(!breakers.try_acquire("reporting", start + Duration::from_secs(5)));
+
+        // Half-open: one probe after the cooldown, others still rejected
+        let later = start + Duration::from_secs(10);
+        assert!(breakers.try_acquire("reporting", later));
+        assert_eq!(breakers.state("reporting"), BreakerState::HalfOpen);
+        assert!(!breakers.try_acquire("reporting", later));
+
+        // Closed: the probe succeeded
+        breakers.record_success("reporting");
+        assert_eq!(breakers.state("reporting"), BreakerState::Closed);
+        assert!(breakers.try_acquire("reporting", later));
+    }
+
+    #[test]
+    fn test_failed_probe_reopens_breaker() {
+        let breakers = breakers();
+        let start = Instant::now();
+        for _ in 0..3 {
+            breakers.record_failure("discovery", start);
+        }
+
+        let probe_at = start + Duration::from_secs(10);
+        assert!(breakers.try_acquire("discovery", probe_at));
+        breakers.record_failure("discovery", probe_at);
+
+        assert_eq!(breakers.state("discovery"), BreakerState::Open);
+        assert!(!breakers.try_acquire("discovery", probe_at + Duration::from_secs(9)));
+
+        let statuses = breakers.statuses(probe_at + Duration::from_secs(4));
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].state, BreakerState::Open);
+        assert_eq!(statuses[0].retry_after_seconds, Some(6));
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breakers = breakers();
+        let now = Instant::now();
+
+        breakers.record_failure("auth", now);
+        breakers.record_failure("auth", now);
+        breakers.record_success("auth");
+        breakers.record_failure("auth", now);
+
+        assert_eq!(breakers.state("auth"), BreakerState::Closed);
+        assert_eq!(breakers.statuses(now)[0].consecutive_failures, 1);
+    }
+}
@@ -1,6 +1,7 @@
 //! Configuration for API Gateway service
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,3 +25,85 @@ impl Config {
         })
     }
 }
+
+/// Largest request body the gateway forwards, unless `PROXY_MAX_BODY_BYTES` is set
+pub const DEFAULT_MAX_REQUEST_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+pub fn max_request_body_bytes() -> usize {
+    env::var("PROXY_MAX_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_REQUEST_BODY_BYTES)
+}
+
+/// Downstream services and their default (Docker network) URLs
+pub const DEFAULT_SERVICE_ENDPOINTS: &[(&str, &str)] = &[
+    ("auth", "http://auth-service:8081"),
+    ("user-management", "http://user-management-service:8082"),
+    (
+        "scan-orchestration",
+        "http://scan-orchestration-service:8083",
+    ),
+    ("module-registry", "http://module-registry-service:8084"),
+    ("data-collection", "http://data-collection-service:8085"),
+    ("data-storage", "http://data-storage-service:8086"),
+    (
+        "correlation-engine",
+        "http://correlation-engine-service:8087",
+    ),
+    ("visualization", "http://visualization-service:8088"),
+    ("reporting", "http://reporting-service:8089"),
+    ("notification", "http://notification-service:8090"),
+    ("integration", "http://integration-service:8091"),
+    ("configuration", "http://configuration-service:8092"),
+    ("discovery", "http://discovery-service:8093"),
+];
+
+/// Environment variable overriding a service URL, e.g. `SERVICE_USER_MANAGEMENT_URL`
+pub fn service_url_var(service: &str) -> String {
+    format!("SERVICE_{}_URL", service.to_uppercase().replace('-', "_"))
+}
+
+/// Builds the service endpoint map from `SERVICE_<NAME>_URL` overrides and
+/// the defaults, rejecting any URL that is not a valid http(s) URL
+pub fn service_endpoints_from_env() -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let mut endpoints = HashMap::new();
+
+    for (service, default_url) in DEFAULT_SERVICE_ENDPOINTS {
+        let var = service_url_var(service);
+        let url = env::var(&var).unwrap_or_else(|_| default_url.to_string());
+
+        match reqwest::Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => {}
+            _ => return Err(format!("Invalid URL for {} ({}): {}", service, var, url).into()),
+        }
+
+        endpoints.insert(service.to_string(), url.trim_end_matches('/').to_string());
+    }
+
+    Ok(endpoints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_endpoint_env_override() {
+        let var = service_url_var("reporting");
+        assert_eq!(var, "SERVICE_REPORTING_URL");
+
+        env::set_var(&var, "https://reports.internal.example.com:9443/");
+        let endpoints = service_endpoints_from_env().unwrap();
+        assert_eq!(
+            endpoints["reporting"],
+            "https://reports.internal.example.com:9443"
+        );
+        assert_eq!(endpoints["auth"], "http://auth-service:8081");
+        assert_eq!(endpoints.len(), DEFAULT_SERVICE_ENDPOINTS.len());
+
+        env::set_var(&var, "not a url");
+        assert!(service_endpoints_from_env().is_err());
+        env::remove_var(&var);
+    }
+}
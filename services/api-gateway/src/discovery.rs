@@ -0,0 +1,133 @@
+//! Resolving downstream services through the discovery service
+//!
+//! Before proxying, the gateway asks discovery to select one healthy
+//! instance of the target service, so instances can be added or removed
+//! without redeploying the gateway. Answers are cached for `cache_ttl` to
+//! keep discovery off the hot path.
+//!
+//! A service discovery has no instances of, or a discovery service that
+//! cannot be reached, falls back to the static `service_endpoints` map.
+//! A service whose instances are all unhealthy is not routed at all.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a discovery lookup may take before the static map is used
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Base URL of the instance discovery selected
+    Instance(String),
+    /// Discovery knows the service, but none of its instances are healthy
+    NoHealthyInstance,
+    /// Discovery has no answer; use the static endpoint
+    Static,
+}
+
+// The fields of a discovery `ServiceResponse` the gateway needs
+#[derive(Debug, Deserialize)]
+struct SelectedInstance {
+    address: String,
+    port: u16,
+}
+
+pub struct ServiceResolver {
+    client: reqwest::Client,
+    discovery_url: Option<String>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Resolution)>>,
+}
+
+impl ServiceResolver {
+    /// Resolver asking the discovery service at `discovery_url`. With
+    /// `None`, every service resolves to its static endpoint.
+    pub fn new(discovery_url: Option<String>, cache_ttl: Duration) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(LOOKUP_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            discovery_url: discovery_url.map(|url| url.trim_end_matches('/').to_string()),
+            cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Uses `discovery_url` unless `DISCOVERY_ROUTING` is `false`, caching
+    /// lookups for `DISCOVERY_CACHE_TTL_SECONDS` (5 by default)
+    pub fn from_env(discovery_url: Option<&String>) -> Self {
+        let enabled = env::var("DISCOVERY_ROUTING")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(true);
+        let cache_ttl = env::var("DISCOVERY_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(5));
+
+        Self::new(discovery_url.filter(|_| enabled).cloned(), cache_ttl)
+    }
+
+    /// Where requests for `service` should go right now
+    pub async fn resolve(&self, service: &str) -> Resolution {
+        let Some(discovery_url) = &self.discovery_url else {
+            return Resolution::Static;
+        };
+        // Discovery itself is always reached through its static endpoint
+        if service == "discovery" {
+            return Resolution::Static;
+        }
+
+        if let Some((resolved_at, resolution)) = self.cache.lock().unwrap().get(service) {
+            if resolved_at.elapsed() < self.cache_ttl {
+                return resolution.clone();
+            }
+        }
+
+        let resolution = self.lookup(discovery_url, service).await;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(service.to_string(), (Instant::now(), resolution.clone()));
+
+        resolution
+    }
+
+    async fn lookup(&self, discovery_url: &str, service: &str) -> Resolution {
+        let url = format!(
+            "{}/api/v1/discovery/services/instances/{}/select",
+            discovery_url, service
+        );
+
+        let response = match self.client.get(&url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Discovery lookup for {} failed: {}", service, e);
+                return Resolution::Static;
+            }
+        };
+
+        match response.status() {
+            status if status.is_success() => match response.json::<SelectedInstance>().await {
+                Ok(instance) => {
+                    Resolution::Instance(format!("http://{}:{}", instance.address, instance.port))
+                }
+                Err(e) => {
+                    tracing::warn!("Invalid discovery answer for {}: {}", service, e);
+                    Resolution::Static
+                }
+            },
+            reqwest::StatusCode::NOT_FOUND => Resolution::Static,
+            reqwest::StatusCode::SERVICE_UNAVAILABLE => Resolution::NoHealthyInstance,
+            status => {
+                tracing::warn!("Discovery lookup for {} failed with {}", service, status);
+                Resolution::Static
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use std::time::Instant;
+
+/// Current circuit breaker state of every downstream service seen so far
+pub async fn circuit_breakers(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.circuit_breakers.statuses(Instant::now()))
+}
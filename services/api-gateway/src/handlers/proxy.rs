@@ -1,5 +1,84 @@
+use crate::discovery::Resolution;
 use crate::AppState;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::body::SizedStream;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use mirage_common::telemetry::{self, RequestId, REQUEST_ID_HEADER};
+use rand::Rng;
+use std::env;
+use std::time::{Duration, Instant};
+
+/// Retry settings for forwarded requests
+///
+/// Only transport failures (connection refused or reset, timeouts) are
+/// retried; any response from the upstream, including 5xx, is passed through.
+/// POST and PATCH are not idempotent and are sent once unless `retry_post`
+/// is set.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub retry_post: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 100,
+            max_delay_ms: 2_000,
+            retry_post: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_attempts: env_or("PROXY_MAX_ATTEMPTS", defaults.max_attempts),
+            base_delay_ms: env_or("PROXY_RETRY_BASE_DELAY_MS", defaults.base_delay_ms),
+            max_delay_ms: env_or("PROXY_RETRY_MAX_DELAY_MS", defaults.max_delay_ms),
+            retry_post: env_or("PROXY_RETRY_POST", defaults.retry_post),
+        }
+    }
+
+    fn attempts_for(&self, method: &reqwest::Method) -> u32 {
+        let idempotent = matches!(
+            *method,
+            reqwest::Method::GET | reqwest::Method::PUT | reqwest::Method::DELETE
+        );
+
+        if idempotent || self.retry_post {
+            self.max_attempts.max(1)
+        } else {
+            1
+        }
+    }
+
+    /// Exponential backoff with jitter, drawn from the upper half of the step
+    fn delay(&self, attempt: u32) -> Duration {
+        let step = self
+            .base_delay_ms
+            .saturating_mul(2_u64.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay_ms);
+        let jittered = rand::thread_rng().gen_range(step / 2..=step);
+
+        Duration::from_millis(jittered)
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn is_retryable(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
 
 pub async fn proxy_request(
     req: HttpRequest,
@@ -31,10 +110,21 @@ pub async fn proxy_request(
         _ => return HttpResponse::NotFound().body("Service not found"),
     };
 
-    // Get service endpoint
-    let service_url = match state.service_endpoints.get(service_name) {
-        Some(url) => url,
-        None => return HttpResponse::ServiceUnavailable().body("Service endpoint not configured"),
+    // Get service endpoint, from discovery when it knows the service
+    let service_url = match state.resolver.resolve(service_name).await {
+        Resolution::Instance(url) => url,
+        Resolution::NoHealthyInstance => {
+            return HttpResponse::ServiceUnavailable().body(format!(
+                "No healthy instance of service {} is available",
+                service_name
+            ))
+        }
+        Resolution::Static => match state.service_endpoints.get(service_name) {
+            Some(url) => url.clone(),
+            None => {
+                return HttpResponse::ServiceUnavailable().body("Service endpoint not configured")
+            }
+        },
     };
 
     // Create the target URL
@@ -42,45 +132,451 @@ pub async fn proxy_request(
     let target_url = format!("{}/api/v1/{}", service_url, target_path);
 
     // Forward the request
-    let client = reqwest::Client::new();
-    let mut request_builder = match req.method().as_str() {
-        "GET" => client.get(&target_url),
-        "POST" => client.post(&target_url).body(body),
-        "PUT" => client.put(&target_url).body(body),
-        "DELETE" => client.delete(&target_url),
-        "PATCH" => client.patch(&target_url).body(body),
+    let method = match req.method().as_str() {
+        "GET" => reqwest::Method::GET,
+        "POST" => reqwest::Method::POST,
+        "PUT" => reqwest::Method::PUT,
+        "DELETE" => reqwest::Method::DELETE,
+        "PATCH" => reqwest::Method::PATCH,
         _ => return HttpResponse::MethodNotAllowed().finish(),
     };
+    let sends_body = !matches!(method, reqwest::Method::GET | reqwest::Method::DELETE);
 
-    // Copy headers
+    // Copy headers, skipping connection-specific ones
+    let mut headers = reqwest::header::HeaderMap::new();
     for (header_name, header_value) in req.headers() {
-        // Skip connection-specific headers
         if header_name == "connection" || header_name == "host" {
             continue;
         }
-        request_builder = request_builder.header(header_name, header_value);
+        headers.append(header_name.clone(), header_value.clone());
     }
 
-    // Execute the request
-    match request_builder.send().await {
+    // Pass our request id on so the downstream logs under the same one
+    if let Some(RequestId(id)) = req.extensions().get::<RequestId>() {
+        if let Ok(value) = reqwest::header::HeaderValue::from_str(id) {
+            headers.insert(REQUEST_ID_HEADER, value);
+        }
+    }
+
+    // Continue the caller's trace, if spans are being exported. Otherwise an
+    // inbound `traceparent` is forwarded as it came.
+    for (name, value) in telemetry::trace_headers() {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(name.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+
+    // Fail fast while the downstream is known to be unhealthy
+    let breakers = &state.circuit_breakers;
+    if !breakers.try_acquire(service_name, Instant::now()) {
+        return HttpResponse::ServiceUnavailable().body(format!(
+            "Service {} is temporarily unavailable",
+            service_name
+        ));
+    }
+
+    let client = reqwest::Client::new();
+    let policy = &state.retry_policy;
+    let attempts = policy.attempts_for(&method);
+    let mut attempt = 1;
+
+    let result = loop {
+        let mut request_builder = client
+            .request(method.clone(), &target_url)
+            .headers(headers.clone());
+        if sends_body {
+            request_builder = request_builder.body(body.clone());
+        }
+
+        match request_builder.send().await {
+            Err(e) if attempt < attempts && is_retryable(&e) => {
+                tracing::warn!(
+                    "Proxy request to {} failed (attempt {}/{}): {}",
+                    target_url,
+                    attempt,
+                    attempts,
+                    e
+                );
+                actix_web::rt::time::sleep(policy.delay(attempt)).await;
+                attempt += 1;
+            }
+            result => break result,
+        }
+    };
+
+    // Gateway-level errors from the downstream count against its breaker,
+    // application errors do not
+    let healthy = match &result {
+        Ok(response) => !matches!(response.status().as_u16(), 502..=504),
+        Err(_) => false,
+    };
+    if healthy {
+        breakers.record_success(service_name);
+    } else {
+        breakers.record_failure(service_name, Instant::now());
+    }
+
+    match result {
         Ok(response) => {
             // Create response builder
             let mut builder = HttpResponse::build(response.status());
 
-            // Copy headers
+            // Copy headers; framing is re-derived from the streamed body
             for (name, value) in response.headers() {
+                if matches!(
+                    name.as_str(),
+                    "connection" | "content-length" | "transfer-encoding"
+                ) {
+                    continue;
+                }
                 builder.insert_header((name.clone(), value.clone()));
             }
 
-            // Set body and return
-            match response.bytes().await {
-                Ok(bytes) => builder.body(bytes),
-                Err(_) => HttpResponse::InternalServerError().body("Failed to read response body"),
+            // Stream the body through instead of buffering it
+            match response.content_length() {
+                Some(length) => builder.body(SizedStream::new(length, response.bytes_stream())),
+                None => builder.streaming(response.bytes_stream()),
             }
         }
         Err(e) => {
-            log::error!("Proxy request error: {}", e);
+            tracing::error!("Proxy request error: {}", e);
             HttpResponse::InternalServerError().body(format!("Proxy request failed: {}", e))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::{BreakerConfig, CircuitBreakers};
+    use crate::discovery::ServiceResolver;
+    use crate::token_cache::TokenCache;
+    use actix_web::{test, App};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::Mutex;
+
+    // Reads a request up to the end of its head, or until the peer closes
+    async fn read_head(socket: &mut TcpStream) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = socket.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        buf
+    }
+
+    // Upstream that drops the first `failures` connections without answering
+    async fn flaky_upstream(failures: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let seen = counter.fetch_add(1, Ordering::SeqCst);
+                read_head(&mut socket).await;
+
+                if seen < failures {
+                    drop(socket);
+                    continue;
+                }
+                let response =
+                    "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, connections)
+    }
+
+    // Upstream that answers with the head of the request it received
+    async fn echo_upstream() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let buf = read_head(&mut socket).await;
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    buf.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.write_all(&buf).await.unwrap();
+            }
+        });
+
+        url
+    }
+
+    // Discovery service answering every lookup with `status` and `body`,
+    // counting the lookups
+    async fn mock_discovery(status: &'static str, body: String) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&lookups);
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                read_head(&mut socket).await;
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, lookups)
+    }
+
+    fn state(upstream: String) -> web::Data<AppState> {
+        state_with(upstream, ServiceResolver::new(None, Duration::ZERO))
+    }
+
+    fn state_with(upstream: String, resolver: ServiceResolver) -> web::Data<AppState> {
+        web::Data::new(AppState {
+            service_endpoints: HashMap::from([("scan-orchestration".to_string(), upstream)]),
+            auth_cache: Arc::new(Mutex::new(TokenCache::new(1))),
+            circuit_breakers: Arc::new(CircuitBreakers::new(BreakerConfig::default())),
+            retry_policy: RetryPolicy {
+                base_delay_ms: 1,
+                max_delay_ms: 5,
+                ..RetryPolicy::default()
+            },
+            resolver: Arc::new(resolver),
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_get_is_retried_after_connection_reset() {
+        let (upstream, connections) = flaky_upstream(1).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state(upstream))
+                .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(test::read_body(resp).await, "ok");
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_post_is_not_retried() {
+        let (upstream, connections) = flaky_upstream(1).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state(upstream))
+                .route("/api/v1/scans", web::post().to(proxy_request)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/scans")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_server_error());
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_open_breaker_short_circuits() {
+        let (upstream, connections) = flaky_upstream(usize::MAX).await;
+        let state = state(upstream);
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+        )
+        .await;
+
+        for _ in 0..BreakerConfig::default().failure_threshold {
+            let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+            assert!(test::call_service(&app, req)
+                .await
+                .status()
+                .is_server_error());
+        }
+        let attempted = connections.load(Ordering::SeqCst);
+
+        let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), attempted);
+    }
+
+    #[actix_web::test]
+    async fn test_request_body_limit() {
+        let (upstream, connections) = flaky_upstream(0).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state(upstream))
+                .app_data(web::PayloadConfig::new(16))
+                .route("/api/v1/scans", web::post().to(proxy_request)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/scans")
+            .set_payload(vec![b'x'; 17])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/scans")
+            .set_payload(vec![b'x'; 16])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(test::read_body(resp).await, "ok");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_traceparent_is_forwarded_upstream() {
+        let app = test::init_service(
+            App::new()
+                .app_data(state(echo_upstream().await))
+                .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+        )
+        .await;
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let req = test::TestRequest::get()
+            .uri("/api/v1/scans/1")
+            .insert_header((
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-01", trace_id),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let upstream_head = String::from_utf8_lossy(&body);
+        let traceparent = upstream_head
+            .lines()
+            .find_map(|line| line.strip_prefix("traceparent: "))
+            .expect("traceparent was forwarded");
+        // The gateway's own span may stand in as parent, but the trace is the
+        // caller's
+        assert!(
+            traceparent.starts_with(&format!("00-{}-", trace_id)),
+            "{}",
+            traceparent
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_routes_to_discovered_instance() {
+        // The static endpoint would fail every request
+        let (static_upstream, static_connections) = flaky_upstream(usize::MAX).await;
+        let (instance, _) = flaky_upstream(0).await;
+        let port = instance.rsplit(':').next().unwrap();
+        let (discovery, lookups) = mock_discovery(
+            "200 OK",
+            format!(r#"{{"id":"scan-1","address":"127.0.0.1","port":{}}}"#, port),
+        )
+        .await;
+        let resolver = ServiceResolver::new(Some(discovery), Duration::from_secs(60));
+        let app = test::init_service(
+            App::new()
+                .app_data(state_with(static_upstream, resolver))
+                .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+            assert_eq!(test::read_body(resp).await, "ok");
+        }
+        assert_eq!(static_connections.load(Ordering::SeqCst), 0);
+        // The second request was served from the cache
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_falls_back_to_static_endpoint() {
+        // Discovery has no instances of the service
+        let (not_found, _) = mock_discovery("404 Not Found", "{}".to_string()).await;
+        // Or can't be reached at all
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        for discovery in [not_found, unreachable] {
+            let (upstream, connections) = flaky_upstream(0).await;
+            let resolver = ServiceResolver::new(Some(discovery), Duration::from_secs(60));
+            let app = test::init_service(
+                App::new()
+                    .app_data(state_with(upstream, resolver))
+                    .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+            )
+            .await;
+
+            let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+            assert_eq!(test::read_body(resp).await, "ok");
+            assert_eq!(connections.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_no_healthy_instance_is_unavailable() {
+        let (upstream, connections) = flaky_upstream(0).await;
+        let (discovery, _) = mock_discovery("503 Service Unavailable", "{}".to_string()).await;
+        let resolver = ServiceResolver::new(Some(discovery), Duration::from_secs(60));
+        let app = test::init_service(
+            App::new()
+                .app_data(state_with(upstream, resolver))
+                .route("/api/v1/scans/{id}", web::get().to(proxy_request)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/v1/scans/1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,7 +1,10 @@
-use actix_web::middleware::{Logger, NormalizePath};
-use actix_web::{http, middleware, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::middleware::{from_fn, Logger, NormalizePath};
+use actix_web::{middleware, web, App, HttpServer};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use actix_web_httpauth::middleware::HttpAuthentication;
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
@@ -9,11 +12,18 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 mod auth;
+mod circuit_breaker;
 mod config;
+mod discovery;
 mod handlers;
 mod models;
+mod token_cache;
 
-#[derive(Debug, Serialize, Deserialize)]
+use circuit_breaker::{BreakerConfig, CircuitBreakers};
+use discovery::ServiceResolver;
+use token_cache::TokenCache;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Claims {
     sub: String,
     exp: usize,
@@ -30,32 +40,24 @@ struct ServiceResponse {
 #[derive(Clone)]
 struct AppState {
     service_endpoints: HashMap<String, String>,
-    auth_cache: Arc<Mutex<HashMap<String, Claims>>>,
-}
-
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok()
-        .json(serde_json::json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+    auth_cache: Arc<Mutex<TokenCache<Claims>>>,
+    circuit_breakers: Arc<CircuitBreakers>,
+    retry_policy: handlers::proxy::RetryPolicy,
+    resolver: Arc<ServiceResolver>,
 }
 
 async fn validate_token(
     req: actix_web::dev::ServiceRequest,
     auth: BearerAuth,
-) -> Result<actix_web::dev::ServiceRequest, actix_web::Error> {
+) -> Result<actix_web::dev::ServiceRequest, (actix_web::Error, actix_web::dev::ServiceRequest)> {
     let token = auth.token();
     let app_state = req.app_data::<web::Data<AppState>>().unwrap();
 
-    // Check cache first
-    {
-        let cache = app_state.auth_cache.lock().await;
-        if let Some(claims) = cache.get(token) {
-            // Check if token is expired
-            let now = chrono::Utc::now().timestamp() as usize;
-            if claims.exp > now {
-                // Token valid, proceed
-                return Ok(req);
-            }
-        }
+    let now = chrono::Utc::now().timestamp() as u64;
+
+    // Check cache first; expired entries are dropped on lookup
+    if app_state.auth_cache.lock().await.get(token, now).is_some() {
+        return Ok(req);
     }
 
     // Not in cache or expired, validate with auth service
@@ -68,89 +70,86 @@ async fn validate_token(
         &validation,
     ) {
         Ok(token_data) => {
-            // Add to cache
-            {
-                let mut cache = app_state.auth_cache.lock().await;
-                cache.insert(token.to_string(), token_data.claims.clone());
-            }
+            // Add to cache until the token expires
+            let exp = token_data.claims.exp as u64;
+            app_state
+                .auth_cache
+                .lock()
+                .await
+                .insert(token, token_data.claims, exp, now);
             Ok(req)
         }
-        Err(_) => Err(actix_web::error::ErrorUnauthorized("Invalid token")),
+        Err(_) => Err((actix_web::error::ErrorUnauthorized("Invalid token"), req)),
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
+    let _telemetry = telemetry::init("api-gateway");
 
     // Load service configuration
-    let mut service_endpoints = HashMap::new();
-    service_endpoints.insert("auth".to_string(), "http://auth-service:8081".to_string());
-    service_endpoints.insert(
-        "user-management".to_string(),
-        "http://user-management-service:8082".to_string(),
-    );
-    service_endpoints.insert(
-        "scan-orchestration".to_string(),
-        "http://scan-orchestration-service:8083".to_string(),
-    );
-    service_endpoints.insert(
-        "module-registry".to_string(),
-        "http://module-registry-service:8084".to_string(),
-    );
-    service_endpoints.insert(
-        "data-collection".to_string(),
-        "http://data-collection-service:8085".to_string(),
-    );
-    service_endpoints.insert(
-        "data-storage".to_string(),
-        "http://data-storage-service:8086".to_string(),
-    );
-    service_endpoints.insert(
-        "correlation-engine".to_string(),
-        "http://correlation-engine-service:8087".to_string(),
-    );
-    service_endpoints.insert(
-        "visualization".to_string(),
-        "http://visualization-service:8088".to_string(),
-    );
-    service_endpoints.insert(
-        "reporting".to_string(),
-        "http://reporting-service:8089".to_string(),
-    );
-    service_endpoints.insert(
-        "notification".to_string(),
-        "http://notification-service:8090".to_string(),
-    );
-    service_endpoints.insert(
-        "integration".to_string(),
-        "http://integration-service:8091".to_string(),
-    );
-    service_endpoints.insert(
-        "configuration".to_string(),
-        "http://configuration-service:8092".to_string(),
-    );
-    service_endpoints.insert(
-        "discovery".to_string(),
-        "http://discovery-service:8093".to_string(),
-    );
+    let service_endpoints = config::service_endpoints_from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+
+    let auth_cache_capacity = env::var("AUTH_CACHE_CAPACITY")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(token_cache::DEFAULT_CAPACITY);
+
+    // Instances are looked up in discovery, with the static endpoints as
+    // the fallback
+    let resolver = Arc::new(ServiceResolver::from_env(
+        service_endpoints.get("discovery"),
+    ));
 
     let app_state = web::Data::new(AppState {
         service_endpoints,
-        auth_cache: Arc::new(Mutex::new(HashMap::new())),
+        auth_cache: Arc::new(Mutex::new(TokenCache::new(auth_cache_capacity))),
+        circuit_breakers: Arc::new(CircuitBreakers::new(BreakerConfig::from_env())),
+        retry_policy: handlers::proxy::RetryPolicy::from_env(),
+        resolver,
     });
 
+    // Oversized request bodies are rejected with 413 before being proxied
+    let max_body_bytes = config::max_request_body_bytes();
+
+    // A downstream service being down only degrades the gateway, since routes
+    // to the other services keep working
+    let probe_client = reqwest::Client::new();
+    let mut endpoints: Vec<_> = app_state.service_endpoints.iter().collect();
+    endpoints.sort();
+    let health = web::Data::new(endpoints.into_iter().fold(
+        HealthChecker::new("api-gateway", env!("CARGO_PKG_VERSION")),
+        |checker, (service, url)| {
+            checker.optional_probe(
+                service,
+                health::http_probe(probe_client.clone(), &format!("{}/api/v1/health", url)),
+            )
+        },
+    ));
+
+    // Prometheus metrics, served from /metrics
+    let metrics = web::Data::new(Metrics::new("api-gateway").map_err(std::io::Error::other)?);
+
     HttpServer::new(move || {
         let auth = HttpAuthentication::bearer(validate_token);
         App::new()
             .app_data(app_state.clone())
+            .app_data(metrics.clone())
+            .app_data(health.clone())
+            .app_data(web::PayloadConfig::new(max_body_bytes))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
             .wrap(NormalizePath::default())
             .wrap(middleware::Compress::default())
+            .route("/metrics", web::get().to(metrics::render))
             // Public routes
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(
                         web::scope("/auth")
                             .route("/login", web::post().to(handlers::auth::login))
@@ -162,6 +161,10 @@ async fn main() -> std::io::Result<()> {
             .service(
                 web::scope("/api/v1")
                     .wrap(auth)
+                    .service(web::scope("/admin").route(
+                        "/circuit-breakers",
+                        web::get().to(handlers::admin::circuit_breakers),
+                    ))
                     .service(
                         web::scope("/users")
                             .route("", web::get().to(handlers::proxy::proxy_request))
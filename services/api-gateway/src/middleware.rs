@@ -4,12 +4,12 @@ use actix_web::{
 };
 use chrono::Utc;
 use futures::future::{ready, LocalBoxFuture, Ready};
-use log::{debug, error, info};
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll};
 use std::time::Instant;
+use tracing::{debug, error, info};
 use uuid::Uuid;
 
 // Global request metrics middleware
@@ -0,0 +1,141 @@
+//! Bounded cache of validated tokens
+//!
+//! Entries expire with the token's `exp` claim and are dropped when they are
+//! looked up after that point. Once the cache is full, the least recently
+//! used entry is evicted to make room, so memory stays bounded under token
+//! churn.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+struct Entry<V> {
+    value: V,
+    expires_at: u64,
+    last_used: u64,
+}
+
+pub struct TokenCache<V> {
+    capacity: usize,
+    entries: HashMap<String, Entry<V>>,
+    // Access tick -> token, oldest first
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl<V: Clone> TokenCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Returns the cached value if it has not expired at `now` (unix seconds)
+    pub fn get(&mut self, token: &str, now: u64) -> Option<V> {
+        let expired = self.entries.get(token)?.expires_at <= now;
+        if expired {
+            self.remove(token);
+            return None;
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(token)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, token.to_string());
+        entry.last_used = tick;
+
+        Some(entry.value.clone())
+    }
+
+    /// Caches `value` until `expires_at` (unix seconds)
+    pub fn insert(&mut self, token: &str, value: V, expires_at: u64, now: u64) {
+        if expires_at <= now {
+            return;
+        }
+
+        self.remove(token);
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+
+        let tick = self.next_tick();
+        self.recency.insert(tick, token.to_string());
+        self.entries.insert(
+            token.to_string(),
+            Entry {
+                value,
+                expires_at,
+                last_used: tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, token: &str) {
+        if let Some(entry) = self.entries.remove(token) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let mut cache = TokenCache::new(2);
+        cache.insert("a", 1, NOW + 60, NOW);
+        cache.insert("b", 2, NOW + 60, NOW);
+
+        // Touch "a" so "b" becomes the eviction candidate
+        assert_eq!(cache.get("a", NOW), Some(1));
+        cache.insert("c", 3, NOW + 60, NOW);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get("b", NOW), None);
+        assert_eq!(cache.get("a", NOW), Some(1));
+        assert_eq!(cache.get("c", NOW), Some(3));
+    }
+
+    #[test]
+    fn test_expired_entries_are_not_served() {
+        let mut cache = TokenCache::new(10);
+        cache.insert("short", 1, NOW + 5, NOW);
+        cache.insert("long", 2, NOW + 3600, NOW);
+
+        assert_eq!(cache.get("short", NOW + 4), Some(1));
+        assert_eq!(cache.get("short", NOW + 5), None);
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.get("long", NOW + 5), Some(2));
+
+        // Already expired tokens are never cached
+        cache.insert("stale", 3, NOW - 1, NOW);
+        assert_eq!(cache.get("stale", NOW - 10), None);
+    }
+
+    #[test]
+    fn test_reinserting_a_token_does_not_grow_the_cache() {
+        let mut cache = TokenCache::new(2);
+        for _ in 0..5 {
+            cache.insert("a", 1, NOW + 60, NOW);
+        }
+        cache.insert("b", 2, NOW + 60, NOW);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get("a", NOW), Some(1));
+        assert_eq!(cache.recency.len(), 2);
+    }
+}
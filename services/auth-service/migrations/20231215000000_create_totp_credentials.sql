@@ -0,0 +1,11 @@
+-- One TOTP secret per user; `enabled_at` stays unset until the user has
+-- proven, with a first code, that their authenticator holds the secret
+CREATE TABLE IF NOT EXISTS totp_credentials (
+    user_id VARCHAR(255) PRIMARY KEY,
+    -- AES-256-GCM sealed base32 secret, never stored in the clear
+    encrypted_secret TEXT NOT NULL,
+    enabled_at TIMESTAMPTZ,
+    -- Time step of the last accepted code, so a code works only once
+    last_used_step BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
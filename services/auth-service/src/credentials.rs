@@ -0,0 +1,93 @@
+//! Password checks for login
+//!
+//! Accounts and their password hashes live in the user-management service;
+//! auth-service only asks it whether a username and password match, and
+//! which user they belong to.
+
+use crate::tokens::TokenError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[async_trait]
+pub trait CredentialVerifier: Send + Sync {
+    /// ID of the active user the username and password belong to
+    async fn verify(&self, username: &str, password: &str) -> Result<String, TokenError>;
+}
+
+#[derive(Deserialize)]
+struct VerifiedUser {
+    id: String,
+}
+
+/// Asks user-management's `POST /api/v1/users/login`
+pub struct UserManagementCredentials {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl UserManagementCredentials {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl CredentialVerifier for UserManagementCredentials {
+    async fn verify(&self, username: &str, password: &str) -> Result<String, TokenError> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/users/login", self.base_url))
+            .json(&serde_json::json!({ "username": username, "password": password }))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| TokenError::Credentials(e.to_string()))?;
+
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED => Err(TokenError::InvalidCredentials),
+            status if !status.is_success() => Err(TokenError::Credentials(format!(
+                "user-management returned {}",
+                status
+            ))),
+            _ => response
+                .json::<VerifiedUser>()
+                .await
+                .map(|user| user.id)
+                .map_err(|e| TokenError::Credentials(e.to_string())),
+        }
+    }
+}
+
+/// Fixed username/password pairs, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct StaticCredentials {
+    users: std::sync::Mutex<std::collections::HashMap<String, (String, String)>>,
+}
+
+#[cfg(test)]
+impl StaticCredentials {
+    pub fn add(&self, user_id: &str, username: &str, password: &str) {
+        self.users.lock().unwrap().insert(
+            username.to_string(),
+            (password.to_string(), user_id.to_string()),
+        );
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CredentialVerifier for StaticCredentials {
+    async fn verify(&self, username: &str, password: &str) -> Result<String, TokenError> {
+        match self.users.lock().unwrap().get(username) {
+            Some((expected, user_id)) if expected == password => Ok(user_id.clone()),
+            _ => Err(TokenError::InvalidCredentials),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! PostgreSQL pool and schema migrations
+//!
+//! The SQL files in `migrations/` are embedded at build time and applied at
+//! startup, before the service takes requests, so a fresh database gets its
+//! tables and an existing one only the migrations it hasn't seen yet.
+
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+const MAX_CONNECTIONS: u32 = 10;
+
+/// Connects to `url` and brings the schema up to date
+pub async fn connect(url: &str) -> Result<PgPool, sqlx::Error> {
+    let pool = PgPoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect(url)
+        .await?;
+
+    run_migrations(&pool).await?;
+    Ok(pool)
+}
+
+/// Applies the migrations that haven't run yet
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::migrate!("./migrations").run(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_migrations_create_tables(pool: PgPool) {
+        run_migrations(&pool).await.unwrap();
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT table_name::text FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert!(tables.iter().any(|t| t == "users"), "{:?}", tables);
+
+        // Already applied migrations are skipped on the next start
+        run_migrations(&pool).await.unwrap();
+    }
+}
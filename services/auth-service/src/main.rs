@@ -1,8 +1,15 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
 use log::info;
+use mirage_common::crypto::SecretCipher;
+use std::sync::Arc;
 
+mod credentials;
+mod db;
 mod models;
+mod permissions;
 mod routes;
+mod tokens;
+mod totp;
 
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "service": "auth-service" }))
@@ -12,10 +19,48 @@ async fn health_check() -> impl Responder {
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
+    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // Refuse to start on a schema the code doesn't match
+    let db_pool = db::connect(&database_url)
+        .await
+        .map_err(std::io::Error::other)?;
+    let redis_url =
+        std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    let redis_client = redis::Client::open(redis_url).map_err(std::io::Error::other)?;
+
+    let user_management_url = std::env::var("USER_MANAGEMENT_URL")
+        .unwrap_or_else(|_| "http://user-management-service:8082".to_string());
+
+    let totp_key = std::env::var("TOTP_ENCRYPTION_KEY").expect("TOTP_ENCRYPTION_KEY must be set");
+    let totp_cipher = SecretCipher::new(&totp_key).map_err(std::io::Error::other)?;
+
+    let token_store = Arc::new(tokens::RedisTokenStore::new(redis_client, "auth"));
+    let permissions = Arc::new(permissions::UserManagementPermissions::new(
+        user_management_url.clone(),
+    ));
+    let credentials: Arc<dyn credentials::CredentialVerifier> = Arc::new(
+        credentials::UserManagementCredentials::new(user_management_url),
+    );
+    let token_service = web::Data::new(tokens::TokenService::new(
+        &jwt_secret,
+        token_store,
+        permissions,
+    ));
+    let totp_service = web::Data::new(totp::TotpService::new(
+        totp::TotpConfig::from_env(),
+        totp_cipher,
+        Arc::new(totp::PgTotpStore::new(db_pool)),
+    ));
+    let credentials = web::Data::from(credentials);
+
     info!("Starting auth-service on port 8001");
 
     HttpServer::new(move || {
         App::new()
+            .app_data(token_service.clone())
+            .app_data(totp_service.clone())
+            .app_data(credentials.clone())
             .route("/health", web::get().to(health_check))
             .configure(routes::config)
     })
@@ -44,23 +89,162 @@ mod tests {
         assert_eq!(body["service"], "auth-service");
     }
 
+    fn totp_service() -> web::Data<totp::TotpService> {
+        web::Data::new(totp::TotpService::new(
+            totp::TotpConfig::default(),
+            SecretCipher::new("YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=").unwrap(),
+            Arc::new(totp::InMemoryTotpStore::default()),
+        ))
+    }
+
+    fn credentials() -> web::Data<dyn credentials::CredentialVerifier> {
+        let credentials = credentials::StaticCredentials::default();
+        credentials.add("user-1", "alice", "correct horse");
+        web::Data::from(Arc::new(credentials) as Arc<dyn credentials::CredentialVerifier>)
+    }
+
     #[actix_web::test]
     async fn test_auth_routes() {
-        let app = test::init_service(App::new().configure(routes::config)).await;
+        let permissions = permissions::StaticPermissions::default();
+        permissions.grant("user-1", &["scans:read"]);
+        let token_service = web::Data::new(tokens::TokenService::new(
+            "test-secret",
+            Arc::new(tokens::InMemoryTokenStore::default()),
+            Arc::new(permissions),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(token_service.clone())
+                .app_data(totp_service())
+                .app_data(credentials())
+                .configure(routes::config),
+        )
+        .await;
 
         // Test login endpoint
-        let req = test::TestRequest::post().uri("/auth/login").to_request();
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({ "username": "alice", "password": "wrong" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({ "username": "alice", "password": "correct horse" }))
+            .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+        let issued: models::TokenPair = test::read_body_json(resp).await;
+        assert_eq!(
+            token_service.decode(&issued.access_token).unwrap().sub,
+            "user-1"
+        );
 
         // Test register endpoint
         let req = test::TestRequest::post().uri("/auth/register").to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
+        // Test refresh endpoint
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({ "refresh_token": issued.refresh_token }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let rotated: models::TokenPair = test::read_body_json(resp).await;
+
         // Test logout endpoint
-        let req = test::TestRequest::post().uri("/auth/logout").to_request();
+        let req = test::TestRequest::post()
+            .uri("/auth/logout")
+            .set_json(serde_json::json!({ "refresh_token": rotated.refresh_token }))
+            .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+
+        // The family is gone after logout
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({ "refresh_token": rotated.refresh_token }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_login_needs_totp_code_once_enabled() {
+        let permissions = permissions::StaticPermissions::default();
+        permissions.grant("user-1", &[]);
+        let token_service = web::Data::new(tokens::TokenService::new(
+            "test-secret",
+            Arc::new(tokens::InMemoryTokenStore::default()),
+            Arc::new(permissions),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(token_service.clone())
+                .app_data(totp_service())
+                .app_data(credentials())
+                .configure(routes::config),
+        )
+        .await;
+        let login = |code: Option<String>| {
+            test::TestRequest::post()
+                .uri("/auth/login")
+                .set_json(serde_json::json!({
+                    "username": "alice",
+                    "password": "correct horse",
+                    "totp_code": code
+                }))
+                .to_request()
+        };
+
+        // Enrolling takes an access token
+        let req = test::TestRequest::post()
+            .uri("/auth/2fa/enroll")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let resp = test::call_service(&app, login(None)).await;
+        let issued: models::TokenPair = test::read_body_json(resp).await;
+        let bearer = format!("Bearer {}", issued.access_token);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/2fa/enroll")
+            .insert_header(("Authorization", bearer.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let enrollment: serde_json::Value = test::read_body_json(resp).await;
+        let authenticator =
+            totp_rs::TOTP::from_url(enrollment["otpauth_uri"].as_str().unwrap()).unwrap();
+
+        // Pending enrollment doesn't change login yet
+        let resp = test::call_service(&app, login(None)).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/auth/2fa/enable")
+            .insert_header(("Authorization", bearer))
+            .set_json(serde_json::json!({ "code": authenticator.generate_current().unwrap() }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let resp = test::call_service(&app, login(None)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "Two-factor code required");
+
+        let resp = test::call_service(&app, login(Some("000000".to_string()))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        // The code used to enable is spent; the next step's code logs in
+        let next = authenticator.generate(authenticator.next_step_current().unwrap());
+        let resp = test::call_service(&app, login(Some(next))).await;
+        assert!(resp.status().is_success());
     }
 }
@@ -16,6 +16,9 @@ pub struct User {
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Current code from the user's authenticator, if they have 2FA on
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,3 +33,48 @@ pub struct AuthResponse {
     pub token: String,
     pub user_id: Uuid,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    pub iat: usize,
+    /// Unique token ID
+    pub jti: String,
+    /// Refresh-token family the token was issued under
+    pub fid: Uuid,
+    pub token_type: TokenType,
+    /// Permissions from the user's roles; access tokens only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perms: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnableTotpRequest {
+    pub code: String,
+}
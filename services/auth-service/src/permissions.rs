@@ -0,0 +1,146 @@
+//! Permission lookup for minted access tokens
+//!
+//! Users hold roles in the user-management service, and each role grants a
+//! set of permissions. Access tokens carry the union in their `perms` claim,
+//! resolved afresh on every issue and refresh, so role changes reach a user
+//! no later than their next refresh.
+
+use crate::tokens::TokenError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[async_trait]
+pub trait PermissionResolver: Send + Sync {
+    /// Permissions the user currently holds through their roles
+    async fn permissions(&self, user_id: &str) -> Result<Vec<String>, TokenError>;
+}
+
+#[derive(Deserialize)]
+struct UserPermissions {
+    permissions: Vec<String>,
+}
+
+/// Asks user-management's `GET /api/v1/users/{id}/permissions`
+pub struct UserManagementPermissions {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl UserManagementPermissions {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl PermissionResolver for UserManagementPermissions {
+    async fn permissions(&self, user_id: &str) -> Result<Vec<String>, TokenError> {
+        let response = self
+            .client
+            .get(format!(
+                "{}/api/v1/users/{}/permissions",
+                self.base_url, user_id
+            ))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| TokenError::Permissions(e.to_string()))?;
+
+        match response.status() {
+            // Deleted users, and subjects that were never user IDs
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::BAD_REQUEST => {
+                Err(TokenError::UnknownUser)
+            }
+            status if !status.is_success() => Err(TokenError::Permissions(format!(
+                "user-management returned {}",
+                status
+            ))),
+            _ => response
+                .json::<UserPermissions>()
+                .await
+                .map(|body| body.permissions)
+                .map_err(|e| TokenError::Permissions(e.to_string())),
+        }
+    }
+}
+
+/// Fixed permissions per user, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct StaticPermissions {
+    users: std::sync::Mutex<std::collections::HashMap<String, Vec<String>>>,
+}
+
+#[cfg(test)]
+impl StaticPermissions {
+    pub fn grant(&self, user_id: &str, permissions: &[&str]) {
+        self.users.lock().unwrap().insert(
+            user_id.to_string(),
+            permissions.iter().map(|p| p.to_string()).collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl PermissionResolver for StaticPermissions {
+    async fn permissions(&self, user_id: &str) -> Result<Vec<String>, TokenError> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .cloned()
+            .ok_or(TokenError::UnknownUser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    #[actix_web::test]
+    async fn test_permissions_are_read_from_user_management() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/api/v1/users/{id}/permissions",
+                web::get().to(|id: web::Path<String>| async move {
+                    if id.as_str() == "user-1" {
+                        HttpResponse::Ok().json(serde_json::json!({
+                            "user_id": "user-1",
+                            "roles": ["triage"],
+                            "permissions": ["findings:read", "scans:read"]
+                        }))
+                    } else {
+                        HttpResponse::NotFound().finish()
+                    }
+                }),
+            )
+        })
+        .listen(listener)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let resolver = UserManagementPermissions::new(base_url);
+        assert_eq!(
+            resolver.permissions("user-1").await.unwrap(),
+            vec!["findings:read", "scans:read"]
+        );
+        assert!(matches!(
+            resolver.permissions("user-2").await,
+            Err(TokenError::UnknownUser)
+        ));
+
+        handle.stop(false).await;
+    }
+}
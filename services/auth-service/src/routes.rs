@@ -1,15 +1,78 @@
-use actix_web::{web, HttpResponse, Responder};
+use crate::credentials::CredentialVerifier;
+use crate::models::{EnableTotpRequest, LoginRequest, LogoutRequest, RefreshRequest};
+use crate::tokens::{TokenError, TokenService};
+use crate::totp::TotpService;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 
-pub async fn login() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "message": "Login endpoint" }))
+pub async fn login(
+    credentials: web::Data<dyn CredentialVerifier>,
+    totp: web::Data<TotpService>,
+    tokens: web::Data<TokenService>,
+    request: web::Json<LoginRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = credentials
+        .verify(&request.username, &request.password)
+        .await?;
+    totp.verify_login(&user_id, request.totp_code.as_deref())
+        .await?;
+
+    let pair = tokens.issue(&user_id).await?;
+    Ok(HttpResponse::Ok().json(pair))
 }
 
 pub async fn register() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({ "message": "Register endpoint" }))
 }
 
-pub async fn logout() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "message": "Logout endpoint" }))
+pub async fn refresh(
+    tokens: web::Data<TokenService>,
+    request: web::Json<RefreshRequest>,
+) -> Result<HttpResponse, TokenError> {
+    let pair = tokens.refresh(&request.refresh_token).await?;
+    Ok(HttpResponse::Ok().json(pair))
+}
+
+pub async fn logout(
+    tokens: web::Data<TokenService>,
+    request: web::Json<LogoutRequest>,
+) -> Result<HttpResponse, TokenError> {
+    tokens.revoke(&request.refresh_token).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Logged out" })))
+}
+
+/// The user a request's bearer access token was issued to
+fn authenticated_user(req: &HttpRequest, tokens: &TokenService) -> Result<String, TokenError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(TokenError::Invalid)?;
+
+    Ok(tokens.decode_access(token)?.sub)
+}
+
+pub async fn enroll_totp(
+    req: HttpRequest,
+    tokens: web::Data<TokenService>,
+    totp: web::Data<TotpService>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = authenticated_user(&req, &tokens)?;
+    let enrollment = totp.enroll(&user_id).await?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+pub async fn enable_totp(
+    req: HttpRequest,
+    tokens: web::Data<TokenService>,
+    totp: web::Data<TotpService>,
+    request: web::Json<EnableTotpRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = authenticated_user(&req, &tokens)?;
+    totp.enable(&user_id, &request.code).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Two-factor authentication enabled"
+    })))
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -17,6 +80,9 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/auth")
             .route("/login", web::post().to(login))
             .route("/register", web::post().to(register))
-            .route("/logout", web::post().to(logout)),
+            .route("/refresh", web::post().to(refresh))
+            .route("/logout", web::post().to(logout))
+            .route("/2fa/enroll", web::post().to(enroll_totp))
+            .route("/2fa/enable", web::post().to(enable_totp)),
     );
 }
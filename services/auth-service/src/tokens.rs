@@ -0,0 +1,491 @@
+//! Access/refresh token issuance with refresh-token rotation
+//!
+//! Every login starts a token family. Each `/auth/refresh` swaps the family's
+//! current refresh token for a new one; presenting a refresh token that has
+//! already been rotated out is treated as theft and revokes the whole family.
+
+use crate::models::{Claims, TokenPair, TokenType};
+use crate::permissions::PermissionResolver;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Invalid token")]
+    Invalid,
+    #[error("Refresh token reuse detected, session revoked")]
+    Reused,
+    #[error("Session has been revoked")]
+    Revoked,
+    #[error("Token store error: {0}")]
+    Store(String),
+    #[error("Token encoding error: {0}")]
+    Encoding(String),
+    #[error("Unknown user")]
+    UnknownUser,
+    #[error("Permission lookup failed: {0}")]
+    Permissions(String),
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Credential check failed: {0}")]
+    Credentials(String),
+}
+
+impl ResponseError for TokenError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TokenError::Invalid
+            | TokenError::Reused
+            | TokenError::Revoked
+            | TokenError::UnknownUser
+            | TokenError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            TokenError::Store(_)
+            | TokenError::Encoding(_)
+            | TokenError::Permissions(_)
+            | TokenError::Credentials(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .json(serde_json::json!({ "error": self.to_string() }))
+    }
+}
+
+impl From<redis::RedisError> for TokenError {
+    fn from(e: redis::RedisError) -> Self {
+        TokenError::Store(e.to_string())
+    }
+}
+
+/// Result of presenting a refresh token for rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Rotated,
+    /// The token was already rotated out; the family has been revoked
+    Reused,
+    /// The family is unknown, expired, or revoked
+    Revoked,
+}
+
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// Starts a family whose current refresh token is `jti`.
+    async fn create_family(
+        &self,
+        family_id: Uuid,
+        jti: &str,
+        ttl_secs: u64,
+    ) -> Result<(), TokenError>;
+
+    /// Atomically replaces the family's current token `presented` with `next`.
+    /// Presenting any other token revokes the family.
+    async fn rotate(
+        &self,
+        family_id: Uuid,
+        presented: &str,
+        next: &str,
+        ttl_secs: u64,
+    ) -> Result<Rotation, TokenError>;
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<(), TokenError>;
+}
+
+// Compare-and-swap of the family's current token. Returns 1 when rotated,
+// -1 on reuse (family deleted) and 0 when the family does not exist.
+const ROTATE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if not current then
+    return 0
+end
+if current ~= ARGV[1] then
+    redis.call('DEL', KEYS[1])
+    return -1
+end
+redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+return 1
+"#;
+
+pub struct RedisTokenStore {
+    client: redis::Client,
+    prefix: String,
+}
+
+impl RedisTokenStore {
+    pub fn new(client: redis::Client, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn family_key(&self, family_id: Uuid) -> String {
+        format!("{}:family:{}", self.prefix, family_id)
+    }
+}
+
+#[async_trait]
+impl TokenStore for RedisTokenStore {
+    async fn create_family(
+        &self,
+        family_id: Uuid,
+        jti: &str,
+        ttl_secs: u64,
+    ) -> Result<(), TokenError> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("SET")
+            .arg(self.family_key(family_id))
+            .arg(jti)
+            .arg("EX")
+            .arg(ttl_secs)
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn rotate(
+        &self,
+        family_id: Uuid,
+        presented: &str,
+        next: &str,
+        ttl_secs: u64,
+    ) -> Result<Rotation, TokenError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let outcome: i32 = redis::Script::new(ROTATE_SCRIPT)
+            .key(self.family_key(family_id))
+            .arg(presented)
+            .arg(next)
+            .arg(ttl_secs)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(match outcome {
+            1 => Rotation::Rotated,
+            -1 => Rotation::Reused,
+            _ => Rotation::Revoked,
+        })
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<(), TokenError> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("DEL")
+            .arg(self.family_key(family_id))
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Issues, rotates, and revokes token pairs
+pub struct TokenService {
+    store: Arc<dyn TokenStore>,
+    permissions: Arc<dyn PermissionResolver>,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+}
+
+impl TokenService {
+    pub fn new(
+        secret: &str,
+        store: Arc<dyn TokenStore>,
+        permissions: Arc<dyn PermissionResolver>,
+    ) -> Self {
+        Self {
+            store,
+            permissions,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            access_ttl: Duration::minutes(15),
+            refresh_ttl: Duration::days(7),
+        }
+    }
+
+    /// Starts a new token family for the user.
+    pub async fn issue(&self, user_id: &str) -> Result<TokenPair, TokenError> {
+        let family_id = Uuid::new_v4();
+        let jti = Uuid::new_v4().to_string();
+
+        self.store
+            .create_family(family_id, &jti, self.refresh_ttl_secs())
+            .await?;
+
+        self.token_pair(user_id, family_id, jti).await
+    }
+
+    /// Exchanges a refresh token for a new pair in the same family.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, TokenError> {
+        let claims = self.decode_refresh(refresh_token)?;
+        let next_jti = Uuid::new_v4().to_string();
+
+        match self
+            .store
+            .rotate(claims.fid, &claims.jti, &next_jti, self.refresh_ttl_secs())
+            .await?
+        {
+            Rotation::Rotated => self.token_pair(&claims.sub, claims.fid, next_jti).await,
+            Rotation::Reused => {
+                tracing::warn!(
+                    "Refresh token reuse for user {}, revoked family {}",
+                    claims.sub,
+                    claims.fid
+                );
+                Err(TokenError::Reused)
+            }
+            Rotation::Revoked => Err(TokenError::Revoked),
+        }
+    }
+
+    /// Revokes the family the refresh token belongs to.
+    pub async fn revoke(&self, refresh_token: &str) -> Result<(), TokenError> {
+        let claims = self.decode_refresh(refresh_token)?;
+        self.store.revoke_family(claims.fid).await
+    }
+
+    pub fn decode(&self, token: &str) -> Result<Claims, TokenError> {
+        decode::<Claims>(token, &self.decoding_key, &Validation::default())
+            .map(|data| data.claims)
+            .map_err(|_| TokenError::Invalid)
+    }
+
+    /// Claims of an access token presented as a bearer credential
+    pub fn decode_access(&self, token: &str) -> Result<Claims, TokenError> {
+        let claims = self.decode(token)?;
+        if claims.token_type != TokenType::Access {
+            return Err(TokenError::Invalid);
+        }
+        Ok(claims)
+    }
+
+    fn decode_refresh(&self, token: &str) -> Result<Claims, TokenError> {
+        let claims = self.decode(token)?;
+        if claims.token_type != TokenType::Refresh {
+            return Err(TokenError::Invalid);
+        }
+        Ok(claims)
+    }
+
+    async fn token_pair(
+        &self,
+        user_id: &str,
+        family_id: Uuid,
+        refresh_jti: String,
+    ) -> Result<TokenPair, TokenError> {
+        let perms = self.permissions.permissions(user_id).await?;
+        let access_token = self.encode(
+            user_id,
+            family_id,
+            Uuid::new_v4().to_string(),
+            TokenType::Access,
+            self.access_ttl,
+            Some(perms),
+        )?;
+        let refresh_token = self.encode(
+            user_id,
+            family_id,
+            refresh_jti,
+            TokenType::Refresh,
+            self.refresh_ttl,
+            None,
+        )?;
+
+        Ok(TokenPair {
+            access_token,
+            refresh_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.access_ttl.num_seconds(),
+        })
+    }
+
+    fn encode(
+        &self,
+        user_id: &str,
+        family_id: Uuid,
+        jti: String,
+        token_type: TokenType,
+        ttl: Duration,
+        perms: Option<Vec<String>>,
+    ) -> Result<String, TokenError> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: user_id.to_string(),
+            iat: now.timestamp() as usize,
+            exp: (now + ttl).timestamp() as usize,
+            jti,
+            fid: family_id,
+            token_type,
+            perms,
+        };
+
+        encode(&Header::default(), &claims, &self.encoding_key)
+            .map_err(|e| TokenError::Encoding(e.to_string()))
+    }
+
+    fn refresh_ttl_secs(&self) -> u64 {
+        self.refresh_ttl.num_seconds().max(1) as u64
+    }
+}
+
+/// In-process `TokenStore` with the same semantics as the Redis store, minus expiry
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryTokenStore {
+    families: std::sync::Mutex<std::collections::HashMap<Uuid, String>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl TokenStore for InMemoryTokenStore {
+    async fn create_family(&self, family_id: Uuid, jti: &str, _: u64) -> Result<(), TokenError> {
+        self.families
+            .lock()
+            .unwrap()
+            .insert(family_id, jti.to_string());
+        Ok(())
+    }
+
+    async fn rotate(
+        &self,
+        family_id: Uuid,
+        presented: &str,
+        next: &str,
+        _: u64,
+    ) -> Result<Rotation, TokenError> {
+        let mut families = self.families.lock().unwrap();
+        match families.get(&family_id) {
+            None => Ok(Rotation::Revoked),
+            Some(current) if current != presented => {
+                families.remove(&family_id);
+                Ok(Rotation::Reused)
+            }
+            Some(_) => {
+                families.insert(family_id, next.to_string());
+                Ok(Rotation::Rotated)
+            }
+        }
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<(), TokenError> {
+        self.families.lock().unwrap().remove(&family_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::StaticPermissions;
+
+    fn service() -> TokenService {
+        let permissions = StaticPermissions::default();
+        permissions.grant("user-1", &[]);
+        service_with(Arc::new(permissions))
+    }
+
+    fn service_with(permissions: Arc<StaticPermissions>) -> TokenService {
+        TokenService::new(
+            "test-secret",
+            Arc::new(InMemoryTokenStore::default()),
+            permissions,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_token() {
+        let service = service();
+        let issued = service.issue("user-1").await.unwrap();
+
+        let rotated = service.refresh(&issued.refresh_token).await.unwrap();
+        assert_ne!(rotated.refresh_token, issued.refresh_token);
+
+        let old = service.decode(&issued.refresh_token).unwrap();
+        let new = service.decode(&rotated.refresh_token).unwrap();
+        assert_eq!(old.fid, new.fid);
+        assert_ne!(old.jti, new.jti);
+        assert_eq!(new.sub, "user-1");
+
+        // The rotated token is itself usable
+        assert!(service.refresh(&rotated.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replayed_refresh_token_revokes_family() {
+        let service = service();
+        let issued = service.issue("user-1").await.unwrap();
+        let rotated = service.refresh(&issued.refresh_token).await.unwrap();
+
+        // Replaying the rotated-out token is detected...
+        assert!(matches!(
+            service.refresh(&issued.refresh_token).await,
+            Err(TokenError::Reused)
+        ));
+        // ...and kills the legitimate successor as well
+        assert!(matches!(
+            service.refresh(&rotated.refresh_token).await,
+            Err(TokenError::Revoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_family() {
+        let service = service();
+        let issued = service.issue("user-1").await.unwrap();
+        let other = service.issue("user-1").await.unwrap();
+
+        service.revoke(&issued.refresh_token).await.unwrap();
+
+        assert!(matches!(
+            service.refresh(&issued.refresh_token).await,
+            Err(TokenError::Revoked)
+        ));
+        // Other sessions are unaffected
+        assert!(service.refresh(&other.refresh_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_access_token_rejected_for_refresh() {
+        let service = service();
+        let issued = service.issue("user-1").await.unwrap();
+
+        let claims = service.decode(&issued.access_token).unwrap();
+        assert_eq!(claims.token_type, TokenType::Access);
+        assert!(!claims.jti.is_empty());
+        assert!(matches!(
+            service.refresh(&issued.access_token).await,
+            Err(TokenError::Invalid)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_access_token_carries_current_permissions() {
+        let permissions = Arc::new(StaticPermissions::default());
+        permissions.grant("user-1", &["findings:read", "scans:read"]);
+        let service = service_with(permissions.clone());
+
+        let issued = service.issue("user-1").await.unwrap();
+        let claims = service.decode(&issued.access_token).unwrap();
+        assert_eq!(
+            claims.perms,
+            Some(vec!["findings:read".to_string(), "scans:read".to_string()])
+        );
+        assert_eq!(service.decode(&issued.refresh_token).unwrap().perms, None);
+
+        // A role change shows up at the next refresh
+        permissions.grant("user-1", &["scans:read"]);
+        let rotated = service.refresh(&issued.refresh_token).await.unwrap();
+        let claims = service.decode(&rotated.access_token).unwrap();
+        assert_eq!(claims.perms, Some(vec!["scans:read".to_string()]));
+
+        // Users who no longer exist get no more tokens
+        assert!(matches!(
+            service.issue("user-2").await,
+            Err(TokenError::UnknownUser)
+        ));
+    }
+}
@@ -0,0 +1,577 @@
+//! Optional TOTP two-factor authentication
+//!
+//! A user enrolls to get a fresh secret, as both base32 and an `otpauth://`
+//! URI for authenticator apps, and turns 2FA on by confirming one code from
+//! it. From then on login also needs a current 6-digit code. Codes are
+//! accepted up to `skew_steps` time steps either side of the server clock,
+//! and each step's code works once. Secrets are stored encrypted.
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use async_trait::async_trait;
+use mirage_common::crypto::{CryptoError, SecretCipher};
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use thiserror::Error;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const DIGITS: usize = 6;
+/// 160 bits, as RFC 4226 recommends for HMAC-SHA1
+const SECRET_LEN: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct TotpConfig {
+    /// Shown by authenticator apps next to the code
+    pub issuer: String,
+    pub step_secs: u64,
+    /// How many steps a code may be early or late
+    pub skew_steps: u8,
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "Mirage".to_string(),
+            step_secs: 30,
+            skew_steps: 1,
+        }
+    }
+}
+
+impl TotpConfig {
+    /// Reads `TOTP_ISSUER`, `TOTP_STEP_SECS` and `TOTP_SKEW_STEPS`, keeping
+    /// the default for any that is unset or invalid
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            issuer: std::env::var("TOTP_ISSUER").unwrap_or(defaults.issuer),
+            step_secs: std::env::var("TOTP_STEP_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|step| *step > 0)
+                .unwrap_or(defaults.step_secs),
+            skew_steps: std::env::var("TOTP_SKEW_STEPS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.skew_steps),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TotpError {
+    #[error("Two-factor code required")]
+    CodeRequired,
+    #[error("Invalid two-factor code")]
+    InvalidCode,
+    #[error("Two-factor authentication has not been enrolled")]
+    NotEnrolled,
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("Secret encryption error: {0}")]
+    Crypto(String),
+    #[error("TOTP store error: {0}")]
+    Store(String),
+}
+
+impl ResponseError for TotpError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TotpError::CodeRequired | TotpError::InvalidCode => StatusCode::UNAUTHORIZED,
+            TotpError::NotEnrolled => StatusCode::BAD_REQUEST,
+            TotpError::AlreadyEnabled => StatusCode::CONFLICT,
+            TotpError::Crypto(_) | TotpError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .json(serde_json::json!({ "error": self.to_string() }))
+    }
+}
+
+impl From<CryptoError> for TotpError {
+    fn from(e: CryptoError) -> Self {
+        TotpError::Crypto(e.to_string())
+    }
+}
+
+impl From<sqlx::Error> for TotpError {
+    fn from(e: sqlx::Error) -> Self {
+        TotpError::Store(e.to_string())
+    }
+}
+
+/// A user's stored TOTP secret
+#[derive(Debug, Clone)]
+pub struct TotpCredential {
+    pub encrypted_secret: String,
+    pub enabled: bool,
+    pub last_used_step: Option<i64>,
+}
+
+#[async_trait]
+pub trait TotpStore: Send + Sync {
+    async fn find(&self, user_id: &str) -> Result<Option<TotpCredential>, TotpError>;
+
+    /// Stores a secret awaiting confirmation, replacing any earlier pending
+    /// one. Returns false, storing nothing, if 2FA is already enabled.
+    async fn save_pending(&self, user_id: &str, encrypted_secret: &str) -> Result<bool, TotpError>;
+
+    /// Enables the pending secret with `step` as its first used step.
+    /// Returns false if there was no pending secret.
+    async fn enable(&self, user_id: &str, step: i64) -> Result<bool, TotpError>;
+
+    /// Records `step` as used, unless it is not past the last used one.
+    /// Returns false for a replayed or stale step.
+    async fn use_step(&self, user_id: &str, step: i64) -> Result<bool, TotpError>;
+}
+
+pub struct PgTotpStore {
+    pool: PgPool,
+}
+
+impl PgTotpStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TotpStore for PgTotpStore {
+    async fn find(&self, user_id: &str) -> Result<Option<TotpCredential>, TotpError> {
+        let row: Option<(String, bool, Option<i64>)> = sqlx::query_as(
+            "SELECT encrypted_secret, enabled_at IS NOT NULL, last_used_step \
+             FROM totp_credentials WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(
+            |(encrypted_secret, enabled, last_used_step)| TotpCredential {
+                encrypted_secret,
+                enabled,
+                last_used_step,
+            },
+        ))
+    }
+
+    async fn save_pending(&self, user_id: &str, encrypted_secret: &str) -> Result<bool, TotpError> {
+        let result = sqlx::query(
+            "INSERT INTO totp_credentials (user_id, encrypted_secret) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE \
+             SET encrypted_secret = EXCLUDED.encrypted_secret, last_used_step = NULL, \
+                 created_at = NOW() \
+             WHERE totp_credentials.enabled_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(encrypted_secret)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn enable(&self, user_id: &str, step: i64) -> Result<bool, TotpError> {
+        let result = sqlx::query(
+            "UPDATE totp_credentials SET enabled_at = NOW(), last_used_step = $2 \
+             WHERE user_id = $1 AND enabled_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_step(&self, user_id: &str, step: i64) -> Result<bool, TotpError> {
+        let result = sqlx::query(
+            "UPDATE totp_credentials SET last_used_step = $2 \
+             WHERE user_id = $1 AND enabled_at IS NOT NULL \
+               AND (last_used_step IS NULL OR last_used_step < $2)",
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// What a user needs to set up their authenticator
+#[derive(Debug, Serialize)]
+pub struct Enrollment {
+    /// Base32, for typing in by hand
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Enrolls users in 2FA and checks their codes
+pub struct TotpService {
+    config: TotpConfig,
+    cipher: SecretCipher,
+    store: Arc<dyn TotpStore>,
+}
+
+impl TotpService {
+    pub fn new(config: TotpConfig, cipher: SecretCipher, store: Arc<dyn TotpStore>) -> Self {
+        Self {
+            config,
+            cipher,
+            store,
+        }
+    }
+
+    /// Gives the user a new secret. 2FA stays off until it is confirmed
+    /// with [`TotpService::enable`].
+    pub async fn enroll(&self, user_id: &str) -> Result<Enrollment, TotpError> {
+        let mut secret = vec![0u8; SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let totp = self.totp(secret, user_id);
+        let encoded = totp.get_secret_base32();
+
+        if !self
+            .store
+            .save_pending(user_id, &self.cipher.encrypt(&encoded)?)
+            .await?
+        {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        Ok(Enrollment {
+            secret: encoded,
+            otpauth_uri: totp.get_url(),
+        })
+    }
+
+    /// Turns 2FA on once the user shows a code from their new secret
+    pub async fn enable(&self, user_id: &str, code: &str) -> Result<(), TotpError> {
+        self.enable_at(user_id, code, unix_now()).await
+    }
+
+    /// Checks the code login needs from users who have 2FA enabled. Users
+    /// without it pass whatever they send.
+    pub async fn verify_login(&self, user_id: &str, code: Option<&str>) -> Result<(), TotpError> {
+        self.verify_login_at(user_id, code, unix_now()).await
+    }
+
+    async fn enable_at(&self, user_id: &str, code: &str, now: u64) -> Result<(), TotpError> {
+        let credential = match self.store.find(user_id).await? {
+            None => return Err(TotpError::NotEnrolled),
+            Some(credential) if credential.enabled => return Err(TotpError::AlreadyEnabled),
+            Some(credential) => credential,
+        };
+
+        let step = self
+            .matching_step(&credential, user_id, code, now)?
+            .ok_or(TotpError::InvalidCode)?;
+
+        if !self.store.enable(user_id, step).await? {
+            return Err(TotpError::AlreadyEnabled);
+        }
+        Ok(())
+    }
+
+    async fn verify_login_at(
+        &self,
+        user_id: &str,
+        code: Option<&str>,
+        now: u64,
+    ) -> Result<(), TotpError> {
+        let credential = match self.store.find(user_id).await? {
+            Some(credential) if credential.enabled => credential,
+            _ => return Ok(()),
+        };
+        let code = code.ok_or(TotpError::CodeRequired)?;
+
+        match self.matching_step(&credential, user_id, code, now)? {
+            Some(step) if self.store.use_step(user_id, step).await? => Ok(()),
+            _ => Err(TotpError::InvalidCode),
+        }
+    }
+
+    /// The time step within the drift window whose code `code` is. Steps
+    /// up to the last used one are skipped, so a code is never taken twice.
+    fn matching_step(
+        &self,
+        credential: &TotpCredential,
+        user_id: &str,
+        code: &str,
+        now: u64,
+    ) -> Result<Option<i64>, TotpError> {
+        if code.len() != DIGITS || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(None);
+        }
+
+        let encoded = self.cipher.decrypt(&credential.encrypted_secret)?;
+        let secret = Secret::Encoded(encoded)
+            .to_bytes()
+            .map_err(|e| TotpError::Crypto(format!("Stored secret is not base32: {:?}", e)))?;
+        let totp = self.totp(secret, user_id);
+
+        let current = (now / self.config.step_secs) as i64;
+        let skew = i64::from(self.config.skew_steps);
+        let first = credential.last_used_step.map_or(0, |last| last + 1);
+        Ok((current - skew..=current + skew)
+            .filter(|step| *step >= first)
+            .find(|step| totp.check(code, *step as u64 * self.config.step_secs)))
+    }
+
+    fn totp(&self, secret: Vec<u8>, user_id: &str) -> TOTP {
+        // Skew 0: the drift window is walked in `matching_step`, which needs
+        // to know which step matched
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            DIGITS,
+            0,
+            self.config.step_secs,
+            secret,
+            Some(self.config.issuer.clone()),
+            user_id.to_string(),
+        )
+    }
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+/// In-process `TotpStore` with the same semantics as the Postgres store
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryTotpStore {
+    credentials: std::sync::Mutex<std::collections::HashMap<String, TotpCredential>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl TotpStore for InMemoryTotpStore {
+    async fn find(&self, user_id: &str) -> Result<Option<TotpCredential>, TotpError> {
+        Ok(self.credentials.lock().unwrap().get(user_id).cloned())
+    }
+
+    async fn save_pending(&self, user_id: &str, encrypted_secret: &str) -> Result<bool, TotpError> {
+        let mut credentials = self.credentials.lock().unwrap();
+        if credentials.get(user_id).is_some_and(|c| c.enabled) {
+            return Ok(false);
+        }
+        credentials.insert(
+            user_id.to_string(),
+            TotpCredential {
+                encrypted_secret: encrypted_secret.to_string(),
+                enabled: false,
+                last_used_step: None,
+            },
+        );
+        Ok(true)
+    }
+
+    async fn enable(&self, user_id: &str, step: i64) -> Result<bool, TotpError> {
+        match self.credentials.lock().unwrap().get_mut(user_id) {
+            Some(credential) if !credential.enabled => {
+                credential.enabled = true;
+                credential.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_step(&self, user_id: &str, step: i64) -> Result<bool, TotpError> {
+        match self.credentials.lock().unwrap().get_mut(user_id) {
+            Some(credential)
+                if credential.enabled && credential.last_used_step.is_none_or(|s| s < step) =>
+            {
+                credential.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=";
+    // Well inside the 30-second step that starts at 1_700_000_010
+    const NOW: u64 = 1_700_000_025;
+
+    fn service_with(store: Arc<dyn TotpStore>) -> TotpService {
+        TotpService::new(
+            TotpConfig::default(),
+            SecretCipher::new(KEY).unwrap(),
+            store,
+        )
+    }
+
+    fn service() -> TotpService {
+        service_with(Arc::new(InMemoryTotpStore::default()))
+    }
+
+    /// The code an authenticator set up from `enrollment` shows at `time`
+    fn code_at(enrollment: &Enrollment, time: u64) -> String {
+        TOTP::from_url(&enrollment.otpauth_uri)
+            .unwrap()
+            .generate(time)
+    }
+
+    #[tokio::test]
+    async fn test_enrollment_hands_out_an_encrypted_secret() {
+        let store = Arc::new(InMemoryTotpStore::default());
+        let service = service_with(store.clone());
+
+        let enrollment = service.enroll("user-1").await.unwrap();
+        assert_eq!(enrollment.secret.len(), 32);
+        assert!(enrollment
+            .otpauth_uri
+            .starts_with("otpauth://totp/Mirage:user-1?"));
+        assert!(enrollment
+            .otpauth_uri
+            .contains(&format!("secret={}", enrollment.secret)));
+
+        let stored = store.find("user-1").await.unwrap().unwrap();
+        assert!(!stored.enabled);
+        assert!(!stored.encrypted_secret.contains(&enrollment.secret));
+
+        // Until it is enabled, login doesn't ask for a code
+        service.verify_login_at("user-1", None, NOW).await.unwrap();
+
+        // Enrolling again replaces the pending secret
+        let again = service.enroll("user-1").await.unwrap();
+        assert_ne!(again.secret, enrollment.secret);
+        assert!(matches!(
+            service
+                .enable_at("user-1", &code_at(&enrollment, NOW), NOW)
+                .await,
+            Err(TotpError::InvalidCode)
+        ));
+        service
+            .enable_at("user-1", &code_at(&again, NOW), NOW)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.enroll("user-1").await,
+            Err(TotpError::AlreadyEnabled)
+        ));
+        assert!(matches!(
+            service.enable_at("user-2", "123456", NOW).await,
+            Err(TotpError::NotEnrolled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_code_within_drift_window_passes_login() {
+        let service = service();
+        let enrollment = service.enroll("user-1").await.unwrap();
+        service
+            .enable_at("user-1", &code_at(&enrollment, NOW), NOW)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.verify_login_at("user-1", None, NOW + 30).await,
+            Err(TotpError::CodeRequired)
+        ));
+
+        // One step late, from a slow clock
+        let code = code_at(&enrollment, NOW + 30);
+        service
+            .verify_login_at("user-1", Some(&code), NOW + 60)
+            .await
+            .unwrap();
+
+        // Each code works once, and none from before the last one used
+        assert!(matches!(
+            service
+                .verify_login_at("user-1", Some(&code), NOW + 60)
+                .await,
+            Err(TotpError::InvalidCode)
+        ));
+        assert!(matches!(
+            service
+                .verify_login_at("user-1", Some(&code_at(&enrollment, NOW)), NOW + 30)
+                .await,
+            Err(TotpError::InvalidCode)
+        ));
+
+        let code = code_at(&enrollment, NOW + 90);
+        service
+            .verify_login_at("user-1", Some(&code), NOW + 90)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_code_outside_drift_window_fails() {
+        let service = service();
+        let enrollment = service.enroll("user-1").await.unwrap();
+        service
+            .enable_at("user-1", &code_at(&enrollment, NOW), NOW)
+            .await
+            .unwrap();
+
+        let later = NOW + 300;
+        for time in [later - 60, later + 60] {
+            let code = code_at(&enrollment, time);
+            assert!(matches!(
+                service.verify_login_at("user-1", Some(&code), later).await,
+                Err(TotpError::InvalidCode)
+            ));
+        }
+        assert!(matches!(
+            service
+                .verify_login_at("user-1", Some("12345"), later)
+                .await,
+            Err(TotpError::InvalidCode)
+        ));
+
+        // A wider window takes the same early code
+        let service = TotpService {
+            config: TotpConfig {
+                skew_steps: 2,
+                ..TotpConfig::default()
+            },
+            ..service
+        };
+        let code = code_at(&enrollment, later + 60);
+        service
+            .verify_login_at("user-1", Some(&code), later)
+            .await
+            .unwrap();
+    }
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_postgres_store_enables_once_and_refuses_replayed_steps(pool: PgPool) {
+        let store = PgTotpStore::new(pool);
+
+        assert!(store.save_pending("user-1", "sealed-1").await.unwrap());
+        assert!(store.save_pending("user-1", "sealed-2").await.unwrap());
+        let pending = store.find("user-1").await.unwrap().unwrap();
+        assert_eq!(pending.encrypted_secret, "sealed-2");
+        assert!(!pending.enabled);
+        assert!(!store.use_step("user-1", 10).await.unwrap());
+
+        assert!(store.enable("user-1", 10).await.unwrap());
+        assert!(!store.enable("user-1", 11).await.unwrap());
+        assert!(!store.save_pending("user-1", "sealed-3").await.unwrap());
+
+        assert!(!store.use_step("user-1", 10).await.unwrap());
+        assert!(store.use_step("user-1", 11).await.unwrap());
+        assert!(!store.use_step("user-1", 11).await.unwrap());
+
+        let enabled = store.find("user-1").await.unwrap().unwrap();
+        assert_eq!(enabled.encrypted_secret, "sealed-2");
+        assert_eq!(enabled.last_used_step, Some(11));
+        assert!(store.find("user-2").await.unwrap().is_none());
+    }
+}
@@ -52,6 +52,26 @@ impl AuditService {
             .await
     }
 
+    // Log rollback to an earlier version
+    pub async fn log_rollback(
+        &self,
+        entity_type: &str,
+        entity_id: &Uuid,
+        user_id: Option<&str>,
+        details: &Value,
+        summary: Option<String>,
+    ) -> Result<()> {
+        self.log_action(
+            "rollback",
+            entity_type,
+            entity_id,
+            user_id,
+            details,
+            summary,
+        )
+        .await
+    }
+
     // Generic action logging
     async fn log_action(
         &self,
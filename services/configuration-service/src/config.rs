@@ -2,18 +2,14 @@ use config::{Config, ConfigError, File};
 use serde::Deserialize;
 use std::env;
 
+pub use mirage_common::config::DatabaseConfig;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
     pub host: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct DatabaseConfig {
-    pub url: String,
-    pub max_connections: u32,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct RedisConfig {
     pub uri: String,
@@ -16,6 +16,10 @@ pub fn config_routes() -> actix_web::Scope {
         .service(delete_config)
         .service(list_configs)
         .service(get_config_history)
+        .service(get_config_history_by_key)
+        .service(rollback_config)
+        .service(get_config_schema)
+        .service(set_config_schema)
         .service(create_namespace)
         .service(list_namespaces)
         .service(get_raw_config_value)
@@ -186,6 +190,99 @@ async fn get_config_history(
     Ok(HttpResponse::Ok().json(history))
 }
 
+#[get("/namespaces/{namespace}/items/{key}/history")]
+async fn get_config_history_by_key(
+    path: web::Path<(String, String)>,
+    config_service: web::Data<ConfigService>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, key) = path.into_inner();
+
+    let history = config_service
+        .get_config_history_by_key(&key, &namespace)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Error fetching configuration history: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[post("/namespaces/{namespace}/items/{key}/rollback/{version}")]
+async fn rollback_config(
+    path: web::Path<(String, String, i32)>,
+    config_service: web::Data<ConfigService>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, key, version) = path.into_inner();
+
+    // Mock user ID for testing
+    let user_id = Some("config-api".to_string());
+
+    let config = config_service
+        .rollback_config(&key, &namespace, version, user_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Error rolling back configuration: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(config))
+}
+
+#[get("/namespaces/{namespace}/items/{key}/schema")]
+async fn get_config_schema(
+    path: web::Path<(String, String)>,
+    config_service: web::Data<ConfigService>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, key) = path.into_inner();
+
+    let schema = config_service
+        .get_config_schema(&key, &namespace)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Error fetching configuration schema: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(schema))
+}
+
+#[put("/namespaces/{namespace}/items/{key}/schema")]
+async fn set_config_schema(
+    path: web::Path<(String, String)>,
+    schema: web::Json<serde_json::Value>,
+    config_service: web::Data<ConfigService>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, key) = path.into_inner();
+
+    // Mock user ID for testing
+    let user_id = Some("config-api".to_string());
+
+    let config = config_service
+        .set_config_schema(&key, &namespace, schema.into_inner(), user_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Error registering configuration schema: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(config))
+}
+
 #[post("/namespaces")]
 async fn create_namespace(
     request: web::Json<CreateNamespaceRequest>,
@@ -247,3 +344,253 @@ async fn get_raw_config_value(
 
     Ok(HttpResponse::Ok().json(value))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditService;
+    use crate::config::{AppConfig, DatabaseConfig, RedisConfig, ServerConfig};
+    use crate::repositories::{run_migrations, AuditRepository, ConfigRepository, DbPool};
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::{json, Value};
+
+    fn config() -> AppConfig {
+        AppConfig {
+            server: ServerConfig {
+                port: 8010,
+                host: "127.0.0.1".into(),
+            },
+            database: DatabaseConfig {
+                url: "postgres://localhost/mirage_config".into(),
+                max_connections: 5,
+                acquire_timeout_seconds: 5,
+                idle_timeout_seconds: 0,
+            },
+            // Nothing listens here, so every lookup goes to the database
+            redis: RedisConfig {
+                uri: "redis://127.0.0.1:1".into(),
+                cache_ttl_seconds: 60,
+                prefix: "mirage:config".into(),
+            },
+            service_name: "configuration-service".into(),
+            audit_enabled: true,
+        }
+    }
+
+    fn service(pool: DbPool) -> web::Data<ConfigService> {
+        let config = config();
+        web::Data::new(ConfigService::new(
+            ConfigRepository::new(pool.clone()),
+            redis::Client::open(config.redis.uri.clone()).unwrap(),
+            AuditService::new(AuditRepository::new(pool)),
+            config,
+        ))
+    }
+
+    // Creates `scan.max_depth` with `value`, then updates it to each of
+    // `updates` in turn
+    async fn max_depth(service: &ConfigService, value: Value, updates: &[Value]) -> Uuid {
+        let request = serde_json::from_value(json!({
+            "key": "max_depth",
+            "namespace": "scan",
+            "value": value,
+            "value_type": "integer"
+        }))
+        .unwrap();
+        let id = service.create_config(request, None).await.unwrap().id;
+
+        for value in updates {
+            let request = serde_json::from_value(json!({ "value": value })).unwrap();
+            service.update_config(id, request, None).await.unwrap();
+        }
+
+        id
+    }
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_history_lists_every_version(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool);
+        max_depth(&service, json!(2), &[json!(3), json!(5)]).await;
+        let app = test::init_service(App::new().app_data(service).service(config_routes())).await;
+
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/max_depth/history")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let history: Vec<Value> = test::read_body_json(resp).await;
+        let versions: Vec<(i64, i64)> = history
+            .iter()
+            .map(|v| (v["version"].as_i64().unwrap(), v["value"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(versions, vec![(3, 5), (2, 3), (1, 2)]);
+
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/missing/history")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_rollback_restores_a_version(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool.clone());
+        let id = max_depth(&service, json!(2), &[json!(3), json!(5)]).await;
+        let app = test::init_service(App::new().app_data(service).service(config_routes())).await;
+
+        let req = test::TestRequest::post()
+            .uri("/config/namespaces/scan/items/max_depth/rollback/2")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let restored: Value = test::read_body_json(resp).await;
+        assert_eq!(restored["value"], 3);
+        assert_eq!(restored["version"], 4);
+
+        // The rollback is the newest version rather than a rewrite of history
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/max_depth/history")
+            .to_request();
+        let history: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0]["value"], 3);
+        assert_eq!(history[0]["comment"], "Rolled back to version 2");
+
+        // And is in the audit log
+        let (action, details): (String, Value) = sqlx::query_as(
+            "SELECT action, details FROM audit_logs WHERE entity_id = $1 \
+             ORDER BY timestamp DESC LIMIT 1",
+        )
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(action, "rollback");
+        assert_eq!(details["old_value"], 5);
+        assert_eq!(details["restored_version"], 2);
+
+        let req = test::TestRequest::post()
+            .uri("/config/namespaces/scan/items/max_depth/rollback/9")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_rollback_checks_the_current_schema(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool);
+        let id = max_depth(&service, json!(50), &[]).await;
+
+        // Tightening the schema leaves version 1 out of range
+        let request = serde_json::from_value(json!({
+            "value": 5,
+            "schema": { "type": "integer", "maximum": 10 }
+        }))
+        .unwrap();
+        service.update_config(id, request, None).await.unwrap();
+        let app = test::init_service(App::new().app_data(service).service(config_routes())).await;
+
+        let req = test::TestRequest::post()
+            .uri("/config/namespaces/scan/items/max_depth/rollback/1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/max_depth")
+            .to_request();
+        let current: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(current["value"], 5);
+        assert_eq!(current["version"], 2);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_writes_are_validated_against_the_schema(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool);
+        let id = max_depth(&service, json!(2), &[]).await;
+        let app = test::init_service(App::new().app_data(service).service(config_routes())).await;
+
+        let req = test::TestRequest::put()
+            .uri("/config/namespaces/scan/items/max_depth/schema")
+            .set_json(json!({ "type": "integer", "minimum": 1, "maximum": 10 }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/config/items/{}", id))
+            .set_json(json!({ "value": 4 }))
+            .to_request();
+        let updated: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(updated["value"], 4);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/config/items/{}", id))
+            .set_json(json!({ "value": "4" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        let message = std::str::from_utf8(&body).unwrap();
+        assert!(
+            message.contains(r#""4" is not of type "integer""#),
+            "{}",
+            message
+        );
+
+        // Nor can a schema be registered that the current value breaks
+        let req = test::TestRequest::put()
+            .uri("/config/namespaces/scan/items/max_depth/schema")
+            .set_json(json!({ "type": "integer", "maximum": 3 }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_schema_is_served_by_key(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool);
+        max_depth(&service, json!(2), &[]).await;
+        let app = test::init_service(App::new().app_data(service).service(config_routes())).await;
+
+        // Nothing registered yet
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/max_depth/schema")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let schema = json!({ "type": "integer", "minimum": 1 });
+        let req = test::TestRequest::put()
+            .uri("/config/namespaces/scan/items/max_depth/schema")
+            .set_json(&schema)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/max_depth/schema")
+            .to_request();
+        let served: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(served, schema);
+
+        let req = test::TestRequest::get()
+            .uri("/config/namespaces/scan/items/missing/schema")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
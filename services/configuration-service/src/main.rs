@@ -1,4 +1,10 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
 use tracing::info;
 
 mod audit;
@@ -9,14 +15,10 @@ mod repositories;
 mod services;
 mod validation;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("configuration-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -43,7 +45,7 @@ async fn main() -> std::io::Result<()> {
     };
 
     // Initialize Redis connection for caching
-    let redis_client = match redis::Client::open(&config.redis.uri) {
+    let redis_client = match redis::Client::open(config.redis.uri.clone()) {
         Ok(client) => client,
         Err(e) => {
             tracing::error!("Failed to connect to Redis: {}", e);
@@ -62,7 +64,7 @@ async fn main() -> std::io::Result<()> {
     let audit_service = web::Data::new(audit::AuditService::new(audit_repo));
     let config_service = web::Data::new(services::ConfigService::new(
         config_repo,
-        redis_client,
+        redis_client.clone(),
         audit_service.get_ref().clone(),
         config.clone(),
     ));
@@ -72,19 +74,47 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("configuration-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone())
+            // Lookups fall back to the database when the cache is down
+            .optional_probe("redis", {
+                let client = redis_client.clone();
+                move || services::ping_cache(client.clone())
+            }),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("configuration-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::other("Failed to set up metrics"));
+        }
+    };
+
+    let bind_address = format!("{}:{}", config.server.host, config.server.port);
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(config_service.clone())
             .app_data(audit_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::config_routes()),
             )
     })
-    .bind(format!("{}:{}", config.server.host, config.server.port))?
+    .bind(bind_address)?
     .run()
     .await
 }
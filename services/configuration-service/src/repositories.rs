@@ -1,8 +1,8 @@
 use crate::config::DatabaseConfig;
 use crate::models::{AuditLog, ConfigItem, ConfigNamespace, ConfigValueType, ConfigVersion};
 use chrono::Utc;
-use mirage_common::{Error, Result};
-use sqlx::{postgres::PgPoolOptions, query, query_as, Pool, Postgres};
+use mirage_common::{database, Error, Result};
+use sqlx::{query, query_as, Pool, Postgres};
 use std::collections::HashMap;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -10,21 +10,21 @@ use uuid::Uuid;
 pub type DbPool = Pool<Postgres>;
 
 pub async fn create_db_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await
-        .map_err(|e| Error::Database(format!("Failed to connect to database: {}", e)))?;
+    let pool = database::create_pool(config).await?;
 
     // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .map_err(|e| Error::Database(format!("Failed to run migrations: {}", e)))?;
+    run_migrations(&pool).await?;
 
     Ok(pool)
 }
 
+pub async fn run_migrations(pool: &DbPool) -> Result<()> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to run migrations: {}", e)))
+}
+
 #[derive(Clone)]
 pub struct ConfigRepository {
     pool: DbPool,
@@ -274,13 +274,13 @@ impl ConfigRepository {
 
         if let Some(ns) = namespace {
             query_str.push_str(&format!(" AND namespace = ${}", param_idx));
-            params.push(ns);
+            params.push(ns.to_string());
             param_idx += 1;
         }
 
         if let Some(t) = tag {
             query_str.push_str(&format!(" AND ${}::text = ANY(tags)", param_idx));
-            params.push(t);
+            params.push(t.to_string());
             param_idx += 1;
         }
 
@@ -394,6 +394,37 @@ impl ConfigRepository {
         Ok(versions)
     }
 
+    // Get one version of a configuration item
+    pub async fn get_config_version(
+        &self,
+        config_id: &Uuid,
+        version: i32,
+    ) -> Result<Option<ConfigVersion>> {
+        let row = query!(
+            r#"
+            SELECT
+                id, config_id, value, version, created_at, created_by, comment
+            FROM config_versions
+            WHERE config_id = $1 AND version = $2
+            "#,
+            config_id,
+            version
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch config version: {}", e)))?;
+
+        Ok(row.map(|r| ConfigVersion {
+            id: r.id,
+            config_id: r.config_id,
+            value: r.value,
+            version: r.version,
+            created_at: r.created_at,
+            created_by: r.created_by,
+            comment: r.comment,
+        }))
+    }
+
     // Create a new namespace
     pub async fn create_namespace(&self, namespace: &ConfigNamespace) -> Result<()> {
         query!(
@@ -511,7 +542,7 @@ impl ConfigRepository {
 impl FromStr for ConfigValueType {
     type Err = ();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s {
             "string" => Ok(ConfigValueType::String),
             "integer" => Ok(ConfigValueType::Integer),
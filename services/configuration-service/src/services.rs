@@ -8,11 +8,45 @@ use crate::models::{
 use crate::repositories::ConfigRepository;
 use crate::validation::ConfigValidator;
 use chrono::Utc;
+use mirage_common::config_watch::{ConfigChange, CHANGES_CHANNEL};
 use mirage_common::{Error, Result};
 use redis::{AsyncCommands, Client as RedisClient, Commands};
 use std::sync::Arc;
+use tracing::warn;
 use uuid::Uuid;
 
+/// Readiness probe: checks the Redis cache answers
+pub async fn ping_cache(client: RedisClient) -> Result<()> {
+    let mut conn = client
+        .get_async_connection()
+        .await
+        .map_err(|e| Error::Internal(format!("Redis connection error: {}", e)))?;
+
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut conn)
+        .await
+        .map_err(|e| Error::Internal(format!("Redis ping error: {}", e)))?;
+
+    Ok(())
+}
+
+// The change watchers are sent for `config`. Secret items are not published:
+// their value is only ever served masked, which a watcher must not swap in.
+fn published_change(config: &ConfigItem) -> Option<ConfigChange> {
+    if config.is_secret {
+        return None;
+    }
+
+    Some(ConfigChange {
+        id: config.id,
+        namespace: config.namespace.clone(),
+        key: config.key.clone(),
+        version: config.version,
+        value: config.value.clone(),
+        is_secret: false,
+    })
+}
+
 #[derive(Clone)]
 pub struct ConfigService {
     repo: Arc<ConfigRepository>,
@@ -117,6 +151,9 @@ impl ConfigService {
         // Add to cache
         self.cache_config(&config_item).await?;
 
+        // Tell watching services
+        self.publish_change(&config_item).await;
+
         // Log audit event
         self.audit_service
             .log_create(
@@ -309,6 +346,9 @@ impl ConfigService {
         // Update cache
         self.cache_config(&config).await?;
 
+        // Tell watching services
+        self.publish_change(&config).await;
+
         // Log audit event
         let change_details = serde_json::json!({
             "old_value": old_value,
@@ -458,6 +498,193 @@ impl ConfigService {
         Ok(responses)
     }
 
+    // Get configuration history by key and namespace, newest first
+    pub async fn get_config_history_by_key(
+        &self,
+        key: &str,
+        namespace: &str,
+    ) -> Result<Vec<ConfigVersionResponse>> {
+        let config = self
+            .repo
+            .get_config_by_key(key, namespace)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Configuration with key '{}' in namespace '{}' not found",
+                    key, namespace
+                ))
+            })?;
+
+        self.get_config_history(config.id).await
+    }
+
+    // Restore the value a configuration had at an earlier version. The
+    // rollback is recorded as a new version, so history is never rewritten.
+    pub async fn rollback_config(
+        &self,
+        key: &str,
+        namespace: &str,
+        version: i32,
+        user_id: Option<String>,
+    ) -> Result<ConfigResponse> {
+        let mut config = self
+            .repo
+            .get_config_by_key(key, namespace)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Configuration with key '{}' in namespace '{}' not found",
+                    key, namespace
+                ))
+            })?;
+
+        let restored = self
+            .repo
+            .get_config_version(&config.id, version)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Version {} of configuration '{}' in namespace '{}' not found",
+                    version, key, namespace
+                ))
+            })?;
+
+        // The schema may have changed since that version was written, and
+        // the restored value has to satisfy the current one
+        if let Some(schema) = &config.schema {
+            self.validator
+                .validate_against_schema(&restored.value, schema)?;
+        }
+        self.validator
+            .validate_value_type(&restored.value, &config.value_type)?;
+
+        // Store old value for auditing
+        let old_value = config.value.clone();
+        let old_version = config.version;
+
+        config.value = restored.value;
+        config.updated_at = Utc::now();
+        config.updated_by = user_id.clone();
+        config.version += 1;
+
+        let new_version = ConfigVersion {
+            id: Uuid::new_v4(),
+            config_id: config.id,
+            value: config.value.clone(),
+            version: config.version,
+            created_at: config.updated_at,
+            created_by: user_id.clone(),
+            comment: Some(format!("Rolled back to version {}", version)),
+        };
+
+        // Save changes
+        self.repo.update_config(&config).await?;
+        self.repo.create_config_version(&new_version).await?;
+
+        // Update cache
+        self.cache_config(&config).await?;
+
+        // Tell watching services
+        self.publish_change(&config).await;
+
+        // Log audit event
+        let change_details = serde_json::json!({
+            "old_value": old_value,
+            "new_value": config.value,
+            "from_version": old_version,
+            "restored_version": version,
+            "version": config.version,
+        });
+
+        self.audit_service
+            .log_rollback(
+                "config_item",
+                &config.id,
+                user_id.as_deref(),
+                &change_details,
+                Some(format!(
+                    "Rolled back to the value of version {} as version {}",
+                    version, config.version
+                )),
+            )
+            .await?;
+
+        Ok(self.config_response(config))
+    }
+
+    // Get the JSON schema values of a configuration are validated against
+    pub async fn get_config_schema(&self, key: &str, namespace: &str) -> Result<serde_json::Value> {
+        let config = self
+            .repo
+            .get_config_by_key(key, namespace)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Configuration with key '{}' in namespace '{}' not found",
+                    key, namespace
+                ))
+            })?;
+
+        config.schema.ok_or_else(|| {
+            Error::NotFound(format!(
+                "No schema registered for configuration '{}' in namespace '{}'",
+                key, namespace
+            ))
+        })
+    }
+
+    // Register the JSON schema for a configuration. The current value has to
+    // satisfy it; the value itself is unchanged, so no version is added.
+    pub async fn set_config_schema(
+        &self,
+        key: &str,
+        namespace: &str,
+        schema: serde_json::Value,
+        user_id: Option<String>,
+    ) -> Result<ConfigResponse> {
+        let mut config = self
+            .repo
+            .get_config_by_key(key, namespace)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Configuration with key '{}' in namespace '{}' not found",
+                    key, namespace
+                ))
+            })?;
+
+        self.validator
+            .validate_against_schema(&config.value, &schema)?;
+
+        let old_schema = config.schema.replace(schema);
+        config.updated_at = Utc::now();
+        config.updated_by = user_id.clone();
+
+        // Save changes
+        self.repo.update_config(&config).await?;
+
+        // Update cache
+        self.cache_config(&config).await?;
+
+        // Log audit event
+        let change_details = serde_json::json!({
+            "old_schema": old_schema,
+            "new_schema": config.schema,
+        });
+
+        self.audit_service
+            .log_update(
+                "config_item",
+                &config.id,
+                user_id.as_deref(),
+                &change_details,
+                Some("Registered a new schema".to_string()),
+            )
+            .await?;
+
+        Ok(self.config_response(config))
+    }
+
     // Create a new namespace
     pub async fn create_namespace(
         &self,
@@ -574,6 +801,25 @@ impl ConfigService {
 
     // Helper methods
 
+    // Map a config item to its response, masking a secret value
+    fn config_response(&self, config: ConfigItem) -> ConfigResponse {
+        ConfigResponse {
+            id: config.id,
+            key: config.key,
+            namespace: config.namespace,
+            value: self.mask_secret_value(&config.value, config.is_secret),
+            value_type: config.value_type,
+            description: config.description,
+            version: config.version,
+            is_secret: config.is_secret,
+            created_at: config.created_at,
+            updated_at: config.updated_at,
+            tags: config.tags,
+            metadata: config.metadata,
+            schema: config.schema,
+        }
+    }
+
     // Mask secret value in responses
     fn mask_secret_value(&self, value: &serde_json::Value, is_secret: bool) -> serde_json::Value {
         if is_secret {
@@ -599,15 +845,49 @@ impl ConfigService {
         format!("{}:config:{}:{}", self.config.redis.prefix, namespace, key)
     }
 
+    // Connection to the cache, or `None` when it's down. The cache is
+    // optional: lookups fall back to the database without it.
+    async fn cache_connection(&self) -> Option<redis::aio::Connection> {
+        match self.redis_client.get_async_connection().await {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                warn!("Config cache unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    // Publish a new value to the services watching it. Best effort like the
+    // cache: a change that is not delivered reaches a watcher once it next
+    // resubscribes and reloads.
+    async fn publish_change(&self, config: &ConfigItem) {
+        let Some(change) = published_change(config) else {
+            return;
+        };
+        let Some(mut conn) = self.cache_connection().await else {
+            return;
+        };
+
+        let payload = match serde_json::to_string(&change) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize config change: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = conn.publish::<_, _, ()>(CHANGES_CHANNEL, payload).await {
+            warn!("Failed to publish config change: {}", e);
+        }
+    }
+
     // Cache a config item
     async fn cache_config(&self, config: &ConfigItem) -> Result<()> {
         let key = self.cache_key(&config.key, &config.namespace);
 
-        let mut conn = self
-            .redis_client
-            .get_async_connection()
-            .await
-            .map_err(|e| Error::Internal(format!("Redis connection error: {}", e)))?;
+        let Some(mut conn) = self.cache_connection().await else {
+            return Ok(());
+        };
 
         let value = serde_json::to_string(config)
             .map_err(|e| Error::Internal(format!("Failed to serialize config: {}", e)))?;
@@ -623,11 +903,9 @@ impl ConfigService {
     async fn get_from_cache(&self, key: &str, namespace: &str) -> Result<Option<ConfigItem>> {
         let cache_key = self.cache_key(key, namespace);
 
-        let mut conn = self
-            .redis_client
-            .get_async_connection()
-            .await
-            .map_err(|e| Error::Internal(format!("Redis connection error: {}", e)))?;
+        let Some(mut conn) = self.cache_connection().await else {
+            return Ok(None);
+        };
 
         let value: Option<String> = conn
             .get(&cache_key)
@@ -649,11 +927,9 @@ impl ConfigService {
     async fn invalidate_cache(&self, key: &str, namespace: &str) -> Result<()> {
         let cache_key = self.cache_key(key, namespace);
 
-        let mut conn = self
-            .redis_client
-            .get_async_connection()
-            .await
-            .map_err(|e| Error::Internal(format!("Redis connection error: {}", e)))?;
+        let Some(mut conn) = self.cache_connection().await else {
+            return Ok(());
+        };
 
         conn.del::<_, ()>(&cache_key)
             .await
@@ -662,3 +938,40 @@ impl ConfigService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ConfigValueType;
+    use serde_json::json;
+
+    fn item(is_secret: bool) -> ConfigItem {
+        ConfigItem {
+            id: Uuid::new_v4(),
+            key: "api_token".into(),
+            namespace: "shodan".into(),
+            value: json!("tok-123"),
+            value_type: ConfigValueType::String,
+            description: None,
+            version: 2,
+            is_secret,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            created_by: None,
+            updated_by: None,
+            schema: None,
+            tags: Vec::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_secret_items_are_not_published() {
+        assert_eq!(published_change(&item(true)), None);
+
+        let change = published_change(&item(false)).unwrap();
+        assert_eq!(change.value, json!("tok-123"));
+        assert_eq!(change.version, 2);
+        assert!(!change.is_secret);
+    }
+}
@@ -11,25 +11,39 @@ impl ConfigValidator {
         Self {}
     }
 
-    pub fn validate_against_schema(&self, value: &Value, schema: &Value) -> Result<()> {
-        // Parse and compile schema
-        let compiled_schema = JSONSchema::options()
+    // Compile a schema registered for a config key
+    fn compile_schema(&self, schema: &Value) -> Result<JSONSchema> {
+        JSONSchema::options()
             .with_draft(jsonschema::Draft::Draft7)
             .compile(schema)
-            .map_err(|e| Error::Validation(format!("Invalid JSON schema: {}", e)))?;
+            .map_err(|e| Error::Validation(format!("Invalid JSON schema: {}", e)))
+    }
 
-        // Validate value against schema
-        match compiled_schema.validate(value) {
+    pub fn validate_against_schema(&self, value: &Value, schema: &Value) -> Result<()> {
+        let compiled_schema = self.compile_schema(schema)?;
+
+        // Report every failure, each with where in the value it is
+        let result = match compiled_schema.validate(value) {
             Ok(_) => Ok(()),
             Err(errors) => {
-                let error_messages: Vec<String> = errors.map(|err| format!("{}", err)).collect();
+                let error_messages: Vec<String> = errors
+                    .map(|err| {
+                        let path = err.instance_path.to_string();
+                        if path.is_empty() {
+                            err.to_string()
+                        } else {
+                            format!("{} at {}", err, path)
+                        }
+                    })
+                    .collect();
 
                 Err(Error::Validation(format!(
                     "Value does not match schema: {}",
                     error_messages.join(", ")
                 )))
             }
-        }
+        };
+        result
     }
 
     pub fn validate_value_type(&self, value: &Value, value_type: &ConfigValueType) -> Result<()> {
@@ -69,3 +83,52 @@ impl ConfigValidator {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scan_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "max_depth": { "type": "integer", "minimum": 1 },
+                "modules": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["max_depth"]
+        })
+    }
+
+    #[test]
+    fn test_matching_value_passes() {
+        let value = json!({ "max_depth": 3, "modules": ["dns", "whois"] });
+
+        assert!(ConfigValidator::new()
+            .validate_against_schema(&value, &scan_schema())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_wrong_types_are_reported_with_their_location() {
+        let value = json!({ "max_depth": "3", "modules": ["dns", 7] });
+
+        let err = ConfigValidator::new()
+            .validate_against_schema(&value, &scan_schema())
+            .unwrap_err();
+        let Error::Validation(message) = err else {
+            panic!("expected a validation error, got {:?}", err);
+        };
+        assert!(message.contains(r#""3" is not of type "integer" at /max_depth"#));
+        assert!(message.contains(r#"7 is not of type "string" at /modules/1"#));
+    }
+
+    #[test]
+    fn test_invalid_schema_is_rejected() {
+        let err = ConfigValidator::new()
+            .validate_against_schema(&json!(3), &json!({ "type": "number-ish" }))
+            .unwrap_err();
+
+        assert!(err.to_string().contains("Invalid JSON schema"));
+    }
+}
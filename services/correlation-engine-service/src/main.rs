@@ -1,4 +1,10 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
 use tracing::info;
 
 mod analysis;
@@ -8,14 +14,10 @@ mod models;
 mod repositories;
 mod services;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("correlation-engine-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -64,14 +66,44 @@ async fn main() -> std::io::Result<()> {
 
     info!("Starting Correlation Engine on port {}", config.server.port);
 
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("correlation-engine-service", env!("CARGO_PKG_VERSION")).probe(
+            "data-storage-service",
+            health::http_probe(
+                http_client.clone(),
+                &format!("{}/api/v1/health", config.data_storage.url),
+            ),
+        ),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("correlation-engine-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(correlation_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::correlation_routes()),
             )
     })
@@ -9,9 +9,10 @@ use gremlin_client::{
     process::traversal::{GraphTraversalSource, __},
     GremlinClient,
 };
+use mirage_common::client::ServiceClient;
 use mirage_common::{Error, Result};
 use neo4rs::{Graph, Node, Query, Relation};
-use reqwest::Client as HttpClient;
+use reqwest::{Client as HttpClient, Method};
 use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -415,113 +416,33 @@ impl AnalysisJobRepository {
 
 // Repository for accessing the Data Storage service
 pub struct DataStorageRepository {
-    client: HttpClient,
-    base_url: String,
+    client: ServiceClient,
 }
 
 impl DataStorageRepository {
-    pub fn new(client: HttpClient, base_url: String) -> Self {
-        Self { client, base_url }
+    pub fn new(client: ServiceClient) -> Self {
+        Self { client }
     }
 
     pub async fn get_entity(&self, id: &Uuid) -> Result<serde_json::Value> {
-        let url = format!("{}/api/v1/data/{}", self.base_url, id);
-
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            Error::ExternalApi(format!("Failed to fetch entity from data storage: {}", e))
-        })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            if status.as_u16() == 404 {
-                return Err(Error::NotFound(format!("Entity with ID {} not found", id)));
-            } else {
-                return Err(Error::ExternalApi(format!(
-                    "Data storage error ({}): {}",
-                    status, error_text
-                )));
-            }
-        }
-
-        let data = response
-            .json::<serde_json::Value>()
-            .await
-            .map_err(|e| Error::ExternalApi(format!("Failed to parse entity data: {}", e)))?;
-
-        Ok(data)
+        self.client.get_json(&format!("/api/v1/data/{}", id)).await
     }
 
     pub async fn get_relationships(&self, entity_id: &Uuid) -> Result<Vec<serde_json::Value>> {
-        let url = format!("{}/api/v1/data/relationships/{}", self.base_url, entity_id);
-
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            Error::ExternalApi(format!(
-                "Failed to fetch relationships from data storage: {}",
-                e
-            ))
-        })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            return Err(Error::ExternalApi(format!(
-                "Data storage error ({}): {}",
-                status, error_text
-            )));
-        }
-
-        let data = response
-            .json::<Vec<serde_json::Value>>()
+        self.client
+            .get_json(&format!("/api/v1/data/relationships/{}", entity_id))
             .await
-            .map_err(|e| Error::ExternalApi(format!("Failed to parse relationship data: {}", e)))?;
-
-        Ok(data)
     }
 
     pub async fn query_entities(
         &self,
         query_params: &serde_json::Value,
     ) -> Result<Vec<serde_json::Value>> {
-        let url = format!("{}/api/v1/data", self.base_url);
-
-        let response = self
+        let request = self
             .client
-            .get(&url)
-            .query(&query_params)
-            .send()
-            .await
-            .map_err(|e| {
-                Error::ExternalApi(format!("Failed to query entities from data storage: {}", e))
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            return Err(Error::ExternalApi(format!(
-                "Data storage error ({}): {}",
-                status, error_text
-            )));
-        }
-
-        let data = response
-            .json::<Vec<serde_json::Value>>()
-            .await
-            .map_err(|e| Error::ExternalApi(format!("Failed to parse entity data: {}", e)))?;
-
-        Ok(data)
+            .request(Method::GET, "/api/v1/data")
+            .query(query_params);
+        self.client.send_json(request).await
     }
 }
 
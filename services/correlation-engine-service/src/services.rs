@@ -8,6 +8,7 @@ use crate::models::{
 };
 use crate::repositories::{DataStorageRepository, GraphDatabase, GraphRepository};
 use chrono::Utc;
+use mirage_common::client::ServiceClient;
 use mirage_common::{Error, Result};
 use neo4rs::Graph;
 use reqwest::Client as HttpClient;
@@ -32,10 +33,10 @@ impl CorrelationService {
     pub fn new(graph: Graph, http_client: HttpClient, config: AppConfig) -> Self {
         Self {
             graph_repo: Arc::new(GraphRepository::new(graph)),
-            data_storage_repo: Arc::new(DataStorageRepository::new(
+            data_storage_repo: Arc::new(DataStorageRepository::new(ServiceClient::with_client(
                 http_client.clone(),
-                config.data_storage.url.clone(),
-            )),
+                &config.data_storage.url,
+            ))),
             config: Arc::new(config),
             graph_db: Arc::new(GraphDatabase::new(graph)),
             http_client: Arc::new(http_client),
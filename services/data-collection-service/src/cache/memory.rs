@@ -0,0 +1,66 @@
+use super::ResultCache;
+use async_trait::async_trait;
+use mirage_common::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Cache held in process memory, for local runs and tests. Expired entries
+/// are dropped when they are next read.
+#[derive(Default)]
+pub struct MemoryResultCache {
+    entries: Mutex<HashMap<String, (serde_json::Value, Instant)>>,
+}
+
+impl MemoryResultCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ResultCache for MemoryResultCache {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((response, expires_at)) if *expires_at > Instant::now() => {
+                Ok(Some(response.clone()))
+            }
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, response: &serde_json::Value, ttl: Duration) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (response.clone(), Instant::now() + ttl));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_entries_expire_after_their_ttl() {
+        let cache = MemoryResultCache::new();
+        let response = serde_json::json!({ "entities": [] });
+
+        cache
+            .put("dns:1", &response, Duration::from_millis(30))
+            .await
+            .unwrap();
+        assert_eq!(cache.get("dns:1").await.unwrap(), Some(response));
+        assert_eq!(cache.get("dns:2").await.unwrap(), None);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(cache.get("dns:1").await.unwrap(), None);
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! Reuse of module responses for repeated tasks
+//!
+//! Re-running a scan collects the same targets with the same modules again.
+//! Instead of spending the source's quota on that, a task gets the response
+//! its module gave for the same module version, target and parameters within
+//! the last `cache.ttl_seconds`. Only the raw module response is cached:
+//! extraction, enrichment and tagging still run on a hit, so changed rules
+//! apply. Tasks created with `force_refresh` always ask the module and
+//! replace the cached response.
+//!
+//! The cache only saves work. When it can't be read or written the task goes
+//! to the module as if nothing was cached.
+//!
+//! `cache.backend` in the config picks Redis, the default, the in-memory
+//! cache, or no caching.
+
+mod memory;
+mod redis;
+
+pub use self::memory::MemoryResultCache;
+pub use self::redis::RedisResultCache;
+
+use crate::config::{AppConfig, CacheBackend};
+use crate::models::CollectionTask;
+use crate::repositories::create_redis_client;
+use async_trait::async_trait;
+use mirage_common::Result;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[async_trait]
+pub trait ResultCache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>>;
+
+    /// Stores `response` under `key`, replacing any entry, for `ttl`
+    async fn put(&self, key: &str, response: &serde_json::Value, ttl: Duration) -> Result<()>;
+}
+
+/// Cached module responses by task. The default caches nothing.
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    store: Option<Arc<dyn ResultCache>>,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(store: Arc<dyn ResultCache>, ttl: Duration) -> Self {
+        Self {
+            store: Some(store),
+            ttl,
+        }
+    }
+
+    /// Earlier response of the task's module for the same work, unless the
+    /// task wants a fresh one
+    pub async fn get(&self, task: &CollectionTask) -> Option<serde_json::Value> {
+        let store = self.store.as_ref()?;
+        if task.force_refresh {
+            return None;
+        }
+
+        match store.get(&cache_key(task)).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Failed to read cached response for task {}: {}", task.id, e);
+                None
+            }
+        }
+    }
+
+    pub async fn put(&self, task: &CollectionTask, response: &serde_json::Value) {
+        let Some(store) = &self.store else {
+            return;
+        };
+
+        if let Err(e) = store.put(&cache_key(task), response, self.ttl).await {
+            tracing::warn!("Failed to cache response for task {}: {}", task.id, e);
+        }
+    }
+}
+
+/// Identifies the work a task asks of its module. Parameters are hashed in
+/// key order, so the order a client sent them in doesn't matter.
+pub fn cache_key(task: &CollectionTask) -> String {
+    let parameters: BTreeMap<_, _> = task.parameters.iter().collect();
+    let work = serde_json::json!([
+        task.module_version,
+        task.target.target_type,
+        task.target.value,
+        parameters,
+    ]);
+
+    let digest = Sha256::digest(work.to_string().as_bytes());
+    format!("{}:{}", task.module_id, hex::encode(digest))
+}
+
+/// Builds the cache selected in `config`
+pub fn from_config(config: &AppConfig) -> Result<ResponseCache> {
+    let ttl = Duration::from_secs(config.cache.ttl_seconds);
+    let store: Arc<dyn ResultCache> = match config.cache.backend {
+        CacheBackend::Redis => Arc::new(RedisResultCache::new(
+            create_redis_client(&config.redis)?,
+            format!("{}:cache", config.redis.queue_prefix),
+        )),
+        CacheBackend::Memory => Arc::new(MemoryResultCache::new()),
+        CacheBackend::Disabled => return Ok(ResponseCache::default()),
+    };
+
+    Ok(ResponseCache::new(store, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CollectionTarget, TaskStatus, TaskType};
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn task(parameters: &[(&str, serde_json::Value)]) -> CollectionTask {
+        CollectionTask {
+            id: Uuid::new_v4(),
+            task_type: TaskType::SingleTarget,
+            status: TaskStatus::Pending,
+            priority: 5,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            started_at: None,
+            completed_at: None,
+            target: CollectionTarget {
+                id: Uuid::new_v4(),
+                target_type: "domain".to_string(),
+                value: "example.com".to_string(),
+                metadata: HashMap::new(),
+                entity_id: None,
+            },
+            module_id: Uuid::from_u128(1),
+            module_name: "whois".to_string(),
+            module_version: "1.0.0".to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            scan_id: Some(Uuid::new_v4()),
+            created_by: None,
+            error_message: None,
+            result_summary: None,
+            max_duration_seconds: None,
+            attempts: 0,
+            use_proxy: false,
+            force_refresh: false,
+        }
+    }
+
+    #[test]
+    fn test_cache_key_covers_module_target_and_parameters() {
+        let parameters = [
+            ("raw", serde_json::json!(true)),
+            ("servers", serde_json::json!(["whois.iana.org"])),
+        ];
+        let key = cache_key(&task(&parameters));
+
+        // Same work from another task or scan, with parameters in any order
+        let mut reversed = parameters.clone();
+        reversed.reverse();
+        assert_eq!(cache_key(&task(&reversed)), key);
+        assert!(key.starts_with(&format!("{}:", Uuid::from_u128(1))));
+
+        let mut other_value = task(&parameters);
+        other_value.target.value = "example.org".to_string();
+        let mut other_version = task(&parameters);
+        other_version.module_version = "1.1.0".to_string();
+        for other in [other_value, other_version, task(&parameters[..1])] {
+            assert_ne!(cache_key(&other), key);
+        }
+    }
+}
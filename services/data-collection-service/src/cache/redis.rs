@@ -0,0 +1,58 @@
+use super::ResultCache;
+use async_trait::async_trait;
+use mirage_common::{Error, Result};
+use redis::{AsyncCommands, Client};
+use std::time::Duration;
+
+/// Responses stored as JSON strings under `<prefix>:<key>`, left to Redis
+/// to expire
+#[derive(Clone)]
+pub struct RedisResultCache {
+    client: Client,
+    key_prefix: String,
+}
+
+impl RedisResultCache {
+    pub fn new(client: Client, key_prefix: String) -> Self {
+        Self { client, key_prefix }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.key_prefix, key)
+    }
+}
+
+#[async_trait]
+impl ResultCache for RedisResultCache {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let mut conn = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to get Redis connection: {}", e)))?;
+
+        let cached: Option<String> = conn
+            .get(self.key(key))
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read cached response: {}", e)))?;
+
+        // An entry that no longer parses is treated as a miss and overwritten
+        Ok(cached.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    async fn put(&self, key: &str, response: &serde_json::Value, ttl: Duration) -> Result<()> {
+        let mut conn = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to get Redis connection: {}", e)))?;
+
+        conn.set_ex::<_, _, ()>(
+            self.key(key),
+            response.to_string(),
+            ttl.as_secs().max(1) as usize,
+        )
+        .await
+        .map_err(|e| Error::Database(format!("Failed to cache response: {}", e)))
+    }
+}
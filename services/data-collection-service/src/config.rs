@@ -1,5 +1,7 @@
 use config::{Config, ConfigError, File};
+use mirage_common::http::HttpClientOptions;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -35,6 +37,210 @@ pub struct WorkerConfig {
     pub min_workers: usize,
     pub max_workers: usize,
     pub queue_poll_interval_ms: u64,
+    /// Collection tasks running at once across all targets
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Collection tasks running at once against a single host
+    #[serde(default = "default_max_per_host")]
+    pub max_per_host: usize,
+}
+
+fn default_max_in_flight() -> usize {
+    32
+}
+
+fn default_max_per_host() -> usize {
+    2
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueBackend {
+    #[default]
+    Redis,
+    /// In-process queue for local runs; tasks are lost on restart
+    Memory,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueueConfig {
+    #[serde(default)]
+    pub backend: QueueBackend,
+    /// How long a dequeued task stays hidden before it is handed out again.
+    /// Workers extend it while the task runs, so it only bounds how long a
+    /// crashed worker's task waits to be redelivered.
+    #[serde(default = "default_visibility_timeout_seconds")]
+    pub visibility_timeout_seconds: u64,
+    /// Deliveries without an ack before a task is dead-lettered
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_visibility_timeout_seconds() -> u64 {
+    300
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            backend: QueueBackend::default(),
+            visibility_timeout_seconds: default_visibility_timeout_seconds(),
+            max_attempts: default_max_attempts(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    #[default]
+    Redis,
+    /// In-process cache for local runs; emptied on restart
+    Memory,
+    /// Every task asks its module
+    Disabled,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub backend: CacheBackend,
+    /// How long a module response is reused for the same task
+    #[serde(default = "default_cache_ttl_seconds")]
+    pub ttl_seconds: u64,
+}
+
+fn default_cache_ttl_seconds() -> u64 {
+    3600
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackend::default(),
+            ttl_seconds: default_cache_ttl_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnrichmentConfig {
+    #[serde(default = "default_ip_info_url")]
+    pub ip_info_url: String,
+    #[serde(default = "default_dns_resolver_url")]
+    pub dns_resolver_url: String,
+}
+
+fn default_ip_info_url() -> String {
+    "http://ip-api.com/json".to_string()
+}
+
+fn default_dns_resolver_url() -> String {
+    "https://dns.google/resolve".to_string()
+}
+
+impl Default for EnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            ip_info_url: default_ip_info_url(),
+            dns_resolver_url: default_dns_resolver_url(),
+        }
+    }
+}
+
+/// How politely collection tasks treat one source
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SourcePolicy {
+    /// Least time between the starts of two requests to the source
+    #[serde(default)]
+    pub min_delay_ms: u64,
+    /// Up to this much random delay on top, so requests don't arrive in a
+    /// fixed rhythm
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// Requests allowed per minute; unlimited when unset
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+}
+
+impl Default for SourcePolicy {
+    fn default() -> Self {
+        Self {
+            min_delay_ms: 1000,
+            jitter_ms: 500,
+            requests_per_minute: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ThrottleConfig {
+    /// Policy for sources without their own entry
+    #[serde(default)]
+    pub default: SourcePolicy,
+    /// Policies by source host, e.g. `api.shodan.io`
+    #[serde(default)]
+    pub sources: HashMap<String, SourcePolicy>,
+}
+
+impl ThrottleConfig {
+    pub fn policy(&self, source: &str) -> &SourcePolicy {
+        self.sources
+            .iter()
+            .find(|(host, _)| host.eq_ignore_ascii_case(source))
+            .map(|(_, policy)| policy)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// One outbound proxy. `url` is `http://`, `https://`, `socks5://` or
+/// `socks5h://` with host and port.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyEntry {
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Sealed with `proxy.encryption_key`, never in plain text
+    #[serde(default)]
+    pub encrypted_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyConfig {
+    /// Base64-encoded 32-byte key the proxy passwords are encrypted with
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    #[serde(default)]
+    pub proxies: Vec<ProxyEntry>,
+    /// Failures in a row before a proxy is taken out of rotation
+    #[serde(default = "default_proxy_max_failures")]
+    pub max_failures: u32,
+    /// How long a failing proxy stays out of rotation
+    #[serde(default = "default_proxy_cooldown_seconds")]
+    pub cooldown_seconds: u64,
+}
+
+fn default_proxy_max_failures() -> u32 {
+    3
+}
+
+fn default_proxy_cooldown_seconds() -> u64 {
+    300
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            encryption_key: None,
+            proxies: Vec::new(),
+            max_failures: default_proxy_max_failures(),
+            cooldown_seconds: default_proxy_cooldown_seconds(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -45,6 +251,22 @@ pub struct AppConfig {
     pub module_registry: ModuleRegistryConfig,
     pub data_storage: DataStorageConfig,
     pub worker: WorkerConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    /// Reuse of module responses for repeated tasks
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub enrichment: EnrichmentConfig,
+    /// Per-source delays and rate limits for collection requests
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
+    /// Proxies for tasks created with `use_proxy`
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Outbound client and TLS policy used by collectors
+    #[serde(default)]
+    pub http_client: HttpClientOptions,
 }
 
 pub fn load_config() -> Result<AppConfig, ConfigError> {
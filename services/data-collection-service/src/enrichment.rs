@@ -0,0 +1,460 @@
+//! Post-collection artifact enrichment
+//!
+//! An `EnrichmentPipeline` runs an ordered list of `Enricher` steps over the
+//! entities produced by a collection task. Each step may attach extra data to
+//! an entity; a failing step is recorded on the entity and skipped so the rest
+//! of the pipeline still runs.
+
+use crate::config::EnrichmentConfig;
+use crate::models::Entity;
+use async_trait::async_trait;
+use mirage_common::{Error, Result};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Task parameter holding the ordered list of enricher names for a scan.
+pub const ENRICHMENT_PARAMETER: &str = "enrichment";
+
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    /// Unique name used to select and order the enricher in scan configuration.
+    fn name(&self) -> &str;
+
+    /// Whether this enricher applies to the given entity.
+    fn supports(&self, entity: &Entity) -> bool;
+
+    /// Returns the data to merge into the entity.
+    async fn enrich(&self, entity: &Entity) -> Result<HashMap<String, serde_json::Value>>;
+}
+
+/// Outcome of running the pipeline over a batch of entities.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EnrichmentReport {
+    pub applied: usize,
+    pub failed: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct EnrichmentPipeline {
+    steps: Vec<Arc<dyn Enricher>>,
+}
+
+impl EnrichmentPipeline {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Appends a step; steps run in insertion order.
+    pub fn with_step(mut self, step: Arc<dyn Enricher>) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Builds a pipeline from an ordered list of enricher names, looking each
+    /// one up in the available set. Unknown names are rejected.
+    pub fn from_names(names: &[String], available: &[Arc<dyn Enricher>]) -> Result<Self> {
+        let mut pipeline = Self::new();
+
+        for name in names {
+            let step = available
+                .iter()
+                .find(|e| e.name() == name)
+                .ok_or_else(|| Error::Validation(format!("Unknown enricher: {}", name)))?;
+            pipeline.steps.push(step.clone());
+        }
+
+        Ok(pipeline)
+    }
+
+    /// Builds the pipeline configured on a task via the `enrichment` parameter.
+    /// Tasks without the parameter get an empty pipeline.
+    pub fn from_parameters(
+        parameters: &HashMap<String, serde_json::Value>,
+        available: &[Arc<dyn Enricher>],
+    ) -> Result<Self> {
+        let names: Vec<String> = match parameters.get(ENRICHMENT_PARAMETER) {
+            Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+                Error::Validation(format!("Invalid enrichment configuration: {}", e))
+            })?,
+            None => return Ok(Self::new()),
+        };
+
+        Self::from_names(&names, available)
+    }
+
+    pub fn step_names(&self) -> Vec<&str> {
+        self.steps.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Runs every step over every entity. Enriched values are stored in
+    /// `entity.data` and step failures in `entity.metadata` under
+    /// `enrichment.<step>.error`.
+    pub async fn apply(&self, entities: &mut [Entity]) -> EnrichmentReport {
+        let mut report = EnrichmentReport::default();
+
+        for entity in entities.iter_mut() {
+            for step in &self.steps {
+                if !step.supports(entity) {
+                    continue;
+                }
+
+                match step.enrich(entity).await {
+                    Ok(data) => {
+                        entity.data.extend(data);
+                        report.applied += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Enricher {} failed for {} {}: {}",
+                            step.name(),
+                            entity.entity_type,
+                            entity.value,
+                            e
+                        );
+                        entity
+                            .metadata
+                            .insert(format!("enrichment.{}.error", step.name()), e.to_string());
+                        report.failed += 1;
+                    }
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// Returns the built-in enrichers available to scans.
+pub fn default_enrichers(client: Arc<Client>, config: &EnrichmentConfig) -> Vec<Arc<dyn Enricher>> {
+    vec![
+        Arc::new(GeoEnricher::new(client.clone(), config.ip_info_url.clone())),
+        Arc::new(AsnEnricher::new(client.clone(), config.ip_info_url.clone())),
+        Arc::new(ReverseDnsEnricher::new(client, config.dns_resolver_url.clone())),
+    ]
+}
+
+fn entity_ip(entity: &Entity) -> Option<IpAddr> {
+    entity.value.parse().ok()
+}
+
+fn is_ip_entity(entity: &Entity) -> bool {
+    matches!(
+        entity.entity_type.as_str(),
+        "ip" | "ip_address" | "ipv4" | "ipv6"
+    ) && entity_ip(entity).is_some()
+}
+
+async fn fetch_ip_info(client: &Client, base_url: &str, ip: &str) -> Result<serde_json::Value> {
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), ip);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| Error::ExternalApi(format!("IP info lookup failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        return Err(mirage_common::error::map_status_error(
+            response.status(),
+            "IP info lookup failed",
+        ));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| Error::ExternalApi(format!("Failed to parse IP info: {}", e)))
+}
+
+/// Adds country, city, and coordinates for IP entities.
+pub struct GeoEnricher {
+    client: Arc<Client>,
+    base_url: String,
+}
+
+impl GeoEnricher {
+    pub fn new(client: Arc<Client>, base_url: String) -> Self {
+        Self { client, base_url }
+    }
+}
+
+#[async_trait]
+impl Enricher for GeoEnricher {
+    fn name(&self) -> &str {
+        "geo"
+    }
+
+    fn supports(&self, entity: &Entity) -> bool {
+        is_ip_entity(entity)
+    }
+
+    async fn enrich(&self, entity: &Entity) -> Result<HashMap<String, serde_json::Value>> {
+        let info = fetch_ip_info(&self.client, &self.base_url, &entity.value).await?;
+
+        let mut data = HashMap::new();
+        for (source, key) in [
+            ("country", "geo_country"),
+            ("countryCode", "geo_country_code"),
+            ("city", "geo_city"),
+            ("lat", "geo_latitude"),
+            ("lon", "geo_longitude"),
+        ] {
+            if let Some(value) = info.get(source) {
+                data.insert(key.to_string(), value.clone());
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Adds the autonomous system number and owning organisation for IP entities.
+pub struct AsnEnricher {
+    client: Arc<Client>,
+    base_url: String,
+}
+
+impl AsnEnricher {
+    pub fn new(client: Arc<Client>, base_url: String) -> Self {
+        Self { client, base_url }
+    }
+}
+
+#[async_trait]
+impl Enricher for AsnEnricher {
+    fn name(&self) -> &str {
+        "asn"
+    }
+
+    fn supports(&self, entity: &Entity) -> bool {
+        is_ip_entity(entity)
+    }
+
+    async fn enrich(&self, entity: &Entity) -> Result<HashMap<String, serde_json::Value>> {
+        let info = fetch_ip_info(&self.client, &self.base_url, &entity.value).await?;
+
+        let mut data = HashMap::new();
+        // ip-api style responses carry "AS15169 Google LLC" in the `as` field
+        if let Some(as_field) = info.get("as").and_then(|v| v.as_str()) {
+            let mut parts = as_field.splitn(2, ' ');
+            if let Some(number) = parts.next() {
+                data.insert("asn".to_string(), serde_json::json!(number));
+            }
+            if let Some(org) = parts.next() {
+                data.insert("asn_org".to_string(), serde_json::json!(org));
+            }
+        }
+        if let Some(isp) = info.get("isp") {
+            data.insert("isp".to_string(), isp.clone());
+        }
+
+        Ok(data)
+    }
+}
+
+/// Resolves PTR records for IP entities through a DNS-over-HTTPS resolver.
+pub struct ReverseDnsEnricher {
+    client: Arc<Client>,
+    resolver_url: String,
+}
+
+impl ReverseDnsEnricher {
+    pub fn new(client: Arc<Client>, resolver_url: String) -> Self {
+        Self {
+            client,
+            resolver_url,
+        }
+    }
+
+    fn reverse_name(ip: &IpAddr) -> String {
+        match ip {
+            IpAddr::V4(v4) => {
+                let o = v4.octets();
+                format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+            }
+            IpAddr::V6(v6) => {
+                let nibbles: Vec<String> = v6
+                    .octets()
+                    .iter()
+                    .rev()
+                    .flat_map(|b| [b & 0x0f, b >> 4])
+                    .map(|n| format!("{:x}", n))
+                    .collect();
+                format!("{}.ip6.arpa", nibbles.join("."))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Enricher for ReverseDnsEnricher {
+    fn name(&self) -> &str {
+        "reverse_dns"
+    }
+
+    fn supports(&self, entity: &Entity) -> bool {
+        is_ip_entity(entity)
+    }
+
+    async fn enrich(&self, entity: &Entity) -> Result<HashMap<String, serde_json::Value>> {
+        let ip = entity_ip(entity)
+            .ok_or_else(|| Error::Validation(format!("Not an IP address: {}", entity.value)))?;
+
+        let response = self
+            .client
+            .get(&self.resolver_url)
+            .query(&[("name", Self::reverse_name(&ip)), ("type", "PTR".to_string())])
+            .header("Accept", "application/dns-json")
+            .send()
+            .await
+            .map_err(|e| Error::ExternalApi(format!("Reverse DNS lookup failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(mirage_common::error::map_status_error(
+                response.status(),
+                "Reverse DNS lookup failed",
+            ));
+        }
+
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| Error::ExternalApi(format!("Failed to parse DNS response: {}", e)))?;
+
+        let hostnames: Vec<String> = body["Answer"]
+            .as_array()
+            .map(|answers| {
+                answers
+                    .iter()
+                    .filter_map(|a| a["data"].as_str())
+                    .map(|h| h.trim_end_matches('.').to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut data = HashMap::new();
+        data.insert("reverse_dns".to_string(), serde_json::json!(hostnames));
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticEnricher {
+        name: &'static str,
+        key: &'static str,
+        value: &'static str,
+    }
+
+    #[async_trait]
+    impl Enricher for StaticEnricher {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn supports(&self, entity: &Entity) -> bool {
+            is_ip_entity(entity)
+        }
+
+        async fn enrich(&self, _entity: &Entity) -> Result<HashMap<String, serde_json::Value>> {
+            let mut data = HashMap::new();
+            data.insert(self.key.to_string(), serde_json::json!(self.value));
+            Ok(data)
+        }
+    }
+
+    struct FailingEnricher;
+
+    #[async_trait]
+    impl Enricher for FailingEnricher {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn supports(&self, _entity: &Entity) -> bool {
+            true
+        }
+
+        async fn enrich(&self, _entity: &Entity) -> Result<HashMap<String, serde_json::Value>> {
+            Err(Error::ExternalApi("lookup service unavailable".to_string()))
+        }
+    }
+
+    fn ip_entity(value: &str) -> Entity {
+        Entity {
+            id: None,
+            entity_type: "ip_address".to_string(),
+            value: value.to_string(),
+            data: HashMap::new(),
+            metadata: HashMap::new(),
+            confidence: 70,
+            source: "test".to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    fn available() -> Vec<Arc<dyn Enricher>> {
+        vec![
+            Arc::new(StaticEnricher {
+                name: "geo",
+                key: "geo_country",
+                value: "US",
+            }),
+            Arc::new(FailingEnricher),
+            Arc::new(StaticEnricher {
+                name: "asn",
+                key: "asn",
+                value: "AS15169",
+            }),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_accumulates_metadata_and_isolates_failures() {
+        let names = vec!["geo".to_string(), "broken".to_string(), "asn".to_string()];
+        let pipeline = EnrichmentPipeline::from_names(&names, &available()).unwrap();
+        assert_eq!(pipeline.step_names(), vec!["geo", "broken", "asn"]);
+
+        let mut entities = vec![ip_entity("8.8.8.8")];
+        let report = pipeline.apply(&mut entities).await;
+
+        assert_eq!(report, EnrichmentReport { applied: 2, failed: 1 });
+        let entity = &entities[0];
+        assert_eq!(entity.data["geo_country"], "US");
+        assert_eq!(entity.data["asn"], "AS15169");
+        assert!(entity.metadata.contains_key("enrichment.broken.error"));
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_from_parameters() {
+        let mut parameters = HashMap::new();
+        assert!(EnrichmentPipeline::from_parameters(&parameters, &available())
+            .unwrap()
+            .is_empty());
+
+        parameters.insert(ENRICHMENT_PARAMETER.to_string(), serde_json::json!(["asn", "geo"]));
+        let pipeline = EnrichmentPipeline::from_parameters(&parameters, &available()).unwrap();
+        assert_eq!(pipeline.step_names(), vec!["asn", "geo"]);
+
+        parameters.insert(ENRICHMENT_PARAMETER.to_string(), serde_json::json!(["whois"]));
+        assert!(EnrichmentPipeline::from_parameters(&parameters, &available()).is_err());
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        let ip: IpAddr = "192.0.2.10".parse().unwrap();
+        assert_eq!(ReverseDnsEnricher::reverse_name(&ip), "10.2.0.192.in-addr.arpa");
+
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(ReverseDnsEnricher::reverse_name(&ip).starts_with("1.0.0.0."));
+        assert!(ReverseDnsEnricher::reverse_name(&ip).ends_with(".8.b.d.0.1.0.0.2.ip6.arpa"));
+    }
+}
@@ -1,5 +1,11 @@
+use crate::cache::ResponseCache;
 use crate::config::AppConfig;
+use crate::enrichment::{default_enrichers, EnrichmentPipeline};
+use crate::limiter::host_key;
 use crate::models::{CollectionTask, Entity, Relationship, TaskResult};
+use crate::proxy::{ProxyLease, ProxyPool};
+use crate::tagging::TaggingEngine;
+use crate::throttle::{self, SourceThrottle};
 use chrono::Utc;
 use mirage_common::{Error, Result};
 use reqwest::Client;
@@ -13,6 +19,10 @@ pub struct TaskExecutor {
     task: CollectionTask,
     client: Arc<Client>,
     config: Arc<AppConfig>,
+    tagging: TaggingEngine,
+    throttle: SourceThrottle,
+    proxies: ProxyPool,
+    cache: ResponseCache,
 }
 
 impl TaskExecutor {
@@ -21,9 +31,37 @@ impl TaskExecutor {
             task,
             client,
             config,
+            tagging: TaggingEngine::default(),
+            throttle: SourceThrottle::default(),
+            proxies: ProxyPool::default(),
+            cache: ResponseCache::default(),
         }
     }
 
+    /// Apply the given tagging rules to every entity the task collects.
+    pub fn with_tagging(mut self, tagging: TaggingEngine) -> Self {
+        self.tagging = tagging;
+        self
+    }
+
+    /// Pace requests with `throttle`, shared by every task of the pool
+    pub fn with_throttle(mut self, throttle: SourceThrottle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Proxies to pick from when the task has `use_proxy` set
+    pub fn with_proxies(mut self, proxies: ProxyPool) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Reuse module responses from `cache` for repeated tasks
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
     pub async fn execute(&self) -> Result<TaskResult> {
         // Set timeout if configured
         let max_duration = self.task.max_duration_seconds.unwrap_or_else(|| 300); // Default 5 minutes
@@ -44,6 +82,76 @@ impl TaskExecutor {
     }
 
     async fn execute_internal(&self) -> Result<TaskResult> {
+        let proxy = if self.task.use_proxy {
+            let lease = self.proxies.lease().ok_or_else(|| {
+                Error::ExternalApi(format!(
+                    "Task {} needs a proxy, but none is available",
+                    self.task.id
+                ))
+            })?;
+            tracing::debug!("Task {} collecting through proxy {}", self.task.id, lease);
+            Some(lease)
+        } else {
+            None
+        };
+
+        // A repeated task reuses its module's earlier response
+        let execution_result = match self.cache.get(&self.task).await {
+            Some(cached) => {
+                tracing::debug!("Task {} reusing cached module response", self.task.id);
+                cached
+            }
+            None => {
+                let response = self.run_module(proxy.as_ref()).await?;
+                self.cache.put(&self.task, &response).await;
+                response
+            }
+        };
+
+        // Extract entities and relationships
+        let mut entities = self.extract_entities(&execution_result)?;
+        let relationships = self.extract_relationships(&execution_result)?;
+
+        // Run the scan's configured enrichment steps before storing
+        let client = proxy
+            .as_ref()
+            .map_or_else(|| self.client.clone(), |lease| lease.client());
+        let enrichers = default_enrichers(client, &self.config.enrichment);
+        let pipeline = EnrichmentPipeline::from_parameters(&self.task.parameters, &enrichers)?;
+        if !pipeline.is_empty() {
+            let report = pipeline.apply(&mut entities).await;
+            tracing::debug!(
+                "Enrichment for task {}: {} applied, {} failed",
+                self.task.id,
+                report.applied,
+                report.failed
+            );
+        }
+
+        // Tag after enrichment so rules can see the final entity
+        if !self.tagging.is_empty() {
+            let tagged = self.tagging.apply_all(&mut entities);
+            tracing::debug!("Tagging rules matched {} entities for task {}", tagged, self.task.id);
+        }
+
+        // Store data in data storage service
+        self.store_results(&entities, &relationships).await?;
+
+        // Create task result
+        let result = TaskResult {
+            task_id: self.task.id,
+            entities,
+            relationships,
+            raw_data: Some(execution_result),
+            created_at: Utc::now(),
+        };
+
+        Ok(result)
+    }
+
+    /// Runs the task's module via the module registry and returns its
+    /// response
+    async fn run_module(&self, proxy: Option<&ProxyLease>) -> Result<serde_json::Value> {
         // Prepare execution request
         let execution_request = serde_json::json!({
             "target": {
@@ -54,6 +162,7 @@ impl TaskExecutor {
             },
             "parameters": self.task.parameters,
             "task_id": self.task.id,
+            "proxy": proxy.map(|lease| lease.url()),
         });
 
         // Execute module via module registry
@@ -62,6 +171,10 @@ impl TaskExecutor {
             self.config.module_registry.url, self.task.module_id
         );
 
+        // Keep to the source's politeness policy
+        let source = host_key(&self.task.target);
+        self.throttle.wait(&source).await;
+
         let response = self
             .client
             .post(&url)
@@ -70,6 +183,28 @@ impl TaskExecutor {
             .await
             .map_err(|e| Error::ExternalApi(format!("Failed to execute module: {}", e)))?;
 
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = throttle::retry_after(response.headers());
+            self.throttle.back_off(&source, retry_after);
+            return Err(Error::RateLimited(format!(
+                "{} is rate limiting requests, retry after {}s",
+                source,
+                retry_after.as_secs()
+            )));
+        }
+
+        // A module that couldn't reach its source answers with a gateway
+        // error; through a proxy, that counts against the proxy
+        if let Some(lease) = proxy {
+            match response.status() {
+                reqwest::StatusCode::BAD_GATEWAY | reqwest::StatusCode::GATEWAY_TIMEOUT => {
+                    lease.failed()
+                }
+                status if status.is_success() => lease.succeeded(),
+                _ => {}
+            }
+        }
+
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
@@ -79,29 +214,10 @@ impl TaskExecutor {
             )));
         }
 
-        // Process results
-        let execution_result: serde_json::Value = response
+        response
             .json()
             .await
-            .map_err(|e| Error::ExternalApi(format!("Failed to parse execution result: {}", e)))?;
-
-        // Extract entities and relationships
-        let entities = self.extract_entities(&execution_result)?;
-        let relationships = self.extract_relationships(&execution_result)?;
-
-        // Store data in data storage service
-        self.store_results(&entities, &relationships).await?;
-
-        // Create task result
-        let result = TaskResult {
-            task_id: self.task.id,
-            entities,
-            relationships,
-            raw_data: Some(execution_result),
-            created_at: Utc::now(),
-        };
-
-        Ok(result)
+            .map_err(|e| Error::ExternalApi(format!("Failed to parse execution result: {}", e)))
     }
 
     fn extract_entities(&self, result: &serde_json::Value) -> Result<Vec<Entity>> {
@@ -132,6 +248,16 @@ impl TaskExecutor {
                         }
                     }
 
+                    // Keep any tags the module already assigned
+                    let tags = entity_value["tags"]
+                        .as_array()
+                        .map(|tags| {
+                            tags.iter()
+                                .filter_map(|t| t.as_str().map(String::from))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+
                     // Create entity
                     let entity = Entity {
                         id: entity_value["id"]
@@ -143,6 +269,7 @@ impl TaskExecutor {
                         metadata,
                         confidence: entity_value["confidence"].as_u64().unwrap_or(70) as u8,
                         source: self.task.module_name.clone(),
+                        tags,
                     };
 
                     entities.push(entity);
@@ -280,3 +407,179 @@ impl TaskExecutor {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryResultCache;
+    use crate::config::{SourcePolicy, ThrottleConfig};
+    use crate::models::{CollectionTarget, TaskStatus, TaskType};
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Calls = web::Data<AtomicUsize>;
+
+    /// Each execution finds a differently numbered host, so a reused
+    /// response can be told apart from a new one
+    async fn execute_module(calls: Calls) -> HttpResponse {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        HttpResponse::Ok().json(serde_json::json!({
+            "entities": [{
+                "entity_type": "domain",
+                "value": format!("host{}.example.com", call),
+            }]
+        }))
+    }
+
+    /// Stand-in for the module registry and data storage
+    fn mock_services(calls: Calls) -> (String, ServerHandle) {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(calls.clone())
+                .route(
+                    "/api/v1/modules/{id}/execute",
+                    web::post().to(execute_module),
+                )
+                .route(
+                    "/api/v1/data/entities",
+                    web::post().to(HttpResponse::Created),
+                )
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        (url, handle)
+    }
+
+    fn config(url: &str) -> Arc<AppConfig> {
+        let config = serde_json::json!({
+            "server": { "port": 8080, "host": "127.0.0.1" },
+            "mongodb": { "uri": "mongodb://127.0.0.1:1", "database": "test" },
+            "redis": { "uri": "redis://127.0.0.1:1", "queue_prefix": "test" },
+            "module_registry": { "url": url },
+            "data_storage": { "url": url },
+            "worker": { "min_workers": 1, "max_workers": 1, "queue_poll_interval_ms": 10 },
+        });
+        Arc::new(serde_json::from_value(config).unwrap())
+    }
+
+    fn task(module_id: Uuid, force_refresh: bool) -> CollectionTask {
+        CollectionTask {
+            id: Uuid::new_v4(),
+            task_type: TaskType::SingleTarget,
+            status: TaskStatus::Pending,
+            priority: 5,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            started_at: None,
+            completed_at: None,
+            target: CollectionTarget {
+                id: Uuid::new_v4(),
+                target_type: "domain".to_string(),
+                value: "example.com".to_string(),
+                metadata: HashMap::new(),
+                entity_id: None,
+            },
+            module_id,
+            module_name: "subdomains".to_string(),
+            module_version: "1.0.0".to_string(),
+            parameters: HashMap::from([("depth".to_string(), serde_json::json!(2))]),
+            scan_id: Some(Uuid::new_v4()),
+            created_by: None,
+            error_message: None,
+            result_summary: None,
+            max_duration_seconds: None,
+            attempts: 1,
+            use_proxy: false,
+            force_refresh,
+        }
+    }
+
+    async fn collected_host(
+        task: CollectionTask,
+        config: &Arc<AppConfig>,
+        cache: &ResponseCache,
+    ) -> String {
+        let unthrottled = SourceThrottle::new(ThrottleConfig {
+            default: SourcePolicy {
+                min_delay_ms: 0,
+                jitter_ms: 0,
+                requests_per_minute: None,
+            },
+            ..ThrottleConfig::default()
+        });
+        let result = TaskExecutor::new(task, Arc::new(Client::new()), config.clone())
+            .with_throttle(unthrottled)
+            .with_cache(cache.clone())
+            .execute()
+            .await
+            .unwrap();
+        result.entities[0].value.clone()
+    }
+
+    fn memory_cache() -> ResponseCache {
+        ResponseCache::new(Arc::new(MemoryResultCache::new()), Duration::from_secs(60))
+    }
+
+    #[actix_web::test]
+    async fn test_identical_task_is_answered_from_the_cache() {
+        let calls = web::Data::new(AtomicUsize::new(0));
+        let (url, server) = mock_services(calls.clone());
+        let config = config(&url);
+        let cache = memory_cache();
+        let module_id = Uuid::new_v4();
+
+        let first = collected_host(task(module_id, false), &config, &cache).await;
+        let second = collected_host(task(module_id, false), &config, &cache).await;
+        assert_eq!(first, "host1.example.com");
+        assert_eq!(second, first);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Other parameters are other work
+        let mut deeper = task(module_id, false);
+        deeper
+            .parameters
+            .insert("depth".to_string(), serde_json::json!(3));
+        assert_eq!(
+            collected_host(deeper, &config, &cache).await,
+            "host2.example.com"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_force_refresh_bypasses_and_replaces_the_cache() {
+        let calls = web::Data::new(AtomicUsize::new(0));
+        let (url, server) = mock_services(calls.clone());
+        let config = config(&url);
+        let cache = memory_cache();
+        let module_id = Uuid::new_v4();
+
+        collected_host(task(module_id, false), &config, &cache).await;
+        let refreshed = collected_host(task(module_id, true), &config, &cache).await;
+        assert_eq!(refreshed, "host2.example.com");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Later tasks get the refreshed response
+        assert_eq!(
+            collected_host(task(module_id, false), &config, &cache).await,
+            "host2.example.com"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Without a cache every task asks the module
+        let uncached = ResponseCache::default();
+        collected_host(task(module_id, false), &config, &uncached).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        server.stop(false).await;
+    }
+}
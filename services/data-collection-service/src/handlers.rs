@@ -1,17 +1,22 @@
-use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
+use mirage_common::models::{Page, PageRequest};
 use mirage_common::Error as CommonError;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::models::{
-    BatchTaskRequest, CollectionResult, CreateTaskRequest, ExecuteModuleRequest, TaskQueryParams,
+    BatchTaskRequest, CollectionResult, CreateTaskRequest, ExecuteModuleRequest, ResultQuery,
+    TaskQueryParams,
 };
-use crate::services::CollectionService;
+use crate::services::{CollectionService, TaggingService};
+use crate::tagging::{CreateTaggingRuleRequest, UpdateTaggingRuleRequest};
+use crate::throttle::SourceThrottle;
 
 pub fn collection_routes() -> actix_web::Scope {
     web::scope("/collection")
         .service(execute_module)
         .service(list_modules)
+        .service(list_results)
         .service(get_result)
         .service(create_task)
         .service(create_batch_tasks)
@@ -19,6 +24,17 @@ pub fn collection_routes() -> actix_web::Scope {
         .service(get_task_result)
         .service(cancel_task)
         .service(list_tasks)
+        .service(cancel_scan)
+        .service(throttle_state)
+}
+
+pub fn tagging_routes() -> actix_web::Scope {
+    web::scope("/tagging/rules")
+        .service(create_tagging_rule)
+        .service(list_tagging_rules)
+        .service(get_tagging_rule)
+        .service(update_tagging_rule)
+        .service(delete_tagging_rule)
 }
 
 #[post("/execute")]
@@ -29,14 +45,9 @@ async fn execute_module(
     let job_id = collection_service
         .execute_module(data.into_inner())
         .await
-        .map_err(|e| {
+        .map_err(|e: CommonError| {
             tracing::error!("Failed to execute module: {}", e);
-            match e {
-                CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
-                CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
-                CommonError::RateLimit(_) => actix_web::error::ErrorTooManyRequests(e),
-                _ => actix_web::error::ErrorInternalServerError(e),
-            }
+            e
         })?;
 
     Ok(HttpResponse::Accepted().json(serde_json::json!({ "job_id": job_id })))
@@ -57,6 +68,24 @@ async fn list_modules(
     Ok(HttpResponse::Ok().json(modules))
 }
 
+#[get("/results")]
+async fn list_results(
+    query: web::Query<ResultQuery>,
+    collection_service: web::Data<CollectionService>,
+) -> Result<HttpResponse, Error> {
+    let page = PageRequest {
+        limit: query.limit,
+        offset: query.offset,
+    };
+    let (limit, offset) = page.resolve()?;
+
+    let (results, total) = collection_service
+        .list_results(&query, limit, offset)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(results, total, limit, offset)))
+}
+
 #[get("/results/{id}")]
 async fn get_result(
     id: web::Path<String>,
@@ -92,6 +121,7 @@ async fn create_task(
             match e {
                 CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
                 CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+                CommonError::Conflict(_) => actix_web::error::ErrorConflict(e),
                 _ => actix_web::error::ErrorInternalServerError(e),
             }
         })?;
@@ -112,6 +142,7 @@ async fn create_batch_tasks(
             match e {
                 CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
                 CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+                CommonError::Conflict(_) => actix_web::error::ErrorConflict(e),
                 _ => actix_web::error::ErrorInternalServerError(e),
             }
         })?;
@@ -182,6 +213,19 @@ async fn cancel_task(
     Ok(HttpResponse::Ok().json(result))
 }
 
+#[post("/scans/{id}/cancel")]
+async fn cancel_scan(
+    id: web::Path<Uuid>,
+    collection_service: web::Data<CollectionService>,
+) -> Result<HttpResponse, Error> {
+    let result = collection_service.cancel_scan(*id).await.map_err(|e| {
+        tracing::error!("Failed to cancel tasks of scan {}: {}", id, e);
+        actix_web::error::ErrorInternalServerError(e)
+    })?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 #[get("/tasks")]
 async fn list_tasks(
     query: web::Query<TaskQueryParams>,
@@ -213,3 +257,99 @@ async fn list_tasks(
         "pages": (total + per_page - 1) / per_page
     })))
 }
+
+/// Pacing of every source collected from since startup
+#[get("/throttle")]
+async fn throttle_state(throttle: web::Data<SourceThrottle>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(throttle.state()))
+}
+
+#[post("")]
+async fn create_tagging_rule(
+    data: web::Json<CreateTaggingRuleRequest>,
+    tagging_service: web::Data<TaggingService>,
+) -> Result<HttpResponse, Error> {
+    let rule = tagging_service
+        .create_rule(data.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to create tagging rule: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Created().json(rule))
+}
+
+#[get("")]
+async fn list_tagging_rules(
+    tagging_service: web::Data<TaggingService>,
+) -> Result<HttpResponse, Error> {
+    let rules = tagging_service.list_rules().await.map_err(|e| {
+        tracing::error!("Failed to list tagging rules: {}", e);
+        actix_web::error::ErrorInternalServerError(e)
+    })?;
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+#[get("/{id}")]
+async fn get_tagging_rule(
+    id: web::Path<Uuid>,
+    tagging_service: web::Data<TaggingService>,
+) -> Result<HttpResponse, Error> {
+    let rule = tagging_service
+        .get_rule(*id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to get tagging rule {}: {}", id, e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[put("/{id}")]
+async fn update_tagging_rule(
+    id: web::Path<Uuid>,
+    data: web::Json<UpdateTaggingRuleRequest>,
+    tagging_service: web::Data<TaggingService>,
+) -> Result<HttpResponse, Error> {
+    let rule = tagging_service
+        .update_rule(*id, data.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to update tagging rule {}: {}", id, e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[delete("/{id}")]
+async fn delete_tagging_rule(
+    id: web::Path<Uuid>,
+    tagging_service: web::Data<TaggingService>,
+) -> Result<HttpResponse, Error> {
+    tagging_service
+        .delete_rule(*id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to delete tagging rule {}: {}", id, e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
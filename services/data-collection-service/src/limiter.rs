@@ -0,0 +1,214 @@
+//! Concurrency limits for running collection tasks
+//!
+//! Each running task holds a `Slot`, made of one permit from the global
+//! in-flight limit and one from the limit for the host it collects from. A
+//! scan with many tasks against a single site then can't flood that site or
+//! take every slot from other targets. The worker pool puts a task that can't
+//! get a slot back on the queue.
+
+use crate::models::CollectionTarget;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    max_in_flight: usize,
+    max_per_host: usize,
+    in_flight: Arc<Semaphore>,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+/// Held for as long as a task runs, released on drop
+pub struct Slot {
+    _in_flight: OwnedSemaphorePermit,
+    _host: OwnedSemaphorePermit,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_in_flight: usize, max_per_host: usize) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+
+        Self {
+            max_in_flight,
+            max_per_host: max_per_host.max(1),
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a slot for a task against `host`, or `None` when either the
+    /// global or the per-host limit is reached
+    pub fn try_acquire(&self, host: &str) -> Option<Slot> {
+        let in_flight = self.in_flight.clone().try_acquire_owned().ok()?;
+
+        let host_limit = {
+            let mut hosts = self.hosts.lock().unwrap();
+            // Forget hosts with nothing running so the map stays bounded by
+            // the number of tasks in flight
+            hosts.retain(|_, limit| limit.available_permits() < self.max_per_host);
+            hosts
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+                .clone()
+        };
+        let host = host_limit.try_acquire_owned().ok()?;
+
+        Some(Slot {
+            _in_flight: in_flight,
+            _host: host,
+        })
+    }
+
+    /// Whether a task for a host with nothing running could start now
+    pub fn has_capacity(&self) -> bool {
+        self.in_flight.available_permits() > 0
+    }
+
+    /// Tasks currently holding a slot
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.in_flight.available_permits()
+    }
+}
+
+/// The host a task collects from, used as its per-host limit key. URL
+/// targets use their host, email targets their domain, and anything else
+/// (domains, IPs, usernames) its value.
+pub fn host_key(target: &CollectionTarget) -> String {
+    let value = target.value.trim();
+
+    if let Some(host) = Url::parse(value)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    {
+        return host.to_lowercase();
+    }
+
+    match value.rsplit_once('@') {
+        Some((_, domain)) if !domain.is_empty() => domain.to_lowercase(),
+        _ => value.to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn target(target_type: &str, value: &str) -> CollectionTarget {
+        CollectionTarget {
+            id: Uuid::new_v4(),
+            target_type: target_type.to_string(),
+            value: value.to_string(),
+            metadata: HashMap::new(),
+            entity_id: None,
+        }
+    }
+
+    /// Stands in for a collector, recording how many tasks ran against each
+    /// host at the same time
+    #[derive(Default)]
+    struct MockCollector {
+        running: Mutex<HashMap<String, usize>>,
+        peak: Mutex<HashMap<String, usize>>,
+    }
+
+    impl MockCollector {
+        async fn collect(&self, host: &str) {
+            {
+                let mut running = self.running.lock().unwrap();
+                let count = running.entry(host.to_string()).or_default();
+                *count += 1;
+
+                let mut peak = self.peak.lock().unwrap();
+                let max = peak.entry(host.to_string()).or_default();
+                *max = (*max).max(*count);
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            *self.running.lock().unwrap().get_mut(host).unwrap() -= 1;
+        }
+
+        fn peak(&self, host: &str) -> usize {
+            self.peak.lock().unwrap().get(host).copied().unwrap_or(0)
+        }
+    }
+
+    #[test]
+    fn test_host_key_groups_targets_by_host() {
+        assert_eq!(
+            host_key(&target("url", "https://Example.com/login?next=/")),
+            "example.com"
+        );
+        assert_eq!(
+            host_key(&target("url", "http://example.com:8080/")),
+            "example.com"
+        );
+        assert_eq!(
+            host_key(&target("email", "admin@example.com")),
+            "example.com"
+        );
+        assert_eq!(host_key(&target("domain", " Example.com ")), "example.com");
+        assert_eq!(host_key(&target("ip_address", "10.0.0.1")), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_slots_are_limited_per_host_and_globally() {
+        let limiter = ConcurrencyLimiter::new(3, 2);
+
+        let a1 = limiter.try_acquire("a.example").unwrap();
+        let _a2 = limiter.try_acquire("a.example").unwrap();
+        assert!(limiter.try_acquire("a.example").is_none());
+
+        // Another host still gets the remaining global slot
+        let _b1 = limiter.try_acquire("b.example").unwrap();
+        assert!(limiter.try_acquire("c.example").is_none());
+        assert!(!limiter.has_capacity());
+        assert_eq!(limiter.in_flight(), 3);
+
+        drop(a1);
+        assert_eq!(limiter.in_flight(), 2);
+        assert!(limiter.try_acquire("a.example").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_mock_collector_respects_per_host_concurrency() {
+        let limiter = ConcurrencyLimiter::new(8, 2);
+        let collector = Arc::new(MockCollector::default());
+
+        let mut queue: VecDeque<String> = (0..10)
+            .map(|_| "slow.example".to_string())
+            .chain((0..4).map(|_| "other.example".to_string()))
+            .collect();
+        let mut running = Vec::new();
+
+        // Dispatch like the worker pool: tasks without a slot go back on the
+        // queue until a running task finishes
+        while let Some(host) = queue.pop_front() {
+            match limiter.try_acquire(&host) {
+                Some(slot) => {
+                    let collector = collector.clone();
+                    running.push(tokio::spawn(async move {
+                        collector.collect(&host).await;
+                        drop(slot);
+                    }));
+                }
+                None => {
+                    queue.push_back(host);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        }
+        for task in running {
+            task.await.unwrap();
+        }
+
+        assert_eq!(collector.peak("slow.example"), 2);
+        assert_eq!(collector.peak("other.example"), 2);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+}
@@ -1,24 +1,33 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
+use mirage_common::shutdown::{self, Shutdown};
 use tracing::info;
 
+mod cache;
 mod config;
+mod enrichment;
 mod execution;
 mod handlers;
+mod limiter;
 mod models;
 mod module;
+mod proxy;
 mod queue;
 mod repositories;
 mod services;
+mod tagging;
+mod throttle;
 mod workers;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("data-collection-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -46,27 +55,42 @@ async fn main() -> std::io::Result<()> {
 
     let mongo_db = mongo_client.database(&config.mongodb.database);
 
-    // Initialize Redis connection
-    let redis_client = match redis::Client::open(config.redis.uri.clone()) {
-        Ok(client) => client,
+    // Initialize repositories
+    let task_repository = repositories::TaskRepository::new(mongo_db.clone());
+    let result_repository = repositories::ResultRepository::new(mongo_db.clone());
+    let tagging_repository = repositories::TaggingRuleRepository::new(mongo_db.clone());
+
+    if let Err(e) = result_repository.ensure_indexes().await {
+        tracing::error!("Failed to create MongoDB indexes: {}", e);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Failed to create MongoDB indexes",
+        ));
+    }
+
+    // Initialize task queue
+    let task_queue = match queue::from_config(&config) {
+        Ok(queue) => queue,
         Err(e) => {
-            tracing::error!("Failed to connect to Redis: {}", e);
+            tracing::error!("Failed to set up task queue: {}", e);
             return Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
-                "Failed to connect to Redis",
+                "Failed to set up task queue",
             ));
         }
     };
 
-    // Initialize repositories
-    let task_repository = repositories::TaskRepository::new(mongo_db.clone());
-    let result_repository = repositories::ResultRepository::new(mongo_db.clone());
-
-    // Initialize task queue
-    let task_queue = queue::TaskQueue::new(redis_client.clone(), config.redis.queue_prefix.clone());
-
     // Initialize HTTP client for external services
-    let http_client = reqwest::Client::new();
+    let http_client = match config.http_client.build_client() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to build HTTP client: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to build HTTP client",
+            ));
+        }
+    };
 
     // Initialize services
     let collection_service = web::Data::new(services::CollectionService::new(
@@ -76,25 +100,108 @@ async fn main() -> std::io::Result<()> {
         http_client.clone(),
         config.clone(),
     ));
+    let tagging_service = web::Data::new(services::TaggingService::new(
+        tagging_repository.clone(),
+    ));
+
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("data-collection-service", env!("CARGO_PKG_VERSION"))
+            .probe("mongodb", {
+                let db = mongo_db.clone();
+                move || repositories::ping_mongo(db.clone())
+            })
+            .probe(task_queue.name(), {
+                let queue = task_queue.clone();
+                move || {
+                    let queue = queue.clone();
+                    async move { queue.ping().await }
+                }
+            }),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("data-collection-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    let pool_metrics = match workers::PoolMetrics::new(&metrics) {
+        Ok(pool_metrics) => pool_metrics,
+        Err(e) => {
+            tracing::error!("Failed to set up worker pool metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    // Proxies for tasks that ask for one
+    let proxy_pool = match proxy::ProxyPool::from_config(&config.proxy, &config.http_client) {
+        Ok(pool) => pool,
+        Err(e) => {
+            tracing::error!("Failed to set up proxies: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up proxies",
+            ));
+        }
+    };
+
+    // Module responses reused by repeated tasks
+    let response_cache = match cache::from_config(&config) {
+        Ok(cache) => cache,
+        Err(e) => {
+            tracing::error!("Failed to set up response cache: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up response cache",
+            ));
+        }
+    };
+
+    // Request pacing per source, shared by the workers and the API
+    let source_throttle = throttle::SourceThrottle::new(config.throttle.clone());
+
+    // Stops the server, then the worker pool, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
 
     // Start worker pool
+    let worker_shutdown = shutdown.subscribe();
     let worker_config = config.worker.clone();
     let worker_task_repo = task_repository.clone();
     let worker_result_repo = result_repository.clone();
+    let worker_tagging_repo = tagging_repository.clone();
     let worker_task_queue = task_queue.clone();
     let worker_http_client = http_client.clone();
     let worker_app_config = config.clone();
+    let worker_throttle = source_throttle.clone();
+    let worker_proxies = proxy_pool.clone();
+    let worker_cache = response_cache.clone();
 
-    tokio::spawn(async move {
+    let worker_pool = tokio::spawn(async move {
         workers::start_worker_pool(
             worker_task_repo,
             worker_result_repo,
+            worker_tagging_repo,
             worker_task_queue,
             worker_http_client,
             worker_app_config,
             worker_config.min_workers,
             worker_config.max_workers,
             worker_config.queue_poll_interval_ms,
+            pool_metrics,
+            worker_throttle,
+            worker_proxies,
+            worker_cache,
+            worker_shutdown,
         )
         .await;
     });
@@ -104,18 +211,31 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(collection_service.clone())
+            .app_data(tagging_service.clone())
+            .app_data(web::Data::new(source_throttle.clone()))
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
-                    .service(handlers::collection_routes()),
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
+                    .service(handlers::collection_routes())
+                    .service(handlers::tagging_routes()),
             )
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
     .bind(format!("0.0.0.0:{}", config.server.port))?
-    .run()
-    .await
+    .run();
+
+    shutdown.run(server, vec![worker_pool]).await
 }
@@ -121,6 +121,15 @@ pub struct CollectionTask {
     pub error_message: Option<String>,
     pub result_summary: Option<ResultSummary>,
     pub max_duration_seconds: Option<i32>,
+    /// Times a worker has picked the task up
+    #[serde(default)]
+    pub attempts: u32,
+    /// Send the task's requests through one of the configured proxies
+    #[serde(default)]
+    pub use_proxy: bool,
+    /// Ask the module even if a cached response for the same task exists
+    #[serde(default)]
+    pub force_refresh: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -144,6 +153,14 @@ pub enum TaskStatus {
     Timeout,
 }
 
+impl TaskStatus {
+    /// Whether the task has stopped for good. Cancelling a scan leaves its
+    /// finished tasks, and their results, as they are.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, TaskStatus::Pending | TaskStatus::Running)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CollectionTarget {
     pub id: Uuid,
@@ -171,6 +188,8 @@ pub struct CreateTaskRequest {
     pub scan_id: Option<Uuid>,
     pub task_type: Option<TaskType>,
     pub max_duration_seconds: Option<i32>,
+    pub use_proxy: Option<bool>,
+    pub force_refresh: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -192,6 +211,14 @@ pub struct TaskResponse {
     pub module_name: String,
 }
 
+/// Returned by `POST /collection/scans/{id}/cancel`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanCancellation {
+    pub scan_id: Uuid,
+    /// Pending and running tasks that were cancelled
+    pub cancelled_tasks: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskStatusRequest {
     pub task_ids: Vec<Uuid>,
@@ -205,6 +232,8 @@ pub struct BatchTaskRequest {
     pub priority: Option<i32>,
     pub scan_id: Option<Uuid>,
     pub max_duration_seconds: Option<i32>,
+    pub use_proxy: Option<bool>,
+    pub force_refresh: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -222,6 +251,21 @@ pub struct TaskResult {
     pub created_at: DateTime<Utc>,
 }
 
+/// Filters for `GET /collection/results`. A result matches when one of its
+/// entities has `data_type` with at least `min_confidence`, and it was
+/// collected between `from` and `to`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResultQuery {
+    pub task_id: Option<Uuid>,
+    /// Entity type, e.g. `domain`
+    pub data_type: Option<String>,
+    pub min_confidence: Option<u8>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub id: Option<Uuid>,
@@ -231,6 +275,8 @@ pub struct Entity {
     pub metadata: HashMap<String, String>,
     pub confidence: u8,
     pub source: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,411 @@
+//! Outbound proxies for collection tasks
+//!
+//! Tasks created with `use_proxy` each take the next proxy of the pool, so
+//! their traffic is spread over all of them. A proxy that fails
+//! `max_failures` times in a row is left out of the rotation for
+//! `cooldown_seconds`; after that it gets one more chance before it is
+//! benched again. Proxy passwords are kept encrypted in the config and only
+//! decrypted into the clients built here.
+
+use crate::config::{ProxyConfig, ProxyEntry};
+use mirage_common::crypto::SecretCipher;
+use mirage_common::http::HttpClientOptions;
+use mirage_common::{Error, Result};
+use reqwest::Client;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+#[derive(Clone, Default)]
+pub struct ProxyPool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Default)]
+struct PoolInner {
+    proxies: Vec<Proxy>,
+    next: AtomicUsize,
+    max_failures: u32,
+    cooldown: Duration,
+}
+
+struct Proxy {
+    /// With credentials, for handing to modules
+    url: String,
+    /// Without credentials, for logs
+    display: String,
+    client: Arc<Client>,
+    health: Mutex<Health>,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    benched_until: Option<Instant>,
+}
+
+impl ProxyPool {
+    /// One client per configured proxy, each with the service's usual HTTP
+    /// options
+    pub fn from_config(config: &ProxyConfig, http: &HttpClientOptions) -> Result<Self> {
+        let cipher = config
+            .encryption_key
+            .as_deref()
+            .map(SecretCipher::new)
+            .transpose()
+            .map_err(|e| Error::Config(format!("Invalid proxy encryption key: {}", e)))?;
+
+        let proxies = config
+            .proxies
+            .iter()
+            .map(|entry| Proxy::new(entry, cipher.as_ref(), http))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            inner: Arc::new(PoolInner {
+                proxies,
+                next: AtomicUsize::new(0),
+                max_failures: config.max_failures.max(1),
+                cooldown: Duration::from_secs(config.cooldown_seconds),
+            }),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.proxies.is_empty()
+    }
+
+    /// The next proxy in rotation that isn't benched, if there is one
+    pub fn lease(&self) -> Option<ProxyLease> {
+        let count = self.inner.proxies.len();
+        if count == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&index| self.inner.proxies[index].is_available(now))
+            .map(|index| ProxyLease {
+                pool: self.clone(),
+                index,
+            })
+    }
+}
+
+impl Proxy {
+    fn new(
+        entry: &ProxyEntry,
+        cipher: Option<&SecretCipher>,
+        http: &HttpClientOptions,
+    ) -> Result<Self> {
+        let mut url = Url::parse(&entry.url)
+            .map_err(|e| Error::Config(format!("Invalid proxy URL '{}': {}", entry.url, e)))?;
+        if !SCHEMES.contains(&url.scheme()) || url.host_str().is_none() {
+            return Err(Error::Config(format!(
+                "Proxy '{}' must be an HTTP(S) or SOCKS5 URL with a host",
+                entry.url
+            )));
+        }
+        let display = url.to_string();
+
+        if let Some(username) = &entry.username {
+            let password = match &entry.encrypted_password {
+                Some(sealed) => {
+                    let cipher = cipher.ok_or_else(|| {
+                        Error::Config(format!(
+                            "Proxy '{}' has a password but no proxy encryption key is set",
+                            display
+                        ))
+                    })?;
+                    Some(cipher.decrypt(sealed).map_err(|e| {
+                        Error::Config(format!(
+                            "Cannot decrypt password of proxy '{}': {}",
+                            display, e
+                        ))
+                    })?)
+                }
+                None => None,
+            };
+
+            let credentials = url
+                .set_username(username)
+                .and_then(|_| url.set_password(password.as_deref()));
+            if credentials.is_err() {
+                return Err(Error::Config(format!(
+                    "Proxy '{}' cannot carry credentials",
+                    display
+                )));
+            }
+        }
+
+        // Credentials in the URL are sent as Proxy-Authorization for HTTP
+        // proxies and used in the handshake for SOCKS5 ones
+        let proxy = reqwest::Proxy::all(url.as_str())
+            .map_err(|e| Error::Config(format!("Invalid proxy '{}': {}", display, e)))?;
+        let client = http
+            .client_builder()?
+            .proxy(proxy)
+            .build()
+            .map_err(|e| Error::Config(format!("Failed to build HTTP client: {}", e)))?;
+
+        Ok(Self {
+            url: url.to_string(),
+            display,
+            client: Arc::new(client),
+            health: Mutex::new(Health::default()),
+        })
+    }
+
+    fn is_available(&self, now: Instant) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .benched_until
+            .is_none_or(|until| until <= now)
+    }
+}
+
+/// A proxy handed to one task. The task reports back whether the proxy
+/// worked so the pool can bench it.
+pub struct ProxyLease {
+    pool: ProxyPool,
+    index: usize,
+}
+
+impl ProxyLease {
+    fn proxy(&self) -> &Proxy {
+        &self.pool.inner.proxies[self.index]
+    }
+
+    /// Client that sends every request through the proxy
+    pub fn client(&self) -> Arc<Client> {
+        self.proxy().client.clone()
+    }
+
+    /// Proxy URL including credentials. Don't log it.
+    pub fn url(&self) -> &str {
+        &self.proxy().url
+    }
+
+    pub fn succeeded(&self) {
+        let mut health = self.proxy().health.lock().unwrap();
+        health.consecutive_failures = 0;
+        health.benched_until = None;
+    }
+
+    pub fn failed(&self) {
+        let proxy = self.proxy();
+        let mut health = proxy.health.lock().unwrap();
+        health.consecutive_failures += 1;
+
+        if health.consecutive_failures >= self.pool.inner.max_failures {
+            health.benched_until = Some(Instant::now() + self.pool.inner.cooldown);
+            tracing::warn!(
+                "Proxy {} failed {} times in a row, leaving it out for {}s",
+                proxy.display,
+                health.consecutive_failures,
+                self.pool.inner.cooldown.as_secs()
+            );
+        }
+    }
+}
+
+impl fmt::Display for ProxyLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.proxy().display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use base64::{engine::general_purpose, Engine as _};
+
+    const KEY: &str = "YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=";
+
+    /// Request line and `Proxy-Authorization` of each request seen
+    type Seen = web::Data<Mutex<Vec<(String, Option<String>)>>>;
+
+    async fn forward(seen: Seen, req: HttpRequest) -> HttpResponse {
+        let auth = req
+            .headers()
+            .get("proxy-authorization")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        seen.lock().unwrap().push((req.uri().to_string(), auth));
+        HttpResponse::Ok().body("via proxy")
+    }
+
+    /// Forward proxy stand-in that answers every request itself
+    fn mock_proxy(seen: Seen) -> (String, ServerHandle) {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(seen.clone())
+                .default_service(web::to(forward))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        (url, handle)
+    }
+
+    fn entry(url: &str) -> ProxyEntry {
+        ProxyEntry {
+            url: url.to_string(),
+            username: None,
+            encrypted_password: None,
+        }
+    }
+
+    fn pool(proxies: Vec<ProxyEntry>, max_failures: u32, cooldown_seconds: u64) -> ProxyPool {
+        let config = ProxyConfig {
+            encryption_key: Some(KEY.to_string()),
+            proxies,
+            max_failures,
+            cooldown_seconds,
+        };
+        ProxyPool::from_config(&config, &HttpClientOptions::default()).unwrap()
+    }
+
+    async fn fetch(lease: &ProxyLease) -> Option<String> {
+        let response = lease
+            .client()
+            .get("http://collect.example/whois?q=example.com")
+            .send()
+            .await;
+        match response {
+            Ok(response) => {
+                lease.succeeded();
+                Some(response.text().await.unwrap())
+            }
+            Err(_) => {
+                lease.failed();
+                None
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_requests_go_through_the_proxy_with_its_credentials() {
+        let seen = web::Data::new(Mutex::new(Vec::new()));
+        let (url, server) = mock_proxy(seen.clone());
+        let sealed = SecretCipher::new(KEY).unwrap().encrypt("s3cret").unwrap();
+        let pool = pool(
+            vec![ProxyEntry {
+                url: url.clone(),
+                username: Some("collector".to_string()),
+                encrypted_password: Some(sealed),
+            }],
+            3,
+            300,
+        );
+
+        let lease = pool.lease().unwrap();
+        assert_eq!(lease.to_string(), format!("{}/", url));
+        assert!(lease.url().contains("collector:s3cret@"));
+        assert_eq!(fetch(&lease).await.as_deref(), Some("via proxy"));
+
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].0, "http://collect.example/whois?q=example.com");
+        let expected = format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("collector:s3cret")
+        );
+        assert_eq!(seen[0].1.as_deref(), Some(expected.as_str()));
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_dead_proxy_is_skipped() {
+        let seen = web::Data::new(Mutex::new(Vec::new()));
+        let (url, server) = mock_proxy(seen.clone());
+        // Nothing listens on port 1
+        let pool = pool(vec![entry("http://127.0.0.1:1"), entry(&url)], 1, 300);
+
+        let dead = pool.lease().unwrap();
+        assert_eq!(dead.to_string(), "http://127.0.0.1:1/");
+        assert_eq!(fetch(&dead).await, None);
+
+        for _ in 0..3 {
+            let lease = pool.lease().unwrap();
+            assert_eq!(lease.to_string(), format!("{}/", url));
+            assert_eq!(fetch(&lease).await.as_deref(), Some("via proxy"));
+        }
+        assert_eq!(seen.lock().unwrap().len(), 3);
+
+        // With every proxy benched there is nothing to hand out
+        let only_dead = self::pool(vec![entry("socks5://127.0.0.1:1")], 2, 300);
+        for _ in 0..2 {
+            assert_eq!(fetch(&only_dead.lease().unwrap()).await, None);
+        }
+        assert!(only_dead.lease().is_none());
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_benched_proxy_returns_after_cooldown() {
+        let pool = pool(vec![entry("http://127.0.0.1:1")], 1, 0);
+        let lease = pool.lease().unwrap();
+        lease.failed();
+        // A zero cooldown ends at once
+        assert!(pool.lease().is_some());
+
+        let pool = self::pool(vec![entry("http://127.0.0.1:1")], 2, 300);
+        let lease = pool.lease().unwrap();
+        lease.failed();
+        lease.succeeded();
+        lease.failed();
+        // Only failures in a row count
+        assert!(pool.lease().is_some());
+    }
+
+    #[test]
+    fn test_invalid_proxy_config_is_rejected() {
+        let build = |proxies: Vec<ProxyEntry>, encryption_key: Option<&str>| {
+            let config = ProxyConfig {
+                encryption_key: encryption_key.map(String::from),
+                proxies,
+                ..ProxyConfig::default()
+            };
+            ProxyPool::from_config(&config, &HttpClientOptions::default())
+        };
+        let with_password = |password: &str| ProxyEntry {
+            username: Some("collector".to_string()),
+            encrypted_password: Some(password.to_string()),
+            ..entry("http://proxy.example:3128")
+        };
+
+        assert!(build(Vec::new(), None).unwrap().is_empty());
+        assert!(matches!(
+            build(vec![entry("ftp://proxy.example")], None),
+            Err(Error::Config(_))
+        ));
+        // Passwords can't be used without the key, or when not sealed with it
+        let sealed = SecretCipher::new(KEY).unwrap().encrypt("s3cret").unwrap();
+        assert!(matches!(
+            build(vec![with_password(&sealed)], None),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            build(vec![with_password("s3cret")], Some(KEY)),
+            Err(Error::Config(_))
+        ));
+        assert!(build(vec![with_password(&sealed)], Some(KEY)).is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+use super::{QueuedTask, TaskQueue};
+use async_trait::async_trait;
+use chrono::Utc;
+use mirage_common::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Queue held in process memory, for local runs and tests. Tasks are lost
+/// when the service stops.
+#[derive(Default)]
+pub struct MemoryTaskQueue {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // Keyed by priority, then by insertion order among equal priorities
+    ready: BTreeMap<(i32, u64), QueuedTask>,
+    in_flight: HashMap<Uuid, (QueuedTask, Instant)>,
+    dead_letter: Vec<QueuedTask>,
+    next_seq: u64,
+}
+
+impl State {
+    fn push(&mut self, task: QueuedTask) {
+        self.ready.insert((task.priority, self.next_seq), task);
+        self.next_seq += 1;
+    }
+
+    // Hands tasks whose visibility timeout ran out back to the queue
+    fn reclaim_expired(&mut self, now: Instant) {
+        let expired: Vec<Uuid> = self
+            .in_flight
+            .iter()
+            .filter(|(_, (_, deadline))| *deadline <= now)
+            .map(|(task_id, _)| *task_id)
+            .collect();
+
+        for task_id in expired {
+            if let Some((task, _)) = self.in_flight.remove(&task_id) {
+                self.push(task);
+            }
+        }
+    }
+}
+
+impl MemoryTaskQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TaskQueue for MemoryTaskQueue {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    async fn enqueue_task(&self, task_id: Uuid, priority: i32) -> Result<()> {
+        self.state.lock().unwrap().push(QueuedTask {
+            task_id,
+            priority,
+            enqueued_at: Utc::now(),
+            attempts: 0,
+        });
+        Ok(())
+    }
+
+    async fn dequeue_task(&self, visibility_timeout: Duration) -> Result<Option<QueuedTask>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reclaim_expired(now);
+
+        let Some((_, mut task)) = state.ready.pop_first() else {
+            return Ok(None);
+        };
+        task.attempts += 1;
+        state
+            .in_flight
+            .insert(task.task_id, (task.clone(), now + visibility_timeout));
+        Ok(Some(task))
+    }
+
+    async fn ack_task(&self, task_id: Uuid) -> Result<()> {
+        self.state.lock().unwrap().in_flight.remove(&task_id);
+        Ok(())
+    }
+
+    async fn nack_task(&self, task_id: Uuid) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((mut task, _)) = state.in_flight.remove(&task_id) {
+            task.attempts = task.attempts.saturating_sub(1);
+            state.push(task);
+        }
+        Ok(())
+    }
+
+    async fn extend_visibility(&self, task_id: Uuid, visibility_timeout: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reclaim_expired(now);
+
+        match state.in_flight.get_mut(&task_id) {
+            Some((_, deadline)) => {
+                *deadline = now + visibility_timeout;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn dead_letter_task(&self, task_id: Uuid) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((task, _)) = state.in_flight.remove(&task_id) {
+            state.dead_letter.push(task);
+        }
+        Ok(())
+    }
+
+    async fn remove_task(&self, task_id: Uuid) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.ready.len();
+        state.ready.retain(|_, task| task.task_id != task_id);
+        Ok(state.ready.len() < before)
+    }
+
+    async fn queue_size(&self) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        state.reclaim_expired(Instant::now());
+        Ok(state.ready.len() as u64)
+    }
+
+    async fn dead_letter_size(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().dead_letter.len() as u64)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VISIBILITY: Duration = Duration::from_secs(60);
+
+    async fn dequeue_id(queue: &MemoryTaskQueue, visibility: Duration) -> Option<Uuid> {
+        queue
+            .dequeue_task(visibility)
+            .await
+            .unwrap()
+            .map(|task| task.task_id)
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_takes_lowest_priority_number_first() {
+        let queue = MemoryTaskQueue::new();
+        let (low, first, second) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        queue.enqueue_task(first, 1).await.unwrap();
+        queue.enqueue_task(low, 9).await.unwrap();
+        queue.enqueue_task(second, 1).await.unwrap();
+        assert_eq!(queue.queue_size().await.unwrap(), 3);
+
+        assert_eq!(dequeue_id(&queue, VISIBILITY).await, Some(first));
+        assert_eq!(dequeue_id(&queue, VISIBILITY).await, Some(second));
+        assert_eq!(dequeue_id(&queue, VISIBILITY).await, Some(low));
+        assert_eq!(dequeue_id(&queue, VISIBILITY).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_acked_task_is_not_redelivered() {
+        let queue = MemoryTaskQueue::new();
+        let task_id = Uuid::new_v4();
+        queue.enqueue_task(task_id, 5).await.unwrap();
+
+        let visibility = Duration::from_millis(20);
+        assert_eq!(dequeue_id(&queue, visibility).await, Some(task_id));
+        assert_eq!(queue.queue_size().await.unwrap(), 0);
+        queue.ack_task(task_id).await.unwrap();
+
+        tokio::time::sleep(visibility * 2).await;
+        assert_eq!(dequeue_id(&queue, visibility).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_unacked_task_is_redelivered_after_visibility_timeout() {
+        let queue = MemoryTaskQueue::new();
+        let task_id = Uuid::new_v4();
+        queue.enqueue_task(task_id, 5).await.unwrap();
+
+        let visibility = Duration::from_millis(20);
+        assert_eq!(dequeue_id(&queue, visibility).await, Some(task_id));
+
+        // Hidden from other consumers until the timeout runs out
+        assert_eq!(dequeue_id(&queue, visibility).await, None);
+
+        tokio::time::sleep(visibility * 2).await;
+        let redelivered = queue.dequeue_task(visibility).await.unwrap().unwrap();
+        assert_eq!((redelivered.task_id, redelivered.attempts), (task_id, 2));
+    }
+
+    #[tokio::test]
+    async fn test_extended_task_stays_hidden_past_its_first_deadline() {
+        let queue = MemoryTaskQueue::new();
+        let task_id = Uuid::new_v4();
+        queue.enqueue_task(task_id, 5).await.unwrap();
+
+        let visibility = Duration::from_millis(100);
+        assert_eq!(dequeue_id(&queue, visibility).await, Some(task_id));
+
+        tokio::time::sleep(visibility / 2).await;
+        assert!(queue.extend_visibility(task_id, visibility).await.unwrap());
+        tokio::time::sleep(visibility / 2 + visibility / 4).await;
+        assert_eq!(dequeue_id(&queue, visibility).await, None);
+
+        tokio::time::sleep(visibility).await;
+        assert_eq!(dequeue_id(&queue, visibility).await, Some(task_id));
+
+        // Nothing is left to extend once the task is acked
+        queue.ack_task(task_id).await.unwrap();
+        assert!(!queue.extend_visibility(task_id, visibility).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_nacked_task_is_redelivered_at_once() {
+        let queue = MemoryTaskQueue::new();
+        let task_id = Uuid::new_v4();
+        queue.enqueue_task(task_id, 5).await.unwrap();
+
+        assert_eq!(dequeue_id(&queue, VISIBILITY).await, Some(task_id));
+        queue.nack_task(task_id).await.unwrap();
+
+        // A nack isn't counted as an attempt
+        let redelivered = queue.dequeue_task(VISIBILITY).await.unwrap().unwrap();
+        assert_eq!((redelivered.task_id, redelivered.attempts), (task_id, 1));
+
+        // Only waiting tasks can be removed
+        assert!(!queue.remove_task(task_id).await.unwrap());
+        queue.nack_task(task_id).await.unwrap();
+        assert!(queue.remove_task(task_id).await.unwrap());
+        assert_eq!(queue.queue_size().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,155 @@
+//! Task queue backends
+//!
+//! The worker pool takes tasks from a `TaskQueue`. A dequeued task stays
+//! hidden from other consumers until it is acked or nacked, or until its
+//! visibility timeout runs out and it is handed out again, so a task whose
+//! worker died is not lost: delivery is at least once. Workers extend the
+//! timeout while a task runs. A task delivered `max_attempts` times without
+//! an ack is moved to the dead-letter set by `next_delivery` instead of being
+//! retried forever.
+//!
+//! `queue.backend` in the config picks Redis, the default, or the in-memory
+//! queue for local runs and tests.
+
+mod memory;
+mod redis;
+
+pub use self::memory::MemoryTaskQueue;
+pub use self::redis::RedisTaskQueue;
+
+use crate::config::{AppConfig, QueueBackend};
+use crate::repositories::create_redis_client;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mirage_common::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTask {
+    pub task_id: Uuid,
+    pub priority: i32,
+    pub enqueued_at: DateTime<Utc>,
+    /// Times the task has been handed out, counting the current delivery
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A task taken off the queue by `next_delivery`
+#[derive(Debug, Clone)]
+pub enum Delivery {
+    /// Ready to run
+    Task(QueuedTask),
+    /// Out of attempts and moved to the dead-letter set
+    DeadLettered(QueuedTask),
+}
+
+#[async_trait]
+pub trait TaskQueue: Send + Sync {
+    /// Backend name, used for the readiness probe
+    fn name(&self) -> &str;
+
+    /// Adds a task; lower `priority` numbers are dequeued first
+    async fn enqueue_task(&self, task_id: Uuid, priority: i32) -> Result<()>;
+
+    /// Takes the next task and hides it from other consumers for
+    /// `visibility_timeout`
+    async fn dequeue_task(&self, visibility_timeout: Duration) -> Result<Option<QueuedTask>>;
+
+    /// Marks a dequeued task as done so it isn't handed out again
+    async fn ack_task(&self, task_id: Uuid) -> Result<()>;
+
+    /// Puts a dequeued task back on the queue straight away, without
+    /// counting the delivery as an attempt
+    async fn nack_task(&self, task_id: Uuid) -> Result<()>;
+
+    /// Pushes a dequeued task's deadline to `visibility_timeout` from now.
+    /// Returns false if the task is no longer in flight.
+    async fn extend_visibility(&self, task_id: Uuid, visibility_timeout: Duration) -> Result<bool>;
+
+    /// Moves a dequeued task to the dead-letter set, where it stays for
+    /// inspection and is never handed out again
+    async fn dead_letter_task(&self, task_id: Uuid) -> Result<()>;
+
+    /// Removes a task still waiting in the queue
+    async fn remove_task(&self, task_id: Uuid) -> Result<bool>;
+
+    /// Tasks waiting in the queue, not counting dequeued ones
+    async fn queue_size(&self) -> Result<u64>;
+
+    /// Tasks in the dead-letter set
+    async fn dead_letter_size(&self) -> Result<u64>;
+
+    /// Readiness probe: checks the backend answers
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Builds the queue backend selected in `config`
+pub fn from_config(config: &AppConfig) -> Result<Arc<dyn TaskQueue>> {
+    match config.queue.backend {
+        QueueBackend::Redis => {
+            let client = create_redis_client(&config.redis)?;
+            Ok(Arc::new(RedisTaskQueue::new(
+                client,
+                config.redis.queue_prefix.clone(),
+            )))
+        }
+        QueueBackend::Memory => Ok(Arc::new(MemoryTaskQueue::new())),
+    }
+}
+
+/// Dequeues the next task, dead-lettering it instead when it has already
+/// been delivered `max_attempts` times without being acked
+pub async fn next_delivery(
+    queue: &dyn TaskQueue,
+    visibility_timeout: Duration,
+    max_attempts: u32,
+) -> Result<Option<Delivery>> {
+    let Some(task) = queue.dequeue_task(visibility_timeout).await? else {
+        return Ok(None);
+    };
+
+    if task.attempts > max_attempts {
+        queue.dead_letter_task(task.task_id).await?;
+        return Ok(Some(Delivery::DeadLettered(task)));
+    }
+
+    Ok(Some(Delivery::Task(task)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unacked_task_is_dead_lettered_after_max_attempts() {
+        let queue = MemoryTaskQueue::new();
+        let task_id = Uuid::new_v4();
+        queue.enqueue_task(task_id, 5).await.unwrap();
+
+        let visibility = Duration::from_millis(20);
+        for attempt in 1..=2 {
+            match next_delivery(&queue, visibility, 2).await.unwrap() {
+                Some(Delivery::Task(task)) => assert_eq!(task.attempts, attempt),
+                other => panic!("expected a delivery, got {:?}", other),
+            }
+            // The worker dies without acking
+            tokio::time::sleep(visibility * 2).await;
+        }
+
+        match next_delivery(&queue, visibility, 2).await.unwrap() {
+            Some(Delivery::DeadLettered(task)) => assert_eq!(task.task_id, task_id),
+            other => panic!("expected a dead letter, got {:?}", other),
+        }
+        assert_eq!(queue.dead_letter_size().await.unwrap(), 1);
+
+        tokio::time::sleep(visibility * 2).await;
+        assert!(next_delivery(&queue, visibility, 2)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(queue.queue_size().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,309 @@
+use super::{QueuedTask, TaskQueue};
+use async_trait::async_trait;
+use chrono::Utc;
+use mirage_common::{Error, Result};
+use redis::aio::Connection;
+use redis::{AsyncCommands, Client, Script};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Moves in-flight tasks whose visibility timeout ran out back to the queue,
+// then pops the next task, counts the attempt and keeps it in flight until
+// ARGV[2]. Running it as one script stops two workers from taking the same
+// task. KEYS[3] maps each in-flight task's ID to its member in KEYS[2].
+const DEQUEUE_SCRIPT: &str = r#"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1])
+for _, member in ipairs(expired) do
+    local task = cjson.decode(member)
+    redis.call('ZREM', KEYS[2], member)
+    redis.call('HDEL', KEYS[3], task.task_id)
+    redis.call('ZADD', KEYS[1], task.priority, member)
+end
+local popped = redis.call('ZPOPMIN', KEYS[1])
+if #popped == 0 then
+    return false
+end
+local task = cjson.decode(popped[1])
+task.attempts = (task.attempts or 0) + 1
+local member = cjson.encode(task)
+redis.call('ZADD', KEYS[2], ARGV[2], member)
+redis.call('HSET', KEYS[3], task.task_id, member)
+return member
+"#;
+
+// Takes task ARGV[1] out of the in-flight set KEYS[1] and its index KEYS[2],
+// returning its member, or false if it is no longer in flight
+const TAKE_IN_FLIGHT_SCRIPT: &str = r#"
+local member = redis.call('HGET', KEYS[2], ARGV[1])
+if not member then
+    return false
+end
+redis.call('HDEL', KEYS[2], ARGV[1])
+if redis.call('ZREM', KEYS[1], member) == 0 then
+    return false
+end
+return member
+"#;
+
+/// Queue kept in Redis sorted sets: `<prefix>:tasks` scored by priority,
+/// `<prefix>:in_flight` scored by visibility deadline and
+/// `<prefix>:dead_letter` scored by the time the task was given up on. The
+/// hash `<prefix>:in_flight_index` holds each in-flight task's member by task
+/// ID, so acks and extensions don't scan the in-flight set.
+#[derive(Clone)]
+pub struct RedisTaskQueue {
+    client: Client,
+    queue_prefix: String,
+}
+
+impl RedisTaskQueue {
+    pub fn new(client: Client, queue_prefix: String) -> Self {
+        Self {
+            client,
+            queue_prefix,
+        }
+    }
+
+    fn queue_key(&self) -> String {
+        format!("{}:tasks", self.queue_prefix)
+    }
+
+    fn in_flight_key(&self) -> String {
+        format!("{}:in_flight", self.queue_prefix)
+    }
+
+    fn in_flight_index_key(&self) -> String {
+        format!("{}:in_flight_index", self.queue_prefix)
+    }
+
+    fn dead_letter_key(&self) -> String {
+        format!("{}:dead_letter", self.queue_prefix)
+    }
+
+    async fn connection(&self) -> Result<Connection> {
+        self.client
+            .get_async_connection()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to get Redis connection: {}", e)))
+    }
+
+    // Finds a dequeued task's entry in the in-flight set
+    async fn find_in_flight(
+        &self,
+        conn: &mut Connection,
+        task_id: Uuid,
+    ) -> Result<Option<(String, QueuedTask)>> {
+        let member: Option<String> = conn
+            .hget(self.in_flight_index_key(), task_id.to_string())
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read in-flight task: {}", e)))?;
+
+        member.map(|task_json| parse_member(task_json)).transpose()
+    }
+
+    // Takes a dequeued task out of the in-flight set, returning its entry
+    async fn take_in_flight(
+        &self,
+        conn: &mut Connection,
+        task_id: Uuid,
+    ) -> Result<Option<(String, QueuedTask)>> {
+        // Another worker may have redelivered it in the meantime
+        let member: Option<String> = Script::new(TAKE_IN_FLIGHT_SCRIPT)
+            .key(self.in_flight_key())
+            .key(self.in_flight_index_key())
+            .arg(task_id.to_string())
+            .invoke_async(conn)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to remove in-flight task: {}", e)))?;
+
+        member.map(|task_json| parse_member(task_json)).transpose()
+    }
+}
+
+fn parse_member(task_json: String) -> Result<(String, QueuedTask)> {
+    let task_data = serde_json::from_str::<QueuedTask>(&task_json)
+        .map_err(|e| Error::Internal(format!("Failed to deserialize task data: {}", e)))?;
+    Ok((task_json, task_data))
+}
+
+#[async_trait]
+impl TaskQueue for RedisTaskQueue {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    // Add a task to the queue with priority
+    async fn enqueue_task(&self, task_id: Uuid, priority: i32) -> Result<()> {
+        let mut conn = self.connection().await?;
+
+        // Create task data
+        let task_data = QueuedTask {
+            task_id,
+            priority,
+            enqueued_at: Utc::now(),
+            attempts: 0,
+        };
+
+        // Serialize task data
+        let task_json = serde_json::to_string(&task_data)
+            .map_err(|e| Error::Internal(format!("Failed to serialize task data: {}", e)))?;
+
+        // Add to sorted set with priority as score (lower priority numbers = higher priority)
+        conn.zadd::<_, _, _, i64>(self.queue_key(), task_json, priority)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to add task to queue: {}", e)))?;
+
+        Ok(())
+    }
+
+    // Get the next task from the queue
+    async fn dequeue_task(&self, visibility_timeout: Duration) -> Result<Option<QueuedTask>> {
+        let mut conn = self.connection().await?;
+
+        let now = Utc::now().timestamp_millis();
+        let deadline = now + visibility_timeout.as_millis() as i64;
+
+        let result: Option<String> = Script::new(DEQUEUE_SCRIPT)
+            .key(self.queue_key())
+            .key(self.in_flight_key())
+            .key(self.in_flight_index_key())
+            .arg(now)
+            .arg(deadline)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to pop task from queue: {}", e)))?;
+
+        if let Some(task_json) = result {
+            // Deserialize task data
+            let task_data = serde_json::from_str::<QueuedTask>(&task_json)
+                .map_err(|e| Error::Internal(format!("Failed to deserialize task data: {}", e)))?;
+
+            Ok(Some(task_data))
+        } else {
+            // Queue is empty
+            Ok(None)
+        }
+    }
+
+    async fn ack_task(&self, task_id: Uuid) -> Result<()> {
+        let mut conn = self.connection().await?;
+        self.take_in_flight(&mut conn, task_id).await?;
+        Ok(())
+    }
+
+    async fn nack_task(&self, task_id: Uuid) -> Result<()> {
+        let mut conn = self.connection().await?;
+
+        if let Some((_, mut task_data)) = self.take_in_flight(&mut conn, task_id).await? {
+            task_data.attempts = task_data.attempts.saturating_sub(1);
+            let task_json = serde_json::to_string(&task_data)
+                .map_err(|e| Error::Internal(format!("Failed to serialize task data: {}", e)))?;
+
+            conn.zadd::<_, _, _, i64>(self.queue_key(), task_json, task_data.priority)
+                .await
+                .map_err(|e| Error::Database(format!("Failed to requeue task: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    async fn extend_visibility(&self, task_id: Uuid, visibility_timeout: Duration) -> Result<bool> {
+        let mut conn = self.connection().await?;
+
+        let Some((task_json, _)) = self.find_in_flight(&mut conn, task_id).await? else {
+            return Ok(false);
+        };
+
+        let deadline = Utc::now().timestamp_millis() + visibility_timeout.as_millis() as i64;
+
+        // XX only updates the deadline if the task is still in flight, so a
+        // task redelivered in the meantime isn't put back
+        let changed: u32 = redis::cmd("ZADD")
+            .arg(self.in_flight_key())
+            .arg("XX")
+            .arg("CH")
+            .arg(deadline)
+            .arg(&task_json)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to extend task visibility: {}", e)))?;
+
+        Ok(changed > 0)
+    }
+
+    async fn dead_letter_task(&self, task_id: Uuid) -> Result<()> {
+        let mut conn = self.connection().await?;
+
+        if let Some((task_json, _)) = self.take_in_flight(&mut conn, task_id).await? {
+            conn.zadd::<_, _, _, i64>(self.dead_letter_key(), task_json, Utc::now().timestamp())
+                .await
+                .map_err(|e| Error::Database(format!("Failed to dead-letter task: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    // Remove a task from the queue (if it exists)
+    async fn remove_task(&self, task_id: Uuid) -> Result<bool> {
+        let mut conn = self.connection().await?;
+
+        let queue_key = self.queue_key();
+
+        // Get all tasks from the queue
+        let tasks: Vec<String> = conn
+            .zrange(queue_key.clone(), 0, -1)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read tasks from queue: {}", e)))?;
+
+        // Find the task with matching ID
+        for task_json in tasks {
+            if let Ok(task_data) = serde_json::from_str::<QueuedTask>(&task_json) {
+                if task_data.task_id == task_id {
+                    // Remove the task
+                    let removed: u32 = conn.zrem(queue_key, task_json).await.map_err(|e| {
+                        Error::Database(format!("Failed to remove task from queue: {}", e))
+                    })?;
+
+                    return Ok(removed > 0);
+                }
+            }
+        }
+
+        // Task not found
+        Ok(false)
+    }
+
+    // Get the number of tasks in the queue
+    async fn queue_size(&self) -> Result<u64> {
+        let mut conn = self.connection().await?;
+
+        let size: u64 = conn
+            .zcard(self.queue_key())
+            .await
+            .map_err(|e| Error::Database(format!("Failed to get queue size: {}", e)))?;
+
+        Ok(size)
+    }
+
+    async fn dead_letter_size(&self) -> Result<u64> {
+        let mut conn = self.connection().await?;
+
+        let size: u64 = conn
+            .zcard(self.dead_letter_key())
+            .await
+            .map_err(|e| Error::Database(format!("Failed to get dead-letter size: {}", e)))?;
+
+        Ok(size)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        let mut conn = self.connection().await?;
+
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await
+            .map_err(|e| Error::Database(format!("Redis ping failed: {}", e)))?;
+
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
 use crate::config::{MongoDBConfig, RedisConfig};
 use crate::models::{CollectionJob, CollectionStatus};
+use crate::tagging::TaggingRule;
 use chrono::{DateTime, Duration, Utc};
 use futures::TryStreamExt;
 use mirage_common::{Error, Result};
 use mongodb::{
     bson::{doc, Document},
-    options::{ClientOptions, FindOptions, UpdateOptions},
-    Client as MongoClient, Database,
+    options::{ClientOptions, FindOneOptions, FindOptions, IndexOptions, UpdateOptions},
+    Client as MongoClient, Database, IndexModel,
 };
 use redis::{AsyncCommands, Client as RedisClient};
 use std::time::Duration as StdDuration;
@@ -32,6 +33,14 @@ pub async fn create_mongo_client(config: &MongoDBConfig) -> Result<Database> {
     Ok(client.database(&config.db_name))
 }
 
+/// Readiness probe: pings the MongoDB server
+pub async fn ping_mongo(db: Database) -> Result<()> {
+    db.run_command(doc! {"ping": 1}, None)
+        .await
+        .map_err(|e| Error::Database(format!("MongoDB ping failed: {}", e)))?;
+    Ok(())
+}
+
 pub fn create_redis_client(config: &RedisConfig) -> Result<RedisClient> {
     RedisClient::open(&config.uri)
         .map_err(|e| Error::Database(format!("Redis connection failed: {}", e)))
@@ -264,8 +273,8 @@ impl RateLimitRepository {
 }
 
 use crate::models::{
-    CollectionTarget, CollectionTask, Entity, Relationship, ResultSummary, TaskResult, TaskStatus,
-    TaskType,
+    CollectionTarget, CollectionTask, Entity, Relationship, ResultQuery, ResultSummary, TaskResult,
+    TaskStatus, TaskType,
 };
 use chrono::{DateTime, Utc};
 use futures::stream::TryStreamExt;
@@ -279,12 +288,14 @@ use uuid::Uuid;
 
 pub struct TaskRepository {
     collection: Collection<Document>,
+    cancelled_scans: Collection<Document>,
 }
 
 impl TaskRepository {
     pub fn new(db: Database) -> Self {
         Self {
             collection: db.collection("collection_tasks"),
+            cancelled_scans: db.collection("cancelled_scans"),
         }
     }
 
@@ -385,6 +396,127 @@ impl TaskRepository {
         }
     }
 
+    // Record how many times a worker has picked the task up
+    pub async fn record_attempt(&self, id: &Uuid, attempts: u32) -> Result<()> {
+        let updated_at = Self::timestamp(Utc::now())?;
+
+        self.collection
+            .update_one(
+                doc! {"id": id.to_string()},
+                doc! {"$set": {"attempts": attempts, "updated_at": updated_at}},
+                None,
+            )
+            .await
+            .map_err(|e| Error::Database(format!("Failed to record task attempt: {}", e)))?;
+
+        Ok(())
+    }
+
+    // Mark a task as running unless it has finished, e.g. because its scan
+    // was cancelled while it sat in the queue. Returns false in that case.
+    pub async fn start_task(&self, id: &Uuid) -> Result<bool> {
+        let now = Self::timestamp(Utc::now())?;
+        let mut filter = Self::unfinished_filter();
+        filter.insert("id", id.to_string());
+
+        let result = self
+            .collection
+            .update_one(
+                filter,
+                doc! {"$set": {
+                    "status": Self::status(&TaskStatus::Running)?,
+                    "started_at": now.clone(),
+                    "updated_at": now,
+                }},
+                None,
+            )
+            .await
+            .map_err(|e| Error::Database(format!("Failed to start task: {}", e)))?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    // Record a cancelled scan so no new tasks are accepted for it
+    pub async fn mark_scan_cancelled(&self, scan_id: &Uuid) -> Result<()> {
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.cancelled_scans
+            .update_one(
+                doc! {"scan_id": scan_id.to_string()},
+                doc! {"$setOnInsert": {"cancelled_at": bson_datetime(Utc::now())}},
+                options,
+            )
+            .await
+            .map_err(|e| Error::Database(format!("Failed to record scan cancellation: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn is_scan_cancelled(&self, scan_id: &Uuid) -> Result<bool> {
+        let cancelled = self
+            .cancelled_scans
+            .find_one(doc! {"scan_id": scan_id.to_string()}, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to check scan cancellation: {}", e)))?;
+
+        Ok(cancelled.is_some())
+    }
+
+    // Cancel the pending and running tasks of a scan and return their IDs.
+    // Finished tasks keep their status and results.
+    pub async fn cancel_scan_tasks(&self, scan_id: &Uuid, reason: &str) -> Result<Vec<Uuid>> {
+        let mut filter = Self::unfinished_filter();
+        filter.insert("scan_id", scan_id.to_string());
+
+        let docs: Vec<Document> = self
+            .collection
+            .find(filter.clone(), None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to fetch scan tasks: {}", e)))?
+            .try_collect()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to collect task documents: {}", e)))?;
+
+        let mut task_ids = Vec::with_capacity(docs.len());
+        for doc in &docs {
+            task_ids.push(self.document_to_task(doc)?.id);
+        }
+
+        self.collection
+            .update_many(filter, Self::cancel_update(reason, Utc::now())?, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to cancel scan tasks: {}", e)))?;
+
+        Ok(task_ids)
+    }
+
+    // Tasks that are still pending or running
+    fn unfinished_filter() -> Document {
+        doc! {"status": {"$in": ["pending", "running"]}}
+    }
+
+    fn cancel_update(reason: &str, now: DateTime<Utc>) -> Result<Document> {
+        let now = Self::timestamp(now)?;
+
+        Ok(doc! {"$set": {
+            "status": Self::status(&TaskStatus::Cancelled)?,
+            "error_message": reason,
+            "completed_at": now.clone(),
+            "updated_at": now,
+        }})
+    }
+
+    // Task fields are stored the way `task_to_document` writes them
+    fn status(status: &TaskStatus) -> Result<bson::Bson> {
+        bson::to_bson(status)
+            .map_err(|e| Error::Internal(format!("Failed to serialize task status: {}", e)))
+    }
+
+    fn timestamp(at: DateTime<Utc>) -> Result<bson::Bson> {
+        bson::to_bson(&at)
+            .map_err(|e| Error::Internal(format!("Failed to serialize timestamp: {}", e)))
+    }
+
     // Get pending tasks (for worker to process)
     pub async fn get_pending_tasks(&self, limit: u64) -> Result<Vec<CollectionTask>> {
         let filter = doc! {
@@ -513,13 +645,52 @@ impl ResultRepository {
         }
     }
 
+    /// Indexes on `task_results`. Each has a fixed name, so creating it again
+    /// on the next start is a no-op.
+    ///
+    /// - `task_id_created_at` serves `get_result_by_task_id`, which looks a
+    ///   task up by `task_id` and takes its newest result, and `find_results`
+    ///   filtered by `task_id`, with or without a time range
+    /// - `entity_type` serves `find_results` filtered by `data_type`, without
+    ///   scanning every result
+    fn indexes() -> Vec<IndexModel> {
+        vec![
+            IndexModel::builder()
+                .keys(doc! {"task_id": 1, "created_at": -1})
+                .options(
+                    IndexOptions::builder()
+                        .name("task_id_created_at".to_string())
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"entities.entity_type": 1})
+                .options(
+                    IndexOptions::builder()
+                        .name("entity_type".to_string())
+                        .build(),
+                )
+                .build(),
+        ]
+    }
+
+    /// Creates the collection's indexes that don't exist yet
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.collection
+            .create_indexes(Self::indexes(), None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to create result indexes: {}", e)))?;
+
+        Ok(())
+    }
+
     // Save a task result
     pub async fn save_result(&self, result: &TaskResult) -> Result<Uuid> {
         // Convert result to BSON document
         let bson_value = bson::to_bson(result)
             .map_err(|e| Error::Internal(format!("Failed to serialize result: {}", e)))?;
 
-        let doc = match bson_value {
+        let mut doc = match bson_value {
             bson::Bson::Document(doc) => doc,
             _ => {
                 return Err(Error::Internal(
@@ -528,6 +699,9 @@ impl ResultRepository {
             }
         };
 
+        // Stored as a BSON date rather than a string, so ranges compare times
+        doc.insert("created_at", bson_datetime(result.created_at));
+
         // Insert into collection
         self.collection
             .insert_one(doc, None)
@@ -540,21 +714,355 @@ impl ResultRepository {
     // Get result by task ID
     pub async fn get_result_by_task_id(&self, task_id: &Uuid) -> Result<Option<TaskResult>> {
         let filter = doc! {"task_id": task_id.to_string()};
+        let options = FindOneOptions::builder()
+            .sort(doc! {"created_at": -1})
+            .build();
 
         let result = self
             .collection
-            .find_one(filter, None)
+            .find_one(filter, options)
             .await
             .map_err(|e| Error::Database(format!("Failed to fetch result: {}", e)))?;
 
-        match result {
-            Some(doc) => {
-                let task_result: TaskResult = bson::from_document(doc)
-                    .map_err(|e| Error::Internal(format!("Failed to deserialize result: {}", e)))?;
+        result.map(Self::result_from_document).transpose()
+    }
 
-                Ok(Some(task_result))
-            }
-            None => Ok(None),
+    /// Results matching `query`, newest first, and the number of matches
+    pub async fn find_results(
+        &self,
+        query: &ResultQuery,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<TaskResult>, u64)> {
+        let filter = Self::result_filter(query);
+
+        let total = self
+            .collection
+            .count_documents(filter.clone(), None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to count results: {}", e)))?;
+
+        let options = FindOptions::builder()
+            .sort(doc! {"created_at": -1})
+            .skip(u64::from(offset))
+            .limit(i64::from(limit))
+            .build();
+        let mut cursor = self
+            .collection
+            .find(filter, options)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to query results: {}", e)))?;
+
+        let mut results = Vec::new();
+        while let Some(doc) = cursor
+            .try_next()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read results: {}", e)))?
+        {
+            results.push(Self::result_from_document(doc)?);
+        }
+
+        Ok((results, total))
+    }
+
+    /// Filter for `query`. The entity conditions share one `$elemMatch`, so
+    /// the type and the confidence have to hold for the same entity.
+    fn result_filter(query: &ResultQuery) -> Document {
+        let mut filter = Document::new();
+        if let Some(task_id) = query.task_id {
+            filter.insert("task_id", task_id.to_string());
+        }
+
+        let mut entity = Document::new();
+        if let Some(data_type) = &query.data_type {
+            entity.insert("entity_type", data_type.as_str());
+        }
+        if let Some(min_confidence) = query.min_confidence {
+            entity.insert("confidence", doc! {"$gte": i32::from(min_confidence)});
+        }
+        if !entity.is_empty() {
+            filter.insert("entities", doc! {"$elemMatch": entity});
+        }
+
+        let mut created_at = Document::new();
+        if let Some(from) = query.from {
+            created_at.insert("$gte", bson_datetime(from));
+        }
+        if let Some(to) = query.to {
+            created_at.insert("$lte", bson_datetime(to));
+        }
+        if !created_at.is_empty() {
+            filter.insert("created_at", created_at);
         }
+
+        filter
+    }
+
+    fn result_from_document(mut doc: Document) -> Result<TaskResult> {
+        // `TaskResult` reads its timestamp as RFC 3339; results stored before
+        // it became a BSON date already hold a string
+        if let Ok(created_at) = doc.get_datetime("created_at") {
+            let created_at = DateTime::<Utc>::from_timestamp_millis(created_at.timestamp_millis())
+                .unwrap_or_default();
+            doc.insert("created_at", created_at.to_rfc3339());
+        }
+
+        bson::from_document(doc)
+            .map_err(|e| Error::Internal(format!("Failed to deserialize result: {}", e)))
+    }
+}
+
+fn bson_datetime(at: DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(at.timestamp_millis())
+}
+
+#[derive(Clone)]
+pub struct TaggingRuleRepository {
+    collection: Collection<TaggingRule>,
+}
+
+impl TaggingRuleRepository {
+    pub fn new(db: Database) -> Self {
+        Self {
+            collection: db.collection("tagging_rules"),
+        }
+    }
+
+    pub async fn create_rule(&self, rule: &TaggingRule) -> Result<()> {
+        self.collection
+            .insert_one(rule, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to insert tagging rule: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn get_rule(&self, id: &Uuid) -> Result<Option<TaggingRule>> {
+        self.collection
+            .find_one(doc! {"id": id.to_string()}, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to fetch tagging rule: {}", e)))
+    }
+
+    // List rules ordered by priority, optionally only the enabled ones
+    pub async fn list_rules(&self, enabled_only: bool) -> Result<Vec<TaggingRule>> {
+        let filter = if enabled_only {
+            doc! {"enabled": true}
+        } else {
+            doc! {}
+        };
+
+        let options = FindOptions::builder().sort(doc! {"priority": 1}).build();
+
+        let cursor = self
+            .collection
+            .find(filter, options)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to fetch tagging rules: {}", e)))?;
+
+        cursor
+            .try_collect()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to collect tagging rules: {}", e)))
+    }
+
+    pub async fn update_rule(&self, rule: &TaggingRule) -> Result<bool> {
+        let result = self
+            .collection
+            .replace_one(doc! {"id": rule.id.to_string()}, rule, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to update tagging rule: {}", e)))?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn delete_rule(&self, id: &Uuid) -> Result<bool> {
+        let result = self
+            .collection
+            .delete_one(doc! {"id": id.to_string()}, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to delete tagging rule: {}", e)))?;
+
+        Ok(result.deleted_count > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn result(created_at: DateTime<Utc>) -> TaskResult {
+        TaskResult {
+            task_id: Uuid::new_v4(),
+            entities: vec![Entity {
+                id: None,
+                entity_type: "domain".to_string(),
+                value: "example.com".to_string(),
+                data: Default::default(),
+                metadata: Default::default(),
+                confidence: 70,
+                source: "dns".to_string(),
+                tags: Vec::new(),
+            }],
+            relationships: Vec::new(),
+            raw_data: None,
+            created_at,
+        }
+    }
+
+    fn task(status: TaskStatus) -> CollectionTask {
+        let created_at = Utc.with_ymd_and_hms(2024, 3, 10, 11, 0, 0).unwrap();
+
+        CollectionTask {
+            id: Uuid::new_v4(),
+            task_type: TaskType::SingleTarget,
+            status,
+            priority: 5,
+            created_at,
+            updated_at: created_at,
+            started_at: Some(created_at),
+            completed_at: None,
+            target: CollectionTarget {
+                id: Uuid::new_v4(),
+                target_type: "domain".to_string(),
+                value: "example.com".to_string(),
+                metadata: Default::default(),
+                entity_id: None,
+            },
+            module_id: Uuid::new_v4(),
+            module_name: "dns".to_string(),
+            module_version: "1.0.0".to_string(),
+            parameters: Default::default(),
+            scan_id: Some(Uuid::new_v4()),
+            created_by: None,
+            error_message: None,
+            result_summary: None,
+            max_duration_seconds: None,
+            attempts: 1,
+            use_proxy: false,
+            force_refresh: false,
+        }
+    }
+
+    #[test]
+    fn test_scan_cancellation_only_matches_unfinished_tasks() {
+        let filter = TaskRepository::unfinished_filter();
+        let matched = filter
+            .get_document("status")
+            .unwrap()
+            .get_array("$in")
+            .unwrap();
+
+        for status in [
+            TaskStatus::Pending,
+            TaskStatus::Running,
+            TaskStatus::Completed,
+            TaskStatus::Failed,
+            TaskStatus::Cancelled,
+            TaskStatus::Timeout,
+        ] {
+            let stored = TaskRepository::status(&status).unwrap();
+            assert_eq!(
+                matched.contains(&stored),
+                !status.is_finished(),
+                "{:?}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn test_cancel_update_flips_status_and_reads_back() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let running = task(TaskStatus::Running);
+
+        let mut doc = bson::to_document(&running).unwrap();
+        let update = TaskRepository::cancel_update("Scan cancelled", now).unwrap();
+        for (field, value) in update.get_document("$set").unwrap() {
+            doc.insert(field, value.clone());
+        }
+
+        let cancelled: CollectionTask = bson::from_document(doc).unwrap();
+        assert_eq!(cancelled.status, TaskStatus::Cancelled);
+        assert!(cancelled.status.is_finished());
+        assert_eq!(cancelled.completed_at, Some(now));
+        assert_eq!(cancelled.error_message.as_deref(), Some("Scan cancelled"));
+        assert_eq!(cancelled.started_at, running.started_at);
+    }
+
+    #[test]
+    fn test_min_confidence_applies_to_the_matching_entity() {
+        let query = ResultQuery {
+            data_type: Some("domain".to_string()),
+            min_confidence: Some(80),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ResultRepository::result_filter(&query),
+            doc! {
+                "entities": {
+                    "$elemMatch": {"entity_type": "domain", "confidence": {"$gte": 80}}
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn test_time_range_compares_bson_dates() {
+        let from = Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let task_id = Uuid::new_v4();
+        let query = ResultQuery {
+            task_id: Some(task_id),
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        };
+
+        // Dates, not strings, so the comparison is chronological
+        assert_eq!(
+            ResultRepository::result_filter(&query),
+            doc! {
+                "task_id": task_id.to_string(),
+                "created_at": {
+                    "$gte": bson::DateTime::from_millis(from.timestamp_millis()),
+                    "$lte": bson::DateTime::from_millis(to.timestamp_millis()),
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn test_stored_date_reads_back_as_timestamp() {
+        let stored = result(Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap());
+        let mut doc = bson::to_document(&stored).unwrap();
+        doc.insert("created_at", bson_datetime(stored.created_at));
+
+        let read = ResultRepository::result_from_document(doc).unwrap();
+        assert_eq!(read.created_at, stored.created_at);
+    }
+
+    #[test]
+    fn test_result_indexes_cover_task_and_entity_type_lookups() {
+        let indexes: Vec<(String, Document)> = ResultRepository::indexes()
+            .into_iter()
+            .map(|index| {
+                let name = index.options.and_then(|options| options.name);
+                (name.unwrap(), index.keys)
+            })
+            .collect();
+
+        assert_eq!(
+            indexes,
+            vec![
+                (
+                    "task_id_created_at".to_string(),
+                    doc! {"task_id": 1, "created_at": -1}
+                ),
+                ("entity_type".to_string(), doc! {"entities.entity_type": 1}),
+            ]
+        );
     }
 }
@@ -1,10 +1,11 @@
 use crate::config::AppConfig;
 use crate::models::{
     BatchTaskRequest, BatchTaskResponse, CollectionTarget, CollectionTask, CreateTaskRequest,
-    TaskResponse, TaskResult, TaskStatus, TaskType,
+    ResultQuery, ScanCancellation, TaskResponse, TaskResult, TaskStatus, TaskType,
 };
 use crate::queue::TaskQueue;
-use crate::repositories::{ResultRepository, TaskRepository};
+use crate::repositories::{ResultRepository, TaggingRuleRepository, TaskRepository};
+use crate::tagging::{CreateTaggingRuleRequest, TaggingRule, UpdateTaggingRuleRequest};
 use chrono::Utc;
 use mirage_common::{Error, Result};
 use reqwest::Client;
@@ -16,7 +17,7 @@ use uuid::Uuid;
 pub struct CollectionService {
     task_repo: Arc<TaskRepository>,
     result_repo: Arc<ResultRepository>,
-    task_queue: Arc<TaskQueue>,
+    task_queue: Arc<dyn TaskQueue>,
     http_client: Arc<Client>,
     config: Arc<AppConfig>,
 }
@@ -25,14 +26,14 @@ impl CollectionService {
     pub fn new(
         task_repo: TaskRepository,
         result_repo: ResultRepository,
-        task_queue: TaskQueue,
+        task_queue: Arc<dyn TaskQueue>,
         http_client: Client,
         config: AppConfig,
     ) -> Self {
         Self {
             task_repo: Arc::new(task_repo),
             result_repo: Arc::new(result_repo),
-            task_queue: Arc::new(task_queue),
+            task_queue,
             http_client: Arc::new(http_client),
             config: Arc::new(config),
         }
@@ -40,6 +41,10 @@ impl CollectionService {
 
     // Create a new collection task
     pub async fn create_task(&self, request: CreateTaskRequest) -> Result<TaskResponse> {
+        if let Some(scan_id) = request.scan_id {
+            self.ensure_scan_not_cancelled(&scan_id).await?;
+        }
+
         // Generate a UUID for the task
         let task_id = Uuid::new_v4();
 
@@ -79,6 +84,9 @@ impl CollectionService {
             error_message: None,
             result_summary: None,
             max_duration_seconds: request.max_duration_seconds,
+            attempts: 0,
+            use_proxy: request.use_proxy.unwrap_or(false),
+            force_refresh: request.force_refresh.unwrap_or(false),
         };
 
         // Save task to database
@@ -107,6 +115,11 @@ impl CollectionService {
             ));
         }
 
+        // Checked up front, since failed targets are otherwise skipped
+        if let Some(scan_id) = request.scan_id {
+            self.ensure_scan_not_cancelled(&scan_id).await?;
+        }
+
         let mut responses = Vec::with_capacity(request.targets.len());
 
         for target in request.targets {
@@ -118,6 +131,8 @@ impl CollectionService {
                 scan_id: request.scan_id,
                 task_type: Some(TaskType::BatchTarget),
                 max_duration_seconds: request.max_duration_seconds,
+                use_proxy: request.use_proxy,
+                force_refresh: request.force_refresh,
             };
 
             match self.create_task(task_request).await {
@@ -161,6 +176,27 @@ impl CollectionService {
         self.result_repo.get_result_by_task_id(&task_id).await
     }
 
+    // Search stored results
+    pub async fn list_results(
+        &self,
+        query: &ResultQuery,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<TaskResult>, u64)> {
+        if let (Some(from), Some(to)) = (query.from, query.to) {
+            if from > to {
+                return Err(Error::Validation("from must not be after to".into()));
+            }
+        }
+        if matches!(query.min_confidence, Some(confidence) if confidence > 100) {
+            return Err(Error::Validation(
+                "min_confidence must be between 0 and 100".into(),
+            ));
+        }
+
+        self.result_repo.find_results(query, limit, offset).await
+    }
+
     // Cancel a task
     pub async fn cancel_task(&self, task_id: Uuid) -> Result<TaskResponse> {
         // Check if task exists and is in a state that can be cancelled
@@ -202,6 +238,32 @@ impl CollectionService {
         self.get_task(task_id).await
     }
 
+    // Cancel a scan: its pending and running tasks are cancelled and new tasks
+    // for it are refused. Running tasks stop at their next heartbeat. Results
+    // of tasks that already finished are kept.
+    pub async fn cancel_scan(&self, scan_id: Uuid) -> Result<ScanCancellation> {
+        self.task_repo.mark_scan_cancelled(&scan_id).await?;
+
+        let task_ids = self
+            .task_repo
+            .cancel_scan_tasks(&scan_id, "Scan cancelled")
+            .await?;
+
+        for task_id in &task_ids {
+            // Running tasks aren't in the queue; their workers stop them
+            if let Err(e) = self.task_queue.remove_task(*task_id).await {
+                tracing::warn!("Failed to remove task {} from queue: {}", task_id, e);
+            }
+        }
+
+        tracing::info!("Cancelled {} tasks of scan {}", task_ids.len(), scan_id);
+
+        Ok(ScanCancellation {
+            scan_id,
+            cancelled_tasks: task_ids.len() as u64,
+        })
+    }
+
     // List tasks with filtering
     pub async fn list_tasks(
         &self,
@@ -244,6 +306,17 @@ impl CollectionService {
 
     // Helper methods
 
+    async fn ensure_scan_not_cancelled(&self, scan_id: &Uuid) -> Result<()> {
+        if self.task_repo.is_scan_cancelled(scan_id).await? {
+            return Err(Error::Conflict(format!(
+                "Scan {} has been cancelled",
+                scan_id
+            )));
+        }
+
+        Ok(())
+    }
+
     // Get module information from Module Registry
     async fn get_module_info(&self, module_id: &Uuid) -> Result<ModuleInfo> {
         let url = format!(
@@ -301,3 +374,58 @@ struct ModuleInfo {
     name: String,
     version: String,
 }
+
+#[derive(Clone)]
+pub struct TaggingService {
+    rule_repo: Arc<TaggingRuleRepository>,
+}
+
+impl TaggingService {
+    pub fn new(rule_repo: TaggingRuleRepository) -> Self {
+        Self {
+            rule_repo: Arc::new(rule_repo),
+        }
+    }
+
+    pub async fn create_rule(&self, request: CreateTaggingRuleRequest) -> Result<TaggingRule> {
+        let rule = TaggingRule::from_request(request)?;
+        self.rule_repo.create_rule(&rule).await?;
+
+        Ok(rule)
+    }
+
+    pub async fn get_rule(&self, id: Uuid) -> Result<TaggingRule> {
+        self.rule_repo
+            .get_rule(&id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Tagging rule {} not found", id)))
+    }
+
+    // Rules are returned in evaluation order
+    pub async fn list_rules(&self) -> Result<Vec<TaggingRule>> {
+        self.rule_repo.list_rules(false).await
+    }
+
+    pub async fn update_rule(
+        &self,
+        id: Uuid,
+        request: UpdateTaggingRuleRequest,
+    ) -> Result<TaggingRule> {
+        let mut rule = self.get_rule(id).await?;
+        rule.apply_update(request)?;
+
+        if !self.rule_repo.update_rule(&rule).await? {
+            return Err(Error::NotFound(format!("Tagging rule {} not found", id)));
+        }
+
+        Ok(rule)
+    }
+
+    pub async fn delete_rule(&self, id: Uuid) -> Result<()> {
+        if !self.rule_repo.delete_rule(&id).await? {
+            return Err(Error::NotFound(format!("Tagging rule {} not found", id)));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! Rule-based automatic tagging of collected artifacts
+//!
+//! Tagging rules are stored in MongoDB and managed through the `/tagging/rules`
+//! endpoints. During collection the enabled rules are loaded into a
+//! `TaggingEngine` and evaluated against every entity in priority order
+//! (lower value first), adding the rule's tags to each matching entity.
+
+use crate::models::Entity;
+use chrono::{DateTime, Utc};
+use mirage_common::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaggingRule {
+    pub id: Uuid,
+    pub name: String,
+    pub priority: i32,
+    pub enabled: bool,
+    pub condition: TagCondition,
+    pub tags: Vec<String>,
+    /// Skip lower-priority rules once this one matches
+    #[serde(default)]
+    pub stop_on_match: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TagCondition {
+    /// Entity value is an IP address inside any of the CIDR ranges
+    IpInCidr { cidrs: Vec<String> },
+    /// Entity type equals the given type (case-insensitive)
+    EntityType { entity_type: String },
+    /// Entity value ends with the suffix (case-insensitive), e.g. `.amazonaws.com`
+    ValueSuffix { suffix: String },
+    /// Entity value contains the substring (case-insensitive)
+    ValueContains { substring: String },
+    /// Every nested condition must match
+    All { conditions: Vec<TagCondition> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateTaggingRuleRequest {
+    pub name: String,
+    pub priority: Option<i32>,
+    pub enabled: Option<bool>,
+    pub condition: TagCondition,
+    pub tags: Vec<String>,
+    pub stop_on_match: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateTaggingRuleRequest {
+    pub name: Option<String>,
+    pub priority: Option<i32>,
+    pub enabled: Option<bool>,
+    pub condition: Option<TagCondition>,
+    pub tags: Option<Vec<String>>,
+    pub stop_on_match: Option<bool>,
+}
+
+impl TaggingRule {
+    pub fn from_request(request: CreateTaggingRuleRequest) -> Result<Self> {
+        let rule = Self {
+            id: Uuid::new_v4(),
+            name: request.name,
+            priority: request.priority.unwrap_or(100),
+            enabled: request.enabled.unwrap_or(true),
+            condition: request.condition,
+            tags: request.tags,
+            stop_on_match: request.stop_on_match.unwrap_or(false),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    pub fn apply_update(&mut self, request: UpdateTaggingRuleRequest) -> Result<()> {
+        if let Some(name) = request.name {
+            self.name = name;
+        }
+        if let Some(priority) = request.priority {
+            self.priority = priority;
+        }
+        if let Some(enabled) = request.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(condition) = request.condition {
+            self.condition = condition;
+        }
+        if let Some(tags) = request.tags {
+            self.tags = tags;
+        }
+        if let Some(stop_on_match) = request.stop_on_match {
+            self.stop_on_match = stop_on_match;
+        }
+        self.updated_at = Utc::now();
+
+        self.validate()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::Validation("Rule name cannot be empty".to_string()));
+        }
+        if self.tags.is_empty() || self.tags.iter().any(|t| t.trim().is_empty()) {
+            return Err(Error::Validation(
+                "Rule must define at least one non-empty tag".to_string(),
+            ));
+        }
+        self.condition.validate()
+    }
+}
+
+impl TagCondition {
+    fn validate(&self) -> Result<()> {
+        match self {
+            TagCondition::IpInCidr { cidrs } => {
+                if cidrs.is_empty() {
+                    return Err(Error::Validation(
+                        "ip_in_cidr condition requires at least one CIDR".to_string(),
+                    ));
+                }
+                for cidr in cidrs {
+                    Cidr::parse(cidr)?;
+                }
+                Ok(())
+            }
+            TagCondition::All { conditions } => {
+                conditions.iter().try_for_each(|c| c.validate())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, entity: &Entity) -> bool {
+        match self {
+            TagCondition::IpInCidr { cidrs } => match entity.value.parse::<IpAddr>() {
+                Ok(ip) => cidrs
+                    .iter()
+                    .filter_map(|c| Cidr::parse(c).ok())
+                    .any(|cidr| cidr.contains(&ip)),
+                Err(_) => false,
+            },
+            TagCondition::EntityType { entity_type } => {
+                entity.entity_type.eq_ignore_ascii_case(entity_type)
+            }
+            TagCondition::ValueSuffix { suffix } => entity
+                .value
+                .to_lowercase()
+                .ends_with(&suffix.to_lowercase()),
+            TagCondition::ValueContains { substring } => entity
+                .value
+                .to_lowercase()
+                .contains(&substring.to_lowercase()),
+            TagCondition::All { conditions } => conditions.iter().all(|c| c.matches(entity)),
+        }
+    }
+}
+
+/// An IPv4 or IPv6 network in CIDR notation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(cidr: &str) -> Result<Self> {
+        let invalid = || Error::Validation(format!("Invalid CIDR: {}", cidr));
+
+        let (addr, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+        let network: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.trim().parse().map_err(|_| invalid())?;
+
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Evaluates a snapshot of tagging rules against collected entities
+#[derive(Debug, Clone, Default)]
+pub struct TaggingEngine {
+    rules: Vec<TaggingRule>,
+}
+
+impl TaggingEngine {
+    /// Builds an engine from the given rules, keeping only enabled ones and
+    /// ordering them by ascending priority.
+    pub fn new(rules: Vec<TaggingRule>) -> Self {
+        let mut rules: Vec<TaggingRule> = rules.into_iter().filter(|r| r.enabled).collect();
+        rules.sort_by_key(|r| r.priority);
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Adds the tags of every matching rule to the entity and returns the
+    /// names of the rules that matched.
+    pub fn apply(&self, entity: &mut Entity) -> Vec<String> {
+        let mut matched = Vec::new();
+
+        for rule in &self.rules {
+            if !rule.condition.matches(entity) {
+                continue;
+            }
+
+            for tag in &rule.tags {
+                if !entity.tags.contains(tag) {
+                    entity.tags.push(tag.clone());
+                }
+            }
+            matched.push(rule.name.clone());
+
+            if rule.stop_on_match {
+                break;
+            }
+        }
+
+        matched
+    }
+
+    pub fn apply_all(&self, entities: &mut [Entity]) -> usize {
+        entities
+            .iter_mut()
+            .map(|entity| self.apply(entity))
+            .filter(|matched| !matched.is_empty())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entity(entity_type: &str, value: &str) -> Entity {
+        Entity {
+            id: None,
+            entity_type: entity_type.to_string(),
+            value: value.to_string(),
+            data: HashMap::new(),
+            metadata: HashMap::new(),
+            confidence: 70,
+            source: "test".to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    fn rule(name: &str, priority: i32, condition: TagCondition, tags: &[&str]) -> TaggingRule {
+        TaggingRule::from_request(CreateTaggingRuleRequest {
+            name: name.to_string(),
+            priority: Some(priority),
+            enabled: Some(true),
+            condition,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            stop_on_match: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ip_in_aws_cidr_is_tagged() {
+        let engine = TaggingEngine::new(vec![rule(
+            "aws",
+            10,
+            TagCondition::IpInCidr {
+                cidrs: vec!["52.0.0.0/11".to_string(), "3.0.0.0/9".to_string()],
+            },
+            &["cloud:aws"],
+        )]);
+
+        let mut inside = entity("ip_address", "52.14.20.7");
+        let mut outside = entity("ip_address", "8.8.8.8");
+
+        assert_eq!(engine.apply(&mut inside), vec!["aws".to_string()]);
+        assert!(engine.apply(&mut outside).is_empty());
+        assert_eq!(inside.tags, vec!["cloud:aws".to_string()]);
+        assert!(outside.tags.is_empty());
+    }
+
+    #[test]
+    fn test_rules_evaluated_in_priority_order() {
+        let mut first = rule(
+            "internal",
+            1,
+            TagCondition::IpInCidr {
+                cidrs: vec!["10.0.0.0/8".to_string()],
+            },
+            &["network:internal"],
+        );
+        first.stop_on_match = true;
+        let second = rule(
+            "any-ip",
+            50,
+            TagCondition::EntityType {
+                entity_type: "IP_ADDRESS".to_string(),
+            },
+            &["type:ip"],
+        );
+
+        // Inserted out of order; the engine sorts by priority
+        let engine = TaggingEngine::new(vec![second, first]);
+
+        let mut internal = entity("ip_address", "10.1.2.3");
+        let mut public = entity("ip_address", "1.1.1.1");
+        engine.apply(&mut internal);
+        engine.apply(&mut public);
+
+        assert_eq!(internal.tags, vec!["network:internal".to_string()]);
+        assert_eq!(public.tags, vec!["type:ip".to_string()]);
+    }
+
+    #[test]
+    fn test_disabled_rules_are_skipped() {
+        let mut disabled = rule(
+            "s3",
+            1,
+            TagCondition::ValueSuffix {
+                suffix: ".s3.amazonaws.com".to_string(),
+            },
+            &["cloud:aws"],
+        );
+        disabled.enabled = false;
+
+        let engine = TaggingEngine::new(vec![disabled]);
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn test_cidr_parsing() {
+        assert!(Cidr::parse("192.168.0.0/16").is_ok());
+        assert!(Cidr::parse("2600:1f00::/24").is_ok());
+        assert!(Cidr::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(&"203.0.113.9".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("10.0.0.0").is_err());
+        assert!(Cidr::parse("not-an-ip/8").is_err());
+
+        let v6 = Cidr::parse("2600:1f00::/24").unwrap();
+        assert!(v6.contains(&"2600:1f18::1".parse().unwrap()));
+        assert!(!v6.contains(&"52.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_rule_rejected() {
+        let result = TaggingRule::from_request(CreateTaggingRuleRequest {
+            name: "bad".to_string(),
+            priority: None,
+            enabled: None,
+            condition: TagCondition::IpInCidr {
+                cidrs: vec!["300.0.0.0/8".to_string()],
+            },
+            tags: vec!["cloud:aws".to_string()],
+            stop_on_match: None,
+        });
+        assert!(matches!(result, Err(Error::Validation(_))));
+    }
+}
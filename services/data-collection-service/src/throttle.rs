@@ -0,0 +1,317 @@
+//! Request pacing per collection source
+//!
+//! Where `ConcurrencyLimiter` caps how many tasks run against a host at once,
+//! `SourceThrottle` spaces out when their requests start. Each request
+//! reserves the next free start time for its source: at least the policy's
+//! `min_delay_ms` plus random jitter after the previous one, inside the
+//! per-minute budget, and not before a `Retry-After` the source sent with a
+//! 429 has passed. Sources are keyed like the limiter, by `host_key`.
+
+use crate::config::{SourcePolicy, ThrottleConfig};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Back-off after a 429 that doesn't say how long to wait
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Default)]
+pub struct SourceThrottle {
+    config: Arc<ThrottleConfig>,
+    sources: Arc<Mutex<HashMap<String, SourceState>>>,
+}
+
+struct SourceState {
+    /// Earliest start for the next request
+    next_start: Instant,
+    /// Start of the current per-minute window and requests reserved in it
+    window_start: Instant,
+    window_requests: u32,
+    /// Set from a 429's `Retry-After`
+    blocked_until: Option<Instant>,
+    last_request: Option<DateTime<Utc>>,
+}
+
+/// Throttle state of one source, as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceThrottleState {
+    pub source: String,
+    pub policy: SourcePolicyView,
+    pub requests_this_minute: u32,
+    /// Wait before a new request to the source could start
+    pub next_request_in_ms: u64,
+    /// Remaining back-off requested by the source, if any
+    pub retry_after_ms: Option<u64>,
+    pub last_request_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourcePolicyView {
+    pub min_delay_ms: u64,
+    pub jitter_ms: u64,
+    pub requests_per_minute: Option<u32>,
+}
+
+impl From<&SourcePolicy> for SourcePolicyView {
+    fn from(policy: &SourcePolicy) -> Self {
+        Self {
+            min_delay_ms: policy.min_delay_ms,
+            jitter_ms: policy.jitter_ms,
+            requests_per_minute: policy.requests_per_minute,
+        }
+    }
+}
+
+impl SourceThrottle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            sources: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits until a request to `source` may start, and counts it
+    pub async fn wait(&self, source: &str) {
+        let start = self.reserve(source, Instant::now());
+        tokio::time::sleep_until(start).await;
+    }
+
+    /// Books the next start time for `source` at or after `now`
+    fn reserve(&self, source: &str, now: Instant) -> Instant {
+        let policy = self.config.policy(source);
+        let mut sources = self.sources.lock().unwrap();
+        let state = state_for(&mut sources, source, now);
+
+        let mut start = state.next_start.max(now);
+        if let Some(blocked_until) = state.blocked_until {
+            start = start.max(blocked_until);
+        }
+
+        if start >= state.window_start + WINDOW {
+            state.window_start = start;
+            state.window_requests = 0;
+        }
+        if let Some(limit) = policy.requests_per_minute {
+            if state.window_requests >= limit.max(1) {
+                start = start.max(state.window_start + WINDOW);
+                state.window_start = start;
+                state.window_requests = 0;
+            }
+        }
+
+        state.window_requests += 1;
+        state.next_start = start + Duration::from_millis(policy.min_delay_ms + jitter(policy));
+        state.last_request =
+            Some(Utc::now() + chrono::Duration::from_std(start - now).unwrap_or_default());
+
+        start
+    }
+
+    /// Holds off `source` for `retry_after`, after it answered 429
+    pub fn back_off(&self, source: &str, retry_after: Duration) {
+        let now = Instant::now();
+        let until = now + retry_after;
+        let mut sources = self.sources.lock().unwrap();
+        let state = state_for(&mut sources, source, now);
+        state.blocked_until = Some(state.blocked_until.map_or(until, |b| b.max(until)));
+        tracing::warn!(
+            "Source {} asked to slow down, pausing it for {}s",
+            source,
+            retry_after.as_secs()
+        );
+    }
+
+    /// Sources that have been requested, with their current pacing
+    pub fn state(&self) -> Vec<SourceThrottleState> {
+        let now = Instant::now();
+        let sources = self.sources.lock().unwrap();
+
+        let mut states: Vec<_> = sources
+            .iter()
+            .map(|(source, state)| {
+                let blocked_for = state
+                    .blocked_until
+                    .filter(|until| *until > now)
+                    .map(|until| until - now);
+                let window_open = now < state.window_start + WINDOW;
+                SourceThrottleState {
+                    source: source.clone(),
+                    policy: self.config.policy(source).into(),
+                    requests_this_minute: if window_open {
+                        state.window_requests
+                    } else {
+                        0
+                    },
+                    next_request_in_ms: state
+                        .next_start
+                        .saturating_duration_since(now)
+                        .max(blocked_for.unwrap_or_default())
+                        .as_millis() as u64,
+                    retry_after_ms: blocked_for.map(|d| d.as_millis() as u64),
+                    last_request_at: state.last_request,
+                }
+            })
+            .collect();
+        states.sort_by(|a, b| a.source.cmp(&b.source));
+        states
+    }
+}
+
+fn state_for<'a>(
+    sources: &'a mut HashMap<String, SourceState>,
+    source: &str,
+    now: Instant,
+) -> &'a mut SourceState {
+    sources
+        .entry(source.to_lowercase())
+        .or_insert_with(|| SourceState {
+            next_start: now,
+            window_start: now,
+            window_requests: 0,
+            blocked_until: None,
+            last_request: None,
+        })
+}
+
+fn jitter(policy: &SourcePolicy) -> u64 {
+    if policy.jitter_ms == 0 {
+        0
+    } else {
+        rand::thread_rng().gen_range(0..=policy.jitter_ms)
+    }
+}
+
+/// How long a 429 response asks the client to wait. `Retry-After` is either
+/// a number of seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Duration {
+    let Some(value) = headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()) else {
+        return DEFAULT_RETRY_AFTER;
+    };
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Duration::from_secs(seconds);
+    }
+
+    match DateTime::parse_from_rfc2822(value) {
+        Ok(at) => (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+        Err(_) => DEFAULT_RETRY_AFTER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy(min_delay_ms: u64, jitter_ms: u64, requests_per_minute: Option<u32>) -> SourcePolicy {
+        SourcePolicy {
+            min_delay_ms,
+            jitter_ms,
+            requests_per_minute,
+        }
+    }
+
+    fn throttle(default: SourcePolicy) -> SourceThrottle {
+        SourceThrottle::new(ThrottleConfig {
+            default,
+            sources: HashMap::from([("slow.example".to_string(), policy(300, 0, None))]),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_requests_to_one_source_are_spaced_by_the_delay() {
+        let throttle = throttle(policy(100, 20, None));
+        let started = Instant::now();
+
+        // Two tasks against the same source, plus one against another
+        let tasks: Vec<_> = ["a.example", "a.example", "b.example"]
+            .into_iter()
+            .map(|source| {
+                let throttle = throttle.clone();
+                tokio::spawn(async move {
+                    throttle.wait(source).await;
+                    Instant::now() - started
+                })
+            })
+            .collect();
+        let mut times = Vec::new();
+        for task in tasks {
+            times.push(task.await.unwrap());
+        }
+
+        let (first, second) = (times[0].min(times[1]), times[0].max(times[1]));
+        assert!(second - first >= Duration::from_millis(100), "{:?}", times);
+        assert!(second - first <= Duration::from_millis(200), "{:?}", times);
+        // Other sources aren't held up
+        assert!(times[2] < Duration::from_millis(50), "{:?}", times);
+    }
+
+    #[tokio::test]
+    async fn test_source_policy_overrides_default() {
+        let throttle = throttle(policy(0, 0, None));
+        let now = Instant::now();
+
+        let first = throttle.reserve("Slow.Example", now);
+        let second = throttle.reserve("slow.example", now);
+        assert_eq!(second - first, Duration::from_millis(300));
+        assert_eq!(throttle.reserve("fast.example", now), now);
+        assert_eq!(throttle.reserve("fast.example", now), now);
+    }
+
+    #[tokio::test]
+    async fn test_per_minute_budget_defers_to_next_window() {
+        let throttle = throttle(policy(0, 0, Some(2)));
+        let now = Instant::now();
+
+        assert_eq!(throttle.reserve("a.example", now), now);
+        assert_eq!(throttle.reserve("a.example", now), now);
+        assert_eq!(throttle.reserve("a.example", now), now + WINDOW);
+        assert_eq!(throttle.reserve("a.example", now), now + WINDOW);
+        assert_eq!(throttle.reserve("a.example", now), now + WINDOW * 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_pauses_the_source() {
+        let throttle = throttle(policy(0, 0, None));
+        throttle.wait("a.example").await;
+
+        throttle.back_off("a.example", Duration::from_millis(150));
+        let state = throttle.state();
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[0].source, "a.example");
+        assert_eq!(state[0].requests_this_minute, 1);
+        assert!(state[0].retry_after_ms.unwrap() > 100);
+
+        let started = Instant::now();
+        throttle.wait("a.example").await;
+        assert!(Instant::now() - started >= Duration::from_millis(140));
+        assert_eq!(throttle.state()[0].retry_after_ms, None);
+    }
+
+    #[test]
+    fn test_retry_after_header_forms() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), DEFAULT_RETRY_AFTER);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Duration::from_secs(120));
+
+        let at = (Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&at).unwrap());
+        let wait = retry_after(&headers);
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), DEFAULT_RETRY_AFTER);
+    }
+}
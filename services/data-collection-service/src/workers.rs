@@ -1,9 +1,16 @@
+use crate::cache::ResponseCache;
 use crate::config::AppConfig;
 use crate::execution::TaskExecutor;
+use crate::limiter::{host_key, ConcurrencyLimiter};
 use crate::models::{CollectionTask, ResultSummary, TaskResult, TaskStatus};
-use crate::queue::TaskQueue;
-use crate::repositories::{ResultRepository, TaskRepository};
+use crate::proxy::ProxyPool;
+use crate::queue::{next_delivery, Delivery, QueuedTask, TaskQueue};
+use crate::repositories::{ResultRepository, TaggingRuleRepository, TaskRepository};
+use crate::tagging::TaggingEngine;
+use crate::throttle::SourceThrottle;
 use chrono::Utc;
+use mirage_common::metrics::{IntGauge, Metrics};
+use mirage_common::shutdown::ShutdownSignal;
 use mirage_common::{Error, Result};
 use reqwest::Client;
 use std::collections::HashMap;
@@ -13,16 +20,52 @@ use tokio::sync::{mpsc, Mutex, RwLock, Semaphore};
 use tokio::time;
 use uuid::Uuid;
 
-// Start a worker pool for processing tasks
+/// Gauges the worker pool keeps up to date, exported on /metrics
+#[derive(Clone)]
+pub struct PoolMetrics {
+    active_tasks: IntGauge,
+    queue_depth: IntGauge,
+    in_flight: IntGauge,
+    dead_letter: IntGauge,
+}
+
+impl PoolMetrics {
+    pub fn new(metrics: &Metrics) -> Result<Self> {
+        Ok(Self {
+            active_tasks: metrics.gauge("collection_active_tasks", "Collection tasks running")?,
+            queue_depth: metrics.gauge("collection_queue_depth", "Collection tasks queued")?,
+            in_flight: metrics.gauge(
+                "collection_in_flight_tasks",
+                "Collection tasks holding a concurrency slot",
+            )?,
+            dead_letter: metrics.gauge(
+                "collection_dead_letter_tasks",
+                "Collection tasks given up on after too many attempts",
+            )?,
+        })
+    }
+}
+
+// Tasks set aside in one pass over the queue before they are put back
+const MAX_DEFERRED: usize = 100;
+
+// Start a worker pool for processing tasks. Runs until `shutdown`, then
+// returns once the tasks already running have finished.
 pub async fn start_worker_pool(
     task_repo: TaskRepository,
     result_repo: ResultRepository,
-    task_queue: TaskQueue,
+    tagging_repo: TaggingRuleRepository,
+    task_queue: Arc<dyn TaskQueue>,
     http_client: Client,
     config: AppConfig,
     min_workers: usize,
     max_workers: usize,
     poll_interval_ms: u64,
+    pool_metrics: PoolMetrics,
+    throttle: SourceThrottle,
+    proxies: ProxyPool,
+    cache: ResponseCache,
+    shutdown: ShutdownSignal,
 ) {
     tracing::info!(
         "Starting worker pool with min={}, max={} workers",
@@ -37,6 +80,11 @@ pub async fn start_worker_pool(
     // Create shared repository instances
     let task_repo = Arc::new(task_repo);
     let result_repo = Arc::new(result_repo);
+    let tagging_repo = Arc::new(tagging_repo);
+    // Caps on running tasks, globally and per target host
+    let limiter = ConcurrencyLimiter::new(config.worker.max_in_flight, config.worker.max_per_host);
+    let visibility_timeout = Duration::from_secs(config.queue.visibility_timeout_seconds);
+    let max_attempts = config.queue.max_attempts;
     let config = Arc::new(config);
     let http_client = Arc::new(http_client);
 
@@ -54,18 +102,33 @@ pub async fn start_worker_pool(
     let monitor_queue = task_queue.clone();
     let monitor_active_tasks = active_tasks.clone();
     let monitor_semaphore = worker_semaphore.clone();
+    let monitor_limiter = limiter.clone();
+    let monitor_metrics = pool_metrics.clone();
+    let mut monitor_shutdown = shutdown.clone();
     tokio::spawn(async move {
         // Ensure at least min_workers are always running
         loop {
             let queue_size = match monitor_queue.queue_size().await {
-                Ok(size) => size,
+                Ok(size) => {
+                    monitor_metrics.queue_depth.set(size as i64);
+                    size
+                }
                 Err(e) => {
                     tracing::error!("Failed to get queue size: {}", e);
                     0
                 }
             };
 
+            match monitor_queue.dead_letter_size().await {
+                Ok(size) => monitor_metrics.dead_letter.set(size as i64),
+                Err(e) => tracing::error!("Failed to get dead-letter size: {}", e),
+            }
+
             let active_count = monitor_active_tasks.read().await.len();
+            monitor_metrics.active_tasks.set(active_count as i64);
+            monitor_metrics
+                .in_flight
+                .set(monitor_limiter.in_flight() as i64);
 
             // If we have less active workers than minimum and there are tasks in the queue,
             // start more workers up to min_workers
@@ -94,6 +157,7 @@ pub async fn start_worker_pool(
                                 worker_queue,
                                 worker_active_tasks,
                                 worker_queue_lock,
+                                visibility_timeout,
                                 permit,
                             )
                             .await;
@@ -102,14 +166,18 @@ pub async fn start_worker_pool(
                 }
             }
 
-            time::sleep(Duration::from_secs(5)).await;
+            tokio::select! {
+                _ = time::sleep(Duration::from_secs(5)) => {}
+                _ = monitor_shutdown.recv() => break,
+            }
         }
     });
 
     // Start completion handler
     let completion_task_repo = task_repo.clone();
     let completion_result_repo = result_repo.clone();
-    tokio::spawn(async move {
+    let completion_queue = task_queue.clone();
+    let completion_handler = tokio::spawn(async move {
         while let Some((task_id, success, error_message, task_result)) = completion_rx.recv().await
         {
             // Update task status
@@ -154,6 +222,11 @@ pub async fn start_worker_pool(
                     tracing::error!("Failed to save task result: {}", e);
                 }
             }
+
+            // The task is finished either way, so it must not be redelivered
+            if let Err(e) = completion_queue.ack_task(task_id).await {
+                tracing::error!("Failed to ack task {}: {}", task_id, e);
+            }
         }
     });
 
@@ -163,20 +236,60 @@ pub async fn start_worker_pool(
     let processing_semaphore = worker_semaphore.clone();
     let processing_queue_lock = queue_lock.clone();
     let processing_active_tasks = active_tasks.clone();
-    tokio::spawn(async move {
+    let processing_metrics = pool_metrics;
+    let processing = tokio::spawn(async move {
+        // Tasks whose host is at its limit. They stay in flight until the
+        // end of the pass, otherwise the same task would be dequeued again
+        // straight away and block the tasks behind it.
+        let mut deferred = Vec::new();
+
         loop {
+            // Stop taking tasks from the queue once shutdown starts
+            if shutdown.is_shutting_down() {
+                requeue(&processing_queue, &mut deferred).await;
+                break;
+            }
+
             // Check if we have available capacity to process more tasks
             let permits_available = processing_semaphore.available_permits();
             let active_count = processing_active_tasks.read().await.len();
 
-            if permits_available > 0 && active_count < max_workers {
+            if permits_available > 0
+                && active_count < max_workers
+                && limiter.has_capacity()
+                && deferred.len() < MAX_DEFERRED
+            {
                 // Try to get next task from queue
                 let queue_task = {
                     let _lock = processing_queue_lock.lock().await;
-                    match processing_queue.dequeue_task().await {
-                        Ok(Some(task)) => task,
+                    match next_delivery(processing_queue.as_ref(), visibility_timeout, max_attempts)
+                        .await
+                    {
+                        Ok(Some(Delivery::Task(task))) => task,
+                        Ok(Some(Delivery::DeadLettered(task))) => {
+                            tracing::warn!(
+                                "Task {} dead-lettered after {} attempts",
+                                task.task_id,
+                                max_attempts
+                            );
+                            if let Err(e) = processing_task_repo
+                                .update_task_status(
+                                    &task.task_id,
+                                    TaskStatus::Failed,
+                                    None,
+                                    Some(Utc::now()),
+                                    Some(format!("Gave up after {} attempts", max_attempts)),
+                                    None,
+                                )
+                                .await
+                            {
+                                tracing::error!("Failed to update task status: {}", e);
+                            }
+                            continue;
+                        }
                         Ok(None) => {
                             // Queue is empty, sleep and try again
+                            requeue(&processing_queue, &mut deferred).await;
                             time::sleep(Duration::from_millis(poll_interval_ms)).await;
                             continue;
                         }
@@ -199,13 +312,36 @@ pub async fn start_worker_pool(
                             "Task {} from queue not found in database",
                             queue_task.task_id
                         );
+                        if let Err(e) = processing_queue.ack_task(queue_task.task_id).await {
+                            tracing::error!("Failed to ack task {}: {}", queue_task.task_id, e);
+                        }
                         continue;
                     }
                     Err(e) => {
                         tracing::error!("Failed to get task {}: {}", queue_task.task_id, e);
+                        deferred.push(queue_task);
+                        continue;
+                    }
+                };
+
+                // Cancelled while it waited in the queue
+                if task.status.is_finished() {
+                    if let Err(e) = processing_queue.ack_task(task.id).await {
+                        tracing::error!("Failed to ack task {}: {}", task.id, e);
+                    }
+                    continue;
+                }
+
+                // Wait for a slot if the target host already has its share
+                // of running tasks
+                let slot = match limiter.try_acquire(&host_key(&task.target)) {
+                    Some(slot) => slot,
+                    None => {
+                        deferred.push(queue_task);
                         continue;
                     }
                 };
+                processing_metrics.in_flight.set(limiter.in_flight() as i64);
 
                 // Try to acquire worker permit
                 if let Ok(permit) = processing_semaphore.clone().try_acquire_owned() {
@@ -215,6 +351,14 @@ pub async fn start_worker_pool(
                     let worker_completion_tx = completion_tx.clone();
                     let worker_http_client = http_client.clone();
                     let worker_config = config.clone();
+                    let worker_tagging_repo = tagging_repo.clone();
+                    let worker_limiter = limiter.clone();
+                    let worker_throttle = throttle.clone();
+                    let worker_proxies = proxies.clone();
+                    let worker_cache = cache.clone();
+                    let worker_metrics = processing_metrics.clone();
+                    let worker_queue = processing_queue.clone();
+                    let attempts = queue_task.attempts;
 
                     tokio::spawn(async move {
                         // Add to active tasks
@@ -223,37 +367,77 @@ pub async fn start_worker_pool(
                             active.insert(task.id, ());
                         }
 
-                        // Update task status to running
-                        if let Err(e) = worker_task_repo
-                            .update_task_status(
-                                &task.id,
-                                TaskStatus::Running,
-                                Some(Utc::now()),
-                                None,
-                                None,
-                                None,
+                        // Update task status to running, unless its scan
+                        // was cancelled since it was dequeued
+                        let started = match worker_task_repo.start_task(&task.id).await {
+                            Ok(started) => started,
+                            Err(e) => {
+                                tracing::error!("Failed to update task status: {}", e);
+                                true
+                            }
+                        };
+
+                        let outcome = if started {
+                            if let Err(e) =
+                                worker_task_repo.record_attempt(&task.id, attempts).await
+                            {
+                                tracing::error!("Failed to record task attempt: {}", e);
+                            }
+
+                            // Load the current tagging rules so edits apply to the next task
+                            let tagging = match worker_tagging_repo.list_rules(true).await {
+                                Ok(rules) => TaggingEngine::new(rules),
+                                Err(e) => {
+                                    tracing::error!("Failed to load tagging rules: {}", e);
+                                    TaggingEngine::default()
+                                }
+                            };
+
+                            // Create task executor
+                            let executor =
+                                TaskExecutor::new(task.clone(), worker_http_client, worker_config)
+                                    .with_tagging(tagging)
+                                    .with_throttle(worker_throttle)
+                                    .with_proxies(worker_proxies)
+                                    .with_cache(worker_cache);
+
+                            // Execute task, keeping it hidden from other workers
+                            // for as long as it runs
+                            execute_with_heartbeat(
+                                &executor,
+                                worker_queue.as_ref(),
+                                worker_task_repo.as_ref(),
+                                task.id,
+                                visibility_timeout,
                             )
                             .await
-                        {
-                            tracing::error!("Failed to update task status: {}", e);
-                        }
-
-                        // Create task executor
-                        let executor =
-                            TaskExecutor::new(task.clone(), worker_http_client, worker_config);
-
-                        // Execute task
-                        let (success, error, result) = match executor.execute().await {
-                            Ok(result) => (true, None, Some(result)),
-                            Err(e) => (false, Some(format!("{}", e)), None),
+                        } else {
+                            Outcome::Cancelled
                         };
 
-                        // Send completion message
-                        if let Err(e) = worker_completion_tx
-                            .send((task.id, success, error, result))
-                            .await
-                        {
-                            tracing::error!("Failed to send completion message: {}", e);
+                        match outcome {
+                            Outcome::Finished(execution) => {
+                                let (success, error, result) = match execution {
+                                    Ok(result) => (true, None, Some(result)),
+                                    Err(e) => (false, Some(format!("{}", e)), None),
+                                };
+
+                                // Send completion message
+                                if let Err(e) = worker_completion_tx
+                                    .send((task.id, success, error, result))
+                                    .await
+                                {
+                                    tracing::error!("Failed to send completion message: {}", e);
+                                }
+                            }
+                            Outcome::Cancelled => {
+                                // The status is already set, only the queue
+                                // entry is left
+                                tracing::info!("Task {} was cancelled", task.id);
+                                if let Err(e) = worker_queue.ack_task(task.id).await {
+                                    tracing::error!("Failed to ack task {}: {}", task.id, e);
+                                }
+                            }
                         }
 
                         // Remove from active tasks
@@ -262,24 +446,100 @@ pub async fn start_worker_pool(
                             active.remove(&task.id);
                         }
 
-                        // Drop permit to release worker
+                        // Drop permit and slot to release worker
                         drop(permit);
+                        drop(slot);
+                        worker_metrics
+                            .in_flight
+                            .set(worker_limiter.in_flight() as i64);
                     });
                 }
             } else {
                 // No capacity available, sleep and try again
+                requeue(&processing_queue, &mut deferred).await;
                 time::sleep(Duration::from_millis(poll_interval_ms)).await;
             }
         }
     });
+
+    if let Err(e) = processing.await {
+        tracing::error!("Task processing loop failed: {}", e);
+    }
+
+    // Running tasks each hold a completion sender, so the handler only stops
+    // after the last of them has reported back
+    tracing::info!("Waiting for running collection tasks to finish");
+    if let Err(e) = completion_handler.await {
+        tracing::error!("Task completion handler failed: {}", e);
+    }
+
+    tracing::info!("Worker pool stopped");
+}
+
+// How a task run ended
+enum Outcome {
+    Finished(Result<TaskResult>),
+    // Its scan was cancelled; the execution was dropped
+    Cancelled,
+}
+
+// Runs a task, extending its visibility timeout every third of the timeout
+// so it isn't redelivered while it is still running. The task is stopped at
+// the first heartbeat after it is cancelled.
+async fn execute_with_heartbeat(
+    executor: &TaskExecutor,
+    task_queue: &dyn TaskQueue,
+    task_repo: &TaskRepository,
+    task_id: Uuid,
+    visibility_timeout: Duration,
+) -> Outcome {
+    let execution = executor.execute();
+    tokio::pin!(execution);
+
+    let mut heartbeat = time::interval((visibility_timeout / 3).max(Duration::from_secs(1)));
+    // The first tick completes immediately
+    heartbeat.tick().await;
+
+    loop {
+        tokio::select! {
+            result = &mut execution => return Outcome::Finished(result),
+            _ = heartbeat.tick() => {
+                match task_repo.get_task_by_id(&task_id).await {
+                    Ok(Some(task)) if task.status == TaskStatus::Cancelled => {
+                        return Outcome::Cancelled
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to check task {} status: {}", task_id, e),
+                }
+
+                match task_queue.extend_visibility(task_id, visibility_timeout).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::warn!("Task {} is no longer in flight, it may run twice", task_id)
+                    }
+                    Err(e) => tracing::error!("Failed to extend task {} visibility: {}", task_id, e),
+                }
+            }
+        }
+    }
+}
+
+// Put deferred tasks back on the queue
+async fn requeue(task_queue: &Arc<dyn TaskQueue>, deferred: &mut Vec<QueuedTask>) {
+    for queued in deferred.drain(..) {
+        if let Err(e) = task_queue.nack_task(queued.task_id).await {
+            tracing::error!("Failed to requeue task {}: {}", queued.task_id, e);
+        }
+    }
 }
 
 // Process tasks from the queue
 async fn process_tasks(
     task_repo: Arc<TaskRepository>,
-    task_queue: TaskQueue,
+    task_queue: Arc<dyn TaskQueue>,
     active_tasks: Arc<RwLock<HashMap<Uuid, ()>>>,
     queue_lock: Arc<Mutex<()>>,
+    visibility_timeout: Duration,
     _permit: tokio::sync::OwnedSemaphorePermit,
 ) {
     loop {
@@ -288,7 +548,7 @@ async fn process_tasks(
             // Ensure only one worker dequeues at a time
             let _lock = queue_lock.lock().await;
 
-            match task_queue.dequeue_task().await {
+            match task_queue.dequeue_task(visibility_timeout).await {
                 Ok(Some(queued_task)) => {
                     // Get full task details
                     match task_repo.get_task_by_id(&queued_task.task_id).await {
@@ -328,7 +588,10 @@ async fn process_tasks(
             }
         }
 
-        // Process the task in a separate task to keep this loop running
+        // Hand the task back for the processing loop to run
+        if let Err(e) = task_queue.nack_task(task.id).await {
+            tracing::error!("Failed to requeue task {}: {}", task.id, e);
+        }
         break;
     }
 }
@@ -0,0 +1,26 @@
+-- Entity metadata; the full entity lives in MongoDB and Elasticsearch
+CREATE TABLE entities (
+    id UUID PRIMARY KEY,
+    source_module UUID NOT NULL,
+    scan_id UUID,
+    entity_type VARCHAR(100) NOT NULL,
+    value TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_entities_scan_id ON entities(scan_id);
+CREATE INDEX idx_entities_type_value ON entities(entity_type, value);
+
+-- Relationships between entities; extra data is kept in MongoDB
+CREATE TABLE relationships (
+    id UUID PRIMARY KEY,
+    source_id UUID NOT NULL,
+    target_id UUID NOT NULL,
+    relationship_type VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_relationships_source_id ON relationships(source_id);
+CREATE INDEX idx_relationships_target_id ON relationships(target_id);
@@ -0,0 +1,387 @@
+//! Streaming NDJSON exports
+//!
+//! An export is a sequence of newline-delimited JSON objects tagged by `type`:
+//! a leading `metadata` line with the expected count and starting cursor, one
+//! `record` line per entity, and a trailing `summary` line with the number of
+//! records actually sent. `complete` is only `true` when the stream reached
+//! the end of the result set; otherwise `next_cursor` resumes the export.
+//!
+//! Records are paged with offsets, so entities inserted while the export runs
+//! can shift pages. Records already sent are skipped, and when the final
+//! count differs from the expected one the summary reports `count_changed`.
+
+use crate::models::DataEntity;
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use mirage_common::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: u64 = 500;
+
+/// Where an export starts; serialized as an opaque string
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExportCursor {
+    offset: u64,
+}
+
+impl ExportCursor {
+    pub fn parse(cursor: &str) -> Result<Self> {
+        cursor
+            .parse()
+            .map(|offset| Self { offset })
+            .map_err(|_| Error::Validation(format!("Invalid export cursor: {}", cursor)))
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl std::fmt::Display for ExportCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.offset)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportLine {
+    Metadata {
+        expected_count: u64,
+        cursor: String,
+        started_at: DateTime<Utc>,
+    },
+    Record {
+        data: DataEntity,
+    },
+    Summary {
+        count: u64,
+        expected_count: u64,
+        /// Set when the result set grew or shrank while streaming
+        count_changed: bool,
+        /// Size of the result set when the export finished
+        final_count: Option<u64>,
+        complete: bool,
+        next_cursor: Option<String>,
+        error: Option<String>,
+    },
+}
+
+impl ExportLine {
+    fn to_ndjson(&self, buf: &mut Vec<u8>) {
+        // Serializing these types cannot fail
+        serde_json::to_writer(&mut *buf, self).expect("export line serializes");
+        buf.push(b'\n');
+    }
+}
+
+/// Paged access to the entities being exported
+#[async_trait]
+pub trait ExportSource: Send + Sync {
+    async fn count(&self) -> Result<u64>;
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<DataEntity>>;
+}
+
+struct ExportState<S> {
+    source: S,
+    page_size: u64,
+    start_offset: u64,
+    offset: u64,
+    expected_count: u64,
+    count: u64,
+    seen: HashSet<Uuid>,
+    finished: bool,
+}
+
+/// Streams the export as NDJSON. `expected_count` is the size of the result
+/// set counted before streaming starts, from the cursor onwards.
+pub fn ndjson_stream<S>(
+    source: S,
+    cursor: ExportCursor,
+    expected_count: u64,
+    page_size: u64,
+) -> impl Stream<Item = std::result::Result<Bytes, actix_web::Error>>
+where
+    S: ExportSource + 'static,
+{
+    let mut header = Vec::new();
+    ExportLine::Metadata {
+        expected_count,
+        cursor: cursor.to_string(),
+        started_at: Utc::now(),
+    }
+    .to_ndjson(&mut header);
+
+    let state = ExportState {
+        source,
+        page_size: page_size.max(1),
+        start_offset: cursor.offset,
+        offset: cursor.offset,
+        expected_count,
+        count: 0,
+        seen: HashSet::new(),
+        finished: false,
+    };
+
+    let body = stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+
+        let mut buf = Vec::new();
+        match state.source.page(state.offset, state.page_size).await {
+            Ok(page) => {
+                let page_len = page.len() as u64;
+                state.offset += page_len;
+
+                for entity in page {
+                    if state.seen.insert(entity.id) {
+                        state.count += 1;
+                        ExportLine::Record { data: entity }.to_ndjson(&mut buf);
+                    }
+                }
+
+                if page_len < state.page_size {
+                    state.finished = true;
+                    let final_count = match state.source.count().await {
+                        Ok(total) => Some(total.saturating_sub(state.start_offset)),
+                        Err(e) => {
+                            tracing::warn!("Failed to recount export result set: {}", e);
+                            None
+                        }
+                    };
+                    state.summary(true, final_count, None).to_ndjson(&mut buf);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Export failed at offset {}: {}", state.offset, e);
+                state.finished = true;
+                state
+                    .summary(false, None, Some(e.to_string()))
+                    .to_ndjson(&mut buf);
+            }
+        }
+
+        Some((Ok(Bytes::from(buf)), state))
+    });
+
+    stream::once(async move { Ok(Bytes::from(header)) }).chain(body)
+}
+
+impl<S> ExportState<S> {
+    fn summary(
+        &self,
+        complete: bool,
+        final_count: Option<u64>,
+        error: Option<String>,
+    ) -> ExportLine {
+        let count_changed = final_count.is_some_and(|c| c != self.expected_count)
+            || (complete && self.count != self.expected_count);
+
+        ExportLine::Summary {
+            count: self.count,
+            expected_count: self.expected_count,
+            count_changed,
+            final_count,
+            complete,
+            next_cursor: (!complete).then(|| {
+                ExportCursor {
+                    offset: self.offset,
+                }
+                .to_string()
+            }),
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    fn entity(n: usize) -> DataEntity {
+        DataEntity {
+            id: Uuid::new_v4(),
+            source_module: Uuid::nil(),
+            scan_id: None,
+            entity_type: "domain".to_string(),
+            value: format!("host{}.example.com", n),
+            data: serde_json::json!({}),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            metadata: HashMap::new(),
+            version: 1,
+            deleted_at: None,
+        }
+    }
+
+    /// In-memory source; optionally prepends a new entity after the first
+    /// page is read, shifting later pages like a concurrent insert would.
+    struct MemorySource {
+        entities: Mutex<Vec<DataEntity>>,
+        insert_after_first_page: bool,
+        fail_at_offset: Option<u64>,
+    }
+
+    impl MemorySource {
+        fn new(count: usize) -> Self {
+            Self {
+                entities: Mutex::new((0..count).map(entity).collect()),
+                insert_after_first_page: false,
+                fail_at_offset: None,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ExportSource for MemorySource {
+        async fn count(&self) -> Result<u64> {
+            Ok(self.entities.lock().unwrap().len() as u64)
+        }
+
+        async fn page(&self, offset: u64, limit: u64) -> Result<Vec<DataEntity>> {
+            if self.fail_at_offset == Some(offset) {
+                return Err(Error::Database("search backend unavailable".to_string()));
+            }
+
+            let mut entities = self.entities.lock().unwrap();
+            let page: Vec<DataEntity> = entities
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect();
+
+            if offset == 0 && self.insert_after_first_page {
+                entities.insert(0, entity(999));
+            }
+
+            Ok(page)
+        }
+    }
+
+    async fn export(source: MemorySource, page_size: u64) -> Vec<ExportLine> {
+        let expected = source.count().await.unwrap();
+        let chunks: Vec<_> = ndjson_stream(source, ExportCursor::default(), expected, page_size)
+            .collect()
+            .await;
+
+        let body: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+
+        String::from_utf8(body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn records(lines: &[ExportLine]) -> usize {
+        lines
+            .iter()
+            .filter(|l| matches!(l, ExportLine::Record { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_export_ends_with_matching_summary() {
+        let lines = export(MemorySource::new(7), 3).await;
+
+        assert!(matches!(
+            lines.first(),
+            Some(ExportLine::Metadata {
+                expected_count: 7,
+                ..
+            })
+        ));
+        assert_eq!(records(&lines), 7);
+
+        match lines.last() {
+            Some(ExportLine::Summary {
+                count,
+                expected_count,
+                count_changed,
+                complete,
+                next_cursor,
+                ..
+            }) => {
+                assert_eq!(*count, 7);
+                assert_eq!(*expected_count, 7);
+                assert!(!count_changed);
+                assert!(complete);
+                assert!(next_cursor.is_none());
+            }
+            other => panic!("expected summary trailer, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_during_export_is_reported() {
+        let mut source = MemorySource::new(6);
+        source.insert_after_first_page = true;
+
+        let lines = export(source, 3).await;
+
+        // The shifted record is skipped rather than sent twice
+        assert_eq!(records(&lines), 6);
+        match lines.last() {
+            Some(ExportLine::Summary {
+                count,
+                expected_count,
+                count_changed,
+                final_count,
+                complete,
+                ..
+            }) => {
+                assert_eq!(*count as usize, records(&lines));
+                assert_eq!(*expected_count, 6);
+                assert_eq!(*final_count, Some(7));
+                assert!(count_changed);
+                assert!(complete);
+            }
+            other => panic!("expected summary trailer, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_export_is_resumable() {
+        let mut source = MemorySource::new(10);
+        source.fail_at_offset = Some(4);
+
+        let lines = export(source, 4).await;
+
+        assert_eq!(records(&lines), 4);
+        match lines.last() {
+            Some(ExportLine::Summary {
+                count,
+                complete,
+                next_cursor,
+                error,
+                ..
+            }) => {
+                assert_eq!(*count, 4);
+                assert!(!complete);
+                assert_eq!(next_cursor.as_deref(), Some("4"));
+                assert!(error.is_some());
+            }
+            other => panic!("expected summary trailer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cursor_parsing() {
+        assert_eq!(ExportCursor::parse("25").unwrap().to_string(), "25");
+        assert!(matches!(
+            ExportCursor::parse("abc"),
+            Err(Error::Validation(_))
+        ));
+    }
+}
@@ -1,29 +1,44 @@
-use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
+use actix_web::http::header;
+use actix_web::{
+    delete, get, post, put, web, Error, HttpMessage, HttpRequest, HttpResponse, Responder,
+};
 use mirage_common::Error as CommonError;
+use mirage_middleware::Claims;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::{QueryParams, StoreDataRequest, StoreRelationshipRequest};
+use crate::export::{ndjson_stream, DEFAULT_PAGE_SIZE};
+use crate::models::{ExportParams, QueryParams, StoreDataRequest, StoreRelationshipRequest};
 use crate::services::StorageService;
 
 pub fn storage_routes() -> actix_web::Scope {
     web::scope("/data")
         .service(store_data)
+        // Registered before `/{id}` so "export" isn't parsed as an ID
+        .service(export_data)
         .service(get_data)
         .service(update_data)
         .service(delete_data)
+        .service(restore_data)
+        .service(get_audit_log)
         .service(query_data)
         .service(create_relationship)
         .service(get_relationships)
 }
 
+pub fn artifact_routes() -> actix_web::Scope {
+    web::scope("/artifacts").service(get_provenance)
+}
+
 #[post("")]
 async fn store_data(
+    req: HttpRequest,
     data: web::Json<StoreDataRequest>,
     storage_service: web::Data<StorageService>,
 ) -> Result<HttpResponse, Error> {
+    let caller = caller_id(&req);
     let data_id = storage_service
-        .store_data(data.into_inner())
+        .store_data(data.into_inner(), caller.as_deref())
         .await
         .map_err(|e| {
             tracing::error!("Failed to store data: {}", e);
@@ -36,69 +51,171 @@ async fn store_data(
     Ok(HttpResponse::Created().json(serde_json::json!({ "data_id": data_id })))
 }
 
+#[derive(Debug, Deserialize)]
+struct GetDataQuery {
+    include_deleted: Option<bool>,
+}
+
 #[get("/{id}")]
 async fn get_data(
     id: web::Path<String>,
+    query: web::Query<GetDataQuery>,
     storage_service: web::Data<StorageService>,
 ) -> Result<HttpResponse, Error> {
     let id =
         Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid data ID"))?;
+    let include_deleted = query.include_deleted.unwrap_or(false);
 
-    let data = storage_service.get_data(&id).await.map_err(|e| match e {
+    let data = storage_service
+        .get_data(&id, include_deleted)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to get data: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(version_etag(data.version))
+        .json(data))
+}
+
+/// Version the client expects to overwrite, from `If-Match: "<version>"`
+fn expected_version(req: &HttpRequest) -> Result<i64, Error> {
+    let value = req
+        .headers()
+        .get(header::IF_MATCH)
+        .ok_or_else(|| {
+            actix_web::error::ErrorPreconditionRequired(
+                "If-Match header with the entity version is required",
+            )
+        })?
+        .to_str()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid If-Match header"))?;
+
+    let value = value.trim();
+    let value = value.strip_prefix("W/").unwrap_or(value);
+    value
+        .trim_matches('"')
+        .parse()
+        .map_err(|_| actix_web::error::ErrorBadRequest("If-Match must be an entity version"))
+}
+
+/// User recorded in the audit trail for a change, as authenticated by the
+/// middleware wrapping the routes
+fn caller_id(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+}
+
+/// Maps the error from a versioned write: 409 when the entity changed since
+/// the version the write was based on
+fn write_error(e: CommonError, action: &str) -> Error {
+    match e {
         CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+        CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+        CommonError::Conflict(_) => actix_web::error::ErrorConflict(e),
         _ => {
-            tracing::error!("Failed to get data: {}", e);
+            tracing::error!("Failed to {} data: {}", action, e);
             actix_web::error::ErrorInternalServerError(e)
         }
-    })?;
+    }
+}
 
-    Ok(HttpResponse::Ok().json(data))
+fn version_etag(version: i64) -> (header::HeaderName, String) {
+    (header::ETAG, format!("\"{}\"", version))
 }
 
 #[put("/{id}")]
 async fn update_data(
+    req: HttpRequest,
     id: web::Path<String>,
     data: web::Json<serde_json::Value>,
     storage_service: web::Data<StorageService>,
 ) -> Result<HttpResponse, Error> {
     let id =
         Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid data ID"))?;
+    let expected_version = expected_version(&req)?;
+    let caller = caller_id(&req);
 
-    storage_service
-        .update_data(&id, data.into_inner())
+    let entity = storage_service
+        .update_data(&id, data.into_inner(), expected_version, caller.as_deref())
         .await
-        .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
-            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
-            _ => {
-                tracing::error!("Failed to update data: {}", e);
-                actix_web::error::ErrorInternalServerError(e)
-            }
-        })?;
+        .map_err(|e| write_error(e, "update"))?;
 
-    Ok(HttpResponse::NoContent().finish())
+    Ok(HttpResponse::NoContent()
+        .insert_header(version_etag(entity.version))
+        .finish())
 }
 
 #[delete("/{id}")]
 async fn delete_data(
+    req: HttpRequest,
     id: web::Path<String>,
     storage_service: web::Data<StorageService>,
 ) -> Result<HttpResponse, Error> {
     let id =
         Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid data ID"))?;
+    let caller = caller_id(&req);
 
     storage_service
-        .delete_data(&id)
+        .delete_data(&id, caller.as_deref())
+        .await
+        .map_err(|e| write_error(e, "delete"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/{id}/restore")]
+async fn restore_data(
+    req: HttpRequest,
+    id: web::Path<String>,
+    storage_service: web::Data<StorageService>,
+) -> Result<HttpResponse, Error> {
+    let id =
+        Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid data ID"))?;
+    let caller = caller_id(&req);
+
+    let entity = storage_service
+        .restore_data(&id, caller.as_deref())
+        .await
+        .map_err(|e| write_error(e, "restore"))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(version_etag(entity.version))
+        .json(entity))
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditLogQuery {
+    limit: Option<i64>,
+}
+
+#[get("/{id}/audit")]
+async fn get_audit_log(
+    id: web::Path<String>,
+    query: web::Query<AuditLogQuery>,
+    storage_service: web::Data<StorageService>,
+) -> Result<HttpResponse, Error> {
+    let id =
+        Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid data ID"))?;
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    let logs = storage_service
+        .get_audit_log(&id, limit)
         .await
         .map_err(|e| match e {
             CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
-                tracing::error!("Failed to delete data: {}", e);
+                tracing::error!("Failed to get audit log: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
             }
         })?;
 
-    Ok(HttpResponse::NoContent().finish())
+    Ok(HttpResponse::Ok().json(logs))
 }
 
 #[get("")]
@@ -117,6 +234,32 @@ async fn query_data(
     Ok(HttpResponse::Ok().json(data))
 }
 
+#[get("/export")]
+async fn export_data(
+    query: web::Query<ExportParams>,
+    storage_service: web::Data<StorageService>,
+) -> Result<HttpResponse, Error> {
+    let params = query.into_inner();
+    let (source, cursor, expected) =
+        storage_service
+            .export_data(&params)
+            .await
+            .map_err(|e| match e {
+                CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+                _ => {
+                    tracing::error!("Failed to start export: {}", e);
+                    actix_web::error::ErrorInternalServerError(e)
+                }
+            })?;
+
+    let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE).min(10_000);
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(("X-Total-Count", expected.to_string()))
+        .streaming(ndjson_stream(source, cursor, expected, page_size)))
+}
+
 #[post("/relationships")]
 async fn create_relationship(
     data: web::Json<StoreRelationshipRequest>,
@@ -154,3 +297,78 @@ async fn get_relationships(
 
     Ok(HttpResponse::Ok().json(relationships))
 }
+
+#[get("/{id}/provenance")]
+async fn get_provenance(
+    id: web::Path<String>,
+    storage_service: web::Data<StorageService>,
+) -> Result<HttpResponse, Error> {
+    let id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid artifact ID"))?;
+
+    let provenance = storage_service.get_provenance(&id).await.map_err(|e| match e {
+        CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+        _ => {
+            tracing::error!("Failed to get provenance: {}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        }
+    })?;
+
+    Ok(HttpResponse::Ok().json(provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn status(error: Error) -> StatusCode {
+        error.as_response_error().status_code()
+    }
+
+    #[test]
+    fn test_missing_if_match_is_precondition_required() {
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(
+            status(expected_version(&req).unwrap_err()),
+            StatusCode::PRECONDITION_REQUIRED
+        );
+    }
+
+    #[test]
+    fn test_if_match_gives_the_expected_version() {
+        for value in ["\"3\"", "W/\"3\"", "3"] {
+            let req = TestRequest::default()
+                .insert_header((header::IF_MATCH, value))
+                .to_http_request();
+            assert_eq!(expected_version(&req).unwrap(), 3);
+        }
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_MATCH, "\"abc\""))
+            .to_http_request();
+        assert_eq!(
+            status(expected_version(&req).unwrap_err()),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_version_conflict_is_409() {
+        let conflict = CommonError::Conflict("Entity is at version 4, not 3".into());
+        assert_eq!(
+            status(write_error(conflict, "update")),
+            StatusCode::CONFLICT
+        );
+
+        assert_eq!(
+            status(write_error(CommonError::NotFound("gone".into()), "delete")),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(write_error(CommonError::Internal("down".into()), "restore")),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -1,20 +1,25 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
+use mirage_middleware::Authentication;
 use tracing::info;
 
+mod audit;
 mod config;
+mod export;
 mod handlers;
 mod models;
 mod repositories;
 mod services;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("data-storage-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -63,17 +68,60 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("data-storage-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone())
+            .probe("mongodb", {
+                let db = mongo_client.clone();
+                move || repositories::ping_mongo(db.clone())
+            }),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("data-storage-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    // Verifies the caller of every data route; changes are audited under
+    // their user ID
+    let auth = match Authentication::from_env() {
+        Ok(auth) => auth,
+        Err(e) => {
+            tracing::error!("Failed to set up authentication: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up authentication",
+            ));
+        }
+    };
+
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(web::Data::new(db_pool.clone()))
             .app_data(web::Data::new(mongo_client.clone()))
             .app_data(storage_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
-                    .service(handlers::storage_routes()),
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
+                    .service(handlers::storage_routes().wrap(auth.clone()))
+                    .service(handlers::artifact_routes().wrap(auth.clone())),
             )
     })
     .bind(format!("0.0.0.0:{}", config.server.port))?
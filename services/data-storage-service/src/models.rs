@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use mirage_common::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -14,18 +15,163 @@ pub struct DataEntity {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub metadata: HashMap<String, String>,
+    /// Incremented on every update; entities stored before versioning are 0
+    #[serde(default)]
+    pub version: i64,
+    /// Set when the entity is soft-deleted; hidden from reads until restored
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl DataEntity {
+    /// Replaces the data if the caller saw the current version, bumping it
+    pub fn apply_update(&mut self, data: serde_json::Value, expected_version: i64) -> Result<()> {
+        if self.version != expected_version {
+            return Err(Error::Conflict(format!(
+                "Entity {} is at version {}, not {}",
+                self.id, self.version, expected_version
+            )));
+        }
+
+        self.data = data;
+        self.version += 1;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Whether the entity should be returned to a reader
+    pub fn is_visible(&self, include_deleted: bool) -> bool {
+        include_deleted || self.deleted_at.is_none()
+    }
+
+    /// Marks the entity deleted at `now`, bumping the version
+    pub fn soft_delete(&mut self, now: DateTime<Utc>) -> Result<()> {
+        if self.deleted_at.is_some() {
+            return Err(Error::NotFound(format!(
+                "Entity with ID {} not found",
+                self.id
+            )));
+        }
+
+        self.deleted_at = Some(now);
+        self.version += 1;
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Clears a soft delete, bumping the version
+    pub fn restore(&mut self, now: DateTime<Utc>) -> Result<()> {
+        if self.deleted_at.is_none() {
+            return Err(Error::Conflict(format!(
+                "Entity {} is not deleted",
+                self.id
+            )));
+        }
+
+        self.deleted_at = None;
+        self.version += 1;
+        self.updated_at = now;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoreDataRequest {
     pub source_module: Uuid,
     pub scan_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
     pub entity_type: String,
     pub value: String,
     pub data: serde_json::Value,
     pub metadata: Option<HashMap<String, String>>,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvenanceKind {
+    /// The module created the artifact
+    Produced,
+    /// The module observed an artifact that already existed
+    Confirmed,
+}
+
+/// One module/task observation of an artifact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvenanceRecord {
+    pub entity_id: Uuid,
+    pub module_id: Uuid,
+    pub task_id: Option<Uuid>,
+    pub scan_id: Option<Uuid>,
+    pub kind: ProvenanceKind,
+    pub observed_at: DateTime<Utc>,
+    /// What a confirming module reported. The entity keeps the data of the
+    /// module that produced it, so later payloads are kept here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+impl ProvenanceRecord {
+    /// Records `req` confirming the already stored artifact `entity_id`
+    pub fn confirmation(
+        entity_id: Uuid,
+        req: StoreDataRequest,
+        observed_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            entity_id,
+            module_id: req.source_module,
+            task_id: req.task_id,
+            scan_id: req.scan_id,
+            kind: ProvenanceKind::Confirmed,
+            observed_at,
+            data: Some(req.data),
+            metadata: req.metadata,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvenanceResponse {
+    pub entity_id: Uuid,
+    pub modules: Vec<Uuid>,
+    pub scans: Vec<Uuid>,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub records: Vec<ProvenanceRecord>,
+}
+
+impl ProvenanceResponse {
+    /// Orders the records chronologically and collects the distinct modules
+    /// and scans in the order they first touched the artifact.
+    pub fn from_records(entity_id: Uuid, mut records: Vec<ProvenanceRecord>) -> Self {
+        records.sort_by_key(|r| r.observed_at);
+
+        let mut modules = Vec::new();
+        let mut scans = Vec::new();
+        for record in &records {
+            if !modules.contains(&record.module_id) {
+                modules.push(record.module_id);
+            }
+            if let Some(scan_id) = record.scan_id {
+                if !scans.contains(&scan_id) {
+                    scans.push(scan_id);
+                }
+            }
+        }
+
+        Self {
+            entity_id,
+            modules,
+            scans,
+            first_seen: records.first().map(|r| r.observed_at),
+            last_seen: records.last().map(|r| r.observed_at),
+            records,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryParams {
     pub entity_type: Option<String>,
@@ -36,6 +182,51 @@ pub struct QueryParams {
     pub to_date: Option<DateTime<Utc>>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// Also return soft-deleted entities
+    pub include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportParams {
+    pub entity_type: Option<String>,
+    pub value: Option<String>,
+    pub source_module: Option<Uuid>,
+    pub scan_id: Option<Uuid>,
+    pub from_date: Option<DateTime<Utc>>,
+    pub to_date: Option<DateTime<Utc>>,
+    /// `next_cursor` from the summary of an interrupted export
+    pub cursor: Option<String>,
+    pub page_size: Option<u64>,
+}
+
+impl ExportParams {
+    pub fn query(&self) -> QueryParams {
+        QueryParams {
+            entity_type: self.entity_type.clone(),
+            value: self.value.clone(),
+            source_module: self.source_module,
+            scan_id: self.scan_id,
+            from_date: self.from_date,
+            to_date: self.to_date,
+            limit: None,
+            offset: None,
+            include_deleted: None,
+        }
+    }
+}
+
+/// One create/update/delete of a stored entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLog {
+    pub id: Uuid,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub user_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub details: serde_json::Value,
+    pub change_summary: Option<String>,
+    pub service: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -214,3 +405,180 @@ pub struct ImportResult {
     pub relationships_skipped: usize,
     pub errors: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn record(
+        entity_id: Uuid,
+        module_id: Uuid,
+        scan_id: Uuid,
+        kind: ProvenanceKind,
+        observed_at: DateTime<Utc>,
+    ) -> ProvenanceRecord {
+        ProvenanceRecord {
+            entity_id,
+            module_id,
+            task_id: Some(Uuid::new_v4()),
+            scan_id: Some(scan_id),
+            kind,
+            observed_at,
+            data: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_confirmation_keeps_the_second_module_payload() {
+        let entity_id = Uuid::new_v4();
+        let web_module = Uuid::new_v4();
+        let req = StoreDataRequest {
+            source_module: web_module,
+            scan_id: None,
+            task_id: None,
+            entity_type: "domain".to_string(),
+            value: "example.com".to_string(),
+            data: serde_json::json!({ "title": "Example Domain" }),
+            metadata: Some(HashMap::from([("status".to_string(), "200".to_string())])),
+        };
+
+        let record = ProvenanceRecord::confirmation(entity_id, req, Utc::now());
+        let stored: ProvenanceRecord =
+            serde_json::from_value(serde_json::to_value(&record).unwrap()).unwrap();
+
+        assert_eq!(stored.module_id, web_module);
+        assert_eq!(stored.kind, ProvenanceKind::Confirmed);
+        assert_eq!(
+            stored.data,
+            Some(serde_json::json!({ "title": "Example Domain" }))
+        );
+        assert_eq!(stored.metadata.unwrap()["status"], "200");
+    }
+
+    #[test]
+    fn test_provenance_includes_every_module_in_order() {
+        let entity_id = Uuid::new_v4();
+        let dns_module = Uuid::new_v4();
+        let web_module = Uuid::new_v4();
+        let scan_id = Uuid::new_v4();
+        let now = Utc::now();
+
+        // Stored out of order on purpose
+        let records = vec![
+            record(entity_id, web_module, scan_id, ProvenanceKind::Confirmed, now),
+            record(
+                entity_id,
+                dns_module,
+                scan_id,
+                ProvenanceKind::Produced,
+                now - Duration::minutes(5),
+            ),
+        ];
+
+        let provenance = ProvenanceResponse::from_records(entity_id, records);
+
+        assert_eq!(provenance.modules, vec![dns_module, web_module]);
+        assert_eq!(provenance.scans, vec![scan_id]);
+        assert_eq!(provenance.records.len(), 2);
+        assert_eq!(provenance.records[0].kind, ProvenanceKind::Produced);
+        assert_eq!(provenance.records[1].kind, ProvenanceKind::Confirmed);
+        assert_eq!(provenance.first_seen, Some(now - Duration::minutes(5)));
+        assert_eq!(provenance.last_seen, Some(now));
+    }
+
+    fn entity(version: i64) -> DataEntity {
+        DataEntity {
+            id: Uuid::new_v4(),
+            source_module: Uuid::new_v4(),
+            scan_id: None,
+            entity_type: "domain".to_string(),
+            value: "example.com".to_string(),
+            data: serde_json::json!({ "a": 1 }),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            metadata: HashMap::new(),
+            version,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_update_with_current_version_bumps_it() {
+        let mut entity = entity(3);
+        entity
+            .apply_update(serde_json::json!({ "a": 2 }), 3)
+            .unwrap();
+
+        assert_eq!(entity.version, 4);
+        assert_eq!(entity.data, serde_json::json!({ "a": 2 }));
+    }
+
+    #[test]
+    fn test_update_with_stale_version_conflicts() {
+        let mut entity = entity(4);
+        let result = entity.apply_update(serde_json::json!({ "a": 2 }), 3);
+
+        assert!(matches!(result, Err(Error::Conflict(_))));
+        assert_eq!(entity.version, 4);
+        assert_eq!(entity.data, serde_json::json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn test_legacy_entity_defaults_to_version_zero() {
+        let mut json = serde_json::to_value(entity(7)).unwrap();
+        json.as_object_mut().unwrap().remove("version");
+
+        let legacy: DataEntity = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.version, 0);
+    }
+
+    #[test]
+    fn test_soft_deleted_entity_is_hidden() {
+        let mut entity = entity(2);
+        assert!(entity.is_visible(false));
+
+        entity.soft_delete(Utc::now()).unwrap();
+
+        assert!(!entity.is_visible(false));
+        assert_eq!(entity.version, 3);
+        // Deleting twice looks like deleting a missing entity
+        assert!(matches!(
+            entity.soft_delete(Utc::now()),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_include_deleted_surfaces_soft_deleted_entity() {
+        let mut entity = entity(1);
+        entity.soft_delete(Utc::now()).unwrap();
+
+        assert!(entity.is_visible(true));
+        assert!(entity.deleted_at.is_some());
+    }
+
+    #[test]
+    fn test_restore_clears_soft_delete() {
+        let mut entity = entity(1);
+        assert!(matches!(
+            entity.restore(Utc::now()),
+            Err(Error::Conflict(_))
+        ));
+
+        entity.soft_delete(Utc::now()).unwrap();
+        entity.restore(Utc::now()).unwrap();
+
+        assert!(entity.is_visible(false));
+        assert_eq!(entity.deleted_at, None);
+        assert_eq!(entity.version, 3);
+    }
+
+    #[test]
+    fn test_provenance_empty() {
+        let provenance = ProvenanceResponse::from_records(Uuid::new_v4(), Vec::new());
+        assert!(provenance.modules.is_empty());
+        assert!(provenance.first_seen.is_none());
+    }
+}
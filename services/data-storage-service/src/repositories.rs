@@ -1,15 +1,15 @@
 use crate::config::{DatabaseConfig, ElasticsearchConfig, MongoDBConfig};
-use crate::models::{DataEntity, QueryParams, Relationship};
+use crate::models::{AuditLog, DataEntity, ProvenanceRecord, QueryParams, Relationship};
 use chrono::Utc;
-use elasticsearch::{http::transport::Transport, Elasticsearch, SearchParts};
+use elasticsearch::{http::transport::Transport, CountParts, Elasticsearch, SearchParts};
 use futures::TryStreamExt;
-use mirage_common::{Error, Result};
+use mirage_common::{database, Error, Result};
 use mongodb::{
     bson::{doc, to_document, Document},
     options::{ClientOptions, FindOptions},
     Client as MongoClient, Database,
 };
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::{Pool, Postgres};
 use std::ops::Range;
 use uuid::Uuid;
 
@@ -17,21 +17,22 @@ pub type DbPool = Pool<Postgres>;
 
 /// Create PostgreSQL database connection pool
 pub async fn create_db_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await
-        .map_err(|e| Error::Database(format!("Database connection failed: {}", e)))?;
+    let pool = database::create_pool(config).await?;
 
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .map_err(|e| Error::Database(format!("Migration failed: {}", e)))?;
+    run_migrations(&pool).await?;
 
     Ok(pool)
 }
 
+/// Applies the migrations in `migrations/` that haven't run yet. They are
+/// embedded at build time, so a deployment needs no files besides the binary.
+pub async fn run_migrations(pool: &DbPool) -> Result<()> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .map_err(|e| Error::Database(format!("Migration failed: {}", e)))
+}
+
 /// Create MongoDB client
 pub async fn create_mongo_client(config: &MongoDBConfig) -> Result<Database> {
     let mut client_options = ClientOptions::parse(&config.uri)
@@ -53,6 +54,14 @@ pub async fn create_mongo_client(config: &MongoDBConfig) -> Result<Database> {
     Ok(client.database(&config.db_name))
 }
 
+/// Readiness probe: pings the MongoDB server
+pub async fn ping_mongo(db: Database) -> Result<()> {
+    db.run_command(doc! {"ping": 1}, None)
+        .await
+        .map_err(|e| Error::Database(format!("MongoDB ping failed: {}", e)))?;
+    Ok(())
+}
+
 /// Create Elasticsearch client
 pub fn create_elasticsearch_client(config: &ElasticsearchConfig) -> Result<Elasticsearch> {
     let transport = Transport::single_node(&config.url)
@@ -142,7 +151,34 @@ impl DataRepository {
         Ok(entity)
     }
 
-    pub async fn update_entity(&self, entity: &DataEntity) -> Result<()> {
+    /// Writes the entity if the stored copy is still at `expected_version`.
+    /// Returns `false` without writing anything when it is not.
+    pub async fn update_entity(&self, entity: &DataEntity, expected_version: i64) -> Result<bool> {
+        // MongoDB holds the full entity, so the version check happens there
+        let collection = self.mongo_db.collection::<Document>("entities");
+        let entity_doc = mongodb::bson::to_document(entity)
+            .map_err(|e| Error::Internal(format!("Failed to serialize entity: {}", e)))?;
+
+        // Entities stored before versioning have no version field
+        let version_filter = if expected_version == 0 {
+            doc! { "$in": [0_i64, mongodb::bson::Bson::Null] }
+        } else {
+            doc! { "$eq": expected_version }
+        };
+
+        let result = collection
+            .replace_one(
+                doc! {"id": entity.id.to_string(), "version": version_filter},
+                entity_doc,
+                None,
+            )
+            .await
+            .map_err(|e| Error::Database(format!("Failed to update entity in MongoDB: {}", e)))?;
+
+        if result.matched_count == 0 {
+            return Ok(false);
+        }
+
         // Update metadata in PostgreSQL
         sqlx::query!(
             r#"
@@ -160,16 +196,6 @@ impl DataRepository {
         .await
         .map_err(|e| Error::Database(format!("Failed to update entity in SQL: {}", e)))?;
 
-        // Update full data in MongoDB
-        let collection = self.mongo_db.collection::<Document>("entities");
-        let entity_doc = mongodb::bson::to_document(entity)
-            .map_err(|e| Error::Internal(format!("Failed to serialize entity: {}", e)))?;
-
-        collection
-            .replace_one(doc! {"id": entity.id.to_string()}, entity_doc, None)
-            .await
-            .map_err(|e| Error::Database(format!("Failed to update entity in MongoDB: {}", e)))?;
-
         // Update Elasticsearch index
         let es_index = format!("{}_entities", self.es_index_prefix);
         let es_doc = serde_json::to_value(entity)
@@ -187,42 +213,55 @@ impl DataRepository {
                 Error::Database(format!("Failed to index entity in Elasticsearch: {}", e))
             })?;
 
-        Ok(())
+        Ok(true)
     }
 
-    pub async fn delete_entity(&self, id: &Uuid) -> Result<bool> {
-        // Delete from PostgreSQL
-        let result = sqlx::query!(
-            r#"
-            DELETE FROM entities WHERE id = $1
-            "#,
-            id
-        )
-        .execute(&self.db_pool)
-        .await
-        .map_err(|e| Error::Database(format!("Failed to delete entity from SQL: {}", e)))?;
+    pub async fn find_entity_by_value(
+        &self,
+        entity_type: &str,
+        value: &str,
+    ) -> Result<Option<DataEntity>> {
+        let collection = self.mongo_db.collection::<DataEntity>("entities");
 
-        // Delete from MongoDB
-        let collection = self.mongo_db.collection::<Document>("entities");
         collection
-            .delete_one(doc! {"id": id.to_string()}, None)
+            .find_one(Self::value_filter(entity_type, value), None)
             .await
-            .map_err(|e| Error::Database(format!("Failed to delete entity from MongoDB: {}", e)))?;
+            .map_err(|e| Error::Database(format!("Failed to find entity: {}", e)))
+    }
 
-        // Delete from Elasticsearch
-        let es_index = format!("{}_entities", self.es_index_prefix);
-        self.es_client
-            .delete(elasticsearch::DeleteParts::index_id(
-                &es_index,
-                &id.to_string(),
-            ))
-            .send()
+    // A soft-deleted entity doesn't count as already stored; a new finding
+    // of the same value gets an entity of its own
+    fn value_filter(entity_type: &str, value: &str) -> Document {
+        doc! {"entity_type": entity_type, "value": value, "deleted_at": null}
+    }
+
+    // Provenance methods
+    pub async fn record_provenance(&self, record: &ProvenanceRecord) -> Result<()> {
+        let collection = self.mongo_db.collection::<ProvenanceRecord>("provenance");
+
+        collection
+            .insert_one(record, None)
             .await
-            .map_err(|e| {
-                Error::Database(format!("Failed to delete entity from Elasticsearch: {}", e))
-            })?;
+            .map_err(|e| Error::Database(format!("Failed to record provenance: {}", e)))?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(())
+    }
+
+    pub async fn get_provenance(&self, entity_id: &Uuid) -> Result<Vec<ProvenanceRecord>> {
+        let collection = self.mongo_db.collection::<ProvenanceRecord>("provenance");
+        let options = FindOptions::builder()
+            .sort(doc! {"observed_at": 1})
+            .build();
+
+        let cursor = collection
+            .find(doc! {"entity_id": entity_id.to_string()}, options)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to query provenance: {}", e)))?;
+
+        cursor
+            .try_collect()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read provenance: {}", e)))
     }
 
     pub async fn query_entities(&self, params: &QueryParams) -> Result<Vec<DataEntity>> {
@@ -230,12 +269,8 @@ impl DataRepository {
         let es_index = format!("{}_entities", self.es_index_prefix);
 
         // Build Elasticsearch query
-        let mut query = serde_json::json!({
-            "query": {
-                "bool": {
-                    "must": []
-                }
-            },
+        let query = serde_json::json!({
+            "query": Self::entity_filter(params),
             "size": params.limit.unwrap_or(100),
             "from": params.offset.unwrap_or(0),
             "sort": [
@@ -243,7 +278,61 @@ impl DataRepository {
             ]
         });
 
-        let must = query["query"]["bool"]["must"].as_array_mut().unwrap();
+        // Execute search
+        let response = self
+            .es_client
+            .search(SearchParts::index(&[&es_index]))
+            .body(query)
+            .send()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to search entities: {}", e)))?;
+
+        // Parse response
+        let response_body = response
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to parse search response: {}", e)))?;
+
+        // Extract hits and convert to DataEntity objects
+        let hits = response_body["hits"]["hits"]
+            .as_array()
+            .ok_or_else(|| Error::Internal("Invalid search response format".to_string()))?;
+
+        let mut entities = Vec::new();
+        for hit in hits {
+            let source = hit["_source"].clone();
+            let entity: DataEntity = serde_json::from_value(source)
+                .map_err(|e| Error::Internal(format!("Failed to deserialize entity: {}", e)))?;
+            entities.push(entity);
+        }
+
+        Ok(entities)
+    }
+
+    /// Number of entities matching the query, ignoring `limit`/`offset`
+    pub async fn count_entities(&self, params: &QueryParams) -> Result<u64> {
+        let es_index = format!("{}_entities", self.es_index_prefix);
+
+        let response = self
+            .es_client
+            .count(CountParts::Index(&[&es_index]))
+            .body(serde_json::json!({ "query": Self::entity_filter(params) }))
+            .send()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to count entities: {}", e)))?;
+
+        let response_body = response
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to parse count response: {}", e)))?;
+
+        response_body["count"]
+            .as_u64()
+            .ok_or_else(|| Error::Internal("Invalid count response format".to_string()))
+    }
+
+    fn entity_filter(params: &QueryParams) -> serde_json::Value {
+        let mut must = Vec::new();
 
         // Add query conditions based on params
         if let Some(ref entity_type) = params.entity_type {
@@ -291,35 +380,16 @@ impl DataRepository {
             must.push(range);
         }
 
-        // Execute search
-        let response = self
-            .es_client
-            .search(SearchParts::index(&[&es_index]))
-            .body(query)
-            .send()
-            .await
-            .map_err(|e| Error::Database(format!("Failed to search entities: {}", e)))?;
-
-        // Parse response
-        let response_body = response
-            .json::<serde_json::Value>()
-            .await
-            .map_err(|e| Error::Database(format!("Failed to parse search response: {}", e)))?;
-
-        // Extract hits and convert to DataEntity objects
-        let hits = response_body["hits"]["hits"]
-            .as_array()
-            .ok_or_else(|| Error::Internal("Invalid search response format".to_string()))?;
-
-        let mut entities = Vec::new();
-        for hit in hits {
-            let source = hit["_source"].clone();
-            let entity: DataEntity = serde_json::from_value(source)
-                .map_err(|e| Error::Internal(format!("Failed to deserialize entity: {}", e)))?;
-            entities.push(entity);
+        // Soft-deleted entities keep a `deleted_at` timestamp; live ones
+        // have it null, which Elasticsearch treats as missing
+        let mut must_not = Vec::new();
+        if !params.include_deleted.unwrap_or(false) {
+            must_not.push(serde_json::json!({
+                "exists": { "field": "deleted_at" }
+            }));
         }
 
-        Ok(entities)
+        serde_json::json!({ "bool": { "must": must, "must_not": must_not } })
     }
 
     // Relationship methods
@@ -475,6 +545,47 @@ impl DataRepository {
     }
 }
 
+#[derive(Clone)]
+pub struct AuditRepository {
+    mongo_db: Database,
+}
+
+impl AuditRepository {
+    pub fn new(mongo_db: Database) -> Self {
+        Self { mongo_db }
+    }
+
+    pub async fn create_audit_log(&self, log: &AuditLog) -> Result<()> {
+        let collection = self.mongo_db.collection::<AuditLog>("audit_logs");
+
+        collection
+            .insert_one(log, None)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to create audit log: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Most recent entries first
+    pub async fn get_logs_for_entity(&self, entity_id: &Uuid, limit: i64) -> Result<Vec<AuditLog>> {
+        let collection = self.mongo_db.collection::<AuditLog>("audit_logs");
+        let options = FindOptions::builder()
+            .sort(doc! {"timestamp": -1})
+            .limit(limit)
+            .build();
+
+        let cursor = collection
+            .find(doc! {"entity_id": entity_id.to_string()}, options)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to fetch audit logs: {}", e)))?;
+
+        cursor
+            .try_collect()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to read audit logs: {}", e)))
+    }
+}
+
 // Internal struct for SQL query results
 struct RelationshipRecord {
     id: Uuid,
@@ -484,3 +595,70 @@ struct RelationshipRecord {
     created_at: chrono::DateTime<Utc>,
     updated_at: chrono::DateTime<Utc>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(include_deleted: Option<bool>) -> QueryParams {
+        QueryParams {
+            entity_type: Some("domain".to_string()),
+            value: None,
+            source_module: None,
+            scan_id: None,
+            from_date: None,
+            to_date: None,
+            limit: None,
+            offset: None,
+            include_deleted,
+        }
+    }
+
+    #[test]
+    fn test_filter_hides_soft_deleted_entities_by_default() {
+        let filter = DataRepository::entity_filter(&params(None));
+
+        assert_eq!(
+            filter["bool"]["must_not"],
+            serde_json::json!([{ "exists": { "field": "deleted_at" } }])
+        );
+    }
+
+    #[test]
+    fn test_filter_includes_deleted_entities_on_request() {
+        let filter = DataRepository::entity_filter(&params(Some(true)));
+
+        assert_eq!(filter["bool"]["must_not"], serde_json::json!([]));
+        assert_eq!(filter["bool"]["must"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_value_lookup_skips_soft_deleted_entities() {
+        let filter = DataRepository::value_filter("domain", "example.com");
+
+        assert_eq!(filter.get_str("value").unwrap(), "example.com");
+        // Matches entities without the field as well as ones where it is null
+        assert_eq!(filter.get("deleted_at"), Some(&mongodb::bson::Bson::Null));
+    }
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_migrations_create_tables(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT table_name::text FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        for table in ["entities", "relationships"] {
+            assert!(tables.iter().any(|t| t == table), "missing {}", table);
+        }
+
+        // Already applied migrations are skipped on the next start
+        run_migrations(&pool).await.unwrap();
+    }
+}
@@ -1,7 +1,11 @@
+use crate::audit::AuditService;
+use crate::export::{ExportCursor, ExportSource};
 use crate::models::{
-    DataEntity, QueryParams, Relationship, StoreDataRequest, StoreRelationshipRequest,
+    AuditLog, DataEntity, ExportParams, ProvenanceKind, ProvenanceRecord, ProvenanceResponse,
+    QueryParams, Relationship, StoreDataRequest, StoreRelationshipRequest,
 };
-use crate::repositories::{DataRepository, DbPool};
+use crate::repositories::{AuditRepository, DataRepository, DbPool};
+use async_trait::async_trait;
 use chrono::Utc;
 use elasticsearch::Elasticsearch;
 use mirage_common::{Error, Result};
@@ -12,6 +16,7 @@ use uuid::Uuid;
 #[derive(Clone)]
 pub struct StorageService {
     repo: Arc<DataRepository>,
+    audit_service: Arc<AuditService>,
     es_index_prefix: String,
 }
 
@@ -23,6 +28,7 @@ impl StorageService {
         es_index_prefix: String,
     ) -> Self {
         Self {
+            audit_service: Arc::new(AuditService::new(AuditRepository::new(mongo_db.clone()))),
             repo: Arc::new(DataRepository::new(
                 db_pool,
                 mongo_db,
@@ -33,7 +39,8 @@ impl StorageService {
         }
     }
 
-    pub async fn store_data(&self, req: StoreDataRequest) -> Result<Uuid> {
+    /// Stores a finding on behalf of `user_id`, the authenticated caller if any
+    pub async fn store_data(&self, req: StoreDataRequest, user_id: Option<&str>) -> Result<Uuid> {
         // Validate request
         if req.value.is_empty() {
             return Err(Error::Validation(
@@ -45,6 +52,25 @@ impl StorageService {
             return Err(Error::Validation("Entity type cannot be empty".to_string()));
         }
 
+        // An artifact already stored by another module is confirmed rather than duplicated
+        if let Some(existing) = self
+            .repo
+            .find_entity_by_value(&req.entity_type, &req.value)
+            .await?
+        {
+            // The entity keeps the first module's data; this module's payload
+            // goes on its provenance record
+            self.repo
+                .record_provenance(&ProvenanceRecord::confirmation(
+                    existing.id,
+                    req,
+                    Utc::now(),
+                ))
+                .await?;
+
+            return Ok(existing.id);
+        }
+
         // Create DataEntity
         let entity = DataEntity {
             id: Uuid::new_v4(),
@@ -56,43 +82,143 @@ impl StorageService {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             metadata: req.metadata.unwrap_or_default(),
+            version: 1,
+            deleted_at: None,
         };
 
-        self.repo.store_entity(&entity).await
+        let entity_id = self.repo.store_entity(&entity).await?;
+
+        self.audit_service
+            .log_create(
+                &entity.entity_type,
+                &entity_id,
+                user_id,
+                &serde_json::to_value(&entity).unwrap_or_default(),
+            )
+            .await?;
+
+        self.repo
+            .record_provenance(&ProvenanceRecord {
+                entity_id,
+                module_id: entity.source_module,
+                task_id: req.task_id,
+                scan_id: entity.scan_id,
+                kind: ProvenanceKind::Produced,
+                observed_at: entity.created_at,
+                data: None,
+                metadata: None,
+            })
+            .await?;
+
+        Ok(entity_id)
     }
 
-    pub async fn get_data(&self, id: &Uuid) -> Result<DataEntity> {
-        let entity = self
-            .repo
+    pub async fn get_provenance(&self, id: &Uuid) -> Result<ProvenanceResponse> {
+        // Make sure the artifact exists before reporting on it
+        self.get_data(id, false).await?;
+
+        let records = self.repo.get_provenance(id).await?;
+        Ok(ProvenanceResponse::from_records(*id, records))
+    }
+
+    /// Fetches an entity; soft-deleted ones are only returned when
+    /// `include_deleted` is set
+    pub async fn get_data(&self, id: &Uuid, include_deleted: bool) -> Result<DataEntity> {
+        self.repo
             .get_entity(id)
             .await?
-            .ok_or_else(|| Error::NotFound(format!("Entity with ID {} not found", id)))?;
+            .filter(|entity| entity.is_visible(include_deleted))
+            .ok_or_else(|| Error::NotFound(format!("Entity with ID {} not found", id)))
+    }
+
+    /// Updates the entity's data if it is still at `expected_version` and
+    /// returns the updated entity
+    pub async fn update_data(
+        &self,
+        id: &Uuid,
+        data: serde_json::Value,
+        expected_version: i64,
+        user_id: Option<&str>,
+    ) -> Result<DataEntity> {
+        // Get existing entity
+        let mut entity = self.get_data(id, false).await?;
+        let old_data = entity.data.clone();
+
+        entity.apply_update(data, expected_version)?;
+        self.write_entity(&entity, expected_version).await?;
+
+        self.audit_service
+            .log_update(
+                &entity.entity_type,
+                id,
+                user_id,
+                &serde_json::json!({ "old": old_data, "new": entity.data }),
+                Some(format!(
+                    "Version {} -> {}",
+                    expected_version, entity.version
+                )),
+            )
+            .await?;
 
         Ok(entity)
     }
 
-    pub async fn update_data(&self, id: &Uuid, data: serde_json::Value) -> Result<()> {
-        // Get existing entity
-        let mut entity = self
-            .repo
-            .get_entity(id)
-            .await?
-            .ok_or_else(|| Error::NotFound(format!("Entity with ID {} not found", id)))?;
+    /// Soft-deletes the entity; it stays stored and can be restored
+    pub async fn delete_data(&self, id: &Uuid, user_id: Option<&str>) -> Result<()> {
+        let mut entity = self.get_data(id, false).await?;
+        let expected_version = entity.version;
+
+        entity.soft_delete(Utc::now())?;
+        self.write_entity(&entity, expected_version).await?;
+
+        self.audit_service
+            .log_delete(
+                &entity.entity_type,
+                id,
+                user_id,
+                &serde_json::json!({ "deleted_at": entity.deleted_at }),
+            )
+            .await
+    }
+
+    /// Brings a soft-deleted entity back and returns it
+    pub async fn restore_data(&self, id: &Uuid, user_id: Option<&str>) -> Result<DataEntity> {
+        let mut entity = self.get_data(id, true).await?;
+        let expected_version = entity.version;
+
+        entity.restore(Utc::now())?;
+        self.write_entity(&entity, expected_version).await?;
 
-        // Update data and timestamp
-        entity.data = data;
-        entity.updated_at = Utc::now();
+        self.audit_service
+            .log_restore(
+                &entity.entity_type,
+                id,
+                user_id,
+                &serde_json::json!({ "version": entity.version }),
+            )
+            .await?;
 
-        self.repo.update_entity(&entity).await
+        Ok(entity)
     }
 
-    pub async fn delete_data(&self, id: &Uuid) -> Result<()> {
-        let deleted = self.repo.delete_entity(id).await?;
+    pub async fn get_audit_log(&self, id: &Uuid, limit: i64) -> Result<Vec<AuditLog>> {
+        // Deleted entities keep their history
+        self.get_data(id, true).await?;
+
+        self.audit_service
+            .get_audit_logs_for_entity(id, limit)
+            .await
+    }
 
-        if deleted {
+    // The write is conditional too, in case another writer got in first
+    async fn write_entity(&self, entity: &DataEntity, expected_version: i64) -> Result<()> {
+        if self.repo.update_entity(entity, expected_version).await? {
             Ok(())
         } else {
-            Err(Error::NotFound(format!("Entity with ID {} not found", id)))
+            Err(Error::Conflict(format!(
+                "Entity {} was modified concurrently",
+                entity.id
+            )))
         }
     }
 
@@ -100,6 +226,27 @@ impl StorageService {
         self.repo.query_entities(&params).await
     }
 
+    /// Prepares a streaming export, returning the source, its starting cursor
+    /// and the number of entities expected from that cursor onwards
+    pub async fn export_data(
+        &self,
+        params: &ExportParams,
+    ) -> Result<(EntityExport, ExportCursor, u64)> {
+        let cursor = match &params.cursor {
+            Some(cursor) => ExportCursor::parse(cursor)?,
+            None => ExportCursor::default(),
+        };
+
+        let export = EntityExport {
+            repo: Arc::clone(&self.repo),
+            params: params.query(),
+        };
+        let total = export.count().await?;
+        let expected = total.saturating_sub(cursor.offset());
+
+        Ok((export, cursor, expected))
+    }
+
     pub async fn create_relationship(&self, req: StoreRelationshipRequest) -> Result<Uuid> {
         // Validate request
         if req.relationship_type.is_empty() {
@@ -134,3 +281,26 @@ impl StorageService {
         Ok(relationships)
     }
 }
+
+/// Entities matching an export's filters, paged from Elasticsearch
+pub struct EntityExport {
+    repo: Arc<DataRepository>,
+    params: QueryParams,
+}
+
+#[async_trait]
+impl ExportSource for EntityExport {
+    async fn count(&self) -> Result<u64> {
+        self.repo.count_entities(&self.params).await
+    }
+
+    async fn page(&self, offset: u64, limit: u64) -> Result<Vec<DataEntity>> {
+        let params = QueryParams {
+            limit: Some(limit as i64),
+            offset: Some(offset as i64),
+            ..self.params.clone()
+        };
+
+        self.repo.query_entities(&params).await
+    }
+}
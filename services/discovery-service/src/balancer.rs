@@ -0,0 +1,193 @@
+//! Picking one instance of a service for a caller
+//!
+//! Only routable instances are candidates. They are ordered by ID, since
+//! Redis hands set members back in no particular order, so a round-robin
+//! cursor walks the same cycle from one call to the next. The cursors and
+//! last-use marks live in memory for this discovery instance.
+
+use crate::models::{SelectionStrategy, ServiceInstance};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct SelectionState {
+    // Next round-robin position, per service
+    cursors: HashMap<String, usize>,
+    // Selection sequence number each instance was last handed out at, per
+    // service
+    last_used: HashMap<String, HashMap<String, u64>>,
+    selections: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct LoadBalancer {
+    state: Arc<Mutex<SelectionState>>,
+}
+
+impl LoadBalancer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Pick one of `instances` of `service_name`, skipping any that are not
+    // routable. None if no instance can take traffic.
+    pub fn select(
+        &self,
+        service_name: &str,
+        strategy: SelectionStrategy,
+        instances: Vec<ServiceInstance>,
+    ) -> Option<ServiceInstance> {
+        let mut candidates: Vec<ServiceInstance> = instances
+            .into_iter()
+            .filter(ServiceInstance::is_routable)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        candidates.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut state = self.state.lock().unwrap();
+        let index = match strategy {
+            SelectionStrategy::RoundRobin => {
+                let cursor = state.cursors.entry(service_name.to_string()).or_insert(0);
+                let index = *cursor % candidates.len();
+                *cursor = index + 1;
+                index
+            }
+            SelectionStrategy::LeastRecentlyUsed => {
+                let last_used = state.last_used.entry(service_name.to_string()).or_default();
+                // Forget instances that have gone away
+                last_used.retain(|id, _| candidates.iter().any(|c| &c.id == id));
+
+                // Never-used instances come first, then the longest idle
+                candidates
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, c)| last_used.get(&c.id).copied().unwrap_or(0))
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            }
+            SelectionStrategy::Random => rand::thread_rng().gen_range(0..candidates.len()),
+        };
+
+        // Every selection counts as a use, whichever strategy made it
+        state.selections += 1;
+        let selection = state.selections;
+        let chosen = candidates.swap_remove(index);
+        state
+            .last_used
+            .entry(service_name.to_string())
+            .or_default()
+            .insert(chosen.id.clone(), selection);
+
+        Some(chosen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ServiceStatus;
+
+    fn instance(port: u16, status: ServiceStatus) -> ServiceInstance {
+        let mut instance = ServiceInstance::new("scanner", "10.0.0.1", port, HashMap::new(), None);
+        instance.status = status;
+        instance
+    }
+
+    fn ports(
+        balancer: &LoadBalancer,
+        strategy: SelectionStrategy,
+        instances: &[ServiceInstance],
+        count: usize,
+    ) -> Vec<u16> {
+        (0..count)
+            .map(|_| {
+                balancer
+                    .select("scanner", strategy, instances.to_vec())
+                    .unwrap()
+                    .port
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_healthy_instances() {
+        let balancer = LoadBalancer::new();
+        let instances = vec![
+            instance(9002, ServiceStatus::Up),
+            instance(9001, ServiceStatus::Up),
+            instance(9003, ServiceStatus::Unhealthy),
+            instance(9004, ServiceStatus::Up),
+        ];
+
+        assert_eq!(
+            ports(&balancer, SelectionStrategy::RoundRobin, &instances, 6),
+            vec![9001, 9002, 9004, 9001, 9002, 9004]
+        );
+
+        // Each service has its own cursor
+        let other = balancer
+            .select("storage", SelectionStrategy::RoundRobin, instances.clone())
+            .unwrap();
+        assert_eq!(other.port, 9001);
+    }
+
+    #[test]
+    fn test_least_recently_used_picks_the_longest_idle() {
+        let balancer = LoadBalancer::new();
+        let instances = vec![
+            instance(9001, ServiceStatus::Up),
+            instance(9002, ServiceStatus::Up),
+            instance(9003, ServiceStatus::Up),
+        ];
+        balancer
+            .select("scanner", SelectionStrategy::RoundRobin, instances.clone())
+            .unwrap();
+
+        // 9001 was just used by round-robin
+        assert_eq!(
+            ports(
+                &balancer,
+                SelectionStrategy::LeastRecentlyUsed,
+                &instances,
+                4
+            ),
+            vec![9002, 9003, 9001, 9002]
+        );
+    }
+
+    #[test]
+    fn test_random_only_picks_healthy_instances() {
+        let balancer = LoadBalancer::new();
+        let instances = vec![
+            instance(9001, ServiceStatus::Unhealthy),
+            instance(9002, ServiceStatus::Up),
+            instance(9003, ServiceStatus::Starting),
+        ];
+
+        assert!(ports(&balancer, SelectionStrategy::Random, &instances, 10)
+            .iter()
+            .all(|port| *port == 9002));
+    }
+
+    #[test]
+    fn test_no_routable_instance_selects_nothing() {
+        let balancer = LoadBalancer::new();
+        let instances = vec![
+            instance(9001, ServiceStatus::Unhealthy),
+            instance(9002, ServiceStatus::Down),
+        ];
+
+        for strategy in [
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::LeastRecentlyUsed,
+            SelectionStrategy::Random,
+        ] {
+            assert!(balancer
+                .select("scanner", strategy, instances.clone())
+                .is_none());
+        }
+    }
+}
@@ -14,6 +14,9 @@ pub enum DiscoveryError {
     #[error("Service error: {0}")]
     Service(String),
 
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -44,6 +47,7 @@ impl From<DiscoveryError> for mirage_common::Error {
             DiscoveryError::Validation(msg) => mirage_common::Error::Validation(msg),
             DiscoveryError::NotFound(msg) => mirage_common::Error::NotFound(msg),
             DiscoveryError::Service(msg) => mirage_common::Error::ExternalApi(msg),
+            DiscoveryError::Unavailable(msg) => mirage_common::Error::ServiceUnavailable(msg),
             DiscoveryError::Internal(msg) => mirage_common::Error::Internal(msg),
         }
     }
@@ -2,9 +2,11 @@ use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
 use mirage_common::Error as CommonError;
 use mirage_common::Result as CommonResult;
 
+use crate::error::DiscoveryError;
 use crate::health::HealthService;
 use crate::models::{
-    ServiceHeartbeatRequest, ServiceQuery, ServiceRegistrationRequest, ServiceStatus,
+    InstanceSelectionQuery, ServiceHeartbeatRequest, ServiceQuery, ServiceRegistrationRequest,
+    ServiceStatus,
 };
 use crate::services::DiscoveryService;
 
@@ -15,6 +17,7 @@ pub fn discovery_routes() -> actix_web::Scope {
         .service(get_services)
         .service(query_services)
         .service(get_service_instances)
+        .service(select_instance)
         .service(send_heartbeat)
         .service(deregister_service)
         .service(get_service_health)
@@ -30,6 +33,9 @@ async fn register_service(
         .register_service(request.into_inner())
         .await
         .map_err(|e| match e {
+            DiscoveryError::Validation(_) => {
+                actix_web::error::ErrorBadRequest(CommonError::from(e))
+            }
             _ => {
                 tracing::error!("Error registering service: {}", e);
                 actix_web::error::ErrorInternalServerError(CommonError::from(e))
@@ -45,6 +51,7 @@ async fn get_service(
     service: web::Data<DiscoveryService>,
 ) -> Result<HttpResponse, Error> {
     let instance = service.get_service(&id).await.map_err(|e| match e {
+        DiscoveryError::NotFound(_) => actix_web::error::ErrorNotFound(CommonError::from(e)),
         _ => {
             tracing::error!("Error getting service {}: {}", id, e);
             actix_web::error::ErrorInternalServerError(CommonError::from(e))
@@ -89,6 +96,7 @@ async fn get_service_instances(
         .get_service_instances(&name)
         .await
         .map_err(|e| match e {
+            DiscoveryError::NotFound(_) => actix_web::error::ErrorNotFound(CommonError::from(e)),
             _ => {
                 tracing::error!("Error getting instances for service {}: {}", name, e);
                 actix_web::error::ErrorInternalServerError(CommonError::from(e))
@@ -98,6 +106,31 @@ async fn get_service_instances(
     Ok(HttpResponse::Ok().json(instances))
 }
 
+#[get("/services/instances/{name}/select")]
+async fn select_instance(
+    name: web::Path<String>,
+    query: web::Query<InstanceSelectionQuery>,
+    service: web::Data<DiscoveryService>,
+) -> Result<HttpResponse, Error> {
+    let strategy = query.strategy.unwrap_or_default();
+
+    let instance = service
+        .select_instance(&name, strategy)
+        .await
+        .map_err(|e| match e {
+            DiscoveryError::NotFound(_) => actix_web::error::ErrorNotFound(CommonError::from(e)),
+            DiscoveryError::Unavailable(_) => {
+                actix_web::error::ErrorServiceUnavailable(CommonError::from(e))
+            }
+            _ => {
+                tracing::error!("Error selecting an instance of service {}: {}", name, e);
+                actix_web::error::ErrorInternalServerError(CommonError::from(e))
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(instance))
+}
+
 #[put("/services/{id}/heartbeat")]
 async fn send_heartbeat(
     id: web::Path<String>,
@@ -111,6 +144,7 @@ async fn send_heartbeat(
         .heartbeat(heartbeat_req)
         .await
         .map_err(|e| match e {
+            DiscoveryError::NotFound(_) => actix_web::error::ErrorNotFound(CommonError::from(e)),
             _ => {
                 tracing::error!("Error sending heartbeat: {}", e);
                 actix_web::error::ErrorInternalServerError(CommonError::from(e))
@@ -126,6 +160,7 @@ async fn deregister_service(
     service: web::Data<DiscoveryService>,
 ) -> Result<HttpResponse, Error> {
     service.deregister_service(&id).await.map_err(|e| match e {
+        DiscoveryError::NotFound(_) => actix_web::error::ErrorNotFound(CommonError::from(e)),
         _ => {
             tracing::error!("Error deregistering service {}: {}", id, e);
             actix_web::error::ErrorInternalServerError(CommonError::from(e))
@@ -3,6 +3,7 @@ use crate::error::{DiscoveryError, DiscoveryResult};
 use crate::models::{HealthCheckResult, ServiceInstance, ServiceStatus};
 use crate::repository::ServiceRepository;
 use chrono::Utc;
+use mirage_common::shutdown::ShutdownSignal;
 use reqwest::Client;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -110,12 +111,14 @@ impl HealthService {
             }
         }
 
-        // Update status in repository if threshold is met
+        // Update status in repository if threshold is met. A failing instance
+        // is marked unhealthy, which takes it out of routing until it either
+        // recovers or stops heartbeating and expires.
         let status_changed = if current_state.consecutive_failures >= self.config.failure_threshold
         {
-            if instance.status != ServiceStatus::Down {
+            if instance.status != ServiceStatus::Unhealthy {
                 self.repo
-                    .update_service_status(&instance.id, ServiceStatus::Down)
+                    .update_service_status(&instance.id, ServiceStatus::Unhealthy)
                     .await?;
                 true
             } else {
@@ -143,8 +146,15 @@ impl HealthService {
         // Log status changes
         if status_changed {
             info!(
-                "Service {} status changed to {:?}. Response time: {}ms",
-                instance.name, current_state.status, response_time_ms
+                "Service {} ({}) is now {}. Response time: {}ms",
+                instance.name,
+                instance.id,
+                if current_state.status == ServiceStatus::Up {
+                    "up"
+                } else {
+                    "unhealthy"
+                },
+                response_time_ms
             );
         }
 
@@ -156,6 +166,12 @@ impl HealthService {
         let instances = self.repo.get_all_services().await?;
         let mut results = Vec::with_capacity(instances.len());
 
+        // Forget instances that have expired or deregistered
+        {
+            let mut states = self.health_states.lock().await;
+            states.retain(|id, _| instances.iter().any(|instance| &instance.id == id));
+        }
+
         for instance in instances {
             match self.check_service_health(&instance).await {
                 Ok(result) => results.push(result),
@@ -198,11 +214,11 @@ impl HealthService {
     }
 }
 
-// Background health check task
-pub async fn run_health_checker(health_service: HealthService) {
+// Background health check task, which runs until `shutdown`
+pub async fn run_health_checker(health_service: HealthService, mut shutdown: ShutdownSignal) {
     info!("Starting health checker background task");
 
-    loop {
+    while !shutdown.is_shutting_down() {
         match health_service.check_all_services().await {
             Ok(results) => {
                 let up_count = results
@@ -244,6 +260,168 @@ pub async fn run_health_checker(health_service: HealthService) {
 
         // Sleep until next check cycle
         let interval = health_service.config.interval_seconds;
-        sleep(Duration::from_secs(interval)).await;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(interval)) => {}
+            _ = shutdown.recv() => break,
+        }
+    }
+
+    info!("Health checker stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AppConfig, RedisConfig, ServerConfig};
+    use crate::error::DiscoveryError;
+    use crate::models::ServiceHeartbeatRequest;
+    use crate::services::DiscoveryService;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn config(ttl_seconds: u64) -> AppConfig {
+        AppConfig {
+            server: ServerConfig {
+                port: 8007,
+                host: "127.0.0.1".into(),
+            },
+            redis: RedisConfig {
+                uri: std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1".into()),
+                // Each test gets its own registry on the shared server
+                key_prefix: format!("mirage:discovery:test:{}", uuid::Uuid::new_v4()),
+                service_ttl_seconds: ttl_seconds,
+            },
+            health_check: HealthCheckConfig {
+                interval_seconds: 1,
+                timeout_seconds: 1,
+                failure_threshold: 2,
+                success_threshold: 1,
+            },
+        }
+    }
+
+    fn repo(config: &AppConfig) -> ServiceRepository {
+        let client = redis::Client::open(config.redis.uri.as_str()).unwrap();
+        ServiceRepository::new(client, config.redis.clone())
+    }
+
+    // Health endpoint answering every check with `status`
+    async fn health_endpoint(status: u16) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {} Health\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}/health", addr)
+    }
+
+    async fn register(repo: &ServiceRepository, health_url: String) -> ServiceInstance {
+        let instance = ServiceInstance::new(
+            "scanner",
+            "127.0.0.1",
+            9000,
+            HashMap::new(),
+            Some(health_url),
+        );
+        repo.register_service(&instance).await.unwrap();
+        instance
+    }
+
+    fn heartbeat(instance: &ServiceInstance) -> ServiceHeartbeatRequest {
+        ServiceHeartbeatRequest {
+            id: instance.id.clone(),
+            status: ServiceStatus::Up,
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server in REDIS_URL"]
+    async fn test_instance_that_stops_heartbeating_expires() {
+        let config = config(1);
+        let repo = repo(&config);
+        let health = HealthService::new(repo.clone(), Client::new(), config.health_check.clone());
+        let discovery = DiscoveryService::new(repo.clone(), config);
+        let instance = register(&repo, health_endpoint(200).await).await;
+
+        // Heartbeats within the TTL keep it registered
+        sleep(Duration::from_millis(600)).await;
+        discovery.heartbeat(heartbeat(&instance)).await.unwrap();
+        sleep(Duration::from_millis(600)).await;
+        assert_eq!(
+            repo.get_service_instances("scanner").await.unwrap().len(),
+            1
+        );
+
+        // Passing health checks are not heartbeats, so they don't
+        health.check_all_services().await.unwrap();
+        sleep(Duration::from_millis(600)).await;
+        assert!(repo
+            .get_service_instances("scanner")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repo
+            .get_service_by_id(&instance.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Once expired, it has to register again
+        let err = discovery.heartbeat(heartbeat(&instance)).await.unwrap_err();
+        assert!(matches!(err, DiscoveryError::NotFound(_)));
+
+        assert_eq!(repo.cleanup_expired_services().await.unwrap(), 1);
+        assert!(repo.get_service_names().await.unwrap().is_empty());
+
+        // And the health checker forgets it
+        health.check_all_services().await.unwrap();
+        assert!(health.get_health_results().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Redis server in REDIS_URL"]
+    async fn test_failing_instance_is_marked_unhealthy() {
+        let config = config(60);
+        let repo = repo(&config);
+        let health = HealthService::new(repo.clone(), Client::new(), config.health_check.clone());
+        let discovery = DiscoveryService::new(repo.clone(), config);
+        let instance = register(&repo, health_endpoint(503).await).await;
+        discovery.heartbeat(heartbeat(&instance)).await.unwrap();
+        assert_eq!(
+            discovery
+                .get_service_instances("scanner")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // One failure is under the threshold
+        health.check_all_services().await.unwrap();
+        let stored = repo.get_service_by_id(&instance.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, ServiceStatus::Up);
+
+        health.check_all_services().await.unwrap();
+        let stored = repo.get_service_by_id(&instance.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, ServiceStatus::Unhealthy);
+
+        // Still registered, but no longer handed out to callers, even if
+        // the instance itself says it is up
+        discovery.heartbeat(heartbeat(&instance)).await.unwrap();
+        assert_eq!(discovery.get_all_services().await.unwrap().count, 1);
+        let err = discovery
+            .get_service_instances("scanner")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DiscoveryError::NotFound(_)));
     }
 }
@@ -1,6 +1,15 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+// Service-level probes; `health` below is this service's instance checker
+use mirage_common::health::{self as probes, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::shutdown::{self, Shutdown};
+use mirage_common::telemetry;
 use tracing::info;
 
+mod balancer;
 mod config;
 mod error;
 mod handlers;
@@ -9,39 +18,104 @@ mod models;
 mod repository;
 mod services;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // Initialize logging
+    let _telemetry = telemetry::init("discovery-service");
 
-use axum::{
-    routing::{get, post},
-    Router,
-};
+    // Load configuration
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to load config: {}", e);
+            return Err(std::io::Error::other("Failed to load configuration"));
+        }
+    };
 
-#[tokio::main]
-async fn main() {
-    // initialize tracing
-    tracing_subscriber::fmt::init();
-
-    // build our application with a route
-    let app = Router::new()
-        .route("/", get(|| async { "Hello, Discovery Service!" }))
-        .route("/services", get(list_services))
-        .route("/services", post(register_service));
-
-    // run it
-    let addr = "0.0.0.0:3000".parse().unwrap();
-    tracing::debug!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
+    // Initialize Redis client for the registry
+    let redis_client = match redis::Client::open(config.redis.uri.clone()) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to connect to Redis: {}", e);
+            return Err(std::io::Error::other("Failed to connect to Redis"));
+        }
+    };
+    let repo = repository::ServiceRepository::new(redis_client, config.redis.clone());
 
-async fn list_services() -> &'static str {
-    "List of registered services"
-}
+    // Initialize HTTP client for health checks
+    let http_client = reqwest::Client::builder()
+        .timeout(config.health_check_timeout())
+        .build()
+        .expect("Failed to create HTTP client");
+
+    // Initialize services
+    let health_service =
+        health::HealthService::new(repo.clone(), http_client, config.health_check.clone());
+    let discovery_service = web::Data::new(services::DiscoveryService::new(
+        repo.clone(),
+        config.clone(),
+    ));
+
+    // Stops the server, then the health checker, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+
+    // Start health checker background task, which also drops expired
+    // registrations
+    let background_health = health_service.clone();
+    let health_shutdown = shutdown.subscribe();
+    let health_checker = tokio::spawn(async move {
+        health::run_health_checker(background_health, health_shutdown).await;
+    });
+    let health_service = web::Data::new(health_service);
+
+    info!(
+        "Starting Discovery Service on {}:{}",
+        config.server.host, config.server.port
+    );
+
+    // Dependency probes, served from /api/v1/health/ready
+    let readiness = web::Data::new(
+        HealthChecker::new("discovery-service", env!("CARGO_PKG_VERSION")).probe("redis", {
+            let repo = repo.clone();
+            move || {
+                let repo = repo.clone();
+                async move { repo.ping().await.map_err(mirage_common::Error::from) }
+            }
+        }),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("discovery-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::other("Failed to set up metrics"));
+        }
+    };
+
+    let bind_address = (config.server.host.clone(), config.server.port);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(metrics.clone())
+            .app_data(readiness.clone())
+            .app_data(discovery_service.clone())
+            .app_data(health_service.clone())
+            .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
+            .service(
+                web::scope("/api/v1")
+                    .route("/health", web::get().to(probes::live))
+                    .route("/health/live", web::get().to(probes::live))
+                    .route("/health/ready", web::get().to(probes::ready))
+                    .service(handlers::discovery_routes()),
+            )
+    })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
+    .bind(bind_address)?
+    .run();
 
-async fn register_service() -> &'static str {
-    "Service registered successfully"
+    shutdown.run(server, vec![health_checker]).await
 }
@@ -11,6 +11,8 @@ pub enum ServiceStatus {
     Starting,
     Stopping,
     Unknown,
+    // Failing its health checks, though still heartbeating
+    Unhealthy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,6 +56,11 @@ impl ServiceInstance {
         format!("http://{}:{}", self.address, self.port)
     }
 
+    // Only instances that are up get traffic
+    pub fn is_routable(&self) -> bool {
+        self.status == ServiceStatus::Up
+    }
+
     pub fn get_health_url(&self) -> Option<String> {
         self.health_check_url
             .clone()
@@ -126,6 +133,21 @@ pub struct ServiceQuery {
     pub metadata_value: Option<String>,
 }
 
+// How a caller wants one instance picked out of a service's healthy ones
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    #[default]
+    RoundRobin,
+    LeastRecentlyUsed,
+    Random,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceSelectionQuery {
+    pub strategy: Option<SelectionStrategy>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceRegistry {
     pub services: Vec<ServiceResponse>,
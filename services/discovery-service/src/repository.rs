@@ -1,7 +1,6 @@
 use crate::config::RedisConfig;
 use crate::error::{DiscoveryError, DiscoveryResult};
 use crate::models::{ServiceInstance, ServiceQuery, ServiceStatus};
-use chrono::Utc;
 use redis::{AsyncCommands, Client as RedisClient, FromRedisValue, RedisResult};
 use std::sync::Arc;
 use std::time::Duration;
@@ -20,6 +19,15 @@ impl ServiceRepository {
         }
     }
 
+    // Check that Redis is reachable
+    pub async fn ping(&self) -> DiscoveryResult<()> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
     // Register a new service instance
     pub async fn register_service(&self, instance: &ServiceInstance) -> DiscoveryResult<()> {
         let mut conn = self.client.get_async_connection().await?;
@@ -35,8 +43,9 @@ impl ServiceRepository {
             DiscoveryError::Internal(format!("Failed to serialize instance: {}", e))
         })?;
 
-        // Store the instance with an expiration (TTL)
-        conn.set_ex(
+        // Store the instance with an expiration (TTL). It is dropped from
+        // discovery unless a heartbeat renews it before then.
+        conn.set_ex::<_, _, ()>(
             instance_key.clone(),
             instance_json,
             self.config.service_ttl_seconds as usize,
@@ -44,10 +53,10 @@ impl ServiceRepository {
         .await?;
 
         // Add instance ID to the set of instances for this service
-        conn.sadd(service_key, &instance.id).await?;
+        conn.sadd::<_, _, ()>(service_key, &instance.id).await?;
 
         // Add service to the list of known services
-        conn.sadd(
+        conn.sadd::<_, _, ()>(
             format!("{}:services", self.config.key_prefix),
             &instance.name,
         )
@@ -56,32 +65,44 @@ impl ServiceRepository {
         Ok(())
     }
 
-    // Update a service instance (heartbeat)
+    // Update a service instance (heartbeat), renewing its TTL
     pub async fn update_service(&self, instance: &ServiceInstance) -> DiscoveryResult<()> {
         let mut conn = self.client.get_async_connection().await?;
 
         // Key for this specific service instance
         let instance_key = format!("{}:instance:{}", self.config.key_prefix, instance.id);
 
-        // Check if instance exists
-        let exists: bool = conn.exists(&instance_key).await?;
-        if !exists {
-            return Err(DiscoveryError::NotFound(format!(
-                "Service instance {} not found",
-                instance.id
-            )));
-        }
+        // Key for the list of instances for this service type
+        let service_key = format!("{}:service:{}", self.config.key_prefix, instance.name);
 
         // Serialize the instance
         let instance_json = serde_json::to_string(instance).map_err(|e| {
             DiscoveryError::Internal(format!("Failed to serialize instance: {}", e))
         })?;
 
-        // Store the updated instance with an expiration (TTL)
-        conn.set_ex(
-            instance_key,
-            instance_json,
-            self.config.service_ttl_seconds as usize,
+        // Store the updated instance with a fresh expiration (TTL), but only
+        // if it has not expired already; an expired instance must register
+        // again
+        let stored: Option<String> = redis::cmd("SET")
+            .arg(&instance_key)
+            .arg(instance_json)
+            .arg("XX")
+            .arg("EX")
+            .arg(self.config.service_ttl_seconds)
+            .query_async(&mut conn)
+            .await?;
+        if stored.is_none() {
+            return Err(DiscoveryError::NotFound(format!(
+                "Service instance {} not found",
+                instance.id
+            )));
+        }
+
+        // A cleanup racing a re-registration may have dropped the ID
+        conn.sadd::<_, _, ()>(service_key, &instance.id).await?;
+        conn.sadd::<_, _, ()>(
+            format!("{}:services", self.config.key_prefix),
+            &instance.name,
         )
         .await?;
 
@@ -109,22 +130,22 @@ impl ServiceRepository {
                 let service_key = format!("{}:service:{}", self.config.key_prefix, instance.name);
 
                 // Delete instance from Redis
-                conn.del(&instance_key).await?;
+                conn.del::<_, ()>(&instance_key).await?;
 
                 // Remove from service set
-                conn.srem(service_key, instance_id).await?;
+                conn.srem::<_, _, ()>(&service_key, instance_id).await?;
 
                 // Check if this was the last instance of this service
-                let count: u64 = conn.scard(service_key.clone()).await?;
+                let count: u64 = conn.scard(&service_key).await?;
                 if count == 0 {
                     // Remove service from list of known services
-                    conn.srem(
+                    conn.srem::<_, _, ()>(
                         format!("{}:services", self.config.key_prefix),
                         instance.name,
                     )
                     .await?;
                     // Remove empty set
-                    conn.del(service_key).await?;
+                    conn.del::<_, ()>(service_key).await?;
                 }
 
                 Ok(())
@@ -209,18 +230,16 @@ impl ServiceRepository {
         Ok(all_instances)
     }
 
-    // Update service status
+    // Update service status. This is not a heartbeat: the instance keeps
+    // the TTL its last heartbeat gave it, so one that stopped heartbeating
+    // still expires however its health checks go.
     pub async fn update_service_status(
         &self,
         instance_id: &str,
         status: ServiceStatus,
     ) -> DiscoveryResult<()> {
-        let instance = match self.get_service_by_id(instance_id).await? {
-            Some(mut instance) => {
-                instance.status = status;
-                instance.last_heartbeat = Utc::now();
-                instance
-            }
+        let mut instance = match self.get_service_by_id(instance_id).await? {
+            Some(instance) => instance,
             None => {
                 return Err(DiscoveryError::NotFound(format!(
                     "Service instance {} not found",
@@ -228,8 +247,29 @@ impl ServiceRepository {
                 )))
             }
         };
+        instance.status = status;
 
-        self.update_service(&instance).await
+        let mut conn = self.client.get_async_connection().await?;
+        let instance_key = format!("{}:instance:{}", self.config.key_prefix, instance_id);
+        let instance_json = serde_json::to_string(&instance).map_err(|e| {
+            DiscoveryError::Internal(format!("Failed to serialize instance: {}", e))
+        })?;
+
+        let stored: Option<String> = redis::cmd("SET")
+            .arg(&instance_key)
+            .arg(instance_json)
+            .arg("XX")
+            .arg("KEEPTTL")
+            .query_async(&mut conn)
+            .await?;
+        if stored.is_none() {
+            return Err(DiscoveryError::NotFound(format!(
+                "Service instance {} not found",
+                instance_id
+            )));
+        }
+
+        Ok(())
     }
 
     // Query services by criteria
@@ -296,7 +336,7 @@ impl ServiceRepository {
 
                 if !exists {
                     // Instance expired from Redis TTL, remove it from the service set
-                    conn.srem(&service_key, &id).await?;
+                    conn.srem::<_, _, ()>(&service_key, &id).await?;
                     removed_count += 1;
                 }
             }
@@ -305,10 +345,10 @@ impl ServiceRepository {
             let count: u64 = conn.scard(&service_key).await?;
             if count == 0 {
                 // Remove service from list of known services
-                conn.srem(format!("{}:services", self.config.key_prefix), &name)
+                conn.srem::<_, _, ()>(format!("{}:services", self.config.key_prefix), &name)
                     .await?;
                 // Remove empty set
-                conn.del(&service_key).await?;
+                conn.del::<_, ()>(&service_key).await?;
             }
         }
 
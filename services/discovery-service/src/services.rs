@@ -1,8 +1,9 @@
+use crate::balancer::LoadBalancer;
 use crate::config::AppConfig;
 use crate::error::{DiscoveryError, DiscoveryResult};
 use crate::models::{
-    ServiceHeartbeatRequest, ServiceInstance, ServiceQuery, ServiceRegistrationRequest,
-    ServiceRegistry, ServiceResponse, ServiceStatus,
+    SelectionStrategy, ServiceHeartbeatRequest, ServiceInstance, ServiceQuery,
+    ServiceRegistrationRequest, ServiceRegistry, ServiceResponse, ServiceStatus,
 };
 use crate::repository::ServiceRepository;
 use chrono::Utc;
@@ -14,6 +15,7 @@ use tokio::time::{sleep, Duration};
 pub struct DiscoveryService {
     repo: Arc<ServiceRepository>,
     config: Arc<AppConfig>,
+    balancer: LoadBalancer,
 }
 
 impl DiscoveryService {
@@ -21,6 +23,7 @@ impl DiscoveryService {
         Self {
             repo: Arc::new(repo),
             config: Arc::new(config),
+            balancer: LoadBalancer::new(),
         }
     }
 
@@ -80,8 +83,11 @@ impl DiscoveryService {
             }
         };
 
-        // Update fields
-        instance.status = request.status;
+        // Update fields. An instance failing its health checks stays
+        // unhealthy, whatever it reports, until the checks pass again.
+        if instance.status != ServiceStatus::Unhealthy {
+            instance.status = request.status;
+        }
         instance.last_heartbeat = Utc::now();
 
         // Update metadata if provided
@@ -115,16 +121,23 @@ impl DiscoveryService {
         }
     }
 
-    // Get all instances of a service
+    // Get the instances of a service that can take traffic. Expired
+    // instances are gone from the repository; unhealthy ones are skipped.
     pub async fn get_service_instances(
         &self,
         service_name: &str,
     ) -> DiscoveryResult<Vec<ServiceResponse>> {
-        let instances = self.repo.get_service_instances(service_name).await?;
+        let instances: Vec<ServiceInstance> = self
+            .repo
+            .get_service_instances(service_name)
+            .await?
+            .into_iter()
+            .filter(ServiceInstance::is_routable)
+            .collect();
 
         if instances.is_empty() {
             return Err(DiscoveryError::NotFound(format!(
-                "No instances found for service {}",
+                "No available instances found for service {}",
                 service_name
             )));
         }
@@ -132,6 +145,31 @@ impl DiscoveryService {
         Ok(instances.into_iter().map(ServiceResponse::from).collect())
     }
 
+    // Pick one healthy instance of a service for a caller. A service with
+    // no instances registered is not found; one whose instances are all
+    // unhealthy is unavailable.
+    pub async fn select_instance(
+        &self,
+        service_name: &str,
+        strategy: SelectionStrategy,
+    ) -> DiscoveryResult<ServiceResponse> {
+        let instances = self.repo.get_service_instances(service_name).await?;
+        if instances.is_empty() {
+            return Err(DiscoveryError::NotFound(format!(
+                "No instances registered for service {}",
+                service_name
+            )));
+        }
+
+        match self.balancer.select(service_name, strategy, instances) {
+            Some(instance) => Ok(instance.into()),
+            None => Err(DiscoveryError::Unavailable(format!(
+                "No healthy instances of service {}",
+                service_name
+            ))),
+        }
+    }
+
     // Get all services
     pub async fn get_all_services(&self) -> DiscoveryResult<ServiceRegistry> {
         let instances = self.repo.get_all_services().await?;
@@ -1,72 +1,316 @@
+//! Secrets at rest
+//!
+//! Credentials and the secret fields of integration configs are encrypted
+//! with AES-256-GCM under the master key configured as
+//! `security.encryption_key`, a base64-encoded 32-byte key. Every value gets
+//! its own random nonce, which is stored next to the ciphertext. Secrets are
+//! decrypted only to run an integration and are masked in API responses.
+
 use crate::error::{IntegrationError, IntegrationResult};
-use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit},
-    Aes256Gcm, Key, Nonce,
-};
 use base64::{engine::general_purpose, Engine as _};
-use uuid::Uuid;
+use mirage_common::crypto::SecretCipher;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+
+/// What API responses show in place of a secret. Sending it back in an
+/// update keeps the stored secret.
+pub const MASKED_SECRET: &str = "********";
+
+/// Config field names that hold secrets, matched against the end of the
+/// lowercased name with dashes read as underscores
+const SECRET_FIELD_SUFFIXES: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "access_key",
+    "private_key",
+    "authorization",
+];
+
+/// Whether a config field holds a secret, judged by its name
+pub fn is_secret_field(name: &str) -> bool {
+    let name = name.to_lowercase().replace('-', "_");
+    SECRET_FIELD_SUFFIXES
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+/// A secret config field as stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SealedSecret {
+    /// Base64-encoded ciphertext, including the GCM tag
+    pub ciphertext: String,
+    /// Base64-encoded nonce
+    pub nonce: String,
+}
+
+impl SealedSecret {
+    fn from_value(value: &Value) -> Option<Self> {
+        serde_json::from_value(value.clone()).ok()
+    }
+}
 
 #[derive(Clone)]
 pub struct CryptoService {
-    key: [u8; 32],
+    cipher: SecretCipher,
 }
 
 impl CryptoService {
-    pub fn new(key_str: &str) -> Self {
-        // In production, this should be more sophisticated
-        // For now, using a simple key derivation
-        let mut key = [0u8; 32];
-        let key_bytes = key_str.as_bytes();
-        let len = std::cmp::min(key_bytes.len(), 32);
-        key[..len].copy_from_slice(&key_bytes[..len]);
-
-        Self { key }
+    /// Takes the base64-encoded master key
+    pub fn new(encoded_key: &str) -> IntegrationResult<Self> {
+        Ok(Self {
+            cipher: SecretCipher::new(encoded_key)?,
+        })
     }
 
+    /// Encrypts credential data into base64 of the nonce followed by the
+    /// ciphertext
     pub fn encrypt(&self, data: &str) -> IntegrationResult<String> {
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        Ok(self.cipher.encrypt(data)?)
+    }
 
-        // Generate a random nonce
-        let nonce_array = Aes256Gcm::generate_nonce(&mut rand::thread_rng());
-        let nonce = Nonce::from_slice(nonce_array.as_slice());
+    pub fn decrypt(&self, encrypted_data: &str) -> IntegrationResult<String> {
+        Ok(self.cipher.decrypt(encrypted_data)?)
+    }
 
-        // Encrypt the data
-        let ciphertext = cipher
-            .encrypt(nonce, data.as_bytes())
-            .map_err(|e| IntegrationError::Crypto(format!("Encryption failed: {}", e)))?;
+    pub fn seal(&self, secret: &str) -> IntegrationResult<SealedSecret> {
+        let (nonce, ciphertext) = self.cipher.seal_bytes(secret)?;
 
-        // Combine nonce and ciphertext and encode with base64
-        let mut combined = nonce.to_vec();
-        combined.extend_from_slice(&ciphertext);
-        let encoded = general_purpose::STANDARD.encode(combined);
+        Ok(SealedSecret {
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+            nonce: general_purpose::STANDARD.encode(nonce),
+        })
+    }
 
-        Ok(encoded)
+    pub fn open(&self, sealed: &SealedSecret) -> IntegrationResult<String> {
+        let decode = |field: &str| {
+            general_purpose::STANDARD
+                .decode(field)
+                .map_err(|e| IntegrationError::Crypto(format!("Base64 decoding failed: {}", e)))
+        };
+
+        Ok(self
+            .cipher
+            .open_bytes(&decode(&sealed.nonce)?, &decode(&sealed.ciphertext)?)?)
     }
 
-    pub fn decrypt(&self, encrypted_data: &str) -> IntegrationResult<String> {
-        // Decode base64
-        let combined = general_purpose::STANDARD
-            .decode(encrypted_data)
-            .map_err(|e| IntegrationError::Crypto(format!("Base64 decoding failed: {}", e)))?;
-
-        // Split nonce and ciphertext
-        if combined.len() < 12 {
-            return Err(IntegrationError::Crypto(
-                "Invalid encrypted data format".to_string(),
-            ));
+    /// Seals the plaintext secret fields of an integration config for
+    /// storage. Fields that are already sealed are left as they are.
+    pub fn encrypt_config(&self, config: &Value) -> IntegrationResult<Value> {
+        map_secrets(config, &mut |value| match value {
+            Value::String(secret) => serde_json::to_value(self.seal(secret)?).map_err(|e| {
+                IntegrationError::Internal(format!("Failed to serialize secret: {}", e))
+            }),
+            other => Ok(other.clone()),
+        })
+    }
+
+    /// Opens the sealed secret fields of a stored integration config
+    pub fn decrypt_config(&self, config: &Value) -> IntegrationResult<Value> {
+        map_secrets(config, &mut |value| match SealedSecret::from_value(value) {
+            Some(sealed) => Ok(Value::String(self.open(&sealed)?)),
+            None => Ok(value.clone()),
+        })
+    }
+}
+
+/// Copies a config, replacing the value of every secret field, at any depth,
+/// with what `f` returns for it
+fn map_secrets<E>(
+    value: &Value,
+    f: &mut impl FnMut(&Value) -> Result<Value, E>,
+) -> Result<Value, E> {
+    Ok(match value {
+        Value::Object(fields) => {
+            let mut mapped = serde_json::Map::with_capacity(fields.len());
+            for (name, field) in fields {
+                let field = if is_secret_field(name) {
+                    f(field)?
+                } else {
+                    map_secrets(field, f)?
+                };
+                mapped.insert(name.clone(), field);
+            }
+            Value::Object(mapped)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| map_secrets(item, f))
+                .collect::<Result<_, _>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Copies a config with its secret fields masked, sealed or not
+pub fn mask_config(config: &Value) -> Value {
+    let Ok(masked) = map_secrets::<Infallible>(config, &mut |value| {
+        Ok(match value {
+            Value::Null => Value::Null,
+            _ => Value::String(MASKED_SECRET.to_string()),
+        })
+    });
+    masked
+}
+
+/// Puts the stored secrets back into an updated config wherever it still
+/// has them masked
+pub fn keep_masked_secrets(config: &mut Value, stored: &Value) -> IntegrationResult<()> {
+    match config {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                let stored_field = stored.get(name.as_str()).unwrap_or(&Value::Null);
+
+                if is_secret_field(name) && field.as_str() == Some(MASKED_SECRET) {
+                    if stored_field.is_null() {
+                        return Err(IntegrationError::Validation(format!(
+                            "Secret field '{}' is masked but has no stored value",
+                            name
+                        )));
+                    }
+                    *field = stored_field.clone();
+                } else {
+                    keep_masked_secrets(field, stored_field)?;
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                keep_masked_secrets(item, stored.get(i).unwrap_or(&Value::Null))?;
+            }
         }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEY: &str = "YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=";
+
+    fn crypto() -> CryptoService {
+        CryptoService::new(KEY).unwrap()
+    }
+
+    fn config() -> Value {
+        json!({
+            "base_url": "https://api.example.com",
+            "method": "GET",
+            "api_key": "sk-live-1234",
+            "headers": {
+                "Accept": "application/json",
+                "X-Api-Key": "header-secret"
+            },
+            "accounts": [{ "name": "main", "password": "hunter2" }],
+            "token_type": "Bearer"
+        })
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let crypto = crypto();
+
+        let encrypted = crypto.encrypt(r#"{"token":"abc"}"#).unwrap();
+        assert!(!encrypted.contains("abc"));
+        assert_eq!(crypto.decrypt(&encrypted).unwrap(), r#"{"token":"abc"}"#);
+
+        // A fresh nonce for every value
+        let sealed = crypto.seal("abc").unwrap();
+        assert_ne!(sealed, crypto.seal("abc").unwrap());
+        assert_eq!(crypto.open(&sealed).unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_wrong_key_or_tampering_fails_to_decrypt() {
+        let sealed = crypto().seal("abc").unwrap();
+
+        let other = CryptoService::new(&general_purpose::STANDARD.encode([7u8; 32])).unwrap();
+        assert!(matches!(
+            other.open(&sealed),
+            Err(IntegrationError::Crypto(_))
+        ));
+
+        let mut ciphertext = general_purpose::STANDARD
+            .decode(&sealed.ciphertext)
+            .unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = SealedSecret {
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+            ..sealed
+        };
+        assert!(matches!(
+            crypto().open(&tampered),
+            Err(IntegrationError::Crypto(_))
+        ));
+    }
+
+    #[test]
+    fn test_master_key_must_be_32_base64_bytes() {
+        assert!(CryptoService::new("your-32-character-encryption-key").is_err());
+        assert!(CryptoService::new(&general_purpose::STANDARD.encode([1u8; 16])).is_err());
+        assert!(CryptoService::new(&format!(" {}\n", KEY)).is_ok());
+    }
+
+    #[test]
+    fn test_config_stores_only_sealed_secrets() {
+        let crypto = crypto();
+        let stored = crypto.encrypt_config(&config()).unwrap();
+
+        let text = stored.to_string();
+        for secret in ["sk-live-1234", "header-secret", "hunter2"] {
+            assert!(!text.contains(secret), "{} stored in plaintext", secret);
+        }
+        assert!(SealedSecret::from_value(&stored["api_key"]).is_some());
+        assert_eq!(stored["base_url"], "https://api.example.com");
+        assert_eq!(stored["headers"]["Accept"], "application/json");
+        assert_eq!(stored["token_type"], "Bearer");
+
+        // Re-encrypting a stored config leaves it as it is
+        assert_eq!(crypto.encrypt_config(&stored).unwrap(), stored);
+        assert_eq!(crypto.decrypt_config(&stored).unwrap(), config());
+    }
+
+    #[test]
+    fn test_masking_hides_secrets() {
+        let masked = mask_config(&crypto().encrypt_config(&config()).unwrap());
+
+        assert_eq!(masked["api_key"], MASKED_SECRET);
+        assert_eq!(masked["headers"]["X-Api-Key"], MASKED_SECRET);
+        assert_eq!(masked["accounts"][0]["password"], MASKED_SECRET);
+        assert_eq!(masked["accounts"][0]["name"], "main");
+        assert_eq!(masked["method"], "GET");
+    }
+
+    #[test]
+    fn test_masked_secrets_keep_their_stored_value() {
+        let stored = crypto().encrypt_config(&config()).unwrap();
 
-        let (nonce_bytes, ciphertext) = combined.split_at(12);
-        let nonce = Nonce::from_slice(nonce_bytes);
+        let mut updated = mask_config(&stored);
+        updated["method"] = json!("POST");
+        updated["headers"]["X-Api-Key"] = json!("rotated");
+        keep_masked_secrets(&mut updated, &stored).unwrap();
 
-        // Create cipher and decrypt
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|e| IntegrationError::Crypto(format!("Decryption failed: {}", e)))?;
+        assert_eq!(updated["api_key"], stored["api_key"]);
+        assert_eq!(
+            updated["accounts"][0]["password"],
+            stored["accounts"][0]["password"]
+        );
+        assert_eq!(updated["headers"]["X-Api-Key"], "rotated");
+        assert_eq!(updated["method"], "POST");
 
-        // Convert to string
-        String::from_utf8(plaintext)
-            .map_err(|e| IntegrationError::Crypto(format!("UTF-8 decoding failed: {}", e)))
+        let mut new_field = json!({ "client_secret": MASKED_SECRET });
+        assert!(matches!(
+            keep_masked_secrets(&mut new_field, &stored),
+            Err(IntegrationError::Validation(_))
+        ));
     }
 }
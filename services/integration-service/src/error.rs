@@ -20,6 +20,9 @@ pub enum IntegrationError {
     #[error("External API error: {0}")]
     ExternalApi(String),
 
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
     #[error("Crypto error: {0}")]
     Crypto(String),
 
@@ -30,6 +33,13 @@ pub enum IntegrationError {
     Internal(String),
 }
 
+impl IntegrationError {
+    // Whether the same execution could succeed later without any changes
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, IntegrationError::RateLimited(_))
+    }
+}
+
 impl From<sqlx::Error> for IntegrationError {
     fn from(err: sqlx::Error) -> Self {
         IntegrationError::Database(format!("{}", err))
@@ -60,6 +70,12 @@ impl From<std::io::Error> for IntegrationError {
     }
 }
 
+impl From<mirage_common::crypto::CryptoError> for IntegrationError {
+    fn from(err: mirage_common::crypto::CryptoError) -> Self {
+        IntegrationError::Crypto(format!("{}", err))
+    }
+}
+
 // Conversion to mirage_common::Error for API handlers
 impl From<IntegrationError> for mirage_common::Error {
     fn from(err: IntegrationError) -> Self {
@@ -72,6 +88,7 @@ impl From<IntegrationError> for mirage_common::Error {
             }
             IntegrationError::Authentication(msg) => mirage_common::Error::Unauthorized(msg),
             IntegrationError::ExternalApi(msg) => mirage_common::Error::ExternalApi(msg),
+            IntegrationError::RateLimited(msg) => mirage_common::Error::RateLimited(msg),
             IntegrationError::Crypto(msg) => {
                 mirage_common::Error::Internal(format!("Crypto error: {}", msg))
             }
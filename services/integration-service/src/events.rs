@@ -0,0 +1,97 @@
+//! Platform events from integrations
+//!
+//! Entities an integration brings in are wrapped as `EntityCreated` events
+//! and stored through the data storage service, with the integration's ID
+//! as the source module so storage records where each artifact came from.
+
+use crate::error::{IntegrationError, IntegrationResult};
+use mirage_common::event::{Event, EventType};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Wrap an entity as an `EntityCreated` event from `integration:<id>`
+pub fn entity_created(integration_id: Uuid, entity: Value) -> Event {
+    Event::new(
+        EventType::EntityCreated,
+        &format!("integration:{}", integration_id),
+        entity,
+    )
+}
+
+#[derive(Clone)]
+pub struct EventPublisher {
+    client: Client,
+    data_storage_url: String,
+}
+
+impl EventPublisher {
+    pub fn new(client: Client, data_storage_url: &str) -> Self {
+        Self {
+            client,
+            data_storage_url: data_storage_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // Store the entity each event carries, in order, stopping at the first
+    // the data storage service refuses
+    pub async fn publish(&self, integration_id: Uuid, events: &[Event]) -> IntegrationResult<()> {
+        let url = format!("{}/api/v1/data", self.data_storage_url);
+
+        for event in events {
+            if event.event_type != EventType::EntityCreated {
+                continue;
+            }
+
+            let response = self
+                .client
+                .post(&url)
+                .json(&store_request(integration_id, event))
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(IntegrationError::ExternalApi(format!(
+                    "Data storage error: {} - {}",
+                    status, error_text
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The data storage request for an `EntityCreated` event
+fn store_request(integration_id: Uuid, event: &Event) -> Value {
+    let entity = &event.data;
+
+    let mut metadata = HashMap::from([
+        ("event_id".to_string(), event.id.to_string()),
+        ("event_source".to_string(), event.source.clone()),
+    ]);
+    for key in ["source", "confidence", "tags"] {
+        match entity.get(key) {
+            Some(Value::String(v)) => {
+                metadata.insert(key.to_string(), v.clone());
+            }
+            Some(Value::Null) | None => {}
+            Some(v) => {
+                metadata.insert(key.to_string(), v.to_string());
+            }
+        }
+    }
+
+    json!({
+        "source_module": integration_id,
+        "scan_id": null,
+        "task_id": null,
+        "entity_type": entity.get("entity_type"),
+        "value": entity.get("value"),
+        "data": entity.get("data").cloned().unwrap_or_else(|| json!({})),
+        "metadata": metadata,
+    })
+}
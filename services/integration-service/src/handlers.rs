@@ -1,28 +1,31 @@
-use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
-use mirage_common::Error as CommonError;
+use actix_web::{delete, get, post, put, web, Error, HttpRequest, HttpResponse, Responder};
 use uuid::Uuid;
 
+use crate::error::IntegrationError;
 use crate::models::{
     CreateIntegrationRequest, CredentialRequest, ExecutionRequest, IntegrationQueryParams,
     IntegrationStatus, UpdateIntegrationRequest,
 };
 use crate::scheduler::SchedulerService;
 use crate::services::IntegrationService;
+use crate::webhooks::SIGNATURE_HEADER;
 
 pub fn integration_routes() -> actix_web::Scope {
     web::scope("/integrations")
+        // Before `/{id}`, which would otherwise claim `/providers`
+        .service(list_providers)
         .service(create_integration)
         .service(get_integration)
         .service(list_integrations)
         .service(update_integration)
         .service(delete_integration)
-        .service(list_providers)
         .service(create_credential)
         .service(get_credentials)
         .service(delete_credential)
         .service(execute_integration)
         .service(get_execution)
         .service(get_recent_executions)
+        .service(receive_webhook)
 }
 
 #[post("")]
@@ -37,8 +40,8 @@ async fn create_integration(
         .create_integration(request.into_inner(), user_id)
         .await
         .map_err(|e| match e {
-            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to create integration: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -56,7 +59,7 @@ async fn get_integration(
     let id = path.into_inner();
 
     let integration = service.get_integration(id).await.map_err(|e| match e {
-        CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+        IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
         _ => {
             tracing::error!("Failed to get integration: {}", e);
             actix_web::error::ErrorInternalServerError(e)
@@ -105,8 +108,8 @@ async fn update_integration(
         .update_integration(id, request.into_inner())
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
-            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::Validation(_) => actix_web::error::ErrorBadRequest(e),
             _ => {
                 tracing::error!("Failed to update integration: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -124,7 +127,7 @@ async fn delete_integration(
     let id = path.into_inner();
 
     service.delete_integration(id).await.map_err(|e| match e {
-        CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+        IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
         _ => {
             tracing::error!("Failed to delete integration: {}", e);
             actix_web::error::ErrorInternalServerError(e)
@@ -156,8 +159,8 @@ async fn create_credential(
         .create_credential(integration_id, request.into_inner())
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
-            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::Validation(_) => actix_web::error::ErrorBadRequest(e),
             _ => {
                 tracing::error!("Failed to create credential: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -178,7 +181,7 @@ async fn get_credentials(
         .get_credentials(integration_id)
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to get credentials: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -199,7 +202,7 @@ async fn delete_credential(
         .delete_credential(integration_id, credential_id)
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to delete credential: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -225,7 +228,7 @@ async fn execute_integration(
         )
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to execute integration: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -235,6 +238,35 @@ async fn execute_integration(
     Ok(HttpResponse::Accepted().json(execution))
 }
 
+#[post("/{id}/webhook")]
+async fn receive_webhook(
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+    body: web::Bytes,
+    service: web::Data<IntegrationService>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok());
+
+    let events = service
+        .receive_webhook(id, signature, &body)
+        .await
+        .map_err(|e| match e {
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::Authentication(_) => actix_web::error::ErrorUnauthorized(e),
+            IntegrationError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to receive webhook: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({ "events": events })))
+}
+
 #[get("/{integration_id}/executions/{execution_id}")]
 async fn get_execution(
     path: web::Path<(Uuid, Uuid)>,
@@ -246,7 +278,7 @@ async fn get_execution(
         .get_execution(integration_id, execution_id)
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to get execution: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -267,7 +299,7 @@ async fn get_recent_executions(
         .get_recent_executions(integration_id)
         .await
         .map_err(|e| match e {
-            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            IntegrationError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
                 tracing::error!("Failed to get executions: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -276,3 +308,328 @@ async fn get_recent_executions(
 
     Ok(HttpResponse::Ok().json(executions))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        ApiConfig, AppConfig, DatabaseConfig, RedisConfig, SchedulerConfig, SecurityConfig,
+        ServerConfig, ServiceEndpointConfig,
+    };
+    use crate::crypto::{CryptoService, MASKED_SECRET};
+    use crate::providers::ProviderRegistry;
+    use crate::repositories::{
+        run_migrations, CredentialRepository, DbPool, ExecutionRepository, IntegrationRepository,
+    };
+    use crate::webhooks::{sign_payload, SECRET_FIELD};
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    const KEY: &str = "YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=";
+
+    fn config() -> AppConfig {
+        AppConfig {
+            server: ServerConfig {
+                port: 8008,
+                host: "127.0.0.1".into(),
+            },
+            database: DatabaseConfig {
+                url: "postgres://localhost/mirage_integrations".into(),
+                max_connections: 5,
+            },
+            redis: RedisConfig {
+                uri: "redis://127.0.0.1:6379".into(),
+                prefix: "mirage:integration".into(),
+            },
+            security: SecurityConfig {
+                encryption_key: KEY.into(),
+                jwt_secret: "test-secret".into(),
+            },
+            scheduler: SchedulerConfig {
+                enabled: false,
+                execution_interval_seconds: 60,
+            },
+            api: ApiConfig {
+                timeout_seconds: 5,
+                max_retries: 0,
+                retry_delay_ms: 0,
+            },
+            services: ServiceEndpointConfig {
+                data_collection_url: "http://127.0.0.1:8004".into(),
+                data_storage_url: "http://127.0.0.1:8005".into(),
+            },
+        }
+    }
+
+    fn service(pool: DbPool) -> web::Data<IntegrationService> {
+        service_with(pool, config())
+    }
+
+    fn service_with(pool: DbPool, config: AppConfig) -> web::Data<IntegrationService> {
+        let crypto = CryptoService::new(KEY).unwrap();
+        web::Data::new(IntegrationService::new(
+            IntegrationRepository::new(pool.clone()),
+            CredentialRepository::new(pool.clone(), crypto.clone()),
+            ExecutionRepository::new(pool),
+            ProviderRegistry::new(),
+            crypto,
+            reqwest::Client::new(),
+            config,
+        ))
+    }
+
+    #[actix_web::test]
+    async fn test_providers_route_is_not_an_integration_id() {
+        // Listing providers never touches the database
+        let pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(service(pool))
+                .service(integration_routes()),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/integrations/providers")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let providers: Value = test::read_body_json(resp).await;
+        let ids: Vec<&str> = providers
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|p| p["id"].as_str())
+            .collect();
+        assert!(ids.contains(&"http-api"), "{:?}", ids);
+    }
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_created_integrations_are_persisted_and_listed(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(service(pool.clone()))
+                .service(integration_routes()),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/integrations")
+            .set_json(json!({
+                "name": "threat feed",
+                "description": "Indicators from the feed",
+                "integration_type": "threat_intel",
+                "provider_id": "http-api",
+                "config": {
+                    "base_url": "https://feed.example.com",
+                    "method": "GET",
+                    "api_key": "feed-key"
+                },
+                "tags": ["feeds"],
+                "schedule_type": "none"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created: Value = test::read_body_json(resp).await;
+        let id = created["id"].as_str().unwrap().parse::<Uuid>().unwrap();
+
+        // The row is in the database, with its secret encrypted
+        let (name, config): (String, Value) =
+            sqlx::query_as("SELECT name, config FROM integrations WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(name, "threat feed");
+        assert_eq!(config["base_url"], "https://feed.example.com");
+        assert!(!config.to_string().contains("feed-key"));
+
+        let req = test::TestRequest::get()
+            .uri("/integrations?provider_id=http-api")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let listed: Value = test::read_body_json(resp).await;
+
+        assert_eq!(listed["total"], 1);
+        assert_eq!(listed["items"][0]["id"], created["id"]);
+        assert_eq!(listed["items"][0]["name"], "threat feed");
+        assert_eq!(listed["items"][0]["config"]["api_key"], MASKED_SECRET);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/integrations/{}", id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // Minimal data storage that accepts every request and reports its body
+    async fn mock_storage() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let (body_start, length) = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+                        let length = headers
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        break (pos + 4, length);
+                    }
+                };
+                while buf.len() < body_start + length {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+
+                let _ = tx.send(serde_json::from_slice(&buf[body_start..]).unwrap());
+                let body = json!({ "data_id": Uuid::new_v4() }).to_string();
+                let response = format!(
+                    "HTTP/1.1 201 Created\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    // A generic HTTP API integration that accepts signed webhooks
+    async fn webhook_integration(service: &IntegrationService) -> Uuid {
+        let request = serde_json::from_value(json!({
+            "name": "pushed indicators",
+            "integration_type": "threat_intel",
+            "provider_id": "http-api",
+            "config": {
+                "base_url": "https://feed.example.com",
+                "method": "GET",
+                SECRET_FIELD: "hook-secret"
+            },
+            "schedule_type": "none"
+        }))
+        .unwrap();
+
+        service.create_integration(request, None).await.unwrap().id
+    }
+
+    fn webhook_request(id: Uuid, body: &[u8], signature: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/integrations/{}/webhook", id))
+            .insert_header(("content-type", "application/json"))
+            .insert_header((SIGNATURE_HEADER, signature))
+            .set_payload(body.to_vec())
+    }
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_signed_webhook_emits_events(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let (storage_url, mut stored) = mock_storage().await;
+        let mut config = config();
+        config.services.data_storage_url = storage_url;
+        let service = service_with(pool, config);
+        let id = webhook_integration(&service).await;
+        let app =
+            test::init_service(App::new().app_data(service).service(integration_routes())).await;
+
+        let body = json!({
+            "entities": [
+                { "entity_type": "domain", "value": "evil.example", "tags": ["phishing"] },
+                { "entity_type": "ip_address", "value": "203.0.113.7", "confidence": 60 }
+            ]
+        })
+        .to_string();
+        let signature = format!("sha256={}", sign_payload(b"hook-secret", body.as_bytes()));
+
+        let resp = test::call_service(
+            &app,
+            webhook_request(id, body.as_bytes(), &signature).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let accepted: Value = test::read_body_json(resp).await;
+        assert_eq!(accepted["events"], 2);
+
+        let domain = stored.recv().await.unwrap();
+        assert_eq!(domain["source_module"], id.to_string());
+        assert_eq!(domain["entity_type"], "domain");
+        assert_eq!(domain["value"], "evil.example");
+        assert_eq!(
+            domain["metadata"]["event_source"],
+            format!("integration:{}", id)
+        );
+        assert_eq!(domain["metadata"]["tags"], r#"["phishing"]"#);
+        let ip = stored.recv().await.unwrap();
+        assert_eq!(ip["value"], "203.0.113.7");
+        assert_eq!(ip["metadata"]["confidence"], "60");
+
+        // A signed payload that isn't an entity is malformed
+        let body = br#"{"value":"no type"}"#;
+        let signature = format!("sha256={}", sign_payload(b"hook-secret", body));
+        let resp =
+            test::call_service(&app, webhook_request(id, body, &signature).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_tampered_webhook_is_unauthorized(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let service = service(pool);
+        let id = webhook_integration(&service).await;
+        let app =
+            test::init_service(App::new().app_data(service).service(integration_routes())).await;
+
+        let body = br#"{"entity_type":"domain","value":"evil.example"}"#;
+        let signature = format!("sha256={}", sign_payload(b"hook-secret", body));
+        let tampered = br#"{"entity_type":"domain","value":"good.example"}"#;
+
+        let resp =
+            test::call_service(&app, webhook_request(id, tampered, &signature).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_webhook_for_unknown_integration_is_not_found(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(service(pool))
+                .service(integration_routes()),
+        )
+        .await;
+
+        let body = br#"{"entity_type":"domain","value":"evil.example"}"#;
+        let signature = format!("sha256={}", sign_payload(b"hook-secret", body));
+
+        let resp = test::call_service(
+            &app,
+            webhook_request(Uuid::new_v4(), body, &signature).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,49 +1,155 @@
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::shutdown::{self, Shutdown};
+use mirage_common::telemetry;
 use tracing::info;
 
 mod config;
 mod crypto;
 mod error;
+mod events;
 mod handlers;
 mod models;
 mod providers;
 mod repositories;
 mod scheduler;
 mod services;
+mod throttle;
+mod webhooks;
 
-async fn health_check() -> actix_web::HttpResponse {
-    actix_web::HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // Initialize logging
+    let _telemetry = telemetry::init("integration-service");
 
-use axum::{
-    routing::{get, post},
-    Router,
-};
+    // Load configuration
+    let config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to load config: {}", e);
+            return Err(std::io::Error::other("Failed to load configuration"));
+        }
+    };
 
-#[tokio::main]
-async fn main() {
-    // initialize tracing
-    tracing_subscriber::fmt::init();
-
-    // build our application with routes
-    let app = Router::new()
-        .route("/", get(|| async { "Hello, Integration Service!" }))
-        .route("/integrations", get(list_integrations))
-        .route("/integrations", post(create_integration));
-
-    // run it
-    let addr = "0.0.0.0:3001".parse().unwrap();
-    tracing::debug!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
+    // Master key for the secrets stored with integrations
+    let crypto = match crypto::CryptoService::new(&config.security.encryption_key) {
+        Ok(crypto) => crypto,
+        Err(e) => {
+            tracing::error!("Invalid encryption key: {}", e);
+            return Err(std::io::Error::other("Invalid encryption key"));
+        }
+    };
 
-async fn list_integrations() -> &'static str {
-    "List of available integrations"
-}
+    // Initialize database connection
+    let db_pool = match repositories::create_db_pool(&config.database).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            tracing::error!("Failed to connect to database: {}", e);
+            return Err(std::io::Error::other("Failed to connect to database"));
+        }
+    };
+
+    // Initialize repositories
+    let integration_repo = repositories::IntegrationRepository::new(db_pool.clone());
+    let credential_repo = repositories::CredentialRepository::new(db_pool.clone(), crypto.clone());
+    let execution_repo = repositories::ExecutionRepository::new(db_pool.clone());
+
+    // Initialize HTTP client for provider APIs
+    let http_client = reqwest::Client::builder()
+        .timeout(config.api_timeout())
+        .build()
+        .expect("Failed to create HTTP client");
+
+    // Initialize Redis client for execution locks
+    let redis_client = match redis::Client::open(config.redis.uri.clone()) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to connect to Redis: {}", e);
+            return Err(std::io::Error::other("Failed to connect to Redis"));
+        }
+    };
+
+    // Initialize services
+    let provider_registry = providers::ProviderRegistry::new();
+
+    let scheduler_service = scheduler::SchedulerService::new(
+        integration_repo.clone(),
+        execution_repo.clone(),
+        provider_registry.clone(),
+        crypto.clone(),
+        http_client.clone(),
+        redis_client,
+        config.clone(),
+    );
+
+    let integration_service = web::Data::new(services::IntegrationService::new(
+        integration_repo,
+        credential_repo,
+        execution_repo,
+        provider_registry,
+        crypto,
+        http_client,
+        config.clone(),
+    ));
+
+    // Stops the server, then the scheduler, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+
+    // Start scheduler background task
+    let background_scheduler = scheduler_service.clone();
+    let scheduler_shutdown = shutdown.subscribe();
+    let scheduler = tokio::spawn(async move {
+        scheduler::run_scheduler(background_scheduler, scheduler_shutdown).await;
+    });
+    let scheduler_service = web::Data::new(scheduler_service);
+
+    info!(
+        "Starting Integration Service on {}:{}",
+        config.server.host, config.server.port
+    );
+
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("integration-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone()),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("integration-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::other("Failed to set up metrics"));
+        }
+    };
+
+    let bind_address = (config.server.host.clone(), config.server.port);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
+            .app_data(integration_service.clone())
+            .app_data(scheduler_service.clone())
+            .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
+            .service(
+                web::scope("/api/v1")
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
+                    .service(handlers::integration_routes()),
+            )
+    })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
+    .bind(bind_address)?
+    .run();
 
-async fn create_integration() -> &'static str {
-    "Integration created successfully"
+    shutdown.run(server, vec![scheduler]).await
 }
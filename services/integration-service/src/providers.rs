@@ -2,15 +2,20 @@ use crate::error::{IntegrationError, IntegrationResult};
 use crate::models::{
     AuthType, Credential, ExecutionRecord, ExecutionStatus, Integration, ProviderInfo,
 };
+use crate::throttle::Throttle;
 use async_trait::async_trait;
 use chrono::Utc;
-use reqwest::Client;
-use serde_json::Value;
-use std::collections::HashMap;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+// Decrypts a credential's data for a provider
+pub type DecryptFn<'a> = dyn Fn(&str) -> IntegrationResult<String> + Sync + 'a;
+
 // Provider interface for implementing different integration providers
 #[async_trait]
 pub trait Provider: Send + Sync {
@@ -31,8 +36,16 @@ pub trait Provider: Send + Sync {
         parameters: Option<&Value>,
         target: Option<&str>,
         client: &Client,
-        decrypt_fn: &dyn Fn(&str) -> IntegrationResult<String>,
+        decrypt_fn: &DecryptFn<'_>,
     ) -> IntegrationResult<(Option<i32>, Option<String>)>;
+
+    // Parse a payload pushed to the integration's webhook into entities
+    fn parse_webhook(&self, _payload: &Value) -> IntegrationResult<Vec<Value>> {
+        Err(IntegrationError::Validation(format!(
+            "Provider {} does not accept webhooks",
+            self.get_info().id
+        )))
+    }
 }
 
 // Provider registry to manage available providers
@@ -58,6 +71,20 @@ impl ProviderRegistry {
             Arc::new(TwitterProvider::new()) as Arc<dyn Provider>,
         );
 
+        // Add threat intelligence providers
+        providers.insert(
+            "shodan".to_string(),
+            Arc::new(ShodanProvider::new()) as Arc<dyn Provider>,
+        );
+        providers.insert(
+            "virustotal".to_string(),
+            Arc::new(VirusTotalProvider::new()) as Arc<dyn Provider>,
+        );
+        providers.insert(
+            "censys".to_string(),
+            Arc::new(CensysProvider::new()) as Arc<dyn Provider>,
+        );
+
         Self {
             providers: Arc::new(providers),
         }
@@ -180,7 +207,7 @@ impl Provider for HttpApiProvider {
         parameters: Option<&Value>,
         target: Option<&str>,
         client: &Client,
-        decrypt_fn: &dyn Fn(&str) -> IntegrationResult<String>,
+        decrypt_fn: &DecryptFn<'_>,
     ) -> IntegrationResult<(Option<i32>, Option<String>)> {
         // Extract config
         let config = integration
@@ -365,6 +392,79 @@ impl Provider for HttpApiProvider {
         // Return result count and raw response
         Ok((result_count, Some(response_body)))
     }
+
+    // Entities pushed as they are: one entity, a list of them, or an
+    // `{"entities": [...]}` envelope
+    fn parse_webhook(&self, payload: &Value) -> IntegrationResult<Vec<Value>> {
+        let items = match payload {
+            Value::Array(items) => items.as_slice(),
+            Value::Object(obj) => match obj.get("entities") {
+                Some(Value::Array(items)) => items.as_slice(),
+                Some(_) => {
+                    return Err(IntegrationError::Validation(
+                        "entities must be an array".into(),
+                    ))
+                }
+                None => std::slice::from_ref(payload),
+            },
+            _ => {
+                return Err(IntegrationError::Validation(
+                    "Webhook payload must be an entity or a list of entities".into(),
+                ))
+            }
+        };
+
+        items.iter().map(pushed_entity).collect()
+    }
+}
+
+// Validate an entity pushed to a webhook and fill in what it leaves out
+fn pushed_entity(item: &Value) -> IntegrationResult<Value> {
+    let field = |name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| {
+                IntegrationError::Validation(format!("Pushed entity is missing {}", name))
+            })
+    };
+    let entity_type = field("entity_type")?;
+    let value = field("value")?;
+
+    let data = match item.get("data") {
+        None | Some(Value::Null) => json!({}),
+        Some(data @ Value::Object(_)) => data.clone(),
+        Some(_) => {
+            return Err(IntegrationError::Validation(
+                "Pushed entity data must be an object".into(),
+            ))
+        }
+    };
+    let source = item
+        .get("source")
+        .and_then(|v| v.as_str())
+        .unwrap_or("webhook");
+
+    let mut pushed = entity(source, entity_type, value, data);
+    if let Some(confidence) = item.get("confidence") {
+        let confidence = confidence.as_u64().filter(|c| *c <= 100).ok_or_else(|| {
+            IntegrationError::Validation("Pushed entity confidence must be 0-100".into())
+        })?;
+        pushed["confidence"] = json!(confidence);
+    }
+    if let Some(tags) = item.get("tags") {
+        if !tags
+            .as_array()
+            .is_some_and(|tags| tags.iter().all(|t| t.is_string()))
+        {
+            return Err(IntegrationError::Validation(
+                "Pushed entity tags must be a list of strings".into(),
+            ));
+        }
+        pushed["tags"] = tags.clone();
+    }
+
+    Ok(pushed)
 }
 
 // Twitter API Provider
@@ -456,7 +556,7 @@ impl Provider for TwitterProvider {
         parameters: Option<&Value>,
         target: Option<&str>,
         client: &Client,
-        decrypt_fn: &dyn Fn(&str) -> IntegrationResult<String>,
+        decrypt_fn: &DecryptFn<'_>,
     ) -> IntegrationResult<(Option<i32>, Option<String>)> {
         // We need credentials for Twitter API
         let cred = credential.ok_or_else(|| {
@@ -619,12 +719,12 @@ impl Provider for TwitterProvider {
         let response = req_builder.send().await?;
 
         // Check for errors
-        if !response.status().is_success() {
+        let status = response.status();
+        if !status.is_success() {
             let error_body = response.text().await?;
             return Err(IntegrationError::ExternalApi(format!(
                 "Twitter API request failed with status: {}. Details: {}",
-                response.status(),
-                error_body
+                status, error_body
             )));
         }
 
@@ -654,3 +754,1490 @@ impl Provider for TwitterProvider {
         Ok((result_count, Some(response_body)))
     }
 }
+
+// Shodan API, used unless an integration overrides `base_url`
+const SHODAN_API_URL: &str = "https://api.shodan.io";
+
+// Shodan host lookup provider
+pub struct ShodanProvider;
+
+impl ShodanProvider {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    // Look up a host and map what Shodan knows about it into platform entities.
+    // An address Shodan has never seen yields no entities rather than an error.
+    pub async fn query(
+        &self,
+        client: &Client,
+        base_url: &str,
+        api_key: &str,
+        target: &str,
+    ) -> IntegrationResult<Vec<Value>> {
+        let ip: IpAddr = target.parse().map_err(|_| {
+            IntegrationError::Validation(format!(
+                "Shodan host lookups need an IP address, got '{}'",
+                target
+            ))
+        })?;
+
+        let url = format!("{}/shodan/host/{}", base_url.trim_end_matches('/'), ip);
+
+        // The key travels in the query string, so keep the URL out of errors
+        let response = client
+            .get(&url)
+            .query(&[("key", api_key)])
+            .send()
+            .await
+            .map_err(|e| IntegrationError::ExternalApi(e.without_url().to_string()))?;
+
+        let status = response.status();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(IntegrationError::Authentication(format!(
+                    "Shodan rejected the API key: {}",
+                    shodan_error(response).await
+                )))
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| format!(", retry after {}s", v))
+                    .unwrap_or_default();
+                Err(IntegrationError::RateLimited(format!(
+                    "Shodan rate limit reached{}",
+                    retry_after
+                )))
+            }
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            _ if !status.is_success() => Err(IntegrationError::ExternalApi(format!(
+                "Shodan request failed with status: {}. Details: {}",
+                status,
+                shodan_error(response).await
+            ))),
+            _ => {
+                let host: Value = response
+                    .json()
+                    .await
+                    .map_err(|e| IntegrationError::ExternalApi(e.without_url().to_string()))?;
+                Ok(host_entities(&host))
+            }
+        }
+    }
+}
+
+// Shodan explains failures in an `{"error": ...}` body
+async fn shodan_error(response: reqwest::Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+        .unwrap_or(body)
+}
+
+// Entity from `source` in the shape the data-collection service stores
+fn entity(source: &str, entity_type: &str, value: &str, data: Value) -> Value {
+    json!({
+        "entity_type": entity_type,
+        "value": value,
+        "data": data,
+        "metadata": {},
+        "confidence": 90,
+        "source": source,
+        "tags": [],
+    })
+}
+
+// Map a Shodan host record to an IP address entity plus its domains and ASN
+fn host_entities(host: &Value) -> Vec<Value> {
+    let Some(ip) = host.get("ip_str").and_then(|v| v.as_str()) else {
+        return Vec::new();
+    };
+
+    let services: Vec<Value> = host
+        .get("data")
+        .and_then(|v| v.as_array())
+        .map(|banners| {
+            banners
+                .iter()
+                .map(|banner| {
+                    json!({
+                        "port": banner.get("port"),
+                        "transport": banner.get("transport"),
+                        "product": banner.get("product"),
+                        "version": banner.get("version"),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut entities = vec![entity(
+        "shodan",
+        "ip_address",
+        ip,
+        json!({
+            "org": host.get("org"),
+            "isp": host.get("isp"),
+            "asn": host.get("asn"),
+            "os": host.get("os"),
+            "country": host.get("country_name"),
+            "country_code": host.get("country_code"),
+            "city": host.get("city"),
+            "ports": host.get("ports").cloned().unwrap_or_else(|| json!([])),
+            "vulns": host.get("vulns").cloned().unwrap_or_else(|| json!([])),
+            "services": services,
+            "last_update": host.get("last_update"),
+        }),
+    )];
+
+    // Hostnames and domains overlap, so list each name once
+    let names: BTreeSet<&str> = ["hostnames", "domains"]
+        .iter()
+        .filter_map(|key| host.get(*key).and_then(|v| v.as_array()))
+        .flatten()
+        .filter_map(|v| v.as_str())
+        .collect();
+    for name in names {
+        entities.push(entity("shodan", "domain", name, json!({ "ip": ip })));
+    }
+
+    if let Some(asn) = host.get("asn").and_then(|v| v.as_str()) {
+        entities.push(entity(
+            "shodan",
+            "asn",
+            asn,
+            json!({ "org": host.get("org"), "isp": host.get("isp") }),
+        ));
+    }
+
+    entities
+}
+
+#[async_trait]
+impl Provider for ShodanProvider {
+    fn get_info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: "shodan".to_string(),
+            name: "Shodan Provider".to_string(),
+            description: "Host lookups against the Shodan API".to_string(),
+            version: "1.0.0".to_string(),
+            auth_types: vec![AuthType::ApiKey],
+            supported_targets: vec!["ip".to_string()],
+            config_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "base_url": {
+                        "type": "string",
+                        "format": "uri",
+                        "default": SHODAN_API_URL
+                    }
+                }
+            }),
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn supports_auth_type(&self, auth_type: &AuthType) -> bool {
+        matches!(auth_type, AuthType::ApiKey)
+    }
+
+    fn validate_config(&self, config: &Value) -> IntegrationResult<()> {
+        let obj = config.as_object().ok_or_else(|| {
+            IntegrationError::Validation("Configuration must be an object".into())
+        })?;
+
+        if let Some(base_url) = obj.get("base_url") {
+            if !base_url.is_string() {
+                return Err(IntegrationError::Validation(
+                    "base_url must be a string".into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        integration: &Integration,
+        credential: Option<&Credential>,
+        _parameters: Option<&Value>,
+        target: Option<&str>,
+        client: &Client,
+        decrypt_fn: &DecryptFn<'_>,
+    ) -> IntegrationResult<(Option<i32>, Option<String>)> {
+        let cred = credential.ok_or_else(|| {
+            IntegrationError::Authentication("Shodan integration requires an API key".into())
+        })?;
+
+        let target = target.ok_or_else(|| {
+            IntegrationError::Validation("Shodan host lookups need a target IP address".into())
+        })?;
+
+        let base_url = integration
+            .config
+            .get("base_url")
+            .and_then(|v| v.as_str())
+            .unwrap_or(SHODAN_API_URL);
+
+        let api_key = match cred.auth_type {
+            AuthType::ApiKey => {
+                let auth_data = decrypt_fn(&cred.encrypted_data)?;
+                serde_json::from_str::<crate::models::ApiKeyAuth>(&auth_data)
+                    .map_err(|_| IntegrationError::Authentication("Invalid API key format".into()))?
+                    .api_key
+            }
+            _ => {
+                return Err(IntegrationError::Authentication(
+                    "Unsupported auth type for Shodan provider".into(),
+                ))
+            }
+        };
+
+        let entities = self.query(client, base_url, &api_key, target).await?;
+
+        Ok((
+            Some(entities.len() as i32),
+            Some(serde_json::to_string(&entities)?),
+        ))
+    }
+
+    // Shodan Monitor pushes the banner for each alert, on its own or in a list
+    fn parse_webhook(&self, payload: &Value) -> IntegrationResult<Vec<Value>> {
+        let banners = match payload {
+            Value::Array(banners) => banners.as_slice(),
+            _ => std::slice::from_ref(payload),
+        };
+
+        let mut entities = Vec::new();
+        for banner in banners {
+            if !banner.get("ip_str").is_some_and(|v| v.is_string()) {
+                return Err(IntegrationError::Validation(
+                    "Shodan banner is missing ip_str".into(),
+                ));
+            }
+
+            // A banner is a host record holding just the one service
+            let mut host = banner.clone();
+            host["ports"] = json!([banner.get("port")]);
+            host["data"] = json!([banner]);
+            entities.extend(host_entities(&host));
+        }
+
+        Ok(entities)
+    }
+}
+
+// VirusTotal API, used unless an integration overrides `base_url`
+const VIRUSTOTAL_API_URL: &str = "https://www.virustotal.com/api/v3";
+
+// Requests a minute allowed by VirusTotal's public API
+const VIRUSTOTAL_DEFAULT_REQUESTS_PER_MINUTE: usize = 4;
+
+// VirusTotal domain, IP address and file hash reports
+pub struct VirusTotalProvider {
+    // Shared by every VirusTotal integration, keyed by API key
+    throttle: Throttle,
+}
+
+impl VirusTotalProvider {
+    pub fn new() -> Self {
+        Self {
+            throttle: Throttle::new(Duration::from_secs(60), Duration::from_secs(60)),
+        }
+    }
+
+    // Fetch the report for a domain, IP address or file hash and map it into
+    // an entity. A target VirusTotal has never seen yields no entities.
+    pub async fn query(
+        &self,
+        client: &Client,
+        base_url: &str,
+        api_key: &str,
+        requests_per_minute: usize,
+        target: &str,
+    ) -> IntegrationResult<Vec<Value>> {
+        let (collection, entity_type, value) = virustotal_resource(target)?;
+        let url = format!(
+            "{}/{}/{}",
+            base_url.trim_end_matches('/'),
+            collection,
+            value
+        );
+
+        self.throttle.acquire(api_key, requests_per_minute).await?;
+
+        let response = client.get(&url).header("x-apikey", api_key).send().await?;
+
+        let status = response.status();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(IntegrationError::Authentication(format!(
+                    "VirusTotal rejected the API key: {}",
+                    virustotal_error(response).await
+                )))
+            }
+            // Covers both the per-minute and the daily quota
+            StatusCode::TOO_MANY_REQUESTS => Err(IntegrationError::RateLimited(format!(
+                "VirusTotal quota exceeded: {}",
+                virustotal_error(response).await
+            ))),
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            _ if !status.is_success() => Err(IntegrationError::ExternalApi(format!(
+                "VirusTotal request failed with status: {}. Details: {}",
+                status,
+                virustotal_error(response).await
+            ))),
+            _ => {
+                let report: Value = response.json().await?;
+                let attributes = report
+                    .pointer("/data/attributes")
+                    .cloned()
+                    .unwrap_or_else(|| json!({}));
+                Ok(vec![report_entity(entity_type, &value, &attributes)])
+            }
+        }
+    }
+}
+
+// The report collection, entity type and normalized value for a target
+fn virustotal_resource(target: &str) -> IntegrationResult<(&'static str, &'static str, String)> {
+    let target = target.trim();
+
+    if target.parse::<IpAddr>().is_ok() {
+        return Ok(("ip_addresses", "ip_address", target.to_string()));
+    }
+
+    // MD5, SHA-1 or SHA-256
+    if matches!(target.len(), 32 | 40 | 64) && target.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(("files", "file_hash", target.to_lowercase()));
+    }
+
+    if target.contains('.') && !target.contains(['/', ':', '@', ' ']) {
+        return Ok(("domains", "domain", target.to_lowercase()));
+    }
+
+    Err(IntegrationError::Validation(format!(
+        "VirusTotal reports need a domain, IP address or file hash, got '{}'",
+        target
+    )))
+}
+
+// VirusTotal explains failures in an `{"error": {"code": ..., "message": ...}}` body
+async fn virustotal_error(response: reqwest::Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| {
+            let error = v.get("error")?;
+            Some(format!(
+                "{} ({})",
+                error.get("message")?.as_str()?,
+                error.get("code")?.as_str()?
+            ))
+        })
+        .unwrap_or(body)
+}
+
+// Condense the engines' verdicts into one verdict and a 0-100 confidence in
+// it, so VirusTotal results weigh comparably with other sources when
+// correlating. Five engines calling a target malicious is full confidence,
+// and a clean verdict is stronger the more engines call it harmless rather
+// than just finding nothing.
+fn virustotal_verdict(stats: &Value) -> (&'static str, u8) {
+    let count = |key: &str| stats.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+    let malicious = count("malicious");
+    let suspicious = count("suspicious");
+    let harmless = count("harmless");
+    let undetected = count("undetected");
+
+    if malicious > 0 {
+        (
+            "malicious",
+            (20 * malicious + 10 * suspicious).min(100) as u8,
+        )
+    } else if suspicious > 0 {
+        ("suspicious", (10 * suspicious).min(100) as u8)
+    } else if let Some(share) = (50 * harmless).checked_div(harmless + undetected) {
+        ("clean", (50 + share) as u8)
+    } else {
+        ("unknown", 0)
+    }
+}
+
+// Map a report's attributes to an entity carrying the verdict
+fn report_entity(entity_type: &str, value: &str, attributes: &Value) -> Value {
+    let stats = attributes
+        .get("last_analysis_stats")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    let (verdict, confidence) = virustotal_verdict(&stats);
+
+    let mut data = json!({
+        "verdict": verdict,
+        "last_analysis_stats": stats,
+        "last_analysis_date": attributes.get("last_analysis_date"),
+        "reputation": attributes.get("reputation"),
+    });
+    for key in [
+        "as_owner",
+        "asn",
+        "country",
+        "registrar",
+        "meaningful_name",
+        "type_description",
+        "size",
+    ] {
+        if let Some(v) = attributes.get(key) {
+            data[key] = v.clone();
+        }
+    }
+
+    let mut entity = entity("virustotal", entity_type, value, data);
+    entity["confidence"] = json!(confidence);
+    if matches!(verdict, "malicious" | "suspicious") {
+        entity["tags"] = json!([verdict]);
+    }
+    entity
+}
+
+#[async_trait]
+impl Provider for VirusTotalProvider {
+    fn get_info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: "virustotal".to_string(),
+            name: "VirusTotal Provider".to_string(),
+            description: "Domain, IP address and file hash reports from VirusTotal".to_string(),
+            version: "1.0.0".to_string(),
+            auth_types: vec![AuthType::None],
+            supported_targets: vec![
+                "domain".to_string(),
+                "ip".to_string(),
+                "file_hash".to_string(),
+            ],
+            config_schema: serde_json::json!({
+                "type": "object",
+                "required": ["api_key"],
+                "properties": {
+                    "api_key": {
+                        "type": "string"
+                    },
+                    "base_url": {
+                        "type": "string",
+                        "format": "uri",
+                        "default": VIRUSTOTAL_API_URL
+                    },
+                    "requests_per_minute": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "default": VIRUSTOTAL_DEFAULT_REQUESTS_PER_MINUTE
+                    }
+                }
+            }),
+            metadata: HashMap::new(),
+        }
+    }
+
+    // The API key lives in the integration's encrypted config
+    fn supports_auth_type(&self, auth_type: &AuthType) -> bool {
+        matches!(auth_type, AuthType::None)
+    }
+
+    fn validate_config(&self, config: &Value) -> IntegrationResult<()> {
+        let obj = config.as_object().ok_or_else(|| {
+            IntegrationError::Validation("Configuration must be an object".into())
+        })?;
+
+        if !obj.get("api_key").is_some_and(|v| v.is_string()) {
+            return Err(IntegrationError::Validation(
+                "Missing required field: api_key".into(),
+            ));
+        }
+
+        if obj.get("base_url").is_some_and(|v| !v.is_string()) {
+            return Err(IntegrationError::Validation(
+                "base_url must be a string".into(),
+            ));
+        }
+
+        if let Some(limit) = obj.get("requests_per_minute") {
+            if limit.as_u64().is_none_or(|n| n == 0) {
+                return Err(IntegrationError::Validation(
+                    "requests_per_minute must be a positive integer".into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        integration: &Integration,
+        _credential: Option<&Credential>,
+        _parameters: Option<&Value>,
+        target: Option<&str>,
+        client: &Client,
+        _decrypt_fn: &DecryptFn<'_>,
+    ) -> IntegrationResult<(Option<i32>, Option<String>)> {
+        let target = target.ok_or_else(|| {
+            IntegrationError::Validation(
+                "VirusTotal reports need a target domain, IP address or file hash".into(),
+            )
+        })?;
+
+        let config = &integration.config;
+        let api_key = config
+            .get("api_key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                IntegrationError::Authentication(
+                    "VirusTotal integration requires an api_key".into(),
+                )
+            })?;
+        let base_url = config
+            .get("base_url")
+            .and_then(|v| v.as_str())
+            .unwrap_or(VIRUSTOTAL_API_URL);
+        let requests_per_minute = config
+            .get("requests_per_minute")
+            .and_then(|v| v.as_u64())
+            .map_or(VIRUSTOTAL_DEFAULT_REQUESTS_PER_MINUTE, |n| n as usize);
+
+        let entities = self
+            .query(client, base_url, api_key, requests_per_minute, target)
+            .await?;
+
+        Ok((
+            Some(entities.len() as i32),
+            Some(serde_json::to_string(&entities)?),
+        ))
+    }
+}
+
+// Censys Search API, used unless an integration overrides `base_url`
+const CENSYS_API_URL: &str = "https://search.censys.io/api";
+
+// Search result pages fetched per query unless configured otherwise
+const CENSYS_DEFAULT_MAX_PAGES: u64 = 5;
+
+// Hits per search page; the most Censys returns
+const CENSYS_PAGE_SIZE: &str = "100";
+
+// Censys host records and certificate searches
+pub struct CensysProvider;
+
+impl CensysProvider {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    // Look up an IP address's host record, or search the certificates naming
+    // a domain, and map the results into entities. Certificate search stops
+    // after `max_pages` pages.
+    pub async fn query(
+        &self,
+        client: &Client,
+        base_url: &str,
+        api_id: &str,
+        api_secret: &str,
+        max_pages: u64,
+        target: &str,
+    ) -> IntegrationResult<Vec<Value>> {
+        let target = target.trim();
+        let base_url = base_url.trim_end_matches('/');
+
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            let url = format!("{}/v2/hosts/{}", base_url, ip);
+            let host = censys_get(client, &url, &[], api_id, api_secret).await?;
+            return Ok(host.map(|h| censys_host_entities(&h)).unwrap_or_default());
+        }
+
+        let Some((domain, _)) = certificate_name(target) else {
+            return Err(IntegrationError::Validation(format!(
+                "Censys lookups need an IP address or a domain, got '{}'",
+                target
+            )));
+        };
+
+        let url = format!("{}/v2/certificates/search", base_url);
+        let query = format!("names: {}", domain);
+        let mut certificates = Vec::new();
+        let mut cursor = String::new();
+        for _ in 0..max_pages.max(1) {
+            let mut params = vec![("q", query.as_str()), ("per_page", CENSYS_PAGE_SIZE)];
+            if !cursor.is_empty() {
+                params.push(("cursor", cursor.as_str()));
+            }
+
+            let Some(page) = censys_get(client, &url, &params, api_id, api_secret).await? else {
+                break;
+            };
+            if let Some(hits) = page.get("hits").and_then(|v| v.as_array()) {
+                certificates.extend(hits.iter().cloned());
+            }
+
+            cursor = page
+                .pointer("/links/next")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            if cursor.is_empty() {
+                break;
+            }
+        }
+
+        Ok(certificate_domains(&domain, &certificates))
+    }
+}
+
+// GET a Censys endpoint and return the `result` of its response, or None for
+// a 404
+async fn censys_get(
+    client: &Client,
+    url: &str,
+    params: &[(&str, &str)],
+    api_id: &str,
+    api_secret: &str,
+) -> IntegrationResult<Option<Value>> {
+    let response = client
+        .get(url)
+        .query(params)
+        .basic_auth(api_id, Some(api_secret))
+        .send()
+        .await?;
+
+    let status = response.status();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(IntegrationError::Authentication(format!(
+                "Censys rejected the API credentials: {}",
+                censys_error(response).await
+            )))
+        }
+        StatusCode::TOO_MANY_REQUESTS => Err(IntegrationError::RateLimited(format!(
+            "Censys rate limit reached: {}",
+            censys_error(response).await
+        ))),
+        StatusCode::NOT_FOUND => Ok(None),
+        _ if !status.is_success() => Err(IntegrationError::ExternalApi(format!(
+            "Censys request failed with status: {}. Details: {}",
+            status,
+            censys_error(response).await
+        ))),
+        _ => {
+            let body: Value = response.json().await?;
+            Ok(Some(body.get("result").cloned().unwrap_or(Value::Null)))
+        }
+    }
+}
+
+// Censys explains failures in an `{"error": ...}` body
+async fn censys_error(response: reqwest::Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+        .unwrap_or(body)
+}
+
+// A certificate name as a lowercase host name, and whether it was a
+// wildcard. Anything else a certificate can name, such as IP addresses and
+// email addresses, is None.
+fn certificate_name(name: &str) -> Option<(String, bool)> {
+    let name = name.trim().trim_end_matches('.').to_lowercase();
+    let (name, wildcard) = match name.strip_prefix("*.") {
+        Some(base) => (base.to_string(), true),
+        None => (name, false),
+    };
+
+    let is_host = name.contains('.')
+        && name.parse::<IpAddr>().is_err()
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+
+    is_host.then_some((name, wildcard))
+}
+
+// Names a certificate is valid for: its SANs and its subject's common name.
+// Search hits, full certificate records and the leaf certificates on host
+// services each keep them in different places.
+fn certificate_names(certificate: &Value) -> Vec<(String, bool)> {
+    let listed = [
+        "/names",
+        "/parsed/names",
+        "/parsed/extensions/subject_alt_name/dns_names",
+        "/parsed/subject/common_name",
+    ]
+    .iter()
+    .filter_map(|pointer| certificate.pointer(pointer).and_then(|v| v.as_array()))
+    .flatten()
+    .filter_map(|v| v.as_str());
+
+    let common_names = ["/subject_dn", "/parsed/subject_dn"]
+        .iter()
+        .filter_map(|pointer| certificate.pointer(pointer).and_then(|v| v.as_str()))
+        .flat_map(|dn| dn.split(','))
+        .filter_map(|part| part.trim().strip_prefix("CN="));
+
+    let names: BTreeSet<(String, bool)> = listed
+        .chain(common_names)
+        .filter_map(certificate_name)
+        .collect();
+    names.into_iter().collect()
+}
+
+// One domain entity per name on the certificates, listing the certificates
+// it appears on, so subdomains of `domain` can be followed up
+fn certificate_domains(domain: &str, certificates: &[Value]) -> Vec<Value> {
+    let mut names: BTreeMap<String, (bool, BTreeSet<String>)> = BTreeMap::new();
+    for certificate in certificates {
+        let fingerprint = certificate
+            .get("fingerprint_sha256")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+
+        for (name, wildcard) in certificate_names(certificate) {
+            let (seen_as_wildcard, fingerprints) = names.entry(name).or_default();
+            *seen_as_wildcard |= wildcard;
+            if !fingerprint.is_empty() {
+                fingerprints.insert(fingerprint.to_string());
+            }
+        }
+    }
+
+    let suffix = format!(".{}", domain);
+    names
+        .into_iter()
+        .map(|(name, (wildcard, fingerprints))| {
+            let parent = name.ends_with(&suffix).then_some(domain);
+            entity(
+                "censys",
+                "domain",
+                &name,
+                json!({
+                    "parent_domain": parent,
+                    "wildcard": wildcard,
+                    "certificates": fingerprints,
+                }),
+            )
+        })
+        .collect()
+}
+
+// Map a Censys host record to an IP address entity plus the names it serves
+// and its ASN
+fn censys_host_entities(host: &Value) -> Vec<Value> {
+    let Some(ip) = host.get("ip").and_then(|v| v.as_str()) else {
+        return Vec::new();
+    };
+
+    let service_list = host
+        .get("services")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let services: Vec<Value> = service_list
+        .iter()
+        .map(|service| {
+            json!({
+                "port": service.get("port"),
+                "transport": service.get("transport_protocol"),
+                "service_name": service.get("service_name"),
+            })
+        })
+        .collect();
+
+    let autonomous_system = host.get("autonomous_system");
+    let asn = autonomous_system
+        .and_then(|a| a.get("asn"))
+        .and_then(|v| v.as_u64());
+    let as_name = autonomous_system.and_then(|a| a.get("name"));
+
+    let mut entities = vec![entity(
+        "censys",
+        "ip_address",
+        ip,
+        json!({
+            "asn": asn.map(|n| format!("AS{}", n)),
+            "as_name": as_name,
+            "bgp_prefix": autonomous_system.and_then(|a| a.get("bgp_prefix")),
+            "country": host.pointer("/location/country"),
+            "country_code": host.pointer("/location/country_code"),
+            "city": host.pointer("/location/city"),
+            "services": services,
+            "last_updated_at": host.get("last_updated_at"),
+        }),
+    )];
+
+    // Names from DNS and from the certificates the host presents
+    let dns_names = ["/dns/names", "/dns/reverse_dns/names"]
+        .iter()
+        .filter_map(|pointer| host.pointer(pointer).and_then(|v| v.as_array()))
+        .flatten()
+        .filter_map(|v| v.as_str())
+        .filter_map(certificate_name);
+    let tls_names = service_list
+        .iter()
+        .filter_map(|service| service.pointer("/tls/certificates/leaf_data"))
+        .flat_map(certificate_names);
+    let names: BTreeSet<String> = dns_names.chain(tls_names).map(|(name, _)| name).collect();
+    for name in names {
+        entities.push(entity("censys", "domain", &name, json!({ "ip": ip })));
+    }
+
+    if let Some(asn) = asn {
+        entities.push(entity(
+            "censys",
+            "asn",
+            &format!("AS{}", asn),
+            json!({ "name": as_name }),
+        ));
+    }
+
+    entities
+}
+
+#[async_trait]
+impl Provider for CensysProvider {
+    fn get_info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: "censys".to_string(),
+            name: "Censys Provider".to_string(),
+            description: "Host records and certificate names from Censys Search".to_string(),
+            version: "1.0.0".to_string(),
+            auth_types: vec![AuthType::None],
+            supported_targets: vec!["ip".to_string(), "domain".to_string()],
+            config_schema: serde_json::json!({
+                "type": "object",
+                "required": ["api_id", "api_secret"],
+                "properties": {
+                    "api_id": {
+                        "type": "string"
+                    },
+                    "api_secret": {
+                        "type": "string"
+                    },
+                    "base_url": {
+                        "type": "string",
+                        "format": "uri",
+                        "default": CENSYS_API_URL
+                    },
+                    "max_pages": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "default": CENSYS_DEFAULT_MAX_PAGES
+                    }
+                }
+            }),
+            metadata: HashMap::new(),
+        }
+    }
+
+    // The API credentials live in the integration's encrypted config
+    fn supports_auth_type(&self, auth_type: &AuthType) -> bool {
+        matches!(auth_type, AuthType::None)
+    }
+
+    fn validate_config(&self, config: &Value) -> IntegrationResult<()> {
+        let obj = config.as_object().ok_or_else(|| {
+            IntegrationError::Validation("Configuration must be an object".into())
+        })?;
+
+        for field in ["api_id", "api_secret"] {
+            if !obj.get(field).is_some_and(|v| v.is_string()) {
+                return Err(IntegrationError::Validation(format!(
+                    "Missing required field: {}",
+                    field
+                )));
+            }
+        }
+
+        if obj.get("base_url").is_some_and(|v| !v.is_string()) {
+            return Err(IntegrationError::Validation(
+                "base_url must be a string".into(),
+            ));
+        }
+
+        if let Some(max_pages) = obj.get("max_pages") {
+            if max_pages.as_u64().is_none_or(|n| n == 0) {
+                return Err(IntegrationError::Validation(
+                    "max_pages must be a positive integer".into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        integration: &Integration,
+        _credential: Option<&Credential>,
+        _parameters: Option<&Value>,
+        target: Option<&str>,
+        client: &Client,
+        _decrypt_fn: &DecryptFn<'_>,
+    ) -> IntegrationResult<(Option<i32>, Option<String>)> {
+        let target = target.ok_or_else(|| {
+            IntegrationError::Validation("Censys lookups need a target IP address or domain".into())
+        })?;
+
+        let config = &integration.config;
+        let credential = |field: &str| {
+            config.get(field).and_then(|v| v.as_str()).ok_or_else(|| {
+                IntegrationError::Authentication(format!(
+                    "Censys integration requires an {}",
+                    field
+                ))
+            })
+        };
+        let api_id = credential("api_id")?;
+        let api_secret = credential("api_secret")?;
+        let base_url = config
+            .get("base_url")
+            .and_then(|v| v.as_str())
+            .unwrap_or(CENSYS_API_URL);
+        let max_pages = config
+            .get("max_pages")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(CENSYS_DEFAULT_MAX_PAGES);
+
+        let entities = self
+            .query(client, base_url, api_id, api_secret, max_pages, target)
+            .await?;
+
+        Ok((
+            Some(entities.len() as i32),
+            Some(serde_json::to_string(&entities)?),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, Engine as _};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    // Minimal HTTP endpoint that answers every request with `status`, `headers`
+    // and `body`, and reports the head of each request it receives
+    async fn mock_api(
+        status: u16,
+        headers: &'static str,
+        body: Value,
+    ) -> (String, mpsc::UnboundedReceiver<String>) {
+        mock_api_pages(status, headers, vec![body]).await
+    }
+
+    // Like `mock_api`, but answers the n-th request with the n-th body, and
+    // any after the last with the last
+    async fn mock_api_pages(
+        status: u16,
+        headers: &'static str,
+        bodies: Vec<Value>,
+    ) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut bodies = bodies.into_iter();
+            let mut body = Value::Null;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                if let Some(next) = bodies.next() {
+                    body = next;
+                }
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let _ = tx.send(String::from_utf8_lossy(&buf).into_owned());
+
+                let body = body.to_string();
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-type: application/json\r\n{}content-length: {}\r\n\r\n{}",
+                    status,
+                    headers,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_host_lookup_maps_to_entities() {
+        let (url, mut requests) = mock_api(
+            200,
+            "",
+            json!({
+                "ip_str": "93.184.216.34",
+                "org": "Edgecast",
+                "isp": "Verizon",
+                "asn": "AS15133",
+                "os": null,
+                "country_name": "United States",
+                "country_code": "US",
+                "city": "Norwell",
+                "hostnames": ["example.com"],
+                "domains": ["example.com", "example.net"],
+                "ports": [80, 443],
+                "vulns": ["CVE-2021-41773"],
+                "last_update": "2024-01-01T00:00:00.000000",
+                "data": [
+                    {"port": 443, "transport": "tcp", "product": "nginx", "version": "1.25.3"}
+                ]
+            }),
+        )
+        .await;
+
+        let entities = ShodanProvider::new()
+            .query(&Client::new(), &url, "test-key", "93.184.216.34")
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("GET /shodan/host/93.184.216.34?key=test-key "));
+
+        assert_eq!(entities.len(), 4);
+        let host = &entities[0];
+        assert_eq!(host["entity_type"], "ip_address");
+        assert_eq!(host["value"], "93.184.216.34");
+        assert_eq!(host["source"], "shodan");
+        assert_eq!(host["data"]["org"], "Edgecast");
+        assert_eq!(host["data"]["ports"], json!([80, 443]));
+        assert_eq!(host["data"]["vulns"], json!(["CVE-2021-41773"]));
+        assert_eq!(host["data"]["services"][0]["product"], "nginx");
+
+        let domains: Vec<&Value> = entities
+            .iter()
+            .filter(|e| e["entity_type"] == "domain")
+            .map(|e| &e["value"])
+            .collect();
+        assert_eq!(domains, vec!["example.com", "example.net"]);
+        assert_eq!(entities[3]["entity_type"], "asn");
+        assert_eq!(entities[3]["value"], "AS15133");
+
+        // Every entity carries the fields the data-collection service requires
+        for entity in &entities {
+            assert!(entity["confidence"].is_u64());
+            assert!(entity["metadata"].is_object());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_key_is_an_authentication_error() {
+        let (url, _requests) =
+            mock_api(401, "", json!({"error": "Please provide a valid API key"})).await;
+
+        let err = ShodanProvider::new()
+            .query(&Client::new(), &url, "bad-key", "93.184.216.34")
+            .await
+            .unwrap_err();
+
+        match err {
+            IntegrationError::Authentication(msg) => {
+                assert!(msg.contains("Please provide a valid API key"));
+                assert!(!msg.contains("bad-key"));
+            }
+            other => panic!("expected an authentication error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_is_distinct_from_other_failures() {
+        let (url, _requests) = mock_api(
+            429,
+            "retry-after: 30\r\n",
+            json!({"error": "Rate limit reached"}),
+        )
+        .await;
+
+        let err = ShodanProvider::new()
+            .query(&Client::new(), &url, "test-key", "93.184.216.34")
+            .await
+            .unwrap_err();
+
+        match err {
+            IntegrationError::RateLimited(msg) => assert!(msg.contains("retry after 30s")),
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_host_has_no_entities() {
+        let (url, _requests) = mock_api(
+            404,
+            "",
+            json!({"error": "No information available for that IP."}),
+        )
+        .await;
+
+        let entities = ShodanProvider::new()
+            .query(&Client::new(), &url, "test-key", "10.0.0.1")
+            .await
+            .unwrap();
+        assert!(entities.is_empty());
+    }
+
+    #[test]
+    fn test_shodan_is_registered() {
+        let registry = ProviderRegistry::new();
+        let provider = registry.get_provider("shodan").unwrap();
+        assert_eq!(provider.get_info().id, "shodan");
+        assert!(provider.supports_auth_type(&AuthType::ApiKey));
+    }
+
+    fn virustotal() -> VirusTotalProvider {
+        VirusTotalProvider {
+            throttle: Throttle::new(Duration::from_secs(60), Duration::from_secs(1)),
+        }
+    }
+
+    fn virustotal_report(stats: Value) -> Value {
+        json!({
+            "data": {
+                "id": "example.com",
+                "type": "domain",
+                "attributes": {
+                    "last_analysis_stats": stats,
+                    "last_analysis_date": 1700000000,
+                    "reputation": 0,
+                    "registrar": "RESERVED-Internet Assigned Numbers Authority"
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_clean_virustotal_report() {
+        let (url, mut requests) = mock_api(
+            200,
+            "",
+            virustotal_report(json!({
+                "harmless": 60, "malicious": 0, "suspicious": 0, "undetected": 20, "timeout": 0
+            })),
+        )
+        .await;
+
+        let entities = virustotal()
+            .query(&Client::new(), &url, "vt-key", 4, "Example.com")
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("GET /domains/example.com "));
+        assert!(request.to_lowercase().contains("x-apikey: vt-key"));
+
+        assert_eq!(entities.len(), 1);
+        let domain = &entities[0];
+        assert_eq!(domain["entity_type"], "domain");
+        assert_eq!(domain["value"], "example.com");
+        assert_eq!(domain["source"], "virustotal");
+        assert_eq!(domain["data"]["verdict"], "clean");
+        assert_eq!(domain["confidence"], 87);
+        assert_eq!(domain["tags"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_flagged_virustotal_report() {
+        let (url, mut requests) = mock_api(
+            200,
+            "",
+            virustotal_report(json!({
+                "harmless": 50, "malicious": 3, "suspicious": 1, "undetected": 20, "timeout": 0
+            })),
+        )
+        .await;
+
+        let hash = "44d88612fea8a8f36de82e1278abb02f";
+        let entities = virustotal()
+            .query(&Client::new(), &url, "vt-key", 4, hash)
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with(&format!("GET /files/{} ", hash)));
+
+        let file = &entities[0];
+        assert_eq!(file["entity_type"], "file_hash");
+        assert_eq!(file["data"]["verdict"], "malicious");
+        assert_eq!(file["data"]["last_analysis_stats"]["malicious"], 3);
+        assert_eq!(file["confidence"], 70);
+        assert_eq!(file["tags"], json!(["malicious"]));
+    }
+
+    #[tokio::test]
+    async fn test_virustotal_quota_is_retryable() {
+        let (url, _requests) = mock_api(
+            429,
+            "",
+            json!({"error": {"code": "QuotaExceededError", "message": "Quota exceeded"}}),
+        )
+        .await;
+
+        let err = virustotal()
+            .query(&Client::new(), &url, "vt-key", 4, "8.8.8.8")
+            .await
+            .unwrap_err();
+
+        assert!(err.is_retryable());
+        match err {
+            IntegrationError::RateLimited(msg) => assert!(msg.contains("QuotaExceededError")),
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_virustotal_requests_share_the_quota() {
+        let (url, _requests) = mock_api(200, "", virustotal_report(json!({"harmless": 1}))).await;
+        let provider = virustotal();
+
+        provider
+            .query(&Client::new(), &url, "vt-key", 1, "example.com")
+            .await
+            .unwrap();
+        let err = provider
+            .query(&Client::new(), &url, "vt-key", 1, "example.org")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, IntegrationError::RateLimited(_)));
+    }
+
+    #[test]
+    fn test_virustotal_targets() {
+        assert!(virustotal_resource("not a target").is_err());
+        assert_eq!(virustotal_resource("8.8.8.8").unwrap().0, "ip_addresses");
+        assert_eq!(virustotal_resource("example.com").unwrap().0, "domains");
+    }
+
+    fn censys_certificates_page(hits: Value, next: &str) -> Value {
+        json!({
+            "code": 200,
+            "status": "OK",
+            "result": {
+                "query": "names: example.com",
+                "total": 3,
+                "hits": hits,
+                "links": { "prev": "", "next": next }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_censys_host_maps_to_entities() {
+        let (url, mut requests) = mock_api(
+            200,
+            "",
+            json!({
+                "code": 200,
+                "status": "OK",
+                "result": {
+                    "ip": "8.8.8.8",
+                    "services": [
+                        {
+                            "port": 443,
+                            "service_name": "HTTP",
+                            "transport_protocol": "TCP",
+                            "tls": {"certificates": {"leaf_data": {
+                                "names": ["dns.google", "*.dns.google.com", "8.8.8.8"],
+                                "subject_dn": "CN=dns.google"
+                            }}}
+                        },
+                        {"port": 53, "service_name": "DNS", "transport_protocol": "UDP"}
+                    ],
+                    "location": {"country": "United States", "country_code": "US"},
+                    "autonomous_system": {"asn": 15169, "name": "GOOGLE", "bgp_prefix": "8.8.8.0/24"},
+                    "dns": {"reverse_dns": {"names": ["dns.google."]}},
+                    "last_updated_at": "2024-01-01T00:00:00.000Z"
+                }
+            }),
+        )
+        .await;
+
+        let entities = CensysProvider::new()
+            .query(
+                &Client::new(),
+                &url,
+                "censys-id",
+                "censys-secret",
+                5,
+                "8.8.8.8",
+            )
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("GET /v2/hosts/8.8.8.8 "));
+        let basic = general_purpose::STANDARD.encode("censys-id:censys-secret");
+        assert!(request
+            .to_lowercase()
+            .contains(&format!("authorization: basic {}", basic.to_lowercase())));
+
+        let host = &entities[0];
+        assert_eq!(host["entity_type"], "ip_address");
+        assert_eq!(host["value"], "8.8.8.8");
+        assert_eq!(host["source"], "censys");
+        assert_eq!(host["data"]["asn"], "AS15169");
+        assert_eq!(host["data"]["services"][1]["port"], 53);
+        assert_eq!(host["data"]["services"][1]["transport"], "UDP");
+
+        // Reverse DNS and the TLS certificate name the same host once
+        let domains: Vec<&Value> = entities
+            .iter()
+            .filter(|e| e["entity_type"] == "domain")
+            .map(|e| &e["value"])
+            .collect();
+        assert_eq!(domains, vec!["dns.google", "dns.google.com"]);
+        assert_eq!(entities.last().unwrap()["entity_type"], "asn");
+        assert_eq!(entities.last().unwrap()["value"], "AS15169");
+    }
+
+    #[tokio::test]
+    async fn test_censys_certificate_names_become_domains() {
+        let (url, mut requests) = mock_api_pages(
+            200,
+            "",
+            vec![
+                censys_certificates_page(
+                    json!([{
+                        "fingerprint_sha256": "aa11",
+                        "names": ["example.com", "*.example.com", "www.example.com"],
+                        "parsed": {"subject_dn": "C=US, O=Example Inc., CN=example.com"}
+                    }]),
+                    "cursor-2",
+                ),
+                censys_certificates_page(
+                    json!([{
+                        "fingerprint_sha256": "bb22",
+                        "parsed": {
+                            "subject": {"common_name": ["API.example.com"]},
+                            "extensions": {"subject_alt_name": {
+                                "dns_names": ["api.example.com", "www.example.com", "cdn.example.net"],
+                                "email_addresses": ["admin@example.com"]
+                            }}
+                        }
+                    }]),
+                    "cursor-3",
+                ),
+                censys_certificates_page(
+                    json!([{"fingerprint_sha256": "cc33", "names": ["beyond.example.com"]}]),
+                    "",
+                ),
+            ],
+        )
+        .await;
+
+        let entities = CensysProvider::new()
+            .query(
+                &Client::new(),
+                &url,
+                "censys-id",
+                "censys-secret",
+                2,
+                "Example.com",
+            )
+            .await
+            .unwrap();
+
+        // The page cap stops the search before the third page
+        let first = requests.recv().await.unwrap();
+        assert!(
+            first.starts_with("GET /v2/certificates/search?q=names%3A+example.com&per_page=100 ")
+        );
+        let second = requests.recv().await.unwrap();
+        assert!(second.contains("&cursor=cursor-2 "));
+        assert!(requests.try_recv().is_err());
+
+        let names: Vec<&Value> = entities.iter().map(|e| &e["value"]).collect();
+        assert_eq!(
+            names,
+            vec![
+                "api.example.com",
+                "cdn.example.net",
+                "example.com",
+                "www.example.com"
+            ]
+        );
+        assert!(entities.iter().all(|e| e["entity_type"] == "domain"));
+
+        let by_name = |name: &str| entities.iter().find(|e| e["value"] == name).unwrap();
+        assert_eq!(
+            by_name("www.example.com")["data"]["certificates"],
+            json!(["aa11", "bb22"])
+        );
+        assert_eq!(
+            by_name("www.example.com")["data"]["parent_domain"],
+            "example.com"
+        );
+        assert_eq!(by_name("example.com")["data"]["wildcard"], true);
+        assert_eq!(by_name("example.com")["data"]["parent_domain"], Value::Null);
+        assert_eq!(
+            by_name("cdn.example.net")["data"]["parent_domain"],
+            Value::Null
+        );
+    }
+
+    #[tokio::test]
+    async fn test_censys_errors() {
+        let (url, _requests) = mock_api(
+            401,
+            "",
+            json!({"code": 401, "status": "Unauthorized", "error": "Invalid API ID or secret"}),
+        )
+        .await;
+        match CensysProvider::new()
+            .query(&Client::new(), &url, "censys-id", "wrong", 5, "8.8.8.8")
+            .await
+        {
+            Err(IntegrationError::Authentication(msg)) => {
+                assert!(msg.contains("Invalid API ID or secret"))
+            }
+            other => panic!("expected an authentication error, got {:?}", other),
+        }
+
+        let (url, _requests) = mock_api(404, "", json!({"code": 404, "error": "Not found"})).await;
+        let entities = CensysProvider::new()
+            .query(
+                &Client::new(),
+                &url,
+                "censys-id",
+                "censys-secret",
+                5,
+                "10.0.0.1",
+            )
+            .await
+            .unwrap();
+        assert!(entities.is_empty());
+
+        assert!(matches!(
+            CensysProvider::new()
+                .query(
+                    &Client::new(),
+                    &url,
+                    "censys-id",
+                    "censys-secret",
+                    5,
+                    "not a target"
+                )
+                .await,
+            Err(IntegrationError::Validation(_))
+        ));
+    }
+}
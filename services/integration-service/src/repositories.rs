@@ -19,12 +19,19 @@ pub async fn create_db_pool(config: &DatabaseConfig) -> IntegrationResult<DbPool
         .connect(&config.url)
         .await?;
 
-    // Run migrations
-    sqlx::migrate!("./migrations").run(&pool).await?;
+    run_migrations(&pool).await?;
 
     Ok(pool)
 }
 
+/// Applies the migrations in `migrations/` that haven't run yet
+pub async fn run_migrations(pool: &DbPool) -> IntegrationResult<()> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .map_err(|e| IntegrationError::Database(format!("Migration failed: {}", e)))
+}
+
 #[derive(Clone)]
 pub struct IntegrationRepository {
     pool: DbPool,
@@ -246,8 +253,8 @@ impl IntegrationRepository {
                 schedule_type as "schedule_type: ScheduleType", schedule_config, 
                 last_execution, next_execution, error_message, metadata
             FROM integrations
-            WHERE status = 'active'::integration_status 
-              AND schedule_type != 'none'::schedule_type
+            WHERE status = 'active'
+              AND schedule_type != 'none'
               AND (next_execution IS NULL OR next_execution <= $1)
             ORDER BY next_execution ASC NULLS FIRST
             "#,
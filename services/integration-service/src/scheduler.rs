@@ -1,4 +1,5 @@
 use crate::config::AppConfig;
+use crate::crypto::CryptoService;
 use crate::error::{IntegrationError, IntegrationResult};
 use crate::models::{
     Credential, ExecutionRecord, ExecutionStatus, Integration, IntegrationStatus, ScheduleType,
@@ -7,6 +8,7 @@ use crate::providers::ProviderRegistry;
 use crate::repositories::{ExecutionRepository, IntegrationRepository};
 use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use cron::Schedule;
+use mirage_common::shutdown::ShutdownSignal;
 use redis::{AsyncCommands, Client as RedisClient};
 use reqwest::Client;
 use std::str::FromStr;
@@ -15,11 +17,15 @@ use tokio::time::{sleep, Duration, Instant};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+// How long a scheduled execution that hit a retryable error waits to run again
+const RETRY_DELAY_SECONDS: i64 = 60;
+
 #[derive(Clone)]
 pub struct SchedulerService {
     integration_repo: Arc<IntegrationRepository>,
     execution_repo: Arc<ExecutionRepository>,
     provider_registry: Arc<ProviderRegistry>,
+    crypto: Arc<CryptoService>,
     http_client: Arc<Client>,
     redis_client: Arc<RedisClient>,
     config: Arc<AppConfig>,
@@ -30,6 +36,7 @@ impl SchedulerService {
         integration_repo: IntegrationRepository,
         execution_repo: ExecutionRepository,
         provider_registry: ProviderRegistry,
+        crypto: CryptoService,
         http_client: Client,
         redis_client: RedisClient,
         config: AppConfig,
@@ -38,6 +45,7 @@ impl SchedulerService {
             integration_repo: Arc::new(integration_repo),
             execution_repo: Arc::new(execution_repo),
             provider_registry: Arc::new(provider_registry),
+            crypto: Arc::new(crypto),
             http_client: Arc::new(http_client),
             redis_client: Arc::new(redis_client),
             config: Arc::new(config),
@@ -107,7 +115,7 @@ impl SchedulerService {
         }
 
         // Set expiry on lock
-        redis_conn.expire(&lock_key, 600).await?; // 10 minutes
+        redis_conn.expire::<_, ()>(&lock_key, 600).await?; // 10 minutes
 
         // Execute integration
         let start_time = Instant::now();
@@ -161,13 +169,20 @@ impl SchedulerService {
                 execution.error_message = Some(format!("{}", e));
                 execution.execution_time_ms = Some(elapsed.as_millis() as i64);
 
-                // Update integration with error
                 let mut updated_integration = integration.clone();
-                updated_integration.status = IntegrationStatus::Failed;
-                updated_integration.error_message = Some(format!("Execution failed: {}", e));
                 updated_integration.last_execution = Some(started_at);
-                updated_integration.next_execution =
-                    self.calculate_next_execution(&updated_integration);
+                if e.is_retryable() {
+                    // A used-up quota comes back, so try again soon rather
+                    // than failing the integration
+                    updated_integration.next_execution =
+                        Some(Utc::now() + ChronoDuration::seconds(RETRY_DELAY_SECONDS));
+                } else {
+                    // Update integration with error
+                    updated_integration.status = IntegrationStatus::Failed;
+                    updated_integration.error_message = Some(format!("Execution failed: {}", e));
+                    updated_integration.next_execution =
+                        self.calculate_next_execution(&updated_integration);
+                }
 
                 // Update integration
                 self.integration_repo
@@ -352,6 +367,12 @@ impl SchedulerService {
                 ))
             })?;
 
+        // The provider needs the config's secrets in plaintext
+        let integration = &Integration {
+            config: self.crypto.decrypt_config(&integration.config)?,
+            ..integration.clone()
+        };
+
         // Get credentials if any
         let credentials = self
             .get_credentials_for_integration(&integration.id)
@@ -400,21 +421,21 @@ impl SchedulerService {
         credential: &Credential,
         data: &str,
     ) -> IntegrationResult<String> {
-        // This would use a crypto service to decrypt the data
-        // For now, just return the data itself
-        Ok(data.to_string())
+        self.crypto.decrypt(data)
     }
 }
 
-// Background scheduler task
-pub async fn run_scheduler(scheduler: SchedulerService) {
+// Background scheduler task, which runs until `shutdown`
+pub async fn run_scheduler(scheduler: SchedulerService, mut shutdown: ShutdownSignal) {
     info!("Starting scheduler background task");
 
     loop {
         if !scheduler.config.scheduler.enabled {
             info!("Scheduler is disabled, sleeping for 60 seconds");
-            sleep(Duration::from_secs(60)).await;
-            continue;
+            tokio::select! {
+                _ = sleep(Duration::from_secs(60)) => continue,
+                _ = shutdown.recv() => break,
+            }
         }
 
         info!("Checking for scheduled integrations to execute");
@@ -449,6 +470,11 @@ pub async fn run_scheduler(scheduler: SchedulerService) {
         // Sleep until next cycle
         let interval = scheduler.config.scheduler_interval();
         info!("Scheduler sleeping for {} seconds", interval.as_secs());
-        sleep(interval).await;
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = shutdown.recv() => break,
+        }
     }
+
+    info!("Scheduler stopped");
 }
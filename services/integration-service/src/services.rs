@@ -1,5 +1,7 @@
 use crate::config::AppConfig;
+use crate::crypto::{self, CryptoService};
 use crate::error::{IntegrationError, IntegrationResult};
+use crate::events::EventPublisher;
 use crate::models::{
     AuthType, CreateIntegrationRequest, Credential, CredentialRequest, CredentialResponse,
     ExecutionRecord, ExecutionRequest, ExecutionResponse, ExecutionStatus, Integration,
@@ -8,6 +10,7 @@ use crate::models::{
 };
 use crate::providers::{Provider, ProviderRegistry};
 use crate::repositories::{CredentialRepository, ExecutionRepository, IntegrationRepository};
+use crate::webhooks;
 use chrono::{DateTime, Duration, Utc};
 use cron::Schedule;
 use reqwest::Client;
@@ -21,7 +24,10 @@ use uuid::Uuid;
 pub struct IntegrationService {
     integration_repo: Arc<IntegrationRepository>,
     credential_repo: Arc<CredentialRepository>,
+    execution_repo: Arc<ExecutionRepository>,
     provider_registry: Arc<ProviderRegistry>,
+    crypto: Arc<CryptoService>,
+    events: Arc<EventPublisher>,
     client: Arc<Client>,
     config: Arc<AppConfig>,
 }
@@ -30,14 +36,22 @@ impl IntegrationService {
     pub fn new(
         integration_repo: IntegrationRepository,
         credential_repo: CredentialRepository,
+        execution_repo: ExecutionRepository,
         provider_registry: ProviderRegistry,
+        crypto: CryptoService,
         client: Client,
         config: AppConfig,
     ) -> Self {
         Self {
             integration_repo: Arc::new(integration_repo),
             credential_repo: Arc::new(credential_repo),
+            execution_repo: Arc::new(execution_repo),
             provider_registry: Arc::new(provider_registry),
+            crypto: Arc::new(crypto),
+            events: Arc::new(EventPublisher::new(
+                client.clone(),
+                &config.services.data_storage_url,
+            )),
             client: Arc::new(client),
             config: Arc::new(config),
         }
@@ -134,7 +148,8 @@ impl IntegrationService {
             integration_type: request.integration_type,
             provider_id: request.provider_id,
             status: IntegrationStatus::Active,
-            config: request.config,
+            // Secrets are only stored encrypted
+            config: self.crypto.encrypt_config(&request.config)?,
             created_at: now,
             updated_at: now,
             created_by: user_id,
@@ -153,24 +168,7 @@ impl IntegrationService {
             .await?;
 
         // Convert to response
-        let response = IntegrationResponse {
-            id: integration.id,
-            name: integration.name,
-            description: integration.description,
-            integration_type: integration.integration_type,
-            provider_id: integration.provider_id,
-            status: integration.status,
-            config: integration.config,
-            created_at: integration.created_at,
-            updated_at: integration.updated_at,
-            tags: integration.tags,
-            schedule_type: integration.schedule_type,
-            schedule_config: integration.schedule_config,
-            last_execution: integration.last_execution,
-            next_execution: integration.next_execution,
-            metadata: integration.metadata,
-            has_credentials: false, // No credentials yet
-        };
+        let response = integration_response(integration, false);
 
         Ok(response)
     }
@@ -193,24 +191,7 @@ impl IntegrationService {
         let has_credentials = !credentials.is_empty();
 
         // Convert to response
-        let response = IntegrationResponse {
-            id: integration.id,
-            name: integration.name,
-            description: integration.description,
-            integration_type: integration.integration_type,
-            provider_id: integration.provider_id,
-            status: integration.status,
-            config: integration.config,
-            created_at: integration.created_at,
-            updated_at: integration.updated_at,
-            tags: integration.tags,
-            schedule_type: integration.schedule_type,
-            schedule_config: integration.schedule_config,
-            last_execution: integration.last_execution,
-            next_execution: integration.next_execution,
-            metadata: integration.metadata,
-            has_credentials,
-        };
+        let response = integration_response(integration, has_credentials);
 
         Ok(response)
     }
@@ -231,7 +212,10 @@ impl IntegrationService {
             })?;
 
         // Get provider to validate updated config if needed
-        if let Some(ref new_config) = request.config {
+        if let Some(mut new_config) = request.config {
+            // Secrets sent back masked, as they were returned, are unchanged
+            crypto::keep_masked_secrets(&mut new_config, &integration.config)?;
+
             let provider = self
                 .provider_registry
                 .get_provider(&integration.provider_id)
@@ -243,9 +227,9 @@ impl IntegrationService {
                 })?;
 
             // Validate updated configuration
-            provider.validate_config(new_config)?;
+            provider.validate_config(&new_config)?;
 
-            integration.config = new_config.clone();
+            integration.config = self.crypto.encrypt_config(&new_config)?;
         }
 
         // Update fields from request
@@ -254,7 +238,7 @@ impl IntegrationService {
         }
 
         if let Some(description) = request.description {
-            integration.description = description;
+            integration.description = Some(description);
         }
 
         if let Some(status) = request.status {
@@ -356,24 +340,7 @@ impl IntegrationService {
         let has_credentials = !credentials.is_empty();
 
         // Convert to response
-        let response = IntegrationResponse {
-            id: integration.id,
-            name: integration.name,
-            description: integration.description,
-            integration_type: integration.integration_type,
-            provider_id: integration.provider_id,
-            status: integration.status,
-            config: integration.config,
-            created_at: integration.created_at,
-            updated_at: integration.updated_at,
-            tags: integration.tags,
-            schedule_type: integration.schedule_type,
-            schedule_config: integration.schedule_config,
-            last_execution: integration.last_execution,
-            next_execution: integration.next_execution,
-            metadata: integration.metadata,
-            has_credentials,
-        };
+        let response = integration_response(integration, has_credentials);
 
         Ok(response)
     }
@@ -433,24 +400,7 @@ impl IntegrationService {
                 .await?;
             let has_credentials = !credentials.is_empty();
 
-            responses.push(IntegrationResponse {
-                id: integration.id,
-                name: integration.name,
-                description: integration.description,
-                integration_type: integration.integration_type,
-                provider_id: integration.provider_id,
-                status: integration.status,
-                config: integration.config,
-                created_at: integration.created_at,
-                updated_at: integration.updated_at,
-                tags: integration.tags,
-                schedule_type: integration.schedule_type,
-                schedule_config: integration.schedule_config,
-                last_execution: integration.last_execution,
-                next_execution: integration.next_execution,
-                metadata: integration.metadata,
-                has_credentials,
-            });
+            responses.push(integration_response(integration, has_credentials));
         }
 
         // Calculate pagination info
@@ -649,7 +599,7 @@ impl IntegrationService {
 
         // Get execution record
         let execution = self
-            .get_execution_repo()
+            .execution_repo
             .get_execution_by_id(&execution_id)
             .await?
             .ok_or_else(|| {
@@ -700,7 +650,7 @@ impl IntegrationService {
 
         // Get recent executions
         let executions = self
-            .get_execution_repo()
+            .execution_repo
             .get_recent_executions(&integration_id, 10)
             .await?;
 
@@ -730,14 +680,136 @@ impl IntegrationService {
         Ok(providers)
     }
 
-    // Helper method to get ExecutionRepository
-    // This should be in the struct, but for this example I'm stubbing it
-    fn get_execution_repo(&self) -> Arc<ExecutionRepository> {
-        // This would come from the instance, I'm just creating a placeholder
-        // because we didn't actually store it in the struct
-        // In a real implementation, this would be stored in the struct
-        Arc::new(ExecutionRepository::new(
-            sqlx::PgPool::connect_lazy("postgres://localhost").unwrap(),
-        ))
+    // Verify a payload pushed to an integration's webhook and emit the
+    // entities its provider finds in it, returning how many were emitted
+    pub async fn receive_webhook(
+        &self,
+        id: Uuid,
+        signature: Option<&str>,
+        body: &[u8],
+    ) -> IntegrationResult<usize> {
+        let integration = self
+            .integration_repo
+            .get_integration_by_id(&id)
+            .await?
+            .ok_or_else(|| {
+                IntegrationError::NotFound(format!("Integration with ID {} not found", id))
+            })?;
+
+        let config = self.crypto.decrypt_config(&integration.config)?;
+        let secret = config
+            .get(webhooks::SECRET_FIELD)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| {
+                IntegrationError::Authentication(format!(
+                    "Integration {} has no {} to verify webhooks with",
+                    id,
+                    webhooks::SECRET_FIELD
+                ))
+            })?;
+        webhooks::verify_signature(secret.as_bytes(), body, signature)?;
+
+        let provider = self
+            .provider_registry
+            .get_provider(&integration.provider_id)
+            .ok_or_else(|| {
+                IntegrationError::Provider(format!(
+                    "Provider {} not found",
+                    integration.provider_id
+                ))
+            })?;
+        let events = webhooks::payload_events(id, provider.as_ref(), body)?;
+
+        self.events.publish(id, &events).await?;
+        info!(
+            "Received webhook for integration '{}' ({}) - Emitted {} events",
+            integration.name,
+            id,
+            events.len()
+        );
+
+        Ok(events.len())
+    }
+}
+
+// Convert an integration to its API response, masking the config's secrets
+fn integration_response(integration: Integration, has_credentials: bool) -> IntegrationResponse {
+    IntegrationResponse {
+        id: integration.id,
+        name: integration.name,
+        description: integration.description,
+        integration_type: integration.integration_type,
+        provider_id: integration.provider_id,
+        status: integration.status,
+        config: crypto::mask_config(&integration.config),
+        created_at: integration.created_at,
+        updated_at: integration.updated_at,
+        tags: integration.tags,
+        schedule_type: integration.schedule_type,
+        schedule_config: integration.schedule_config,
+        last_execution: integration.last_execution,
+        next_execution: integration.next_execution,
+        metadata: integration.metadata,
+        has_credentials,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::MASKED_SECRET;
+    use serde_json::json;
+
+    fn integration(config: serde_json::Value) -> Integration {
+        Integration {
+            id: Uuid::new_v4(),
+            name: "threat feed".into(),
+            description: None,
+            integration_type: IntegrationType::ThreatIntel,
+            provider_id: "http-api".into(),
+            status: IntegrationStatus::Active,
+            config,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            created_by: None,
+            tags: vec![],
+            schedule_type: ScheduleType::None,
+            schedule_config: None,
+            last_execution: None,
+            next_execution: None,
+            error_message: None,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_listed_integrations_mask_secrets() {
+        let crypto = CryptoService::new("YFtqP9UIUl6ExTaAdxcOP9jBd0YF7ISskthV8fl3jdE=").unwrap();
+        let config = json!({
+            "base_url": "https://feed.example.com",
+            "method": "GET",
+            "headers": { "Authorization": "Bearer feed-token" },
+            "api_key": "feed-key"
+        });
+        let stored = integration(crypto.encrypt_config(&config).unwrap());
+
+        let listed = serde_json::to_string(&PaginatedResponse {
+            items: vec![integration_response(stored, true)],
+            total: 1,
+            page: 1,
+            per_page: 20,
+            pages: 1,
+        })
+        .unwrap();
+        let listed: serde_json::Value = serde_json::from_str(&listed).unwrap();
+        let item = &listed["items"][0];
+
+        assert_eq!(item["config"]["api_key"], MASKED_SECRET);
+        assert_eq!(item["config"]["headers"]["Authorization"], MASKED_SECRET);
+        assert_eq!(item["config"]["base_url"], "https://feed.example.com");
+        for secret in ["feed-key", "feed-token", "ciphertext"] {
+            assert!(!listed.to_string().contains(secret));
+        }
     }
 }
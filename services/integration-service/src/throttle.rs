@@ -0,0 +1,102 @@
+//! Per-minute request quotas for provider APIs
+//!
+//! Some provider APIs allow only a handful of requests a minute per API key.
+//! A provider holds one `Throttle` for all of its integrations, so requests
+//! sharing a key also share its quota. A caller waits for a free slot, up to
+//! `max_wait`; past that it gets a `RateLimited` error to retry later instead
+//! of tying up an execution.
+
+use crate::error::{IntegrationError, IntegrationResult};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone)]
+pub struct Throttle {
+    window: Duration,
+    max_wait: Duration,
+    sent: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl Throttle {
+    pub fn new(window: Duration, max_wait: Duration) -> Self {
+        Self {
+            window,
+            max_wait,
+            sent: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits until a request under `key` fits in `limit` requests per window
+    pub async fn acquire(&self, key: &str, limit: usize) -> IntegrationResult<()> {
+        let limit = limit.max(1);
+
+        loop {
+            let wait = {
+                let mut sent = self.sent.lock().unwrap();
+                let times = sent.entry(key.to_string()).or_default();
+                let now = Instant::now();
+                while times
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= self.window)
+                {
+                    times.pop_front();
+                }
+
+                if times.len() < limit {
+                    times.push_back(now);
+                    return Ok(());
+                }
+
+                // The oldest request leaving the window frees the next slot
+                *times.front().unwrap() + self.window - now
+            };
+
+            if wait > self.max_wait {
+                return Err(IntegrationError::RateLimited(format!(
+                    "Quota of {} requests per {}s used up, next slot in {}s",
+                    limit,
+                    self.window.as_secs(),
+                    wait.as_secs_f64().ceil()
+                )));
+            }
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_requests_past_the_quota_wait_for_the_window() {
+        let throttle = Throttle::new(Duration::from_millis(200), Duration::from_secs(1));
+        let started = Instant::now();
+
+        throttle.acquire("key", 2).await.unwrap();
+        throttle.acquire("key", 2).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        throttle.acquire("key", 2).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_keys_have_separate_quotas() {
+        let throttle = Throttle::new(Duration::from_secs(60), Duration::ZERO);
+
+        throttle.acquire("first", 1).await.unwrap();
+        throttle.acquire("second", 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_waits_past_max_wait_are_rate_limited() {
+        let throttle = Throttle::new(Duration::from_secs(60), Duration::from_secs(1));
+
+        throttle.acquire("key", 1).await.unwrap();
+        let err = throttle.acquire("key", 1).await.unwrap_err();
+        assert!(matches!(err, IntegrationError::RateLimited(_)));
+    }
+}
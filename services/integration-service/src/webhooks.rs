@@ -0,0 +1,108 @@
+//! Signed webhooks pushed by external tools
+//!
+//! A tool that pushes data rather than being polled POSTs JSON to
+//! `/integrations/{id}/webhook`. The body is signed with HMAC-SHA256 keyed
+//! with the integration's `webhook_secret` config field, and the hex digest
+//! is sent in the `X-Mirage-Signature` header as `sha256=<digest>`, the same
+//! scheme the scanner coordinator signs its callbacks with. The integration's
+//! provider turns a verified payload into entities, each emitted as an
+//! `EntityCreated` event.
+
+use crate::error::{IntegrationError, IntegrationResult};
+use crate::events;
+use crate::providers::Provider;
+use hmac::{Hmac, Mac};
+use mirage_common::event::Event;
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub const SIGNATURE_HEADER: &str = "X-Mirage-Signature";
+
+// Config field holding the secret webhook payloads are signed with
+pub const SECRET_FIELD: &str = "webhook_secret";
+
+/// Hex-encoded HMAC-SHA256 of `body` keyed with `secret`
+#[cfg(test)]
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks `signature`, the `X-Mirage-Signature` header, against `body`
+pub fn verify_signature(
+    secret: &[u8],
+    body: &[u8],
+    signature: Option<&str>,
+) -> IntegrationResult<()> {
+    let signature = signature.ok_or_else(|| {
+        IntegrationError::Authentication(format!("Missing {} header", SIGNATURE_HEADER))
+    })?;
+    let digest = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex_digest| hex::decode(hex_digest).ok())
+        .ok_or_else(|| IntegrationError::Authentication("Malformed webhook signature".into()))?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    // Constant-time comparison
+    mac.verify_slice(&digest).map_err(|_| {
+        IntegrationError::Authentication("Webhook signature does not match the payload".into())
+    })
+}
+
+/// Parses a verified payload with the integration's provider into events
+pub fn payload_events(
+    integration_id: Uuid,
+    provider: &dyn Provider,
+    body: &[u8],
+) -> IntegrationResult<Vec<Event>> {
+    let payload: Value = serde_json::from_slice(body).map_err(|e| {
+        IntegrationError::Validation(format!("Webhook payload is not valid JSON: {}", e))
+    })?;
+
+    Ok(provider
+        .parse_webhook(&payload)?
+        .into_iter()
+        .map(|entity| events::entity_created(integration_id, entity))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"webhook-secret";
+
+    #[test]
+    fn test_signed_payload_verifies() {
+        let body = br#"{"entity_type":"domain","value":"example.com"}"#;
+        let signature = format!("sha256={}", sign_payload(SECRET, body));
+
+        assert!(verify_signature(SECRET, body, Some(&signature)).is_ok());
+    }
+
+    #[test]
+    fn test_bad_signatures_are_rejected() {
+        let body = br#"{"entity_type":"domain","value":"example.com"}"#;
+        let signature = format!("sha256={}", sign_payload(SECRET, body));
+
+        let tampered = br#"{"entity_type":"domain","value":"example.org"}"#;
+        let other_secret = format!("sha256={}", sign_payload(b"other", body));
+        for (body, signature) in [
+            (&tampered[..], Some(signature.as_str())),
+            (&body[..], Some(other_secret.as_str())),
+            (&body[..], Some("sha256=not-hex")),
+            (&body[..], Some(sign_payload(SECRET, body).as_str())),
+            (&body[..], None),
+        ] {
+            let err = verify_signature(SECRET, body, signature).unwrap_err();
+            assert!(
+                matches!(err, IntegrationError::Authentication(_)),
+                "{:?}",
+                signature
+            );
+        }
+    }
+}
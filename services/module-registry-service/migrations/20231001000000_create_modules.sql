@@ -0,0 +1,26 @@
+-- Create modules table
+--
+-- Catalog entries and uploaded packages share the table, so the columns only
+-- one of them writes have defaults
+CREATE TABLE IF NOT EXISTS modules (
+    id UUID PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    version VARCHAR(50) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    author VARCHAR(255) NOT NULL DEFAULT '',
+    license VARCHAR(100) NOT NULL DEFAULT '',
+    dependencies TEXT[] NOT NULL DEFAULT '{}',
+    capabilities TEXT[] NOT NULL DEFAULT '{}',
+    required_capabilities TEXT[] NOT NULL DEFAULT '{}',
+    configuration JSONB NOT NULL DEFAULT '{}'::jsonb,
+    parameters JSONB NOT NULL DEFAULT '{}'::jsonb,
+    metadata JSONB NOT NULL DEFAULT '{}'::jsonb,
+    status VARCHAR(50) NOT NULL DEFAULT 'active',
+    file_path TEXT NOT NULL DEFAULT '',
+    hash VARCHAR(128) NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (name, version)
+);
+
+CREATE INDEX IF NOT EXISTS idx_modules_capabilities ON modules USING GIN (capabilities);
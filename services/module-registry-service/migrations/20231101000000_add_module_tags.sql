@@ -0,0 +1,4 @@
+-- Tags let the catalog be browsed by category
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_modules_tags ON modules USING GIN (tags);
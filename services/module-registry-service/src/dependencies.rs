@@ -0,0 +1,536 @@
+//! Module dependency graph
+//!
+//! A dependency is either a module ID, pinning that exact module, or a module
+//! name with an optional semver requirement (`dns-scanner >=1.2` or
+//! `dns-scanner@^1.2`). Every module in a load order must agree on the
+//! version of each name, so resolving picks the newest version that satisfies
+//! all requirements on a name, and falls back to older ones when a choice
+//! leads to a conflict. Before a module is registered or its dependencies
+//! change, the graph is checked so every dependency resolves and no chain of
+//! dependencies leads back to the module.
+
+use mirage_common::{Error, Result};
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Parses a module version, which must be valid semver
+pub fn parse_version(version: &str) -> Result<Version> {
+    Version::parse(version)
+        .map_err(|_| Error::Validation(format!("Invalid version format: {}", version)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DependencySpec {
+    Id(Uuid),
+    Name {
+        name: String,
+        requirement: VersionReq,
+    },
+}
+
+impl DependencySpec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if let Ok(id) = Uuid::parse_str(spec) {
+            return Ok(Self::Id(id));
+        }
+
+        let (name, requirement) = match spec.split_once(|c: char| c == '@' || c.is_whitespace()) {
+            Some((name, requirement)) => (name, requirement.trim()),
+            None => (spec, "*"),
+        };
+        if name.is_empty() {
+            return Err(Error::Validation(format!(
+                "Dependency \"{}\" has no module name",
+                spec
+            )));
+        }
+
+        let requirement = VersionReq::parse(requirement).map_err(|e| {
+            Error::Validation(format!(
+                "Invalid version requirement in dependency \"{}\": {}",
+                spec, e
+            ))
+        })?;
+
+        Ok(Self::Name {
+            name: name.to_string(),
+            requirement,
+        })
+    }
+}
+
+struct Node {
+    name: String,
+    version: Version,
+    dependencies: Vec<String>,
+}
+
+/// What one module needs of a dependency
+struct Requirement {
+    dependent: String,
+    /// The dependency as the module declared it
+    dependency: String,
+    name: String,
+    requirement: VersionReq,
+    /// Set when the dependency names a module by ID
+    id: Option<Uuid>,
+}
+
+impl Requirement {
+    fn allows(&self, id: &Uuid, node: &Node) -> bool {
+        self.id.is_none_or(|required| required == *id) && self.requirement.matches(&node.version)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+#[derive(Default)]
+struct Resolution {
+    visits: HashMap<Uuid, Visit>,
+    path: Vec<Uuid>,
+    order: Vec<Uuid>,
+}
+
+#[derive(Default)]
+pub struct DependencyGraph {
+    nodes: HashMap<Uuid, Node>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a module, replacing any earlier entry with the same ID
+    pub fn insert(
+        &mut self,
+        id: Uuid,
+        name: impl Into<String>,
+        version: Version,
+        dependencies: Vec<String>,
+    ) {
+        self.nodes.insert(
+            id,
+            Node {
+                name: name.into(),
+                version,
+                dependencies,
+            },
+        );
+    }
+
+    /// Checks that everything `id` depends on resolves and is acyclic
+    pub fn validate(&self, id: &Uuid) -> Result<()> {
+        self.load_order(id).map(|_| ())
+    }
+
+    /// Modules in the order they must be loaded for `id`: each module comes
+    /// after all of its dependencies, and `id` itself comes last
+    pub fn load_order(&self, id: &Uuid) -> Result<Vec<Uuid>> {
+        let root = self
+            .nodes
+            .get(id)
+            .ok_or_else(|| Error::NotFound(format!("Module with ID {} not found", id)))?;
+
+        let mut chosen = BTreeMap::from([(root.name.clone(), *id)]);
+        self.solve(&mut chosen)?;
+
+        let mut resolution = Resolution::default();
+        self.visit(*id, &chosen, &mut resolution)?;
+
+        Ok(resolution.order)
+    }
+
+    /// Picks a module for every name the modules in `chosen` depend on, so
+    /// that each satisfies all requirements on its name. Newer versions are
+    /// tried first; when one leads to a conflict further down, the next
+    /// older one is tried instead.
+    fn solve(&self, chosen: &mut BTreeMap<String, Uuid>) -> Result<()> {
+        let mut requirements: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+        for id in chosen.values() {
+            for requirement in self.requirements(id)? {
+                requirements
+                    .entry(requirement.name.clone())
+                    .or_default()
+                    .push(requirement);
+            }
+        }
+
+        // Later choices can add requirements on names chosen earlier
+        for (name, id) in chosen.iter() {
+            let node = &self.nodes[id];
+            let unmet = requirements
+                .get(name)
+                .and_then(|reqs| reqs.iter().find(|r| !r.allows(id, node)));
+            if let Some(unmet) = unmet {
+                return Err(Error::Conflict(format!(
+                    "{} requires {} {}, but {} {} was selected",
+                    unmet.dependent, name, unmet.requirement, name, node.version
+                )));
+            }
+        }
+
+        let Some((name, reqs)) = requirements
+            .iter()
+            .find(|(name, _)| !chosen.contains_key(*name))
+        else {
+            return Ok(());
+        };
+
+        let mut candidates: Vec<(&Uuid, &Node)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| &node.name == name)
+            .collect();
+        if candidates.is_empty() {
+            return Err(Error::Validation(format!(
+                "Module {} depends on unknown module {}",
+                reqs[0].dependent, reqs[0].dependency
+            )));
+        }
+        candidates.sort_by(|a, b| b.1.version.cmp(&a.1.version));
+
+        let matching: Vec<Uuid> = candidates
+            .iter()
+            .filter(|(id, node)| reqs.iter().all(|r| r.allows(id, node)))
+            .map(|(id, _)| **id)
+            .collect();
+        if matching.is_empty() {
+            return Err(Self::unsatisfiable(name, reqs, &candidates));
+        }
+
+        let name = name.clone();
+        let mut failure = None;
+        for id in matching {
+            chosen.insert(name.clone(), id);
+            match self.solve(chosen) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    chosen.remove(&name);
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+
+        Err(failure.expect("at least one candidate was tried"))
+    }
+
+    /// The dependencies `id` declares, parsed
+    fn requirements(&self, id: &Uuid) -> Result<Vec<Requirement>> {
+        let node = &self.nodes[id];
+        node.dependencies
+            .iter()
+            .map(|dependency| {
+                let (name, requirement, id) = match DependencySpec::parse(dependency)? {
+                    DependencySpec::Id(id) => {
+                        let target = self.nodes.get(&id).ok_or_else(|| {
+                            Error::Validation(format!(
+                                "Module {} depends on unknown module {}",
+                                node.name, dependency
+                            ))
+                        })?;
+                        (target.name.clone(), VersionReq::STAR, Some(id))
+                    }
+                    DependencySpec::Name { name, requirement } => (name, requirement, None),
+                };
+
+                Ok(Requirement {
+                    dependent: node.name.clone(),
+                    dependency: dependency.clone(),
+                    name,
+                    requirement,
+                    id,
+                })
+            })
+            .collect()
+    }
+
+    fn unsatisfiable(name: &str, reqs: &[Requirement], candidates: &[(&Uuid, &Node)]) -> Error {
+        let mut available: Vec<String> = candidates
+            .iter()
+            .map(|(_, node)| node.version.to_string())
+            .collect();
+        available.reverse();
+
+        if let [requirement] = reqs {
+            return Error::Conflict(format!(
+                "{} requires {} {}, but only versions {} are registered",
+                requirement.dependent,
+                name,
+                requirement.requirement,
+                available.join(", ")
+            ));
+        }
+
+        let wanted: Vec<String> = reqs
+            .iter()
+            .map(|r| format!("{} ({})", r.dependent, r.requirement))
+            .collect();
+        Error::Conflict(format!(
+            "No version of {} satisfies {}; registered versions are {}",
+            name,
+            wanted.join(", "),
+            available.join(", ")
+        ))
+    }
+
+    fn visit(
+        &self,
+        id: Uuid,
+        chosen: &BTreeMap<String, Uuid>,
+        resolution: &mut Resolution,
+    ) -> Result<()> {
+        match resolution.visits.get(&id) {
+            Some(Visit::Done) => return Ok(()),
+            Some(Visit::InProgress) => return Err(self.cycle_error(&resolution.path, id)),
+            None => {}
+        }
+
+        resolution.visits.insert(id, Visit::InProgress);
+        resolution.path.push(id);
+
+        for requirement in self.requirements(&id)? {
+            self.visit(chosen[&requirement.name], chosen, resolution)?;
+        }
+
+        resolution.path.pop();
+        resolution.visits.insert(id, Visit::Done);
+        resolution.order.push(id);
+        Ok(())
+    }
+
+    /// Names the cycle `path` closes by reaching `id` again
+    fn cycle_error(&self, path: &[Uuid], id: Uuid) -> Error {
+        let start = path.iter().position(|p| *p == id).unwrap_or(0);
+        let names: Vec<&str> = path[start..]
+            .iter()
+            .chain(std::iter::once(&id))
+            .map(|p| self.nodes[p].name.as_str())
+            .collect();
+
+        Error::Validation(format!("Dependency cycle: {}", names.join(" -> ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn test_missing_dependency_is_rejected() {
+        let mut graph = DependencyGraph::new();
+        let scanner = Uuid::new_v4();
+        let missing = Uuid::new_v4();
+        graph.insert(scanner, "scanner", v("1.0.0"), vec![missing.to_string()]);
+
+        match graph.validate(&scanner) {
+            Err(Error::Validation(message)) => {
+                assert!(message.contains(&missing.to_string()), "{}", message)
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_three_node_cycle_is_named() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut graph = DependencyGraph::new();
+        graph.insert(a, "a", v("1.0.0"), vec![b.to_string()]);
+        graph.insert(b, "b", v("1.0.0"), vec![c.to_string()]);
+        graph.insert(c, "c", v("1.0.0"), vec![a.to_string()]);
+
+        match graph.validate(&a) {
+            Err(Error::Validation(message)) => {
+                assert_eq!(message, "Dependency cycle: a -> b -> c -> a")
+            }
+            other => panic!("expected a cycle error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_order_puts_dependencies_first() {
+        let (dns, http, crawler, report) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let mut graph = DependencyGraph::new();
+        graph.insert(dns, "dns", v("1.0.0"), vec![]);
+        graph.insert(http, "http", v("1.0.0"), vec![dns.to_string()]);
+        graph.insert(
+            crawler,
+            "crawler",
+            v("1.0.0"),
+            vec![http.to_string(), dns.to_string()],
+        );
+        graph.insert(
+            report,
+            "report",
+            v("1.0.0"),
+            vec![crawler.to_string(), http.to_string()],
+        );
+
+        let order = graph.load_order(&report).unwrap();
+        assert_eq!(order, vec![dns, http, crawler, report]);
+
+        assert!(matches!(
+            graph.load_order(&Uuid::new_v4()),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_satisfiable_constraint_picks_newest_match() {
+        let (dns_1_1, dns_1_3, dns_2, probe) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let mut graph = DependencyGraph::new();
+        graph.insert(dns_1_1, "dns-scanner", v("1.1.0"), vec![]);
+        graph.insert(dns_1_3, "dns-scanner", v("1.3.0"), vec![]);
+        graph.insert(dns_2, "dns-scanner", v("2.0.0"), vec![]);
+        graph.insert(
+            probe,
+            "http-probe",
+            v("0.4.0"),
+            vec!["dns-scanner >=1.2, <2".to_string()],
+        );
+
+        assert_eq!(graph.load_order(&probe).unwrap(), vec![dns_1_3, probe]);
+    }
+
+    #[test]
+    fn test_unsatisfiable_constraints_explain_the_conflict() {
+        let (dns_1, dns_2, probe, crawler, report) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let mut graph = DependencyGraph::new();
+        graph.insert(dns_1, "dns-scanner", v("1.4.0"), vec![]);
+        graph.insert(dns_2, "dns-scanner", v("2.1.0"), vec![]);
+        graph.insert(
+            probe,
+            "http-probe",
+            v("1.0.0"),
+            vec!["dns-scanner@^3".to_string()],
+        );
+        graph.insert(
+            crawler,
+            "crawler",
+            v("1.0.0"),
+            vec!["dns-scanner@^1".to_string()],
+        );
+        graph.insert(
+            report,
+            "report",
+            v("1.0.0"),
+            vec!["crawler".to_string(), "dns-scanner ^2".to_string()],
+        );
+
+        match graph.validate(&probe) {
+            Err(Error::Conflict(message)) => assert_eq!(
+                message,
+                "http-probe requires dns-scanner ^3, but only versions 1.4.0, 2.1.0 are registered"
+            ),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+
+        match graph.validate(&report) {
+            Err(Error::Conflict(message)) => assert_eq!(
+                message,
+                "No version of dns-scanner satisfies crawler (^1), report (^2); registered versions are 1.4.0, 2.1.0"
+            ),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_version_satisfying_every_dependent_is_chosen() {
+        let (dns_1, dns_2, whois, probe, scan) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let mut graph = DependencyGraph::new();
+        graph.insert(dns_1, "dns", v("1.4.0"), vec![]);
+        graph.insert(dns_2, "dns", v("2.0.0"), vec![]);
+        graph.insert(whois, "whois", v("1.0.0"), vec!["dns *".to_string()]);
+        graph.insert(probe, "probe", v("1.0.0"), vec!["dns ^1".to_string()]);
+        graph.insert(
+            scan,
+            "scan",
+            v("1.0.0"),
+            vec!["whois".to_string(), "probe".to_string()],
+        );
+
+        assert_eq!(
+            graph.load_order(&scan).unwrap(),
+            vec![dns_1, whois, probe, scan]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_choice_is_backtracked() {
+        let (dns_1, dns_2, probe, scan) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let mut graph = DependencyGraph::new();
+        graph.insert(dns_1, "a-dns", v("1.4.0"), vec![]);
+        graph.insert(dns_2, "a-dns", v("2.0.0"), vec![]);
+        graph.insert(probe, "probe", v("1.0.0"), vec!["a-dns ^1".to_string()]);
+        // a-dns is picked before probe's requirement on it is known
+        graph.insert(
+            scan,
+            "scan",
+            v("1.0.0"),
+            vec!["a-dns".to_string(), "probe".to_string()],
+        );
+
+        assert_eq!(graph.load_order(&scan).unwrap(), vec![dns_1, probe, scan]);
+    }
+
+    #[test]
+    fn test_invalid_versions_are_rejected() {
+        assert!(parse_version("1.2.0").is_ok());
+        for version in ["1.2", "latest", "v1.2.0", ""] {
+            assert!(
+                matches!(parse_version(version), Err(Error::Validation(_))),
+                "{}",
+                version
+            );
+        }
+
+        assert!(matches!(
+            DependencySpec::parse("dns-scanner >=banana"),
+            Err(Error::Validation(_))
+        ));
+        assert_eq!(
+            DependencySpec::parse("dns-scanner").unwrap(),
+            DependencySpec::Name {
+                name: "dns-scanner".to_string(),
+                requirement: VersionReq::STAR,
+            }
+        );
+    }
+}
@@ -1,15 +1,22 @@
 use actix_web::{delete, get, post, put, web, Error, HttpResponse, Responder};
-use mirage_common::{models::Module, Error as CommonError};
+use mirage_common::{
+    models::{Module, Page, PageRequest},
+    Error as CommonError,
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::models::{CreateModuleRequest, UpdateModuleRequest};
+use crate::search::SearchQuery;
 use crate::services::ModuleService;
 
 pub fn module_routes() -> actix_web::Scope {
     web::scope("/modules")
         .service(list_modules)
+        // Registered before `/{id}` so "search" isn't parsed as an ID
+        .service(search_modules)
         .service(get_module)
+        .service(resolve_module)
         .service(register_module)
         .service(update_module)
         .service(delete_module)
@@ -20,15 +27,40 @@ async fn list_modules(
     module_service: web::Data<ModuleService>,
     query: web::Query<ListModulesQuery>,
 ) -> Result<HttpResponse, Error> {
-    let modules = module_service
-        .list_modules(query.limit.unwrap_or(100), query.offset.unwrap_or(0))
+    let page = PageRequest {
+        limit: query.limit,
+        offset: query.offset,
+    };
+    let (limit, offset) = page.resolve().map_err(actix_web::error::ErrorBadRequest)?;
+
+    let (modules, total) = module_service
+        .list_modules(query.capability.as_deref(), limit.into(), offset.into())
         .await
         .map_err(|e| {
             tracing::error!("Failed to list modules: {}", e);
             actix_web::error::ErrorInternalServerError(e)
         })?;
 
-    Ok(HttpResponse::Ok().json(modules))
+    Ok(HttpResponse::Ok().json(Page::new(modules, total, limit, offset)))
+}
+
+#[get("/search")]
+async fn search_modules(
+    module_service: web::Data<ModuleService>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, Error> {
+    let page = PageRequest {
+        limit: query.limit,
+        offset: query.offset,
+    };
+    let (limit, offset) = page.resolve().map_err(actix_web::error::ErrorBadRequest)?;
+
+    let modules = module_service.search_modules(&query).await.map_err(|e| {
+        tracing::error!("Failed to search modules: {}", e);
+        actix_web::error::ErrorInternalServerError(e)
+    })?;
+
+    Ok(HttpResponse::Ok().json(Page::from_slice(&modules, limit, offset)))
 }
 
 #[get("/{id}")]
@@ -50,6 +82,32 @@ async fn get_module(
     Ok(HttpResponse::Ok().json(module))
 }
 
+#[get("/{id}/resolve")]
+async fn resolve_module(
+    id: web::Path<String>,
+    module_service: web::Data<ModuleService>,
+) -> Result<HttpResponse, Error> {
+    let id =
+        Uuid::parse_str(&id).map_err(|_| actix_web::error::ErrorBadRequest("Invalid module ID"))?;
+
+    // Validation errors here mean the stored dependencies can't be satisfied
+    let modules = module_service
+        .resolve_module(&id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) | CommonError::Conflict(_) => {
+                actix_web::error::ErrorConflict(e)
+            }
+            _ => {
+                tracing::error!("Failed to resolve module: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(modules))
+}
+
 #[post("")]
 async fn register_module(
     data: web::Json<CreateModuleRequest>,
@@ -62,6 +120,7 @@ async fn register_module(
             tracing::error!("Failed to register module: {}", e);
             match e {
                 CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+                CommonError::Conflict(_) => actix_web::error::ErrorConflict(e),
                 _ => actix_web::error::ErrorInternalServerError(e),
             }
         })?;
@@ -84,6 +143,7 @@ async fn update_module(
         .map_err(|e| match e {
             CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            CommonError::Conflict(_) => actix_web::error::ErrorConflict(e),
             _ => {
                 tracing::error!("Failed to update module: {}", e);
                 actix_web::error::ErrorInternalServerError(e)
@@ -1,21 +1,25 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
 use mirage_common::models::Module;
+use mirage_common::telemetry;
 use tracing::info;
 
 mod config;
+mod dependencies;
 mod handlers;
 mod models;
 mod repositories;
+mod search;
 mod services;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("module-registry-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -47,15 +51,40 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("module-registry-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone()),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("module-registry-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(web::Data::new(db_pool.clone()))
             .app_data(module_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::module_routes()),
             )
     })
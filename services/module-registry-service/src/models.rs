@@ -14,6 +14,7 @@ pub struct ModuleModel {
     pub author: String,
     pub dependencies: Vec<String>,
     pub capabilities: Vec<String>,
+    pub tags: Vec<String>,
     pub configuration: serde_json::Value,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -29,6 +30,7 @@ impl From<ModuleModel> for CommonModule {
             author: model.author,
             dependencies: model.dependencies,
             capabilities: model.capabilities,
+            tags: model.tags,
             configuration: model.configuration,
         }
     }
@@ -40,8 +42,13 @@ pub struct CreateModuleRequest {
     pub version: String,
     pub description: String,
     pub author: String,
+    /// Module IDs or names with an optional semver requirement, e.g.
+    /// `dns-scanner >=1.2`
     pub dependencies: Vec<String>,
     pub capabilities: Vec<String>,
+    /// Categories for browsing the catalog, e.g. `dns` or `passive`
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub configuration: serde_json::Value,
 }
 
@@ -51,6 +58,7 @@ pub struct UpdateModuleRequest {
     pub description: Option<String>,
     pub dependencies: Option<Vec<String>>,
     pub capabilities: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
     pub configuration: Option<serde_json::Value>,
 }
 
@@ -1,8 +1,8 @@
 use crate::config::{DatabaseConfig, ModuleStorageConfig};
 use crate::models::{Module, ModuleModel, ModuleStatus};
 use chrono::Utc;
-use mirage_common::{Error, Result};
-use sqlx::{postgres::PgPoolOptions, query, query_as, Pool, Postgres};
+use mirage_common::{database, Error, Result};
+use sqlx::{query, query_as, Pool, Postgres};
 use std::fs;
 use std::path::Path;
 use uuid::Uuid;
@@ -11,11 +11,7 @@ pub type DbPool = Pool<Postgres>;
 
 /// Create database connection pool
 pub async fn create_db_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await
-        .map_err(|e| Error::Database(format!("Database connection failed: {}", e)))?;
+    let pool = database::create_pool(config).await?;
 
     // Run migrations
     sqlx::migrate!("./migrations")
@@ -43,10 +39,11 @@ impl ModuleRepository {
         let created = sqlx::query_as!(
             ModuleModel,
             r#"
-            INSERT INTO modules (id, name, version, description, author, dependencies, capabilities, configuration)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            INSERT INTO modules (id, name, version, description, author, dependencies, capabilities, tags, configuration)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             RETURNING id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                     capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
+                     capabilities as "capabilities: Vec<String>", tags as "tags: Vec<String>", configuration,
+                     created_at, updated_at
             "#,
             module.id,
             module.name,
@@ -55,6 +52,7 @@ impl ModuleRepository {
             module.author,
             &module.dependencies as _,
             &module.capabilities as _,
+            &module.tags as _,
             module.configuration
         )
         .fetch_one(&self.pool)
@@ -77,7 +75,8 @@ impl ModuleRepository {
             ModuleModel,
             r#"
             SELECT id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                  capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
+                  capabilities as "capabilities: Vec<String>", tags as "tags: Vec<String>", configuration,
+                     created_at, updated_at
             FROM modules
             ORDER BY name
             LIMIT $1 OFFSET $2
@@ -92,6 +91,18 @@ impl ModuleRepository {
         Ok(modules)
     }
 
+    pub async fn count(&self, capability: Option<&str>) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM modules WHERE $1::TEXT IS NULL OR $1 = ANY(capabilities)",
+        )
+        .bind(capability)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to count modules: {}", e)))?;
+
+        Ok(count as u64)
+    }
+
     pub async fn find_by_capability(
         &self,
         capability: &str,
@@ -102,7 +113,8 @@ impl ModuleRepository {
             ModuleModel,
             r#"
             SELECT id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                   capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
+                   capabilities as "capabilities: Vec<String>", tags as "tags: Vec<String>", configuration,
+                     created_at, updated_at
             FROM modules
             WHERE $1 = ANY(capabilities)
             ORDER BY name
@@ -124,7 +136,8 @@ impl ModuleRepository {
             ModuleModel,
             r#"
             SELECT id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                   capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
+                   capabilities as "capabilities: Vec<String>", tags as "tags: Vec<String>", configuration,
+                     created_at, updated_at
             FROM modules
             WHERE id = $1
             "#,
@@ -137,36 +150,17 @@ impl ModuleRepository {
         Ok(module)
     }
 
-    pub async fn find_by_name(&self, name: &str) -> Result<Option<ModuleModel>> {
-        let module = sqlx::query_as!(
-            ModuleModel,
-            r#"
-            SELECT id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                   capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
-            FROM modules
-            WHERE name = $1
-            ORDER BY version DESC
-            LIMIT 1
-            "#,
-            name
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| Error::Database(format!("Failed to find module by name: {}", e)))?;
-
-        Ok(module)
-    }
-
     pub async fn update(&self, module: &ModuleModel) -> Result<ModuleModel> {
         let updated = sqlx::query_as!(
             ModuleModel,
             r#"
             UPDATE modules
             SET version = $2, description = $3, author = $4, dependencies = $5, 
-                capabilities = $6, configuration = $7, updated_at = NOW()
+                capabilities = $6, tags = $7, configuration = $8, updated_at = NOW()
             WHERE id = $1
             RETURNING id, name, version, description, author, dependencies as "dependencies: Vec<String>", 
-                     capabilities as "capabilities: Vec<String>", configuration, created_at, updated_at
+                     capabilities as "capabilities: Vec<String>", tags as "tags: Vec<String>", configuration,
+                     created_at, updated_at
             "#,
             module.id,
             module.version,
@@ -174,6 +168,7 @@ impl ModuleRepository {
             module.author,
             &module.dependencies as _,
             &module.capabilities as _,
+            &module.tags as _,
             module.configuration
         )
         .fetch_one(&self.pool)
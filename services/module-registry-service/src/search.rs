@@ -0,0 +1,219 @@
+//! Module catalog search
+//!
+//! `name` and `tag` narrow the catalog, `q` matches free text against names,
+//! tags, capabilities and descriptions. All matching ignores case. Results are
+//! ranked by how closely they match, best first.
+
+use crate::models::ModuleModel;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    /// Comma-separated; a module must carry every tag
+    pub tag: Option<String>,
+    pub name: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Lowercases, trims and deduplicates tags so they compare case-insensitively
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+/// Modules matching `query`, most relevant first
+pub fn search(modules: Vec<ModuleModel>, query: &SearchQuery) -> Vec<ModuleModel> {
+    let text = lowercase(&query.q);
+    let name = lowercase(&query.name);
+    let tags = normalize_tags(
+        query
+            .tag
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(String::from)
+            .collect(),
+    );
+
+    let mut ranked: Vec<(u32, ModuleModel)> = modules
+        .into_iter()
+        .filter_map(|module| {
+            let module_name = module.name.to_lowercase();
+            if let Some(ref name) = name {
+                if !module_name.contains(name.as_str()) {
+                    return None;
+                }
+            }
+
+            let has_tag = |tag: &String| module.tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+            if !tags.iter().all(has_tag) {
+                return None;
+            }
+
+            let mut score = name
+                .as_deref()
+                .map_or(0, |name| name_score(&module_name, name));
+            if let Some(ref text) = text {
+                let text_score = text_score(&module, &module_name, text);
+                if text_score == 0 {
+                    return None;
+                }
+                score += text_score;
+            }
+
+            Some((score, module))
+        })
+        .collect();
+
+    ranked.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| b.created_at.cmp(&a.created_at))
+    });
+    ranked.into_iter().map(|(_, module)| module).collect()
+}
+
+fn lowercase(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+}
+
+fn name_score(module_name: &str, needle: &str) -> u32 {
+    if module_name == needle {
+        100
+    } else if module_name.starts_with(needle) {
+        50
+    } else if module_name.contains(needle) {
+        25
+    } else {
+        0
+    }
+}
+
+fn text_score(module: &ModuleModel, module_name: &str, text: &str) -> u32 {
+    let mut score = name_score(module_name, text);
+    if module.tags.iter().any(|tag| tag.eq_ignore_ascii_case(text)) {
+        score += 20;
+    }
+    if module
+        .capabilities
+        .iter()
+        .any(|capability| capability.to_lowercase().contains(text))
+    {
+        score += 10;
+    }
+    if module.description.to_lowercase().contains(text) {
+        score += 5;
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn module(name: &str, tags: &[&str], description: &str) -> ModuleModel {
+        ModuleModel {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: description.to_string(),
+            author: "mirage".to_string(),
+            dependencies: Vec::new(),
+            capabilities: Vec::new(),
+            tags: normalize_tags(tags.iter().map(|t| t.to_string()).collect()),
+            configuration: serde_json::json!({}),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn catalog() -> Vec<ModuleModel> {
+        vec![
+            module("dns-resolver", &["DNS", "passive"], "Resolves A records"),
+            module("dns-bruteforce", &["dns", "active"], "Guesses subdomains"),
+            module("whois", &["passive"], "Looks up DNS registrars"),
+            module("port-scanner", &["active", "network"], "TCP connect scan"),
+        ]
+    }
+
+    fn names(modules: &[ModuleModel]) -> Vec<&str> {
+        modules.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tag_filter_ignores_case() {
+        let query = SearchQuery {
+            tag: Some("Passive".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&search(catalog(), &query)),
+            vec!["dns-resolver", "whois"]
+        );
+
+        let query = SearchQuery {
+            tag: Some("active,NETWORK".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&search(catalog(), &query)), vec!["port-scanner"]);
+    }
+
+    #[test]
+    fn test_name_substring_ranks_closer_matches_first() {
+        let query = SearchQuery {
+            name: Some("SCAN".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&search(catalog(), &query)), vec!["port-scanner"]);
+
+        let catalog = vec![
+            module("reverse-dns", &[], ""),
+            module("dns", &[], ""),
+            module("dns-resolver", &[], ""),
+        ];
+        let query = SearchQuery {
+            name: Some("dns".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&search(catalog, &query)),
+            vec!["dns", "dns-resolver", "reverse-dns"]
+        );
+    }
+
+    #[test]
+    fn test_combined_query_returns_intersection() {
+        let query = SearchQuery {
+            q: Some("dns".to_string()),
+            tag: Some("passive".to_string()),
+            ..Default::default()
+        };
+        // whois only mentions DNS in its description, so it ranks last
+        assert_eq!(
+            names(&search(catalog(), &query)),
+            vec!["dns-resolver", "whois"]
+        );
+
+        let query = SearchQuery {
+            q: Some("dns".to_string()),
+            tag: Some("active".to_string()),
+            name: Some("brute".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&search(catalog(), &query)), vec!["dns-bruteforce"]);
+    }
+}
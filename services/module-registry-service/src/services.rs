@@ -1,9 +1,12 @@
 use crate::config::ModuleStorageConfig;
+use crate::dependencies::{parse_version, DependencyGraph};
 use crate::models::{CreateModuleRequest, ModuleModel, UpdateModuleRequest};
 use crate::repositories::{DbPool, ModuleRepository};
+use crate::search::{self, normalize_tags, SearchQuery};
 use chrono::Utc;
 use mirage_common::{models::Module, Error, Result};
 use semver::Version;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -30,9 +33,25 @@ impl ModuleService {
         }
     }
 
-    pub async fn list_modules(&self, limit: i64, offset: i64) -> Result<Vec<Module>> {
-        let modules = self.repo.find_all(limit, offset).await?;
-        Ok(modules.into_iter().map(|m| m.into()).collect())
+    /// One page of modules, optionally limited to a capability, plus the
+    /// total number of matching modules
+    pub async fn list_modules(
+        &self,
+        capability: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Module>, u64)> {
+        let modules = match capability {
+            Some(capability) => {
+                self.repo
+                    .find_by_capability(capability, limit, offset)
+                    .await?
+            }
+            None => self.repo.find_all(limit, offset).await?,
+        };
+        let total = self.repo.count(capability).await?;
+
+        Ok((modules.into_iter().map(|m| m.into()).collect(), total))
     }
 
     pub async fn list_modules_by_capability(
@@ -48,6 +67,15 @@ impl ModuleService {
         Ok(modules.into_iter().map(|m| m.into()).collect())
     }
 
+    /// Catalog modules matching `query`, most relevant first
+    pub async fn search_modules(&self, query: &SearchQuery) -> Result<Vec<Module>> {
+        let modules = self.all_modules().await?;
+        Ok(search::search(modules, query)
+            .into_iter()
+            .map(|m| m.into())
+            .collect())
+    }
+
     pub async fn get_module(&self, id: &Uuid) -> Result<Module> {
         let module = self
             .repo
@@ -59,25 +87,20 @@ impl ModuleService {
     }
 
     pub async fn register_module(&self, req: CreateModuleRequest) -> Result<Module> {
-        // Validate semver format
-        let _ = Version::parse(&req.version)
-            .map_err(|_| Error::Validation(format!("Invalid version format: {}", req.version)))?;
-
-        // Check if a module with this name already exists
-        if let Some(existing) = self.repo.find_by_name(&req.name).await? {
-            // If it exists, compare versions
-            let existing_version = Version::parse(&existing.version).map_err(|_| {
-                Error::Internal(format!("Invalid version in database: {}", existing.version))
-            })?;
-
-            let new_version = Version::parse(&req.version).map_err(|_| {
-                Error::Validation(format!("Invalid version format: {}", req.version))
-            })?;
-
+        let new_version = parse_version(&req.version)?;
+
+        // A new release of an existing module must be newer than all of them
+        let modules = self.all_modules().await?;
+        let newest = modules
+            .iter()
+            .filter(|m| m.name == req.name)
+            .filter_map(|m| Version::parse(&m.version).ok())
+            .max();
+        if let Some(existing_version) = newest {
             if new_version <= existing_version {
                 return Err(Error::Validation(format!(
                     "Module version {} is not newer than existing version {}",
-                    req.version, existing.version
+                    req.version, existing_version
                 )));
             }
         }
@@ -91,11 +114,21 @@ impl ModuleService {
             author: req.author,
             dependencies: req.dependencies,
             capabilities: req.capabilities,
+            tags: normalize_tags(req.tags),
             configuration: req.configuration,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
 
+        let mut graph = dependency_graph(&modules);
+        graph.insert(
+            module.id,
+            &module.name,
+            new_version,
+            module.dependencies.clone(),
+        );
+        graph.validate(&module.id)?;
+
         let created = self.repo.create(&module).await?;
         Ok(created.into())
     }
@@ -108,10 +141,11 @@ impl ModuleService {
             .await?
             .ok_or_else(|| Error::NotFound(format!("Module with ID {} not found", id)))?;
 
+        let resolution_changed = req.version.is_some() || req.dependencies.is_some();
+
         // If version is provided, validate semver format
         if let Some(ref version) = req.version {
-            let _ = Version::parse(version)
-                .map_err(|_| Error::Validation(format!("Invalid version format: {}", version)))?;
+            parse_version(version)?;
 
             module.version = version.clone();
         }
@@ -129,10 +163,19 @@ impl ModuleService {
             module.capabilities = capabilities;
         }
 
+        if let Some(tags) = req.tags {
+            module.tags = normalize_tags(tags);
+        }
+
         if let Some(configuration) = req.configuration {
             module.configuration = configuration;
         }
 
+        // A new version or dependency list can change what resolves
+        if resolution_changed {
+            self.validate_dependencies(&module).await?;
+        }
+
         let updated = self.repo.update(&module).await?;
         Ok(updated.into())
     }
@@ -146,6 +189,55 @@ impl ModuleService {
             Err(Error::NotFound(format!("Module with ID {} not found", id)))
         }
     }
+
+    /// The module and everything it depends on, in load order
+    pub async fn resolve_module(&self, id: &Uuid) -> Result<Vec<Module>> {
+        let modules = self.all_modules().await?;
+        let order = dependency_graph(&modules).load_order(id)?;
+
+        let mut modules: HashMap<Uuid, ModuleModel> =
+            modules.into_iter().map(|m| (m.id, m)).collect();
+        Ok(order
+            .iter()
+            .filter_map(|id| modules.remove(id))
+            .map(|m| m.into())
+            .collect())
+    }
+
+    /// Rejects dependencies of `module` that don't resolve, conflict or
+    /// form a cycle
+    async fn validate_dependencies(&self, module: &ModuleModel) -> Result<()> {
+        let mut graph = dependency_graph(&self.all_modules().await?);
+        graph.insert(
+            module.id,
+            &module.name,
+            parse_version(&module.version)?,
+            module.dependencies.clone(),
+        );
+
+        graph.validate(&module.id)
+    }
+
+    async fn all_modules(&self) -> Result<Vec<ModuleModel>> {
+        self.repo.find_all(i64::MAX, 0).await
+    }
+}
+
+fn dependency_graph(modules: &[ModuleModel]) -> DependencyGraph {
+    let mut graph = DependencyGraph::new();
+    for module in modules {
+        match Version::parse(&module.version) {
+            Ok(version) => {
+                graph.insert(module.id, &module.name, version, module.dependencies.clone())
+            }
+            Err(_) => tracing::warn!(
+                "Skipping module {} with invalid version {} in dependency resolution",
+                module.id,
+                module.version
+            ),
+        }
+    }
+    graph
 }
 
 #[derive(Clone)]
@@ -0,0 +1,47 @@
+-- Create notifications table
+CREATE TABLE IF NOT EXISTS notifications (
+    id UUID PRIMARY KEY,
+    type VARCHAR(100) NOT NULL,
+    subject TEXT NOT NULL,
+    content TEXT NOT NULL,
+    metadata JSONB NOT NULL DEFAULT '{}'::jsonb,
+    status VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    processed_at TIMESTAMPTZ
+);
+
+-- Create notification_deliveries table
+CREATE TABLE IF NOT EXISTS notification_deliveries (
+    id UUID PRIMARY KEY,
+    notification_id UUID NOT NULL REFERENCES notifications(id) ON DELETE CASCADE,
+    channel VARCHAR(50) NOT NULL,
+    recipient TEXT NOT NULL,
+    status VARCHAR(50) NOT NULL,
+    error_message TEXT,
+    retry_count INTEGER NOT NULL DEFAULT 0,
+    next_retry_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ
+);
+
+-- Create subscriptions table
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id UUID PRIMARY KEY,
+    notification_type VARCHAR(100) NOT NULL,
+    channel VARCHAR(50) NOT NULL,
+    recipient TEXT NOT NULL,
+    filter_conditions JSONB NOT NULL DEFAULT '{}'::jsonb,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+-- Indexes for the worker's pending scan and per-type subscription lookups
+CREATE INDEX IF NOT EXISTS idx_notification_deliveries_notification_id
+    ON notification_deliveries (notification_id);
+CREATE INDEX IF NOT EXISTS idx_notification_deliveries_pending
+    ON notification_deliveries (created_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_subscriptions_notification_type
+    ON subscriptions (notification_type) WHERE active;
@@ -0,0 +1,5 @@
+-- Deliveries are rendered per channel by the worker, so keep what they are
+-- rendered from
+ALTER TABLE notifications
+    ADD COLUMN IF NOT EXISTS template_name VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS data JSONB NOT NULL DEFAULT '{}'::jsonb;
@@ -0,0 +1,14 @@
+-- Deliveries that failed every retry, with the error from the last attempt
+CREATE TABLE IF NOT EXISTS notification_dead_letters (
+    id UUID PRIMARY KEY,
+    delivery_id UUID NOT NULL UNIQUE REFERENCES notification_deliveries(id) ON DELETE CASCADE,
+    notification_id UUID NOT NULL,
+    channel VARCHAR(50) NOT NULL,
+    recipient TEXT NOT NULL,
+    last_error TEXT NOT NULL,
+    retry_count INTEGER NOT NULL,
+    dead_lettered_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_dead_letters_dead_lettered_at
+    ON notification_dead_letters (dead_lettered_at DESC);
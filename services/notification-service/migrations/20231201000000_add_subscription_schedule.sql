@@ -0,0 +1,5 @@
+-- Minutes between digests for subscriptions that batch their notifications;
+-- NULL sends each notification as soon as it is processed
+ALTER TABLE subscriptions
+    ADD COLUMN IF NOT EXISTS schedule_interval_minutes INTEGER
+        CHECK (schedule_interval_minutes IS NULL OR schedule_interval_minutes > 0);
@@ -5,11 +5,13 @@ use mirage_common::Result;
 mod database;
 mod email;
 mod slack;
+mod telegram;
 mod webhook;
 
 pub use database::DatabaseChannel;
 pub use email::EmailChannel;
 pub use slack::SlackChannel;
+pub use telegram::TelegramChannel;
 pub use webhook::WebhookChannel;
 
 pub trait Channel {
@@ -29,6 +31,7 @@ pub fn get_channel(
         NotificationChannel::Email => Box::new(EmailChannel::new(&config.email)),
         NotificationChannel::Webhook => Box::new(WebhookChannel::new(&config.webhook)),
         NotificationChannel::Slack => Box::new(SlackChannel::new(&config.slack)),
+        NotificationChannel::Telegram => Box::new(TelegramChannel::new(&config.telegram)),
         NotificationChannel::Database => Box::new(DatabaseChannel::new()),
     }
 }
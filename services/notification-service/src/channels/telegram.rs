@@ -0,0 +1,373 @@
+use crate::config::TelegramConfig;
+use crate::models::NotificationDelivery;
+use mirage_common::{Error, Result};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Longest text the Bot API accepts in a single message, in UTF-16 code units
+const MESSAGE_LIMIT: usize = 4096;
+/// Times a single message is resent after the Bot API asks us to slow down
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// Waits longer than this are left to the delivery retry policy instead
+const MAX_RETRY_AFTER_SECONDS: u64 = 60;
+
+pub struct TelegramChannel {
+    config: TelegramConfig,
+    client: Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    description: Option<String>,
+    parameters: Option<ResponseParameters>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseParameters {
+    retry_after: Option<u64>,
+}
+
+impl TelegramChannel {
+    pub fn new(config: &TelegramConfig) -> Self {
+        let client = Client::new();
+        Self {
+            config: config.clone(),
+            client,
+        }
+    }
+
+    /// The recipient overrides the configured chat when it looks like a chat
+    /// id (numeric, negative for groups) or a `@channel` username
+    fn chat_id(&self, recipient: &str) -> String {
+        if recipient.starts_with('@') || recipient.parse::<i64>().is_ok() {
+            recipient.to_string()
+        } else {
+            self.config.default_chat_id.clone()
+        }
+    }
+
+    async fn send_message(&self, chat_id: &str, text: &str) -> Result<()> {
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.config.api_url.trim_end_matches('/'),
+            self.config.bot_token
+        );
+        let payload = serde_json::json!({
+            "chat_id": chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        });
+
+        let mut rate_limited = 0;
+        loop {
+            // The URL carries the bot token, so keep it out of error messages
+            let response = self
+                .client
+                .post(&url)
+                .json(&payload)
+                .send()
+                .await
+                .map_err(|e| {
+                    Error::ExternalApi(format!(
+                        "Failed to send Telegram message: {}",
+                        e.without_url()
+                    ))
+                })?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+
+            let body: Option<ApiResponse> = response.json().await.ok();
+            let description = body
+                .as_ref()
+                .and_then(|body| body.description.clone())
+                .unwrap_or_else(|| "Unknown error".to_string());
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = body
+                    .as_ref()
+                    .and_then(|body| body.parameters.as_ref())
+                    .and_then(|parameters| parameters.retry_after);
+
+                if let Some(seconds) = retry_after {
+                    if rate_limited < MAX_RATE_LIMIT_RETRIES && seconds <= MAX_RETRY_AFTER_SECONDS {
+                        rate_limited += 1;
+                        tracing::warn!("Telegram rate limit hit, retrying in {} seconds", seconds);
+                        tokio::time::sleep(Duration::from_secs(seconds)).await;
+                        continue;
+                    }
+                }
+            }
+
+            return Err(Error::ExternalApi(format!(
+                "Telegram API returned error ({}): {}",
+                status, description
+            )));
+        }
+    }
+}
+
+impl super::Channel for TelegramChannel {
+    async fn send(
+        &self,
+        delivery: &NotificationDelivery,
+        content: &str,
+        subject: &str,
+    ) -> Result<()> {
+        let chat_id = self.chat_id(&delivery.recipient);
+        if chat_id.is_empty() {
+            return Err(Error::Validation(
+                "No Telegram chat id in the recipient or configuration".to_string(),
+            ));
+        }
+
+        let text = if subject.is_empty() {
+            content.to_string()
+        } else {
+            format!("{}\n\n{}", subject, content)
+        };
+
+        // Rate limits are waited out here rather than failing the delivery:
+        // a retry of the whole delivery would resend the chunks that already
+        // went through
+        for chunk in split_message(&text, MESSAGE_LIMIT) {
+            self.send_message(&chat_id, &chunk).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits `text` into pieces of at most `limit` UTF-16 code units, as the Bot
+/// API counts them, breaking at the last newline or space that fits and only
+/// mid-word when there is none
+fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest: Vec<char> = text.chars().collect();
+
+    loop {
+        // Characters outside the BMP take two units
+        let mut units = 0;
+        let fits = rest
+            .iter()
+            .take_while(|c| {
+                units += c.len_utf16();
+                units <= limit
+            })
+            .count();
+        if fits == rest.len() {
+            break;
+        }
+
+        // Always make progress, even if the first character alone is too long
+        let end = fits.max(1);
+        let window = &rest[..end];
+        let split = window
+            .iter()
+            .rposition(|c| *c == '\n')
+            .filter(|&at| at > 0)
+            .or_else(|| {
+                window
+                    .iter()
+                    .rposition(|c| c.is_whitespace())
+                    .filter(|&at| at > 0)
+            })
+            .unwrap_or(end);
+
+        chunks.push(rest[..split].iter().collect());
+        let skip = usize::from(split < end && rest[split].is_whitespace());
+        rest.drain(..split + skip);
+    }
+
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest.into_iter().collect());
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::Channel;
+    use crate::models::{NotificationChannel, NotificationStatus};
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::Utc;
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Stands in for the Bot API: records every request and answers the
+    /// first `rate_limited` of them with a 429
+    #[derive(Default)]
+    struct MockBotApi {
+        requests: Mutex<Vec<(String, Value)>>,
+        rate_limited: AtomicU32,
+    }
+
+    async fn record(
+        req: HttpRequest,
+        body: web::Json<Value>,
+        api: web::Data<MockBotApi>,
+    ) -> HttpResponse {
+        api.requests
+            .lock()
+            .unwrap()
+            .push((req.path().to_string(), body.into_inner()));
+
+        let limited = api
+            .rate_limited
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if limited {
+            return HttpResponse::TooManyRequests().json(serde_json::json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests: retry after 1",
+                "parameters": { "retry_after": 1 }
+            }));
+        }
+
+        HttpResponse::Ok().json(serde_json::json!({ "ok": true, "result": {} }))
+    }
+
+    /// Starts the mock and returns a channel pointed at it
+    fn channel(api: web::Data<MockBotApi>) -> TelegramChannel {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(api.clone())
+                .default_service(web::post().to(record))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        TelegramChannel::new(&TelegramConfig {
+            bot_token: "123:secret".to_string(),
+            default_chat_id: "-1001".to_string(),
+            api_url: format!("http://{}", addr),
+        })
+    }
+
+    fn delivery(recipient: &str) -> NotificationDelivery {
+        NotificationDelivery {
+            id: Uuid::new_v4(),
+            notification_id: Uuid::new_v4(),
+            channel: NotificationChannel::Telegram,
+            recipient: recipient.to_string(),
+            status: NotificationStatus::Pending,
+            error_message: None,
+            retry_count: 0,
+            next_retry_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_send_posts_message_to_default_chat() {
+        let api = web::Data::new(MockBotApi::default());
+        let channel = channel(api.clone());
+
+        channel
+            .send(
+                &delivery(""),
+                "3 new findings for example.com",
+                "Scan complete",
+            )
+            .await
+            .unwrap();
+
+        let requests = api.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let (path, body) = &requests[0];
+        assert_eq!(path, "/bot123:secret/sendMessage");
+        assert_eq!(body["chat_id"], "-1001");
+        assert_eq!(
+            body["text"],
+            "Scan complete\n\n3 new findings for example.com"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_long_content_is_sent_in_chunks_to_recipient_chat() {
+        let api = web::Data::new(MockBotApi::default());
+        let channel = channel(api.clone());
+        let content = "finding on host.example.com\n".repeat(400);
+
+        channel
+            .send(&delivery("@mirage_alerts"), &content, "Scan complete")
+            .await
+            .unwrap();
+
+        let requests = api.requests.lock().unwrap();
+        assert!(requests.len() > 1);
+        let texts: Vec<&str> = requests
+            .iter()
+            .map(|(_, body)| {
+                assert_eq!(body["chat_id"], "@mirage_alerts");
+                body["text"].as_str().unwrap()
+            })
+            .collect();
+        assert!(texts
+            .iter()
+            .all(|text| text.chars().count() <= MESSAGE_LIMIT));
+        assert_eq!(texts.join("\n"), format!("Scan complete\n\n{}", content));
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limited_message_is_resent_after_retry_after() {
+        let api = web::Data::new(MockBotApi {
+            rate_limited: AtomicU32::new(1),
+            ..Default::default()
+        });
+        let channel = channel(api.clone());
+
+        let started = std::time::Instant::now();
+        channel
+            .send(&delivery("42"), "body", "subject")
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        let requests = api.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].1, requests[1].1);
+        assert_eq!(requests[1].1["chat_id"], "42");
+    }
+
+    #[test]
+    fn test_split_message_counts_utf16_units() {
+        // Each emoji is one char but two UTF-16 units
+        assert_eq!(split_message("😀😀😀", 4), vec!["😀😀", "😀"]);
+        assert_eq!(split_message("ab 😀😀", 5), vec!["ab", "😀😀"]);
+
+        let text = "🚨".repeat(MESSAGE_LIMIT);
+        let chunks = split_message(&text, MESSAGE_LIMIT);
+        assert_eq!(chunks.len(), 2);
+        for chunk in &chunks {
+            assert_eq!(chunk.encode_utf16().count(), MESSAGE_LIMIT);
+        }
+    }
+
+    #[test]
+    fn test_split_message_breaks_words_only_when_it_must() {
+        assert_eq!(split_message("short", 10), vec!["short"]);
+        assert_eq!(split_message("", 10), vec![""]);
+        assert_eq!(
+            split_message("alpha beta gamma", 11),
+            vec!["alpha beta", "gamma"]
+        );
+        assert_eq!(
+            split_message("abcdefghijkl", 5),
+            vec!["abcde", "fghij", "kl"]
+        );
+    }
+}
@@ -2,18 +2,14 @@ use config::{Config, ConfigError, File};
 use serde::Deserialize;
 use std::env;
 
+pub use mirage_common::config::DatabaseConfig;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
     pub host: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct DatabaseConfig {
-    pub url: String,
-    pub max_connections: u32,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct EmailConfig {
     pub smtp_server: String,
@@ -37,6 +33,29 @@ pub struct SlackConfig {
     pub default_channel: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    /// Chat that receives deliveries which don't name one of their own
+    pub default_chat_id: String,
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            bot_token: String::new(),
+            default_chat_id: String::new(),
+            api_url: default_telegram_api_url(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct TemplateConfig {
     pub dir: String,
@@ -46,6 +65,38 @@ pub struct TemplateConfig {
 pub struct WorkerConfig {
     pub poll_interval_seconds: u64,
     pub batch_size: usize,
+    /// Longest wait between two attempts at a failing delivery
+    #[serde(default = "default_max_retry_delay_seconds")]
+    pub max_retry_delay_seconds: u64,
+}
+
+fn default_max_retry_delay_seconds() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    /// Identical alerts to the same recipient within this window are
+    /// suppressed; 0 turns deduplication off
+    pub dedup_window_seconds: u64,
+    /// Deliveries a recipient may receive per rate window; 0 turns rate
+    /// limiting off
+    pub max_per_recipient: u32,
+    pub rate_window_seconds: u64,
+    /// Mention how many duplicates were suppressed when the alert next goes out
+    pub digest: bool,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            dedup_window_seconds: 300,
+            max_per_recipient: 30,
+            rate_window_seconds: 60,
+            digest: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -55,8 +106,12 @@ pub struct AppConfig {
     pub email: EmailConfig,
     pub webhook: WebhookConfig,
     pub slack: SlackConfig,
+    #[serde(default)]
+    pub telegram: TelegramConfig,
     pub templates: TemplateConfig,
     pub worker: WorkerConfig,
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
 }
 
 pub fn load_config() -> Result<AppConfig, ConfigError> {
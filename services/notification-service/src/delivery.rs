@@ -0,0 +1,224 @@
+//! Delivery attempts, retries and dead-lettering
+//!
+//! A failed delivery is retried with exponential backoff. Once it has failed
+//! `max_attempts` times it is moved to the dead-letter table with the last
+//! error, where it stays until someone requeues it.
+
+use crate::channels::Channel;
+use crate::config::AppConfig;
+use crate::models::NotificationDelivery;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryOutcome {
+    Delivered {
+        completed_at: DateTime<Utc>,
+    },
+    Retry {
+        retry_count: u32,
+        next_retry_at: DateTime<Utc>,
+        error: String,
+    },
+    DeadLetter {
+        retry_count: u32,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Failed attempts after which a delivery is dead-lettered
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled for every retry after it
+    pub base_delay: Duration,
+    /// Upper bound on the wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            max_attempts: config.webhook.max_retries.max(1),
+            base_delay: Duration::from_secs(config.webhook.retry_delay_seconds),
+            max_delay: Duration::from_secs(config.worker.max_retry_delay_seconds),
+        }
+    }
+
+    /// Wait after the `failures`th failed attempt
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(failures.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    /// What to do with a delivery whose latest attempt failed with `error`
+    pub fn on_failure(
+        &self,
+        delivery: &NotificationDelivery,
+        error: String,
+        now: DateTime<Utc>,
+    ) -> DeliveryOutcome {
+        let retry_count = delivery.retry_count + 1;
+        if retry_count >= self.max_attempts {
+            return DeliveryOutcome::DeadLetter { retry_count, error };
+        }
+
+        let delay = chrono::Duration::seconds(self.backoff(retry_count).as_secs() as i64);
+        DeliveryOutcome::Retry {
+            retry_count,
+            next_retry_at: now + delay,
+            error,
+        }
+    }
+}
+
+/// Sends one delivery and decides what happens to it next
+pub async fn attempt<C: Channel + ?Sized>(
+    channel: &C,
+    delivery: &NotificationDelivery,
+    content: &str,
+    subject: &str,
+    policy: &RetryPolicy,
+    now: DateTime<Utc>,
+) -> DeliveryOutcome {
+    match channel.send(delivery, content, subject).await {
+        Ok(()) => DeliveryOutcome::Delivered { completed_at: now },
+        Err(e) => policy.on_failure(delivery, e.to_string(), now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NotificationChannel, NotificationStatus};
+    use mirage_common::{Error, Result};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use uuid::Uuid;
+
+    /// Fails its first `failures` sends, then succeeds
+    struct FlakyChannel {
+        failures: u32,
+        sends: AtomicU32,
+    }
+
+    impl FlakyChannel {
+        fn new(failures: u32) -> Self {
+            Self {
+                failures,
+                sends: AtomicU32::new(0),
+            }
+        }
+    }
+
+    impl Channel for FlakyChannel {
+        async fn send(
+            &self,
+            _delivery: &NotificationDelivery,
+            _content: &str,
+            _subject: &str,
+        ) -> Result<()> {
+            let send = self.sends.fetch_add(1, Ordering::SeqCst) + 1;
+            if send <= self.failures {
+                Err(Error::ExternalApi(format!("send {} refused", send)))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    fn delivery() -> NotificationDelivery {
+        NotificationDelivery {
+            id: Uuid::new_v4(),
+            notification_id: Uuid::new_v4(),
+            channel: NotificationChannel::Webhook,
+            recipient: "https://hooks.example.com/mirage".to_string(),
+            status: NotificationStatus::Pending,
+            error_message: None,
+            retry_count: 0,
+            next_retry_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    /// Runs the delivery the way the worker would, waking up at each
+    /// scheduled retry, until it is delivered or dead-lettered
+    async fn run(channel: &FlakyChannel) -> Vec<DeliveryOutcome> {
+        let policy = policy();
+        let mut delivery = delivery();
+        let mut now = Utc::now();
+        let mut outcomes = Vec::new();
+
+        loop {
+            let outcome = attempt(channel, &delivery, "body", "subject", &policy, now).await;
+            outcomes.push(outcome.clone());
+            match outcome {
+                DeliveryOutcome::Retry {
+                    retry_count,
+                    next_retry_at,
+                    ..
+                } => {
+                    delivery.retry_count = retry_count;
+                    now = next_retry_at;
+                }
+                _ => return outcomes,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery_succeeds_after_transient_failures() {
+        let channel = FlakyChannel::new(2);
+        let outcomes = run(&channel).await;
+
+        assert_eq!(outcomes.len(), 3);
+        assert!(matches!(
+            outcomes[0],
+            DeliveryOutcome::Retry { retry_count: 1, .. }
+        ));
+        assert!(matches!(
+            outcomes[1],
+            DeliveryOutcome::Retry { retry_count: 2, .. }
+        ));
+        assert!(matches!(outcomes[2], DeliveryOutcome::Delivered { .. }));
+        assert_eq!(channel.sends.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_that_always_fails_is_dead_lettered() {
+        let channel = FlakyChannel::new(u32::MAX);
+        let outcomes = run(&channel).await;
+
+        assert_eq!(outcomes.len(), 4);
+        match outcomes.last().unwrap() {
+            DeliveryOutcome::DeadLetter { retry_count, error } => {
+                assert_eq!(*retry_count, 4);
+                assert!(error.contains("send 4 refused"));
+            }
+            other => panic!("expected a dead letter, got {:?}", other),
+        }
+        assert_eq!(channel.sends.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(10));
+        assert_eq!(policy.backoff(2), Duration::from_secs(20));
+        assert_eq!(policy.backoff(3), Duration::from_secs(40));
+        assert_eq!(policy.backoff(4), Duration::from_secs(60));
+        assert_eq!(policy.backoff(40), Duration::from_secs(60));
+    }
+}
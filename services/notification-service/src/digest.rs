@@ -0,0 +1,324 @@
+//! Batched delivery for subscriptions on a digest schedule
+//!
+//! Deliveries to a subscriber whose schedule is `Batched` are not sent one by
+//! one. The worker collects them here, pushes their `next_retry_at` out to the
+//! end of the batch so they aren't fetched again in the meantime, and once the
+//! interval has passed sends them as a single `digest` message on the
+//! subscription's channel. Should the service stop without flushing, the
+//! deliveries are still pending and are batched again on the next start.
+
+use crate::channels::Channel;
+use crate::delivery::{self, DeliveryOutcome, RetryPolicy};
+use crate::models::{NotificationDelivery, NotificationSchedule, Subscription};
+use crate::templates::{TemplateRegistry, TemplateVariant};
+use chrono::{DateTime, Duration, Utc};
+use mirage_common::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Template used to render digests
+pub const DIGEST_TEMPLATE: &str = "digest";
+
+/// A notification waiting in a digest, already rendered as plain text
+#[derive(Debug, Clone)]
+pub struct DigestItem {
+    pub delivery: NotificationDelivery,
+    pub subject: String,
+    pub content: String,
+}
+
+#[derive(Debug)]
+pub struct Digest {
+    pub subscription_id: Uuid,
+    pub opened_at: DateTime<Utc>,
+    pub items: Vec<DigestItem>,
+}
+
+impl Digest {
+    /// The deliveries this digest stands in for
+    pub fn deliveries(&self) -> impl Iterator<Item = &NotificationDelivery> {
+        self.items.iter().map(|item| &item.delivery)
+    }
+
+    /// Renders the digest in the form its channel expects
+    pub fn render(&self, templates: &TemplateRegistry) -> Result<(String, String)> {
+        let variant = TemplateVariant::for_channel(&self.items[0].delivery.channel);
+        let items: Vec<_> = self
+            .items
+            .iter()
+            .map(|item| {
+                serde_json::json!({
+                    "subject": item.subject,
+                    "content": item.content,
+                    "created_at": item.delivery.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                })
+            })
+            .collect();
+        let vars = serde_json::json!({
+            "count": self.items.len(),
+            "since": self.opened_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+            "items": items,
+        });
+
+        templates.render(DIGEST_TEMPLATE, variant, &vars)
+    }
+}
+
+struct Batch {
+    opened_at: DateTime<Utc>,
+    due_at: DateTime<Utc>,
+    items: Vec<DigestItem>,
+}
+
+/// Open digests, one per batched subscription
+#[derive(Default)]
+pub struct DigestBatcher {
+    batches: HashMap<Uuid, Batch>,
+}
+
+impl DigestBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a delivery to the subscription's open digest, starting one if
+    /// needed, and returns when that digest is due. Returns `None` if the
+    /// subscription isn't batched or the delivery is already waiting.
+    pub fn add(
+        &mut self,
+        subscription: &Subscription,
+        item: DigestItem,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let NotificationSchedule::Batched { interval_minutes } = subscription.schedule else {
+            return None;
+        };
+
+        let batch = self
+            .batches
+            .entry(subscription.id)
+            .or_insert_with(|| Batch {
+                opened_at: now,
+                due_at: now + Duration::minutes(interval_minutes as i64),
+                items: Vec::new(),
+            });
+        if batch
+            .items
+            .iter()
+            .any(|waiting| waiting.delivery.id == item.delivery.id)
+        {
+            return None;
+        }
+
+        batch.items.push(item);
+        Some(batch.due_at)
+    }
+
+    /// Closes and returns the digests whose interval has passed
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<Digest> {
+        let due: Vec<Uuid> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| batch.due_at <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        due.into_iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    /// Closes and returns every open digest, due or not
+    pub fn take_all(&mut self) -> Vec<Digest> {
+        let ids: Vec<Uuid> = self.batches.keys().copied().collect();
+        ids.into_iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    fn close(&mut self, subscription_id: Uuid) -> Option<Digest> {
+        let batch = self.batches.remove(&subscription_id)?;
+        Some(Digest {
+            subscription_id,
+            opened_at: batch.opened_at,
+            items: batch.items,
+        })
+    }
+}
+
+/// Renders a digest and sends it as one message. The outcome applies to every
+/// delivery in the digest.
+pub async fn send<C: Channel + ?Sized>(
+    channel: &C,
+    digest: &Digest,
+    templates: &TemplateRegistry,
+    policy: &RetryPolicy,
+    now: DateTime<Utc>,
+) -> Result<DeliveryOutcome> {
+    let (subject, content) = digest.render(templates)?;
+    // Any of the deliveries can carry the message; they share a channel and
+    // recipient
+    let carrier = &digest.items[0].delivery;
+
+    Ok(delivery::attempt(channel, carrier, &content, &subject, policy, now).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TemplateConfig;
+    use crate::models::{NotificationChannel, NotificationStatus, NotificationType};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Keeps every message it is asked to send
+    #[derive(Default)]
+    struct RecordingChannel {
+        sent: Mutex<Vec<(String, String, String)>>,
+    }
+
+    impl Channel for RecordingChannel {
+        async fn send(
+            &self,
+            delivery: &NotificationDelivery,
+            content: &str,
+            subject: &str,
+        ) -> Result<()> {
+            self.sent.lock().unwrap().push((
+                delivery.recipient.clone(),
+                subject.to_string(),
+                content.to_string(),
+            ));
+            Ok(())
+        }
+    }
+
+    fn subscription(schedule: NotificationSchedule) -> Subscription {
+        Subscription {
+            id: Uuid::new_v4(),
+            notification_type: NotificationType::NewEntity,
+            channel: NotificationChannel::Slack,
+            recipient: "#recon".to_string(),
+            filter_conditions: HashMap::new(),
+            schedule,
+            active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn item(value: &str) -> DigestItem {
+        DigestItem {
+            delivery: NotificationDelivery {
+                id: Uuid::new_v4(),
+                notification_id: Uuid::new_v4(),
+                channel: NotificationChannel::Slack,
+                recipient: "#recon".to_string(),
+                status: NotificationStatus::Pending,
+                error_message: None,
+                retry_count: 0,
+                next_retry_at: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                completed_at: None,
+            },
+            subject: format!("New Entity Detected: domain - {}", value),
+            content: format!("New domain detected: *{}*", value),
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_secs(60),
+            max_delay: std::time::Duration::from_secs(600),
+        }
+    }
+
+    fn templates() -> TemplateRegistry {
+        TemplateRegistry::new(&TemplateConfig {
+            dir: "does-not-exist".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_events_in_one_window_are_sent_as_one_digest() {
+        let subscription = subscription(NotificationSchedule::Batched {
+            interval_minutes: 15,
+        });
+        let mut batcher = DigestBatcher::new();
+        let start = Utc::now();
+
+        let values = ["a.example.com", "b.example.com", "c.example.com"];
+        for (i, value) in values.iter().enumerate() {
+            let due = batcher.add(
+                &subscription,
+                item(value),
+                start + Duration::minutes(i as i64),
+            );
+            assert_eq!(due, Some(start + Duration::minutes(15)));
+        }
+
+        assert!(batcher.take_due(start + Duration::minutes(14)).is_empty());
+        let digests = batcher.take_due(start + Duration::minutes(15));
+        assert_eq!(digests.len(), 1);
+        assert!(batcher.is_empty());
+
+        let digest = &digests[0];
+        assert_eq!(digest.subscription_id, subscription.id);
+        assert_eq!(digest.deliveries().count(), 3);
+
+        let channel = RecordingChannel::default();
+        let outcome = send(&channel, digest, &templates(), &policy(), Utc::now())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, DeliveryOutcome::Delivered { .. }));
+
+        let sent = channel.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        let (recipient, subject, content) = &sent[0];
+        assert_eq!(recipient, "#recon");
+        assert_eq!(subject, "Mirage digest: 3 notifications");
+        for value in values {
+            assert_eq!(content.matches(value).count(), 2, "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_immediate_subscriptions_and_repeats_are_not_batched() {
+        let mut batcher = DigestBatcher::new();
+        let now = Utc::now();
+
+        assert_eq!(
+            batcher.add(
+                &subscription(NotificationSchedule::Immediate),
+                item("a.example.com"),
+                now
+            ),
+            None
+        );
+        assert!(batcher.is_empty());
+
+        let subscription = subscription(NotificationSchedule::Batched {
+            interval_minutes: 5,
+        });
+        let item = item("a.example.com");
+        assert!(batcher.add(&subscription, item.clone(), now).is_some());
+        assert_eq!(batcher.add(&subscription, item, now), None);
+        assert_eq!(batcher.take_all()[0].items.len(), 1);
+    }
+
+    #[test]
+    fn test_take_all_flushes_digests_that_are_not_due() {
+        let mut batcher = DigestBatcher::new();
+        let now = Utc::now();
+        for interval_minutes in [5, 60] {
+            let subscription = subscription(NotificationSchedule::Batched { interval_minutes });
+            batcher.add(&subscription, item("a.example.com"), now);
+        }
+
+        assert!(batcher.take_due(now).is_empty());
+        assert_eq!(batcher.take_all().len(), 2);
+        assert!(batcher.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use actix_web::{get, post, web, Error, HttpResponse, Responder};
 use mirage_common::Error as CommonError;
+use serde::Deserialize;
 use uuid::Uuid;
 
 use crate::models::{CreateSubscriptionRequest, SendNotificationRequest};
@@ -8,6 +9,9 @@ use crate::services::NotificationService;
 pub fn notification_routes() -> actix_web::Scope {
     web::scope("/notifications")
         .service(send_notification)
+        // Before `/{id}` so "dead-letters" isn't taken for a notification ID
+        .service(list_dead_letters)
+        .service(requeue_dead_letter)
         .service(get_notification_status)
         .service(create_subscription)
 }
@@ -31,6 +35,53 @@ async fn send_notification(
     Ok(HttpResponse::Created().json(result))
 }
 
+#[derive(Debug, Deserialize)]
+struct DeadLetterQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[get("/dead-letters")]
+async fn list_dead_letters(
+    query: web::Query<DeadLetterQuery>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let dead_letters = notification_service
+        .list_dead_letters(limit, offset)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to list dead letters: {}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        })?;
+
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+#[post("/dead-letters/{id}/requeue")]
+async fn requeue_dead_letter(
+    id: web::Path<String>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, Error> {
+    let dead_letter_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid dead letter ID format"))?;
+
+    let result = notification_service
+        .requeue_dead_letter(dead_letter_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to requeue dead letter: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 #[get("/{id}")]
 async fn get_notification_status(
     id: web::Path<String>,
@@ -1,22 +1,28 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::shutdown::{self, Shutdown};
+use mirage_common::telemetry;
 use tracing::info;
 
 mod channels;
 mod config;
+mod delivery;
+mod digest;
 mod handlers;
 mod models;
 mod repositories;
 mod services;
 mod templates;
-
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
+mod throttle;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("notification-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -51,8 +57,11 @@ async fn main() -> std::io::Result<()> {
     // Initialize background worker for processing notification queue
     let worker_pool = db_pool.clone();
     let worker_config = config.clone();
-    tokio::spawn(async move {
-        services::start_notification_worker(worker_pool, worker_config).await;
+    // Stops the server, then the worker, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+    let worker_shutdown = shutdown.subscribe();
+    let worker = tokio::spawn(async move {
+        services::start_notification_worker(worker_pool, worker_config, worker_shutdown).await;
     });
 
     info!(
@@ -60,19 +69,48 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
-    HttpServer::new(move || {
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("notification-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone()),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("notification-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    let server = HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(web::Data::new(db_pool.clone()))
             .app_data(notification_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::notification_routes()),
             )
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
     .bind(format!("0.0.0.0:{}", config.server.port))?
-    .run()
-    .await
+    .run();
+
+    // The worker sends the digests it is holding before it stops
+    shutdown.run(server, vec![worker]).await
 }
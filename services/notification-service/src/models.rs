@@ -50,6 +50,7 @@ pub enum NotificationChannel {
     Email,
     Webhook,
     Slack,
+    Telegram,
     Database,
 }
 
@@ -59,6 +60,7 @@ impl ToString for NotificationChannel {
             NotificationChannel::Email => "email".to_string(),
             NotificationChannel::Webhook => "webhook".to_string(),
             NotificationChannel::Slack => "slack".to_string(),
+            NotificationChannel::Telegram => "telegram".to_string(),
             NotificationChannel::Database => "database".to_string(),
         }
     }
@@ -70,6 +72,7 @@ impl From<String> for NotificationChannel {
             "email" => NotificationChannel::Email,
             "webhook" => NotificationChannel::Webhook,
             "slack" => NotificationChannel::Slack,
+            "telegram" => NotificationChannel::Telegram,
             "database" => NotificationChannel::Database,
             _ => NotificationChannel::Database, // Default to database for unknown channels
         }
@@ -101,6 +104,11 @@ pub struct Notification {
     pub type_: NotificationType,
     pub subject: String,
     pub content: String,
+    /// Template each delivery is rendered from for its channel; `None` when
+    /// the request supplied the subject and content itself
+    pub template_name: Option<String>,
+    /// Variables the template is rendered with
+    pub data: serde_json::Value,
     pub metadata: HashMap<String, String>,
     pub status: NotificationStatus,
     pub created_at: DateTime<Utc>,
@@ -175,12 +183,44 @@ pub struct DeliveryStatusResponse {
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+/// When deliveries to a subscriber go out
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NotificationSchedule {
+    /// One message per notification, as soon as the worker picks it up
+    #[default]
+    Immediate,
+    /// Collected and sent as a single digest every `interval_minutes`
+    Batched { interval_minutes: u32 },
+}
+
+impl NotificationSchedule {
+    /// Stored as the batch interval, with no interval meaning immediate
+    pub fn interval_minutes(&self) -> Option<u32> {
+        match self {
+            NotificationSchedule::Immediate => None,
+            NotificationSchedule::Batched { interval_minutes } => Some(*interval_minutes),
+        }
+    }
+}
+
+impl From<Option<i32>> for NotificationSchedule {
+    fn from(value: Option<i32>) -> Self {
+        match value {
+            Some(minutes) if minutes > 0 => NotificationSchedule::Batched {
+                interval_minutes: minutes as u32,
+            },
+            _ => NotificationSchedule::Immediate,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateSubscriptionRequest {
     pub notification_type: NotificationType,
     pub channel: NotificationChannel,
     pub recipient: String,
     pub filter_conditions: Option<HashMap<String, String>>,
+    pub schedule: Option<NotificationSchedule>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -190,6 +230,7 @@ pub struct Subscription {
     pub channel: NotificationChannel,
     pub recipient: String,
     pub filter_conditions: HashMap<String, String>,
+    pub schedule: NotificationSchedule,
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -199,3 +240,22 @@ pub struct Subscription {
 pub struct SubscriptionResponse {
     pub subscription_id: Uuid,
 }
+
+/// A delivery that ran out of retries, kept until it is requeued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub delivery_id: Uuid,
+    pub notification_id: Uuid,
+    pub channel: NotificationChannel,
+    pub recipient: String,
+    pub last_error: String,
+    pub retry_count: u32,
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequeueResponse {
+    pub delivery_id: Uuid,
+    pub status: NotificationStatus,
+}
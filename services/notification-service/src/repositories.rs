@@ -1,11 +1,11 @@
 use crate::config::DatabaseConfig;
 use crate::models::{
-    Notification, NotificationChannel, NotificationDelivery, NotificationStatus, NotificationType,
-    Subscription,
+    DeadLetter, Notification, NotificationChannel, NotificationDelivery, NotificationSchedule,
+    NotificationStatus, NotificationType, Subscription,
 };
 use chrono::{DateTime, Utc};
-use mirage_common::{Error, Result};
-use sqlx::{postgres::PgPoolOptions, query, query_as, Pool, Postgres};
+use mirage_common::{database, Error, Result};
+use sqlx::{query, query_as, Pool, Postgres};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -13,11 +13,7 @@ pub type DbPool = Pool<Postgres>;
 
 /// Create database connection pool
 pub async fn create_db_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await
-        .map_err(|e| Error::Database(format!("Database connection failed: {}", e)))?;
+    let pool = database::create_pool(config).await?;
 
     // Run migrations
     sqlx::migrate!("./migrations")
@@ -41,15 +37,18 @@ impl NotificationRepository {
         let id = query!(
             r#"
             INSERT INTO notifications
-                (id, type, subject, content, metadata, status, created_at, updated_at)
+                (id, type, subject, content, template_name, data, metadata, status,
+                 created_at, updated_at)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             RETURNING id
             "#,
             notification.id,
             notification.type_.to_string(),
             notification.subject,
             notification.content,
+            notification.template_name,
+            notification.data,
             serde_json::to_value(&notification.metadata)
                 .map_err(|e| Error::Internal(format!("Failed to serialize metadata: {}", e)))?,
             notification.status.to_string(),
@@ -242,7 +241,7 @@ impl NotificationRepository {
             NotificationRecord,
             r#"
             SELECT 
-                id, type as "type_", subject, content, 
+                id, type as "type_", subject, content, template_name, data,
                 metadata, status, created_at, updated_at, processed_at
             FROM notifications
             WHERE id = $1
@@ -263,6 +262,8 @@ impl NotificationRepository {
                 type_: NotificationType::from(record.type_),
                 subject: record.subject,
                 content: record.content,
+                template_name: record.template_name,
+                data: record.data,
                 metadata,
                 status: NotificationStatus::from(record.status),
                 created_at: record.created_at,
@@ -320,9 +321,10 @@ impl NotificationRepository {
         let id = query!(
             r#"
             INSERT INTO subscriptions
-                (id, notification_type, channel, recipient, filter_conditions, active, created_at, updated_at)
+                (id, notification_type, channel, recipient, filter_conditions,
+                 schedule_interval_minutes, active, created_at, updated_at)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             RETURNING id
             "#,
             subscription.id,
@@ -331,6 +333,10 @@ impl NotificationRepository {
             subscription.recipient,
             serde_json::to_value(&subscription.filter_conditions)
                 .map_err(|e| Error::Internal(format!("Failed to serialize filter conditions: {}", e)))?,
+            subscription
+                .schedule
+                .interval_minutes()
+                .map(|minutes| minutes as i32),
             subscription.active,
             subscription.created_at,
             subscription.updated_at,
@@ -354,7 +360,7 @@ impl NotificationRepository {
             r#"
             SELECT 
                 id, notification_type, channel, recipient, 
-                filter_conditions, active, created_at, updated_at
+                filter_conditions, schedule_interval_minutes, active, created_at, updated_at
             FROM subscriptions
             WHERE notification_type = $1 AND active = true
             "#,
@@ -365,28 +371,165 @@ impl NotificationRepository {
         .map_err(|e| Error::Database(format!("Failed to fetch subscriptions: {}", e)))?;
 
         // Convert database records to domain objects
-        let result = subscriptions
+        Ok(subscriptions.into_iter().map(Subscription::from).collect())
+    }
+
+    /// Active subscriptions whose deliveries are sent as periodic digests
+    pub async fn get_batched_subscriptions(&self) -> Result<Vec<Subscription>> {
+        let subscriptions = sqlx::query_as::<_, SubscriptionRecord>(
+            r#"
+            SELECT
+                id, notification_type, channel, recipient,
+                filter_conditions, schedule_interval_minutes, active, created_at, updated_at
+            FROM subscriptions
+            WHERE active = true AND schedule_interval_minutes IS NOT NULL
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch batched subscriptions: {}", e)))?;
+
+        Ok(subscriptions.into_iter().map(Subscription::from).collect())
+    }
+
+    /// Moves a delivery that has run out of retries to the dead-letter table
+    pub async fn dead_letter_delivery(
+        &self,
+        delivery: &NotificationDelivery,
+        retry_count: u32,
+        error: &str,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to start transaction: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO notification_dead_letters
+                (id, delivery_id, notification_id, channel, recipient, last_error,
+                 retry_count, dead_lettered_at)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (delivery_id) DO UPDATE
+                SET last_error = EXCLUDED.last_error,
+                    retry_count = EXCLUDED.retry_count,
+                    dead_lettered_at = EXCLUDED.dead_lettered_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(delivery.id)
+        .bind(delivery.notification_id)
+        .bind(delivery.channel.to_string())
+        .bind(&delivery.recipient)
+        .bind(error)
+        .bind(retry_count as i32)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to dead-letter delivery: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            UPDATE notification_deliveries
+            SET status = $2, error_message = $3, retry_count = $4, next_retry_at = NULL,
+                updated_at = $5
+            WHERE id = $1
+            "#,
+        )
+        .bind(delivery.id)
+        .bind(NotificationStatus::Failed.to_string())
+        .bind(error)
+        .bind(retry_count as i32)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to update delivery status: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))
+    }
+
+    pub async fn list_dead_letters(&self, limit: i64, offset: i64) -> Result<Vec<DeadLetter>> {
+        let records = sqlx::query_as::<_, DeadLetterRecord>(
+            r#"
+            SELECT
+                id, delivery_id, notification_id, channel, recipient, last_error,
+                retry_count, dead_lettered_at
+            FROM notification_dead_letters
+            ORDER BY dead_lettered_at DESC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch dead letters: {}", e)))?;
+
+        let result = records
             .into_iter()
-            .map(|record| {
-                // Parse filter conditions JSON
-                let filter_conditions: HashMap<String, String> =
-                    serde_json::from_value(record.filter_conditions).unwrap_or_default();
-
-                Subscription {
-                    id: record.id,
-                    notification_type: NotificationType::from(record.notification_type),
-                    channel: NotificationChannel::from(record.channel),
-                    recipient: record.recipient,
-                    filter_conditions,
-                    active: record.active,
-                    created_at: record.created_at,
-                    updated_at: record.updated_at,
-                }
+            .map(|record| DeadLetter {
+                id: record.id,
+                delivery_id: record.delivery_id,
+                notification_id: record.notification_id,
+                channel: NotificationChannel::from(record.channel),
+                recipient: record.recipient,
+                last_error: record.last_error,
+                retry_count: record.retry_count as u32,
+                dead_lettered_at: record.dead_lettered_at,
             })
             .collect();
 
         Ok(result)
     }
+
+    /// Removes a dead letter and puts its delivery back in the queue with a
+    /// fresh retry budget. Returns the delivery ID, or `None` if there is no
+    /// such dead letter.
+    pub async fn requeue_dead_letter(&self, dead_letter_id: &Uuid) -> Result<Option<Uuid>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to start transaction: {}", e)))?;
+
+        let delivery_id: Option<Uuid> = sqlx::query_scalar(
+            "DELETE FROM notification_dead_letters WHERE id = $1 RETURNING delivery_id",
+        )
+        .bind(dead_letter_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to remove dead letter: {}", e)))?;
+
+        let Some(delivery_id) = delivery_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE notification_deliveries
+            SET status = $2, error_message = NULL, retry_count = 0, next_retry_at = NULL,
+                updated_at = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(NotificationStatus::Pending.to_string())
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to requeue delivery: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(Some(delivery_id))
+    }
 }
 
 // Database Record structs
@@ -395,6 +538,8 @@ struct NotificationRecord {
     type_: String,
     subject: String,
     content: String,
+    template_name: Option<String>,
+    data: serde_json::Value,
     metadata: serde_json::Value,
     status: String,
     created_at: DateTime<Utc>,
@@ -416,17 +561,51 @@ struct NotificationDeliveryRecord {
     completed_at: Option<DateTime<Utc>>,
 }
 
+#[derive(sqlx::FromRow)]
+struct DeadLetterRecord {
+    id: Uuid,
+    delivery_id: Uuid,
+    notification_id: Uuid,
+    channel: String,
+    recipient: String,
+    last_error: String,
+    retry_count: i32,
+    dead_lettered_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
 struct SubscriptionRecord {
     id: Uuid,
     notification_type: String,
     channel: String,
     recipient: String,
     filter_conditions: serde_json::Value,
+    schedule_interval_minutes: Option<i32>,
     active: bool,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
+impl From<SubscriptionRecord> for Subscription {
+    fn from(record: SubscriptionRecord) -> Self {
+        // Parse filter conditions JSON
+        let filter_conditions: HashMap<String, String> =
+            serde_json::from_value(record.filter_conditions).unwrap_or_default();
+
+        Subscription {
+            id: record.id,
+            notification_type: NotificationType::from(record.notification_type),
+            channel: NotificationChannel::from(record.channel),
+            recipient: record.recipient,
+            filter_conditions,
+            schedule: NotificationSchedule::from(record.schedule_interval_minutes),
+            active: record.active,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
 // Helper trait implementations
 impl From<String> for NotificationStatus {
     fn from(value: String) -> Self {
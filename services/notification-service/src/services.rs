@@ -1,14 +1,20 @@
 use crate::channels::{get_channel, Channel};
 use crate::config::AppConfig;
+use crate::delivery::{self, DeliveryOutcome, RetryPolicy};
+use crate::digest::{self, Digest, DigestBatcher, DigestItem};
 use crate::models::{
-    CreateSubscriptionRequest, DeliveryStatusResponse, Notification, NotificationChannelRequest,
-    NotificationDelivery, NotificationResponse, NotificationStatus, NotificationStatusResponse,
-    SendNotificationRequest, Subscription, SubscriptionResponse,
+    CreateSubscriptionRequest, DeadLetter, DeliveryStatusResponse, Notification,
+    NotificationChannelRequest, NotificationDelivery, NotificationResponse, NotificationStatus,
+    NotificationStatusResponse, RequeueResponse, SendNotificationRequest, Subscription,
+    SubscriptionResponse,
 };
 use crate::repositories::{DbPool, NotificationRepository};
-use crate::templates::TemplateRegistry;
+use crate::templates::{default_template_name, TemplateRegistry, TemplateVariant};
+use crate::throttle::{self, Decision, Throttle};
 use chrono::Utc;
+use mirage_common::shutdown::ShutdownSignal;
 use mirage_common::{Error, Result};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
@@ -43,35 +49,30 @@ impl NotificationService {
         }
 
         // Generate subject and content
-        let (subject, content) = if let (Some(custom_subject), Some(custom_content)) =
-            (&request.custom_subject, &request.custom_content)
-        {
-            // Use custom content directly if provided
-            (custom_subject.clone(), custom_content.clone())
-        } else if let Some(template_name) = &request.template_name {
-            // TODO: Lookup custom template by name from template store
-            // For now, fallback to default templates
-            let subject = self.templates.render_subject(
-                &request.notification_type,
-                &serde_json::to_value(&request.data)?,
-            )?;
-            let content = self.templates.render_content(
-                &request.notification_type,
-                &serde_json::to_value(&request.data)?,
-            )?;
-            (subject, content)
-        } else {
-            // Use default templates based on notification type
-            let subject = self.templates.render_subject(
-                &request.notification_type,
-                &serde_json::to_value(&request.data)?,
-            )?;
-            let content = self.templates.render_content(
-                &request.notification_type,
-                &serde_json::to_value(&request.data)?,
-            )?;
-            (subject, content)
-        };
+        let data = serde_json::to_value(&request.data)?;
+        let (subject, content, template_name) =
+            if let (Some(custom_subject), Some(custom_content)) =
+                (&request.custom_subject, &request.custom_content)
+            {
+                // Use custom content directly if provided
+                (custom_subject.clone(), custom_content.clone(), None)
+            } else {
+                // Otherwise render the named template, or the notification type's
+                // default one, for every requested channel so a missing template
+                // or variable is reported now rather than by the worker
+                let template_name = request
+                    .template_name
+                    .clone()
+                    .unwrap_or_else(|| default_template_name(&request.notification_type));
+
+                let mut rendered = Vec::new();
+                for channel_req in &request.channels {
+                    let variant = TemplateVariant::for_channel(&channel_req.channel);
+                    rendered.push(self.templates.render(&template_name, variant, &data)?);
+                }
+                let (subject, content) = rendered.swap_remove(0);
+                (subject, content, Some(template_name))
+            };
 
         // Create notification record
         let notification_id = Uuid::new_v4();
@@ -80,6 +81,8 @@ impl NotificationService {
             type_: request.notification_type,
             subject: subject.clone(),
             content: content.clone(),
+            template_name,
+            data,
             metadata: request.metadata.unwrap_or_default(),
             status: NotificationStatus::Pending,
             created_at: Utc::now(),
@@ -167,6 +170,13 @@ impl NotificationService {
         &self,
         request: CreateSubscriptionRequest,
     ) -> Result<SubscriptionResponse> {
+        let schedule = request.schedule.unwrap_or_default();
+        if schedule.interval_minutes() == Some(0) {
+            return Err(Error::Validation(
+                "A batched schedule needs an interval of at least one minute".into(),
+            ));
+        }
+
         // Create subscription record
         let subscription_id = Uuid::new_v4();
         let subscription = Subscription {
@@ -175,6 +185,7 @@ impl NotificationService {
             channel: request.channel,
             recipient: request.recipient,
             filter_conditions: request.filter_conditions.unwrap_or_default(),
+            schedule,
             active: true,
             created_at: Utc::now(),
             updated_at: Utc::now(),
@@ -185,18 +196,43 @@ impl NotificationService {
 
         Ok(SubscriptionResponse { subscription_id })
     }
+
+    // List deliveries that ran out of retries, most recent first
+    pub async fn list_dead_letters(&self, limit: i64, offset: i64) -> Result<Vec<DeadLetter>> {
+        self.repo.list_dead_letters(limit, offset).await
+    }
+
+    // Put a dead-lettered delivery back in the queue
+    pub async fn requeue_dead_letter(&self, dead_letter_id: Uuid) -> Result<RequeueResponse> {
+        let delivery_id = self
+            .repo
+            .requeue_dead_letter(&dead_letter_id)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!("Dead letter with ID {} not found", dead_letter_id))
+            })?;
+
+        Ok(RequeueResponse {
+            delivery_id,
+            status: NotificationStatus::Pending,
+        })
+    }
 }
 
-// Worker function to process pending notifications
-pub async fn start_notification_worker(pool: DbPool, config: AppConfig) {
-    let repo = NotificationRepository::new(pool);
-    let config = Arc::new(config);
+// Worker function to process pending notifications. Runs until `shutdown`,
+// then sends any digests it is still holding.
+pub async fn start_notification_worker(
+    pool: DbPool,
+    config: AppConfig,
+    mut shutdown: ShutdownSignal,
+) {
+    let mut worker = DeliveryWorker::new(pool, config);
 
     tracing::info!("Starting notification delivery worker");
 
     loop {
         // Process pending deliveries
-        match process_pending_deliveries(&repo, &config).await {
+        match worker.process_pending_deliveries().await {
             Ok(count) => {
                 if count > 0 {
                     tracing::info!("Processed {} notification deliveries", count);
@@ -207,94 +243,337 @@ pub async fn start_notification_worker(pool: DbPool, config: AppConfig) {
             }
         }
 
+        // Send the digests whose interval has passed
+        let due = worker.digests.take_due(Utc::now());
+        worker.flush_digests(due).await;
+
         // Sleep before next poll
-        time::sleep(Duration::from_secs(config.worker.poll_interval_seconds)).await;
+        let poll_interval = Duration::from_secs(worker.config.worker.poll_interval_seconds);
+        tokio::select! {
+            _ = time::sleep(poll_interval) => {}
+            _ = shutdown.recv() => break,
+        }
     }
+
+    let pending = worker.digests.take_all();
+    if !pending.is_empty() {
+        tracing::info!("Flushing {} digests before shutting down", pending.len());
+        worker.flush_digests(pending).await;
+    }
+
+    tracing::info!("Notification delivery worker stopped");
+}
+
+/// State the worker keeps between polls
+struct DeliveryWorker {
+    repo: NotificationRepository,
+    templates: TemplateRegistry,
+    policy: RetryPolicy,
+    throttle: Throttle,
+    digests: DigestBatcher,
+    config: Arc<AppConfig>,
 }
 
-async fn process_pending_deliveries(
-    repo: &NotificationRepository,
-    config: &Arc<AppConfig>,
-) -> Result<usize> {
-    // Get pending deliveries
-    let deliveries = repo
-        .get_pending_deliveries(config.worker.batch_size as i64)
-        .await?;
-
-    if deliveries.is_empty() {
-        return Ok(0);
+impl DeliveryWorker {
+    fn new(pool: DbPool, config: AppConfig) -> Self {
+        Self {
+            repo: NotificationRepository::new(pool),
+            templates: TemplateRegistry::new(&config.templates),
+            policy: RetryPolicy::from_config(&config),
+            throttle: Throttle::new(&config.throttle),
+            digests: DigestBatcher::new(),
+            config: Arc::new(config),
+        }
     }
 
-    let mut processed_count = 0;
+    async fn process_pending_deliveries(&mut self) -> Result<usize> {
+        let repo = &self.repo;
 
-    // Process each delivery
-    for delivery in deliveries {
-        // Get the associated notification
-        if let Some(notification) = repo.get_notification(&delivery.notification_id).await? {
-            // Get appropriate channel handler
-            let channel = get_channel(&delivery.channel, config);
+        // Get pending deliveries
+        let deliveries = repo
+            .get_pending_deliveries(self.config.worker.batch_size as i64)
+            .await?;
 
-            // Attempt to send notification
-            match channel
-                .send(&delivery, &notification.content, &notification.subject)
-                .await
-            {
-                Ok(_) => {
-                    // Update delivery status to processed
-                    repo.update_delivery_status(
+        if deliveries.is_empty() {
+            return Ok(0);
+        }
+
+        // Subscribers who asked for digests, by notification type, channel
+        // and recipient
+        let batched: HashMap<(String, String, String), Subscription> = repo
+            .get_batched_subscriptions()
+            .await?
+            .into_iter()
+            .map(|subscription| {
+                let key = (
+                    subscription.notification_type.to_string(),
+                    subscription.channel.to_string(),
+                    subscription.recipient.clone(),
+                );
+                (key, subscription)
+            })
+            .collect();
+
+        self.throttle.prune(Utc::now());
+        let mut processed_count = 0;
+
+        // Process each delivery
+        for delivery in deliveries {
+            // Get the associated notification
+            if let Some(notification) = repo.get_notification(&delivery.notification_id).await? {
+                let subscription = batched.get(&(
+                    notification.type_.to_string(),
+                    delivery.channel.to_string(),
+                    delivery.recipient.clone(),
+                ));
+
+                // Render the notification in the form this channel expects;
+                // digest entries are plain text whatever the channel
+                let variant = match subscription {
+                    Some(_) => TemplateVariant::Text,
+                    None => TemplateVariant::for_channel(&delivery.channel),
+                };
+                let (subject, content) = match &notification.template_name {
+                    Some(template_name) => {
+                        match self
+                            .templates
+                            .render(template_name, variant, &notification.data)
+                        {
+                            Ok(rendered) => rendered,
+                            Err(e) => {
+                                // Retrying won't make the template render, but
+                                // requeueing after the template is fixed will
+                                repo.dead_letter_delivery(
+                                    &delivery,
+                                    delivery.retry_count,
+                                    &format!("{}", e),
+                                )
+                                .await?;
+
+                                tracing::warn!(
+                                    "Failed to render notification {} for delivery {}: {}",
+                                    notification.id,
+                                    delivery.id,
+                                    e
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                    None => (notification.subject.clone(), notification.content.clone()),
+                };
+
+                // Batched subscribers get the notification in their next
+                // digest instead. The digest is one message, so the throttle
+                // doesn't apply.
+                if let Some(subscription) = subscription {
+                    let item = DigestItem {
+                        delivery: delivery.clone(),
+                        subject,
+                        content,
+                    };
+                    if let Some(due_at) = self.digests.add(subscription, item, Utc::now()) {
+                        // Keep it out of the pending queue until the digest is sent
+                        repo.update_delivery_status(
+                            &delivery.id,
+                            NotificationStatus::Pending,
+                            None,
+                            None,
+                            Some(due_at),
+                            None,
+                        )
+                        .await?;
+                    }
+                    continue;
+                }
+
+                // Hold back repeats of an alert and recipients over their limit
+                let body = match self
+                    .throttle
+                    .check(&delivery, &subject, &content, Utc::now())
+                {
+                    Decision::Send { suppressed } => throttle::with_digest(
+                        &content,
+                        suppressed,
+                        variant == TemplateVariant::Html,
+                    ),
+                    Decision::Duplicate { sent_at } => {
+                        repo.update_delivery_status(
+                            &delivery.id,
+                            NotificationStatus::Canceled,
+                            Some(format!(
+                                "Suppressed as a duplicate of an alert sent at {}",
+                                sent_at
+                            )),
+                            None,
+                            None,
+                            Some(Utc::now()),
+                        )
+                        .await?;
+
+                        tracing::info!(
+                            "Suppressed duplicate delivery {} to {}",
+                            delivery.id,
+                            delivery.recipient
+                        );
+                        continue;
+                    }
+                    Decision::Deferred { until } => {
+                        repo.update_delivery_status(
+                            &delivery.id,
+                            NotificationStatus::Pending,
+                            None,
+                            None,
+                            Some(until),
+                            None,
+                        )
+                        .await?;
+
+                        tracing::debug!(
+                            "Deferred delivery {} to {} until {}",
+                            delivery.id,
+                            delivery.recipient,
+                            until
+                        );
+                        continue;
+                    }
+                };
+
+                // Get appropriate channel handler
+                let channel = get_channel(&delivery.channel, &self.config);
+
+                // Attempt to send notification
+                let outcome = delivery::attempt(
+                    &*channel,
+                    &delivery,
+                    &body,
+                    &subject,
+                    &self.policy,
+                    Utc::now(),
+                )
+                .await;
+                if let DeliveryOutcome::Delivered { completed_at } = &outcome {
+                    self.throttle
+                        .record(&delivery, &subject, &content, *completed_at);
+                    processed_count += 1;
+                }
+                self.record_outcome(&delivery, outcome).await?;
+            }
+        }
+
+        // Update notification status where all deliveries are complete
+        // This would typically be done using a SQL query, but for simplicity we're not
+        // implementing it fully here
+
+        Ok(processed_count)
+    }
+
+    /// Sends each digest as one message and settles all of its deliveries
+    /// with the result
+    async fn flush_digests(&self, digests: Vec<Digest>) {
+        for digest in digests {
+            let channel = get_channel(&digest.items[0].delivery.channel, &self.config);
+            let now = Utc::now();
+
+            let sent = digest::send(&*channel, &digest, &self.templates, &self.policy, now).await;
+            let outcomes: Vec<_> = digest
+                .deliveries()
+                .map(|delivery| {
+                    let outcome = match &sent {
+                        Ok(DeliveryOutcome::Delivered { completed_at }) => {
+                            DeliveryOutcome::Delivered {
+                                completed_at: *completed_at,
+                            }
+                        }
+                        // Each delivery keeps its own retry count
+                        Ok(DeliveryOutcome::Retry { error, .. })
+                        | Ok(DeliveryOutcome::DeadLetter { error, .. }) => {
+                            self.policy.on_failure(delivery, error.clone(), now)
+                        }
+                        // Retrying won't make the digest render, but requeueing
+                        // after the template is fixed will
+                        Err(e) => DeliveryOutcome::DeadLetter {
+                            retry_count: delivery.retry_count,
+                            error: format!("Failed to render digest: {}", e),
+                        },
+                    };
+                    (delivery, outcome)
+                })
+                .collect();
+
+            for (delivery, outcome) in outcomes {
+                if let Err(e) = self.record_outcome(delivery, outcome).await {
+                    tracing::error!(
+                        "Failed to update delivery {} from digest for subscription {}: {}",
+                        delivery.id,
+                        digest.subscription_id,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Stores what happened to a delivery attempt
+    async fn record_outcome(
+        &self,
+        delivery: &NotificationDelivery,
+        outcome: DeliveryOutcome,
+    ) -> Result<()> {
+        match outcome {
+            DeliveryOutcome::Delivered { completed_at } => {
+                // Update delivery status to processed
+                self.repo
+                    .update_delivery_status(
                         &delivery.id,
                         NotificationStatus::Processed,
                         None,
                         None,
                         None,
-                        Some(Utc::now()),
+                        Some(completed_at),
                     )
                     .await?;
-
-                    processed_count += 1;
-                }
-                Err(e) => {
-                    let retry_count = delivery.retry_count + 1;
-                    let retry_delay =
-                        config.webhook.retry_delay_seconds * (2_u64.pow(retry_count.min(5) as u32));
-                    let next_retry = if retry_count < config.webhook.max_retries {
-                        Some(Utc::now() + chrono::Duration::seconds(retry_delay as i64))
-                    } else {
-                        None
-                    };
-
-                    let status = if retry_count >= config.webhook.max_retries {
-                        NotificationStatus::Failed
-                    } else {
-                        NotificationStatus::Pending
-                    };
-
-                    // Update delivery with error and retry information
-                    repo.update_delivery_status(
+            }
+            DeliveryOutcome::Retry {
+                retry_count,
+                next_retry_at,
+                error,
+            } => {
+                // Update delivery with error and retry information
+                self.repo
+                    .update_delivery_status(
                         &delivery.id,
-                        status,
-                        Some(format!("{}", e)),
+                        NotificationStatus::Pending,
+                        Some(error.clone()),
                         Some(retry_count),
-                        next_retry,
+                        Some(next_retry_at),
                         None,
                     )
                     .await?;
 
-                    tracing::warn!(
-                        "Failed to deliver notification {}: {} (retry {}/{})",
-                        delivery.id,
-                        e,
-                        retry_count,
-                        config.webhook.max_retries
-                    );
-                }
+                tracing::warn!(
+                    "Failed to deliver notification {}: {} (attempt {}/{}, next at {})",
+                    delivery.id,
+                    error,
+                    retry_count,
+                    self.policy.max_attempts,
+                    next_retry_at
+                );
             }
-        }
-    }
+            DeliveryOutcome::DeadLetter { retry_count, error } => {
+                self.repo
+                    .dead_letter_delivery(delivery, retry_count, &error)
+                    .await?;
 
-    // Update notification status where all deliveries are complete
-    // This would typically be done using a SQL query, but for simplicity we're not
-    // implementing it fully here
+                tracing::error!(
+                    "Giving up on delivery {} after {} attempts: {}",
+                    delivery.id,
+                    retry_count,
+                    error
+                );
+            }
+        }
 
-    Ok(processed_count)
+        Ok(())
+    }
 }
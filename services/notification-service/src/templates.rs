@@ -1,19 +1,57 @@
 use crate::config::TemplateConfig;
-use crate::models::NotificationType;
-use handlebars::Handlebars;
+use crate::models::{NotificationChannel, NotificationType};
+use handlebars::{Handlebars, TemplateError};
 use mirage_common::{Error, Result};
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 
+/// Which form of a notification a channel gets. Each template name `<name>`
+/// is made of `<name>_subject` plus `<name>_email` (HTML) and/or
+/// `<name>_text` (plain text).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateVariant {
+    Html,
+    Text,
+}
+
+impl TemplateVariant {
+    pub fn for_channel(channel: &NotificationChannel) -> Self {
+        match channel {
+            NotificationChannel::Email => TemplateVariant::Html,
+            NotificationChannel::Slack
+            | NotificationChannel::Telegram
+            | NotificationChannel::Webhook
+            | NotificationChannel::Database => TemplateVariant::Text,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            TemplateVariant::Html => "email",
+            TemplateVariant::Text => "text",
+        }
+    }
+}
+
+/// Template name used for a notification type when the request doesn't name one
+pub fn default_template_name(notification_type: &NotificationType) -> String {
+    match notification_type {
+        NotificationType::Custom(name) => name.clone(),
+        other => other.to_string(),
+    }
+}
+
 pub struct TemplateRegistry {
-    handlebars: Arc<Handlebars<'static>>,
+    /// `*_email` templates, which HTML-escape their variables
+    html: Arc<Handlebars<'static>>,
+    /// `*_subject` and `*_text` templates, which insert variables verbatim
+    text: Arc<Handlebars<'static>>,
 }
 
 impl TemplateRegistry {
     pub fn new(config: &TemplateConfig) -> Self {
-        let mut handlebars = Handlebars::new();
-        handlebars.set_strict_mode(true);
+        let mut registry = RegistryBuilder::new();
 
         // Register templates from template directory
         let template_dir = Path::new(&config.dir);
@@ -27,8 +65,8 @@ impl TemplateRegistry {
                                 if file_name.ends_with(".hbs") {
                                     let template_name = file_name.trim_end_matches(".hbs");
                                     let template_path = entry.path();
-                                    if let Err(e) = handlebars
-                                        .register_template_file(template_name, &template_path)
+                                    if let Err(e) =
+                                        registry.register_file(template_name, &template_path)
                                     {
                                         tracing::error!(
                                             "Failed to register template '{}': {}",
@@ -49,126 +87,286 @@ impl TemplateRegistry {
         }
 
         // Register default templates in memory as fallback
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "new_entity_email",
                 include_str!("../templates/new_entity_email.hbs"),
             )
             .expect("Failed to register new_entity_email template");
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "new_relationship_email",
                 include_str!("../templates/new_relationship_email.hbs"),
             )
             .expect("Failed to register new_relationship_email template");
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "scan_complete_email",
                 include_str!("../templates/scan_complete_email.hbs"),
             )
             .expect("Failed to register scan_complete_email template");
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "alert_triggered_email",
                 include_str!("../templates/alert_triggered_email.hbs"),
             )
             .expect("Failed to register alert_triggered_email template");
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "system_alert_email",
                 include_str!("../templates/system_alert_email.hbs"),
             )
             .expect("Failed to register system_alert_email template");
+        registry
+            .register(
+                "digest_email",
+                include_str!("../templates/digest_email.hbs"),
+            )
+            .expect("Failed to register digest_email template");
 
         // Also register subject templates
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "new_entity_subject",
                 "New Entity Detected: {{entity_type}} - {{value}}",
             )
             .expect("Failed to register new_entity_subject template");
-        handlebars
-            .register_template_string(
+        registry
+            .register(
                 "new_relationship_subject",
                 "New Relationship Detected: {{relationship_type}}",
             )
             .expect("Failed to register new_relationship_subject template");
-        handlebars
-            .register_template_string("scan_complete_subject", "Scan Completed: {{scan_name}}")
+        registry
+            .register("scan_complete_subject", "Scan Completed: {{scan_name}}")
             .expect("Failed to register scan_complete_subject template");
-        handlebars
-            .register_template_string("alert_triggered_subject", "Alert Triggered: {{alert_name}}")
+        registry
+            .register("alert_triggered_subject", "Alert Triggered: {{alert_name}}")
             .expect("Failed to register alert_triggered_subject template");
-        handlebars
-            .register_template_string("system_alert_subject", "System Alert: {{alert_type}}")
+        registry
+            .register("system_alert_subject", "System Alert: {{alert_type}}")
             .expect("Failed to register system_alert_subject template");
+        registry
+            .register("digest_subject", "Mirage digest: {{count}} notifications")
+            .expect("Failed to register digest_subject template");
 
-        Self {
-            handlebars: Arc::new(handlebars),
-        }
+        // And plain text bodies for chat and webhook channels
+        registry
+            .register(
+                "new_entity_text",
+                "New {{entity_type}} detected: *{{value}}*",
+            )
+            .expect("Failed to register new_entity_text template");
+        registry
+            .register(
+                "new_relationship_text",
+                "New {{relationship_type}} relationship detected",
+            )
+            .expect("Failed to register new_relationship_text template");
+        registry
+            .register("scan_complete_text", "Scan *{{scan_name}}* has completed")
+            .expect("Failed to register scan_complete_text template");
+        registry
+            .register(
+                "alert_triggered_text",
+                "Alert *{{alert_name}}* was triggered",
+            )
+            .expect("Failed to register alert_triggered_text template");
+        registry
+            .register("system_alert_text", "System alert: {{alert_type}}")
+            .expect("Failed to register system_alert_text template");
+        registry
+            .register(
+                "digest_text",
+                "{{count}} notifications since {{since}}\
+                 {{#each items}}\n\n*{{subject}}* ({{created_at}})\n{{content}}{{/each}}",
+            )
+            .expect("Failed to register digest_text template");
+
+        registry.build()
     }
 
-    pub fn render_subject(
+    /// Renders the subject and the body of template `template_name` in the
+    /// form `variant` calls for. A template that isn't registered, or a
+    /// variable it uses that `vars` doesn't provide, is a validation error.
+    pub fn render(
         &self,
-        notification_type: &NotificationType,
-        data: &serde_json::Value,
-    ) -> Result<String> {
-        let template_name = match notification_type {
-            NotificationType::NewEntity => "new_entity_subject",
-            NotificationType::NewRelationship => "new_relationship_subject",
-            NotificationType::ScanComplete => "scan_complete_subject",
-            NotificationType::AlertTriggered => "alert_triggered_subject",
-            NotificationType::SystemAlert => "system_alert_subject",
-            NotificationType::Custom(name) => {
-                // For custom types, check if a custom template exists, otherwise use a generic one
-                let custom_name = format!("{}_subject", name);
-                if self.handlebars.has_template(&custom_name) {
-                    &custom_name
-                } else {
-                    "generic_subject"
-                }
-            }
-        };
+        template_name: &str,
+        variant: TemplateVariant,
+        vars: &serde_json::Value,
+    ) -> Result<(String, String)> {
+        let subject = Self::render_one(&self.text, &format!("{}_subject", template_name), vars)?;
 
-        let rendered = self.handlebars.render(template_name, data).map_err(|e| {
-            Error::Internal(format!(
-                "Failed to render subject template {}: {}",
-                template_name, e
-            ))
-        })?;
+        let body_name = format!("{}_{}", template_name, variant.suffix());
+        let body = match variant {
+            TemplateVariant::Html => Self::render_one(&self.html, &body_name, vars)?,
+            TemplateVariant::Text => Self::render_one(&self.text, &body_name, vars)?,
+        };
 
-        Ok(rendered)
+        Ok((subject, body))
     }
 
-    pub fn render_content(
-        &self,
-        notification_type: &NotificationType,
-        data: &serde_json::Value,
+    fn render_one(
+        handlebars: &Handlebars<'static>,
+        name: &str,
+        vars: &serde_json::Value,
     ) -> Result<String> {
-        let template_name = match notification_type {
-            NotificationType::NewEntity => "new_entity_email",
-            NotificationType::NewRelationship => "new_relationship_email",
-            NotificationType::ScanComplete => "scan_complete_email",
-            NotificationType::AlertTriggered => "alert_triggered_email",
-            NotificationType::SystemAlert => "system_alert_email",
-            NotificationType::Custom(name) => {
-                // For custom types, check if a custom template exists, otherwise use a generic one
-                let custom_name = format!("{}_email", name);
-                if self.handlebars.has_template(&custom_name) {
-                    &custom_name
-                } else {
-                    "generic_email"
-                }
+        if !handlebars.has_template(name) {
+            return Err(Error::Validation(format!(
+                "Notification template '{}' is not registered",
+                name
+            )));
+        }
+
+        handlebars
+            .render(name, vars)
+            .map_err(|e| Error::Validation(format!("Failed to render template '{}': {}", name, e)))
+    }
+}
+
+/// Sorts templates into the HTML or the plain text registry by name suffix
+struct RegistryBuilder {
+    html: Handlebars<'static>,
+    text: Handlebars<'static>,
+}
+
+impl RegistryBuilder {
+    fn new() -> Self {
+        let mut html = Handlebars::new();
+        html.set_strict_mode(true);
+
+        let mut text = Handlebars::new();
+        text.set_strict_mode(true);
+        text.register_escape_fn(handlebars::no_escape);
+
+        Self { html, text }
+    }
+
+    fn registry_for(&mut self, name: &str) -> &mut Handlebars<'static> {
+        if name.ends_with("_email") {
+            &mut self.html
+        } else {
+            &mut self.text
+        }
+    }
+
+    fn register(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> std::result::Result<(), Box<TemplateError>> {
+        self.registry_for(name)
+            .register_template_string(name, source)
+            .map_err(Box::new)
+    }
+
+    fn register_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+    ) -> std::result::Result<(), Box<TemplateError>> {
+        self.registry_for(name)
+            .register_template_file(name, path)
+            .map_err(Box::new)
+    }
+
+    fn build(self) -> TemplateRegistry {
+        TemplateRegistry {
+            html: Arc::new(self.html),
+            text: Arc::new(self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn registry() -> TemplateRegistry {
+        let mut registry = RegistryBuilder::new();
+        registry
+            .register(
+                "scan_digest_subject",
+                "Scan {{scan_name}}: {{count}} findings",
+            )
+            .unwrap();
+        registry
+            .register(
+                "scan_digest_email",
+                "<h1>{{scan_name}}</h1><p>{{count}} findings for {{target}}</p>",
+            )
+            .unwrap();
+        registry
+            .register(
+                "scan_digest_text",
+                "*{{scan_name}}*: {{count}} findings for {{target}}",
+            )
+            .unwrap();
+        registry.build()
+    }
+
+    fn vars() -> serde_json::Value {
+        json!({ "scan_name": "Q3 & Q4", "count": 12, "target": "<example.com>" })
+    }
+
+    #[test]
+    fn test_render_html_escapes_variables() {
+        let (subject, body) = registry()
+            .render("scan_digest", TemplateVariant::Html, &vars())
+            .unwrap();
+
+        assert_eq!(subject, "Scan Q3 & Q4: 12 findings");
+        assert_eq!(
+            body,
+            "<h1>Q3 &amp; Q4</h1><p>12 findings for &lt;example.com&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_text_inserts_variables_verbatim() {
+        let (subject, body) = registry()
+            .render(
+                "scan_digest",
+                TemplateVariant::for_channel(&NotificationChannel::Slack),
+                &vars(),
+            )
+            .unwrap();
+
+        assert_eq!(subject, "Scan Q3 & Q4: 12 findings");
+        assert_eq!(body, "*Q3 & Q4*: 12 findings for <example.com>");
+    }
+
+    #[test]
+    fn test_missing_template_or_variable_is_an_error() {
+        let registry = registry();
+
+        match registry.render("weekly", TemplateVariant::Html, &vars()) {
+            Err(Error::Validation(message)) => {
+                assert!(message.contains("'weekly_subject' is not registered"))
             }
-        };
+            other => panic!("expected a validation error, got {:?}", other),
+        }
 
-        let rendered = self.handlebars.render(template_name, data).map_err(|e| {
-            Error::Internal(format!(
-                "Failed to render content template {}: {}",
-                template_name, e
-            ))
-        })?;
+        let incomplete = json!({ "scan_name": "Q3", "count": 1 });
+        match registry.render("scan_digest", TemplateVariant::Text, &incomplete) {
+            Err(Error::Validation(message)) => {
+                assert!(message.contains("scan_digest_text"));
+                assert!(message.contains("target"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
 
-        Ok(rendered)
+    #[test]
+    fn test_default_template_names() {
+        assert_eq!(
+            default_template_name(&NotificationType::ScanComplete),
+            "scan_complete"
+        );
+        assert_eq!(
+            default_template_name(&NotificationType::Custom("weekly".to_string())),
+            "weekly"
+        );
     }
 }
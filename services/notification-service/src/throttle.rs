@@ -0,0 +1,354 @@
+//! Per-recipient deduplication and rate limiting
+//!
+//! The worker asks the throttle before every send. An alert with the same
+//! subject and content as one delivered to the same recipient within the
+//! dedup window is suppressed, and once a recipient has had
+//! `max_per_recipient` deliveries within the rate window, further ones are
+//! deferred until the window has room again. The state lives in the worker's
+//! memory, so it starts empty whenever the service restarts.
+
+use crate::config::ThrottleConfig;
+use crate::models::NotificationDelivery;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Go ahead; `suppressed` duplicates of this alert were dropped since it
+    /// last went out (always 0 unless the digest is enabled)
+    Send { suppressed: u32 },
+    /// The same alert already went to this recipient at `sent_at`
+    Duplicate { sent_at: DateTime<Utc> },
+    /// The recipient is over its rate limit until `until`
+    Deferred { until: DateTime<Utc> },
+}
+
+#[derive(Debug)]
+struct Sent {
+    at: DateTime<Utc>,
+    suppressed: u32,
+}
+
+pub struct Throttle {
+    dedup_window: Duration,
+    rate_window: Duration,
+    max_per_recipient: usize,
+    digest: bool,
+    /// Last delivery of each alert, keyed by `alert_key`
+    sent: HashMap<u64, Sent>,
+    /// Recent delivery times per channel and recipient, oldest first
+    recent: HashMap<(String, String), VecDeque<DateTime<Utc>>>,
+}
+
+impl Throttle {
+    pub fn new(config: &ThrottleConfig) -> Self {
+        Self {
+            dedup_window: Duration::seconds(config.dedup_window_seconds as i64),
+            rate_window: Duration::seconds(config.rate_window_seconds as i64),
+            max_per_recipient: config.max_per_recipient as usize,
+            digest: config.digest,
+            sent: HashMap::new(),
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Decides what to do with a delivery about to be sent. A duplicate is
+    /// counted towards the digest, so call this once per attempt.
+    pub fn check(
+        &mut self,
+        delivery: &NotificationDelivery,
+        subject: &str,
+        content: &str,
+        now: DateTime<Utc>,
+    ) -> Decision {
+        let key = alert_key(delivery, subject, content);
+        if let Some(sent) = self.sent.get_mut(&key) {
+            if now - sent.at < self.dedup_window {
+                sent.suppressed += 1;
+                return Decision::Duplicate { sent_at: sent.at };
+            }
+        }
+
+        if self.max_per_recipient > 0 {
+            if let Some(times) = self.recent.get_mut(&recipient_key(delivery)) {
+                expire(times, now, self.rate_window);
+                if times.len() >= self.max_per_recipient {
+                    return Decision::Deferred {
+                        until: times[0] + self.rate_window,
+                    };
+                }
+            }
+        }
+
+        let suppressed = match self.sent.get(&key) {
+            Some(sent) if self.digest => sent.suppressed,
+            _ => 0,
+        };
+        Decision::Send { suppressed }
+    }
+
+    /// Notes that a delivery went out. Only successful sends count, so a
+    /// failed attempt doesn't suppress its own retry.
+    pub fn record(
+        &mut self,
+        delivery: &NotificationDelivery,
+        subject: &str,
+        content: &str,
+        now: DateTime<Utc>,
+    ) {
+        self.sent.insert(
+            alert_key(delivery, subject, content),
+            Sent {
+                at: now,
+                suppressed: 0,
+            },
+        );
+
+        if self.max_per_recipient > 0 {
+            self.recent
+                .entry(recipient_key(delivery))
+                .or_default()
+                .push_back(now);
+        }
+    }
+
+    /// Forgets deliveries that can no longer affect a decision. With the
+    /// digest enabled, suppressed counts are kept for one more window so the
+    /// next copy of the alert can report them.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let retention = if self.digest {
+            self.dedup_window * 2
+        } else {
+            self.dedup_window
+        };
+        self.sent.retain(|_, sent| now - sent.at < retention);
+
+        let rate_window = self.rate_window;
+        self.recent.retain(|_, times| {
+            expire(times, now, rate_window);
+            !times.is_empty()
+        });
+    }
+}
+
+/// Appends the digest line for `suppressed` duplicates to `content`
+pub fn with_digest(content: &str, suppressed: u32, html: bool) -> String {
+    if suppressed == 0 {
+        return content.to_string();
+    }
+
+    let note = format!(
+        "{} identical alert{} suppressed since this was last sent.",
+        suppressed,
+        if suppressed == 1 { " was" } else { "s were" }
+    );
+    if html {
+        format!("{}\n<p><em>{}</em></p>", content, note)
+    } else {
+        format!("{}\n\n({})", content, note)
+    }
+}
+
+fn expire(times: &mut VecDeque<DateTime<Utc>>, now: DateTime<Utc>, window: Duration) {
+    while times.front().is_some_and(|at| now - *at >= window) {
+        times.pop_front();
+    }
+}
+
+fn recipient_key(delivery: &NotificationDelivery) -> (String, String) {
+    (delivery.channel.to_string(), delivery.recipient.clone())
+}
+
+fn alert_key(delivery: &NotificationDelivery, subject: &str, content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    delivery.channel.to_string().hash(&mut hasher);
+    delivery.recipient.hash(&mut hasher);
+    subject.hash(&mut hasher);
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NotificationChannel, NotificationStatus};
+    use uuid::Uuid;
+
+    fn config() -> ThrottleConfig {
+        ThrottleConfig {
+            dedup_window_seconds: 300,
+            max_per_recipient: 3,
+            rate_window_seconds: 60,
+            digest: false,
+        }
+    }
+
+    fn delivery(recipient: &str) -> NotificationDelivery {
+        NotificationDelivery {
+            id: Uuid::new_v4(),
+            notification_id: Uuid::new_v4(),
+            channel: NotificationChannel::Slack,
+            recipient: recipient.to_string(),
+            status: NotificationStatus::Pending,
+            error_message: None,
+            retry_count: 0,
+            next_retry_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    /// Checks a delivery and records it if it may go out
+    fn dispatch(
+        throttle: &mut Throttle,
+        recipient: &str,
+        content: &str,
+        now: DateTime<Utc>,
+    ) -> Decision {
+        let delivery = delivery(recipient);
+        let decision = throttle.check(&delivery, "Alert", content, now);
+        if matches!(decision, Decision::Send { .. }) {
+            throttle.record(&delivery, "Alert", content, now);
+        }
+        decision
+    }
+
+    #[test]
+    fn test_duplicate_within_window_is_suppressed() {
+        let mut throttle = Throttle::new(&config());
+        let start = Utc::now();
+
+        assert_eq!(
+            dispatch(&mut throttle, "#soc", "open port 22", start),
+            Decision::Send { suppressed: 0 }
+        );
+        assert_eq!(
+            dispatch(
+                &mut throttle,
+                "#soc",
+                "open port 22",
+                start + Duration::seconds(299)
+            ),
+            Decision::Duplicate { sent_at: start }
+        );
+
+        // Different content or a different recipient is not a duplicate
+        assert!(matches!(
+            dispatch(&mut throttle, "#soc", "open port 23", start),
+            Decision::Send { .. }
+        ));
+        assert!(matches!(
+            dispatch(&mut throttle, "#intel", "open port 22", start),
+            Decision::Send { .. }
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_after_window_is_delivered() {
+        let mut throttle = Throttle::new(&config());
+        let start = Utc::now();
+
+        dispatch(&mut throttle, "#soc", "open port 22", start);
+        let later = start + Duration::seconds(300);
+        throttle.prune(later);
+
+        assert_eq!(
+            dispatch(&mut throttle, "#soc", "open port 22", later),
+            Decision::Send { suppressed: 0 }
+        );
+    }
+
+    #[test]
+    fn test_failed_attempt_does_not_suppress_its_retry() {
+        let mut throttle = Throttle::new(&config());
+        let delivery = delivery("#soc");
+        let now = Utc::now();
+
+        assert!(matches!(
+            throttle.check(&delivery, "Alert", "body", now),
+            Decision::Send { .. }
+        ));
+        assert!(matches!(
+            throttle.check(&delivery, "Alert", "body", now + Duration::seconds(10)),
+            Decision::Send { .. }
+        ));
+    }
+
+    #[test]
+    fn test_recipient_over_rate_limit_is_deferred() {
+        let mut throttle = Throttle::new(&config());
+        let start = Utc::now();
+
+        for i in 0..3 {
+            let at = start + Duration::seconds(i);
+            assert!(matches!(
+                dispatch(&mut throttle, "#soc", &format!("alert {}", i), at),
+                Decision::Send { .. }
+            ));
+        }
+
+        assert_eq!(
+            dispatch(
+                &mut throttle,
+                "#soc",
+                "alert 3",
+                start + Duration::seconds(10)
+            ),
+            Decision::Deferred {
+                until: start + Duration::seconds(60)
+            }
+        );
+        assert!(matches!(
+            dispatch(
+                &mut throttle,
+                "#intel",
+                "alert 3",
+                start + Duration::seconds(10)
+            ),
+            Decision::Send { .. }
+        ));
+        assert!(matches!(
+            dispatch(
+                &mut throttle,
+                "#soc",
+                "alert 3",
+                start + Duration::seconds(60)
+            ),
+            Decision::Send { .. }
+        ));
+    }
+
+    #[test]
+    fn test_digest_counts_suppressed_duplicates() {
+        let mut throttle = Throttle::new(&ThrottleConfig {
+            digest: true,
+            ..config()
+        });
+        let start = Utc::now();
+
+        dispatch(&mut throttle, "#soc", "open port 22", start);
+        for i in 1..=4 {
+            dispatch(
+                &mut throttle,
+                "#soc",
+                "open port 22",
+                start + Duration::seconds(i),
+            );
+        }
+
+        let later = start + Duration::seconds(301);
+        throttle.prune(later);
+        assert_eq!(
+            dispatch(&mut throttle, "#soc", "open port 22", later),
+            Decision::Send { suppressed: 4 }
+        );
+        assert_eq!(
+            with_digest("open port 22", 4, false),
+            "open port 22\n\n(4 identical alerts were suppressed since this was last sent.)"
+        );
+        assert_eq!(with_digest("open port 22", 0, true), "open port 22");
+    }
+}
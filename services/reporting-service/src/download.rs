@@ -0,0 +1,374 @@
+//! Resumable report downloads
+//!
+//! `NamedFile` already answers single-range requests with `206 Partial Content`,
+//! advertises `Accept-Ranges: bytes`, and rejects unsatisfiable ranges with
+//! `416`. It only honours the first range of a multi-range request though, so
+//! those are answered here with a `multipart/byteranges` body.
+//!
+//! Overlapping and adjacent ranges are merged before serving, so no byte is
+//! sent twice. A request for more bytes than the file holds gets the whole
+//! file instead, and one with too many ranges is refused. Parts are streamed
+//! from the file a chunk at a time rather than read into memory.
+
+use actix_files::{HttpRange, NamedFile};
+use actix_web::body::SizedStream;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use futures::Stream;
+use std::collections::VecDeque;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use uuid::Uuid;
+
+/// Upper bound on ranges accepted in one request
+const MAX_RANGES: usize = 32;
+
+/// Bytes read from the file for each chunk of the body
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub async fn serve_file(req: &HttpRequest, path: &Path) -> io::Result<HttpResponse> {
+    let named_file = NamedFile::open_async(path).await?;
+    let length = named_file.metadata().len();
+
+    let ranges = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| HttpRange::parse(value, length).ok())
+        .unwrap_or_default();
+
+    if ranges.len() <= 1 {
+        return Ok(named_file.into_response(req));
+    }
+
+    if ranges.len() > MAX_RANGES {
+        return Ok(HttpResponse::RangeNotSatisfiable()
+            .insert_header((header::CONTENT_RANGE, format!("bytes */{}", length)))
+            .finish());
+    }
+
+    let content_type = named_file.content_type().to_string();
+    let file = File::from_std(named_file.file().try_clone()?);
+
+    // Only ranges that overlap can add up to more than the file
+    let requested: u64 = ranges.iter().map(|range| range.length).sum();
+    if requested > length {
+        let body = VecDeque::from([Segment::File { start: 0, length }]);
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .body(SizedStream::new(length, body_stream(file, body))));
+    }
+
+    let ranges = merge_ranges(ranges);
+    if let [range] = ranges[..] {
+        let body = VecDeque::from([Segment::File {
+            start: range.start,
+            length: range.length,
+        }]);
+        return Ok(HttpResponse::PartialContent()
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .insert_header((header::CONTENT_RANGE, content_range(&range, length)))
+            .body(SizedStream::new(range.length, body_stream(file, body))));
+    }
+
+    let boundary = Uuid::new_v4().simple().to_string();
+    let body = multipart_segments(&ranges, length, &content_type, &boundary);
+    let size = body.iter().map(Segment::len).sum();
+
+    Ok(HttpResponse::PartialContent()
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((
+            header::CONTENT_TYPE,
+            format!("multipart/byteranges; boundary={}", boundary),
+        ))
+        .body(SizedStream::new(size, body_stream(file, body))))
+}
+
+/// Sorts `ranges` and merges those that overlap or touch
+fn merge_ranges(mut ranges: Vec<HttpRange>) -> Vec<HttpRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<HttpRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.start + last.length => {
+                let end = (range.start + range.length).max(last.start + last.length);
+                last.length = end - last.start;
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+fn content_range(range: &HttpRange, length: u64) -> String {
+    format!(
+        "bytes {}-{}/{}",
+        range.start,
+        range.start + range.length - 1,
+        length
+    )
+}
+
+/// A piece of a response body
+enum Segment {
+    Text(String),
+    File { start: u64, length: u64 },
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Text(text) => text.len() as u64,
+            Segment::File { length, .. } => *length,
+        }
+    }
+}
+
+fn multipart_segments(
+    ranges: &[HttpRange],
+    length: u64,
+    content_type: &str,
+    boundary: &str,
+) -> VecDeque<Segment> {
+    let mut segments = VecDeque::new();
+    let mut separator = String::new();
+
+    for range in ranges {
+        segments.push_back(Segment::Text(format!(
+            "{}--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            separator,
+            boundary,
+            content_type,
+            content_range(range, length)
+        )));
+        segments.push_back(Segment::File {
+            start: range.start,
+            length: range.length,
+        });
+        separator = "\r\n".to_string();
+    }
+
+    segments.push_back(Segment::Text(format!("\r\n--{}--\r\n", boundary)));
+    segments
+}
+
+// Yields `segments` in order, reading file contents at most `CHUNK_SIZE` at a
+// time as the client takes them
+fn body_stream(
+    file: File,
+    segments: VecDeque<Segment>,
+) -> impl Stream<Item = io::Result<Bytes>> + 'static {
+    futures::stream::try_unfold((file, segments), |(mut file, mut segments)| async move {
+        let chunk = match segments.pop_front() {
+            None => return Ok(None),
+            Some(Segment::Text(text)) => Bytes::from(text),
+            Some(Segment::File { start, length }) => {
+                let read = length.min(CHUNK_SIZE);
+                let mut chunk = vec![0; read as usize];
+                file.seek(SeekFrom::Start(start)).await?;
+                file.read_exact(&mut chunk).await?;
+                if read < length {
+                    segments.push_front(Segment::File {
+                        start: start + read,
+                        length: length - read,
+                    });
+                }
+                Bytes::from(chunk)
+            }
+        };
+
+        Ok(Some((chunk, (file, segments))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use std::path::PathBuf;
+
+    const CONTENT: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    fn report_file() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mirage-report-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, CONTENT).unwrap();
+        path
+    }
+
+    #[actix_web::test]
+    async fn test_full_download() {
+        let path = report_file();
+        let req = TestRequest::default().to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], CONTENT);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_single_range_returns_slice() {
+        let path = report_file();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=10-15"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 10-15/36"
+        );
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"abcdef");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_multi_range_returns_multipart() {
+        let path = report_file();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=0-2, -3"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Content-Range: bytes 0-2/36\r\n\r\n012\r\n"));
+        assert!(body.contains("Content-Range: bytes 33-35/36\r\n\r\nxyz\r\n"));
+        assert!(body.ends_with("--\r\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_overlapping_ranges_are_merged() {
+        let path = report_file();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=20-22, 0-5, 3-9, 10-11"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(body.matches("Content-Range").count(), 2);
+        assert!(body.contains("Content-Range: bytes 0-11/36\r\n\r\n0123456789ab\r\n"));
+        assert!(body.contains("Content-Range: bytes 20-22/36\r\n\r\nklm\r\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_ranges_merged_into_one_return_a_single_part() {
+        let path = report_file();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=10-15, 12-19"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 10-19/36"
+        );
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"abcdefghij");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_ranges_larger_than_the_file_return_it_whole() {
+        let path = report_file();
+        let ranges = ["0-35"; 4].join(",");
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, format!("bytes={}", ranges)))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], CONTENT);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_too_many_ranges() {
+        let path = report_file();
+        let ranges: Vec<String> = (0..=MAX_RANGES).map(|i| format!("{0}-{0}", i)).collect();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, format!("bytes={}", ranges.join(","))))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_large_parts_are_streamed_in_chunks() {
+        let path = std::env::temp_dir().join(format!("mirage-report-{}.bin", Uuid::new_v4()));
+        let content: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=1-100000, 150000-"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        // Each part follows the blank line ending its headers
+        let parts: Vec<usize> = body
+            .windows(4)
+            .enumerate()
+            .filter(|(_, window)| *window == b"\r\n\r\n")
+            .map(|(i, _)| i + 4)
+            .collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(&body[parts[0]..parts[0] + 100_000], &content[1..100_001]);
+        let tail = &content[150_000..];
+        assert_eq!(&body[parts[1]..parts[1] + tail.len()], tail);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_unsatisfiable_range() {
+        let path = report_file();
+        let req = TestRequest::default()
+            .insert_header((header::RANGE, "bytes=100-200"))
+            .to_http_request();
+
+        let resp = serve_file(&req, &path).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes */36"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,7 +1,103 @@
 //! CSV formatter for reports
+//!
+//! One row per entity. Fields are quoted per RFC 4180 whenever they contain a
+//! comma, quote or line break, so values copied from scan output can't shift
+//! columns. Nested data and metadata are written as JSON in a single field.
 
+use super::{
+    collect_layout, stream_layout, EntityStream, Layout, ReportFormatter, ReportStream,
+    StreamingFormatter,
+};
+use crate::models::{EntityData, ReportTemplateContext};
+use mirage_common::{Error, Result as CommonResult};
 use serde_json::Value;
 
+const HEADERS: [&str; 7] = [
+    "id",
+    "entity_type",
+    "value",
+    "created_at",
+    "metadata",
+    "data",
+    "relationships",
+];
+
+#[derive(Default)]
+pub struct CsvFormatter;
+
+impl CsvFormatter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ReportFormatter for CsvFormatter {
+    fn format(
+        &self,
+        context: &ReportTemplateContext,
+        _template_name: &str,
+    ) -> CommonResult<(Vec<u8>, String)> {
+        Ok((collect_layout(&LAYOUT, context)?, "csv".to_string()))
+    }
+}
+
+impl StreamingFormatter for CsvFormatter {
+    fn format_stream(
+        &self,
+        context: &ReportTemplateContext,
+        entities: EntityStream,
+        _template_name: &str,
+    ) -> ReportStream {
+        stream_layout(&LAYOUT, context, entities)
+    }
+}
+
+/// The header row, then one row per entity
+static LAYOUT: Layout = Layout {
+    head: |_| record(HEADERS.map(String::from)),
+    entity: |entity, _| record(row(entity)),
+    tail: b"",
+};
+
+fn row(entity: &EntityData) -> [String; 7] {
+    let metadata: std::collections::BTreeMap<_, _> = entity.metadata.iter().collect();
+    let relationships: Vec<String> = entity
+        .relationships
+        .iter()
+        .map(|r| format!("{}:{}", r.relationship_type, r.target_id))
+        .collect();
+
+    [
+        entity.id.to_string(),
+        entity.entity_type.clone(),
+        entity.value.clone(),
+        entity.created_at.to_rfc3339(),
+        serde_json::to_string(&metadata).unwrap_or_default(),
+        json_field(&entity.data),
+        relationships.join(";"),
+    ]
+}
+
+fn record(fields: [String; 7]) -> CommonResult<Vec<u8>> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer
+        .write_record(fields)
+        .map_err(|e| Error::Internal(format!("CSV writing error: {}", e)))?;
+    writer
+        .into_inner()
+        .map_err(|e| Error::Internal(format!("CSV writing error: {}", e)))
+}
+
+fn json_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
 pub fn format_to_csv(data: &Value, title: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut csv = format!("Report: {}\n", title);
     csv.push_str(&format!(
@@ -38,7 +134,11 @@ pub fn format_to_csv(data: &Value, title: &str) -> Result<String, Box<dyn std::e
         Value::Object(obj) => {
             csv.push_str("Key,Value\n");
             for (key, value) in obj {
-                csv.push_str(&format!("{},{}\n", key, format_csv_value(value)));
+                csv.push_str(&format!(
+                    "{},{}\n",
+                    escape_field(key),
+                    format_csv_value(value)
+                ));
             }
         }
         _ => {
@@ -52,10 +152,77 @@ pub fn format_to_csv(data: &Value, title: &str) -> Result<String, Box<dyn std::e
 
 fn format_csv_value(value: &Value) -> String {
     match value {
-        Value::String(s) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Value::String(s) => escape_field(s),
         Value::Number(n) => n.to_string(),
         Value::Bool(b) => b.to_string(),
         Value::Null => "".to_string(),
-        _ => format!("\"{}\"", value.to_string().replace('"', "\"\"")),
+        _ => escape_field(&value.to_string()),
+    }
+}
+
+/// Quotes a field if it contains a delimiter, quote or line break (RFC 4180)
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    const TRICKY: &str = "a,b\"c\n";
+
+    fn context() -> ReportTemplateContext {
+        ReportTemplateContext {
+            title: "Findings".to_string(),
+            description: None,
+            entities: vec![EntityData {
+                id: Uuid::new_v4(),
+                entity_type: "note".to_string(),
+                value: TRICKY.to_string(),
+                data: serde_json::json!({ "banner": "x\r\ny" }),
+                metadata: HashMap::from([("source".to_string(), "a,b".to_string())]),
+                created_at: Utc::now(),
+                relationships: Vec::new(),
+            }],
+            generated_at: Utc::now(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_output_round_trips_special_characters() {
+        let (bytes, extension) = CsvFormatter::new().format(&context(), "summary").unwrap();
+        assert_eq!(extension, "csv");
+
+        let mut reader = ::csv::Reader::from_reader(bytes.as_slice());
+        assert_eq!(reader.headers().unwrap().len(), HEADERS.len());
+
+        let rows: Vec<::csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), HEADERS.len());
+        assert_eq!(&rows[0][2], TRICKY);
+        assert_eq!(&rows[0][4], r#"{"source":"a,b"}"#);
+        assert_eq!(
+            serde_json::from_str::<Value>(&rows[0][5]).unwrap(),
+            serde_json::json!({ "banner": "x\r\ny" })
+        );
+    }
+
+    #[test]
+    fn test_escape_field_follows_rfc_4180() {
+        assert_eq!(escape_field("plain"), "plain");
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(escape_field(TRICKY), "\"a,b\"\"c\n\"");
     }
 }
@@ -1,7 +1,81 @@
 //! JSON formatter for reports
+//!
+//! The report is a single compact JSON object. `entities` comes last so the
+//! rest of the report can be written up front and the entities streamed after
+//! it one at a time.
 
+use super::{
+    collect_layout, stream_layout, EntityStream, Layout, ReportFormatter, ReportStream,
+    StreamingFormatter,
+};
+use crate::models::ReportTemplateContext;
+use mirage_common::{Error, Result as CommonResult};
 use serde_json::Value;
 
+#[derive(Default)]
+pub struct JsonFormatter;
+
+impl JsonFormatter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ReportFormatter for JsonFormatter {
+    fn format(
+        &self,
+        context: &ReportTemplateContext,
+        _template_name: &str,
+    ) -> CommonResult<(Vec<u8>, String)> {
+        Ok((collect_layout(&LAYOUT, context)?, "json".to_string()))
+    }
+}
+
+impl StreamingFormatter for JsonFormatter {
+    fn format_stream(
+        &self,
+        context: &ReportTemplateContext,
+        entities: EntityStream,
+        _template_name: &str,
+    ) -> ReportStream {
+        stream_layout(&LAYOUT, context, entities)
+    }
+}
+
+/// Everything but the entities and the opening of the entity array, then one
+/// entity per piece, then the closing brackets
+static LAYOUT: Layout = Layout {
+    head,
+    entity: |entity, index| {
+        let mut bytes = if index > 0 { b",".to_vec() } else { Vec::new() };
+        serde_json::to_writer(&mut bytes, entity)
+            .map(|_| bytes)
+            .map_err(json_error)
+    },
+    tail: b"]}",
+};
+
+fn head(context: &ReportTemplateContext) -> CommonResult<Vec<u8>> {
+    let report = serde_json::json!({
+        "title": context.title,
+        "description": context.description,
+        "generated_at": context.generated_at,
+        "generated_by": context.generated_by,
+        "visualizations": context.visualizations,
+        "custom_data": context.custom_data,
+    });
+
+    let mut bytes = serde_json::to_vec(&report).map_err(json_error)?;
+    // Reopen the object to append the entity array
+    bytes.pop();
+    bytes.extend_from_slice(br#","entities":["#);
+    Ok(bytes)
+}
+
+fn json_error(e: serde_json::Error) -> Error {
+    Error::Internal(format!("JSON writing error: {}", e))
+}
+
 pub fn format_to_json(data: &Value, title: &str) -> Result<String, Box<dyn std::error::Error>> {
     let report = serde_json::json!({
         "title": title,
@@ -24,3 +98,49 @@ pub fn format_to_json_compact(
 
     Ok(serde_json::to_string(&report)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EntityData;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn context(entity_count: usize) -> ReportTemplateContext {
+        ReportTemplateContext {
+            title: "Findings".to_string(),
+            description: Some("Quarterly \"external\" review".to_string()),
+            entities: (0..entity_count)
+                .map(|i| EntityData {
+                    id: Uuid::new_v4(),
+                    entity_type: "domain".to_string(),
+                    value: format!("host{}.example.com", i),
+                    data: serde_json::json!({ "ip": "192.0.2.1" }),
+                    metadata: HashMap::new(),
+                    created_at: Utc::now(),
+                    relationships: Vec::new(),
+                })
+                .collect(),
+            generated_at: Utc::now(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        }
+    }
+
+    #[test]
+    fn test_output_is_valid_json() {
+        for entity_count in [0, 1, 3] {
+            let (bytes, extension) = JsonFormatter::new()
+                .format(&context(entity_count), "summary")
+                .unwrap();
+            assert_eq!(extension, "json");
+
+            let report: Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(report["title"], "Findings");
+            assert_eq!(report["description"], "Quarterly \"external\" review");
+            assert_eq!(report["entities"].as_array().unwrap().len(), entity_count);
+        }
+    }
+}
@@ -1,7 +1,94 @@
 //! Markdown formatter for reports
+//!
+//! Entities are rendered as a table. Cell text is escaped so that pipes and
+//! line breaks inside a value stay within their cell.
 
+use super::ReportFormatter;
+use crate::models::ReportTemplateContext;
+use mirage_common::Result as CommonResult;
 use serde_json::Value;
 
+#[derive(Default)]
+pub struct MarkdownFormatter;
+
+impl MarkdownFormatter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ReportFormatter for MarkdownFormatter {
+    fn format(
+        &self,
+        context: &ReportTemplateContext,
+        _template_name: &str,
+    ) -> CommonResult<(Vec<u8>, String)> {
+        let mut markdown = format!("# {}\n\n", escape_inline(&context.title));
+        markdown.push_str(&format!(
+            "**Generated:** {}\n\n",
+            context.generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+        if let Some(generated_by) = &context.generated_by {
+            markdown.push_str(&format!(
+                "**Generated by:** {}\n\n",
+                escape_inline(generated_by)
+            ));
+        }
+        if let Some(description) = &context.description {
+            markdown.push_str(description);
+            markdown.push_str("\n\n");
+        }
+
+        markdown.push_str(&format!("## Entities ({})\n\n", context.entities.len()));
+        if context.entities.is_empty() {
+            markdown.push_str("No entities\n");
+        } else {
+            let rows: Vec<Vec<String>> = context
+                .entities
+                .iter()
+                .map(|entity| {
+                    vec![
+                        entity.entity_type.clone(),
+                        entity.value.clone(),
+                        entity.id.to_string(),
+                        entity
+                            .created_at
+                            .format("%Y-%m-%d %H:%M:%S UTC")
+                            .to_string(),
+                    ]
+                })
+                .collect();
+            markdown.push_str(&create_markdown_table(
+                &["Type", "Value", "ID", "Created"],
+                &rows,
+            ));
+        }
+
+        if !context.visualizations.is_empty() {
+            markdown.push_str("\n## Visualizations\n\n");
+            for visualization in &context.visualizations {
+                let title = visualization
+                    .title
+                    .as_deref()
+                    .unwrap_or(&visualization.visualization_type);
+                markdown.push_str(&format!(
+                    "![{}]({})\n",
+                    escape_inline(title),
+                    visualization.data_url
+                ));
+            }
+        }
+
+        if let Some(custom_data) = &context.custom_data {
+            markdown.push_str("\n## Additional Data\n\n```json\n");
+            markdown.push_str(&serde_json::to_string_pretty(custom_data).unwrap_or_default());
+            markdown.push_str("\n```\n");
+        }
+
+        Ok((markdown.into_bytes(), "md".to_string()))
+    }
+}
+
 pub fn format_to_markdown(data: &Value, title: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut markdown = format!("# {}\n\n", title);
     markdown.push_str(&format!(
@@ -23,7 +110,7 @@ pub fn create_markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
     // Headers
     table.push_str("| ");
     for header in headers {
-        table.push_str(header);
+        table.push_str(&escape_cell(header));
         table.push_str(" | ");
     }
     table.push('\n');
@@ -39,7 +126,7 @@ pub fn create_markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
     for row in rows {
         table.push_str("| ");
         for cell in row {
-            table.push_str(cell);
+            table.push_str(&escape_cell(cell));
             table.push_str(" | ");
         }
         table.push('\n');
@@ -47,3 +134,97 @@ pub fn create_markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
 
     table
 }
+
+/// Escapes a table cell: pipes would start a new column and line breaks would
+/// end the row
+fn escape_cell(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+/// Keeps single-line values (titles, names) on one line
+fn escape_inline(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EntityData;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    /// Cells in a rendered table row, splitting only on unescaped pipes
+    fn cells(row: &str) -> Vec<String> {
+        let mut cells = vec![String::new()];
+        let mut chars = row.trim().chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    let cell = cells.last_mut().unwrap();
+                    cell.push(c);
+                    cell.extend(chars.next());
+                }
+                '|' => cells.push(String::new()),
+                _ => cells.last_mut().unwrap().push(c),
+            }
+        }
+        // Drop the empty edges outside the leading and trailing pipes
+        cells[1..cells.len() - 1]
+            .iter()
+            .map(|cell| cell.trim().to_string())
+            .collect()
+    }
+
+    fn entity(value: &str) -> EntityData {
+        EntityData {
+            id: Uuid::new_v4(),
+            entity_type: "note".to_string(),
+            value: value.to_string(),
+            data: Value::Null,
+            metadata: HashMap::new(),
+            created_at: Utc::now(),
+            relationships: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_table_cells_escape_pipes_and_newlines() {
+        let table = create_markdown_table(
+            &["Name", "Value"],
+            &[vec!["col|umn".to_string(), "a,b\"c\nd".to_string()]],
+        );
+        let rows: Vec<&str> = table.lines().collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(cells(rows[2]), vec!["col\\|umn", "a,b\"c<br>d"]);
+    }
+
+    #[test]
+    fn test_report_rows_keep_their_column_count() {
+        let context = ReportTemplateContext {
+            title: "Findings".to_string(),
+            description: None,
+            entities: vec![entity("col|umn"), entity("a,b\"c\n"), entity("trailing\\")],
+            generated_at: Utc::now(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        };
+
+        let (bytes, extension) = MarkdownFormatter::new()
+            .format(&context, "summary")
+            .unwrap();
+        assert_eq!(extension, "md");
+
+        let markdown = String::from_utf8(bytes).unwrap();
+        let rows: Vec<&str> = markdown.lines().filter(|l| l.starts_with('|')).collect();
+        assert_eq!(rows.len(), 2 + context.entities.len());
+        assert!(rows.iter().all(|row| cells(row).len() == 4));
+        assert_eq!(cells(rows[2])[1], "col\\|umn");
+        assert_eq!(cells(rows[4])[1], "trailing\\\\");
+    }
+}
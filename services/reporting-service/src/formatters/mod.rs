@@ -1,6 +1,10 @@
-use crate::models::ReportTemplateContext;
+use crate::models::{EntityData, ReportTemplateContext};
+use crate::templates::user_template_key;
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
 use handlebars::Handlebars;
 use mirage_common::Result;
+use std::pin::Pin;
 use std::sync::Arc;
 
 // Re-export formatters
@@ -11,9 +15,8 @@ mod json;
 mod markdown;
 mod pdf;
 
-pub use csv::CsvFormatter;
+pub use self::csv::CsvFormatter;
 pub use excel::ExcelFormatter;
-pub use html::HtmlFormatter;
 pub use json::JsonFormatter;
 pub use markdown::MarkdownFormatter;
 pub use pdf::PdfFormatter;
@@ -27,6 +30,62 @@ pub trait ReportFormatter {
     ) -> Result<(Vec<u8>, String)>;
 }
 
+/// A report body produced piece by piece
+pub type ReportStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
+
+/// The entities of a streamed report, fetched a page at a time
+pub type EntityStream = Pin<Box<dyn Stream<Item = Result<EntityData>> + Send>>;
+
+// Formatters that can write a report one entity at a time, so memory use
+// doesn't grow with the size of the report
+pub trait StreamingFormatter: ReportFormatter {
+    /// Writes `entities` as they arrive, in place of `context.entities`,
+    /// which is ignored
+    fn format_stream(
+        &self,
+        context: &ReportTemplateContext,
+        entities: EntityStream,
+        template_name: &str,
+    ) -> ReportStream;
+}
+
+/// How a report is laid out: a head, one piece per entity, then a tail.
+/// Formatters describe this once and get both the buffered and the streamed
+/// output from it, so the two are always byte-for-byte identical.
+struct Layout {
+    head: fn(&ReportTemplateContext) -> Result<Vec<u8>>,
+    /// Also given the entity's position in the report
+    entity: fn(&EntityData, usize) -> Result<Vec<u8>>,
+    tail: &'static [u8],
+}
+
+fn stream_layout(
+    layout: &'static Layout,
+    context: &ReportTemplateContext,
+    entities: EntityStream,
+) -> ReportStream {
+    let head = futures::stream::once(futures::future::ready((layout.head)(context)));
+    let body = entities
+        .enumerate()
+        .map(|(index, entity)| entity.and_then(|entity| (layout.entity)(&entity, index)));
+    let tail = futures::stream::once(futures::future::ready(Ok(layout.tail.to_vec())));
+
+    Box::pin(
+        head.chain(body)
+            .chain(tail)
+            .map(|piece| piece.map(Bytes::from)),
+    )
+}
+
+fn collect_layout(layout: &Layout, context: &ReportTemplateContext) -> Result<Vec<u8>> {
+    let mut bytes = (layout.head)(context)?;
+    for (index, entity) in context.entities.iter().enumerate() {
+        bytes.extend((layout.entity)(entity, index)?);
+    }
+    bytes.extend_from_slice(layout.tail);
+    Ok(bytes)
+}
+
 // HTML formatter implementation
 pub struct HtmlFormatterImpl {
     handlebars: Arc<Handlebars<'static>>,
@@ -44,17 +103,16 @@ impl ReportFormatter for HtmlFormatterImpl {
         context: &ReportTemplateContext,
         template_name: &str,
     ) -> Result<(Vec<u8>, String)> {
-        // Use template_name + _html suffix or fallback to a default
-        let template_key = if self
-            .handlebars
-            .has_template(&format!("{}_html", template_name))
-        {
-            format!("{}_html", template_name)
-        } else if self.handlebars.has_template(template_name) {
-            template_name.to_string()
-        } else {
-            "summary_html".to_string() // Default template
-        };
+        // User templates first, then template_name + _html suffix, then a default
+        let candidates = [
+            user_template_key(template_name, "html"),
+            format!("{}_html", template_name),
+            template_name.to_string(),
+        ];
+        let template_key = candidates
+            .into_iter()
+            .find(|key| self.handlebars.has_template(key))
+            .unwrap_or_else(|| "summary_html".to_string());
 
         let rendered = self
             .handlebars
@@ -66,3 +124,99 @@ impl ReportFormatter for HtmlFormatterImpl {
         Ok((rendered.into_bytes(), "html".to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    /// Built from `i` alone, so it can be made again without keeping it
+    fn entity(i: usize) -> EntityData {
+        EntityData {
+            id: Uuid::from_u128(i as u128),
+            entity_type: "ip".to_string(),
+            value: format!("10.{}.{}.{}", i >> 16 & 0xff, i >> 8 & 0xff, i & 0xff),
+            data: serde_json::json!({ "ports": [22, 443], "banner": "a,b\"c\n" }),
+            metadata: HashMap::from([("source".to_string(), "masscan".to_string())]),
+            created_at: DateTime::from_timestamp(1_700_000_000 + i as i64, 0).unwrap(),
+            relationships: Vec::new(),
+        }
+    }
+
+    fn context(entities: Vec<EntityData>) -> ReportTemplateContext {
+        ReportTemplateContext {
+            title: "Full Scan".to_string(),
+            description: None,
+            entities,
+            generated_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        }
+    }
+
+    /// Drains the stream, checking that no single piece holds the whole report
+    fn drain(stream: ReportStream, entity_count: usize) -> Vec<u8> {
+        block_on(async {
+            let mut pieces = 0;
+            let mut bytes = Vec::new();
+            let mut stream = stream;
+            while let Some(piece) = stream.next().await {
+                let piece = piece.unwrap();
+                assert!(piece.len() < 1024);
+                bytes.extend_from_slice(&piece);
+                pieces += 1;
+            }
+            assert!(pieces > entity_count);
+            bytes
+        })
+    }
+
+    #[test]
+    fn test_streamed_output_matches_buffered_output() {
+        let entity_count = 20_000;
+        let buffered_context = context((0..entity_count).map(entity).collect());
+        let streamed_context = context(Vec::new());
+        let formatters: Vec<Box<dyn StreamingFormatter>> = vec![
+            Box::new(CsvFormatter::new()),
+            Box::new(JsonFormatter::new()),
+        ];
+
+        for formatter in formatters {
+            let (buffered, _) = formatter.format(&buffered_context, "summary").unwrap();
+            // Entities are made as the writer asks for them, as pages are
+            // fetched when a report is streamed
+            let entities = futures::stream::iter((0..entity_count).map(|i| Ok(entity(i))));
+            let streamed = drain(
+                formatter.format_stream(&streamed_context, Box::pin(entities), "summary"),
+                entity_count,
+            );
+
+            assert_eq!(streamed.len(), buffered.len());
+            assert_eq!(streamed, buffered);
+        }
+    }
+
+    #[test]
+    fn test_entity_error_ends_the_stream_with_it() {
+        let entities = futures::stream::iter(vec![
+            Ok(entity(0)),
+            Err(mirage_common::Error::ExternalApi("storage is down".into())),
+        ]);
+        let pieces: Vec<_> = block_on(
+            JsonFormatter::new()
+                .format_stream(&context(Vec::new()), Box::pin(entities), "summary")
+                .collect(),
+        );
+
+        assert!(pieces[0].is_ok());
+        assert!(pieces[1].is_ok());
+        assert!(matches!(
+            pieces[2],
+            Err(mirage_common::Error::ExternalApi(_))
+        ));
+    }
+}
@@ -1,28 +1,400 @@
 //! PDF formatter for reports
+//!
+//! Reports are laid out as plain text on A4 pages using the built-in
+//! Helvetica fonts, so nothing has to be installed on the host. Every page
+//! repeats the report title and generation time and is numbered in the footer.
+//! A registered `<template>_pdf` Handlebars template, or a user template
+//! uploaded for pdf, replaces the default body layout; its output is treated
+//! as plain text.
 
-use serde_json::Value;
+use super::ReportFormatter;
+use crate::models::{EntityData, ReportTemplateContext};
+use crate::templates::user_template_key;
+use handlebars::Handlebars;
+use mirage_common::{Error, Result};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use std::sync::Arc;
 
-pub fn format_to_pdf(data: &Value, title: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Placeholder implementation - in real implementation would use a PDF library
-    let content = format!(
-        "PDF Report: {}\n\nGenerated: {}\n\nData:\n{}\n",
-        title,
-        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
-        serde_json::to_string_pretty(data)?
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+/// Space taken by the title and timestamp at the top of each page
+const HEADER_HEIGHT: f32 = 18.0;
+const FOOTER_HEIGHT: f32 = 10.0;
+const LINE_HEIGHT: f32 = 5.0;
+const BODY_FONT_SIZE: f32 = 10.0;
+/// Roughly what fits between the margins at the body font size
+const WRAP_WIDTH: usize = 95;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    text: String,
+    heading: bool,
+}
+
+impl Line {
+    fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            heading: false,
+        }
+    }
+
+    fn heading(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            heading: true,
+        }
+    }
+
+    fn blank() -> Self {
+        Self::text("")
+    }
+}
+
+pub struct PdfFormatter {
+    handlebars: Arc<Handlebars<'static>>,
+}
+
+impl PdfFormatter {
+    pub fn new(handlebars: Arc<Handlebars<'static>>) -> Self {
+        Self { handlebars }
+    }
+
+    fn body(&self, context: &ReportTemplateContext, template_name: &str) -> Result<Vec<Line>> {
+        let candidates = [
+            user_template_key(template_name, "pdf"),
+            format!("{}_pdf", template_name),
+        ];
+        let Some(template_key) = candidates
+            .into_iter()
+            .find(|key| self.handlebars.has_template(key))
+        else {
+            return Ok(report_lines(context));
+        };
+
+        let rendered = self
+            .handlebars
+            .render(&template_key, context)
+            .map_err(|e| Error::Internal(format!("Template rendering error: {}", e)))?;
+
+        Ok(rendered.lines().map(Line::text).collect())
+    }
+}
+
+impl ReportFormatter for PdfFormatter {
+    fn format(
+        &self,
+        context: &ReportTemplateContext,
+        template_name: &str,
+    ) -> Result<(Vec<u8>, String)> {
+        let body = self.body(context, template_name)?;
+        let bytes = render(context, &body)
+            .map_err(|e| Error::Internal(format!("PDF rendering error: {}", e)))?;
+
+        Ok((bytes, "pdf".to_string()))
+    }
+}
+
+/// Default body layout: description, entities, visualizations, custom data
+fn report_lines(context: &ReportTemplateContext) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    if let Some(description) = &context.description {
+        lines.push(Line::text(description.as_str()));
+        lines.push(Line::blank());
+    }
+    if let Some(generated_by) = &context.generated_by {
+        lines.push(Line::text(format!("Generated by: {}", generated_by)));
+        lines.push(Line::blank());
+    }
+
+    lines.push(Line::heading(format!(
+        "Entities ({})",
+        context.entities.len()
+    )));
+    if context.entities.is_empty() {
+        lines.push(Line::text("No entities"));
+    }
+    for entity in &context.entities {
+        entity_lines(entity, &mut lines);
+    }
+
+    if !context.visualizations.is_empty() {
+        lines.push(Line::blank());
+        lines.push(Line::heading(format!(
+            "Visualizations ({})",
+            context.visualizations.len()
+        )));
+        for visualization in &context.visualizations {
+            let title = visualization
+                .title
+                .as_deref()
+                .unwrap_or(&visualization.visualization_type);
+            lines.push(Line::text(format!(
+                "{} [{}]",
+                title, visualization.visualization_type
+            )));
+            if let Some(description) = &visualization.description {
+                lines.push(Line::text(format!("  {}", description)));
+            }
+        }
+    }
+
+    if let Some(custom_data) = &context.custom_data {
+        lines.push(Line::blank());
+        lines.push(Line::heading("Additional Data"));
+        json_lines(custom_data, "", &mut lines);
+    }
+
+    lines
+}
+
+fn entity_lines(entity: &EntityData, lines: &mut Vec<Line>) {
+    lines.push(Line::blank());
+    lines.push(Line::heading(format!(
+        "[{}] {}",
+        entity.entity_type, entity.value
+    )));
+    lines.push(Line::text(format!(
+        "  ID: {}  Created: {}",
+        entity.id,
+        entity.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+    )));
+
+    let mut metadata: Vec<_> = entity.metadata.iter().collect();
+    metadata.sort();
+    for (key, value) in metadata {
+        lines.push(Line::text(format!("  {}: {}", key, value)));
+    }
+
+    if !entity.data.is_null() {
+        json_lines(&entity.data, "  ", lines);
+    }
+
+    for relationship in &entity.relationships {
+        lines.push(Line::text(format!(
+            "  -> {} {}",
+            relationship.relationship_type, relationship.target_id
+        )));
+    }
+}
+
+fn json_lines(value: &serde_json::Value, indent: &str, lines: &mut Vec<Line>) {
+    let pretty = serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string());
+    lines.extend(
+        pretty
+            .lines()
+            .map(|line| Line::text(format!("{}{}", indent, line))),
     );
+}
+
+/// Breaks lines longer than `width` characters, preferring whitespace and
+/// keeping the original indentation on continuation lines
+fn wrap(lines: Vec<Line>, width: usize) -> Vec<Line> {
+    let mut wrapped = Vec::with_capacity(lines.len());
 
-    // Return as bytes (placeholder - real implementation would generate actual PDF)
-    Ok(content.into_bytes())
+    for line in lines {
+        let chars: Vec<char> = line.text.chars().collect();
+        if chars.len() <= width {
+            wrapped.push(line);
+            continue;
+        }
+
+        let indent: String = chars.iter().take_while(|c| **c == ' ').collect();
+        let mut rest = &chars[..];
+        let mut first = true;
+        while !rest.is_empty() {
+            let prefix = if first { "" } else { indent.as_str() };
+            let available = width.saturating_sub(prefix.len()).max(1);
+            if rest.len() <= available {
+                let text: String = rest.iter().collect();
+                wrapped.push(Line {
+                    text: format!("{}{}", prefix, text),
+                    heading: line.heading,
+                });
+                break;
+            }
+
+            let split = rest[..available]
+                .iter()
+                .rposition(|c| *c == ' ')
+                .filter(|&at| at > 0)
+                .unwrap_or(available);
+            let text: String = rest[..split].iter().collect();
+            wrapped.push(Line {
+                text: format!("{}{}", prefix, text.trim_end()),
+                heading: line.heading,
+            });
+
+            rest = &rest[split..];
+            while rest.first() == Some(&' ') {
+                rest = &rest[1..];
+            }
+            first = false;
+        }
+    }
+
+    wrapped
+}
+
+fn lines_per_page() -> usize {
+    let usable = PAGE_HEIGHT - 2.0 * MARGIN - HEADER_HEIGHT - FOOTER_HEIGHT;
+    (usable / LINE_HEIGHT) as usize
+}
+
+fn paginate(lines: Vec<Line>) -> Vec<Vec<Line>> {
+    let lines = wrap(lines, WRAP_WIDTH);
+    if lines.is_empty() {
+        return vec![Vec::new()];
+    }
+
+    lines
+        .chunks(lines_per_page())
+        .map(|page| page.to_vec())
+        .collect()
+}
+
+/// The built-in fonts only cover Latin-1; anything else would come out as
+/// garbage, so replace it
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' => ' ',
+            c if c.is_control() => '?',
+            c if (c as u32) < 0x100 => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+fn render(
+    context: &ReportTemplateContext,
+    body: &[Line],
+) -> std::result::Result<Vec<u8>, printpdf::Error> {
+    let pages = paginate(body.to_vec());
+    let total = pages.len();
+    let generated = format!(
+        "Generated: {}",
+        context.generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+
+    let (doc, first_page, first_layer) = PdfDocument::new(
+        printable(&context.title),
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Content",
+    );
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    for (number, page) in pages.iter().enumerate() {
+        let layer = if number == 0 {
+            doc.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page, layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+            doc.get_page(page).get_layer(layer)
+        };
+
+        draw_header(&layer, &context.title, &generated, &bold, &regular);
+
+        let mut y = PAGE_HEIGHT - MARGIN - HEADER_HEIGHT;
+        for line in page {
+            let font = if line.heading { &bold } else { &regular };
+            layer.use_text(
+                printable(&line.text),
+                BODY_FONT_SIZE,
+                Mm(MARGIN),
+                Mm(y),
+                font,
+            );
+            y -= LINE_HEIGHT;
+        }
+
+        layer.use_text(
+            format!("Page {} of {}", number + 1, total),
+            8.0,
+            Mm(MARGIN),
+            Mm(MARGIN),
+            &regular,
+        );
+    }
+
+    doc.save_to_bytes()
 }
 
-pub fn create_pdf_header(title: &str) -> String {
-    format!(
-        "Report: {}\nGenerated: {}\n",
-        title,
-        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-    )
+fn draw_header(
+    layer: &PdfLayerReference,
+    title: &str,
+    generated: &str,
+    bold: &IndirectFontRef,
+    regular: &IndirectFontRef,
+) {
+    let top = PAGE_HEIGHT - MARGIN;
+    layer.use_text(printable(title), 16.0, Mm(MARGIN), Mm(top), bold);
+    layer.use_text(generated, 9.0, Mm(MARGIN), Mm(top - 7.0), regular);
 }
 
-pub fn create_pdf_footer() -> String {
-    "End of Report".to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn context(entity_count: usize) -> ReportTemplateContext {
+        ReportTemplateContext {
+            title: "Example Corp Footprint".to_string(),
+            description: Some("Passive reconnaissance of example.com".to_string()),
+            entities: (0..entity_count)
+                .map(|i| EntityData {
+                    id: Uuid::new_v4(),
+                    entity_type: "domain".to_string(),
+                    value: format!("host{}.example.com", i),
+                    data: serde_json::json!({ "ip": "192.0.2.1" }),
+                    metadata: HashMap::from([("source".to_string(), "dns".to_string())]),
+                    created_at: Utc::now(),
+                    relationships: Vec::new(),
+                })
+                .collect(),
+            generated_at: Utc::now(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        }
+    }
+
+    #[test]
+    fn test_output_is_a_pdf() {
+        let formatter = PdfFormatter::new(Arc::new(Handlebars::new()));
+
+        let (bytes, extension) = formatter.format(&context(3), "summary").unwrap();
+
+        assert!(!bytes.is_empty());
+        assert!(bytes.starts_with(b"%PDF-"));
+        assert_eq!(extension, "pdf");
+    }
+
+    #[test]
+    fn test_long_reports_span_multiple_pages() {
+        let context = context(40);
+        let pages = paginate(report_lines(&context));
+
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.len() <= lines_per_page()));
+
+        let formatter = PdfFormatter::new(Arc::new(Handlebars::new()));
+        let (bytes, _) = formatter.format(&context, "summary").unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn test_wrap_keeps_indentation() {
+        let long = format!("  {}", "word ".repeat(30));
+        let wrapped = wrap(vec![Line::text(long)], 40);
+
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|line| line.text.chars().count() <= 40));
+        assert!(wrapped.iter().all(|line| line.text.starts_with("  word")));
+    }
 }
@@ -1,10 +1,13 @@
-use actix_files::NamedFile;
-use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use actix_web::http::header;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse, Responder};
 use mirage_common::Error as CommonError;
+use mirage_middleware::RoleAuthorization;
+use sanitize_filename::sanitize;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::ReportRequest;
+use crate::download;
+use crate::models::{ReportRequest, TemplateUpload};
 use crate::services::ReportService;
 
 pub fn report_routes() -> actix_web::Scope {
@@ -12,6 +15,8 @@ pub fn report_routes() -> actix_web::Scope {
         .service(generate_report)
         .service(get_report)
         .service(list_templates)
+        .service(upload_template)
+        .service(stream_report)
 }
 
 #[post("/generate")]
@@ -35,22 +40,74 @@ async fn generate_report(
     Ok(HttpResponse::Created().json(result))
 }
 
+/// Streams the report body as it is rendered instead of storing it, for
+/// reports too large to build in memory
+#[post("/stream")]
+async fn stream_report(
+    request: web::Json<ReportRequest>,
+    report_service: web::Data<ReportService>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    let title = request.title.to_lowercase().replace(' ', "_");
+
+    let (stream, extension) = report_service.stream_report(request).await.map_err(|e| {
+        tracing::error!("Failed to stream report: {}", e);
+        match e {
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::ExternalApi(_) => actix_web::error::ErrorBadGateway(e),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        }
+    })?;
+
+    let filename = sanitize(format!("{}.{}", title, extension));
+    Ok(HttpResponse::Ok()
+        .content_type(mime_guess::from_ext(&extension).first_or_octet_stream())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .streaming(stream))
+}
+
 #[get("/templates")]
 async fn list_templates(report_service: web::Data<ReportService>) -> Result<HttpResponse, Error> {
     let templates = report_service.get_available_templates();
     Ok(HttpResponse::Ok().json(templates))
 }
 
+/// Uploads a template. Admin only: a user template is used for every report
+/// of its type, whoever requests it.
+#[post("/templates", wrap = "RoleAuthorization::new(Vec::new())")]
+async fn upload_template(
+    upload: web::Json<TemplateUpload>,
+    report_service: web::Data<ReportService>,
+) -> Result<HttpResponse, Error> {
+    let template = report_service
+        .register_template(upload.into_inner())
+        .map_err(|e| match e {
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to register template: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Created().json(template))
+}
+
 #[get("/{id}")]
 async fn get_report(
+    req: HttpRequest,
     id: web::Path<String>,
     report_service: web::Data<ReportService>,
-) -> Result<NamedFile, Error> {
+) -> Result<HttpResponse, Error> {
     let report_id = Uuid::parse_str(&id)
         .map_err(|_| actix_web::error::ErrorBadRequest("Invalid report ID format"))?;
 
-    let file_path = report_service
-        .get_report_file(&report_id)
+    // The lookup lists the report directory, so keep it off the worker
+    let file_path = web::block(move || report_service.get_report_file(&report_id))
+        .await?
         .map_err(|e| match e {
             CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
             _ => {
@@ -59,7 +116,7 @@ async fn get_report(
             }
         })?;
 
-    NamedFile::open(file_path).map_err(|e| {
+    download::serve_file(&req, &file_path).await.map_err(|e| {
         tracing::error!("Failed to open report file: {}", e);
         actix_web::error::ErrorInternalServerError(e)
     })
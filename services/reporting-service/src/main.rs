@@ -1,22 +1,26 @@
 use actix_files as fs;
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::telemetry;
+use mirage_middleware::Authentication;
 use tracing::info;
 
 mod config;
+mod download;
 mod formatters;
 mod handlers;
 mod models;
 mod services;
 mod templates;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("reporting-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -37,19 +41,79 @@ async fn main() -> std::io::Result<()> {
         .expect("Failed to create HTTP client");
 
     // Initialize reporting service
-    let report_service = web::Data::new(services::ReportService::new(http_client, config.clone()));
+    let report_service = web::Data::new(services::ReportService::new(
+        http_client.clone(),
+        config.clone(),
+    ));
 
     info!("Starting Reporting Service on port {}", config.server.port);
 
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("reporting-service", env!("CARGO_PKG_VERSION"))
+            .probe(
+                "data-storage-service",
+                health::http_probe(
+                    http_client.clone(),
+                    &format!("{}/api/v1/health", config.data_storage.url),
+                ),
+            )
+            .optional_probe(
+                "correlation-engine-service",
+                health::http_probe(
+                    http_client.clone(),
+                    &format!("{}/api/v1/health", config.correlation.url),
+                ),
+            )
+            .optional_probe(
+                "visualization-service",
+                health::http_probe(
+                    http_client.clone(),
+                    &format!("{}/api/v1/health", config.visualization.url),
+                ),
+            ),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("reporting-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    // Verifies the caller of every report route
+    let auth = match Authentication::from_env() {
+        Ok(auth) => auth,
+        Err(e) => {
+            tracing::error!("Failed to set up authentication: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up authentication",
+            ));
+        }
+    };
+
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
             .app_data(report_service.clone())
             .app_data(web::Data::new(config.clone()))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
-                    .service(handlers::report_routes()),
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
+                    .service(handlers::report_routes().wrap(auth.clone())),
             )
             .service(fs::Files::new("/reports", &config.report.output_dir).show_files_listing())
     })
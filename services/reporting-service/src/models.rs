@@ -56,6 +56,16 @@ pub struct ReportTemplate {
     pub supported_formats: Vec<ReportFormat>,
 }
 
+/// A user-supplied Handlebars template. Reports whose type is
+/// `custom: <name>` (or a built-in type with the same name) render with it
+/// in `format`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateUpload {
+    pub name: String,
+    pub format: ReportFormat,
+    pub content: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityData {
     pub id: Uuid,
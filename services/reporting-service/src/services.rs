@@ -1,13 +1,15 @@
 use crate::config::AppConfig;
 use crate::formatters::{
-    CsvFormatter, ExcelFormatter, HtmlFormatter, JsonFormatter, MarkdownFormatter, PdfFormatter,
-    ReportFormatter,
+    CsvFormatter, EntityStream, ExcelFormatter, HtmlFormatterImpl, JsonFormatter,
+    MarkdownFormatter, PdfFormatter, ReportFormatter, ReportStream, StreamingFormatter,
 };
 use crate::models::{
     EntityData, RelationshipData, Report, ReportFormat, ReportRequest, ReportTemplate,
-    ReportTemplateContext, ReportType, VisualizationData,
+    ReportTemplateContext, ReportType, TemplateUpload, VisualizationData,
 };
+use crate::templates::TemplateRegistry;
 use chrono::Utc;
+use futures::{StreamExt, TryStreamExt};
 use handlebars::Handlebars;
 use mirage_common::Error;
 use reqwest::Client;
@@ -18,11 +20,14 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use uuid::Uuid;
 
+/// How many entities a streamed report fetches at a time
+const ENTITY_PAGE_SIZE: usize = 50;
+
 #[derive(Clone)]
 pub struct ReportService {
     client: Arc<Client>,
     config: Arc<AppConfig>,
-    handlebars: Arc<Handlebars<'static>>,
+    templates: Arc<TemplateRegistry>,
 }
 
 impl ReportService {
@@ -90,7 +95,7 @@ impl ReportService {
         Self {
             client: Arc::new(client),
             config: Arc::new(config),
-            handlebars: Arc::new(handlebars),
+            templates: Arc::new(TemplateRegistry::new(handlebars)),
         }
     }
 
@@ -98,42 +103,10 @@ impl ReportService {
         &self,
         request: ReportRequest,
     ) -> Result<Report, mirage_common::Error> {
-        // Validate request
-        if request.entity_ids.is_empty() {
-            return Err(Error::Validation(
-                "At least one entity ID must be provided".into(),
-            ));
-        }
-
-        if request.entity_ids.len() > self.config.report.max_entities_per_report {
-            return Err(Error::Validation(format!(
-                "Too many entities. Maximum allowed: {}, provided: {}",
-                self.config.report.max_entities_per_report,
-                request.entity_ids.len()
-            )));
-        }
-
-        // Fetch data for each entity
-        let entities = self.fetch_entities_data(&request.entity_ids).await?;
-
-        // Generate visualizations if needed
-        let visualizations = self.generate_visualizations(&entities, &request).await?;
-
-        // Create template context
-        let context = ReportTemplateContext {
-            title: request.title.clone(),
-            description: request.description.clone(),
-            entities,
-            generated_at: Utc::now(),
-            generated_by: Some("Mirage OSINT Platform".to_string()),
-            visualizations,
-            custom_data: None,
-        };
-
         // Select formatter based on requested format
         let formatter: Box<dyn ReportFormatter> = match request.format {
-            ReportFormat::Html => Box::new(HtmlFormatter::new(self.handlebars.clone())),
-            ReportFormat::Pdf => Box::new(PdfFormatter::new(self.handlebars.clone())),
+            ReportFormat::Html => Box::new(HtmlFormatterImpl::new(self.templates.handlebars())),
+            ReportFormat::Pdf => Box::new(PdfFormatter::new(self.templates.handlebars())),
             ReportFormat::Markdown => Box::new(MarkdownFormatter::new()),
             ReportFormat::Json => Box::new(JsonFormatter::new()),
             ReportFormat::Csv => Box::new(CsvFormatter::new()),
@@ -143,14 +116,8 @@ impl ReportService {
             }
         };
 
-        // Get template name based on report type
-        let template_name = match &request.report_type {
-            ReportType::Summary => "summary",
-            ReportType::Detailed => "detailed",
-            ReportType::Executive => "executive",
-            ReportType::Technical => "technical",
-            ReportType::Custom(name) => name,
-        };
+        let context = self.build_context(&request).await?;
+        let template_name = template_name(&request.report_type);
 
         // Generate content
         let (content, extension) = formatter.format(&context, template_name)?;
@@ -190,6 +157,56 @@ impl ReportService {
         Ok(report)
     }
 
+    /// Renders a report straight into the response instead of a file. Only
+    /// formats that can be written row by row are supported.
+    pub async fn stream_report(
+        &self,
+        request: ReportRequest,
+    ) -> Result<(ReportStream, String), mirage_common::Error> {
+        let (formatter, extension): (Box<dyn StreamingFormatter>, &str) = match request.format {
+            ReportFormat::Csv => (Box::new(CsvFormatter::new()), "csv"),
+            ReportFormat::Json => (Box::new(JsonFormatter::new()), "json"),
+            _ => {
+                return Err(Error::Validation(
+                    "Streaming is only supported for csv and json reports".into(),
+                ))
+            }
+        };
+
+        self.check_entity_count(&request)?;
+
+        // Only one page of entities is held at a time, so the graph is
+        // requested for every entity asked for, fetched or not
+        let visualizations = self
+            .generate_visualizations(&request.entity_ids, &request)
+            .await?;
+        let context = report_context(&request, Vec::new(), visualizations);
+        let entities = self.entity_pages(request.entity_ids.clone());
+        let stream =
+            formatter.format_stream(&context, entities, template_name(&request.report_type));
+
+        Ok((stream, extension.to_string()))
+    }
+
+    /// Fetches entities a page at a time as the report is written
+    fn entity_pages(&self, entity_ids: Vec<Uuid>) -> EntityStream {
+        let service = self.clone();
+        let pages: Vec<Vec<Uuid>> = entity_ids
+            .chunks(ENTITY_PAGE_SIZE)
+            .map(<[Uuid]>::to_vec)
+            .collect();
+
+        Box::pin(
+            futures::stream::iter(pages)
+                .then(move |page| {
+                    let service = service.clone();
+                    async move { service.fetch_entities_data(&page).await }
+                })
+                .map_ok(|entities| futures::stream::iter(entities.into_iter().map(Ok)))
+                .try_flatten(),
+        )
+    }
+
     pub fn get_available_templates(&self) -> Vec<ReportTemplate> {
         let mut templates = Vec::new();
 
@@ -242,7 +259,7 @@ impl ReportService {
         });
 
         // Add custom templates from handlebars registry
-        for name in self.handlebars.get_templates().keys() {
+        for name in &self.templates.template_names() {
             if !templates.iter().any(|t| &t.id == name) {
                 if !name.contains("_html") && !name.contains("_pdf") {
                     templates.push(ReportTemplate {
@@ -260,9 +277,35 @@ impl ReportService {
             }
         }
 
+        // Add templates uploaded through the API
+        for (name, formats) in self.templates.user_templates() {
+            if !templates.iter().any(|t| t.id == name) {
+                templates.push(ReportTemplate {
+                    id: name.clone(),
+                    name: name.replace('_', " "),
+                    description: format!("User template: {}", name),
+                    supported_formats: formats,
+                });
+            }
+        }
+
         templates
     }
 
+    pub fn register_template(
+        &self,
+        upload: TemplateUpload,
+    ) -> Result<ReportTemplate, mirage_common::Error> {
+        self.templates.register_user_template(&upload)?;
+
+        Ok(ReportTemplate {
+            id: upload.name.clone(),
+            name: upload.name.replace('_', " "),
+            description: format!("User template: {}", upload.name),
+            supported_formats: vec![upload.format],
+        })
+    }
+
     pub fn get_report_file(&self, report_id: &Uuid) -> Result<PathBuf, mirage_common::Error> {
         // In a real implementation, we would look up the report metadata in a database
         // For now, we'll just check if a file exists with this ID
@@ -287,6 +330,40 @@ impl ReportService {
 
     // Helper methods
 
+    async fn build_context(
+        &self,
+        request: &ReportRequest,
+    ) -> Result<ReportTemplateContext, mirage_common::Error> {
+        self.check_entity_count(request)?;
+
+        // Fetch data for each entity
+        let entities = self.fetch_entities_data(&request.entity_ids).await?;
+
+        // Generate visualizations if needed
+        let entity_ids: Vec<Uuid> = entities.iter().map(|e| e.id).collect();
+        let visualizations = self.generate_visualizations(&entity_ids, request).await?;
+
+        Ok(report_context(request, entities, visualizations))
+    }
+
+    fn check_entity_count(&self, request: &ReportRequest) -> Result<(), mirage_common::Error> {
+        if request.entity_ids.is_empty() {
+            return Err(Error::Validation(
+                "At least one entity ID must be provided".into(),
+            ));
+        }
+
+        if request.entity_ids.len() > self.config.report.max_entities_per_report {
+            return Err(Error::Validation(format!(
+                "Too many entities. Maximum allowed: {}, provided: {}",
+                self.config.report.max_entities_per_report,
+                request.entity_ids.len()
+            )));
+        }
+
+        Ok(())
+    }
+
     async fn fetch_entities_data(
         &self,
         entity_ids: &[Uuid],
@@ -384,15 +461,13 @@ impl ReportService {
 
     async fn generate_visualizations(
         &self,
-        entities: &[EntityData],
+        entity_ids: &[Uuid],
         request: &ReportRequest,
     ) -> Result<Vec<VisualizationData>, mirage_common::Error> {
         let mut visualizations = Vec::new();
 
         // If we have multiple entities, create a correlation graph
-        if entities.len() > 1 {
-            let entity_ids: Vec<Uuid> = entities.iter().map(|e| e.id).collect();
-
+        if entity_ids.len() > 1 {
             // Request a graph visualization from the visualization service
             let viz_url = format!(
                 "{}/api/v1/visualizations/graph",
@@ -464,7 +539,7 @@ impl ReportService {
 
             let viz_request = serde_json::json!({
                 "data_type": "pie",
-                "entity_ids": entities.iter().map(|e| e.id).collect::<Vec<Uuid>>(),
+                "entity_ids": entity_ids,
                 "format": "svg",
                 "width": 500,
                 "height": 400,
@@ -516,3 +591,30 @@ impl ReportService {
         Ok(visualizations)
     }
 }
+
+fn report_context(
+    request: &ReportRequest,
+    entities: Vec<EntityData>,
+    visualizations: Vec<VisualizationData>,
+) -> ReportTemplateContext {
+    ReportTemplateContext {
+        title: request.title.clone(),
+        description: request.description.clone(),
+        entities,
+        generated_at: Utc::now(),
+        generated_by: Some("Mirage OSINT Platform".to_string()),
+        visualizations,
+        custom_data: None,
+    }
+}
+
+/// Template name based on report type
+fn template_name(report_type: &ReportType) -> &str {
+    match report_type {
+        ReportType::Summary => "summary",
+        ReportType::Detailed => "detailed",
+        ReportType::Executive => "executive",
+        ReportType::Technical => "technical",
+        ReportType::Custom(name) => name,
+    }
+}
@@ -1,6 +1,134 @@
 //! Template management for reports
 
+use crate::models::{ReportFormat, TemplateUpload};
+use handlebars::Handlebars;
+use mirage_common::Error;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Namespace for templates uploaded through the API. Keeping them apart means
+/// an upload can't overwrite a built-in template, and the formatters can look
+/// for them first. Only admins may upload, as an upload applies to everyone.
+const USER_TEMPLATE_PREFIX: &str = "user/";
+const MAX_TEMPLATE_NAME_LENGTH: usize = 64;
+
+/// Registry key of the user template `name` for output `suffix` (`html`, `pdf`)
+pub fn user_template_key(name: &str, suffix: &str) -> String {
+    format!("{}{}_{}", USER_TEMPLATE_PREFIX, name, suffix)
+}
+
+/// Handlebars templates shared by all report renders. User templates can be
+/// added at runtime; they are kept in memory only.
+pub struct TemplateRegistry {
+    // Renders share the current registry; an upload swaps in an updated copy
+    handlebars: RwLock<Arc<Handlebars<'static>>>,
+}
+
+impl TemplateRegistry {
+    pub fn new(handlebars: Handlebars<'static>) -> Self {
+        Self {
+            handlebars: RwLock::new(Arc::new(handlebars)),
+        }
+    }
+
+    /// The registry for one render, unaffected by later uploads
+    pub fn handlebars(&self) -> Arc<Handlebars<'static>> {
+        self.handlebars.read().unwrap().clone()
+    }
+
+    /// Names of every registered template, user templates included
+    pub fn template_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .handlebars
+            .read()
+            .unwrap()
+            .get_templates()
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// User templates by name, with the formats each one has been uploaded for
+    pub fn user_templates(&self) -> BTreeMap<String, Vec<ReportFormat>> {
+        let mut templates: BTreeMap<String, Vec<ReportFormat>> = BTreeMap::new();
+
+        for key in self.template_names() {
+            let Some(rest) = key.strip_prefix(USER_TEMPLATE_PREFIX) else {
+                continue;
+            };
+            let parsed = if let Some(name) = rest.strip_suffix("_html") {
+                Some((name, ReportFormat::Html))
+            } else {
+                rest.strip_suffix("_pdf")
+                    .map(|name| (name, ReportFormat::Pdf))
+            };
+            if let Some((name, format)) = parsed {
+                templates.entry(name.to_string()).or_default().push(format);
+            }
+        }
+
+        templates
+    }
+
+    /// Compiles and registers an uploaded template, replacing an earlier
+    /// upload with the same name and format. Nothing is registered if the
+    /// template doesn't compile.
+    pub fn register_user_template(
+        &self,
+        upload: &TemplateUpload,
+    ) -> Result<String, mirage_common::Error> {
+        validate_template_name(&upload.name)?;
+
+        let suffix = match upload.format {
+            ReportFormat::Html => "html",
+            ReportFormat::Pdf => "pdf",
+            _ => {
+                return Err(Error::Validation(
+                    "Custom templates are only supported for html and pdf reports".into(),
+                ))
+            }
+        };
+
+        let key = user_template_key(&upload.name, suffix);
+        // Copied only while a render still holds the current registry
+        let mut handlebars = self.handlebars.write().unwrap();
+        Arc::make_mut(&mut handlebars)
+            .register_template_string(&key, &upload.content)
+            .map_err(|e| {
+                Error::Validation(format!(
+                    "Template '{}' does not compile: {}",
+                    upload.name, e
+                ))
+            })?;
+
+        tracing::info!("Registered user template: {}", key);
+        Ok(key)
+    }
+}
+
+fn validate_template_name(name: &str) -> Result<(), mirage_common::Error> {
+    if name.is_empty() || name.len() > MAX_TEMPLATE_NAME_LENGTH {
+        return Err(Error::Validation(format!(
+            "Template name must be between 1 and {} characters",
+            MAX_TEMPLATE_NAME_LENGTH
+        )));
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::Validation(format!(
+            "Template name '{}' may only contain letters, digits, '_' and '-'",
+            name
+        )));
+    }
+
+    Ok(())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReportTemplate {
@@ -55,3 +183,119 @@ pub fn list_templates() -> Vec<ReportTemplate> {
         format: "text".to_string(),
     }]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatters::{HtmlFormatterImpl, ReportFormatter};
+    use crate::models::ReportTemplateContext;
+    use chrono::Utc;
+
+    fn upload(name: &str, format: ReportFormat, content: &str) -> TemplateUpload {
+        TemplateUpload {
+            name: name.to_string(),
+            format,
+            content: content.to_string(),
+        }
+    }
+
+    fn registry() -> TemplateRegistry {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars
+            .register_template_string("summary_html", "<p>built-in {{title}}</p>")
+            .unwrap();
+        TemplateRegistry::new(handlebars)
+    }
+
+    fn context() -> ReportTemplateContext {
+        ReportTemplateContext {
+            title: "Weekly Findings".to_string(),
+            description: None,
+            entities: Vec::new(),
+            generated_at: Utc::now(),
+            generated_by: None,
+            visualizations: Vec::new(),
+            custom_data: None,
+        }
+    }
+
+    #[test]
+    fn test_register_valid_template() {
+        let registry = registry();
+
+        let key = registry
+            .register_user_template(&upload("weekly", ReportFormat::Html, "<h1>{{title}}</h1>"))
+            .unwrap();
+
+        assert_eq!(key, "user/weekly_html");
+        assert!(registry.template_names().contains(&key));
+        assert_eq!(
+            registry.user_templates().keys().collect::<Vec<_>>(),
+            vec!["weekly"]
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_template() {
+        let registry = registry();
+
+        let err = registry
+            .register_user_template(&upload("broken", ReportFormat::Html, "{{#each entities}}"))
+            .unwrap_err();
+        match err {
+            Error::Validation(message) => assert!(message.contains("does not compile")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert!(registry.user_templates().is_empty());
+
+        assert!(matches!(
+            registry.register_user_template(&upload("../etc", ReportFormat::Html, "ok")),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            registry.register_user_template(&upload("weekly", ReportFormat::Csv, "ok")),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_renders_share_the_registry_until_an_upload() {
+        let registry = registry();
+        let first = registry.handlebars();
+        assert!(Arc::ptr_eq(&first, &registry.handlebars()));
+
+        registry
+            .register_user_template(&upload("weekly", ReportFormat::Html, "<h1>{{title}}</h1>"))
+            .unwrap();
+
+        // A render already under way keeps the registry it started with
+        assert!(!first.has_template("user/weekly_html"));
+        assert!(registry.handlebars().has_template("user/weekly_html"));
+    }
+
+    #[test]
+    fn test_render_with_custom_template() {
+        let registry = registry();
+        let formatter = HtmlFormatterImpl::new(registry.handlebars());
+        let (before, _) = formatter.format(&context(), "summary").unwrap();
+        assert_eq!(before, b"<p>built-in Weekly Findings</p>");
+
+        // A user template shadows the built-in one of the same name
+        registry
+            .register_user_template(&upload(
+                "summary",
+                ReportFormat::Html,
+                "<h1>custom {{title}}</h1>",
+            ))
+            .unwrap();
+        let formatter = HtmlFormatterImpl::new(registry.handlebars());
+        let (bytes, extension) = formatter.format(&context(), "summary").unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "<h1>custom Weekly Findings</h1>"
+        );
+        assert_eq!(extension, "html");
+    }
+}
@@ -0,0 +1,13 @@
+-- Scans run by the orchestrator
+CREATE TABLE scans (
+    id UUID PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    targets TEXT[] NOT NULL DEFAULT '{}',
+    modules TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_scans_status ON scans(status);
+CREATE INDEX idx_scans_created_at ON scans(created_at);
@@ -0,0 +1,15 @@
+-- Reusable scan presets. `modules` holds the module selection as
+-- [{"module_id", "order", "parameters"}]; the modules themselves live in the
+-- module registry and are checked again whenever a scan is created
+CREATE TABLE scan_templates (
+    id UUID PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    description TEXT,
+    modules JSONB NOT NULL DEFAULT '[]',
+    priority INTEGER,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    metadata JSONB NOT NULL DEFAULT '{}',
+    max_duration_minutes INTEGER,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -2,18 +2,14 @@ use config::{Config, ConfigError, File};
 use serde::Deserialize;
 use std::env;
 
+pub use mirage_common::config::DatabaseConfig;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
     pub host: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct DatabaseConfig {
-    pub url: String,
-    pub max_connections: u32,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct ModuleConfig {
     pub registry_url: String,
@@ -24,12 +20,18 @@ pub struct CollectionConfig {
     pub service_url: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageConfig {
+    pub service_url: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
     pub module: ModuleConfig,
     pub collection: CollectionConfig,
+    pub storage: StorageConfig,
 }
 
 pub fn load_config() -> Result<AppConfig, ConfigError> {
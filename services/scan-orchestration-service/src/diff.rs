@@ -0,0 +1,222 @@
+//! What changed between two scans of the same target
+//!
+//! Findings are matched on `(data_type, value)`; confidence, source and
+//! timestamp don't make a finding new. A scan that reports the same finding
+//! several times counts it once.
+
+use crate::export::{ExportRow, ResultSource, StoredResult, PAGE_SIZE};
+use crate::models::ScanTarget;
+use mirage_common::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    pub from: Uuid,
+    pub to: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanDiff {
+    pub from: Uuid,
+    pub to: Uuid,
+    /// In `to` but not in `from`
+    pub added: Vec<ExportRow>,
+    /// In `from` but not in `to`
+    pub removed: Vec<ExportRow>,
+    /// In both; as reported by `to`
+    pub unchanged: Vec<ExportRow>,
+}
+
+/// Scans are comparable when they ran against the same set of targets
+pub fn ensure_same_targets(from: &[ScanTarget], to: &[ScanTarget]) -> Result<()> {
+    let targets = |targets: &[ScanTarget]| -> BTreeSet<(String, String)> {
+        targets
+            .iter()
+            .map(|t| (t.target_type.clone(), t.value.clone()))
+            .collect()
+    };
+
+    if targets(from) != targets(to) {
+        return Err(Error::Validation(
+            "Scans can only be compared when they have the same targets".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Every result of `scan_id`
+pub async fn collect_results<S>(source: &S, scan_id: Uuid) -> Result<Vec<StoredResult>>
+where
+    S: ResultSource + ?Sized,
+{
+    let mut results = Vec::new();
+    loop {
+        let page = source
+            .page(scan_id, results.len() as u64, PAGE_SIZE)
+            .await?;
+        let last = (page.len() as u64) < PAGE_SIZE;
+        results.extend(page);
+        if last {
+            return Ok(results);
+        }
+    }
+}
+
+pub fn diff_results(
+    from_id: Uuid,
+    from: Vec<StoredResult>,
+    to_id: Uuid,
+    to: Vec<StoredResult>,
+) -> ScanDiff {
+    let mut before = by_finding(from);
+    let after = by_finding(to);
+
+    let mut added = Vec::new();
+    let mut unchanged = Vec::new();
+    for (key, row) in after {
+        if before.remove(&key).is_some() {
+            unchanged.push(row);
+        } else {
+            added.push(row);
+        }
+    }
+
+    ScanDiff {
+        from: from_id,
+        to: to_id,
+        added,
+        removed: before.into_values().collect(),
+        unchanged,
+    }
+}
+
+/// Findings keyed by `(data_type, value)`, first report wins
+fn by_finding(results: Vec<StoredResult>) -> BTreeMap<(String, String), ExportRow> {
+    let mut findings = BTreeMap::new();
+    for result in results {
+        let row = ExportRow::from(result);
+        findings
+            .entry((row.data_type.clone(), row.value.clone()))
+            .or_insert(row);
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::StaticResults;
+    use crate::models::ScanTargetStatus;
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    fn result(data_type: &str, value: &str, confidence: f64) -> StoredResult {
+        StoredResult {
+            source_module: Uuid::from_u128(1),
+            entity_type: data_type.to_string(),
+            value: value.to_string(),
+            data: serde_json::json!({ "confidence": confidence }),
+            created_at: Utc::now(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn target(target_type: &str, value: &str) -> ScanTarget {
+        ScanTarget {
+            id: Uuid::new_v4(),
+            scan_id: Uuid::new_v4(),
+            target_type: target_type.to_string(),
+            value: value.to_string(),
+            created_at: Utc::now(),
+            processed_at: None,
+            status: ScanTargetStatus::Completed,
+            error_message: None,
+        }
+    }
+
+    fn keys(rows: &[ExportRow]) -> Vec<(&str, &str)> {
+        rows.iter()
+            .map(|r| (r.data_type.as_str(), r.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_classifies_findings() {
+        let from = vec![
+            result("domain", "example.com", 0.9),
+            result("ip_address", "93.184.216.34", 0.8),
+            result("email", "old@example.com", 0.7),
+            result("email", "old@example.com", 0.6),
+        ];
+        let to = vec![
+            result("domain", "example.com", 0.5),
+            // Same value, different type: a different finding
+            result("hostname", "93.184.216.34", 0.8),
+            result("email", "new@example.com", 0.7),
+            result("ip_address", "93.184.216.34", 0.8),
+        ];
+
+        let diff = diff_results(Uuid::from_u128(1), from, Uuid::from_u128(2), to);
+
+        assert_eq!(
+            keys(&diff.added),
+            vec![("email", "new@example.com"), ("hostname", "93.184.216.34")]
+        );
+        assert_eq!(keys(&diff.removed), vec![("email", "old@example.com")]);
+        assert_eq!(
+            keys(&diff.unchanged),
+            vec![("domain", "example.com"), ("ip_address", "93.184.216.34")]
+        );
+        // Unchanged findings are reported as the later scan saw them
+        assert_eq!(diff.unchanged[0].confidence, Some(0.5));
+    }
+
+    #[test]
+    fn test_diff_of_empty_scans() {
+        let diff = diff_results(
+            Uuid::from_u128(1),
+            Vec::new(),
+            Uuid::from_u128(2),
+            vec![result("domain", "example.com", 0.9)],
+        );
+        assert_eq!(keys(&diff.added), vec![("domain", "example.com")]);
+        assert!(diff.removed.is_empty());
+        assert!(diff.unchanged.is_empty());
+    }
+
+    #[test]
+    fn test_targets_must_match() {
+        let from = [target("domain", "example.com"), target("ip", "10.0.0.1")];
+        let reordered = [target("ip", "10.0.0.1"), target("domain", "example.com")];
+        assert!(ensure_same_targets(&from, &reordered).is_ok());
+
+        let other = [target("domain", "example.org"), target("ip", "10.0.0.1")];
+        assert!(matches!(
+            ensure_same_targets(&from, &other),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            ensure_same_targets(&from, &from[..1]),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_collect_results_reads_every_page() {
+        let scan_id = Uuid::new_v4();
+        let results: Vec<_> = (0..PAGE_SIZE + 1)
+            .map(|i| result("ip_address", &format!("10.0.{}.{}", i / 256, i % 256), 1.0))
+            .collect();
+        let source = StaticResults(HashMap::from([(scan_id, results)]));
+
+        let collected = collect_results(&source, scan_id).await.unwrap();
+        assert_eq!(collected.len() as u64, PAGE_SIZE + 1);
+        assert!(collect_results(&source, Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Downloads of everything a scan found
+//!
+//! Results live in data-storage; an export pages through the ones tagged with
+//! the scan and streams them out as a JSON array or as CSV with the columns
+//! in `CSV_HEADERS`. The first page is fetched before the response starts, so
+//! an unreachable data-storage is reported with a proper status code. A
+//! failure on a later page ends the stream early.
+
+use actix_web::web::Bytes;
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use mirage_common::client::ServiceClient;
+use mirage_common::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const PAGE_SIZE: u64 = 500;
+
+const CSV_HEADERS: [&str; 5] = ["data_type", "value", "confidence", "source", "timestamp"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// A result as data-storage returns it, reduced to what exports use
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoredResult {
+    pub source_module: Uuid,
+    pub entity_type: String,
+    pub value: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub data_type: String,
+    pub value: String,
+    pub confidence: Option<f64>,
+    /// ID of the module that produced the result
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<StoredResult> for ExportRow {
+    fn from(result: StoredResult) -> Self {
+        // Modules report confidence either in their data or as metadata
+        let confidence = result
+            .data
+            .get("confidence")
+            .and_then(|c| c.as_f64())
+            .or_else(|| {
+                result
+                    .metadata
+                    .get("confidence")
+                    .and_then(|c| c.parse().ok())
+            });
+
+        Self {
+            data_type: result.entity_type,
+            value: result.value,
+            confidence,
+            source: result.source_module.to_string(),
+            timestamp: result.created_at,
+        }
+    }
+}
+
+/// Paged access to a scan's results
+#[async_trait]
+pub trait ResultSource: Send + Sync {
+    async fn page(&self, scan_id: Uuid, offset: u64, limit: u64) -> Result<Vec<StoredResult>>;
+}
+
+/// Reads results from data-storage's `GET /api/v1/data`
+#[derive(Clone)]
+pub struct DataStorageResults {
+    storage: ServiceClient,
+}
+
+impl DataStorageResults {
+    pub fn new(storage_url: &str) -> Result<Self> {
+        Ok(Self {
+            storage: ServiceClient::new(storage_url)?,
+        })
+    }
+
+    /// Copy that reads with the caller's credentials
+    pub fn for_request(&self, req: &HttpRequest) -> Self {
+        Self {
+            storage: self.storage.for_request(req),
+        }
+    }
+}
+
+#[async_trait]
+impl ResultSource for DataStorageResults {
+    async fn page(&self, scan_id: Uuid, offset: u64, limit: u64) -> Result<Vec<StoredResult>> {
+        self.storage
+            .get_json(&format!(
+                "/api/v1/data?scan_id={}&limit={}&offset={}",
+                scan_id, limit, offset
+            ))
+            .await
+    }
+}
+
+/// Fixed results per scan, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct StaticResults(pub HashMap<Uuid, Vec<StoredResult>>);
+
+#[cfg(test)]
+#[async_trait]
+impl ResultSource for StaticResults {
+    async fn page(&self, scan_id: Uuid, offset: u64, limit: u64) -> Result<Vec<StoredResult>> {
+        Ok(self
+            .0
+            .get(&scan_id)
+            .into_iter()
+            .flatten()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+struct ExportState<S> {
+    source: S,
+    scan_id: Uuid,
+    format: ExportFormat,
+    page_size: u64,
+    offset: u64,
+    /// Fetched ahead of streaming; taken by the first poll
+    first_page: Option<Vec<StoredResult>>,
+    rows_written: u64,
+    finished: bool,
+}
+
+impl<S: ResultSource> ExportState<S> {
+    /// Encodes one page, and the closing bytes after the last one
+    fn encode(&mut self, page: Vec<StoredResult>) -> Result<Vec<u8>> {
+        let first = self.offset == 0;
+        let last = (page.len() as u64) < self.page_size;
+        self.offset += page.len() as u64;
+
+        let mut buf = Vec::new();
+        match self.format {
+            ExportFormat::Json => {
+                for result in page {
+                    buf.push(if self.rows_written == 0 { b'[' } else { b',' });
+                    serde_json::to_writer(&mut buf, &ExportRow::from(result))?;
+                    self.rows_written += 1;
+                }
+                if last {
+                    if self.rows_written == 0 {
+                        buf.push(b'[');
+                    }
+                    buf.push(b']');
+                }
+            }
+            ExportFormat::Csv => {
+                let mut writer = ::csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(&mut buf);
+                if first {
+                    writer.write_record(CSV_HEADERS).map_err(csv_error)?;
+                }
+                for result in page {
+                    let row = ExportRow::from(result);
+                    writer
+                        .write_record([
+                            row.data_type,
+                            row.value,
+                            row.confidence.map(|c| c.to_string()).unwrap_or_default(),
+                            row.source,
+                            row.timestamp.to_rfc3339(),
+                        ])
+                        .map_err(csv_error)?;
+                    self.rows_written += 1;
+                }
+                writer.flush()?;
+            }
+        }
+
+        self.finished = last;
+        Ok(buf)
+    }
+}
+
+fn csv_error(e: ::csv::Error) -> Error {
+    Error::Internal(format!("CSV writing error: {}", e))
+}
+
+/// Streams all results of `scan_id` in `format`. Fails if the first page
+/// can't be read.
+pub async fn export_stream<S>(
+    source: S,
+    scan_id: Uuid,
+    format: ExportFormat,
+    page_size: u64,
+) -> Result<impl Stream<Item = std::result::Result<Bytes, actix_web::Error>>>
+where
+    S: ResultSource + 'static,
+{
+    let page_size = page_size.max(1);
+    let first_page = source.page(scan_id, 0, page_size).await?;
+
+    let state = ExportState {
+        source,
+        scan_id,
+        format,
+        page_size,
+        offset: 0,
+        first_page: Some(first_page),
+        rows_written: 0,
+        finished: false,
+    };
+
+    Ok(stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+
+        let page = match state.first_page.take() {
+            Some(page) => Ok(page),
+            None => {
+                state
+                    .source
+                    .page(state.scan_id, state.offset, state.page_size)
+                    .await
+            }
+        };
+
+        match page.and_then(|page| state.encode(page)) {
+            Ok(buf) => Some((Ok(Bytes::from(buf)), state)),
+            Err(e) => {
+                tracing::error!("Export of scan {} failed: {}", state.scan_id, e);
+                state.finished = true;
+                Some((Err(actix_web::error::ErrorInternalServerError(e)), state))
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    struct Unavailable;
+
+    #[async_trait]
+    impl ResultSource for Unavailable {
+        async fn page(&self, _: Uuid, _: u64, _: u64) -> Result<Vec<StoredResult>> {
+            Err(Error::Network("connection refused".to_string()))
+        }
+    }
+
+    fn results() -> Vec<StoredResult> {
+        let module = Uuid::from_u128(7);
+        let at = "2023-12-01T10:00:00Z".parse().unwrap();
+        vec![
+            StoredResult {
+                source_module: module,
+                entity_type: "domain".to_string(),
+                value: "example.com".to_string(),
+                data: serde_json::json!({ "confidence": 0.9 }),
+                created_at: at,
+                metadata: HashMap::new(),
+            },
+            StoredResult {
+                source_module: module,
+                entity_type: "ip_address".to_string(),
+                value: "93.184.216.34".to_string(),
+                data: serde_json::Value::Null,
+                created_at: at,
+                metadata: HashMap::from([("confidence".to_string(), "0.5".to_string())]),
+            },
+            StoredResult {
+                source_module: module,
+                entity_type: "organization".to_string(),
+                value: "Example, Inc. \"EXAMPLE\"".to_string(),
+                data: serde_json::json!({}),
+                created_at: at,
+                metadata: HashMap::new(),
+            },
+        ]
+    }
+
+    async fn export(results: Vec<StoredResult>, format: ExportFormat) -> String {
+        let scan_id = Uuid::new_v4();
+        let source = StaticResults(HashMap::from([(scan_id, results)]));
+        let chunks: Vec<_> = export_stream(source, scan_id, format, 2)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let bytes: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_json_export_spans_pages() {
+        let body = export(results(), ExportFormat::Json).await;
+
+        let rows: Vec<ExportRow> = serde_json::from_str(&body).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].data_type, "domain");
+        assert_eq!(rows[0].confidence, Some(0.9));
+        assert_eq!(rows[1].confidence, Some(0.5));
+        assert_eq!(rows[2].confidence, None);
+        assert_eq!(rows[2].value, "Example, Inc. \"EXAMPLE\"");
+        assert_eq!(rows[2].source, Uuid::from_u128(7).to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_csv_export_has_header_and_quoted_fields() {
+        let body = export(results(), ExportFormat::Csv).await;
+
+        let mut reader = ::csv::Reader::from_reader(body.as_bytes());
+        assert_eq!(reader.headers().unwrap(), CSV_HEADERS.as_slice());
+        let rows: Vec<::csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(&rows[0][1], "example.com");
+        assert_eq!(&rows[0][2], "0.9");
+        assert_eq!(&rows[2][1], "Example, Inc. \"EXAMPLE\"");
+        assert_eq!(&rows[2][2], "");
+        assert_eq!(&rows[2][4], "2023-12-01T10:00:00+00:00");
+    }
+
+    #[actix_web::test]
+    async fn test_empty_scan_exports_valid_empty_output() {
+        let json = export(Vec::new(), ExportFormat::Json).await;
+        assert_eq!(json, "[]");
+        assert!(serde_json::from_str::<Vec<ExportRow>>(&json)
+            .unwrap()
+            .is_empty());
+
+        let csv = export(Vec::new(), ExportFormat::Csv).await;
+        assert_eq!(csv, "data_type,value,confidence,source,timestamp\n");
+
+        // A page that is exactly full is followed by an empty one
+        let two = results().into_iter().take(2).collect();
+        let rows: Vec<ExportRow> =
+            serde_json::from_str(&export(two, ExportFormat::Json).await).unwrap();
+        assert_eq!(rows.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_unreachable_storage_fails_before_streaming() {
+        let result = export_stream(Unavailable, Uuid::new_v4(), ExportFormat::Csv, 2).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+    }
+}
@@ -1,9 +1,43 @@
 //! Request handlers for scan orchestration service
 
-use actix_web::{web, HttpResponse, Responder, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, Result};
+use mirage_common::auth::Claims;
+use mirage_common::Error;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::diff::{self, DiffQuery};
+use crate::export::{self, DataStorageResults, ExportQuery};
+use crate::models::{
+    CreateScanFromTemplateRequest, CreateScanTemplateRequest, UpdateScanTemplateRequest,
+};
+use crate::services::ScanService;
+use crate::templates::TemplateService;
+
+pub fn scan_routes() -> actix_web::Scope {
+    web::scope("/scans")
+        .route("", web::post().to(create_scan))
+        .route("", web::get().to(list_scans))
+        // Registered before `/{id}` so "diff" isn't parsed as an ID
+        .route("/diff", web::get().to(diff_scans))
+        .route(
+            "/from-template/{id}",
+            web::post().to(create_scan_from_template),
+        )
+        .route("/{id}", web::get().to(get_scan))
+        .route("/{id}/stop", web::post().to(stop_scan))
+        .route("/{id}/export", web::get().to(export_scan))
+}
+
+pub fn template_routes() -> actix_web::Scope {
+    web::scope("/templates")
+        .route("", web::post().to(create_template))
+        .route("", web::get().to(list_templates))
+        .route("/{id}", web::get().to(get_template))
+        .route("/{id}", web::put().to(update_template))
+        .route("/{id}", web::delete().to(delete_template))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateScanRequest {
     pub name: String,
@@ -74,3 +108,124 @@ pub async fn stop_scan(path: web::Path<Uuid>) -> Result<impl Responder> {
         "status": "stopped"
     })))
 }
+
+/// Findings added, removed and kept between two scans of the same targets
+pub async fn diff_scans(
+    req: HttpRequest,
+    scan_service: web::Data<ScanService>,
+    results: web::Data<DataStorageResults>,
+    query: web::Query<DiffQuery>,
+) -> Result<impl Responder> {
+    let from = scan_service.get_scan(query.from).await?;
+    let to = scan_service.get_scan(query.to).await?;
+    diff::ensure_same_targets(&from.targets, &to.targets)?;
+
+    let results = results.for_request(&req);
+    let (from_results, to_results) = futures::try_join!(
+        diff::collect_results(&results, from.id),
+        diff::collect_results(&results, to.id)
+    )?;
+
+    Ok(HttpResponse::Ok().json(diff::diff_results(from.id, from_results, to.id, to_results)))
+}
+
+/// All of the scan's results in one download, as JSON or CSV
+pub async fn export_scan(
+    req: HttpRequest,
+    scan_service: web::Data<ScanService>,
+    results: web::Data<DataStorageResults>,
+    path: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+) -> Result<impl Responder> {
+    let scan = scan_service.get_scan(path.into_inner()).await?;
+    let format = query.format;
+
+    let body = export::export_stream(
+        results.for_request(&req),
+        scan.id,
+        format,
+        export::PAGE_SIZE,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"scan-{}.{}\"",
+                scan.id,
+                format.extension()
+            ),
+        ))
+        .streaming(body))
+}
+
+pub async fn create_scan_from_template(
+    http_req: HttpRequest,
+    template_service: web::Data<TemplateService>,
+    scan_service: web::Data<ScanService>,
+    path: web::Path<Uuid>,
+    req: web::Json<CreateScanFromTemplateRequest>,
+) -> Result<impl Responder> {
+    // Scans are owned by whoever created them, so an unknown caller can't
+    // create one
+    let user_id = Claims::from_request(&http_req)
+        .and_then(|claims| Uuid::parse_str(&claims.sub).ok())
+        .ok_or_else(|| Error::Unauthorized("Authentication required".to_string()))?;
+
+    let request = template_service
+        .scan_request(path.into_inner(), req.into_inner())
+        .await?;
+
+    let scan = scan_service.create_scan(request, user_id).await?;
+
+    Ok(HttpResponse::Created().json(scan))
+}
+
+pub async fn create_template(
+    template_service: web::Data<TemplateService>,
+    req: web::Json<CreateScanTemplateRequest>,
+) -> Result<impl Responder> {
+    let template = template_service.create_template(req.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(template))
+}
+
+pub async fn list_templates(
+    template_service: web::Data<TemplateService>,
+) -> Result<impl Responder> {
+    let templates = template_service.list_templates().await?;
+
+    Ok(HttpResponse::Ok().json(templates))
+}
+
+pub async fn get_template(
+    template_service: web::Data<TemplateService>,
+    path: web::Path<Uuid>,
+) -> Result<impl Responder> {
+    let template = template_service.get_template(path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(template))
+}
+
+pub async fn update_template(
+    template_service: web::Data<TemplateService>,
+    path: web::Path<Uuid>,
+    req: web::Json<UpdateScanTemplateRequest>,
+) -> Result<impl Responder> {
+    let template = template_service
+        .update_template(path.into_inner(), req.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(template))
+}
+
+pub async fn delete_template(
+    template_service: web::Data<TemplateService>,
+    path: web::Path<Uuid>,
+) -> Result<impl Responder> {
+    template_service.delete_template(path.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
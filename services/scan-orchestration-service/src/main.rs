@@ -1,21 +1,26 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
 use mirage_common::models::Scan;
+use mirage_common::telemetry;
 use tracing::info;
 
 mod config;
+mod diff;
+mod export;
 mod handlers;
 mod models;
 mod repositories;
 mod services;
-
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
+mod templates;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("scan-orchestration-service");
 
     // Load configuration
     let config = match config::load_config() {
@@ -42,21 +47,74 @@ async fn main() -> std::io::Result<()> {
 
     let scan_service = services::ScanService::new(db_pool.clone());
 
+    let module_repo = match repositories::ModuleRepository::new(&config.module.registry_url) {
+        Ok(repo) => repo,
+        Err(e) => {
+            tracing::error!("Failed to set up module registry client: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up module registry client",
+            ));
+        }
+    };
+    let scan_results = match export::DataStorageResults::new(&config.storage.service_url) {
+        Ok(results) => results,
+        Err(e) => {
+            tracing::error!("Failed to set up data-storage client: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up data-storage client",
+            ));
+        }
+    };
+
+    let template_service = templates::TemplateService::new(
+        repositories::ScanTemplateRepository::new(db_pool.clone()),
+        module_repo,
+    );
+
     info!(
         "Starting Scan Orchestration Service on port {}",
         config.server.port
     );
 
+    let health = web::Data::new(
+        HealthChecker::new("scan-orchestration-service", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone()),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("scan-orchestration-service") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
     HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(web::Data::new(db_pool.clone()))
             .app_data(web::Data::new(scan_service.clone()))
+            .app_data(web::Data::new(template_service.clone()))
+            .app_data(web::Data::new(scan_results.clone()))
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
-                    .service(handlers::scan_routes()),
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
+                    .service(handlers::scan_routes())
+                    .service(handlers::template_routes()),
             )
     })
     .bind(format!("0.0.0.0:{}", config.server.port))?
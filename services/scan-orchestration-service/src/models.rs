@@ -152,3 +152,66 @@ pub struct ScanModuleResult {
     pub data: serde_json::Value,
     pub created_at: DateTime<Utc>,
 }
+
+/// Named preset for a scan: the modules to run and the defaults a scan
+/// created from it starts with. Targets are supplied per scan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanTemplate {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub modules: Vec<CreateScanModuleRequest>,
+    pub priority: Option<i32>,
+    pub tags: Vec<String>,
+    pub metadata: HashMap<String, String>,
+    pub max_duration_minutes: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ScanTemplate {
+    /// Scan request for running this template against `request.targets`
+    pub fn scan_request(&self, request: CreateScanFromTemplateRequest) -> CreateScanRequest {
+        CreateScanRequest {
+            name: request.name.unwrap_or_else(|| self.name.clone()),
+            description: self.description.clone(),
+            targets: request.targets,
+            modules: self.modules.clone(),
+            scheduled_at: request.scheduled_at,
+            priority: self.priority,
+            tags: Some(self.tags.clone()),
+            metadata: Some(self.metadata.clone()),
+            max_duration_minutes: self.max_duration_minutes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateScanTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub modules: Vec<CreateScanModuleRequest>,
+    pub priority: Option<i32>,
+    pub tags: Option<Vec<String>>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub max_duration_minutes: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateScanTemplateRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub modules: Option<Vec<CreateScanModuleRequest>>,
+    pub priority: Option<i32>,
+    pub tags: Option<Vec<String>>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub max_duration_minutes: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateScanFromTemplateRequest {
+    /// Defaults to the template's name
+    pub name: Option<String>,
+    pub targets: Vec<CreateScanTargetRequest>,
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
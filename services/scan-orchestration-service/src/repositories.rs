@@ -1,9 +1,37 @@
 //! Database repositories for scan orchestration service
 
+use crate::config::DatabaseConfig;
+use crate::models::{CreateScanModuleRequest, ScanTemplate};
+use chrono::{DateTime, Utc};
+use mirage_common::client::ServiceClient;
+use mirage_common::models::Module;
+use mirage_common::{database, Error};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{Pool, Postgres};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+pub type DbPool = Pool<Postgres>;
+
+/// Create PostgreSQL database connection pool
+pub async fn create_db_pool(config: &DatabaseConfig) -> mirage_common::Result<DbPool> {
+    let pool = database::create_pool(config).await?;
+
+    run_migrations(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Applies the migrations in `migrations/` that haven't run yet. They are
+/// embedded at build time, so a deployment needs no files besides the binary.
+pub async fn run_migrations(pool: &DbPool) -> mirage_common::Result<()> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .map_err(|e| Error::Database(format!("Migration failed: {}", e)))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScanRecord {
     pub id: Uuid,
@@ -63,3 +91,197 @@ impl ScanRepository {
         Ok(())
     }
 }
+
+/// Read access to the module registry, which owns module metadata
+pub struct ModuleRepository {
+    registry: ServiceClient,
+}
+
+impl ModuleRepository {
+    pub fn new(registry_url: &str) -> mirage_common::Result<Self> {
+        Ok(Self {
+            registry: ServiceClient::new(registry_url)?,
+        })
+    }
+
+    /// `None` if the registry has no module with that ID
+    pub async fn get_module_info(&self, id: &Uuid) -> mirage_common::Result<Option<Module>> {
+        match self
+            .registry
+            .get_json(&format!("/api/v1/modules/{}", id))
+            .await
+        {
+            Ok(module) => Ok(Some(module)),
+            Err(Error::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ScanTemplateRow {
+    id: Uuid,
+    name: String,
+    description: Option<String>,
+    modules: Json<Vec<CreateScanModuleRequest>>,
+    priority: Option<i32>,
+    tags: Vec<String>,
+    metadata: Json<HashMap<String, String>>,
+    max_duration_minutes: Option<i32>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ScanTemplateRow> for ScanTemplate {
+    fn from(row: ScanTemplateRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            modules: row.modules.0,
+            priority: row.priority,
+            tags: row.tags,
+            metadata: row.metadata.0,
+            max_duration_minutes: row.max_duration_minutes,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, description, modules, priority, tags, metadata, \
+     max_duration_minutes, created_at, updated_at";
+
+pub struct ScanTemplateRepository {
+    pool: DbPool,
+}
+
+impl ScanTemplateRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, template: &ScanTemplate) -> mirage_common::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO scan_templates (id, name, description, modules, priority, tags, metadata,
+                                        max_duration_minutes, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .bind(template.id)
+        .bind(&template.name)
+        .bind(&template.description)
+        .bind(Json(&template.modules))
+        .bind(template.priority)
+        .bind(&template.tags)
+        .bind(Json(&template.metadata))
+        .bind(template.max_duration_minutes)
+        .bind(template.created_at)
+        .bind(template.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to create scan template: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> mirage_common::Result<Option<ScanTemplate>> {
+        let row: Option<ScanTemplateRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM scan_templates WHERE id = $1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch scan template: {}", e)))?;
+
+        Ok(row.map(ScanTemplate::from))
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> mirage_common::Result<Option<ScanTemplate>> {
+        let row: Option<ScanTemplateRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM scan_templates WHERE name = $1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch scan template: {}", e)))?;
+
+        Ok(row.map(ScanTemplate::from))
+    }
+
+    pub async fn find_all(&self) -> mirage_common::Result<Vec<ScanTemplate>> {
+        let rows: Vec<ScanTemplateRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM scan_templates ORDER BY name",
+            TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch scan templates: {}", e)))?;
+
+        Ok(rows.into_iter().map(ScanTemplate::from).collect())
+    }
+
+    pub async fn update(&self, template: &ScanTemplate) -> mirage_common::Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE scan_templates
+            SET name = $2, description = $3, modules = $4, priority = $5, tags = $6,
+                metadata = $7, max_duration_minutes = $8, updated_at = $9
+            WHERE id = $1
+            "#,
+        )
+        .bind(template.id)
+        .bind(&template.name)
+        .bind(&template.description)
+        .bind(Json(&template.modules))
+        .bind(template.priority)
+        .bind(&template.tags)
+        .bind(Json(&template.metadata))
+        .bind(template.max_duration_minutes)
+        .bind(template.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to update scan template: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Whether a template was deleted
+    pub async fn delete(&self, id: Uuid) -> mirage_common::Result<bool> {
+        let result = sqlx::query("DELETE FROM scan_templates WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to delete scan template: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `sqlx::test` runs this against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_migrations_create_tables(pool: DbPool) {
+        run_migrations(&pool).await.unwrap();
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT table_name::text FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert!(tables.iter().any(|t| t == "scans"), "{:?}", tables);
+        assert!(tables.iter().any(|t| t == "scan_templates"), "{:?}", tables);
+
+        // Already applied migrations are skipped on the next start
+        run_migrations(&pool).await.unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+//! Scan templates: named presets of modules and scan defaults
+//!
+//! A template only records module IDs. Modules can be removed from the
+//! registry after a template was saved, so they are looked up again each
+//! time a scan is created from it.
+
+use crate::models::{
+    CreateScanFromTemplateRequest, CreateScanModuleRequest, CreateScanRequest,
+    CreateScanTemplateRequest, ScanTemplate, UpdateScanTemplateRequest,
+};
+use crate::repositories::{ModuleRepository, ScanTemplateRepository};
+use chrono::Utc;
+use mirage_common::{Error, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct TemplateService {
+    template_repo: Arc<ScanTemplateRepository>,
+    module_repo: Arc<ModuleRepository>,
+}
+
+impl TemplateService {
+    pub fn new(template_repo: ScanTemplateRepository, module_repo: ModuleRepository) -> Self {
+        Self {
+            template_repo: Arc::new(template_repo),
+            module_repo: Arc::new(module_repo),
+        }
+    }
+
+    pub async fn create_template(
+        &self,
+        request: CreateScanTemplateRequest,
+    ) -> Result<ScanTemplate> {
+        validate(&request.name, &request.modules)?;
+
+        if self
+            .template_repo
+            .find_by_name(&request.name)
+            .await?
+            .is_some()
+        {
+            return Err(Error::Conflict(format!(
+                "Scan template '{}' already exists",
+                request.name
+            )));
+        }
+
+        self.ensure_modules_exist(&request.modules).await?;
+
+        let now = Utc::now();
+        let template = ScanTemplate {
+            id: Uuid::new_v4(),
+            name: request.name,
+            description: request.description,
+            modules: request.modules,
+            priority: request.priority,
+            tags: request.tags.unwrap_or_default(),
+            metadata: request.metadata.unwrap_or_default(),
+            max_duration_minutes: request.max_duration_minutes,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.template_repo.create(&template).await?;
+
+        Ok(template)
+    }
+
+    pub async fn get_template(&self, id: Uuid) -> Result<ScanTemplate> {
+        self.template_repo
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Scan template with ID {} not found", id)))
+    }
+
+    pub async fn list_templates(&self) -> Result<Vec<ScanTemplate>> {
+        self.template_repo.find_all().await
+    }
+
+    pub async fn update_template(
+        &self,
+        id: Uuid,
+        request: UpdateScanTemplateRequest,
+    ) -> Result<ScanTemplate> {
+        let mut template = self.get_template(id).await?;
+
+        if let Some(name) = request.name {
+            if name != template.name && self.template_repo.find_by_name(&name).await?.is_some() {
+                return Err(Error::Conflict(format!(
+                    "Scan template '{}' already exists",
+                    name
+                )));
+            }
+            template.name = name;
+        }
+
+        if let Some(modules) = request.modules {
+            validate(&template.name, &modules)?;
+            self.ensure_modules_exist(&modules).await?;
+            template.modules = modules;
+        }
+
+        if let Some(description) = request.description {
+            template.description = Some(description);
+        }
+
+        if let Some(priority) = request.priority {
+            template.priority = Some(priority);
+        }
+
+        if let Some(tags) = request.tags {
+            template.tags = tags;
+        }
+
+        if let Some(metadata) = request.metadata {
+            template.metadata = metadata;
+        }
+
+        if let Some(max_duration) = request.max_duration_minutes {
+            template.max_duration_minutes = Some(max_duration);
+        }
+
+        template.updated_at = Utc::now();
+
+        self.template_repo.update(&template).await?;
+
+        Ok(template)
+    }
+
+    pub async fn delete_template(&self, id: Uuid) -> Result<()> {
+        if !self.template_repo.delete(id).await? {
+            return Err(Error::NotFound(format!(
+                "Scan template with ID {} not found",
+                id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Scan request for running template `id` against the given targets.
+    /// Fails with `Error::Conflict` if any of its modules has since been
+    /// removed from the registry.
+    pub async fn scan_request(
+        &self,
+        id: Uuid,
+        request: CreateScanFromTemplateRequest,
+    ) -> Result<CreateScanRequest> {
+        if request.targets.is_empty() {
+            return Err(Error::Validation(
+                "At least one target must be specified".into(),
+            ));
+        }
+
+        let template = self.get_template(id).await?;
+        let missing = self.missing_modules(&template.modules).await?;
+        if !missing.is_empty() {
+            return Err(Error::Conflict(format!(
+                "Scan template '{}' uses modules that are no longer in the registry: {}",
+                template.name,
+                missing.join(", ")
+            )));
+        }
+
+        Ok(template.scan_request(request))
+    }
+
+    async fn ensure_modules_exist(&self, modules: &[CreateScanModuleRequest]) -> Result<()> {
+        let missing = self.missing_modules(modules).await?;
+        if !missing.is_empty() {
+            return Err(Error::Validation(format!(
+                "Unknown modules: {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// IDs of the modules the registry doesn't know
+    async fn missing_modules(&self, modules: &[CreateScanModuleRequest]) -> Result<Vec<String>> {
+        let mut missing = Vec::new();
+        for module in modules {
+            if self
+                .module_repo
+                .get_module_info(&module.module_id)
+                .await?
+                .is_none()
+            {
+                missing.push(module.module_id.to_string());
+            }
+        }
+
+        Ok(missing)
+    }
+}
+
+fn validate(name: &str, modules: &[CreateScanModuleRequest]) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::Validation("Template name must not be empty".into()));
+    }
+
+    if modules.is_empty() {
+        return Err(Error::Validation(
+            "At least one module must be specified".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateScanTargetRequest;
+    use crate::repositories::DbPool;
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+
+    type Registered = web::Data<Mutex<HashSet<Uuid>>>;
+
+    async fn module(registered: Registered, id: web::Path<Uuid>) -> HttpResponse {
+        let id = id.into_inner();
+        if !registered.lock().unwrap().contains(&id) {
+            return HttpResponse::NotFound().body("module not found");
+        }
+        HttpResponse::Ok().json(serde_json::json!({
+            "id": id,
+            "name": "dns-resolver",
+            "version": "1.0.0",
+            "description": "",
+            "author": "",
+            "dependencies": [],
+            "capabilities": [],
+            "configuration": {}
+        }))
+    }
+
+    /// Stand-in for the module registry serving the modules in `registered`
+    fn mock_registry(registered: Registered) -> (String, ServerHandle) {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(registered.clone())
+                .route("/api/v1/modules/{id}", web::get().to(module))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        (url, handle)
+    }
+
+    fn template_request(module_id: Uuid) -> CreateScanTemplateRequest {
+        CreateScanTemplateRequest {
+            name: "Domain recon".to_string(),
+            description: Some("DNS and WHOIS for a domain".to_string()),
+            modules: vec![CreateScanModuleRequest {
+                module_id,
+                order: Some(1),
+                parameters: Some(HashMap::from([(
+                    "record_types".to_string(),
+                    serde_json::json!(["A", "MX"]),
+                )])),
+            }],
+            priority: Some(8),
+            tags: Some(vec!["recon".to_string()]),
+            metadata: None,
+            max_duration_minutes: Some(30),
+        }
+    }
+
+    fn targets(value: &str) -> CreateScanFromTemplateRequest {
+        CreateScanFromTemplateRequest {
+            name: None,
+            targets: vec![CreateScanTargetRequest {
+                target_type: "domain".to_string(),
+                value: value.to_string(),
+            }],
+            scheduled_at: None,
+        }
+    }
+
+    fn service(pool: DbPool, registry_url: &str) -> TemplateService {
+        TemplateService::new(
+            ScanTemplateRepository::new(pool),
+            ModuleRepository::new(registry_url).unwrap(),
+        )
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_scan_from_template_uses_its_modules_and_defaults(pool: DbPool) {
+        let module_id = Uuid::new_v4();
+        let registered = web::Data::new(Mutex::new(HashSet::from([module_id])));
+        let (url, server) = mock_registry(registered);
+        let templates = service(pool, &url);
+
+        let template = templates
+            .create_template(template_request(module_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            templates.get_template(template.id).await.unwrap().modules[0].module_id,
+            module_id
+        );
+        assert!(matches!(
+            templates.create_template(template_request(module_id)).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let scan = templates
+            .scan_request(template.id, targets("example.com"))
+            .await
+            .unwrap();
+        assert_eq!(scan.name, "Domain recon");
+        assert_eq!(scan.targets[0].value, "example.com");
+        assert_eq!(scan.modules.len(), 1);
+        assert_eq!(scan.modules[0].module_id, module_id);
+        assert_eq!(
+            scan.modules[0].parameters.as_ref().unwrap()["record_types"],
+            serde_json::json!(["A", "MX"])
+        );
+        assert_eq!(scan.priority, Some(8));
+        assert_eq!(scan.tags, Some(vec!["recon".to_string()]));
+        assert_eq!(scan.max_duration_minutes, Some(30));
+
+        templates.delete_template(template.id).await.unwrap();
+        assert!(matches!(
+            templates
+                .scan_request(template.id, targets("example.com"))
+                .await,
+            Err(Error::NotFound(_))
+        ));
+
+        server.stop(false).await;
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_scan_from_template_fails_once_a_module_is_gone(pool: DbPool) {
+        let module_id = Uuid::new_v4();
+        let registered = web::Data::new(Mutex::new(HashSet::from([module_id])));
+        let (url, server) = mock_registry(registered.clone());
+        let templates = service(pool, &url);
+
+        assert!(matches!(
+            templates
+                .create_template(template_request(Uuid::new_v4()))
+                .await,
+            Err(Error::Validation(_))
+        ));
+
+        let template = templates
+            .create_template(template_request(module_id))
+            .await
+            .unwrap();
+        registered.lock().unwrap().remove(&module_id);
+
+        match templates
+            .scan_request(template.id, targets("example.com"))
+            .await
+        {
+            Err(Error::Conflict(msg)) => assert!(msg.contains(&module_id.to_string()), "{}", msg),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+
+        server.stop(false).await;
+    }
+}
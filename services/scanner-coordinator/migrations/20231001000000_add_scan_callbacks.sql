@@ -0,0 +1,4 @@
+-- Per-scan lifecycle callbacks
+ALTER TABLE scans
+    ADD COLUMN callback_url TEXT,
+    ADD COLUMN callback_disabled BOOLEAN NOT NULL DEFAULT FALSE;
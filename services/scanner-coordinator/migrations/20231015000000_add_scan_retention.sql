@@ -0,0 +1,6 @@
+-- Per-scan retention overrides
+ALTER TABLE scans
+    ADD COLUMN retention_days INT,
+    ADD COLUMN legal_hold BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX idx_scans_completed_at ON scans(completed_at);
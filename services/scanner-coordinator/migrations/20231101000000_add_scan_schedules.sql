@@ -0,0 +1,15 @@
+-- Recurring runs of a scan on a cron schedule
+CREATE TABLE scan_schedules (
+    id UUID PRIMARY KEY,
+    scan_id UUID NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+    cron_expression TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    next_run_at TIMESTAMPTZ,
+    last_run_at TIMESTAMPTZ,
+    last_scan_id UUID,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_scan_schedules_scan_id ON scan_schedules(scan_id);
+CREATE INDEX idx_scan_schedules_next_run_at ON scan_schedules(next_run_at) WHERE enabled;
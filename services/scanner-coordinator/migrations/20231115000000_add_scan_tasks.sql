@@ -0,0 +1,12 @@
+-- Collection tasks dispatched for a scan's targets, polled for progress
+CREATE TABLE scan_tasks (
+    id UUID PRIMARY KEY,
+    scan_id UUID NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+    target_id UUID NOT NULL REFERENCES scan_targets(id) ON DELETE CASCADE,
+    module_id UUID NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_scan_tasks_scan_id ON scan_tasks(scan_id);
@@ -0,0 +1,7 @@
+-- What a module does when a prerequisite found nothing for a target
+ALTER TABLE scan_modules
+    ADD COLUMN prerequisite_policy VARCHAR(20) NOT NULL DEFAULT 'skip';
+
+-- Whether a completed task collected anything its dependents can use
+ALTER TABLE scan_tasks
+    ADD COLUMN has_output BOOLEAN;
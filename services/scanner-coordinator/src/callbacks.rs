@@ -0,0 +1,434 @@
+//! Scan lifecycle callbacks
+//!
+//! When a scan carries a `callback_url`, every lifecycle transition (started,
+//! completed, failed) is POSTed to it as JSON. The body is signed with
+//! HMAC-SHA256 using the configured secret and the hex digest is sent in the
+//! `X-Mirage-Signature` header as `sha256=<digest>`, so receivers can verify
+//! the payload came from the coordinator.
+//!
+//! Callback URLs must be `https` (or `http` when `allow_http` is set). Unless
+//! `allow_private_networks` is set, the host is resolved before every delivery
+//! and refused if any address it resolves to is internal, and the request is
+//! then made to exactly those addresses without following redirects, so a
+//! callback can't be pointed at services inside the network.
+
+use crate::config::CallbackConfig;
+use crate::error::{ScannerError, ScannerResult};
+use crate::models::{ScanStatus, ScanTarget, ScanTargetStatus};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use mirage_common::utils::ip::is_internal_addr;
+use reqwest::{redirect, Client};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::time;
+use url::{Host, Url};
+use uuid::Uuid;
+
+pub const SIGNATURE_HEADER: &str = "X-Mirage-Signature";
+pub const EVENT_HEADER: &str = "X-Mirage-Event";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanEvent {
+    Started,
+    Completed,
+    Failed,
+}
+
+impl ScanEvent {
+    pub fn for_status(status: &ScanStatus) -> Option<Self> {
+        match status {
+            ScanStatus::Running => Some(ScanEvent::Started),
+            ScanStatus::Completed => Some(ScanEvent::Completed),
+            ScanStatus::Failed => Some(ScanEvent::Failed),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ScanEvent::Started => "scan.started",
+            ScanEvent::Completed => "scan.completed",
+            ScanEvent::Failed => "scan.failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub total_targets: usize,
+    pub completed_targets: usize,
+    pub failed_targets: usize,
+    pub skipped_targets: usize,
+}
+
+impl ScanSummary {
+    pub fn from_targets(targets: &[ScanTarget]) -> Self {
+        let count =
+            |status: ScanTargetStatus| targets.iter().filter(|t| t.status == status).count();
+
+        Self {
+            total_targets: targets.len(),
+            completed_targets: count(ScanTargetStatus::Completed),
+            failed_targets: count(ScanTargetStatus::Failed),
+            skipped_targets: count(ScanTargetStatus::Skipped),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallbackPayload {
+    pub event: ScanEvent,
+    pub scan_id: Uuid,
+    pub status: ScanStatus,
+    pub summary: ScanSummary,
+    pub error_message: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Hex-encoded HMAC-SHA256 of `body` keyed with `secret`
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks `url` may receive callbacks, as far as can be told without
+/// resolving it
+pub fn validate_callback_url(url: &str, config: &CallbackConfig) -> ScannerResult<Url> {
+    let parsed = Url::parse(url)
+        .map_err(|_| ScannerError::Validation(format!("Invalid callback URL: {}", url)))?;
+
+    match parsed.scheme() {
+        "https" => {}
+        "http" if config.allow_http => {}
+        scheme => {
+            return Err(ScannerError::Validation(format!(
+                "Callback URL scheme '{}' is not allowed",
+                scheme
+            )))
+        }
+    }
+
+    let ip = match parsed.host() {
+        Some(Host::Domain(_)) => None,
+        Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+        None => {
+            return Err(ScannerError::Validation(format!(
+                "Callback URL has no host: {}",
+                url
+            )))
+        }
+    };
+    if let Some(ip) = ip {
+        check_address(ip, config)?;
+    }
+
+    Ok(parsed)
+}
+
+fn check_address(ip: IpAddr, config: &CallbackConfig) -> ScannerResult<()> {
+    if is_internal_addr(&ip) && !config.allow_private_networks {
+        return Err(ScannerError::Validation(format!(
+            "Callback URL resolves to internal address {}",
+            ip
+        )));
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct CallbackNotifier {
+    config: CallbackConfig,
+}
+
+impl CallbackNotifier {
+    pub fn new(config: CallbackConfig) -> Self {
+        if config.signing_secret.is_empty() {
+            tracing::warn!("Scan callback signing secret is not configured");
+        }
+
+        Self { config }
+    }
+
+    // Client sending to the addresses the callback host resolves to now,
+    // once they are checked. Resolving again on connect could give others.
+    async fn client_for(&self, url: &Url) -> ScannerResult<Client> {
+        let builder = Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(Duration::from_secs(self.config.timeout_seconds));
+
+        let builder = match url.host() {
+            Some(Host::Domain(domain)) => {
+                let port = url.port_or_known_default().unwrap_or(443);
+                let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|e| {
+                        ScannerError::Integration(format!(
+                            "Failed to resolve callback host {}: {}",
+                            domain, e
+                        ))
+                    })?
+                    .collect();
+                for addr in &addrs {
+                    check_address(addr.ip(), &self.config)?;
+                }
+                builder.resolve_to_addrs(domain, &addrs)
+            }
+            _ => builder,
+        };
+
+        builder
+            .build()
+            .map_err(|e| ScannerError::Internal(format!("Failed to build HTTP client: {}", e)))
+    }
+
+    /// Delivers the payload, retrying with exponential backoff. Returns an
+    /// error once every attempt has failed, or straight away if the URL may
+    /// not receive callbacks.
+    pub async fn deliver(&self, url: &str, payload: &CallbackPayload) -> ScannerResult<()> {
+        let url = validate_callback_url(url, &self.config)?;
+        let client = self.client_for(&url).await?;
+        let body = serde_json::to_vec(payload)?;
+        let signature = sign_payload(self.config.signing_secret.as_bytes(), &body);
+        let attempts = self.config.max_attempts.max(1);
+        let mut last_error = String::new();
+
+        for attempt in 1..=attempts {
+            let result = client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+                .header(EVENT_HEADER, payload.event.as_str())
+                .body(body.clone())
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => last_error = format!("endpoint returned {}", response.status()),
+                Err(e) => last_error = e.to_string(),
+            }
+
+            tracing::warn!(
+                "Callback for scan {} failed (attempt {}/{}): {}",
+                payload.scan_id,
+                attempt,
+                attempts,
+                last_error
+            );
+
+            if attempt < attempts {
+                let delay = self.config.retry_delay_ms * 2_u64.pow((attempt - 1).min(5));
+                time::sleep(Duration::from_millis(delay)).await;
+            }
+        }
+
+        Err(ScannerError::Integration(format!(
+            "Callback to {} failed after {} attempts: {}",
+            url, attempts, last_error
+        )))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    pub(crate) struct Received {
+        pub(crate) headers: String,
+        pub(crate) body: Vec<u8>,
+    }
+
+    // Minimal HTTP endpoint that answers every request with `status`
+    pub(crate) async fn mock_endpoint(status: u16) -> (String, mpsc::UnboundedReceiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let (headers, body_start, length) = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+                        let length = headers
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        break (headers, pos + 4, length);
+                    }
+                };
+                while buf.len() < body_start + length {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+
+                let _ = tx.send(Received {
+                    headers,
+                    body: buf[body_start..body_start + length].to_vec(),
+                });
+                let response = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    // Settings for delivering to a mock endpoint on this host
+    pub(crate) fn local_config() -> CallbackConfig {
+        CallbackConfig {
+            signing_secret: "hook-secret".to_string(),
+            max_attempts: 3,
+            retry_delay_ms: 1,
+            timeout_seconds: 5,
+            allow_http: true,
+            allow_private_networks: true,
+        }
+    }
+
+    fn notifier() -> CallbackNotifier {
+        CallbackNotifier::new(local_config())
+    }
+
+    fn completion_payload() -> CallbackPayload {
+        CallbackPayload {
+            event: ScanEvent::Completed,
+            scan_id: Uuid::new_v4(),
+            status: ScanStatus::Completed,
+            summary: ScanSummary {
+                total_targets: 3,
+                completed_targets: 2,
+                failed_targets: 1,
+                skipped_targets: 0,
+            },
+            error_message: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_callback_is_signed() {
+        let (url, mut received) = mock_endpoint(200).await;
+        let payload = completion_payload();
+
+        notifier().deliver(&url, &payload).await.unwrap();
+
+        let request = received.recv().await.unwrap();
+        let expected = format!(
+            "x-mirage-signature: sha256={}",
+            sign_payload(b"hook-secret", &request.body)
+        );
+        assert!(request.headers.contains(&expected));
+        assert!(request.headers.contains("x-mirage-event: scan.completed"));
+
+        let body: CallbackPayload = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.scan_id, payload.scan_id);
+        assert_eq!(body.status, ScanStatus::Completed);
+        assert_eq!(body.summary, payload.summary);
+    }
+
+    #[tokio::test]
+    async fn test_failing_endpoint_is_retried_then_reported() {
+        let (url, mut received) = mock_endpoint(500).await;
+
+        let result = notifier().deliver(&url, &completion_payload()).await;
+        assert!(matches!(result, Err(ScannerError::Integration(_))));
+
+        for _ in 0..3 {
+            assert!(received.recv().await.is_some());
+        }
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_callback_url_must_be_https_and_public() {
+        let config = CallbackConfig::default();
+        let allowed =
+            |url: &str, config: &CallbackConfig| validate_callback_url(url, config).is_ok();
+
+        assert!(allowed("https://hooks.example.com/scan", &config));
+        assert!(!allowed("http://hooks.example.com/scan", &config));
+        assert!(!allowed("ftp://hooks.example.com/scan", &config));
+        assert!(!allowed("not a url", &config));
+        assert!(!allowed("https://127.0.0.1/hook", &config));
+        assert!(!allowed(
+            "https://169.254.169.254/latest/meta-data",
+            &config
+        ));
+        assert!(!allowed("https://10.0.0.8/hook", &config));
+        assert!(!allowed("https://[::1]/hook", &config));
+        assert!(!allowed("https://[::ffff:192.168.0.1]/hook", &config));
+
+        let config = CallbackConfig {
+            allow_http: true,
+            ..CallbackConfig::default()
+        };
+        assert!(allowed("http://hooks.example.com/scan", &config));
+        assert!(!allowed("http://169.254.169.254/latest/meta-data", &config));
+
+        let config = CallbackConfig {
+            allow_private_networks: true,
+            ..CallbackConfig::default()
+        };
+        assert!(allowed("https://10.0.0.8/hook", &config));
+    }
+
+    #[tokio::test]
+    async fn test_host_resolving_to_internal_address_is_refused() {
+        let (url, mut received) = mock_endpoint(200).await;
+        let url = url.replace("127.0.0.1", "localhost");
+        let notifier = CallbackNotifier::new(CallbackConfig {
+            allow_private_networks: false,
+            ..local_config()
+        });
+
+        let result = notifier.deliver(&url, &completion_payload()).await;
+        assert!(matches!(result, Err(ScannerError::Validation(_))));
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_not_followed() {
+        let (target, mut redirected) = mock_endpoint(200).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut chunk = [0u8; 4096];
+                let _ = socket.read(&mut chunk).await;
+                let response = format!(
+                    "HTTP/1.1 307 X\r\nlocation: {}\r\ncontent-length: 0\r\n\r\n",
+                    target
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let result = notifier().deliver(&url, &completion_payload()).await;
+        assert!(matches!(result, Err(ScannerError::Integration(_))));
+        assert!(redirected.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_sign_payload_matches_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
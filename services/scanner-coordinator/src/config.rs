@@ -2,18 +2,14 @@ use config::{Config, ConfigError, File};
 use serde::Deserialize;
 use std::env;
 
+pub use mirage_common::config::DatabaseConfig;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
     pub host: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct DatabaseConfig {
-    pub url: String,
-    pub max_connections: u32,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct RedisConfig {
     pub uri: String,
@@ -35,6 +31,80 @@ pub struct SchedulerConfig {
     pub max_retries: u32,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallbackConfig {
+    /// Key for the HMAC-SHA256 signature on callback payloads
+    #[serde(default)]
+    pub signing_secret: String,
+    #[serde(default = "default_callback_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_callback_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    #[serde(default = "default_callback_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Also accept plain `http://` callback URLs, e.g. in development
+    #[serde(default)]
+    pub allow_http: bool,
+    /// Also deliver to private, loopback and link-local addresses. Off by
+    /// default, so a callback URL can't be used to reach internal services.
+    #[serde(default)]
+    pub allow_private_networks: bool,
+}
+
+fn default_callback_max_attempts() -> u32 {
+    5
+}
+
+fn default_callback_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_callback_timeout_seconds() -> u64 {
+    10
+}
+
+impl Default for CallbackConfig {
+    fn default() -> Self {
+        Self {
+            signing_secret: String::new(),
+            max_attempts: default_callback_max_attempts(),
+            retry_delay_ms: default_callback_retry_delay_ms(),
+            timeout_seconds: default_callback_timeout_seconds(),
+            allow_http: false,
+            allow_private_networks: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Days finished scans are kept unless the scan overrides it
+    #[serde(default = "default_retention_days")]
+    pub default_days: u32,
+    #[serde(default = "default_sweep_interval_seconds")]
+    pub sweep_interval_seconds: u64,
+}
+
+fn default_retention_days() -> u32 {
+    90
+}
+
+fn default_sweep_interval_seconds() -> u64 {
+    3600
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_days: default_retention_days(),
+            sweep_interval_seconds: default_sweep_interval_seconds(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
@@ -45,6 +115,10 @@ pub struct AppConfig {
     pub scan_orchestration: ServiceConfig,
     pub data_collection: ServiceConfig,
     pub data_storage: ServiceConfig,
+    #[serde(default)]
+    pub callbacks: CallbackConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
 }
 
 pub fn load_config() -> Result<AppConfig, ConfigError> {
@@ -0,0 +1,346 @@
+//! Module execution order
+//!
+//! Modules can need the output of others in the same scan, e.g. web scraping
+//! needs the hosts DNS enumeration found. The dependencies form a DAG that is
+//! split into layers: modules without dependencies make up the first layer
+//! and run in parallel, and every later module only depends on modules in
+//! earlier layers. For each target, a module is dispatched once all of its
+//! prerequisites have finished for that target. A prerequisite that failed
+//! or found nothing leaves its dependents without input: they are skipped,
+//! or run anyway if their prerequisite policy says so.
+
+use crate::error::{ScannerError, ScannerResult};
+use crate::models::{PrerequisitePolicy, ScanModule, ScanModuleStatus, ScanTask, ScanTaskStatus};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// How a module's run on a target went, as far as its dependents care
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModuleOutcome {
+    /// Dispatched and not finished yet
+    Running,
+    Output,
+    /// Failed, skipped or found nothing
+    NoOutput,
+}
+
+impl ModuleOutcome {
+    pub fn of(task: &ScanTask) -> Self {
+        match task.status {
+            ScanTaskStatus::Pending | ScanTaskStatus::Running => ModuleOutcome::Running,
+            ScanTaskStatus::Completed if task.has_output == Some(true) => ModuleOutcome::Output,
+            _ => ModuleOutcome::NoOutput,
+        }
+    }
+}
+
+/// What to do next with a module on a target
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Run(Uuid),
+    Skip(Uuid),
+}
+
+#[derive(Debug, Clone)]
+struct PlannedModule {
+    module_id: Uuid,
+    depends_on: Vec<Uuid>,
+    policy: PrerequisitePolicy,
+}
+
+/// A scan's enabled modules in topological layers
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+    layers: Vec<Vec<PlannedModule>>,
+}
+
+impl ExecutionPlan {
+    /// Plans the enabled modules, keeping their order within a layer.
+    /// Dependencies on modules that won't run, e.g. disabled ones, are
+    /// ignored; a cycle is a validation error.
+    pub fn new(modules: &[ScanModule]) -> ScannerResult<Self> {
+        let enabled: Vec<&ScanModule> = modules
+            .iter()
+            .filter(|m| m.status == ScanModuleStatus::Enabled)
+            .collect();
+        let ids: HashSet<Uuid> = enabled.iter().map(|m| m.module_id).collect();
+
+        let mut remaining: Vec<PlannedModule> = enabled
+            .iter()
+            .map(|m| PlannedModule {
+                module_id: m.module_id,
+                depends_on: m
+                    .depends_on
+                    .iter()
+                    .copied()
+                    .filter(|id| ids.contains(id))
+                    .collect(),
+                policy: m.prerequisite_policy,
+            })
+            .collect();
+
+        let mut placed = HashSet::new();
+        let mut layers = Vec::new();
+        while !remaining.is_empty() {
+            let (layer, rest): (Vec<_>, Vec<_>) = remaining
+                .into_iter()
+                .partition(|m| m.depends_on.iter().all(|id| placed.contains(id)));
+
+            if layer.is_empty() {
+                let cycle: Vec<String> = rest.iter().map(|m| m.module_id.to_string()).collect();
+                return Err(ScannerError::Validation(format!(
+                    "Module dependencies form a cycle between {}",
+                    cycle.join(", ")
+                )));
+            }
+
+            placed.extend(layer.iter().map(|m| m.module_id));
+            layers.push(layer);
+            remaining = rest;
+        }
+
+        Ok(Self { layers })
+    }
+
+    /// Module IDs, layer by layer
+    pub fn layers(&self) -> Vec<Vec<Uuid>> {
+        self.layers
+            .iter()
+            .map(|layer| layer.iter().map(|m| m.module_id).collect())
+            .collect()
+    }
+
+    pub fn module_count(&self) -> usize {
+        self.layers.iter().map(Vec::len).sum()
+    }
+
+    /// The modules of a target whose prerequisites have all finished, given
+    /// the outcomes of those already dispatched or skipped. Skips cascade:
+    /// a skipped module leaves its own dependents without input.
+    pub fn next_steps(&self, outcomes: &HashMap<Uuid, ModuleOutcome>) -> Vec<Step> {
+        let mut outcomes = outcomes.clone();
+        let mut steps = Vec::new();
+
+        // Layer order settles every prerequisite before its dependents
+        for module in self.layers.iter().flatten() {
+            if outcomes.contains_key(&module.module_id) {
+                continue;
+            }
+
+            let prerequisites: Option<Vec<ModuleOutcome>> = module
+                .depends_on
+                .iter()
+                .map(|id| outcomes.get(id).copied())
+                .collect();
+            let prerequisites = match prerequisites {
+                Some(prerequisites) if !prerequisites.contains(&ModuleOutcome::Running) => {
+                    prerequisites
+                }
+                _ => continue,
+            };
+
+            if prerequisites.contains(&ModuleOutcome::NoOutput)
+                && module.policy == PrerequisitePolicy::Skip
+            {
+                outcomes.insert(module.module_id, ModuleOutcome::NoOutput);
+                steps.push(Step::Skip(module.module_id));
+            } else {
+                outcomes.insert(module.module_id, ModuleOutcome::Running);
+                steps.push(Step::Run(module.module_id));
+            }
+        }
+
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn module(depends_on: &[Uuid], policy: PrerequisitePolicy) -> ScanModule {
+        ScanModule {
+            id: Uuid::new_v4(),
+            scan_id: Uuid::nil(),
+            module_id: Uuid::new_v4(),
+            module_name: "module".into(),
+            module_version: "1.0.0".into(),
+            status: ScanModuleStatus::Enabled,
+            parameters: HashMap::new(),
+            priority: 1,
+            depends_on: depends_on.to_vec(),
+            prerequisite_policy: policy,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// dns and whois run first, web needs dns, and screenshot and report
+    /// need web (report needs whois too)
+    struct Dag {
+        dns: ScanModule,
+        whois: ScanModule,
+        web: ScanModule,
+        screenshot: ScanModule,
+        report: ScanModule,
+    }
+
+    impl Dag {
+        fn new(web_policy: PrerequisitePolicy) -> Self {
+            let dns = module(&[], PrerequisitePolicy::Skip);
+            let whois = module(&[], PrerequisitePolicy::Skip);
+            let web = module(&[dns.module_id], web_policy);
+            let screenshot = module(&[web.module_id], PrerequisitePolicy::Skip);
+            let report = module(&[web.module_id, whois.module_id], PrerequisitePolicy::Skip);
+            Self {
+                dns,
+                whois,
+                web,
+                screenshot,
+                report,
+            }
+        }
+
+        fn plan(&self) -> ExecutionPlan {
+            // Listed dependents first, so the order comes from the graph
+            ExecutionPlan::new(&[
+                self.report.clone(),
+                self.screenshot.clone(),
+                self.web.clone(),
+                self.whois.clone(),
+                self.dns.clone(),
+            ])
+            .unwrap()
+        }
+    }
+
+    fn outcomes(entries: &[(&ScanModule, ModuleOutcome)]) -> HashMap<Uuid, ModuleOutcome> {
+        entries.iter().map(|(m, o)| (m.module_id, *o)).collect()
+    }
+
+    #[test]
+    fn test_plan_layers_follow_dependencies() {
+        let dag = Dag::new(PrerequisitePolicy::Skip);
+        let plan = dag.plan();
+
+        assert_eq!(
+            plan.layers(),
+            vec![
+                vec![dag.whois.module_id, dag.dns.module_id],
+                vec![dag.web.module_id],
+                vec![dag.report.module_id, dag.screenshot.module_id],
+            ]
+        );
+        assert_eq!(plan.module_count(), 5);
+    }
+
+    #[test]
+    fn test_dependents_wait_for_their_prerequisites() {
+        let dag = Dag::new(PrerequisitePolicy::Skip);
+        let plan = dag.plan();
+        use ModuleOutcome::*;
+
+        // Independent modules start together
+        assert_eq!(
+            plan.next_steps(&HashMap::new()),
+            vec![Step::Run(dag.whois.module_id), Step::Run(dag.dns.module_id)]
+        );
+        assert!(plan
+            .next_steps(&outcomes(&[(&dag.dns, Running), (&dag.whois, Running)]))
+            .is_empty());
+
+        // web only needs dns; report still waits on web
+        assert_eq!(
+            plan.next_steps(&outcomes(&[(&dag.dns, Output), (&dag.whois, Running)])),
+            vec![Step::Run(dag.web.module_id)]
+        );
+        assert!(plan
+            .next_steps(&outcomes(&[
+                (&dag.dns, Output),
+                (&dag.whois, Output),
+                (&dag.web, Running),
+            ]))
+            .is_empty());
+
+        assert_eq!(
+            plan.next_steps(&outcomes(&[
+                (&dag.dns, Output),
+                (&dag.whois, Output),
+                (&dag.web, Output),
+            ])),
+            vec![
+                Step::Run(dag.report.module_id),
+                Step::Run(dag.screenshot.module_id)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_output_skips_dependents() {
+        let dag = Dag::new(PrerequisitePolicy::Skip);
+        let plan = dag.plan();
+
+        // Nothing from dns skips web, and with it everything downstream
+        assert_eq!(
+            plan.next_steps(&outcomes(&[
+                (&dag.dns, ModuleOutcome::NoOutput),
+                (&dag.whois, ModuleOutcome::Output),
+            ])),
+            vec![
+                Step::Skip(dag.web.module_id),
+                Step::Skip(dag.report.module_id),
+                Step::Skip(dag.screenshot.module_id),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_policy_runs_without_input() {
+        let dag = Dag::new(PrerequisitePolicy::Run);
+        let plan = dag.plan();
+
+        assert_eq!(
+            plan.next_steps(&outcomes(&[
+                (&dag.dns, ModuleOutcome::NoOutput),
+                (&dag.whois, ModuleOutcome::Output),
+            ])),
+            vec![Step::Run(dag.web.module_id)]
+        );
+    }
+
+    #[test]
+    fn test_cycles_are_rejected() {
+        let mut a = module(&[], PrerequisitePolicy::Skip);
+        let b = module(&[a.module_id], PrerequisitePolicy::Skip);
+        a.depends_on = vec![b.module_id];
+        let root = module(&[], PrerequisitePolicy::Skip);
+
+        assert!(matches!(
+            ExecutionPlan::new(&[root, a, b]),
+            Err(ScannerError::Validation(_))
+        ));
+
+        let mut own = module(&[], PrerequisitePolicy::Skip);
+        own.depends_on = vec![own.module_id];
+        assert!(matches!(
+            ExecutionPlan::new(&[own]),
+            Err(ScannerError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_disabled_prerequisites_are_ignored() {
+        let mut dns = module(&[], PrerequisitePolicy::Skip);
+        dns.status = ScanModuleStatus::Disabled;
+        let web = module(&[dns.module_id], PrerequisitePolicy::Skip);
+
+        let plan = ExecutionPlan::new(&[dns, web.clone()]).unwrap();
+
+        assert_eq!(plan.layers(), vec![vec![web.module_id]]);
+        assert_eq!(
+            plan.next_steps(&HashMap::new()),
+            vec![Step::Run(web.module_id)]
+        );
+    }
+}
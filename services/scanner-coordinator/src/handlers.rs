@@ -3,8 +3,9 @@ use mirage_common::Error as CommonError;
 use uuid::Uuid;
 
 use crate::models::{
-    AddModuleRequest, AddTargetRequest, CreateScanRequest, ScanQueryParams, ScanStatus,
-    UpdateScanRequest,
+    AddModuleRequest, AddTargetRequest, CreateScanRequest, CreateScheduleRequest, ScanQueryParams,
+    ScanStatus, ScheduleQueryParams, UpdateRetentionRequest, UpdateScanRequest,
+    UpdateScheduleRequest,
 };
 use crate::services::ScannerService;
 
@@ -14,10 +15,17 @@ pub fn scanner_routes() -> actix_web::Scope {
         .service(get_scan)
         .service(list_scans)
         .service(update_scan)
+        .service(update_retention)
         .service(start_scan)
         .service(cancel_scan)
+        .service(get_scan_progress)
         .service(add_targets)
         .service(add_modules)
+        .service(create_schedule)
+        .service(list_schedules)
+        .service(get_schedule)
+        .service(update_schedule)
+        .service(delete_schedule)
 }
 
 #[post("/scans")]
@@ -126,6 +134,30 @@ async fn update_scan(
     Ok(HttpResponse::Ok().json(scan))
 }
 
+#[put("/scans/{id}/retention")]
+async fn update_retention(
+    id: web::Path<String>,
+    request: web::Json<UpdateRetentionRequest>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let scan_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid scan ID format"))?;
+
+    let scan = service
+        .update_retention(scan_id, request.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to update scan retention: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(scan))
+}
+
 #[post("/scans/{id}/start")]
 async fn start_scan(
     id: web::Path<String>,
@@ -157,6 +189,10 @@ async fn cancel_scan(
     let scan = service.cancel_scan(scan_id).await.map_err(|e| match e {
         CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
         CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+        CommonError::ExternalApi(_) => {
+            tracing::error!("Failed to cancel collection tasks: {}", e);
+            actix_web::error::ErrorBadGateway(e)
+        }
         _ => {
             tracing::error!("Failed to cancel scan: {}", e);
             actix_web::error::ErrorInternalServerError(e)
@@ -166,6 +202,28 @@ async fn cancel_scan(
     Ok(HttpResponse::Ok().json(scan))
 }
 
+#[get("/scans/{id}/progress")]
+async fn get_scan_progress(
+    id: web::Path<String>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let scan_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid scan ID format"))?;
+
+    let progress = service
+        .get_scan_progress(scan_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to get scan progress: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(progress))
+}
+
 #[post("/scans/{id}/targets")]
 async fn add_targets(
     id: web::Path<String>,
@@ -175,11 +233,19 @@ async fn add_targets(
     let scan_id = Uuid::parse_str(&id)
         .map_err(|_| actix_web::error::ErrorBadRequest("Invalid scan ID format"))?;
 
-    // This would call service.add_targets(), which we haven't implemented yet
-    // For now, return a not implemented error
-    Err(actix_web::error::ErrorNotImplemented(
-        "Adding targets to an existing scan is not yet implemented",
-    ))
+    let targets = service
+        .add_targets(scan_id, request.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to add targets: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Created().json(targets))
 }
 
 #[post("/scans/{id}/modules")]
@@ -197,3 +263,104 @@ async fn add_modules(
         "Adding modules to an existing scan is not yet implemented",
     ))
 }
+
+#[post("/schedules")]
+async fn create_schedule(
+    request: web::Json<CreateScheduleRequest>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let schedule = service
+        .create_schedule(request.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to create schedule: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Created().json(schedule))
+}
+
+#[get("/schedules")]
+async fn list_schedules(
+    query: web::Query<ScheduleQueryParams>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let schedules = service.list_schedules(query.scan_id).await.map_err(|e| {
+        tracing::error!("Failed to list schedules: {}", e);
+        actix_web::error::ErrorInternalServerError(e)
+    })?;
+
+    Ok(HttpResponse::Ok().json(schedules))
+}
+
+#[get("/schedules/{id}")]
+async fn get_schedule(
+    id: web::Path<String>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let schedule_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid schedule ID format"))?;
+
+    let schedule = service
+        .get_schedule(schedule_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to get schedule: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[put("/schedules/{id}")]
+async fn update_schedule(
+    id: web::Path<String>,
+    request: web::Json<UpdateScheduleRequest>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let schedule_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid schedule ID format"))?;
+
+    let schedule = service
+        .update_schedule(schedule_id, request.into_inner())
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to update schedule: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[delete("/schedules/{id}")]
+async fn delete_schedule(
+    id: web::Path<String>,
+    service: web::Data<ScannerService>,
+) -> Result<HttpResponse, Error> {
+    let schedule_id = Uuid::parse_str(&id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid schedule ID format"))?;
+
+    service
+        .delete_schedule(schedule_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to delete schedule: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
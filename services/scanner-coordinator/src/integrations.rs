@@ -1,6 +1,6 @@
 use crate::config::{AppConfig, ServiceConfig};
 use crate::error::{ScannerError, ScannerResult};
-use crate::models::{CreateTargetRequest, ScanTarget};
+use crate::models::{CreateTargetRequest, ScanTarget, ScanTaskStatus};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -33,7 +33,24 @@ struct CollectionTarget {
 #[derive(Debug, Serialize, Deserialize)]
 struct TaskResponse {
     id: Uuid,
-    status: String,
+    status: ScanTaskStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScanCancellation {
+    scan_id: Uuid,
+    cancelled_tasks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TaskOutput {
+    entities: Vec<serde_json::Value>,
+    relationships: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResolvedModule {
+    id: Uuid,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -168,6 +185,110 @@ impl IntegrationService {
         Ok(task_ids)
     }
 
+    /// Polls the status of a collection task
+    pub async fn get_collection_task_status(&self, task_id: Uuid) -> ScannerResult<ScanTaskStatus> {
+        let url = format!(
+            "{}/api/v1/collection/tasks/{}",
+            self.config.data_collection.url, task_id
+        );
+
+        let response = self.client.get(&url).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.text().await?;
+            return Err(ScannerError::Integration(format!(
+                "Failed to fetch collection task: {} - {}",
+                status, error
+            )));
+        }
+
+        let task_response: TaskResponse = response.json().await?;
+
+        Ok(task_response.status)
+    }
+
+    /// Whether a completed collection task found any entities or
+    /// relationships. A task without stored results found nothing.
+    pub async fn collection_task_has_output(&self, task_id: Uuid) -> ScannerResult<bool> {
+        let url = format!(
+            "{}/api/v1/collection/tasks/{}/results",
+            self.config.data_collection.url, task_id
+        );
+
+        let response = self.client.get(&url).send().await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.text().await?;
+            return Err(ScannerError::Integration(format!(
+                "Failed to fetch collection task results: {} - {}",
+                status, error
+            )));
+        }
+
+        let output: TaskOutput = response.json().await?;
+
+        Ok(!output.entities.is_empty() || !output.relationships.is_empty())
+    }
+
+    /// Has the data collection service cancel a scan's unfinished tasks and
+    /// refuse new ones for it. Returns the number of tasks cancelled.
+    pub async fn cancel_collection_tasks(&self, scan_id: Uuid) -> ScannerResult<u64> {
+        let url = format!(
+            "{}/api/v1/collection/scans/{}/cancel",
+            self.config.data_collection.url, scan_id
+        );
+
+        let response = self.client.post(&url).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.text().await?;
+            return Err(ScannerError::Integration(format!(
+                "Failed to cancel collection tasks: {} - {}",
+                status, error
+            )));
+        }
+
+        let cancellation: ScanCancellation = response.json().await?;
+
+        Ok(cancellation.cancelled_tasks)
+    }
+
+    /// IDs of every module a module depends on, directly or not, as the
+    /// module registry resolves them
+    pub async fn get_module_dependencies(&self, module_id: &Uuid) -> ScannerResult<Vec<Uuid>> {
+        let url = format!(
+            "{}/api/v1/modules/{}/resolve",
+            self.config.module_registry.url, module_id
+        );
+
+        let response = self.client.get(&url).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.text().await?;
+            return Err(ScannerError::Integration(format!(
+                "Failed to resolve module dependencies: {} - {}",
+                status, error
+            )));
+        }
+
+        // The resolved load order includes the module itself
+        let resolved: Vec<ResolvedModule> = response.json().await?;
+
+        Ok(resolved
+            .into_iter()
+            .map(|m| m.id)
+            .filter(|id| id != module_id)
+            .collect())
+    }
+
     pub async fn get_module_info(&self, module_id: &Uuid) -> ScannerResult<ModuleInfo> {
         // Fetch module info from module registry
         let url = format!(
@@ -1,23 +1,31 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::shutdown::{self, Shutdown};
+use mirage_common::telemetry;
 use tracing::info;
 
+mod callbacks;
 mod config;
 mod error;
+mod execution;
 mod handlers;
 mod integrations;
 mod models;
+mod progress;
 mod repositories;
+mod retention;
 mod scheduler;
+mod schedules;
 mod services;
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("scanner-coordinator");
 
     // Load configuration
     let config = match config::load_config() {
@@ -74,12 +82,14 @@ async fn main() -> std::io::Result<()> {
         redis_client.clone(),
         scan_repo.clone(),
         target_repo.clone(),
+        module_repo.clone(),
         integration_service.clone(),
+        callbacks::CallbackNotifier::new(config.callbacks.clone()),
         config.clone(),
     );
 
     let scanner_service = web::Data::new(services::ScannerService::new(
-        scan_repo,
+        scan_repo.clone(),
         target_repo,
         module_repo,
         scheduler_service.clone(),
@@ -87,10 +97,21 @@ async fn main() -> std::io::Result<()> {
         config.clone(),
     ));
 
+    // Stops the server, then the background tasks, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+
     // Start scheduler background task
     let scheduler_config = config.clone();
-    tokio::spawn(async move {
-        scheduler::run_scheduler(scheduler_service, scheduler_config).await;
+    let scheduler_shutdown = shutdown.subscribe();
+    let scheduler = tokio::spawn(async move {
+        scheduler::run_scheduler(scheduler_service, scheduler_config, scheduler_shutdown).await;
+    });
+
+    // Start retention sweeper background task
+    let retention_config = config.retention.clone();
+    let retention_shutdown = shutdown.subscribe();
+    let retention = tokio::spawn(async move {
+        retention::run_retention_sweeper(scan_repo, retention_config, retention_shutdown).await;
     });
 
     info!(
@@ -98,18 +119,53 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
-    HttpServer::new(move || {
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("scanner-coordinator", env!("CARGO_PKG_VERSION"))
+            .database("postgres", db_pool.clone())
+            .probe("redis", {
+                let client = redis_client.clone();
+                move || {
+                    let client = client.clone();
+                    async move { Ok(scheduler::ping_queue(client).await?) }
+                }
+            }),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics = match Metrics::new("scanner-coordinator") {
+        Ok(metrics) => web::Data::new(metrics),
+        Err(e) => {
+            tracing::error!("Failed to set up metrics: {}", e);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to set up metrics",
+            ));
+        }
+    };
+
+    let server = HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(scanner_service.clone())
             .app_data(web::Data::new(config.clone()))
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::scanner_routes()),
             )
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
     .bind(format!("0.0.0.0:{}", config.server.port))?
-    .run()
-    .await
+    .run();
+
+    shutdown.run(server, vec![scheduler, retention]).await
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -15,6 +15,16 @@ pub enum ScanStatus {
     Cancelled,
 }
 
+impl ScanStatus {
+    /// Whether the scan's targets may still be dispatched for collection
+    pub fn accepts_work(&self) -> bool {
+        matches!(
+            self,
+            ScanStatus::Created | ScanStatus::Queued | ScanStatus::Running
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scan {
     pub id: Uuid,
@@ -32,6 +42,39 @@ pub struct Scan {
     pub error_message: Option<String>,
     pub progress: Option<i32>,
     pub estimated_completion_time: Option<DateTime<Utc>>,
+    /// Endpoint notified of lifecycle transitions
+    pub callback_url: Option<String>,
+    /// Set once the callback endpoint has failed repeatedly
+    pub callback_disabled: bool,
+    /// Overrides the global result retention when set
+    pub retention_days: Option<i32>,
+    /// Exempts the scan from automatic deletion
+    pub legal_hold: bool,
+}
+
+impl Scan {
+    pub fn effective_retention_days(&self, default_days: u32) -> u32 {
+        self.retention_days
+            .map(|days| days.max(0) as u32)
+            .unwrap_or(default_days)
+    }
+
+    /// When the retention sweeper may delete the scan. `None` while the scan
+    /// is still active or under legal hold.
+    pub fn expires_at(&self, default_days: u32) -> Option<DateTime<Utc>> {
+        if self.legal_hold {
+            return None;
+        }
+
+        let finished_at = match self.status {
+            ScanStatus::Completed | ScanStatus::Failed | ScanStatus::Cancelled => {
+                self.completed_at.unwrap_or(self.updated_at)
+            }
+            _ => return None,
+        };
+
+        Some(finished_at + Duration::days(self.effective_retention_days(default_days) as i64))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -68,6 +111,17 @@ pub enum ScanModuleStatus {
     Failed,
 }
 
+/// What a module does for a target when one of its prerequisites failed or
+/// found nothing there
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum PrerequisitePolicy {
+    #[default]
+    Skip,
+    Run,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScanModule {
     pub id: Uuid,
@@ -78,7 +132,63 @@ pub struct ScanModule {
     pub status: ScanModuleStatus,
     pub parameters: HashMap<String, serde_json::Value>,
     pub priority: i32,
+    /// Module IDs of the scan's modules this one needs the output of
     pub depends_on: Vec<Uuid>,
+    pub prerequisite_policy: PrerequisitePolicy,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Status of a collection task, as reported by the data collection service
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum ScanTaskStatus {
+    Pending,
+    Running,
+    Completed,
+    #[serde(alias = "timeout")]
+    Failed,
+    Cancelled,
+    /// Never dispatched, as a prerequisite left it without input
+    Skipped,
+}
+
+impl ScanTaskStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, ScanTaskStatus::Pending | ScanTaskStatus::Running)
+    }
+}
+
+/// A collection task dispatched for one of a scan's targets against one of
+/// its modules. The ID is the task's ID in the data collection service,
+/// unless the task was skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanTask {
+    pub id: Uuid,
+    pub scan_id: Uuid,
+    pub target_id: Uuid,
+    pub module_id: Uuid,
+    pub status: ScanTaskStatus,
+    /// Whether the completed task collected anything
+    pub has_output: Option<bool>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Starts a new run of a scan each time its cron expression fires. The scan
+/// it points at is only the definition: every run is a copy of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanSchedule {
+    pub id: Uuid,
+    pub scan_id: Uuid,
+    pub cron_expression: String,
+    pub enabled: bool,
+    /// `None` once the expression has no further occurrences
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// The scan started by the most recent run
+    pub last_scan_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -93,6 +203,9 @@ pub struct CreateScanRequest {
     pub tags: Option<Vec<String>>,
     pub metadata: Option<HashMap<String, String>>,
     pub schedule: Option<ScheduleConfig>,
+    pub callback_url: Option<String>,
+    pub retention_days: Option<i32>,
+    pub legal_hold: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -107,7 +220,9 @@ pub struct ModuleRequest {
     pub module_id: Uuid,
     pub parameters: Option<HashMap<String, serde_json::Value>>,
     pub priority: Option<i32>,
+    /// Added to the dependencies the module registry knows of
     pub depends_on: Option<Vec<Uuid>>,
+    pub prerequisite_policy: Option<PrerequisitePolicy>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -115,6 +230,8 @@ pub struct ScheduleConfig {
     pub start_time: Option<DateTime<Utc>>,
     pub frequency: Option<String>, // "once", "hourly", "daily", "weekly", etc.
     pub frequency_options: Option<HashMap<String, String>>,
+    /// Runs the scan on a recurring schedule, see `ScanSchedule`
+    pub cron: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -149,6 +266,12 @@ pub struct ScanDetailResponse {
     pub error_message: Option<String>,
     pub progress: Option<i32>,
     pub estimated_completion_time: Option<DateTime<Utc>>,
+    pub callback_url: Option<String>,
+    pub callback_disabled: bool,
+    pub retention_days: Option<i32>,
+    pub legal_hold: bool,
+    pub effective_retention_days: u32,
+    pub expires_at: Option<DateTime<Utc>>,
     pub targets: Vec<ScanTargetResponse>,
     pub modules: Vec<ScanModuleResponse>,
 }
@@ -176,6 +299,32 @@ pub struct ScanModuleResponse {
     pub status: ScanModuleStatus,
     pub priority: i32,
     pub depends_on: Vec<Uuid>,
+    pub prerequisite_policy: PrerequisitePolicy,
+}
+
+/// Returned by `GET /scans/{id}/progress`. Counts are collection tasks: one
+/// per target and enabled module.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScanProgress {
+    pub scan_id: Uuid,
+    /// Every task planned so far, which grows as targets are added
+    pub total: u64,
+    pub completed: u64,
+    pub failed: u64,
+    pub cancelled: u64,
+    pub skipped: u64,
+    /// Dispatched and not yet finished
+    pub in_flight: u64,
+    /// Planned, and waiting for their target or their prerequisites
+    pub pending: u64,
+    /// Share of the total that has finished, whatever the outcome
+    pub percentage: u8,
+}
+
+impl ScanProgress {
+    pub fn is_finished(&self) -> bool {
+        self.total > 0 && self.in_flight == 0 && self.pending == 0
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -199,6 +348,33 @@ pub struct UpdateScanRequest {
     pub metadata: Option<HashMap<String, String>>,
 }
 
+/// Replaces a scan's retention settings; omitting `retention_days` falls back
+/// to the global default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateRetentionRequest {
+    pub retention_days: Option<i32>,
+    #[serde(default)]
+    pub legal_hold: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateScheduleRequest {
+    pub scan_id: Uuid,
+    pub cron_expression: String,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateScheduleRequest {
+    pub cron_expression: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleQueryParams {
+    pub scan_id: Option<Uuid>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddTargetRequest {
     pub targets: Vec<CreateTargetRequest>,
@@ -208,3 +384,24 @@ pub struct AddTargetRequest {
 pub struct AddModuleRequest {
     pub modules: Vec<ModuleRequest>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancelled_scan_dispatches_no_more_targets() {
+        for status in [ScanStatus::Created, ScanStatus::Queued, ScanStatus::Running] {
+            assert!(status.accepts_work(), "{:?}", status);
+        }
+
+        for status in [
+            ScanStatus::Paused,
+            ScanStatus::Completed,
+            ScanStatus::Failed,
+            ScanStatus::Cancelled,
+        ] {
+            assert!(!status.accepts_work(), "{:?}", status);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Scan progress
+//!
+//! A scan plans one collection task for each of its targets against each of
+//! its enabled modules. Tasks are recorded as they are dispatched, or
+//! skipped for want of input, and their status is polled from the data
+//! collection service on every scheduler tick: a target finishes with its
+//! last task, and the scan with its last target. Targets added while the
+//! scan runs count toward the total as soon as they are added, and are
+//! dispatched on the next tick.
+
+use crate::models::{ScanProgress, ScanTarget, ScanTargetStatus, ScanTask, ScanTaskStatus};
+use uuid::Uuid;
+
+/// Tallies a scan's recorded tasks, with `undispatched` more still planned
+pub fn scan_progress(scan_id: Uuid, tasks: &[ScanTaskStatus], undispatched: usize) -> ScanProgress {
+    let count = |status: ScanTaskStatus| tasks.iter().filter(|s| **s == status).count() as u64;
+
+    let completed = count(ScanTaskStatus::Completed);
+    let failed = count(ScanTaskStatus::Failed);
+    let cancelled = count(ScanTaskStatus::Cancelled);
+    let skipped = count(ScanTaskStatus::Skipped);
+    let in_flight = tasks.iter().filter(|s| !s.is_finished()).count() as u64;
+    let pending = undispatched as u64;
+    let total = tasks.len() as u64 + pending;
+
+    let finished = completed + failed + cancelled + skipped;
+    let percentage = (finished * 100).checked_div(total).unwrap_or(0) as u8;
+
+    ScanProgress {
+        scan_id,
+        total,
+        completed,
+        failed,
+        cancelled,
+        skipped,
+        in_flight,
+        pending,
+        percentage,
+    }
+}
+
+/// Tasks planned for unfinished targets, one per module, that haven't been
+/// recorded yet
+pub fn undispatched_tasks(targets: &[ScanTarget], tasks: &[ScanTask], modules: usize) -> usize {
+    targets
+        .iter()
+        .filter(|t| {
+            t.status == ScanTargetStatus::Pending || t.status == ScanTargetStatus::InProgress
+        })
+        .map(|target| {
+            let recorded = tasks.iter().filter(|t| t.target_id == target.id).count();
+            modules.saturating_sub(recorded)
+        })
+        .sum()
+}
+
+/// The status a target finishes with, once all `planned` of its tasks have.
+/// A single failed task fails the target.
+pub fn target_status(tasks: &[ScanTaskStatus], planned: usize) -> Option<ScanTargetStatus> {
+    if tasks.len() < planned || tasks.iter().any(|s| !s.is_finished()) {
+        return None;
+    }
+
+    if tasks.contains(&ScanTaskStatus::Failed) {
+        Some(ScanTargetStatus::Failed)
+    } else if tasks
+        .iter()
+        .all(|s| *s == ScanTaskStatus::Cancelled || *s == ScanTaskStatus::Skipped)
+    {
+        Some(ScanTargetStatus::Skipped)
+    } else {
+        Some(ScanTargetStatus::Completed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use ScanTaskStatus::*;
+
+    fn target(status: ScanTargetStatus) -> ScanTarget {
+        ScanTarget {
+            id: Uuid::new_v4(),
+            scan_id: Uuid::nil(),
+            target_type: "domain".into(),
+            value: "example.com".into(),
+            status,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            started_at: None,
+            completed_at: None,
+            error_message: None,
+            metadata: HashMap::new(),
+            result_count: None,
+        }
+    }
+
+    fn task(target: &ScanTarget, status: ScanTaskStatus) -> ScanTask {
+        ScanTask {
+            id: Uuid::new_v4(),
+            scan_id: target.scan_id,
+            target_id: target.id,
+            module_id: Uuid::new_v4(),
+            status,
+            has_output: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_progress_reaches_100_when_all_tasks_complete() {
+        let scan_id = Uuid::new_v4();
+
+        let running = scan_progress(scan_id, &[Completed, Running, Pending, Completed], 0);
+        assert_eq!(running.total, 4);
+        assert_eq!(running.completed, 2);
+        assert_eq!(running.in_flight, 2);
+        assert_eq!(running.percentage, 50);
+        assert!(!running.is_finished());
+
+        let done = scan_progress(scan_id, &[Completed, Completed, Completed, Completed], 0);
+        assert_eq!(done.completed, 4);
+        assert_eq!(done.in_flight, 0);
+        assert_eq!(done.percentage, 100);
+        assert!(done.is_finished());
+    }
+
+    #[test]
+    fn test_progress_reflects_failures() {
+        let progress = scan_progress(Uuid::new_v4(), &[Completed, Failed, Failed, Running], 0);
+
+        assert_eq!(progress.completed, 1);
+        assert_eq!(progress.failed, 2);
+        assert_eq!(progress.in_flight, 1);
+        assert_eq!(progress.percentage, 75);
+
+        // Failed and skipped tasks finish the scan just like completed ones
+        let progress = scan_progress(
+            Uuid::new_v4(),
+            &[Completed, Failed, Failed, Cancelled, Skipped],
+            0,
+        );
+        assert_eq!(progress.failed, 2);
+        assert_eq!(progress.cancelled, 1);
+        assert_eq!(progress.skipped, 1);
+        assert_eq!(progress.percentage, 100);
+        assert!(progress.is_finished());
+    }
+
+    #[test]
+    fn test_added_targets_grow_the_total() {
+        let scan_id = Uuid::new_v4();
+        let done = target(ScanTargetStatus::Completed);
+        let mut targets = vec![done.clone()];
+        let tasks = vec![task(&done, Completed), task(&done, Completed)];
+        let statuses: Vec<_> = tasks.iter().map(|t| t.status.clone()).collect();
+
+        let undispatched = undispatched_tasks(&targets, &tasks, 2);
+        assert_eq!(
+            scan_progress(scan_id, &statuses, undispatched).percentage,
+            100
+        );
+
+        // A target discovered mid-scan plans a task per module
+        targets.push(target(ScanTargetStatus::Pending));
+        let progress = scan_progress(scan_id, &statuses, undispatched_tasks(&targets, &tasks, 2));
+        assert_eq!(progress.total, 4);
+        assert_eq!(progress.pending, 2);
+        assert_eq!(progress.percentage, 50);
+        assert!(!progress.is_finished());
+    }
+
+    #[test]
+    fn test_undispatched_tasks_wait_on_unfinished_targets() {
+        let waiting = target(ScanTargetStatus::InProgress);
+        let failed = target(ScanTargetStatus::Failed);
+        let tasks = vec![task(&waiting, Completed), task(&failed, Failed)];
+
+        // The running target still has two modules to go; the failed one
+        // never dispatches the rest
+        assert_eq!(undispatched_tasks(&[waiting, failed], &tasks, 3), 2);
+    }
+
+    #[test]
+    fn test_progress_rounds_down_until_finished() {
+        let mut tasks = vec![Completed; 199];
+        tasks.push(Running);
+
+        assert_eq!(scan_progress(Uuid::new_v4(), &tasks, 0).percentage, 99);
+        assert_eq!(scan_progress(Uuid::new_v4(), &[], 0).percentage, 0);
+        assert!(!scan_progress(Uuid::new_v4(), &[], 3).is_finished());
+    }
+
+    #[test]
+    fn test_target_finishes_with_its_last_task() {
+        assert_eq!(target_status(&[Completed, Running], 2), None);
+        // Not every module has been dispatched yet
+        assert_eq!(target_status(&[Completed], 2), None);
+        assert_eq!(
+            target_status(&[Completed, Completed], 2),
+            Some(ScanTargetStatus::Completed)
+        );
+        assert_eq!(
+            target_status(&[Completed, Failed], 2),
+            Some(ScanTargetStatus::Failed)
+        );
+        assert_eq!(
+            target_status(&[Completed, Cancelled], 2),
+            Some(ScanTargetStatus::Completed)
+        );
+        assert_eq!(
+            target_status(&[Cancelled, Skipped], 2),
+            Some(ScanTargetStatus::Skipped)
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use crate::config::DatabaseConfig;
 use crate::error::{ScannerError, ScannerResult};
-use crate::models::{Scan, ScanModule, ScanModuleStatus, ScanStatus, ScanTarget, ScanTargetStatus};
+use crate::models::{
+    PrerequisitePolicy, Scan, ScanModule, ScanModuleStatus, ScanSchedule, ScanStatus, ScanTarget,
+    ScanTargetStatus, ScanTask, ScanTaskStatus,
+};
 use chrono::{DateTime, Utc};
-use sqlx::{postgres::PgPoolOptions, query, query_as, Pool, Postgres};
+use mirage_common::database;
+use sqlx::{query, query_as, Pool, Postgres};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -10,10 +14,9 @@ pub type DbPool = Pool<Postgres>;
 
 /// Create database connection pool
 pub async fn create_db_pool(config: &DatabaseConfig) -> ScannerResult<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await?;
+    let pool = database::create_pool(config)
+        .await
+        .map_err(|e| ScannerError::Database(e.to_string()))?;
 
     // Run migrations
     sqlx::migrate!("./migrations").run(&pool).await?;
@@ -21,6 +24,7 @@ pub async fn create_db_pool(config: &DatabaseConfig) -> ScannerResult<DbPool> {
     Ok(pool)
 }
 
+#[derive(Clone)]
 pub struct ScanRepository {
     pool: DbPool,
 }
@@ -36,9 +40,13 @@ impl ScanRepository {
             INSERT INTO scans (
                 id, name, description, status, created_by, created_at, updated_at,
                 started_at, completed_at, priority, tags, metadata, 
-                error_message, progress, estimated_completion_time
+                error_message, progress, estimated_completion_time, callback_url,
+                callback_disabled, retention_days, legal_hold
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                $18, $19
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
             "#,
             scan.id,
             scan.name,
@@ -55,6 +63,10 @@ impl ScanRepository {
             scan.error_message,
             scan.progress,
             scan.estimated_completion_time,
+            scan.callback_url,
+            scan.callback_disabled,
+            scan.retention_days,
+            scan.legal_hold,
         )
         .execute(&self.pool)
         .await?;
@@ -68,7 +80,8 @@ impl ScanRepository {
             SELECT 
                 id, name, description, status as "status!: ScanStatus", created_by, 
                 created_at, updated_at, started_at, completed_at, priority,
-                tags, metadata, error_message, progress, estimated_completion_time
+                tags, metadata, error_message, progress, estimated_completion_time,
+                callback_url, callback_disabled, retention_days, legal_hold
             FROM scans
             WHERE id = $1
             "#,
@@ -97,6 +110,10 @@ impl ScanRepository {
                     error_message: r.error_message,
                     progress: r.progress,
                     estimated_completion_time: r.estimated_completion_time,
+                    callback_url: r.callback_url,
+                    callback_disabled: r.callback_disabled,
+                    retention_days: r.retention_days,
+                    legal_hold: r.legal_hold,
                 }))
             }
             None => Ok(None),
@@ -138,6 +155,82 @@ impl ScanRepository {
         Ok(())
     }
 
+    /// Records a running scan's progress without touching its status
+    pub async fn update_progress(&self, id: Uuid, progress: i32) -> ScannerResult<()> {
+        query!(
+            r#"
+            UPDATE scans
+            SET progress = $1, updated_at = $2
+            WHERE id = $3
+            "#,
+            progress,
+            Utc::now(),
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stops further callbacks for a scan whose endpoint keeps failing
+    pub async fn disable_callback(&self, id: Uuid) -> ScannerResult<()> {
+        query!(
+            r#"
+            UPDATE scans
+            SET callback_disabled = TRUE, updated_at = $1
+            WHERE id = $2
+            "#,
+            Utc::now(),
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_retention(
+        &self,
+        id: Uuid,
+        retention_days: Option<i32>,
+        legal_hold: bool,
+    ) -> ScannerResult<()> {
+        query!(
+            r#"
+            UPDATE scans
+            SET retention_days = $1, legal_hold = $2, updated_at = $3
+            WHERE id = $4
+            "#,
+            retention_days,
+            legal_hold,
+            Utc::now(),
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a finished scan along with its targets and modules, unless it
+    /// has been placed under legal hold. Returns whether it was deleted.
+    pub async fn delete_expired_scan(&self, id: Uuid) -> ScannerResult<bool> {
+        let result = query!(
+            r#"
+            DELETE FROM scans
+            WHERE id = $1
+              AND status IN ('completed', 'failed', 'cancelled')
+              AND legal_hold = FALSE
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn update_scan(&self, scan: &Scan) -> ScannerResult<()> {
         query!(
             r#"
@@ -189,7 +282,8 @@ impl ScanRepository {
             SELECT 
                 id, name, description, status as "status!: ScanStatus", created_by, 
                 created_at, updated_at, started_at, completed_at, priority,
-                tags, metadata, error_message, progress, estimated_completion_time
+                tags, metadata, error_message, progress, estimated_completion_time,
+                callback_url, callback_disabled, retention_days, legal_hold
             FROM scans
             ORDER BY created_at DESC
             LIMIT $1 OFFSET $2
@@ -230,6 +324,10 @@ impl ScanRepository {
                 error_message: r.error_message,
                 progress: r.progress,
                 estimated_completion_time: r.estimated_completion_time,
+                callback_url: r.callback_url,
+                callback_disabled: r.callback_disabled,
+                retention_days: r.retention_days,
+                legal_hold: r.legal_hold,
             });
         }
 
@@ -242,7 +340,8 @@ impl ScanRepository {
             SELECT 
                 id, name, description, status as "status!: ScanStatus", created_by, 
                 created_at, updated_at, started_at, completed_at, priority,
-                tags, metadata, error_message, progress, estimated_completion_time
+                tags, metadata, error_message, progress, estimated_completion_time,
+                callback_url, callback_disabled, retention_days, legal_hold
             FROM scans
             WHERE status = 'created' OR status = 'queued'
             ORDER BY priority, created_at
@@ -273,11 +372,282 @@ impl ScanRepository {
                 error_message: r.error_message,
                 progress: r.progress,
                 estimated_completion_time: r.estimated_completion_time,
+                callback_url: r.callback_url,
+                callback_disabled: r.callback_disabled,
+                retention_days: r.retention_days,
+                legal_hold: r.legal_hold,
             });
         }
 
         Ok(result)
     }
+
+    /// Scans whose tasks are being collected, oldest first
+    pub async fn get_running_scan_ids(&self) -> ScannerResult<Vec<Uuid>> {
+        let ids = query!(
+            r#"
+            SELECT id
+            FROM scans
+            WHERE status = 'running'
+            ORDER BY started_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Finished scans that are not under legal hold, i.e. the candidates for
+    /// retention sweeps
+    pub async fn get_finished_scans(&self) -> ScannerResult<Vec<Scan>> {
+        let scans = query!(
+            r#"
+            SELECT 
+                id, name, description, status as "status!: ScanStatus", created_by, 
+                created_at, updated_at, started_at, completed_at, priority,
+                tags, metadata, error_message, progress, estimated_completion_time,
+                callback_url, callback_disabled, retention_days, legal_hold
+            FROM scans
+            WHERE status IN ('completed', 'failed', 'cancelled') AND legal_hold = FALSE
+            ORDER BY completed_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut result = Vec::with_capacity(scans.len());
+        for r in scans {
+            let metadata: HashMap<String, String> = serde_json::from_value(r.metadata)?;
+
+            result.push(Scan {
+                id: r.id,
+                name: r.name,
+                description: r.description,
+                status: r.status,
+                created_by: r.created_by,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+                started_at: r.started_at,
+                completed_at: r.completed_at,
+                priority: r.priority,
+                tags: r.tags,
+                metadata,
+                error_message: r.error_message,
+                progress: r.progress,
+                estimated_completion_time: r.estimated_completion_time,
+                callback_url: r.callback_url,
+                callback_disabled: r.callback_disabled,
+                retention_days: r.retention_days,
+                legal_hold: r.legal_hold,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Copies a scan with its targets and modules into a new scan with ID
+    /// `run_id`, ready to be queued as a run of `schedule_id`
+    pub async fn create_scan_run(
+        &self,
+        scan_id: Uuid,
+        run_id: Uuid,
+        schedule_id: Uuid,
+    ) -> ScannerResult<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let copied = query!(
+            r#"
+            INSERT INTO scans (
+                id, name, description, status, created_by, created_at, updated_at,
+                priority, tags, metadata, callback_url, callback_disabled,
+                retention_days, legal_hold
+            )
+            SELECT
+                $1, name, description, 'created', created_by, $2, $2,
+                priority, tags, metadata || jsonb_build_object('schedule_id', $3::TEXT),
+                callback_url, FALSE, retention_days, legal_hold
+            FROM scans
+            WHERE id = $4
+            "#,
+            run_id,
+            now,
+            schedule_id.to_string(),
+            scan_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if copied.rows_affected() == 0 {
+            return Err(ScannerError::NotFound(format!(
+                "Scan {} not found",
+                scan_id
+            )));
+        }
+
+        query!(
+            r#"
+            INSERT INTO scan_targets (
+                id, scan_id, target_type, value, status, created_at, updated_at, metadata
+            )
+            SELECT gen_random_uuid(), $1, target_type, value, 'pending', $2, $2, metadata
+            FROM scan_targets
+            WHERE scan_id = $3
+            "#,
+            run_id,
+            now,
+            scan_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            INSERT INTO scan_modules (
+                id, scan_id, module_id, module_name, module_version,
+                status, parameters, priority, depends_on, prerequisite_policy,
+                created_at, updated_at
+            )
+            SELECT
+                gen_random_uuid(), $1, module_id, module_name, module_version,
+                status, parameters, priority, depends_on, prerequisite_policy,
+                $2, $2
+            FROM scan_modules
+            WHERE scan_id = $3
+            "#,
+            run_id,
+            now,
+            scan_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn create_schedule(&self, schedule: &ScanSchedule) -> ScannerResult<()> {
+        query!(
+            r#"
+            INSERT INTO scan_schedules (
+                id, scan_id, cron_expression, enabled, next_run_at, last_run_at,
+                last_scan_id, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            schedule.id,
+            schedule.scan_id,
+            schedule.cron_expression,
+            schedule.enabled,
+            schedule.next_run_at,
+            schedule.last_run_at,
+            schedule.last_scan_id,
+            schedule.created_at,
+            schedule.updated_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_schedule(&self, id: Uuid) -> ScannerResult<Option<ScanSchedule>> {
+        let schedule = query_as!(
+            ScanSchedule,
+            r#"
+            SELECT
+                id, scan_id, cron_expression, enabled, next_run_at, last_run_at,
+                last_scan_id, created_at, updated_at
+            FROM scan_schedules
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    /// All schedules, or only those of `scan_id` when given
+    pub async fn list_schedules(&self, scan_id: Option<Uuid>) -> ScannerResult<Vec<ScanSchedule>> {
+        let schedules = query_as!(
+            ScanSchedule,
+            r#"
+            SELECT
+                id, scan_id, cron_expression, enabled, next_run_at, last_run_at,
+                last_scan_id, created_at, updated_at
+            FROM scan_schedules
+            WHERE $1::UUID IS NULL OR scan_id = $1
+            ORDER BY created_at
+            "#,
+            scan_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(schedules)
+    }
+
+    /// Enabled schedules whose next run is at or before `now`
+    pub async fn get_due_schedules(&self, now: DateTime<Utc>) -> ScannerResult<Vec<ScanSchedule>> {
+        let schedules = query_as!(
+            ScanSchedule,
+            r#"
+            SELECT
+                id, scan_id, cron_expression, enabled, next_run_at, last_run_at,
+                last_scan_id, created_at, updated_at
+            FROM scan_schedules
+            WHERE enabled AND next_run_at <= $1
+            ORDER BY next_run_at
+            "#,
+            now,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(schedules)
+    }
+
+    pub async fn update_schedule(&self, schedule: &ScanSchedule) -> ScannerResult<()> {
+        query!(
+            r#"
+            UPDATE scan_schedules
+            SET
+                cron_expression = $1,
+                enabled = $2,
+                next_run_at = $3,
+                last_run_at = $4,
+                last_scan_id = $5,
+                updated_at = $6
+            WHERE id = $7
+            "#,
+            schedule.cron_expression,
+            schedule.enabled,
+            schedule.next_run_at,
+            schedule.last_run_at,
+            schedule.last_scan_id,
+            schedule.updated_at,
+            schedule.id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_schedule(&self, id: Uuid) -> ScannerResult<bool> {
+        let result = query!("DELETE FROM scan_schedules WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
 }
 
 pub struct ScanTargetRepository {
@@ -430,8 +800,79 @@ impl ScanTargetRepository {
 
         Ok(result)
     }
+
+    pub async fn create_task(&self, task: &ScanTask) -> ScannerResult<()> {
+        query!(
+            r#"
+            INSERT INTO scan_tasks (
+                id, scan_id, target_id, module_id, status, has_output,
+                created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            task.id,
+            task.scan_id,
+            task.target_id,
+            task.module_id,
+            task.status as _,
+            task.has_output,
+            task.created_at,
+            task.updated_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The collection tasks dispatched for a scan's targets
+    pub async fn get_tasks_for_scan(&self, scan_id: Uuid) -> ScannerResult<Vec<ScanTask>> {
+        let tasks = query_as!(
+            ScanTask,
+            r#"
+            SELECT
+                id, scan_id, target_id, module_id, status as "status!: ScanTaskStatus",
+                has_output, created_at, updated_at
+            FROM scan_tasks
+            WHERE scan_id = $1
+            ORDER BY created_at
+            "#,
+            scan_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tasks)
+    }
+
+    pub async fn update_task_status(
+        &self,
+        id: Uuid,
+        status: ScanTaskStatus,
+        has_output: Option<bool>,
+    ) -> ScannerResult<()> {
+        query!(
+            r#"
+            UPDATE scan_tasks
+            SET
+                status = $1,
+                has_output = COALESCE($2, has_output),
+                updated_at = $3
+            WHERE id = $4
+            "#,
+            status as _,
+            has_output,
+            Utc::now(),
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
 }
 
+#[derive(Clone)]
 pub struct ScanModuleRepository {
     pool: DbPool,
 }
@@ -446,9 +887,10 @@ impl ScanModuleRepository {
             r#"
             INSERT INTO scan_modules (
                 id, scan_id, module_id, module_name, module_version,
-                status, parameters, priority, depends_on, created_at, updated_at
+                status, parameters, priority, depends_on, prerequisite_policy,
+                created_at, updated_at
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
             "#,
             module.id,
             module.scan_id,
@@ -459,6 +901,7 @@ impl ScanModuleRepository {
             serde_json::to_value(&module.parameters)?,
             module.priority,
             &module.depends_on,
+            module.prerequisite_policy as _,
             module.created_at,
             module.updated_at,
         )
@@ -474,7 +917,9 @@ impl ScanModuleRepository {
             SELECT 
                 id, scan_id, module_id, module_name, module_version,
                 status as "status!: ScanModuleStatus", parameters,
-                priority, depends_on, created_at, updated_at
+                priority, depends_on,
+                prerequisite_policy as "prerequisite_policy!: PrerequisitePolicy",
+                created_at, updated_at
             FROM scan_modules
             WHERE scan_id = $1
             ORDER BY priority, created_at
@@ -499,6 +944,7 @@ impl ScanModuleRepository {
                 parameters,
                 priority: r.priority,
                 depends_on: r.depends_on,
+                prerequisite_policy: r.prerequisite_policy,
                 created_at: r.created_at,
                 updated_at: r.updated_at,
             });
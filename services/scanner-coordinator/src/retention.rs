@@ -0,0 +1,144 @@
+//! Scan retention sweeper
+//!
+//! Finished scans are deleted once they are older than their retention
+//! period: the scan's own `retention_days` when set, otherwise the global
+//! `retention.default_days`. Scans under legal hold are never deleted.
+
+use crate::config::RetentionConfig;
+use crate::error::ScannerResult;
+use crate::models::Scan;
+use crate::repositories::ScanRepository;
+use chrono::{DateTime, Utc};
+use mirage_common::shutdown::ShutdownSignal;
+use std::time::Duration;
+use tokio::time;
+use uuid::Uuid;
+
+/// IDs of the scans whose retention period has elapsed at `now`
+pub fn expired_scans(scans: &[Scan], default_days: u32, now: DateTime<Utc>) -> Vec<Uuid> {
+    scans
+        .iter()
+        .filter(|scan| matches!(scan.expires_at(default_days), Some(expiry) if expiry <= now))
+        .map(|scan| scan.id)
+        .collect()
+}
+
+/// Runs a single sweep and returns the number of deleted scans
+pub async fn sweep(scan_repo: &ScanRepository, config: &RetentionConfig) -> ScannerResult<usize> {
+    let scans = scan_repo.get_finished_scans().await?;
+    let mut deleted = 0;
+
+    for scan_id in expired_scans(&scans, config.default_days, Utc::now()) {
+        // Checked again by the delete itself, in case the scan was put on
+        // legal hold since it was listed
+        if scan_repo.delete_expired_scan(scan_id).await? {
+            tracing::info!("Deleted scan {} after its retention period", scan_id);
+            deleted += 1;
+        } else {
+            tracing::info!("Kept scan {}, now on legal hold or already gone", scan_id);
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Background retention process, which runs until `shutdown`
+pub async fn run_retention_sweeper(
+    scan_repo: ScanRepository,
+    config: RetentionConfig,
+    mut shutdown: ShutdownSignal,
+) {
+    if !config.enabled {
+        tracing::info!("Scan retention sweeper is disabled");
+        return;
+    }
+
+    tracing::info!(
+        "Starting scan retention sweeper (default retention: {} days)",
+        config.default_days
+    );
+
+    loop {
+        match sweep(&scan_repo, &config).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!("Retention sweep deleted {} scans", deleted),
+            Err(e) => tracing::error!("Retention sweep failed: {}", e),
+        }
+
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(config.sweep_interval_seconds)) => {}
+            _ = shutdown.recv() => break,
+        }
+    }
+
+    tracing::info!("Scan retention sweeper stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ScanStatus;
+    use std::collections::HashMap;
+
+    fn finished_scan(days_ago: i64, retention_days: Option<i32>, legal_hold: bool) -> Scan {
+        let completed_at = Utc::now() - chrono::Duration::days(days_ago);
+
+        Scan {
+            id: Uuid::new_v4(),
+            name: "sweep".to_string(),
+            description: None,
+            status: ScanStatus::Completed,
+            created_by: None,
+            created_at: completed_at,
+            updated_at: completed_at,
+            started_at: Some(completed_at),
+            completed_at: Some(completed_at),
+            priority: 5,
+            tags: Vec::new(),
+            metadata: HashMap::new(),
+            error_message: None,
+            progress: Some(100),
+            estimated_completion_time: None,
+            callback_url: None,
+            callback_disabled: false,
+            retention_days,
+            legal_hold,
+        }
+    }
+
+    #[test]
+    fn test_legal_hold_is_never_swept() {
+        let held = finished_scan(400, Some(7), true);
+        let expired = finished_scan(400, Some(7), false);
+
+        let swept = expired_scans(&[held.clone(), expired.clone()], 30, Utc::now());
+        assert_eq!(swept, vec![expired.id]);
+        assert!(held.expires_at(30).is_none());
+    }
+
+    #[test]
+    fn test_override_takes_precedence_over_default() {
+        let short = finished_scan(10, Some(7), false);
+        let long = finished_scan(60, Some(365), false);
+        let default = finished_scan(45, None, false);
+        let recent = finished_scan(5, None, false);
+
+        let swept = expired_scans(
+            &[short.clone(), long, default.clone(), recent],
+            30,
+            Utc::now(),
+        );
+        assert_eq!(swept, vec![short.id, default.id]);
+        assert_eq!(default.effective_retention_days(30), 30);
+        assert_eq!(short.effective_retention_days(30), 7);
+    }
+
+    #[test]
+    fn test_active_scans_are_not_swept() {
+        let mut running = finished_scan(400, Some(1), false);
+        running.status = ScanStatus::Running;
+        running.completed_at = None;
+
+        assert!(expired_scans(&[running], 30, Utc::now()).is_empty());
+    }
+}
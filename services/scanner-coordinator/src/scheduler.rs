@@ -1,10 +1,20 @@
+use crate::callbacks::{CallbackNotifier, CallbackPayload, ScanEvent, ScanSummary};
 use crate::config::AppConfig;
 use crate::error::{ScannerError, ScannerResult};
+use crate::execution::{ExecutionPlan, ModuleOutcome, Step};
 use crate::integrations::IntegrationService;
-use crate::models::{Scan, ScanModule, ScanModuleStatus, ScanStatus, ScanTarget, ScanTargetStatus};
-use crate::repositories::{ScanRepository, ScanTargetRepository};
+use crate::models::{
+    Scan, ScanModule, ScanProgress, ScanSchedule, ScanStatus, ScanTarget, ScanTargetStatus,
+    ScanTask, ScanTaskStatus,
+};
+use crate::progress;
+use crate::repositories::{ScanModuleRepository, ScanRepository, ScanTargetRepository};
+use crate::schedules::{self, ScanLauncher};
+use async_trait::async_trait;
 use chrono::Utc;
+use mirage_common::shutdown::ShutdownSignal;
 use redis::{AsyncCommands, Client as RedisClient, Commands};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time;
@@ -13,11 +23,22 @@ use uuid::Uuid;
 const SCAN_QUEUE_KEY: &str = "mirage:scanner:scan_queue";
 const TARGET_QUEUE_PREFIX: &str = "mirage:scanner:target_queue:";
 
+/// Readiness probe: checks the Redis task queue answers
+pub async fn ping_queue(client: RedisClient) -> ScannerResult<()> {
+    let mut conn = client.get_async_connection().await?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut conn)
+        .await?;
+    Ok(())
+}
+
 pub struct SchedulerService {
     redis_client: RedisClient,
     scan_repo: ScanRepository,
     target_repo: ScanTargetRepository,
+    module_repo: ScanModuleRepository,
     integration_service: IntegrationService,
+    callbacks: CallbackNotifier,
     config: AppConfig,
 }
 
@@ -26,14 +47,18 @@ impl SchedulerService {
         redis_client: RedisClient,
         scan_repo: ScanRepository,
         target_repo: ScanTargetRepository,
+        module_repo: ScanModuleRepository,
         integration_service: IntegrationService,
+        callbacks: CallbackNotifier,
         config: AppConfig,
     ) -> Self {
         Self {
             redis_client,
             scan_repo,
             target_repo,
+            module_repo,
             integration_service,
+            callbacks,
             config,
         }
     }
@@ -55,6 +80,25 @@ impl SchedulerService {
         Ok(())
     }
 
+    /// Take a scan off the queue, e.g. once it is cancelled
+    pub async fn remove_queued_scan(&self, scan_id: Uuid) -> ScannerResult<()> {
+        let mut conn = self.redis_client.get_async_connection().await?;
+        conn.zrem::<_, _, i64>(SCAN_QUEUE_KEY, scan_id.to_string())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether the scan's targets may still be dispatched, i.e. it hasn't
+    /// been cancelled in the meantime
+    async fn accepts_work(&self, scan_id: Uuid) -> ScannerResult<bool> {
+        Ok(self
+            .scan_repo
+            .get_scan_by_id(scan_id)
+            .await?
+            .is_some_and(|scan| scan.status.accepts_work()))
+    }
+
     /// Start processing a scan
     pub async fn start_scan(&self, scan_id: Uuid) -> ScannerResult<()> {
         // Get scan details
@@ -94,6 +138,8 @@ impl SchedulerService {
             )
             .await?;
 
+        self.notify_callback(scan_id, ScanStatus::Running, None).await;
+
         // Enqueue targets for processing
         self.enqueue_scan_targets(scan_id, &targets).await?;
 
@@ -101,7 +147,7 @@ impl SchedulerService {
     }
 
     /// Enqueue targets for a scan
-    async fn enqueue_scan_targets(
+    pub async fn enqueue_scan_targets(
         &self,
         scan_id: Uuid,
         targets: &[ScanTarget],
@@ -149,8 +195,12 @@ impl SchedulerService {
         }
     }
 
-    /// Process a target against available modules
-    pub async fn process_target(&self, target: &ScanTarget, module_id: Uuid) -> ScannerResult<()> {
+    /// Start a target, dispatching the modules that depend on nothing
+    pub async fn process_target(
+        &self,
+        target: &ScanTarget,
+        plan: &ExecutionPlan,
+    ) -> ScannerResult<()> {
         // Update target status to in-progress
         self.target_repo
             .update_target_status(
@@ -163,7 +213,59 @@ impl SchedulerService {
             )
             .await?;
 
-        // Create collection task
+        self.advance_target(target, plan, &mut Vec::new()).await
+    }
+
+    /// Dispatch or skip the target's modules whose prerequisites have
+    /// finished, adding their tasks to `tasks`. A target that can't be
+    /// dispatched fails.
+    async fn advance_target(
+        &self,
+        target: &ScanTarget,
+        plan: &ExecutionPlan,
+        tasks: &mut Vec<ScanTask>,
+    ) -> ScannerResult<()> {
+        let outcomes: HashMap<Uuid, ModuleOutcome> = tasks
+            .iter()
+            .filter(|t| t.target_id == target.id)
+            .map(|t| (t.module_id, ModuleOutcome::of(t)))
+            .collect();
+
+        for step in plan.next_steps(&outcomes) {
+            let task = match step {
+                Step::Run(module_id) => match self.dispatch_task(target, module_id).await {
+                    Ok(task) => task,
+                    Err(e) => {
+                        self.target_repo
+                            .update_target_status(
+                                target.id,
+                                ScanTargetStatus::Failed,
+                                None,
+                                Some(Utc::now()),
+                                Some(format!("Failed to dispatch module {}: {}", module_id, e)),
+                                None,
+                            )
+                            .await?;
+
+                        return Err(e);
+                    }
+                },
+                Step::Skip(module_id) => {
+                    let task =
+                        scan_task(target, module_id, Uuid::new_v4(), ScanTaskStatus::Skipped);
+                    self.target_repo.create_task(&task).await?;
+                    task
+                }
+            };
+
+            tasks.push(task);
+        }
+
+        Ok(())
+    }
+
+    /// Create a collection task and record it so its progress can be polled
+    async fn dispatch_task(&self, target: &ScanTarget, module_id: Uuid) -> ScannerResult<ScanTask> {
         let task_id = self
             .integration_service
             .create_collection_task(
@@ -172,25 +274,10 @@ impl SchedulerService {
             )
             .await?;
 
-        // Store task ID in target metadata (in a real system we would have a better data model)
-        // This is a simplification for this example
-        let mut metadata = target.metadata.clone();
-        metadata.insert("collection_task_id".to_string(), task_id.to_string());
-
-        // Update target status to completed
-        // In a real implementation, we would track task completion asynchronously
-        self.target_repo
-            .update_target_status(
-                target.id,
-                ScanTargetStatus::Completed,
-                None,
-                Some(Utc::now()),
-                None,
-                Some(1), // result_count
-            )
-            .await?;
+        let task = scan_task(target, module_id, task_id, ScanTaskStatus::Pending);
+        self.target_repo.create_task(&task).await?;
 
-        Ok(())
+        Ok(task)
     }
 
     /// Complete a scan
@@ -209,81 +296,266 @@ impl SchedulerService {
         self.scan_repo
             .update_scan_status(
                 scan_id,
-                status,
+                status.clone(),
                 None,
                 Some(Utc::now()),
                 Some(100), // 100% progress
-                error_message,
+                error_message.clone(),
             )
             .await?;
 
+        self.notify_callback(scan_id, status, error_message).await;
+
         Ok(())
     }
 
-    /// Check if scan is complete (all targets processed)
-    pub async fn check_scan_completion(&self, scan_id: Uuid) -> ScannerResult<bool> {
-        let targets = self.target_repo.get_targets_for_scan(scan_id).await?;
+    /// Send a lifecycle callback if the scan has one. Delivery runs in the
+    /// background; a callback that exhausts its retries is disabled.
+    async fn notify_callback(
+        &self,
+        scan_id: Uuid,
+        status: ScanStatus,
+        error_message: Option<String>,
+    ) {
+        let event = match ScanEvent::for_status(&status) {
+            Some(event) => event,
+            None => return,
+        };
+
+        let scan = match self.scan_repo.get_scan_by_id(scan_id).await {
+            Ok(Some(scan)) => scan,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::error!("Failed to load scan {} for callback: {}", scan_id, e);
+                return;
+            }
+        };
+
+        let url = match scan.callback_url {
+            Some(url) if !scan.callback_disabled => url,
+            _ => return,
+        };
+
+        let targets = match self.target_repo.get_targets_for_scan(scan_id).await {
+            Ok(targets) => targets,
+            Err(e) => {
+                tracing::error!("Failed to load targets for scan {} callback: {}", scan_id, e);
+                Vec::new()
+            }
+        };
+
+        let payload = CallbackPayload {
+            event,
+            scan_id,
+            status,
+            summary: ScanSummary::from_targets(&targets),
+            error_message,
+            timestamp: Utc::now(),
+        };
 
-        // Check if all targets are either completed or failed
-        let all_processed = targets.iter().all(|t| {
-            t.status == ScanTargetStatus::Completed
-                || t.status == ScanTargetStatus::Failed
-                || t.status == ScanTargetStatus::Skipped
+        let callbacks = self.callbacks.clone();
+        let scan_repo = self.scan_repo.clone();
+        tokio::spawn(async move {
+            if let Err(e) = callbacks.deliver(&url, &payload).await {
+                tracing::error!("Disabling callback for scan {}: {}", scan_id, e);
+                if let Err(e) = scan_repo.disable_callback(scan_id).await {
+                    tracing::error!("Failed to disable callback for scan {}: {}", scan_id, e);
+                }
+            }
         });
+    }
 
-        // Calculate completion percentage
-        let total = targets.len();
-        let completed = targets
-            .iter()
-            .filter(|t| {
-                t.status == ScanTargetStatus::Completed
-                    || t.status == ScanTargetStatus::Failed
-                    || t.status == ScanTargetStatus::Skipped
-            })
-            .count();
+    /// Poll the scan's unfinished tasks, dispatch the modules they were
+    /// holding up, finish the targets whose tasks are all done, and finish
+    /// the scan once every target is
+    pub async fn update_progress(
+        &self,
+        scan_id: Uuid,
+        plan: &ExecutionPlan,
+    ) -> ScannerResult<ScanProgress> {
+        let mut tasks = self.target_repo.get_tasks_for_scan(scan_id).await?;
+
+        for task in tasks.iter_mut().filter(|t| !t.status.is_finished()) {
+            let status = match self
+                .integration_service
+                .get_collection_task_status(task.id)
+                .await
+            {
+                Ok(status) if status != task.status => status,
+                Ok(_) => continue,
+                // Retried on the next tick
+                Err(e) => {
+                    tracing::warn!("Failed to poll collection task {}: {}", task.id, e);
+                    continue;
+                }
+            };
 
-        if total > 0 {
-            let progress = (completed * 100) / total;
+            // Dependents only run on what a prerequisite found
+            let has_output = if status == ScanTaskStatus::Completed {
+                match self
+                    .integration_service
+                    .collection_task_has_output(task.id)
+                    .await
+                {
+                    Ok(has_output) => Some(has_output),
+                    Err(e) => {
+                        tracing::warn!(
+                            "Failed to fetch results of collection task {}: {}",
+                            task.id,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            self.target_repo
+                .update_task_status(task.id, status.clone(), has_output)
+                .await?;
+            task.status = status;
+            task.has_output = has_output;
+        }
+
+        // A cancelled scan keeps its status and dispatches nothing more
+        let accepts_work = self.accepts_work(scan_id).await?;
+
+        let mut targets = self.target_repo.get_targets_for_scan(scan_id).await?;
+        for target in targets
+            .iter_mut()
+            .filter(|t| t.status == ScanTargetStatus::InProgress)
+        {
+            if accepts_work {
+                if let Err(e) = self.advance_target(target, plan, &mut tasks).await {
+                    tracing::error!("Failed to dispatch target {}: {}", target.id, e);
+                    target.status = ScanTargetStatus::Failed;
+                    continue;
+                }
+            }
 
-            // Update scan progress
+            let statuses: Vec<ScanTaskStatus> = tasks
+                .iter()
+                .filter(|t| t.target_id == target.id)
+                .map(|t| t.status.clone())
+                .collect();
+
+            if let Some(status) = progress::target_status(&statuses, plan.module_count()) {
+                self.target_repo
+                    .update_target_status(
+                        target.id,
+                        status.clone(),
+                        None,
+                        Some(Utc::now()),
+                        None,
+                        None,
+                    )
+                    .await?;
+                target.status = status;
+            }
+        }
+
+        let undispatched = progress::undispatched_tasks(&targets, &tasks, plan.module_count());
+        let statuses: Vec<ScanTaskStatus> = tasks.into_iter().map(|t| t.status).collect();
+        let progress = progress::scan_progress(scan_id, &statuses, undispatched);
+
+        if !accepts_work {
+            return Ok(progress);
+        }
+
+        let unfinished = targets.iter().any(|t| {
+            t.status == ScanTargetStatus::Pending || t.status == ScanTargetStatus::InProgress
+        });
+        if unfinished || !progress.is_finished() {
             self.scan_repo
-                .update_scan_status(
-                    scan_id,
-                    if all_processed {
-                        ScanStatus::Completed
-                    } else {
-                        ScanStatus::Running
-                    },
-                    None,
-                    if all_processed {
-                        Some(Utc::now())
-                    } else {
-                        None
-                    },
-                    Some(progress as i32),
-                    None,
-                )
+                .update_progress(scan_id, progress.percentage as i32)
                 .await?;
+            return Ok(progress);
         }
 
-        Ok(all_processed)
+        let failed = targets
+            .iter()
+            .filter(|t| t.status == ScanTargetStatus::Failed)
+            .count();
+        let error_message =
+            (failed > 0).then(|| format!("{} of {} targets failed", failed, targets.len()));
+        self.complete_scan(scan_id, failed == 0, error_message)
+            .await?;
+
+        Ok(progress)
+    }
+}
+
+fn scan_task(target: &ScanTarget, module_id: Uuid, id: Uuid, status: ScanTaskStatus) -> ScanTask {
+    let now = Utc::now();
+    ScanTask {
+        id,
+        scan_id: target.scan_id,
+        target_id: target.id,
+        module_id,
+        status,
+        has_output: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+#[async_trait]
+impl ScanLauncher for SchedulerService {
+    /// Copies the scheduled scan into a new scan and queues it
+    async fn launch(&self, schedule: &ScanSchedule) -> ScannerResult<Uuid> {
+        let scan = self
+            .scan_repo
+            .get_scan_by_id(schedule.scan_id)
+            .await?
+            .ok_or_else(|| {
+                ScannerError::NotFound(format!("Scan {} not found", schedule.scan_id))
+            })?;
+
+        let run_id = Uuid::new_v4();
+        self.scan_repo
+            .create_scan_run(scan.id, run_id, schedule.id)
+            .await?;
+        self.enqueue_scan(run_id, scan.priority).await?;
+
+        Ok(run_id)
     }
 }
 
-/// Background scheduler process
-pub async fn run_scheduler(scheduler: SchedulerService, config: AppConfig) {
+/// Background scheduler process, which runs until `shutdown`
+pub async fn run_scheduler(
+    scheduler: SchedulerService,
+    config: AppConfig,
+    mut shutdown: ShutdownSignal,
+) {
     tracing::info!("Starting scan scheduler");
 
     let interval = config.scheduler.interval_seconds;
     loop {
+        // Queue runs of schedules that came due
+        if let Err(e) = schedules::run_schedules(&scheduler.scan_repo, &scheduler).await {
+            tracing::error!("Error running scan schedules: {}", e);
+        }
+
         // Process pending scans
         if let Err(e) = process_pending_scans(&scheduler).await {
             tracing::error!("Error processing pending scans: {}", e);
         }
 
-        // Sleep before next check
-        time::sleep(Duration::from_secs(interval)).await;
+        // Dispatch targets added to running scans and poll their tasks
+        if let Err(e) = process_running_scans(&scheduler).await {
+            tracing::error!("Error processing running scans: {}", e);
+        }
+
+        // Sleep before next check; a scan being started is seen through
+        // before shutting down
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(interval)) => {}
+            _ = shutdown.recv() => break,
+        }
     }
+
+    tracing::info!("Scan scheduler stopped");
 }
 
 /// Process pending scans in the queue
@@ -324,56 +596,170 @@ async fn process_pending_scans(scheduler: &SchedulerService) -> ScannerResult<()
     Ok(())
 }
 
+/// Move running scans along
+async fn process_running_scans(scheduler: &SchedulerService) -> ScannerResult<()> {
+    for scan_id in scheduler.scan_repo.get_running_scan_ids().await? {
+        if let Err(e) = process_scan_targets(scheduler, scan_id).await {
+            tracing::error!("Failed to process scan targets for scan {}: {}", scan_id, e);
+        }
+    }
+
+    Ok(())
+}
+
 /// Process targets for a scan
 async fn process_scan_targets(scheduler: &SchedulerService, scan_id: Uuid) -> ScannerResult<()> {
-    let mut success = true;
-
-    // Get modules for this scan
-    let modules = scheduler
-        .scan_repo
-        .get_scan_by_id(scan_id)
-        .await?
-        .map(|s| {
-            // In a real implementation, we would get modules from the database
-            vec![Uuid::new_v4()] // Placeholder module ID
-        })
-        .unwrap_or_default();
-
-    if modules.is_empty() {
+    // Order the scan's modules by their dependencies
+    let modules = scheduler.module_repo.get_modules_for_scan(scan_id).await?;
+    let plan = ExecutionPlan::new(&modules)?;
+
+    if plan.module_count() == 0 {
         return Err(ScannerError::Validation(
             "No modules configured for scan".into(),
         ));
     }
 
-    // Process each target against each module
+    // Start each target on the modules without prerequisites
     loop {
+        // Stop dispatching once the scan is cancelled
+        if !scheduler.accepts_work(scan_id).await? {
+            tracing::info!(
+                "Scan {} was cancelled, leaving its remaining targets",
+                scan_id
+            );
+            return Ok(());
+        }
+
         // Get next target from queue
         let target = match scheduler.get_next_target(scan_id).await? {
             Some(target) => target,
             None => break, // No more targets
         };
 
-        // Process target against each module
-        for module_id in &modules {
-            if let Err(e) = scheduler.process_target(&target, *module_id).await {
-                tracing::error!(
-                    "Failed to process target {} with module {}: {}",
-                    target.id,
-                    module_id,
-                    e
-                );
-                success = false;
+        if let Err(e) = scheduler.process_target(&target, &plan).await {
+            tracing::error!("Failed to process target {}: {}", target.id, e);
+        }
+    }
+
+    // Poll the dispatched tasks, which moves targets on to their next
+    // modules; the scan completes with its last task
+    scheduler.update_progress(scan_id, &plan).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callbacks::tests::{local_config, mock_endpoint};
+    use crate::config::CallbackConfig;
+    use crate::repositories::DbPool;
+    use serde_json::json;
+
+    fn scheduler(pool: DbPool, callbacks: CallbackConfig) -> SchedulerService {
+        let mut config: AppConfig = serde_json::from_value(json!({
+            "server": { "port": 8085, "host": "127.0.0.1" },
+            "database": { "url": "postgres://localhost/mirage", "max_connections": 5 },
+            "redis": { "uri": "redis://127.0.0.1:1", "task_queue_prefix": "mirage:test" },
+            "scheduler": {
+                "interval_seconds": 5,
+                "max_concurrent_scans": 1,
+                "max_targets_per_batch": 10,
+                "retry_delay_seconds": 1,
+                "max_retries": 1
+            },
+            "module_registry": { "url": "http://127.0.0.1:1", "timeout_seconds": 1 },
+            "scan_orchestration": { "url": "http://127.0.0.1:1", "timeout_seconds": 1 },
+            "data_collection": { "url": "http://127.0.0.1:1", "timeout_seconds": 1 },
+            "data_storage": { "url": "http://127.0.0.1:1", "timeout_seconds": 1 }
+        }))
+        .unwrap();
+        config.callbacks = callbacks.clone();
+
+        SchedulerService::new(
+            RedisClient::open(config.redis.uri.as_str()).unwrap(),
+            ScanRepository::new(pool.clone()),
+            ScanTargetRepository::new(pool.clone()),
+            ScanModuleRepository::new(pool),
+            IntegrationService::new(reqwest::Client::new(), config.clone()),
+            CallbackNotifier::new(callbacks),
+            config,
+        )
+    }
+
+    // A running scan that reports to `callback_url`
+    async fn running_scan(scheduler: &SchedulerService, callback_url: String) -> Uuid {
+        let now = Utc::now();
+        let scan = Scan {
+            id: Uuid::new_v4(),
+            name: "callbacks".to_string(),
+            description: None,
+            status: ScanStatus::Running,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+            started_at: Some(now),
+            completed_at: None,
+            priority: 5,
+            tags: Vec::new(),
+            metadata: HashMap::new(),
+            error_message: None,
+            progress: Some(50),
+            estimated_completion_time: None,
+            callback_url: Some(callback_url),
+            callback_disabled: false,
+            retention_days: None,
+            legal_hold: false,
+        };
+        scheduler.scan_repo.create_scan(&scan).await.unwrap();
+        scan.id
+    }
+
+    // Waits for the background delivery to disable the scan's callback
+    async fn callback_disabled(scheduler: &SchedulerService, scan_id: Uuid) -> bool {
+        for _ in 0..50 {
+            let scan = scheduler.scan_repo.get_scan_by_id(scan_id).await.unwrap();
+            if scan.unwrap().callback_disabled {
+                return true;
             }
+            time::sleep(Duration::from_millis(100)).await;
         }
+        false
     }
 
-    // Check if scan is complete
-    let is_complete = scheduler.check_scan_completion(scan_id).await?;
+    // `sqlx::test` runs these against a throwaway database created on the
+    // server in DATABASE_URL, e.g. `cargo test -- --ignored`
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_completion_callback_is_disabled_once_retries_run_out(pool: DbPool) {
+        let (url, mut received) = mock_endpoint(500).await;
+        let scheduler = scheduler(pool, local_config());
+        let scan_id = running_scan(&scheduler, url).await;
+
+        scheduler.complete_scan(scan_id, true, None).await.unwrap();
 
-    if is_complete {
-        // Mark scan as complete
-        scheduler.complete_scan(scan_id, success, None).await?;
+        for _ in 0..local_config().max_attempts {
+            let request = received.recv().await.unwrap();
+            assert!(request.headers.contains("x-mirage-event: scan.completed"));
+        }
+        assert!(callback_disabled(&scheduler, scan_id).await);
+        assert!(received.try_recv().is_err());
     }
 
-    Ok(())
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_completion_callback_to_internal_address_is_refused(pool: DbPool) {
+        let (url, mut received) = mock_endpoint(200).await;
+        let callbacks = CallbackConfig {
+            allow_private_networks: false,
+            ..local_config()
+        };
+        let scheduler = scheduler(pool, callbacks);
+        let scan_id = running_scan(&scheduler, url).await;
+
+        scheduler.complete_scan(scan_id, true, None).await.unwrap();
+
+        assert!(callback_disabled(&scheduler, scan_id).await);
+        assert!(received.try_recv().is_err());
+    }
 }
@@ -0,0 +1,241 @@
+//! Recurring scans
+//!
+//! A schedule runs a scan each time its cron expression fires. The scheduled
+//! scan is only the definition: each run copies it, with its targets and
+//! modules, into a new scan that is queued like any other, so every run keeps
+//! its own status and results. A schedule that missed several occurrences
+//! while the service was down runs once, then resumes from the next
+//! occurrence.
+//!
+//! Expressions use the `cron` crate's syntax, which starts with a seconds
+//! field. The usual five-field form is accepted too and fires at second 0.
+
+use crate::error::{ScannerError, ScannerResult};
+use crate::models::ScanSchedule;
+use crate::repositories::ScanRepository;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Parses a cron expression; invalid ones are validation errors
+pub fn parse_cron(expression: &str) -> ScannerResult<Schedule> {
+    let expression = expression.trim();
+    let with_seconds = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+
+    Schedule::from_str(&with_seconds).map_err(|e| {
+        ScannerError::Validation(format!("Invalid cron expression '{}': {}", expression, e))
+    })
+}
+
+/// The first occurrence of `expression` strictly after `after`
+pub fn next_run_after(
+    expression: &str,
+    after: DateTime<Utc>,
+) -> ScannerResult<Option<DateTime<Utc>>> {
+    Ok(parse_cron(expression)?.after(&after).next())
+}
+
+/// Starts a run of a scheduled scan
+#[async_trait]
+pub trait ScanLauncher: Send + Sync {
+    /// Returns the ID of the scan created for the run
+    async fn launch(&self, schedule: &ScanSchedule) -> ScannerResult<Uuid>;
+}
+
+fn is_due(schedule: &ScanSchedule, now: DateTime<Utc>) -> bool {
+    schedule.enabled && matches!(schedule.next_run_at, Some(at) if at <= now)
+}
+
+/// Launches the schedules due at `now` and returns them with their next run
+/// moved past `now`. A run that fails to launch is skipped rather than
+/// retried on every pass.
+pub async fn run_due_schedules(
+    schedules: Vec<ScanSchedule>,
+    launcher: &dyn ScanLauncher,
+    now: DateTime<Utc>,
+) -> Vec<ScanSchedule> {
+    let mut ran = Vec::new();
+
+    for mut schedule in schedules.into_iter().filter(|s| is_due(s, now)) {
+        match launcher.launch(&schedule).await {
+            Ok(scan_id) => {
+                tracing::info!(
+                    "Schedule {} started scan {} from scan {}",
+                    schedule.id,
+                    scan_id,
+                    schedule.scan_id
+                );
+                schedule.last_run_at = Some(now);
+                schedule.last_scan_id = Some(scan_id);
+            }
+            Err(e) => tracing::error!("Schedule {} failed to start a run: {}", schedule.id, e),
+        }
+
+        schedule.next_run_at = match next_run_after(&schedule.cron_expression, now) {
+            Ok(next_run_at) => next_run_at,
+            Err(e) => {
+                tracing::error!("Schedule {} has no next run: {}", schedule.id, e);
+                None
+            }
+        };
+        schedule.updated_at = now;
+        ran.push(schedule);
+    }
+
+    ran
+}
+
+/// Runs a single pass over the stored schedules and returns the number that
+/// came due
+pub async fn run_schedules(
+    scan_repo: &ScanRepository,
+    launcher: &dyn ScanLauncher,
+) -> ScannerResult<usize> {
+    let now = Utc::now();
+    let due = scan_repo.get_due_schedules(now).await?;
+    let ran = run_due_schedules(due, launcher, now).await;
+
+    for schedule in &ran {
+        if let Err(e) = scan_repo.update_schedule(schedule).await {
+            tracing::error!("Failed to save schedule {}: {}", schedule.id, e);
+        }
+    }
+
+    Ok(ran.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use std::sync::Mutex;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 11, 6, hour, minute, second)
+            .unwrap()
+    }
+
+    fn schedule(cron_expression: &str, next_run_at: DateTime<Utc>, enabled: bool) -> ScanSchedule {
+        ScanSchedule {
+            id: Uuid::new_v4(),
+            scan_id: Uuid::new_v4(),
+            cron_expression: cron_expression.to_string(),
+            enabled,
+            next_run_at: Some(next_run_at),
+            last_run_at: None,
+            last_scan_id: None,
+            created_at: next_run_at - Duration::days(1),
+            updated_at: next_run_at - Duration::days(1),
+        }
+    }
+
+    /// Records launched schedules instead of copying and queueing scans
+    #[derive(Default)]
+    struct MockLauncher {
+        launched: Mutex<Vec<Uuid>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl ScanLauncher for MockLauncher {
+        async fn launch(&self, schedule: &ScanSchedule) -> ScannerResult<Uuid> {
+            self.launched.lock().unwrap().push(schedule.id);
+            if self.fail {
+                return Err(ScannerError::Queue("queue unavailable".into()));
+            }
+            Ok(Uuid::new_v4())
+        }
+    }
+
+    #[test]
+    fn test_next_run_is_the_following_occurrence() {
+        assert_eq!(
+            next_run_after("*/15 * * * *", at(10, 7, 30)).unwrap(),
+            Some(at(10, 15, 0))
+        );
+        // Strictly after: a run at an occurrence moves on to the next one
+        assert_eq!(
+            next_run_after("*/15 * * * *", at(10, 15, 0)).unwrap(),
+            Some(at(10, 30, 0))
+        );
+        // 2023-11-06 is a Monday
+        assert_eq!(
+            next_run_after("0 30 9 * * Mon", at(10, 0, 0)).unwrap(),
+            Some(at(9, 30, 0) + Duration::weeks(1))
+        );
+        assert_eq!(
+            next_run_after("0 0 0 1 1 * 2020", at(10, 0, 0)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_five_field_expressions_fire_at_second_zero() {
+        let five = next_run_after("30 2 * * *", at(10, 0, 0)).unwrap();
+        let six = next_run_after("0 30 2 * * *", at(10, 0, 0)).unwrap();
+
+        assert_eq!(five, six);
+        assert_eq!(five, Some(at(2, 30, 0) + Duration::days(1)));
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_validation_errors() {
+        for expression in ["", "not a cron", "61 * * * *", "* * * *"] {
+            assert!(
+                matches!(parse_cron(expression), Err(ScannerError::Validation(_))),
+                "{:?} should be rejected",
+                expression
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_due_schedule_launches_a_run() {
+        let now = at(10, 0, 0);
+        let due = schedule("0 * * * *", now, true);
+        let later = schedule("0 * * * *", now + Duration::minutes(30), true);
+        let disabled = schedule("0 * * * *", now - Duration::minutes(5), false);
+        let launcher = MockLauncher::default();
+
+        let ran = run_due_schedules(vec![due.clone(), later, disabled], &launcher, now).await;
+
+        assert_eq!(*launcher.launched.lock().unwrap(), vec![due.id]);
+        assert_eq!(ran.len(), 1);
+        assert_eq!(ran[0].last_run_at, Some(now));
+        assert!(ran[0].last_scan_id.is_some());
+        assert_eq!(ran[0].next_run_at, Some(at(11, 0, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_missed_runs_launch_once() {
+        let now = at(10, 20, 0);
+        let overdue = schedule("0 * * * *", now - Duration::hours(3), true);
+        let launcher = MockLauncher::default();
+
+        let ran = run_due_schedules(vec![overdue], &launcher, now).await;
+
+        assert_eq!(launcher.launched.lock().unwrap().len(), 1);
+        assert_eq!(ran[0].next_run_at, Some(at(11, 0, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_launch_still_moves_to_the_next_run() {
+        let now = at(10, 0, 0);
+        let due = schedule("0 * * * *", now, true);
+        let launcher = MockLauncher {
+            fail: true,
+            ..Default::default()
+        };
+
+        let ran = run_due_schedules(vec![due], &launcher, now).await;
+
+        assert_eq!(ran[0].last_scan_id, None);
+        assert_eq!(ran[0].next_run_at, Some(at(11, 0, 0)));
+    }
+}
@@ -1,15 +1,21 @@
+use crate::callbacks;
 use crate::config::AppConfig;
 use crate::error::{ScannerError, ScannerResult};
+use crate::execution::ExecutionPlan;
 use crate::integrations::IntegrationService;
 use crate::models::{
-    CreateScanRequest, CreateTargetRequest, ModuleRequest, Scan, ScanDetailResponse, ScanModule,
-    ScanModuleResponse, ScanModuleStatus, ScanResponse, ScanStatus, ScanTarget, ScanTargetResponse,
-    ScanTargetStatus, UpdateScanRequest,
+    AddTargetRequest, CreateScanRequest, CreateScheduleRequest, CreateTargetRequest, ModuleRequest,
+    Scan, ScanDetailResponse, ScanModule, ScanModuleResponse, ScanModuleStatus, ScanProgress,
+    ScanResponse, ScanSchedule, ScanStatus, ScanTarget, ScanTargetResponse, ScanTargetStatus,
+    UpdateRetentionRequest, UpdateScanRequest, UpdateScheduleRequest,
 };
+use crate::progress;
 use crate::repositories::{ScanModuleRepository, ScanRepository, ScanTargetRepository};
 use crate::scheduler::SchedulerService;
+use crate::schedules;
 use chrono::Utc;
 use mirage_common::{Error, Result};
+use std::collections::HashSet;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -61,6 +67,17 @@ impl ScannerService {
             ));
         }
 
+        if let Some(url) = &request.callback_url {
+            callbacks::validate_callback_url(url, &self.config.callbacks)?;
+        }
+
+        validate_retention_days(request.retention_days)?;
+
+        let cron_expression = request.schedule.as_ref().and_then(|s| s.cron.clone());
+        if let Some(expression) = &cron_expression {
+            schedules::parse_cron(expression).map_err(|e| Error::from(e))?;
+        }
+
         // Generate scan ID
         let scan_id = Uuid::new_v4();
 
@@ -93,6 +110,10 @@ impl ScannerService {
             error_message: None,
             progress: None,
             estimated_completion_time: None,
+            callback_url: request.callback_url.clone(),
+            callback_disabled: false,
+            retention_days: request.retention_days,
+            legal_hold: request.legal_hold.unwrap_or(false),
         };
 
         // Store scan in database
@@ -125,6 +146,16 @@ impl ScannerService {
                 .map_err(|e| Error::from(e))?;
         }
 
+        // A cron schedule runs copies of the scan rather than the scan itself
+        if let Some(cron_expression) = cron_expression {
+            self.create_schedule(CreateScheduleRequest {
+                scan_id,
+                cron_expression,
+                enabled: None,
+            })
+            .await?;
+        }
+
         // Create response
         let response = ScanResponse {
             id: scan_id,
@@ -200,9 +231,14 @@ impl ScannerService {
                 status: m.status,
                 priority: m.priority,
                 depends_on: m.depends_on,
+                prerequisite_policy: m.prerequisite_policy,
             })
             .collect();
 
+        let default_retention_days = self.config.retention.default_days;
+        let effective_retention_days = scan.effective_retention_days(default_retention_days);
+        let expires_at = scan.expires_at(default_retention_days);
+
         // Create detail response
         let response = ScanDetailResponse {
             id: scan.id,
@@ -220,6 +256,12 @@ impl ScannerService {
             error_message: scan.error_message,
             progress: scan.progress,
             estimated_completion_time: scan.estimated_completion_time,
+            callback_url: scan.callback_url,
+            callback_disabled: scan.callback_disabled,
+            retention_days: scan.retention_days,
+            legal_hold: scan.legal_hold,
+            effective_retention_days,
+            expires_at,
             targets: target_responses,
             modules: module_responses,
         };
@@ -227,6 +269,43 @@ impl ScannerService {
         Ok(response)
     }
 
+    /// How far along a scan's collection tasks are, as of the scheduler's
+    /// last poll
+    pub async fn get_scan_progress(&self, scan_id: Uuid) -> Result<ScanProgress> {
+        self.scan_repo
+            .get_scan_by_id(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?
+            .ok_or_else(|| Error::NotFound(format!("Scan with ID {} not found", scan_id)))?;
+
+        let tasks = self
+            .target_repo
+            .get_tasks_for_scan(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        let targets = self
+            .target_repo
+            .get_targets_for_scan(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        let modules = self
+            .module_repo
+            .get_modules_for_scan(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        let enabled_modules = modules
+            .iter()
+            .filter(|m| m.status == ScanModuleStatus::Enabled)
+            .count();
+        let undispatched = progress::undispatched_tasks(&targets, &tasks, enabled_modules);
+        let statuses: Vec<_> = tasks.into_iter().map(|t| t.status).collect();
+
+        Ok(progress::scan_progress(scan_id, &statuses, undispatched))
+    }
+
     /// List scans with optional filtering
     pub async fn list_scans(
         &self,
@@ -367,6 +446,30 @@ impl ScannerService {
         Ok(response)
     }
 
+    /// Replace the retention override and legal hold of a scan. Unlike other
+    /// updates this is allowed in any status, since holds are typically placed
+    /// on finished scans.
+    pub async fn update_retention(
+        &self,
+        scan_id: Uuid,
+        request: UpdateRetentionRequest,
+    ) -> Result<ScanDetailResponse> {
+        validate_retention_days(request.retention_days)?;
+
+        self.scan_repo
+            .get_scan_by_id(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?
+            .ok_or_else(|| Error::NotFound(format!("Scan with ID {} not found", scan_id)))?;
+
+        self.scan_repo
+            .update_retention(scan_id, request.retention_days, request.legal_hold)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        self.get_scan(scan_id).await
+    }
+
     /// Start a scan (if it's in Created or Queued status)
     pub async fn start_scan(&self, scan_id: Uuid) -> Result<ScanResponse> {
         // Get current scan
@@ -421,29 +524,48 @@ impl ScannerService {
             .map_err(|e| Error::from(e))?
             .ok_or_else(|| Error::NotFound(format!("Scan with ID {} not found", scan_id)))?;
 
-        // Check if scan can be cancelled
-        if scan.status != ScanStatus::Created
-            && scan.status != ScanStatus::Queued
-            && scan.status != ScanStatus::Running
-        {
+        // Check if scan can be cancelled. Cancelling a cancelled scan again
+        // retries stopping its collection tasks.
+        if !scan.status.accepts_work() && scan.status != ScanStatus::Cancelled {
             return Err(Error::Validation(format!(
                 "Cannot cancel scan in status: {}. Only 'created', 'queued', or 'running' scans can be cancelled.",
                 serde_json::to_string(&scan.status).unwrap()
             )));
         }
 
-        // Update scan status
-        self.scan_repo
-            .update_scan_status(
-                scan_id,
-                ScanStatus::Cancelled,
-                None,
-                Some(Utc::now()),
-                None,
-                Some("Scan cancelled by user".to_string()),
-            )
+        if scan.status != ScanStatus::Cancelled {
+            // Update scan status, which stops the scheduler dispatching
+            // further targets
+            self.scan_repo
+                .update_scan_status(
+                    scan_id,
+                    ScanStatus::Cancelled,
+                    None,
+                    Some(Utc::now()),
+                    None,
+                    Some("Scan cancelled by user".to_string()),
+                )
+                .await
+                .map_err(|e| Error::from(e))?;
+
+            self.scheduler
+                .remove_queued_scan(scan_id)
+                .await
+                .map_err(|e| Error::from(e))?;
+        }
+
+        // Stop the collection tasks already dispatched. Results they have
+        // already collected are kept.
+        let cancelled_tasks = self
+            .integration
+            .cancel_collection_tasks(scan_id)
             .await
             .map_err(|e| Error::from(e))?;
+        tracing::info!(
+            "Cancelled {} collection tasks of scan {}",
+            cancelled_tasks,
+            scan_id
+        );
 
         // Return updated scan
         self.get_scan(scan_id).await.map(|detail| ScanResponse {
@@ -465,6 +587,170 @@ impl ScannerService {
         })
     }
 
+    /// Add targets to a scan that hasn't finished, e.g. ones discovered while
+    /// it runs. A running scan dispatches them on the scheduler's next pass.
+    pub async fn add_targets(
+        &self,
+        scan_id: Uuid,
+        request: AddTargetRequest,
+    ) -> Result<Vec<ScanTargetResponse>> {
+        if request.targets.is_empty() {
+            return Err(Error::Validation(
+                "At least one target must be specified".into(),
+            ));
+        }
+
+        let scan = self
+            .scan_repo
+            .get_scan_by_id(scan_id)
+            .await
+            .map_err(|e| Error::from(e))?
+            .ok_or_else(|| Error::NotFound(format!("Scan with ID {} not found", scan_id)))?;
+
+        if !scan.status.accepts_work() {
+            return Err(Error::Validation(format!(
+                "Cannot add targets to scan in status: {}",
+                serde_json::to_string(&scan.status).unwrap()
+            )));
+        }
+
+        let targets = self.create_scan_targets(scan_id, &request.targets).await?;
+
+        for target in &targets {
+            self.target_repo
+                .create_target(target)
+                .await
+                .map_err(|e| Error::from(e))?;
+        }
+
+        // Scans that haven't started yet queue all their targets when they do
+        if scan.status == ScanStatus::Running {
+            self.scheduler
+                .enqueue_scan_targets(scan_id, &targets)
+                .await
+                .map_err(|e| Error::from(e))?;
+        }
+
+        Ok(targets
+            .into_iter()
+            .map(|t| ScanTargetResponse {
+                id: t.id,
+                target_type: t.target_type,
+                value: t.value,
+                status: t.status,
+                created_at: t.created_at,
+                started_at: t.started_at,
+                completed_at: t.completed_at,
+                error_message: t.error_message,
+                result_count: t.result_count,
+                metadata: t.metadata,
+            })
+            .collect())
+    }
+
+    /// Schedule recurring runs of a scan
+    pub async fn create_schedule(&self, request: CreateScheduleRequest) -> Result<ScanSchedule> {
+        let now = Utc::now();
+        let next_run_at =
+            schedules::next_run_after(&request.cron_expression, now).map_err(|e| Error::from(e))?;
+
+        self.scan_repo
+            .get_scan_by_id(request.scan_id)
+            .await
+            .map_err(|e| Error::from(e))?
+            .ok_or_else(|| {
+                Error::NotFound(format!("Scan with ID {} not found", request.scan_id))
+            })?;
+
+        let schedule = ScanSchedule {
+            id: Uuid::new_v4(),
+            scan_id: request.scan_id,
+            cron_expression: request.cron_expression.trim().to_string(),
+            enabled: request.enabled.unwrap_or(true),
+            next_run_at,
+            last_run_at: None,
+            last_scan_id: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.scan_repo
+            .create_schedule(&schedule)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        Ok(schedule)
+    }
+
+    pub async fn get_schedule(&self, schedule_id: Uuid) -> Result<ScanSchedule> {
+        self.scan_repo
+            .get_schedule(schedule_id)
+            .await
+            .map_err(|e| Error::from(e))?
+            .ok_or_else(|| Error::NotFound(format!("Schedule with ID {} not found", schedule_id)))
+    }
+
+    pub async fn list_schedules(&self, scan_id: Option<Uuid>) -> Result<Vec<ScanSchedule>> {
+        self.scan_repo
+            .list_schedules(scan_id)
+            .await
+            .map_err(|e| Error::from(e))
+    }
+
+    /// Update a schedule. Changing the expression or re-enabling the schedule
+    /// starts counting from now, so runs missed meanwhile are not made up.
+    pub async fn update_schedule(
+        &self,
+        schedule_id: Uuid,
+        request: UpdateScheduleRequest,
+    ) -> Result<ScanSchedule> {
+        let mut schedule = self.get_schedule(schedule_id).await?;
+        let now = Utc::now();
+        let mut reschedule = false;
+
+        if let Some(cron_expression) = request.cron_expression {
+            schedules::parse_cron(&cron_expression).map_err(|e| Error::from(e))?;
+            schedule.cron_expression = cron_expression.trim().to_string();
+            reschedule = true;
+        }
+
+        if let Some(enabled) = request.enabled {
+            reschedule |= enabled && !schedule.enabled;
+            schedule.enabled = enabled;
+        }
+
+        if reschedule {
+            schedule.next_run_at = schedules::next_run_after(&schedule.cron_expression, now)
+                .map_err(|e| Error::from(e))?;
+        }
+        schedule.updated_at = now;
+
+        self.scan_repo
+            .update_schedule(&schedule)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        Ok(schedule)
+    }
+
+    /// Delete a schedule; scans it already started are kept
+    pub async fn delete_schedule(&self, schedule_id: Uuid) -> Result<()> {
+        let deleted = self
+            .scan_repo
+            .delete_schedule(schedule_id)
+            .await
+            .map_err(|e| Error::from(e))?;
+
+        if !deleted {
+            return Err(Error::NotFound(format!(
+                "Schedule with ID {} not found",
+                schedule_id
+            )));
+        }
+
+        Ok(())
+    }
+
     // Helper methods
 
     /// Create scan targets from request
@@ -504,6 +790,7 @@ impl ScannerService {
         requests: &[ModuleRequest],
     ) -> Result<Vec<ScanModule>> {
         let mut modules = Vec::with_capacity(requests.len());
+        let requested: HashSet<Uuid> = requests.iter().map(|r| r.module_id).collect();
 
         for (index, request) in requests.iter().enumerate() {
             // Fetch module info to validate it exists
@@ -513,6 +800,26 @@ impl ScannerService {
                 .await
                 .map_err(|e| Error::from(e))?;
 
+            // Only dependencies that are part of this scan order its modules
+            let mut depends_on = request.depends_on.clone().unwrap_or_default();
+            if let Some(missing) = depends_on.iter().find(|id| !requested.contains(id)) {
+                return Err(Error::Validation(format!(
+                    "Module {} depends on module {}, which is not part of the scan",
+                    request.module_id, missing
+                )));
+            }
+
+            let registered = self
+                .integration
+                .get_module_dependencies(&request.module_id)
+                .await
+                .map_err(|e| Error::from(e))?;
+            for id in registered {
+                if requested.contains(&id) && !depends_on.contains(&id) {
+                    depends_on.push(id);
+                }
+            }
+
             // Create module
             let module = ScanModule {
                 id: Uuid::new_v4(),
@@ -523,7 +830,8 @@ impl ScannerService {
                 status: ScanModuleStatus::Enabled,
                 parameters: request.parameters.clone().unwrap_or_default(),
                 priority: request.priority.unwrap_or((index as i32) + 1),
-                depends_on: request.depends_on.clone().unwrap_or_default(),
+                depends_on,
+                prerequisite_policy: request.prerequisite_policy.unwrap_or_default(),
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
             };
@@ -531,6 +839,18 @@ impl ScannerService {
             modules.push(module);
         }
 
+        // Rejects dependency cycles before the scan is stored
+        ExecutionPlan::new(&modules).map_err(|e| Error::from(e))?;
+
         Ok(modules)
     }
 }
+
+fn validate_retention_days(retention_days: Option<i32>) -> Result<()> {
+    match retention_days {
+        Some(days) if days < 1 => Err(Error::Validation(
+            "retention_days must be at least 1".into(),
+        )),
+        _ => Ok(()),
+    }
+}
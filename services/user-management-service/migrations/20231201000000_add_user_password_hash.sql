@@ -0,0 +1,3 @@
+-- Argon2id PHC strings. Users created before this have none and cannot log
+-- in until a password is set.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_hash TEXT;
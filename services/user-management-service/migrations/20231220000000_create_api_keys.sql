@@ -0,0 +1,18 @@
+-- Long-lived keys for automation clients. Only SHA-256 digests are kept;
+-- `prefix` is the start of the key, so owners can tell their keys apart.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    prefix VARCHAR(32) NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- Permissions the key is limited to; NULL for all of the owner's
+    scopes TEXT[],
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user_id ON api_keys(user_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_user_name_active ON api_keys(user_id, name)
+    WHERE revoked_at IS NULL;
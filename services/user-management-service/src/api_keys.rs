@@ -0,0 +1,34 @@
+//! API keys for automation clients
+//!
+//! A key stands in for its owner without the login and refresh dance of
+//! JWTs. It is shown once, when minted; afterwards only its SHA-256 digest
+//! and a short prefix are kept. A key may be scoped to a subset of the
+//! owner's permissions, and stops working once revoked or once its owner is
+//! deactivated.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Marks a string as a Mirage API key, e.g. for secret scanners
+pub const KEY_PREFIX: &str = "mrg_";
+
+/// Characters of the key kept in the clear to identify it
+const DISPLAY_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// A fresh key and the digest to store for it
+pub fn new_key() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = format!("{}{}", KEY_PREFIX, hex::encode(bytes));
+    let digest = hash_key(&key);
+    (key, digest)
+}
+
+/// The part of a key that is stored and listed in the clear
+pub fn display_prefix(key: &str) -> &str {
+    &key[..DISPLAY_LEN.min(key.len())]
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
@@ -1,6 +1,7 @@
 use crate::models::{
-    CreateTeamRequest, CreateUserRequest, RoleModel, TeamModel, UpdateTeamRequest,
-    UpdateUserRequest,
+    ApiKeyIdentity, ApiKeyLookupRequest, ApiKeyModel, CreateApiKeyRequest, CreateRoleRequest,
+    CreateTeamRequest, CreateUserRequest, CreatedApiKey, LoginRequest, RoleModel, TeamModel,
+    UpdateRoleRequest, UpdateTeamRequest, UpdateUserRequest, UserPermissions,
 };
 use crate::services::UserService;
 use mirage_common::{models::User, Error};
@@ -9,17 +10,13 @@ use rocket::serde::json::{json, Json, Value};
 use rocket::State;
 use uuid::Uuid;
 
-// Helper to format errors consistently
+// Rocket counterpart of the actix `ResponseError` impl on `Error`: same
+// status and `{ error, message }` body
 fn error_response(error: mirage_common::Error) -> (Status, Value) {
-    let status = match &error {
-        Error::NotFound(_) => Status::NotFound,
-        Error::Validation(_) => Status::BadRequest,
-        Error::Authentication(_) => Status::Unauthorized,
-        Error::Authorization(_) => Status::Forbidden,
-        _ => Status::InternalServerError,
-    };
-
-    (status, json!({ "error": error.to_string() }))
+    if error.http_status() >= 500 {
+        tracing::error!("Request failed: {}", error);
+    }
+    (Status::new(error.http_status()), json!(error.body()))
 }
 
 // User Routes
@@ -72,6 +69,148 @@ async fn update_user(
         .map_err(error_response)
 }
 
+#[post("/login", data = "<credentials>")]
+async fn login(
+    credentials: Json<LoginRequest>,
+    service: &State<UserService>,
+) -> Result<Json<User>, (Status, Value)> {
+    service
+        .verify_credentials(credentials.into_inner())
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+#[post("/<id>/verification")]
+async fn resend_verification(
+    id: &str,
+    service: &State<UserService>,
+) -> Result<Status, (Status, Value)> {
+    let user_id = Uuid::parse_str(id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    service
+        .resend_verification(&user_id)
+        .await
+        .map(|_| Status::Accepted)
+        .map_err(error_response)
+}
+
+#[get("/<id>/permissions")]
+async fn get_user_permissions(
+    id: &str,
+    service: &State<UserService>,
+) -> Result<Json<UserPermissions>, (Status, Value)> {
+    let user_id = Uuid::parse_str(id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    service
+        .get_user_permissions(&user_id)
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+#[put("/<user_id>/roles/<role_id>")]
+async fn assign_role(
+    user_id: &str,
+    role_id: &str,
+    service: &State<UserService>,
+) -> Result<Status, (Status, Value)> {
+    let user_id = Uuid::parse_str(user_id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    let role_id = Uuid::parse_str(role_id)
+        .map_err(|_| error_response(Error::Validation("Invalid role ID format".to_string())))?;
+
+    service
+        .assign_role(&user_id, &role_id)
+        .await
+        .map(|_| Status::NoContent)
+        .map_err(error_response)
+}
+
+#[delete("/<user_id>/roles/<role_id>")]
+async fn unassign_role(
+    user_id: &str,
+    role_id: &str,
+    service: &State<UserService>,
+) -> Result<Status, (Status, Value)> {
+    let user_id = Uuid::parse_str(user_id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    let role_id = Uuid::parse_str(role_id)
+        .map_err(|_| error_response(Error::Validation("Invalid role ID format".to_string())))?;
+
+    service
+        .unassign_role(&user_id, &role_id)
+        .await
+        .map(|removed| {
+            if removed {
+                Status::NoContent
+            } else {
+                Status::NotFound
+            }
+        })
+        .map_err(error_response)
+}
+
+#[post("/<id>/api-keys", data = "<api_key>")]
+async fn create_api_key(
+    id: &str,
+    api_key: Json<CreateApiKeyRequest>,
+    service: &State<UserService>,
+) -> Result<(Status, Json<CreatedApiKey>), (Status, Value)> {
+    let user_id = Uuid::parse_str(id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    service
+        .create_api_key(&user_id, api_key.into_inner())
+        .await
+        .map(|created| (Status::Created, Json(created)))
+        .map_err(error_response)
+}
+
+#[get("/<id>/api-keys")]
+async fn get_api_keys(
+    id: &str,
+    service: &State<UserService>,
+) -> Result<Json<Vec<ApiKeyModel>>, (Status, Value)> {
+    let user_id = Uuid::parse_str(id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    service
+        .get_api_keys(&user_id)
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+#[delete("/<user_id>/api-keys/<key_id>")]
+async fn revoke_api_key(
+    user_id: &str,
+    key_id: &str,
+    service: &State<UserService>,
+) -> Result<Status, (Status, Value)> {
+    let user_id = Uuid::parse_str(user_id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    let key_id = Uuid::parse_str(key_id)
+        .map_err(|_| error_response(Error::Validation("Invalid API key ID format".to_string())))?;
+
+    service
+        .revoke_api_key(&user_id, &key_id)
+        .await
+        .map(|revoked| {
+            if revoked {
+                Status::NoContent
+            } else {
+                Status::NotFound
+            }
+        })
+        .map_err(error_response)
+}
+
 #[delete("/<id>")]
 async fn delete_user(id: &str, service: &State<UserService>) -> Result<Status, (Status, Value)> {
     let user_id = Uuid::parse_str(id)
@@ -90,6 +229,39 @@ async fn delete_user(id: &str, service: &State<UserService>) -> Result<Status, (
         .map_err(error_response)
 }
 
+// Verification Routes
+#[get("/verify?<token>")]
+async fn verify_email(
+    token: Option<&str>,
+    service: &State<UserService>,
+) -> Result<Json<User>, (Status, Value)> {
+    let token = token.ok_or_else(|| {
+        error_response(Error::Validation("Missing verification token".to_string()))
+    })?;
+
+    service
+        .verify_email(token)
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+// API Key Routes
+
+// Used by `mirage-middleware` to resolve `X-API-Key` headers. The key is
+// sent in the body so it stays out of access logs.
+#[post("/verify", data = "<lookup>")]
+async fn verify_api_key(
+    lookup: Json<ApiKeyLookupRequest>,
+    service: &State<UserService>,
+) -> Result<Json<ApiKeyIdentity>, (Status, Value)> {
+    service
+        .authenticate_api_key(&lookup.key)
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
 // Team Routes
 #[get("/")]
 async fn get_teams(service: &State<UserService>) -> Result<Json<Vec<TeamModel>>, (Status, Value)> {
@@ -201,6 +373,28 @@ async fn add_team_member(
         .map_err(error_response)
 }
 
+// Adding someone who is already a member succeeds without changing their
+// role, so clients can retry freely. The body, with the role, is optional.
+#[post("/<team_id>/members/<user_id>", data = "<data>")]
+async fn ensure_team_member(
+    team_id: &str,
+    user_id: &str,
+    data: Option<Json<TeamMemberRequest>>,
+    service: &State<UserService>,
+) -> Result<Status, (Status, Value)> {
+    let team_id = Uuid::parse_str(team_id)
+        .map_err(|_| error_response(Error::Validation("Invalid team ID format".to_string())))?;
+
+    let user_id = Uuid::parse_str(user_id)
+        .map_err(|_| error_response(Error::Validation("Invalid user ID format".to_string())))?;
+
+    service
+        .ensure_team_member(&team_id, &user_id, data.as_ref().map(|d| d.role.as_str()))
+        .await
+        .map(|added| if added { Status::Created } else { Status::Ok })
+        .map_err(error_response)
+}
+
 #[delete("/<team_id>/members/<user_id>")]
 async fn remove_team_member(
     team_id: &str,
@@ -249,7 +443,7 @@ async fn get_role(
 
 #[post("/", data = "<role>")]
 async fn create_role(
-    role: Json<RoleModel>,
+    role: Json<CreateRoleRequest>,
     service: &State<UserService>,
 ) -> Result<Json<RoleModel>, (Status, Value)> {
     service
@@ -262,21 +456,14 @@ async fn create_role(
 #[put("/<id>", data = "<role>")]
 async fn update_role(
     id: &str,
-    mut role: Json<RoleModel>,
+    role: Json<UpdateRoleRequest>,
     service: &State<UserService>,
 ) -> Result<Json<RoleModel>, (Status, Value)> {
     let role_id = Uuid::parse_str(id)
         .map_err(|_| error_response(Error::Validation("Invalid role ID format".to_string())))?;
 
-    // Ensure the ID in the path matches the ID in the body
-    if role.id != role_id {
-        return Err(error_response(Error::Validation(
-            "Role ID in URL must match ID in body".to_string(),
-        )));
-    }
-
     service
-        .update_role(role.into_inner())
+        .update_role(&role_id, role.into_inner())
         .await
         .map(Json)
         .map_err(error_response)
@@ -302,7 +489,29 @@ async fn delete_role(id: &str, service: &State<UserService>) -> Result<Status, (
 
 // Route collections
 pub fn user_routes() -> Vec<rocket::Route> {
-    routes![get_users, get_user, create_user, update_user, delete_user]
+    routes![
+        get_users,
+        get_user,
+        create_user,
+        update_user,
+        login,
+        resend_verification,
+        get_user_permissions,
+        assign_role,
+        unassign_role,
+        create_api_key,
+        get_api_keys,
+        revoke_api_key,
+        delete_user
+    ]
+}
+
+pub fn verification_routes() -> Vec<rocket::Route> {
+    routes![verify_email]
+}
+
+pub fn api_key_routes() -> Vec<rocket::Route> {
+    routes![verify_api_key]
 }
 
 pub fn team_routes() -> Vec<rocket::Route> {
@@ -314,6 +523,7 @@ pub fn team_routes() -> Vec<rocket::Route> {
         delete_team,
         get_team_members,
         add_team_member,
+        ensure_team_member,
         remove_team_member
     ]
 }
@@ -321,3 +531,534 @@ pub fn team_routes() -> Vec<rocket::Route> {
 pub fn role_routes() -> Vec<rocket::Route> {
     routes![get_roles, get_role, create_role, update_role, delete_role]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TeamMemberModel;
+    use crate::passwords::PasswordPolicy;
+    use crate::repositories::DbPool;
+    use crate::verification::VerificationConfig;
+    use rocket::local::asynchronous::Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    struct Fixture {
+        client: Client,
+        team_id: Uuid,
+        user_id: Uuid,
+    }
+
+    async fn client(service: UserService) -> Client {
+        let rocket = rocket::build()
+            .manage(service)
+            .mount("/", verification_routes())
+            .mount("/users", user_routes())
+            .mount("/teams", team_routes())
+            .mount("/roles", role_routes())
+            .mount("/api-keys", api_key_routes());
+        Client::tracked(rocket).await.unwrap()
+    }
+
+    // A team and an already verified user
+    async fn fixture(pool: DbPool) -> Fixture {
+        let verification = VerificationConfig {
+            enabled: false,
+            ..VerificationConfig::default()
+        };
+        let service = UserService::new(pool, PasswordPolicy::default(), verification);
+        let team_id = service
+            .create_team(CreateTeamRequest {
+                name: "Red team".to_string(),
+                description: "Offensive research".to_string(),
+            })
+            .await
+            .unwrap()
+            .id;
+        let user_id = service
+            .create_user(CreateUserRequest {
+                username: "analyst".to_string(),
+                email: "analyst@example.com".to_string(),
+                password: "correct horse battery staple".to_string(),
+                roles: vec!["analyst".to_string()],
+                is_active: true,
+            })
+            .await
+            .unwrap()
+            .id;
+
+        Fixture {
+            client: client(service).await,
+            team_id,
+            user_id,
+        }
+    }
+
+    async fn members(pool: &DbPool, team_id: Uuid) -> Vec<TeamMemberModel> {
+        sqlx::query_as!(
+            TeamMemberModel,
+            "SELECT team_id, user_id, role, joined_at FROM team_members WHERE team_id = $1",
+            team_id
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn create_role(client: &Client, name: &str, permissions: &[&str]) -> Uuid {
+        let response = client
+            .post("/roles")
+            .json(&json!({
+                "name": name,
+                "description": format!("{} role", name),
+                "permissions": permissions
+            }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let role: RoleModel = response.into_json().await.unwrap();
+        role.id
+    }
+
+    async fn permissions(client: &Client, user_id: Uuid) -> UserPermissions {
+        let response = client
+            .get(format!("/users/{}/permissions", user_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.unwrap()
+    }
+
+    // These need a Postgres server in DATABASE_URL, where `sqlx::test`
+    // creates and migrates a scratch database for each test
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_member_is_added(pool: DbPool) {
+        let Fixture {
+            client,
+            team_id,
+            user_id,
+        } = fixture(pool.clone()).await;
+
+        let response = client
+            .post(format!("/teams/{}/members/{}", team_id, user_id))
+            .json(&json!({ "role": "admin" }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        let members = members(&pool, team_id).await;
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].user_id, user_id);
+        assert_eq!(members[0].role, "admin");
+
+        // Unknown users are not added
+        let response = client
+            .post(format!("/teams/{}/members/{}", team_id, Uuid::new_v4()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_member_is_removed(pool: DbPool) {
+        let Fixture {
+            client,
+            team_id,
+            user_id,
+        } = fixture(pool.clone()).await;
+        let uri = format!("/teams/{}/members/{}", team_id, user_id);
+        client.post(&uri).dispatch().await;
+
+        let response = client.delete(&uri).dispatch().await;
+        assert_eq!(response.status(), Status::NoContent);
+        assert!(members(&pool, team_id).await.is_empty());
+
+        let response = client.delete(&uri).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_adding_an_existing_member_again_changes_nothing(pool: DbPool) {
+        let Fixture {
+            client,
+            team_id,
+            user_id,
+        } = fixture(pool.clone()).await;
+        let uri = format!("/teams/{}/members/{}", team_id, user_id);
+
+        let response = client.post(&uri).dispatch().await;
+        assert_eq!(response.status(), Status::Created);
+        let before = members(&pool, team_id).await;
+        assert_eq!(before[0].role, "member");
+
+        let response = client
+            .post(&uri)
+            .json(&json!({ "role": "admin" }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let after = members(&pool, team_id).await;
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].role, "member");
+        assert_eq!(after[0].joined_at, before[0].joined_at);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_permissions_come_from_assigned_roles(pool: DbPool) {
+        let Fixture {
+            client, user_id, ..
+        } = fixture(pool).await;
+        let triage = create_role(&client, "triage", &["scans:read", "findings:read"]).await;
+        let responder =
+            create_role(&client, "responder", &["findings:read", "findings:write"]).await;
+
+        // The fixture's "analyst" role has no role record and grants nothing
+        assert!(permissions(&client, user_id).await.permissions.is_empty());
+
+        for role_id in [triage, responder, triage] {
+            let response = client
+                .put(format!("/users/{}/roles/{}", user_id, role_id))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::NoContent);
+        }
+
+        let granted = permissions(&client, user_id).await;
+        assert_eq!(granted.roles, vec!["analyst", "triage", "responder"]);
+        assert_eq!(
+            granted.permissions,
+            vec!["findings:read", "findings:write", "scans:read"]
+        );
+
+        let response = client
+            .delete(format!("/users/{}/roles/{}", user_id, responder))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(
+            permissions(&client, user_id).await.permissions,
+            vec!["findings:read", "scans:read"]
+        );
+
+        // Unknown roles cannot be assigned
+        let response = client
+            .put(format!("/users/{}/roles/{}", user_id, Uuid::new_v4()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_renamed_and_deleted_roles_follow_through_to_users(pool: DbPool) {
+        let Fixture {
+            client, user_id, ..
+        } = fixture(pool).await;
+        let role_id = create_role(&client, "triage", &["scans:read"]).await;
+        client
+            .put(format!("/users/{}/roles/{}", user_id, role_id))
+            .dispatch()
+            .await;
+
+        let response = client
+            .put(format!("/roles/{}", role_id))
+            .json(&json!({ "name": "first-line", "permissions": ["scans:read", "scans:write"] }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let granted = permissions(&client, user_id).await;
+        assert_eq!(granted.roles, vec!["analyst", "first-line"]);
+        assert_eq!(granted.permissions, vec!["scans:read", "scans:write"]);
+
+        let response = client
+            .delete(format!("/roles/{}", role_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+
+        let granted = permissions(&client, user_id).await;
+        assert_eq!(granted.roles, vec!["analyst"]);
+        assert!(granted.permissions.is_empty());
+    }
+
+    async fn lookup_api_key(client: &Client, key: &str) -> (Status, Value) {
+        let response = client
+            .post("/api-keys/verify")
+            .json(&json!({ "key": key }))
+            .dispatch()
+            .await;
+        (response.status(), response.into_json().await.unwrap())
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_api_keys_act_for_their_owner_until_revoked(pool: DbPool) {
+        let Fixture {
+            client, user_id, ..
+        } = fixture(pool).await;
+        let triage = create_role(&client, "triage", &["scans:read", "findings:read"]).await;
+        client
+            .put(format!("/users/{}/roles/{}", user_id, triage))
+            .dispatch()
+            .await;
+
+        let mut minted = Vec::new();
+        for body in [
+            json!({ "name": "ci" }),
+            json!({ "name": "dashboard", "scopes": ["findings:read", "findings:write"] }),
+        ] {
+            let response = client
+                .post(format!("/users/{}/api-keys", user_id))
+                .json(&body)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+            let created: CreatedApiKey = response.into_json().await.unwrap();
+            assert!(created.key.starts_with(&created.api_key.prefix));
+            minted.push(created);
+        }
+
+        // Names are unique among a user's live keys
+        let response = client
+            .post(format!("/users/{}/api-keys", user_id))
+            .json(&json!({ "name": "ci" }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let (status, identity) = lookup_api_key(&client, &minted[0].key).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(identity["user_id"], json!(user_id));
+        assert_eq!(identity["roles"], json!(["analyst", "triage"]));
+        assert_eq!(identity["scoped"], json!(false));
+        assert_eq!(
+            identity["permissions"],
+            json!(["findings:read", "scans:read"])
+        );
+
+        // Scopes narrow the owner's permissions but never add to them
+        let (status, identity) = lookup_api_key(&client, &minted[1].key).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(identity["scoped"], json!(true));
+        assert_eq!(identity["permissions"], json!(["findings:read"]));
+
+        let response = client
+            .delete(format!(
+                "/users/{}/api-keys/{}",
+                user_id, minted[0].api_key.id
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        let (status, _) = lookup_api_key(&client, &minted[0].key).await;
+        assert_eq!(status, Status::Unauthorized);
+        let (status, _) = lookup_api_key(&client, "mrg_not-a-key").await;
+        assert_eq!(status, Status::Unauthorized);
+
+        // Only digests are stored; listings show prefixes and revocations
+        let response = client
+            .get(format!("/users/{}/api-keys", user_id))
+            .dispatch()
+            .await;
+        let listed: Value = response.into_json().await.unwrap();
+        assert!(!listed.to_string().contains(&minted[1].key));
+        assert_eq!(listed.as_array().unwrap().len(), 2);
+        let ci = listed
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|k| k["name"] == "ci")
+            .unwrap();
+        assert!(!ci["revoked_at"].is_null());
+        assert!(!ci["last_used_at"].is_null());
+
+        // Deactivating the owner stops their keys too
+        let response = client
+            .put(format!("/users/{}", user_id))
+            .json(&json!({ "is_active": false }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let (status, _) = lookup_api_key(&client, &minted[1].key).await;
+        assert_eq!(status, Status::Unauthorized);
+    }
+
+    // Minimal notification service that accepts every request and reports
+    // its body
+    async fn mock_notifications() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let (body_start, length) = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+                        let length = headers
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        break (pos + 4, length);
+                    }
+                };
+                while buf.len() < body_start + length {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+
+                let _ = tx.send(serde_json::from_slice(&buf[body_start..]).unwrap());
+                let body =
+                    json!({ "notification_id": Uuid::new_v4(), "status": "Pending" }).to_string();
+                let response = format!(
+                    "HTTP/1.1 201 Created\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    // Registers a user with verification on, returning their ID and the
+    // token from the email that went out
+    async fn register(
+        client: &Client,
+        emails: &mut mpsc::UnboundedReceiver<Value>,
+    ) -> (Uuid, String) {
+        let response = client
+            .post("/users")
+            .json(&json!({
+                "username": "newcomer",
+                "email": "newcomer@example.com",
+                "password": "correct horse battery staple",
+                "roles": [],
+                "is_active": true
+            }))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let user: User = response.into_json().await.unwrap();
+        assert!(!user.is_active);
+
+        let email = emails.recv().await.unwrap();
+        assert_eq!(email["channels"][0]["channel"], "Email");
+        assert_eq!(email["channels"][0]["recipient"], "newcomer@example.com");
+        let link = email["data"]["verification_link"].as_str().unwrap();
+        assert!(email["custom_content"].as_str().unwrap().contains(link));
+        let token = link
+            .strip_prefix("https://mirage.example.com/api/v1/verify?token=")
+            .unwrap();
+
+        (user.id, token.to_string())
+    }
+
+    async fn verifying_client(pool: DbPool) -> (Client, mpsc::UnboundedReceiver<Value>) {
+        let (notification_url, emails) = mock_notifications().await;
+        let verification = VerificationConfig {
+            enabled: true,
+            notification_url,
+            link_base_url: "https://mirage.example.com/api/v1/".to_string(),
+            ..VerificationConfig::default()
+        };
+        let service = UserService::new(pool, PasswordPolicy::default(), verification);
+        (client(service).await, emails)
+    }
+
+    async fn login(client: &Client) -> Status {
+        client
+            .post("/users/login")
+            .json(&json!({
+                "username": "newcomer",
+                "password": "correct horse battery staple"
+            }))
+            .dispatch()
+            .await
+            .status()
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_verification_link_activates_the_account(pool: DbPool) {
+        let (client, mut emails) = verifying_client(pool).await;
+        let (user_id, token) = register(&client, &mut emails).await;
+
+        // Pending users can't log in
+        assert_eq!(login(&client).await, Status::Unauthorized);
+
+        let response = client
+            .get(format!("/verify?token={}", token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let user: User = response.into_json().await.unwrap();
+        assert_eq!(user.id, user_id);
+        assert!(user.is_active);
+        assert_eq!(login(&client).await, Status::Ok);
+
+        // Tokens work once
+        let response = client
+            .get(format!("/verify?token={}", token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .post(format!("/users/{}/verification", user_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server in DATABASE_URL"]
+    async fn test_expired_verification_link_is_refused(pool: DbPool) {
+        let (client, mut emails) = verifying_client(pool.clone()).await;
+        let (user_id, token) = register(&client, &mut emails).await;
+        sqlx::query!(
+            "UPDATE email_verification_tokens SET expires_at = NOW() - INTERVAL '1 second'"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let response = client
+            .get(format!("/verify?token={}", token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(login(&client).await, Status::Unauthorized);
+        let response = client.get(format!("/users/{}", user_id)).dispatch().await;
+        let user: User = response.into_json().await.unwrap();
+        assert!(!user.is_active);
+
+        // A resent link replaces the expired one
+        let response = client
+            .post(format!("/users/{}/verification", user_id))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Accepted);
+        let email = emails.recv().await.unwrap();
+        let link = email["data"]["verification_link"].as_str().unwrap();
+        let response = client
+            .get(link.replace("https://mirage.example.com/api/v1", ""))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(login(&client).await, Status::Ok);
+    }
+}
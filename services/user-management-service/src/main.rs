@@ -7,11 +7,14 @@ use rocket::serde::json::{json, Json, Value};
 use rocket::State;
 use rocket::{Build, Rocket};
 
+mod api_keys;
 mod config;
 mod handlers;
 mod models;
+mod passwords;
 mod repositories;
 mod services;
+mod verification;
 
 #[get("/health")]
 fn health_check() -> Value {
@@ -40,7 +43,11 @@ async fn rocket() -> Rocket<Build> {
         }
     };
 
-    let user_service = services::UserService::new(db_pool.clone());
+    let user_service = services::UserService::new(
+        db_pool.clone(),
+        config.password_policy.clone(),
+        config.verification.clone(),
+    );
 
     tracing::info!(
         "Starting User Management Service on port {}",
@@ -52,7 +59,9 @@ async fn rocket() -> Rocket<Build> {
         .manage(user_service)
         .manage(config)
         .mount("/api/v1", routes![health_check,])
+        .mount("/api/v1", handlers::verification_routes())
         .mount("/api/v1/users", handlers::user_routes())
         .mount("/api/v1/teams", handlers::team_routes())
         .mount("/api/v1/roles", handlers::role_routes())
+        .mount("/api/v1/api-keys", handlers::api_key_routes())
 }
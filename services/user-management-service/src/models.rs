@@ -8,8 +8,13 @@ pub struct UserModel {
     pub id: Uuid,
     pub username: String,
     pub email: String,
+    /// Argon2id PHC string; never serialized
+    #[serde(skip_serializing, default)]
+    pub password_hash: Option<String>,
     pub roles: Vec<String>,
     pub is_active: bool,
+    /// Unset while the user is pending email verification
+    pub email_verified_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -60,6 +65,7 @@ pub struct TeamMemberModel {
 pub struct CreateUserRequest {
     pub username: String,
     pub email: String,
+    pub password: String,
     pub roles: Vec<String>,
     pub is_active: bool,
 }
@@ -68,10 +74,17 @@ pub struct CreateUserRequest {
 pub struct UpdateUserRequest {
     pub username: Option<String>,
     pub email: Option<String>,
+    pub password: Option<String>,
     pub roles: Option<Vec<String>>,
     pub is_active: Option<bool>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTeamRequest {
     pub name: String,
@@ -84,6 +97,75 @@ pub struct UpdateTeamRequest {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRoleRequest {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRoleRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub permissions: Option<Vec<String>>,
+}
+
+/// Permissions a user holds through their roles
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserPermissions {
+    pub user_id: Uuid,
+    pub roles: Vec<String>,
+    /// Sorted, without duplicates
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    /// Start of the key, to tell keys apart
+    pub prefix: String,
+    /// Permissions the key is limited to; `None` for all of the owner's
+    pub scopes: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+}
+
+/// A newly minted key; the only time the key itself is returned
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKeyModel,
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyLookupRequest {
+    pub key: String,
+}
+
+/// Who a presented API key acts for, and with which permissions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyIdentity {
+    pub key_id: Uuid,
+    pub user_id: Uuid,
+    pub roles: Vec<String>,
+    /// Whether the key is limited to some of the owner's permissions
+    pub scoped: bool,
+    /// The owner's permissions, narrowed to the key's scopes. Sorted.
+    pub permissions: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddTeamMemberRequest {
     pub user_id: Uuid,
@@ -0,0 +1,183 @@
+//! Password hashing and policy
+//!
+//! Passwords are stored as Argon2id PHC strings, which carry their own salt
+//! and parameters, so hashes made with older parameters keep verifying.
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use mirage_common::{Error, Result};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+// Whether the rule is on, what it asks for, and the characters that satisfy it
+type Rule = (bool, &'static str, fn(char) -> bool);
+
+/// Rules a new password has to meet
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 12,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks `password` against every rule, naming all it breaks
+    pub fn validate(&self, password: &str) -> Result<()> {
+        let mut problems = Vec::new();
+        if password.chars().count() < self.min_length {
+            problems.push(format!("be at least {} characters long", self.min_length));
+        }
+        let rules: [Rule; 4] = [
+            (
+                self.require_uppercase,
+                "an uppercase letter",
+                char::is_uppercase,
+            ),
+            (
+                self.require_lowercase,
+                "a lowercase letter",
+                char::is_lowercase,
+            ),
+            (self.require_digit, "a digit", |c| c.is_ascii_digit()),
+            (self.require_symbol, "a symbol", |c| {
+                !c.is_alphanumeric() && !c.is_whitespace()
+            }),
+        ];
+        for (required, what, matches) in rules {
+            if required && !password.chars().any(matches) {
+                problems.push(format!("contain {}", what));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(format!(
+                "Password must {}",
+                problems.join(", ")
+            )))
+        }
+    }
+}
+
+/// Argon2id hash of `password` with a fresh salt
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| Error::Internal(format!("Failed to hash password: {}", e)))
+}
+
+/// Whether `password` matches `hash`. A user without a hash matches nothing.
+pub fn verify_password(password: &str, hash: Option<&str>) -> Result<bool> {
+    let Some(hash) = hash else {
+        // Spend the same time as a real check, so the response does not tell
+        // whether the account exists
+        let _ = check(password, dummy_hash());
+        return Ok(false);
+    };
+    check(password, hash)
+}
+
+fn check(password: &str, hash: &str) -> Result<bool> {
+    let parsed = PasswordHash::new(hash)
+        .map_err(|e| Error::Internal(format!("Stored password hash is invalid: {}", e)))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok())
+}
+
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password("not a password").unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserModel;
+    use chrono::Utc;
+    use mirage_common::models::User;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_stored_hash_verifies_the_original_password() {
+        let hash = hash_password("correct horse battery staple").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse battery staple", Some(&hash)).unwrap());
+        assert!(!verify_password("correct horse battery stapler", Some(&hash)).unwrap());
+        assert!(!verify_password("correct horse battery staple", None).unwrap());
+
+        // Salted, so the same password never hashes the same way twice
+        assert_ne!(hash, hash_password("correct horse battery staple").unwrap());
+    }
+
+    #[test]
+    fn test_hash_is_left_out_of_serialized_users() {
+        let hash = hash_password("correct horse battery staple").unwrap();
+        let model = UserModel {
+            id: Uuid::new_v4(),
+            username: "analyst".to_string(),
+            email: "analyst@example.com".to_string(),
+            password_hash: Some(hash.clone()),
+            roles: vec!["analyst".to_string()],
+            is_active: true,
+            email_verified_at: Some(Utc::now()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let serialized = serde_json::to_string(&model).unwrap();
+        assert!(!serialized.contains("password_hash"));
+        assert!(!serialized.contains(&hash));
+
+        let user: User = model.into();
+        let serialized = serde_json::to_value(&user).unwrap();
+        assert_eq!(serialized["username"], "analyst");
+        assert!(serialized.get("password_hash").is_none());
+    }
+
+    #[test]
+    fn test_policy_names_every_broken_rule() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: true,
+        };
+
+        assert!(policy.validate("Tr0ub4dor&3x").is_ok());
+        match policy.validate("short") {
+            Err(Error::Validation(message)) => assert_eq!(
+                message,
+                "Password must be at least 10 characters long, contain an uppercase letter, \
+                 contain a digit, contain a symbol"
+            ),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // Length counts characters, not bytes
+        let default = PasswordPolicy::default();
+        assert!(default.validate("pässwörd-äöü").is_ok());
+        assert!(default.validate("ääääää").is_err());
+    }
+}
@@ -1,18 +1,15 @@
 use crate::config::DatabaseConfig;
-use crate::models::{RoleModel, TeamMemberModel, TeamModel, UserModel};
-use mirage_common::{Error, Result};
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use crate::models::{ApiKeyModel, RoleModel, TeamMemberModel, TeamModel, UserModel};
+use chrono::{DateTime, Utc};
+use mirage_common::{database, Error, Result};
+use sqlx::{Pool, Postgres};
 use uuid::Uuid;
 
 pub type DbPool = Pool<Postgres>;
 
 /// Create database connection pool
 pub async fn create_db_pool(config: &DatabaseConfig) -> Result<DbPool> {
-    let pool = PgPoolOptions::new()
-        .max_connections(config.max_connections)
-        .connect(&config.url)
-        .await
-        .map_err(|e| Error::Database(format!("Database connection failed: {}", e)))?;
+    let pool = database::create_pool(config).await?;
 
     // Run migrations
     sqlx::migrate!("./migrations")
@@ -37,7 +34,7 @@ impl UserRepository {
         let users = sqlx::query_as!(
             UserModel,
             r#"
-            SELECT id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            SELECT id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             FROM users
             ORDER BY username
             LIMIT $1 OFFSET $2
@@ -56,7 +53,7 @@ impl UserRepository {
         let user = sqlx::query_as!(
             UserModel,
             r#"
-            SELECT id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            SELECT id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             FROM users
             WHERE id = $1
             "#,
@@ -73,15 +70,17 @@ impl UserRepository {
         let created = sqlx::query_as!(
             UserModel,
             r#"
-            INSERT INTO users (id, username, email, roles, is_active)
-            VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            INSERT INTO users (id, username, email, password_hash, roles, is_active, email_verified_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             "#,
             user.id,
             user.username,
             user.email,
+            user.password_hash,
             &user.roles as _,
-            user.is_active
+            user.is_active,
+            user.email_verified_at
         )
         .fetch_one(&self.pool)
         .await
@@ -95,13 +94,15 @@ impl UserRepository {
             UserModel,
             r#"
             UPDATE users
-            SET username = $2, email = $3, roles = $4, is_active = $5, updated_at = NOW()
+            SET username = $2, email = $3, password_hash = $4, roles = $5, is_active = $6,
+                updated_at = NOW()
             WHERE id = $1
-            RETURNING id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            RETURNING id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             "#,
             user.id,
             user.username,
             user.email,
+            user.password_hash,
             &user.roles as _,
             user.is_active
         )
@@ -131,7 +132,7 @@ impl UserRepository {
         let user = sqlx::query_as!(
             UserModel,
             r#"
-            SELECT id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            SELECT id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             FROM users
             WHERE username = $1
             "#,
@@ -148,7 +149,7 @@ impl UserRepository {
         let user = sqlx::query_as!(
             UserModel,
             r#"
-            SELECT id, username, email, roles as "roles: Vec<String>", is_active, created_at, updated_at
+            SELECT id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
@@ -160,6 +161,113 @@ impl UserRepository {
 
         Ok(user)
     }
+
+    /// Stores the digest of a new verification token for the user, replacing
+    /// any earlier unused one
+    pub async fn replace_verification_token(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx =
+            self.pool.begin().await.map_err(|e| {
+                Error::Database(format!("Failed to store verification token: {}", e))
+            })?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM email_verification_tokens
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to store verification token: {}", e)))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_verification_tokens (token_hash, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            token_hash,
+            user_id,
+            expires_at
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to store verification token: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to store verification token: {}", e)))
+    }
+
+    /// Uses up the token with this digest and activates its user, in one
+    /// statement so a token can't be spent twice. `None` when the token is
+    /// unknown, used or expired.
+    pub async fn verify_email(&self, token_hash: &str) -> Result<Option<UserModel>> {
+        let user = sqlx::query_as!(
+            UserModel,
+            r#"
+            WITH spent AS (
+                UPDATE email_verification_tokens
+                SET used_at = NOW()
+                WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+                RETURNING user_id
+            )
+            UPDATE users
+            SET is_active = TRUE, email_verified_at = NOW(), updated_at = NOW()
+            FROM spent
+            WHERE users.id = spent.user_id AND users.email_verified_at IS NULL
+            RETURNING id, username, email, password_hash, roles as "roles: Vec<String>", is_active, email_verified_at, created_at, updated_at
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to verify email: {}", e)))?;
+
+        Ok(user)
+    }
+
+    /// Gives the user `role` unless they already have it. Returns whether
+    /// the user changed.
+    pub async fn add_role(&self, id: &Uuid, role: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET roles = array_append(roles, $2), updated_at = NOW()
+            WHERE id = $1 AND NOT ($2 = ANY(roles))
+            "#,
+            id,
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to assign role: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Takes `role` away from the user. Returns whether they had it.
+    pub async fn remove_role(&self, id: &Uuid, role: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET roles = array_remove(roles, $2), updated_at = NOW()
+            WHERE id = $1 AND $2 = ANY(roles)
+            "#,
+            id,
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to unassign role: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
 }
 
 /// Team repository for database operations
@@ -262,11 +370,13 @@ impl TeamRepository {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Adds `member`, or changes the role of an existing member
     pub async fn add_member(&self, member: &TeamMemberModel) -> Result<()> {
         sqlx::query!(
             r#"
             INSERT INTO team_members (team_id, user_id, role, joined_at)
             VALUES ($1, $2, $3, $4)
+            ON CONFLICT (team_id, user_id) DO UPDATE SET role = EXCLUDED.role
             "#,
             member.team_id,
             member.user_id,
@@ -280,6 +390,27 @@ impl TeamRepository {
         Ok(())
     }
 
+    /// Adds `member` unless the user is already in the team, leaving an
+    /// existing membership as it is. Returns whether a row was inserted.
+    pub async fn add_member_if_absent(&self, member: &TeamMemberModel) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO team_members (team_id, user_id, role, joined_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (team_id, user_id) DO NOTHING
+            "#,
+            member.team_id,
+            member.user_id,
+            member.role,
+            member.joined_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to add team member: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn remove_member(&self, team_id: &Uuid, user_id: &Uuid) -> Result<bool> {
         let result = sqlx::query!(
             r#"
@@ -429,7 +560,26 @@ impl RoleRepository {
         Ok(role)
     }
 
+    /// Users refer to roles by name, so a rename is carried over to them
     pub async fn update(&self, role: &RoleModel) -> Result<RoleModel> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to update role: {}", e)))?;
+
+        let previous = sqlx::query_scalar!(
+            r#"
+            SELECT name FROM roles
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            role.id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to update role: {}", e)))?;
+
         let updated = sqlx::query_as!(
             RoleModel,
             r#"
@@ -443,25 +593,203 @@ impl RoleRepository {
             role.description,
             &role.permissions as _
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|e| Error::Database(format!("Failed to update role: {}", e)))?;
 
+        if previous != updated.name {
+            sqlx::query!(
+                r#"
+                UPDATE users
+                SET roles = array_replace(roles, $1, $2), updated_at = NOW()
+                WHERE $1 = ANY(roles)
+                "#,
+                previous,
+                updated.name
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to rename role on users: {}", e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to update role: {}", e)))?;
+
         Ok(updated)
     }
 
+    /// Deletes the role and takes it away from every user holding it
     pub async fn delete(&self, id: &Uuid) -> Result<bool> {
-        let result = sqlx::query!(
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to delete role: {}", e)))?;
+
+        let deleted = sqlx::query_scalar!(
             r#"
             DELETE FROM roles
             WHERE id = $1
+            RETURNING name
             "#,
             id
         )
-        .execute(&self.pool)
+        .fetch_optional(&mut *tx)
         .await
         .map_err(|e| Error::Database(format!("Failed to delete role: {}", e)))?;
 
+        if let Some(name) = &deleted {
+            sqlx::query!(
+                r#"
+                UPDATE users
+                SET roles = array_remove(roles, $1), updated_at = NOW()
+                WHERE $1 = ANY(roles)
+                "#,
+                name
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to remove role from users: {}", e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to delete role: {}", e)))?;
+
+        Ok(deleted.is_some())
+    }
+
+    /// Every permission granted by any of the roles named in `names`, sorted
+    /// and without duplicates. Names without a role grant nothing.
+    pub async fn permissions_for(&self, names: &[String]) -> Result<Vec<String>> {
+        let permissions = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT permission AS "permission!"
+            FROM roles, unnest(permissions) AS permission
+            WHERE name = ANY($1)
+            ORDER BY 1
+            "#,
+            names
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to resolve permissions: {}", e)))?;
+
+        Ok(permissions)
+    }
+}
+
+/// API key repository for database operations
+pub struct ApiKeyRepository {
+    pool: DbPool,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, api_key: &ApiKeyModel, key_hash: &str) -> Result<ApiKeyModel> {
+        let created = sqlx::query_as!(
+            ApiKeyModel,
+            r#"
+            INSERT INTO api_keys (id, user_id, name, prefix, key_hash, scopes, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, user_id, name, prefix, scopes as "scopes: Vec<String>", created_at, last_used_at, revoked_at
+            "#,
+            api_key.id,
+            api_key.user_id,
+            api_key.name,
+            api_key.prefix,
+            key_hash,
+            api_key.scopes.as_deref() as _,
+            api_key.created_at
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to create API key: {}", e)))?;
+
+        Ok(created)
+    }
+
+    /// The user's keys, revoked ones included, newest first
+    pub async fn find_by_user(&self, user_id: &Uuid) -> Result<Vec<ApiKeyModel>> {
+        let api_keys = sqlx::query_as!(
+            ApiKeyModel,
+            r#"
+            SELECT id, user_id, name, prefix, scopes as "scopes: Vec<String>", created_at, last_used_at, revoked_at
+            FROM api_keys
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch API keys: {}", e)))?;
+
+        Ok(api_keys)
+    }
+
+    pub async fn find_active_by_name(
+        &self,
+        user_id: &Uuid,
+        name: &str,
+    ) -> Result<Option<ApiKeyModel>> {
+        let api_key = sqlx::query_as!(
+            ApiKeyModel,
+            r#"
+            SELECT id, user_id, name, prefix, scopes as "scopes: Vec<String>", created_at, last_used_at, revoked_at
+            FROM api_keys
+            WHERE user_id = $1 AND name = $2 AND revoked_at IS NULL
+            "#,
+            user_id,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to fetch API key: {}", e)))?;
+
+        Ok(api_key)
+    }
+
+    /// Revokes one of the user's keys. Returns false if they have no such
+    /// key, or it is already revoked.
+    pub async fn revoke(&self, user_id: &Uuid, id: &Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to revoke API key: {}", e)))?;
+
         Ok(result.rows_affected() > 0)
     }
+
+    /// Finds the unrevoked key with this digest and records that it was
+    /// used
+    pub async fn touch(&self, key_hash: &str) -> Result<Option<ApiKeyModel>> {
+        let api_key = sqlx::query_as!(
+            ApiKeyModel,
+            r#"
+            UPDATE api_keys
+            SET last_used_at = NOW()
+            WHERE key_hash = $1 AND revoked_at IS NULL
+            RETURNING id, user_id, name, prefix, scopes as "scopes: Vec<String>", created_at, last_used_at, revoked_at
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to look up API key: {}", e)))?;
+
+        Ok(api_key)
+    }
 }
@@ -1,24 +1,43 @@
+use crate::api_keys;
 use crate::models::{
-    CreateTeamRequest, CreateUserRequest, RoleModel, TeamMemberModel, TeamModel, UpdateTeamRequest,
-    UpdateUserRequest, UserModel,
+    ApiKeyIdentity, ApiKeyModel, CreateApiKeyRequest, CreateRoleRequest, CreateTeamRequest,
+    CreateUserRequest, CreatedApiKey, LoginRequest, RoleModel, TeamMemberModel, TeamModel,
+    UpdateRoleRequest, UpdateTeamRequest, UpdateUserRequest, UserModel, UserPermissions,
 };
-use crate::repositories::{DbPool, RoleRepository, TeamRepository, UserRepository};
+use crate::passwords::{self, PasswordPolicy};
+use crate::repositories::{
+    ApiKeyRepository, DbPool, RoleRepository, TeamRepository, UserRepository,
+};
+use crate::verification::{self, VerificationConfig, VerificationMailer};
 use chrono::Utc;
 use mirage_common::{models::User, Error, Result};
 use uuid::Uuid;
 
+/// Role given to members added without one
+pub const DEFAULT_TEAM_ROLE: &str = "member";
+
 pub struct UserService {
     user_repo: UserRepository,
     team_repo: TeamRepository,
     role_repo: RoleRepository,
+    api_key_repo: ApiKeyRepository,
+    password_policy: PasswordPolicy,
+    mailer: VerificationMailer,
 }
 
 impl UserService {
-    pub fn new(pool: DbPool) -> Self {
+    pub fn new(
+        pool: DbPool,
+        password_policy: PasswordPolicy,
+        verification: VerificationConfig,
+    ) -> Self {
         Self {
             user_repo: UserRepository::new(pool.clone()),
             team_repo: TeamRepository::new(pool.clone()),
-            role_repo: RoleRepository::new(pool),
+            role_repo: RoleRepository::new(pool.clone()),
+            api_key_repo: ApiKeyRepository::new(pool),
+            password_policy,
+            mailer: VerificationMailer::new(verification),
         }
     }
 
@@ -48,21 +67,72 @@ impl UserService {
             return Err(Error::Validation("Email already registered".to_string()));
         }
 
-        // Create user model
+        self.password_policy.validate(&req.password)?;
+
+        // With verification on, the account stays pending until the emailed
+        // link is followed
+        let pending = self.mailer.config().enabled;
         let user = UserModel {
             id: Uuid::new_v4(),
             username: req.username,
             email: req.email,
+            password_hash: Some(passwords::hash_password(&req.password)?),
             roles: req.roles,
-            is_active: req.is_active,
+            is_active: req.is_active && !pending,
+            email_verified_at: if pending { None } else { Some(Utc::now()) },
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
 
         let created = self.user_repo.create(&user).await?;
+        if pending {
+            // The account exists either way; a failed email can be resent
+            if let Err(e) = self.send_verification(&created).await {
+                tracing::error!("Failed to send verification email to {}: {}", created.id, e);
+            }
+        }
         Ok(created.into())
     }
 
+    /// Emails the pending user a new verification link, invalidating any
+    /// earlier one
+    pub async fn resend_verification(&self, id: &Uuid) -> Result<()> {
+        let user = self
+            .user_repo
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("User with ID {} not found", id)))?;
+
+        if user.email_verified_at.is_some() {
+            return Err(Error::Validation("Email is already verified".to_string()));
+        }
+
+        self.send_verification(&user).await
+    }
+
+    /// Activates the account the verification token was sent for
+    pub async fn verify_email(&self, token: &str) -> Result<User> {
+        self.user_repo
+            .verify_email(&verification::hash_token(token))
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| {
+                Error::Validation(
+                    "Verification link is invalid, expired or already used".to_string(),
+                )
+            })
+    }
+
+    async fn send_verification(&self, user: &UserModel) -> Result<()> {
+        let (token, token_hash) = verification::new_token();
+        let expires_at = Utc::now() + self.mailer.config().token_ttl();
+        self.user_repo
+            .replace_verification_token(&user.id, &token_hash, expires_at)
+            .await?;
+
+        self.mailer.send(&user.username, &user.email, &token).await
+    }
+
     pub async fn update_user(&self, id: &Uuid, req: UpdateUserRequest) -> Result<User> {
         // Get existing user
         let mut user = self
@@ -92,6 +162,11 @@ impl UserService {
             user.email = email;
         }
 
+        if let Some(password) = req.password {
+            self.password_policy.validate(&password)?;
+            user.password_hash = Some(passwords::hash_password(&password)?);
+        }
+
         if let Some(roles) = req.roles {
             user.roles = roles;
         }
@@ -104,6 +179,22 @@ impl UserService {
         Ok(updated.into())
     }
 
+    /// The active user `req` names, if the password is theirs
+    pub async fn verify_credentials(&self, req: LoginRequest) -> Result<User> {
+        let user = self.user_repo.find_by_username(&req.username).await?;
+        let hash = user.as_ref().and_then(|u| u.password_hash.as_deref());
+
+        // Unknown users and wrong passwords get the same answer
+        match user {
+            Some(user) if passwords::verify_password(&req.password, hash)? && user.is_active => {
+                Ok(user.into())
+            }
+            _ => Err(Error::Unauthorized(
+                "Invalid username or password".to_string(),
+            )),
+        }
+    }
+
     pub async fn delete_user(&self, id: &Uuid) -> Result<bool> {
         self.user_repo.delete(id).await
     }
@@ -155,17 +246,7 @@ impl UserService {
     }
 
     pub async fn add_team_member(&self, team_id: &Uuid, user_id: &Uuid, role: &str) -> Result<()> {
-        // Verify team exists
-        self.team_repo
-            .find_by_id(team_id)
-            .await?
-            .ok_or_else(|| Error::NotFound(format!("Team with ID {} not found", team_id)))?;
-
-        // Verify user exists
-        self.user_repo
-            .find_by_id(user_id)
-            .await?
-            .ok_or_else(|| Error::NotFound(format!("User with ID {} not found", user_id)))?;
+        self.ensure_team_and_user(team_id, user_id).await?;
 
         let member = TeamMemberModel {
             team_id: *team_id,
@@ -177,7 +258,29 @@ impl UserService {
         self.team_repo.add_member(&member).await
     }
 
+    /// Adds the user to the team unless they are already in it, in which case
+    /// their membership and role are left alone. Returns whether they were
+    /// added.
+    pub async fn ensure_team_member(
+        &self,
+        team_id: &Uuid,
+        user_id: &Uuid,
+        role: Option<&str>,
+    ) -> Result<bool> {
+        self.ensure_team_and_user(team_id, user_id).await?;
+
+        let member = TeamMemberModel {
+            team_id: *team_id,
+            user_id: *user_id,
+            role: role.unwrap_or(DEFAULT_TEAM_ROLE).to_string(),
+            joined_at: Utc::now(),
+        };
+
+        self.team_repo.add_member_if_absent(&member).await
+    }
+
     pub async fn remove_team_member(&self, team_id: &Uuid, user_id: &Uuid) -> Result<bool> {
+        self.ensure_team_and_user(team_id, user_id).await?;
         self.team_repo.remove_member(team_id, user_id).await
     }
 
@@ -210,6 +313,20 @@ impl UserService {
         self.team_repo.get_user_teams(user_id).await
     }
 
+    async fn ensure_team_and_user(&self, team_id: &Uuid, user_id: &Uuid) -> Result<()> {
+        self.team_repo
+            .find_by_id(team_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Team with ID {} not found", team_id)))?;
+
+        self.user_repo
+            .find_by_id(user_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("User with ID {} not found", user_id)))?;
+
+        Ok(())
+    }
+
     // Role operations
     pub async fn get_roles(&self) -> Result<Vec<RoleModel>> {
         self.role_repo.find_all().await
@@ -222,15 +339,179 @@ impl UserService {
             .ok_or_else(|| Error::NotFound(format!("Role with ID {} not found", id)))
     }
 
-    pub async fn create_role(&self, role: RoleModel) -> Result<RoleModel> {
+    pub async fn create_role(&self, req: CreateRoleRequest) -> Result<RoleModel> {
+        if self.role_repo.find_by_name(&req.name).await?.is_some() {
+            return Err(Error::Validation(format!(
+                "Role '{}' already exists",
+                req.name
+            )));
+        }
+
+        let role = RoleModel {
+            id: Uuid::new_v4(),
+            name: req.name,
+            description: req.description,
+            permissions: req.permissions,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
         self.role_repo.create(&role).await
     }
 
-    pub async fn update_role(&self, role: RoleModel) -> Result<RoleModel> {
+    pub async fn update_role(&self, id: &Uuid, req: UpdateRoleRequest) -> Result<RoleModel> {
+        let mut role = self.get_role(id).await?;
+
+        if let Some(name) = req.name {
+            if name != role.name && self.role_repo.find_by_name(&name).await?.is_some() {
+                return Err(Error::Validation(format!("Role '{}' already exists", name)));
+            }
+            role.name = name;
+        }
+
+        if let Some(description) = req.description {
+            role.description = description;
+        }
+
+        if let Some(permissions) = req.permissions {
+            role.permissions = permissions;
+        }
+
         self.role_repo.update(&role).await
     }
 
     pub async fn delete_role(&self, id: &Uuid) -> Result<bool> {
         self.role_repo.delete(id).await
     }
+
+    /// Gives the user the role; assigning a role they already have is a no-op
+    pub async fn assign_role(&self, user_id: &Uuid, role_id: &Uuid) -> Result<()> {
+        self.get_user(user_id).await?;
+        let role = self.get_role(role_id).await?;
+
+        self.user_repo.add_role(user_id, &role.name).await?;
+        Ok(())
+    }
+
+    pub async fn unassign_role(&self, user_id: &Uuid, role_id: &Uuid) -> Result<bool> {
+        self.get_user(user_id).await?;
+        let role = self.get_role(role_id).await?;
+
+        self.user_repo.remove_role(user_id, &role.name).await
+    }
+
+    /// What the user may do: the union of their roles' permissions. Inactive
+    /// users hold none.
+    pub async fn get_user_permissions(&self, user_id: &Uuid) -> Result<UserPermissions> {
+        let user = self
+            .user_repo
+            .find_by_id(user_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("User with ID {} not found", user_id)))?;
+
+        let permissions = if user.is_active {
+            self.role_repo.permissions_for(&user.roles).await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(UserPermissions {
+            user_id: user.id,
+            roles: user.roles,
+            permissions,
+        })
+    }
+
+    // API key operations
+    pub async fn create_api_key(
+        &self,
+        user_id: &Uuid,
+        req: CreateApiKeyRequest,
+    ) -> Result<CreatedApiKey> {
+        self.get_user(user_id).await?;
+
+        let name = req.name.trim().to_string();
+        if name.is_empty() {
+            return Err(Error::Validation("API key name is required".to_string()));
+        }
+        if self
+            .api_key_repo
+            .find_active_by_name(user_id, &name)
+            .await?
+            .is_some()
+        {
+            return Err(Error::Validation(format!(
+                "An API key named '{}' already exists",
+                name
+            )));
+        }
+
+        let scopes = match req.scopes {
+            Some(scopes) => {
+                let mut scopes: Vec<String> = scopes.iter().map(|s| s.trim().to_string()).collect();
+                if scopes.is_empty() || scopes.iter().any(|s| s.is_empty()) {
+                    return Err(Error::Validation(
+                        "API key scopes must be non-empty permission names".to_string(),
+                    ));
+                }
+                scopes.sort();
+                scopes.dedup();
+                Some(scopes)
+            }
+            None => None,
+        };
+
+        let (key, key_hash) = api_keys::new_key();
+        let api_key = ApiKeyModel {
+            id: Uuid::new_v4(),
+            user_id: *user_id,
+            name,
+            prefix: api_keys::display_prefix(&key).to_string(),
+            scopes,
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+
+        let api_key = self.api_key_repo.create(&api_key, &key_hash).await?;
+        Ok(CreatedApiKey { api_key, key })
+    }
+
+    pub async fn get_api_keys(&self, user_id: &Uuid) -> Result<Vec<ApiKeyModel>> {
+        self.get_user(user_id).await?;
+        self.api_key_repo.find_by_user(user_id).await
+    }
+
+    pub async fn revoke_api_key(&self, user_id: &Uuid, key_id: &Uuid) -> Result<bool> {
+        self.api_key_repo.revoke(user_id, key_id).await
+    }
+
+    /// Who the key acts for. Unknown and revoked keys, and keys of inactive
+    /// users, are refused alike.
+    pub async fn authenticate_api_key(&self, key: &str) -> Result<ApiKeyIdentity> {
+        let refused = || Error::Unauthorized("Invalid API key".to_string());
+
+        let api_key = self
+            .api_key_repo
+            .touch(&api_keys::hash_key(key))
+            .await?
+            .ok_or_else(refused)?;
+        let owner = match self.user_repo.find_by_id(&api_key.user_id).await? {
+            Some(owner) if owner.is_active => owner,
+            _ => return Err(refused()),
+        };
+
+        let mut permissions = self.role_repo.permissions_for(&owner.roles).await?;
+        if let Some(scopes) = &api_key.scopes {
+            permissions.retain(|p| scopes.contains(p));
+        }
+
+        Ok(ApiKeyIdentity {
+            key_id: api_key.id,
+            user_id: owner.id,
+            roles: owner.roles,
+            scoped: api_key.scopes.is_some(),
+            permissions,
+        })
+    }
 }
@@ -0,0 +1,121 @@
+//! Email verification for new accounts
+//!
+//! A new user starts out pending: inactive and unverified, so they can
+//! neither log in nor hold permissions. Registration emails them a link,
+//! through the notification service, carrying a random single-use token;
+//! only its SHA-256 digest is stored. Following the link before the token
+//! expires activates the account.
+
+use chrono::Duration;
+use mirage_common::{Error, Result};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VerificationConfig {
+    /// When off, new users are verified as soon as they are created
+    pub enabled: bool,
+    pub token_ttl_secs: i64,
+    /// Base URL of the notification service
+    pub notification_url: String,
+    /// Public base URL the `/verify` link in the email is built on
+    pub link_base_url: String,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            token_ttl_secs: 24 * 60 * 60,
+            notification_url: "http://notification-service:8090".to_string(),
+            link_base_url: "http://localhost:8002/api/v1".to_string(),
+        }
+    }
+}
+
+impl VerificationConfig {
+    pub fn token_ttl(&self) -> Duration {
+        Duration::seconds(self.token_ttl_secs)
+    }
+}
+
+/// A fresh token and the digest to store for it
+pub fn new_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let digest = hash_token(&token);
+    (token, digest)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Sends verification emails through the notification service
+pub struct VerificationMailer {
+    client: reqwest::Client,
+    config: VerificationConfig,
+}
+
+impl VerificationMailer {
+    pub fn new(config: VerificationConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &VerificationConfig {
+        &self.config
+    }
+
+    pub async fn send(&self, username: &str, email: &str, token: &str) -> Result<()> {
+        let link = format!(
+            "{}/verify?token={}",
+            self.config.link_base_url.trim_end_matches('/'),
+            token
+        );
+        let hours = (self.config.token_ttl_secs / 3600).max(1);
+        let request = json!({
+            "notification_type": { "Custom": "email_verification" },
+            "channels": [{ "channel": "Email", "recipient": email }],
+            "data": { "username": username, "verification_link": link },
+            "custom_subject": "Verify your Mirage account",
+            "custom_content": format!(
+                "Hello {},\n\nConfirm your email address to activate your account:\n\n{}\n\n\
+                 The link expires in {} hour{} and works once.",
+                username,
+                link,
+                hours,
+                if hours == 1 { "" } else { "s" }
+            ),
+        });
+
+        let url = format!(
+            "{}/api/v1/notifications",
+            self.config.notification_url.trim_end_matches('/')
+        );
+        let response = self
+            .client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to reach notification service: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(Error::ExternalApi(format!(
+                "Notification service error: {} - {}",
+                status, error_text
+            )));
+        }
+
+        Ok(())
+    }
+}
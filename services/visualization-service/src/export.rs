@@ -0,0 +1,304 @@
+//! Graph export for desktop graph tools
+//!
+//! Serializes a correlation graph as GraphML (yEd, Gephi, NetworkX) or GEXF
+//! (Gephi). Nodes carry their label, entity type and value; edges carry the
+//! relationship kind. Edges are directed from source to target, as the
+//! correlation engine reports them.
+
+use crate::models::{GraphData, GraphEdge};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+const GRAPHML_NAMESPACE: &str = "http://graphml.graphdrawing.org/xmlns";
+const GEXF_NAMESPACE: &str = "http://gexf.net/1.3";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    GraphMl,
+    Gexf,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::GraphMl => "application/graphml+xml",
+            ExportFormat::Gexf => "application/gexf+xml",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::GraphMl => "graphml",
+            ExportFormat::Gexf => "gexf",
+        }
+    }
+
+    pub fn serialize(self, graph: &GraphData) -> String {
+        match self {
+            ExportFormat::GraphMl => to_graphml(graph),
+            ExportFormat::Gexf => to_gexf(graph),
+        }
+    }
+}
+
+/// GraphML document for `graph`
+pub fn to_graphml(graph: &GraphData) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    // Writing to a String cannot fail
+    let _ = writeln!(
+        xml,
+        "<graphml xmlns=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{} {}/1.0/graphml.xsd\">",
+        GRAPHML_NAMESPACE, XSI_NAMESPACE, GRAPHML_NAMESPACE, GRAPHML_NAMESPACE
+    );
+    for (id, target) in [
+        ("label", "node"),
+        ("type", "node"),
+        ("value", "node"),
+        ("relationship", "edge"),
+    ] {
+        let _ = writeln!(
+            xml,
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"string\"/>",
+            id, target, id
+        );
+    }
+    xml.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+
+    for node in &graph.nodes {
+        let _ = writeln!(xml, "    <node id=\"{}\">", escape(&node.id));
+        for (key, value) in [
+            ("label", &node.label),
+            ("type", &node.entity_type),
+            ("value", &node.value),
+        ] {
+            let _ = writeln!(xml, "      <data key=\"{}\">{}</data>", key, escape(value));
+        }
+        xml.push_str("    </node>\n");
+    }
+
+    for (index, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "    <edge id=\"{}\" source=\"{}\" target=\"{}\">",
+            escape(&edge_id(edge, index)),
+            escape(&edge.source),
+            escape(&edge.target)
+        );
+        let _ = writeln!(
+            xml,
+            "      <data key=\"relationship\">{}</data>",
+            escape(&edge.label)
+        );
+        xml.push_str("    </edge>\n");
+    }
+
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+/// GEXF 1.3 document for `graph`
+pub fn to_gexf(graph: &GraphData) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<gexf xmlns=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{} {}/gexf.xsd\" version=\"1.3\">",
+        GEXF_NAMESPACE, XSI_NAMESPACE, GEXF_NAMESPACE, GEXF_NAMESPACE
+    );
+    xml.push_str("  <meta>\n    <creator>Mirage</creator>\n  </meta>\n");
+    xml.push_str("  <graph mode=\"static\" defaultedgetype=\"directed\">\n");
+    xml.push_str("    <attributes class=\"node\">\n");
+    xml.push_str("      <attribute id=\"type\" title=\"type\" type=\"string\"/>\n");
+    xml.push_str("      <attribute id=\"value\" title=\"value\" type=\"string\"/>\n");
+    xml.push_str("    </attributes>\n");
+    xml.push_str("    <attributes class=\"edge\">\n");
+    xml.push_str("      <attribute id=\"relationship\" title=\"relationship\" type=\"string\"/>\n");
+    xml.push_str("    </attributes>\n");
+
+    xml.push_str("    <nodes>\n");
+    for node in &graph.nodes {
+        let _ = writeln!(
+            xml,
+            "      <node id=\"{}\" label=\"{}\">",
+            escape(&node.id),
+            escape(&node.label)
+        );
+        xml.push_str("        <attvalues>\n");
+        for (key, value) in [("type", &node.entity_type), ("value", &node.value)] {
+            let _ = writeln!(
+                xml,
+                "          <attvalue for=\"{}\" value=\"{}\"/>",
+                key,
+                escape(value)
+            );
+        }
+        xml.push_str("        </attvalues>\n      </node>\n");
+    }
+    xml.push_str("    </nodes>\n");
+
+    xml.push_str("    <edges>\n");
+    for (index, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "      <edge id=\"{}\" source=\"{}\" target=\"{}\" label=\"{}\">",
+            escape(&edge_id(edge, index)),
+            escape(&edge.source),
+            escape(&edge.target),
+            escape(&edge.label)
+        );
+        let _ = writeln!(
+            xml,
+            "        <attvalues>\n          <attvalue for=\"relationship\" value=\"{}\"/>\n        </attvalues>",
+            escape(&edge.label)
+        );
+        xml.push_str("      </edge>\n");
+    }
+    xml.push_str("    </edges>\n");
+
+    xml.push_str("  </graph>\n</gexf>\n");
+    xml
+}
+
+// Both formats need unique edge IDs; relationships without one get their
+// position instead
+fn edge_id(edge: &GraphEdge, index: usize) -> Cow<'_, str> {
+    if edge.id.is_empty() {
+        Cow::Owned(format!("e{}", index))
+    } else {
+        Cow::Borrowed(&edge.id)
+    }
+}
+
+// Escapes `value` for use as element text or an attribute value. Whitespace
+// other than spaces is written as character references so attribute values
+// keep it, and control characters XML cannot represent are dropped.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => {
+                let _ = write!(escaped, "&#{};", c as u32);
+            }
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GraphNode;
+    use std::collections::HashMap;
+
+    fn graph() -> GraphData {
+        let node = |id: &str, entity_type: &str, value: &str| GraphNode {
+            id: id.to_string(),
+            label: format!("{}: {}", entity_type, value),
+            entity_type: entity_type.to_string(),
+            value: value.to_string(),
+            properties: HashMap::new(),
+        };
+        let edge = |id: &str, source: &str, target: &str, label: &str| GraphEdge {
+            id: id.to_string(),
+            source: source.to_string(),
+            target: target.to_string(),
+            label: label.to_string(),
+            weight: None,
+            properties: HashMap::new(),
+        };
+
+        GraphData {
+            nodes: vec![
+                node("n1", "domain", "example.com"),
+                node("n2", "ip", "93.184.216.34"),
+                node("n3", "email", "\"Ops\" <ops@example.com>\n& co"),
+            ],
+            edges: vec![
+                edge("r1", "n1", "n2", "resolves_to"),
+                edge("", "n3", "n1", "registrant_of"),
+            ],
+        }
+    }
+
+    fn elements<'a>(
+        document: &'a roxmltree::Document,
+        namespace: &str,
+        name: &str,
+    ) -> Vec<roxmltree::Node<'a, 'a>> {
+        document
+            .descendants()
+            .filter(|n| n.tag_name().namespace() == Some(namespace) && n.tag_name().name() == name)
+            .collect()
+    }
+
+    #[test]
+    fn test_graphml_parses_with_every_node_and_edge() {
+        let xml = to_graphml(&graph());
+        let document = roxmltree::Document::parse(&xml).unwrap();
+
+        let root = document.root_element();
+        assert_eq!(root.tag_name().namespace(), Some(GRAPHML_NAMESPACE));
+        assert_eq!(root.tag_name().name(), "graphml");
+        assert_eq!(elements(&document, GRAPHML_NAMESPACE, "node").len(), 3);
+        let edges = elements(&document, GRAPHML_NAMESPACE, "edge");
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges[1].attribute("id"), Some("e1"));
+        assert_eq!(edges[1].attribute("source"), Some("n3"));
+
+        // Values come back exactly as they went in
+        let email = &elements(&document, GRAPHML_NAMESPACE, "node")[2];
+        let data: HashMap<_, _> = email
+            .children()
+            .filter(|n| n.is_element())
+            .map(|n| (n.attribute("key").unwrap(), n.text().unwrap_or_default()))
+            .collect();
+        assert_eq!(data["type"], "email");
+        assert_eq!(data["value"], "\"Ops\" <ops@example.com>\n& co");
+        assert_eq!(
+            edges[0].first_element_child().unwrap().text(),
+            Some("resolves_to")
+        );
+    }
+
+    #[test]
+    fn test_gexf_parses_with_every_node_and_edge() {
+        let xml = to_gexf(&graph());
+        let document = roxmltree::Document::parse(&xml).unwrap();
+
+        let root = document.root_element();
+        assert_eq!(root.tag_name().namespace(), Some(GEXF_NAMESPACE));
+        assert_eq!(root.attribute("version"), Some("1.3"));
+        let nodes = elements(&document, GEXF_NAMESPACE, "node");
+        assert_eq!(nodes.len(), 3);
+        let edges = elements(&document, GEXF_NAMESPACE, "edge");
+        assert_eq!(edges.len(), 2);
+
+        // Attribute values survive escaping, newline included
+        assert_eq!(
+            nodes[2].attribute("label"),
+            Some("email: \"Ops\" <ops@example.com>\n& co")
+        );
+        let attvalues: Vec<_> = elements(&document, GEXF_NAMESPACE, "attvalue")
+            .into_iter()
+            .filter(|n| n.attribute("for") == Some("relationship"))
+            .map(|n| n.attribute("value").unwrap())
+            .collect();
+        assert_eq!(attvalues, vec!["resolves_to", "registrant_of"]);
+        assert_eq!(edges[1].attribute("label"), Some("registrant_of"));
+    }
+
+    #[test]
+    fn test_unrepresentable_characters_are_dropped() {
+        assert_eq!(escape("a\u{0}b\u{1b}c\td"), "abc&#9;d");
+    }
+}
@@ -1,17 +1,25 @@
-use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse, Responder};
 use mirage_common::Error as CommonError;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::config::AppConfig;
+use crate::export::ExportFormat;
+use crate::layout;
+use crate::live::{self, LiveGraphs};
 use crate::models::{
-    ChartVisualizationRequest, GraphVisualizationRequest, ReportGenerationRequest,
+    ChartVisualizationRequest, GraphLayoutRequest, GraphVisualizationRequest,
+    ReportGenerationRequest, SubgraphQuery,
 };
 use crate::services::VisualizationService;
 
 pub fn visualization_routes() -> actix_web::Scope {
     web::scope("/visualizations")
         .service(create_graph)
+        .service(layout_graph)
+        .service(export_graph)
+        .service(get_subgraph)
+        .service(live_graph)
         .service(create_chart)
         .service(get_visualization)
         .service(render_visualization)
@@ -39,6 +47,114 @@ async fn create_graph(
     Ok(HttpResponse::Created().json(result))
 }
 
+#[post("/graph/layout")]
+async fn layout_graph(request: web::Json<GraphLayoutRequest>) -> Result<HttpResponse, Error> {
+    // The layout is CPU-bound, so it runs off the async workers
+    let result = web::block(move || layout::compute_layout(request.into_inner()))
+        .await?
+        .map_err(|e| match e {
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to lay out graph: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[get("/graph/{correlation_id}/export")]
+async fn export_graph(
+    correlation_id: web::Path<String>,
+    query: web::Query<ExportOptions>,
+    viz_service: web::Data<VisualizationService>,
+) -> Result<HttpResponse, Error> {
+    let correlation_id = Uuid::parse_str(&correlation_id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid correlation ID format"))?;
+    let format = query.format.unwrap_or_default();
+
+    let document = viz_service
+        .export_graph(correlation_id, format)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to export graph: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"correlation_{}.{}\"",
+                correlation_id,
+                format.extension()
+            ),
+        ))
+        .body(document))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportOptions {
+    /// `graphml` (the default) or `gexf`
+    format: Option<ExportFormat>,
+}
+
+#[get("/graph/{correlation_id}/subgraph")]
+async fn get_subgraph(
+    correlation_id: web::Path<String>,
+    query: web::Query<SubgraphQuery>,
+    viz_service: web::Data<VisualizationService>,
+) -> Result<HttpResponse, Error> {
+    let correlation_id = Uuid::parse_str(&correlation_id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid correlation ID format"))?;
+
+    let result = viz_service
+        .extract_subgraph(correlation_id, &query)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            CommonError::Validation(_) => actix_web::error::ErrorBadRequest(e),
+            _ => {
+                tracing::error!("Failed to extract subgraph: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+#[get("/graph/{correlation_id}/live")]
+async fn live_graph(
+    req: HttpRequest,
+    body: web::Payload,
+    correlation_id: web::Path<String>,
+    viz_service: web::Data<VisualizationService>,
+    live_graphs: web::Data<LiveGraphs>,
+) -> Result<HttpResponse, Error> {
+    let correlation_id = Uuid::parse_str(&correlation_id)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid correlation ID format"))?;
+
+    // Subscribed before the snapshot is read, so no change falls between
+    // the two
+    let subscription = live_graphs.subscribe(correlation_id);
+    let snapshot = viz_service
+        .correlation_graph(correlation_id)
+        .await
+        .map_err(|e| match e {
+            CommonError::NotFound(_) => actix_web::error::ErrorNotFound(e),
+            _ => {
+                tracing::error!("Failed to load graph for live updates: {}", e);
+                actix_web::error::ErrorInternalServerError(e)
+            }
+        })?;
+
+    live::connect(&req, body, subscription, snapshot)
+}
+
 #[post("/chart")]
 async fn create_chart(
     request: web::Json<ChartVisualizationRequest>,
@@ -0,0 +1,303 @@
+//! Force-directed graph layout
+//!
+//! Nodes are positioned with the Fruchterman-Reingold algorithm: every pair
+//! of nodes repels, every edge pulls its ends together, and the distance a
+//! node may move in one iteration shrinks until the layout settles. Nodes
+//! without a starting position begin on a circle around the centre, so the
+//! same request always gets the same layout. Pinned nodes push and pull the
+//! others but never move themselves.
+
+use crate::models::{GraphLayoutRequest, GraphLayoutResponse, PositionedNode};
+use mirage_common::Error;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+
+pub const DEFAULT_ITERATIONS: u32 = 300;
+pub const MAX_ITERATIONS: u32 = 2000;
+
+/// Each iteration compares every pair of nodes
+pub const MAX_NODES: usize = 2000;
+
+/// Width and height of the layout area unless the request sets them
+const DEFAULT_SIZE: f64 = 1000.0;
+
+// Nodes closer than this are treated as sitting on the same spot
+const MIN_DISTANCE: f64 = 0.01;
+
+/// Computes a position for every node in `request`
+pub fn compute_layout(request: GraphLayoutRequest) -> Result<GraphLayoutResponse, Error> {
+    let iterations = request.iterations.unwrap_or(DEFAULT_ITERATIONS);
+    if iterations > MAX_ITERATIONS {
+        return Err(Error::Validation(format!(
+            "At most {} iterations are allowed",
+            MAX_ITERATIONS
+        )));
+    }
+    if request.nodes.len() > MAX_NODES {
+        return Err(Error::Validation(format!(
+            "At most {} nodes can be laid out at once",
+            MAX_NODES
+        )));
+    }
+
+    let width = request.width.unwrap_or(DEFAULT_SIZE);
+    let height = request.height.unwrap_or(DEFAULT_SIZE);
+    if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+        return Err(Error::Validation(
+            "Width and height must be positive".into(),
+        ));
+    }
+
+    let mut index = HashMap::new();
+    for (i, node) in request.nodes.iter().enumerate() {
+        if index.insert(node.id.as_str(), i).is_some() {
+            return Err(Error::Validation(format!("Duplicate node '{}'", node.id)));
+        }
+        if [node.x, node.y].iter().flatten().any(|c| !c.is_finite()) {
+            return Err(Error::Validation(format!(
+                "Node '{}' has a non-finite position",
+                node.id
+            )));
+        }
+        if node.pinned && (node.x.is_none() || node.y.is_none()) {
+            return Err(Error::Validation(format!(
+                "Pinned node '{}' needs both x and y",
+                node.id
+            )));
+        }
+    }
+
+    let edges = request
+        .edges
+        .iter()
+        .map(|edge| {
+            let end = |id: &str| {
+                index.get(id).copied().ok_or_else(|| {
+                    Error::Validation(format!("Edge references unknown node '{}'", id))
+                })
+            };
+            Ok((end(&edge.source)?, end(&edge.target)?))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let nodes = request.nodes;
+    let count = nodes.len();
+    let radius = width.min(height) * 0.4;
+    let mut positions: Vec<(f64, f64)> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| match (node.x, node.y) {
+            (Some(x), Some(y)) => (x, y),
+            _ => {
+                let angle = TAU * i as f64 / count as f64;
+                (
+                    width / 2.0 + radius * angle.cos(),
+                    height / 2.0 + radius * angle.sin(),
+                )
+            }
+        })
+        .collect();
+
+    // Ideal edge length, spreading the nodes evenly over the area
+    let k = (width * height / count.max(1) as f64).sqrt();
+    let mut temperature = width.min(height) / 10.0;
+    let cooling = temperature / iterations.max(1) as f64;
+
+    for _ in 0..iterations {
+        let mut displacement = vec![(0.0, 0.0); count];
+
+        for i in 0..count {
+            for j in (i + 1)..count {
+                let (dx, dy, distance) = separation(&positions, i, j);
+                let force = k * k / distance;
+                let (fx, fy) = (dx / distance * force, dy / distance * force);
+                displacement[i].0 += fx;
+                displacement[i].1 += fy;
+                displacement[j].0 -= fx;
+                displacement[j].1 -= fy;
+            }
+        }
+
+        for &(source, target) in &edges {
+            if source == target {
+                continue;
+            }
+            let (dx, dy, distance) = separation(&positions, source, target);
+            let force = distance * distance / k;
+            let (fx, fy) = (dx / distance * force, dy / distance * force);
+            displacement[source].0 -= fx;
+            displacement[source].1 -= fy;
+            displacement[target].0 += fx;
+            displacement[target].1 += fy;
+        }
+
+        for (i, node) in nodes.iter().enumerate() {
+            if node.pinned {
+                continue;
+            }
+            let (dx, dy) = displacement[i];
+            let length = dx.hypot(dy);
+            if length > 0.0 {
+                let step = length.min(temperature);
+                let (x, y) = positions[i];
+                positions[i] = (
+                    (x + dx / length * step).clamp(0.0, width),
+                    (y + dy / length * step).clamp(0.0, height),
+                );
+            }
+        }
+
+        temperature -= cooling;
+    }
+
+    Ok(GraphLayoutResponse {
+        nodes: nodes
+            .into_iter()
+            .zip(positions)
+            .map(|(node, (x, y))| PositionedNode {
+                id: node.id,
+                x,
+                y,
+                pinned: node.pinned,
+                extra: node.extra,
+            })
+            .collect(),
+        width,
+        height,
+        iterations,
+    })
+}
+
+// Offset from node `j` to node `i` and its length. Nodes on the same spot
+// are split along a direction fixed by their indices, so they still push
+// apart and the layout stays deterministic.
+fn separation(positions: &[(f64, f64)], i: usize, j: usize) -> (f64, f64, f64) {
+    let dx = positions[i].0 - positions[j].0;
+    let dy = positions[i].1 - positions[j].1;
+    let distance = dx.hypot(dy);
+    if distance >= MIN_DISTANCE {
+        return (dx, dy, distance);
+    }
+
+    let angle = (i.min(j) * 31 + i.max(j)) as f64;
+    let sign = if i < j { 1.0 } else { -1.0 };
+    (
+        sign * MIN_DISTANCE * angle.cos(),
+        sign * MIN_DISTANCE * angle.sin(),
+        MIN_DISTANCE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: serde_json::Value) -> GraphLayoutRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn position(layout: &GraphLayoutResponse, id: &str) -> (f64, f64) {
+        let node = layout.nodes.iter().find(|node| node.id == id).unwrap();
+        (node.x, node.y)
+    }
+
+    fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+        (a.0 - b.0).hypot(a.1 - b.1)
+    }
+
+    #[test]
+    fn test_small_graph_gets_finite_separated_positions() {
+        let layout = compute_layout(request(json!({
+            "nodes": [
+                {"id": "example.com", "entity_type": "domain"},
+                {"id": "www.example.com"},
+                {"id": "93.184.216.34"},
+                {"id": "admin@example.com"},
+                {"id": "mail.example.com"},
+                // Both start on the same spot
+                {"id": "10.0.0.1", "x": 500.0, "y": 500.0},
+                {"id": "10.0.0.2", "x": 500.0, "y": 500.0}
+            ],
+            "edges": [
+                {"source": "example.com", "target": "www.example.com"},
+                {"source": "example.com", "target": "93.184.216.34"},
+                {"source": "www.example.com", "target": "93.184.216.34"},
+                {"source": "example.com", "target": "admin@example.com"},
+                {"source": "example.com", "target": "mail.example.com"},
+                {"source": "mail.example.com", "target": "10.0.0.1"},
+                {"source": "mail.example.com", "target": "10.0.0.2"}
+            ]
+        })))
+        .unwrap();
+
+        assert_eq!(layout.nodes.len(), 7);
+        assert_eq!(layout.iterations, DEFAULT_ITERATIONS);
+        for node in &layout.nodes {
+            assert!(node.x.is_finite() && node.y.is_finite(), "{:?}", node);
+            assert!((0.0..=layout.width).contains(&node.x), "{:?}", node);
+            assert!((0.0..=layout.height).contains(&node.y), "{:?}", node);
+        }
+        for (i, a) in layout.nodes.iter().enumerate() {
+            for b in &layout.nodes[i + 1..] {
+                let apart = distance((a.x, a.y), (b.x, b.y));
+                assert!(apart > 50.0, "{} and {} are {} apart", a.id, b.id, apart);
+            }
+        }
+
+        // Extra node fields come back unchanged
+        assert_eq!(layout.nodes[0].extra["entity_type"], json!("domain"));
+    }
+
+    #[test]
+    fn test_edges_pull_neighbours_closer_than_strangers() {
+        let layout = compute_layout(request(json!({
+            "nodes": [{"id": "a"}, {"id": "b"}, {"id": "c"}, {"id": "d"}, {"id": "e"}],
+            "edges": [
+                {"source": "a", "target": "b"},
+                {"source": "b", "target": "c"},
+                {"source": "c", "target": "d"},
+                {"source": "d", "target": "e"}
+            ]
+        })))
+        .unwrap();
+
+        let ab = distance(position(&layout, "a"), position(&layout, "b"));
+        let ae = distance(position(&layout, "a"), position(&layout, "e"));
+        assert!(ab < ae, "neighbours {} apart, ends {} apart", ab, ae);
+    }
+
+    #[test]
+    fn test_pinned_nodes_stay_put() {
+        let layout = compute_layout(request(json!({
+            "nodes": [
+                {"id": "hub", "x": 100.0, "y": 150.0, "pinned": true},
+                {"id": "leaf", "x": 900.0, "y": 900.0},
+                {"id": "other"}
+            ],
+            "edges": [{"source": "hub", "target": "leaf"}],
+            "iterations": 50
+        })))
+        .unwrap();
+
+        assert_eq!(position(&layout, "hub"), (100.0, 150.0));
+        assert!(layout.nodes[0].pinned);
+        // The free node it is tied to was pulled in
+        assert!(distance(position(&layout, "hub"), position(&layout, "leaf")) < 800.0);
+        assert_eq!(layout.iterations, 50);
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        for value in [
+            json!({"nodes": [{"id": "a", "pinned": true}]}),
+            json!({"nodes": [{"id": "a"}], "edges": [{"source": "a", "target": "b"}]}),
+            json!({"nodes": [{"id": "a"}, {"id": "a"}]}),
+            json!({"nodes": [{"id": "a"}], "iterations": MAX_ITERATIONS + 1}),
+            json!({"nodes": [{"id": "a"}], "width": 0.0}),
+        ] {
+            let err = compute_layout(request(value.clone())).unwrap_err();
+            assert!(matches!(err, Error::Validation(_)), "{}", value);
+        }
+    }
+}
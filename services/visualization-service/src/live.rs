@@ -0,0 +1,590 @@
+//! Live graph updates over WebSocket
+//!
+//! A client connecting to `/visualizations/graph/{correlation_id}/live` is
+//! sent the correlation's graph as a `snapshot`, then a `delta` each time
+//! the correlation engine reports a change to it. The engine publishes its
+//! events on `EVENTS_CHANNEL`, naming the correlation in
+//! `data.correlation_id`:
+//!
+//! - `entity_created` and `entity_updated` carry the node in `data.entity`
+//! - `relationship_created` carries the edge in `data.relationship`
+//! - the custom `entity_removed` and `relationship_removed` carry
+//!   `data.entity_id` and `data.relationship_id`
+//!
+//! Changes arriving within `COALESCE_WINDOW` of each other, or while the
+//! previous delta is still being sent, go out as one delta. A client more
+//! than `MAX_PENDING_CHANGES` behind, or that does not take a delta within
+//! `SEND_TIMEOUT`, is disconnected and can reconnect for a fresh snapshot.
+
+use crate::models::{GraphData, GraphEdge, GraphNode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use futures::StreamExt;
+use mirage_common::event::{Event, EventType};
+use mirage_common::shutdown::ShutdownSignal;
+use mirage_common::{Error, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Redis channel the correlation engine publishes graph events on
+pub const EVENTS_CHANNEL: &str = "mirage:correlation:events";
+
+/// Custom event types for removals
+pub const ENTITY_REMOVED: &str = "entity_removed";
+pub const RELATIONSHIP_REMOVED: &str = "relationship_removed";
+
+/// Time changes are collected for before a delta goes out
+const COALESCE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Changes a client may have queued before it is dropped
+const MAX_PENDING_CHANGES: usize = 10_000;
+
+/// Time a client has to take one message
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait before resubscribing after the Redis connection drops
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Message sent to a live client
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GraphUpdate {
+    Snapshot { graph: GraphData },
+    Delta(GraphDelta),
+}
+
+/// Changes to a graph. Removals apply before additions, removing a node
+/// removes its edges, and an added node or edge replaces any with its ID.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GraphDelta {
+    pub nodes_added: Vec<GraphNode>,
+    pub nodes_removed: Vec<String>,
+    pub edges_added: Vec<GraphEdge>,
+    pub edges_removed: Vec<String>,
+}
+
+impl GraphDelta {
+    /// The correlation `event` changes and the change, if it is a graph event
+    pub fn from_event(event: &Event) -> Option<(Uuid, GraphDelta)> {
+        let correlation_id = event.data["correlation_id"].as_str()?.parse().ok()?;
+
+        let mut delta = GraphDelta::default();
+        match &event.event_type {
+            EventType::EntityCreated | EventType::EntityUpdated => {
+                delta.add_node(GraphNode::from_correlation(event.data.get("entity")?))
+            }
+            EventType::RelationshipCreated => {
+                delta.add_edge(GraphEdge::from_correlation(event.data.get("relationship")?))
+            }
+            EventType::Custom(kind) if kind == ENTITY_REMOVED => {
+                delta.remove_node(event.data["entity_id"].as_str()?)
+            }
+            EventType::Custom(kind) if kind == RELATIONSHIP_REMOVED => {
+                delta.remove_edge(event.data["relationship_id"].as_str()?)
+            }
+            _ => return None,
+        }
+
+        Some((correlation_id, delta))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn len(&self) -> usize {
+        self.nodes_added.len()
+            + self.nodes_removed.len()
+            + self.edges_added.len()
+            + self.edges_removed.len()
+    }
+
+    /// Folds `later` into these changes, as if it had been applied after them
+    pub fn merge(&mut self, later: GraphDelta) {
+        for id in &later.nodes_removed {
+            self.remove_node(id);
+        }
+        for id in &later.edges_removed {
+            self.remove_edge(id);
+        }
+        for node in later.nodes_added {
+            self.add_node(node);
+        }
+        for edge in later.edges_added {
+            self.add_edge(edge);
+        }
+    }
+
+    fn add_node(&mut self, node: GraphNode) {
+        self.nodes_removed.retain(|id| *id != node.id);
+        self.nodes_added.retain(|added| added.id != node.id);
+        self.nodes_added.push(node);
+    }
+
+    fn remove_node(&mut self, id: &str) {
+        self.nodes_added.retain(|added| added.id != id);
+        self.edges_added
+            .retain(|added| added.source != id && added.target != id);
+        if !self.nodes_removed.iter().any(|removed| removed == id) {
+            self.nodes_removed.push(id.to_string());
+        }
+    }
+
+    fn add_edge(&mut self, edge: GraphEdge) {
+        self.edges_removed.retain(|id| *id != edge.id);
+        self.edges_added.retain(|added| added.id != edge.id);
+        self.edges_added.push(edge);
+    }
+
+    fn remove_edge(&mut self, id: &str) {
+        self.edges_added.retain(|added| added.id != id);
+        if !self.edges_removed.iter().any(|removed| removed == id) {
+            self.edges_removed.push(id.to_string());
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    delta: GraphDelta,
+    // Set once the client has fallen too far behind
+    lagging: bool,
+}
+
+#[derive(Default)]
+struct Client {
+    pending: Mutex<Pending>,
+    notify: Notify,
+}
+
+// Clients following one correlation, by client ID
+type Followers = HashMap<u64, Arc<Client>>;
+
+/// Live clients, by the correlation they follow
+#[derive(Clone, Default)]
+pub struct LiveGraphs {
+    clients: Arc<Mutex<HashMap<Uuid, Followers>>>,
+    next_client: Arc<AtomicU64>,
+}
+
+impl LiveGraphs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts queueing changes to `correlation_id` for a new client
+    pub fn subscribe(&self, correlation_id: Uuid) -> Subscription {
+        let id = self.next_client.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(Client::default());
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(correlation_id)
+            .or_default()
+            .insert(id, client.clone());
+
+        Subscription {
+            graphs: self.clone(),
+            correlation_id,
+            id,
+            client,
+        }
+    }
+
+    /// Queues `delta` for every client following `correlation_id`
+    pub fn publish(&self, correlation_id: Uuid, delta: &GraphDelta) {
+        let clients = self.clients.lock().unwrap();
+        for client in clients
+            .get(&correlation_id)
+            .into_iter()
+            .flat_map(|c| c.values())
+        {
+            let mut pending = client.pending.lock().unwrap();
+            if pending.lagging {
+                continue;
+            }
+            pending.delta.merge(delta.clone());
+            if pending.delta.len() > MAX_PENDING_CHANGES {
+                pending.lagging = true;
+                pending.delta = GraphDelta::default();
+            }
+            drop(pending);
+            client.notify.notify_one();
+        }
+    }
+
+    /// Publishes the change `event` makes, if any. Returns whether it was a
+    /// graph event.
+    pub fn apply_event(&self, event: &Event) -> bool {
+        match GraphDelta::from_event(event) {
+            Some((correlation_id, delta)) => {
+                self.publish(correlation_id, &delta);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies a message published on `EVENTS_CHANNEL`
+    pub fn apply_message(&self, payload: &str) -> Result<bool> {
+        let event: Event = serde_json::from_str(payload)
+            .map_err(|e| Error::Serialization(format!("Invalid correlation event: {}", e)))?;
+        Ok(self.apply_event(&event))
+    }
+
+    /// Follows correlation events until `shutdown`, resubscribing if Redis
+    /// goes away
+    pub async fn run(self, redis: redis::Client, mut shutdown: ShutdownSignal) {
+        while !shutdown.is_shutting_down() {
+            tokio::select! {
+                result = self.follow(&redis) => {
+                    if let Err(e) = result {
+                        tracing::warn!("Following correlation events failed: {}", e);
+                    }
+                }
+                _ = shutdown.recv() => break,
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                _ = shutdown.recv() => break,
+            }
+        }
+    }
+
+    async fn follow(&self, redis: &redis::Client) -> Result<()> {
+        let mut pubsub = redis
+            .get_async_connection()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to connect to Redis: {}", e)))?
+            .into_pubsub();
+        pubsub
+            .subscribe(EVENTS_CHANNEL)
+            .await
+            .map_err(|e| Error::Network(format!("Failed to subscribe to events: {}", e)))?;
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::warn!("Unreadable correlation event: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.apply_message(&payload) {
+                tracing::warn!("Ignoring correlation event: {}", e);
+            }
+        }
+
+        Err(Error::Network("Redis subscription closed".to_string()))
+    }
+}
+
+/// One client's queue of changes. Dropping it unsubscribes the client.
+pub struct Subscription {
+    graphs: LiveGraphs,
+    correlation_id: Uuid,
+    id: u64,
+    client: Arc<Client>,
+}
+
+impl Subscription {
+    async fn changed(&self) {
+        self.client.notify.notified().await
+    }
+
+    // Changes queued since the last call, or None once the client has
+    // fallen too far behind
+    fn take(&self) -> Option<GraphDelta> {
+        let mut pending = self.client.pending.lock().unwrap();
+        if pending.lagging {
+            None
+        } else {
+            Some(std::mem::take(&mut pending.delta))
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut clients = self.graphs.clients.lock().unwrap();
+        if let Some(followers) = clients.get_mut(&self.correlation_id) {
+            followers.remove(&self.id);
+            if followers.is_empty() {
+                clients.remove(&self.correlation_id);
+            }
+        }
+    }
+}
+
+/// Upgrades `req` to a WebSocket that is sent `snapshot`, then the changes
+/// queued on `subscription`
+pub fn connect(
+    req: &HttpRequest,
+    body: web::Payload,
+    subscription: Subscription,
+    snapshot: GraphData,
+) -> std::result::Result<HttpResponse, actix_web::Error> {
+    let (response, session, messages) = actix_ws::handle(req, body)?;
+    actix_web::rt::spawn(stream_updates(session, messages, subscription, snapshot));
+    Ok(response)
+}
+
+async fn stream_updates(
+    mut session: Session,
+    mut messages: MessageStream,
+    subscription: Subscription,
+    snapshot: GraphData,
+) {
+    let reason = if send(&mut session, &GraphUpdate::Snapshot { graph: snapshot }).await {
+        follow_updates(&mut session, &mut messages, &subscription).await
+    } else {
+        Some(too_slow())
+    };
+
+    // A client that is not reading may not take the close frame either
+    let _ = tokio::time::timeout(SEND_TIMEOUT, session.close(reason)).await;
+}
+
+// Sends deltas until the client leaves or falls behind, returning the
+// reason to close with
+async fn follow_updates(
+    session: &mut Session,
+    messages: &mut MessageStream,
+    subscription: &Subscription,
+) -> Option<CloseReason> {
+    loop {
+        tokio::select! {
+            message = messages.recv() => match message {
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return None;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => return reason,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    tracing::debug!("Live graph client sent an invalid frame: {}", e);
+                    return Some(CloseCode::Protocol.into());
+                }
+                None => return None,
+            },
+            _ = subscription.changed() => {
+                // Let a burst of changes settle into one delta
+                tokio::time::sleep(COALESCE_WINDOW).await;
+                let Some(delta) = subscription.take() else {
+                    return Some(too_slow());
+                };
+                if !delta.is_empty() && !send(session, &GraphUpdate::Delta(delta)).await {
+                    return Some(too_slow());
+                }
+            }
+        }
+    }
+}
+
+// Whether `update` was handed to the client in time
+async fn send(session: &mut Session, update: &GraphUpdate) -> bool {
+    let text = match serde_json::to_string(update) {
+        Ok(text) => text,
+        Err(e) => {
+            tracing::error!("Failed to serialize graph update: {}", e);
+            return false;
+        }
+    };
+
+    matches!(
+        tokio::time::timeout(SEND_TIMEOUT, session.text(text)).await,
+        Ok(Ok(()))
+    )
+}
+
+fn too_slow() -> CloseReason {
+    CloseReason {
+        code: CloseCode::Again,
+        description: Some("Too far behind; reconnect for a fresh snapshot".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpServer};
+    use futures::SinkExt;
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite;
+
+    fn node(id: &str) -> GraphNode {
+        GraphNode::from_correlation(&json!({"id": id, "entity_type": "domain", "value": id}))
+    }
+
+    fn edge(id: &str, source: &str, target: &str) -> GraphEdge {
+        GraphEdge::from_correlation(&json!({
+            "id": id,
+            "source_id": source,
+            "target_id": target,
+            "relationship_type": "resolves_to",
+            "strength": 0.8
+        }))
+    }
+
+    fn ids(nodes: &[GraphNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rapid_changes_coalesce_into_one_delta() {
+        let mut delta = GraphDelta::default();
+        delta.add_node(node("a"));
+        delta.add_node(node("b"));
+        delta.add_edge(edge("ab", "a", "b"));
+
+        let mut later = GraphDelta::default();
+        later.remove_node("b");
+        later.add_node(node("c"));
+        delta.merge(later);
+
+        let mut last = GraphDelta::default();
+        last.add_node(node("b"));
+        last.remove_edge("old");
+        delta.merge(last);
+
+        assert_eq!(ids(&delta.nodes_added), vec!["a", "c", "b"]);
+        assert!(delta.nodes_removed.is_empty());
+        // The edge went with its node
+        assert!(delta.edges_added.is_empty());
+        assert_eq!(delta.edges_removed, vec!["old"]);
+    }
+
+    #[test]
+    fn test_events_become_deltas_for_their_correlation() {
+        let correlation_id = Uuid::new_v4();
+        let created = Event::new(
+            EventType::EntityCreated,
+            "correlation-engine",
+            json!({
+                "correlation_id": correlation_id,
+                "entity": {"id": "n1", "entity_type": "ip", "value": "10.0.0.1"}
+            }),
+        );
+        let (id, delta) = GraphDelta::from_event(&created).unwrap();
+        assert_eq!(id, correlation_id);
+        assert_eq!(delta.nodes_added[0].label, "IP: 10.0.0.1");
+
+        let removed = Event::new(
+            EventType::Custom(RELATIONSHIP_REMOVED.to_string()),
+            "correlation-engine",
+            json!({"correlation_id": correlation_id, "relationship_id": "r1"}),
+        );
+        let (_, delta) = GraphDelta::from_event(&removed).unwrap();
+        assert_eq!(delta.edges_removed, vec!["r1"]);
+
+        // Not tied to a correlation, or not a graph change
+        let unrelated = Event::new(EventType::ScanStarted, "scanner", json!({}));
+        assert!(GraphDelta::from_event(&unrelated).is_none());
+        let no_correlation = Event::new(
+            EventType::EntityCreated,
+            "correlation-engine",
+            json!({"entity": {"id": "n1"}}),
+        );
+        assert!(GraphDelta::from_event(&no_correlation).is_none());
+    }
+
+    #[test]
+    fn test_client_too_far_behind_is_dropped() {
+        let graphs = LiveGraphs::new();
+        let correlation_id = Uuid::new_v4();
+        let subscription = graphs.subscribe(correlation_id);
+
+        for i in 0..=MAX_PENDING_CHANGES {
+            let mut delta = GraphDelta::default();
+            delta.add_node(node(&format!("n{}", i)));
+            graphs.publish(correlation_id, &delta);
+        }
+        assert!(subscription.take().is_none());
+
+        drop(subscription);
+        assert!(graphs.clients.lock().unwrap().is_empty());
+    }
+
+    async fn next_update(
+        socket: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+    ) -> Value {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no update within 5s")
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_client_gets_snapshot_then_deltas() {
+        let graphs = LiveGraphs::new();
+        let correlation_id = Uuid::new_v4();
+
+        let server_graphs = graphs.clone();
+        let server = HttpServer::new(move || {
+            let graphs = server_graphs.clone();
+            App::new().route(
+                "/live",
+                web::get().to(move |req: HttpRequest, body: web::Payload| {
+                    let subscription = graphs.subscribe(correlation_id);
+                    async move {
+                        let snapshot = GraphData {
+                            nodes: vec![node("a"), node("b")],
+                            edges: vec![edge("ab", "a", "b")],
+                        };
+                        connect(&req, body, subscription, snapshot)
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("ws://{}/live", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let snapshot = next_update(&mut socket).await;
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(snapshot["graph"]["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(snapshot["graph"]["edges"].as_array().unwrap().len(), 1);
+
+        // The correlation engine finds a new entity
+        let event = Event::new(
+            EventType::EntityCreated,
+            "correlation-engine",
+            json!({
+                "correlation_id": correlation_id,
+                "entity": {"id": "c", "entity_type": "domain", "value": "c.example.com"}
+            }),
+        );
+        assert!(graphs
+            .apply_message(&serde_json::to_string(&event).unwrap())
+            .unwrap());
+
+        let delta = next_update(&mut socket).await;
+        assert_eq!(delta["type"], "delta");
+        assert_eq!(delta["nodes_added"][0]["id"], "c");
+        assert!(delta["nodes_removed"].as_array().unwrap().is_empty());
+
+        socket
+            .send(tungstenite::Message::Close(None))
+            .await
+            .unwrap();
+        handle.stop(false).await;
+    }
+}
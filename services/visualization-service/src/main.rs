@@ -1,20 +1,28 @@
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+use mirage_common::health::{self, HealthChecker};
+use mirage_common::metrics::{self, Metrics};
+use mirage_common::shutdown::{self, Shutdown};
+use mirage_common::telemetry;
+use mirage_middleware::CorsPolicy;
 use tracing::info;
 
 mod error;
+mod export;
 mod handlers;
+mod layout;
+mod live;
 mod models;
 mod repositories;
 mod services;
-
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-}
+mod subgraph;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let _telemetry = telemetry::init("visualization-service");
 
     // Load configuration
     let config = match std::env::var("CONFIG_PATH") {
@@ -31,6 +39,9 @@ async fn main() -> std::io::Result<()> {
                 },
                 "correlation_engine": {
                     "url": "http://correlation-engine-service:8087"
+                },
+                "redis": {
+                    "url": "redis://redis:6379/0"
                 }
             })
         }
@@ -43,31 +54,74 @@ async fn main() -> std::io::Result<()> {
         .expect("Failed to create HTTP client");
 
     // Initialize visualization service
+    let correlation_engine_url = config["correlation_engine"]["url"]
+        .as_str()
+        .unwrap_or("http://localhost:8087")
+        .to_string();
     let viz_service = web::Data::new(services::VisualizationService::new(
-        http_client,
-        config["correlation_engine"]["url"]
-            .as_str()
-            .unwrap_or("http://localhost:8087")
-            .to_string(),
+        http_client.clone(),
+        correlation_engine_url.clone(),
     ));
 
+    // Stops the server, then the event follower, on SIGTERM or SIGINT
+    let shutdown = Shutdown::new(shutdown::DEFAULT_TIMEOUT);
+
+    // Live graph clients, fed from the correlation engine's events
+    let redis_url = config["redis"]["url"]
+        .as_str()
+        .unwrap_or("redis://redis:6379/0");
+    let redis_client = redis::Client::open(redis_url).map_err(std::io::Error::other)?;
+    let live_graphs = live::LiveGraphs::new();
+    let event_follower = tokio::spawn(live_graphs.clone().run(redis_client, shutdown.subscribe()));
+    let live_graphs = web::Data::new(live_graphs);
+
+    // Malformed origins are a startup error rather than a silently open policy
+    let cors_policy = CorsPolicy::from_env().map_err(std::io::Error::other)?;
+
+    // Dependency probes, served from /api/v1/health/ready
+    let health = web::Data::new(
+        HealthChecker::new("visualization-service", env!("CARGO_PKG_VERSION")).probe(
+            "correlation-engine-service",
+            health::http_probe(
+                http_client,
+                &format!("{}/api/v1/health", correlation_engine_url),
+            ),
+        ),
+    );
+
+    // Prometheus metrics, served from /metrics
+    let metrics =
+        web::Data::new(Metrics::new("visualization-service").map_err(std::io::Error::other)?);
+
     let port = config["server"]["port"].as_u64().unwrap_or(8088);
     let host = config["server"]["host"].as_str().unwrap_or("0.0.0.0");
 
     info!("Starting Visualization Service on {}:{}", host, port);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .app_data(metrics.clone())
+            .app_data(health.clone())
             .app_data(viz_service.clone())
+            .app_data(live_graphs.clone())
             .app_data(web::Data::new(config.clone()))
+            .wrap(cors_policy.build())
             .wrap(Logger::default())
+            .wrap(from_fn(metrics::track_requests))
+            .wrap(from_fn(telemetry::trace_requests))
+            .route("/metrics", web::get().to(metrics::render))
             .service(
                 web::scope("/api/v1")
-                    .route("/health", web::get().to(health_check))
+                    .route("/health", web::get().to(health::live))
+                    .route("/health/live", web::get().to(health::live))
+                    .route("/health/ready", web::get().to(health::ready))
                     .service(handlers::visualization_routes()),
             )
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs())
     .bind(format!("{}:{}", host, port))?
-    .run()
-    .await
+    .run();
+
+    shutdown.run(server, vec![event_follower]).await
 }
@@ -148,6 +148,38 @@ pub struct GraphNode {
     pub properties: HashMap<String, serde_json::Value>,
 }
 
+impl GraphNode {
+    /// Node for an entity as the correlation engine reports it
+    pub fn from_correlation(node: &serde_json::Value) -> Self {
+        let id = node["id"].as_str().unwrap_or_default().to_string();
+        let entity_type = node["entity_type"].as_str().unwrap_or_default().to_string();
+        let value = node["value"].as_str().unwrap_or_default().to_string();
+
+        let mut properties = HashMap::new();
+        if let Some(props) = node["properties"].as_object() {
+            for (k, v) in props {
+                properties.insert(k.clone(), v.clone());
+            }
+        }
+
+        // Create meaningful label based on entity type
+        let label = match entity_type.as_str() {
+            "domain" => format!("Domain: {}", value),
+            "ip" => format!("IP: {}", value),
+            "email" => format!("Email: {}", value),
+            _ => value.clone(),
+        };
+
+        GraphNode {
+            id,
+            label,
+            entity_type,
+            value,
+            properties,
+        }
+    }
+}
+
 /// Graph edge
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphEdge {
@@ -155,9 +187,41 @@ pub struct GraphEdge {
     pub source: String,
     pub target: String,
     pub label: String,
+    /// Strength of the relationship, as scored by the correlation engine
+    #[serde(default)]
+    pub weight: Option<f64>,
     pub properties: HashMap<String, serde_json::Value>,
 }
 
+impl GraphEdge {
+    /// Edge for a relationship as the correlation engine reports it
+    pub fn from_correlation(rel: &serde_json::Value) -> Self {
+        let id = rel["id"].as_str().unwrap_or_default().to_string();
+        let source = rel["source_id"].as_str().unwrap_or_default().to_string();
+        let target = rel["target_id"].as_str().unwrap_or_default().to_string();
+        let rel_type = rel["relationship_type"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut properties = HashMap::new();
+        if let Some(props) = rel["properties"].as_object() {
+            for (k, v) in props {
+                properties.insert(k.clone(), v.clone());
+            }
+        }
+
+        GraphEdge {
+            id,
+            source,
+            target,
+            label: rel_type,
+            weight: rel["strength"].as_f64(),
+            properties,
+        }
+    }
+}
+
 /// Graph data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphData {
@@ -165,6 +229,69 @@ pub struct GraphData {
     pub edges: Vec<GraphEdge>,
 }
 
+/// Subgraph extraction query
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubgraphQuery {
+    /// Node the subgraph is centred on
+    pub focus: String,
+    pub max_hops: Option<u32>,
+    /// Comma-separated entity types to keep
+    pub node_types: Option<String>,
+    pub min_weight: Option<f64>,
+}
+
+/// Node to lay out. Any fields besides these are returned unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutNode {
+    pub id: String,
+    /// Starting position; required for pinned nodes
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    /// Pinned nodes keep their position
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Edge pulling two nodes together. Any fields besides these are ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LayoutEdge {
+    pub source: String,
+    pub target: String,
+}
+
+/// Graph layout request
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphLayoutRequest {
+    pub nodes: Vec<LayoutNode>,
+    #[serde(default)]
+    pub edges: Vec<LayoutEdge>,
+    pub iterations: Option<u32>,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+}
+
+/// Node with its computed position
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionedNode {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub pinned: bool,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Graph layout response
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphLayoutResponse {
+    pub nodes: Vec<PositionedNode>,
+    pub width: f64,
+    pub height: f64,
+    pub iterations: u32,
+}
+
 /// Visualization result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VisualizationResult {
@@ -1,11 +1,13 @@
 use crate::config::AppConfig;
+use crate::export::ExportFormat;
 use crate::models::{
     ChartType, ChartVisualizationRequest, GraphData, GraphEdge, GraphNode,
-    GraphVisualizationRequest, ReportGenerationRequest, Visualization, VisualizationResponse,
-    VisualizationResult, VisualizationType,
+    GraphVisualizationRequest, ReportGenerationRequest, SubgraphQuery, Visualization,
+    VisualizationResponse, VisualizationResult, VisualizationType,
 };
 use crate::renderers::chart::ChartRenderer;
 use crate::renderers::graph::GraphRenderer;
+use crate::subgraph;
 use base64::{engine::general_purpose, Engine as _};
 use chrono::Utc;
 use mirage_common::Error;
@@ -249,6 +251,34 @@ impl VisualizationService {
         Ok(result)
     }
 
+    /// The graph of a correlation result
+    pub async fn correlation_graph(
+        &self,
+        correlation_id: Uuid,
+    ) -> Result<GraphData, mirage_common::Error> {
+        self.fetch_correlation_result(correlation_id).await
+    }
+
+    /// Exports the graph of a correlation result for desktop graph tools
+    pub async fn export_graph(
+        &self,
+        correlation_id: Uuid,
+        format: ExportFormat,
+    ) -> Result<String, mirage_common::Error> {
+        let graph_data = self.fetch_correlation_result(correlation_id).await?;
+        Ok(format.serialize(&graph_data))
+    }
+
+    /// The part of a correlation result's graph around one node
+    pub async fn extract_subgraph(
+        &self,
+        correlation_id: Uuid,
+        query: &SubgraphQuery,
+    ) -> Result<GraphData, mirage_common::Error> {
+        let graph_data = self.fetch_correlation_result(correlation_id).await?;
+        subgraph::extract(graph_data, query)
+    }
+
     // Internal helper methods
     async fn fetch_correlation_result(
         &self,
@@ -358,60 +388,14 @@ impl VisualizationService {
                     break; // Limit the number of nodes for visualization
                 }
 
-                let id = node["id"].as_str().unwrap_or_default().to_string();
-                let entity_type = node["entity_type"].as_str().unwrap_or_default().to_string();
-                let value = node["value"].as_str().unwrap_or_default().to_string();
-
-                let mut properties = std::collections::HashMap::new();
-                if let Some(props) = node["properties"].as_object() {
-                    for (k, v) in props {
-                        properties.insert(k.clone(), v.clone());
-                    }
-                }
-
-                // Create meaningful label based on entity type
-                let label = match entity_type.as_str() {
-                    "domain" => format!("Domain: {}", value),
-                    "ip" => format!("IP: {}", value),
-                    "email" => format!("Email: {}", value),
-                    _ => value.clone(),
-                };
-
-                nodes.push(GraphNode {
-                    id,
-                    label,
-                    entity_type,
-                    value,
-                    properties,
-                });
+                nodes.push(GraphNode::from_correlation(node));
             }
         }
 
         // Process edges
         if let Some(rels_array) = correlation_data["relationships"].as_array() {
             for rel in rels_array {
-                let id = rel["id"].as_str().unwrap_or_default().to_string();
-                let source = rel["source_id"].as_str().unwrap_or_default().to_string();
-                let target = rel["target_id"].as_str().unwrap_or_default().to_string();
-                let rel_type = rel["relationship_type"]
-                    .as_str()
-                    .unwrap_or_default()
-                    .to_string();
-
-                let mut properties = std::collections::HashMap::new();
-                if let Some(props) = rel["properties"].as_object() {
-                    for (k, v) in props {
-                        properties.insert(k.clone(), v.clone());
-                    }
-                }
-
-                edges.push(GraphEdge {
-                    id,
-                    source,
-                    target,
-                    label: rel_type.clone(),
-                    properties,
-                });
+                edges.push(GraphEdge::from_correlation(rel));
             }
         }
 
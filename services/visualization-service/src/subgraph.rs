@@ -0,0 +1,282 @@
+//! Cutting a large graph down to the part around one node
+//!
+//! Nodes of unwanted types and edges weaker than the minimum weight are
+//! dropped first, then the subgraph is whatever remains within `max_hops` of
+//! the focus node. Edges are followed in either direction: a domain is as
+//! relevant to its IP address as the IP address is to the domain. The focus
+//! node is kept whatever its type.
+
+use crate::models::{GraphData, SubgraphQuery};
+use mirage_common::utils::split_comma_separated;
+use mirage_common::Error;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub const DEFAULT_MAX_HOPS: u32 = 2;
+pub const MAX_HOPS: u32 = 10;
+
+/// The part of `graph` `query` asks for, in the graph's original order
+pub fn extract(graph: GraphData, query: &SubgraphQuery) -> Result<GraphData, Error> {
+    let max_hops = query.max_hops.unwrap_or(DEFAULT_MAX_HOPS);
+    if max_hops > MAX_HOPS {
+        return Err(Error::Validation(format!(
+            "At most {} hops are allowed",
+            MAX_HOPS
+        )));
+    }
+    if !graph.nodes.iter().any(|node| node.id == query.focus) {
+        return Err(Error::NotFound(format!(
+            "Node {} is not in the graph",
+            query.focus
+        )));
+    }
+
+    let node_types: Option<HashSet<String>> = query
+        .node_types
+        .as_deref()
+        .map(|types| split_comma_separated(types).into_iter().collect());
+    let kept: HashSet<&str> = graph
+        .nodes
+        .iter()
+        .filter(|node| {
+            node.id == query.focus
+                || node_types
+                    .as_ref()
+                    .is_none_or(|types| types.contains(&node.entity_type))
+        })
+        .map(|node| node.id.as_str())
+        .collect();
+    // Edges without a weight only pass when no minimum is set
+    let edge_kept: Vec<bool> = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            kept.contains(edge.source.as_str())
+                && kept.contains(edge.target.as_str())
+                && query
+                    .min_weight
+                    .is_none_or(|min| edge.weight.is_some_and(|weight| weight >= min))
+        })
+        .collect();
+
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (edge, _) in graph
+        .edges
+        .iter()
+        .zip(&edge_kept)
+        .filter(|(_, kept)| **kept)
+    {
+        neighbours
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(edge.target.as_str());
+        neighbours
+            .entry(edge.target.as_str())
+            .or_default()
+            .push(edge.source.as_str());
+    }
+
+    let mut reached: HashSet<&str> = HashSet::from([query.focus.as_str()]);
+    let mut queue = VecDeque::from([(query.focus.as_str(), 0)]);
+    while let Some((id, hops)) = queue.pop_front() {
+        if hops == max_hops {
+            continue;
+        }
+        for &next in neighbours.get(id).into_iter().flatten() {
+            if reached.insert(next) {
+                queue.push_back((next, hops + 1));
+            }
+        }
+    }
+
+    let reached: HashSet<String> = reached.into_iter().map(String::from).collect();
+    let GraphData { nodes, edges } = graph;
+    Ok(GraphData {
+        nodes: nodes
+            .into_iter()
+            .filter(|node| reached.contains(&node.id))
+            .collect(),
+        edges: edges
+            .into_iter()
+            .zip(edge_kept)
+            .filter(|(edge, kept)| {
+                *kept && reached.contains(&edge.source) && reached.contains(&edge.target)
+            })
+            .map(|(edge, _)| edge)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{GraphEdge, GraphNode};
+
+    // example.com -> www -> 93.184.216.34 <- mail.example.net -> ops@example.net
+    //      \-> admin@example.com (weak)
+    fn graph() -> GraphData {
+        let node = |id: &str, entity_type: &str| GraphNode {
+            id: id.to_string(),
+            label: id.to_string(),
+            entity_type: entity_type.to_string(),
+            value: id.to_string(),
+            properties: HashMap::new(),
+        };
+        let edge = |source: &str, target: &str, weight: f64| GraphEdge {
+            id: format!("{}->{}", source, target),
+            source: source.to_string(),
+            target: target.to_string(),
+            label: "related_to".to_string(),
+            weight: Some(weight),
+            properties: HashMap::new(),
+        };
+
+        GraphData {
+            nodes: vec![
+                node("example.com", "domain"),
+                node("www.example.com", "domain"),
+                node("93.184.216.34", "ip"),
+                node("mail.example.net", "domain"),
+                node("ops@example.net", "email"),
+                node("admin@example.com", "email"),
+                node("unrelated.org", "domain"),
+            ],
+            edges: vec![
+                edge("example.com", "www.example.com", 0.9),
+                edge("www.example.com", "93.184.216.34", 0.8),
+                edge("mail.example.net", "93.184.216.34", 0.7),
+                edge("mail.example.net", "ops@example.net", 0.9),
+                edge("example.com", "admin@example.com", 0.2),
+            ],
+        }
+    }
+
+    fn query(focus: &str) -> SubgraphQuery {
+        SubgraphQuery {
+            focus: focus.to_string(),
+            max_hops: None,
+            node_types: None,
+            min_weight: None,
+        }
+    }
+
+    fn node_ids(graph: &GraphData) -> Vec<&str> {
+        graph.nodes.iter().map(|node| node.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_extraction_stops_at_max_hops() {
+        let subgraph = extract(graph(), &query("www.example.com")).unwrap();
+        assert_eq!(
+            node_ids(&subgraph),
+            vec![
+                "example.com",
+                "www.example.com",
+                "93.184.216.34",
+                "mail.example.net",
+                "admin@example.com"
+            ]
+        );
+        // The edge on to ops@example.net is three hops out
+        assert_eq!(subgraph.edges.len(), 4);
+
+        let one_hop = extract(
+            graph(),
+            &SubgraphQuery {
+                max_hops: Some(1),
+                ..query("93.184.216.34")
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            node_ids(&one_hop),
+            vec!["www.example.com", "93.184.216.34", "mail.example.net"]
+        );
+
+        let focus_only = extract(
+            graph(),
+            &SubgraphQuery {
+                max_hops: Some(0),
+                ..query("example.com")
+            },
+        )
+        .unwrap();
+        assert_eq!(node_ids(&focus_only), vec!["example.com"]);
+        assert!(focus_only.edges.is_empty());
+    }
+
+    #[test]
+    fn test_type_filter_keeps_only_matching_nodes_and_the_focus() {
+        let subgraph = extract(
+            graph(),
+            &SubgraphQuery {
+                max_hops: Some(5),
+                node_types: Some("domain, email".to_string()),
+                ..query("93.184.216.34")
+            },
+        )
+        .unwrap();
+
+        // The focus is kept although it is an IP
+        assert_eq!(
+            node_ids(&subgraph),
+            vec![
+                "example.com",
+                "www.example.com",
+                "93.184.216.34",
+                "mail.example.net",
+                "ops@example.net",
+                "admin@example.com"
+            ]
+        );
+
+        let domains = extract(
+            graph(),
+            &SubgraphQuery {
+                max_hops: Some(5),
+                node_types: Some("domain".to_string()),
+                ..query("example.com")
+            },
+        )
+        .unwrap();
+        // mail.example.net is only reachable through the IP
+        assert_eq!(node_ids(&domains), vec!["example.com", "www.example.com"]);
+        assert_eq!(domains.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_weak_edges_are_not_followed() {
+        let subgraph = extract(
+            graph(),
+            &SubgraphQuery {
+                min_weight: Some(0.5),
+                ..query("example.com")
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            node_ids(&subgraph),
+            vec!["example.com", "www.example.com", "93.184.216.34"]
+        );
+        assert!(subgraph
+            .edges
+            .iter()
+            .all(|edge| edge.weight.unwrap() >= 0.5));
+    }
+
+    #[test]
+    fn test_unknown_focus_is_not_found() {
+        let err = extract(graph(), &query("missing.example")).unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+
+        let err = extract(
+            graph(),
+            &SubgraphQuery {
+                max_hops: Some(MAX_HOPS + 1),
+                ..query("example.com")
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Validation(_)));
+    }
+}
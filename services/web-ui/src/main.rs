@@ -1,7 +1,8 @@
 use actix_web::middleware::Logger;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use log::{error, info, warn};
+use actix_web::{delete, get, post, put, web, App, HttpResponse, HttpServer, Responder};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Mutex;
 use uuid::Uuid; // P1372
 
@@ -13,7 +14,7 @@ struct Scan {
     status: String,
 }
 
-type ScanDb = Mutex<Vec<Scan>>;
+type ScanDb = Mutex<HashMap<Uuid, Scan>>;
 
 #[post("/scans")]
 async fn create_scan(scan: web::Json<Scan>, db: web::Data<ScanDb>) -> impl Responder {
@@ -23,7 +24,7 @@ async fn create_scan(scan: web::Json<Scan>, db: web::Data<ScanDb>) -> impl Respo
         status: "created".to_string(),
         ..scan.into_inner()
     };
-    scans.push(new_scan.clone());
+    scans.insert(new_scan.id, new_scan.clone());
     info!("Created new scan: {:?}", new_scan); // P1372
     HttpResponse::Ok().json(new_scan)
 }
@@ -31,7 +32,7 @@ async fn create_scan(scan: web::Json<Scan>, db: web::Data<ScanDb>) -> impl Respo
 #[get("/scans/{id}")]
 async fn get_scan(id: web::Path<Uuid>, db: web::Data<ScanDb>) -> impl Responder {
     let scans = db.lock().unwrap();
-    if let Some(scan) = scans.iter().find(|&scan| scan.id == *id) {
+    if let Some(scan) = scans.get(&id) {
         info!("Fetched scan: {:?}", scan); // P1372
         HttpResponse::Ok().json(scan)
     } else {
@@ -47,8 +48,12 @@ async fn update_scan(
     db: web::Data<ScanDb>,
 ) -> impl Responder {
     let mut scans = db.lock().unwrap();
-    if let Some(existing_scan) = scans.iter_mut().find(|scan| scan.id == *id) {
-        *existing_scan = scan.into_inner();
+    if let Some(existing_scan) = scans.get_mut(&id) {
+        // The path decides which scan is updated, so the id cannot change
+        *existing_scan = Scan {
+            id: *id,
+            ..scan.into_inner()
+        };
         info!("Updated scan: {:?}", existing_scan); // P1372
         HttpResponse::Ok().json(existing_scan.clone())
     } else {
@@ -60,8 +65,7 @@ async fn update_scan(
 #[delete("/scans/{id}")]
 async fn delete_scan(id: web::Path<Uuid>, db: web::Data<ScanDb>) -> impl Responder {
     let mut scans = db.lock().unwrap();
-    if let Some(pos) = scans.iter().position(|scan| scan.id == *id) {
-        scans.remove(pos);
+    if scans.remove(&id).is_some() {
         info!("Deleted scan: {:?}", id); // P1372
         HttpResponse::NoContent().finish()
     } else {
@@ -70,11 +74,63 @@ async fn delete_scan(id: web::Path<Uuid>, db: web::Data<ScanDb>) -> impl Respond
     }
 }
 
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 500;
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    status: Option<String>,
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+}
+
+// Slices `items` according to `query`; limits above the cap are clamped
+fn paginate<T>(items: Vec<T>, query: &ListQuery) -> Result<Page<T>, &'static str> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if limit == 0 {
+        return Err("limit must be greater than zero");
+    }
+    let limit = limit.min(MAX_PAGE_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+
+    Ok(Page {
+        total: items.len(),
+        items: items.into_iter().skip(offset).take(limit).collect(),
+        limit,
+        offset,
+    })
+}
+
 #[get("/scans")]
-async fn list_scans(db: web::Data<ScanDb>) -> impl Responder {
+async fn list_scans(query: web::Query<ListQuery>, db: web::Data<ScanDb>) -> impl Responder {
     let scans = db.lock().unwrap();
     info!("Listing all scans"); // P1372
-    HttpResponse::Ok().json(scans.clone())
+    let mut scans: Vec<Scan> = scans
+        .values()
+        .filter(|scan| {
+            query
+                .status
+                .as_ref()
+                .is_none_or(|status| &scan.status == status)
+        })
+        .cloned()
+        .collect();
+    scans.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    match paginate(scans, &query) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(message) => HttpResponse::BadRequest().body(message),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,7 +142,7 @@ struct Event {
     timestamp: u64,
 }
 
-type EventDb = Mutex<Vec<Event>>;
+type EventDb = Mutex<HashMap<Uuid, Event>>;
 
 #[post("/events")]
 async fn create_event(event: web::Json<Event>, db: web::Data<EventDb>) -> impl Responder {
@@ -95,7 +151,7 @@ async fn create_event(event: web::Json<Event>, db: web::Data<EventDb>) -> impl R
         id: Uuid::new_v4(),
         ..event.into_inner()
     };
-    events.push(new_event.clone());
+    events.insert(new_event.id, new_event.clone());
     info!("Created new event: {:?}", new_event); // P1372
     HttpResponse::Ok().json(new_event)
 }
@@ -103,7 +159,7 @@ async fn create_event(event: web::Json<Event>, db: web::Data<EventDb>) -> impl R
 #[get("/events/{id}")]
 async fn get_event(id: web::Path<Uuid>, db: web::Data<EventDb>) -> impl Responder {
     let events = db.lock().unwrap();
-    if let Some(event) = events.iter().find(|&event| event.id == *id) {
+    if let Some(event) = events.get(&id) {
         info!("Fetched event: {:?}", event); // P1372
         HttpResponse::Ok().json(event)
     } else {
@@ -119,8 +175,11 @@ async fn update_event(
     db: web::Data<EventDb>,
 ) -> impl Responder {
     let mut events = db.lock().unwrap();
-    if let Some(existing_event) = events.iter_mut().find(|event| event.id == *id) {
-        *existing_event = event.into_inner();
+    if let Some(existing_event) = events.get_mut(&id) {
+        *existing_event = Event {
+            id: *id,
+            ..event.into_inner()
+        };
         info!("Updated event: {:?}", existing_event); // P1372
         HttpResponse::Ok().json(existing_event.clone())
     } else {
@@ -132,8 +191,7 @@ async fn update_event(
 #[delete("/events/{id}")]
 async fn delete_event(id: web::Path<Uuid>, db: web::Data<EventDb>) -> impl Responder {
     let mut events = db.lock().unwrap();
-    if let Some(pos) = events.iter().position(|event| event.id == *id) {
-        events.remove(pos);
+    if events.remove(&id).is_some() {
         info!("Deleted event: {:?}", id); // P1372
         HttpResponse::NoContent().finish()
     } else {
@@ -143,18 +201,33 @@ async fn delete_event(id: web::Path<Uuid>, db: web::Data<EventDb>) -> impl Respo
 }
 
 #[get("/events")]
-async fn list_events(db: web::Data<EventDb>) -> impl Responder {
+async fn list_events(query: web::Query<ListQuery>, db: web::Data<EventDb>) -> impl Responder {
     let events = db.lock().unwrap();
     info!("Listing all events"); // P1372
-    HttpResponse::Ok().json(events.clone())
+    let mut events: Vec<Event> = events
+        .values()
+        .filter(|event| {
+            query
+                .event_type
+                .as_ref()
+                .is_none_or(|event_type| &event.event_type == event_type)
+        })
+        .cloned()
+        .collect();
+    events.sort_by_key(|event| event.timestamp);
+
+    match paginate(events, &query) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(message) => HttpResponse::BadRequest().body(message),
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    let scan_db = web::Data::new(Mutex::new(Vec::<Scan>::new()));
-    let event_db = web::Data::new(Mutex::new(Vec::<Event>::new()));
+    let scan_db = web::Data::new(Mutex::new(HashMap::<Uuid, Scan>::new()));
+    let event_db = web::Data::new(Mutex::new(HashMap::<Uuid, Event>::new()));
 
     HttpServer::new(move || {
         App::new()
@@ -176,3 +249,154 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_many_scans_are_indexed_by_id() {
+        let scan_db = web::Data::new(Mutex::new(HashMap::<Uuid, Scan>::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(scan_db.clone())
+                .service(create_scan)
+                .service(get_scan)
+                .service(delete_scan)
+                .service(list_scans),
+        )
+        .await;
+
+        let mut created = Vec::new();
+        for n in 0..1000 {
+            let req = test::TestRequest::post()
+                .uri("/scans")
+                .set_json(Scan {
+                    id: Uuid::nil(),
+                    name: format!("scan-{:04}", n),
+                    target: "example.com".to_string(),
+                    status: String::new(),
+                })
+                .to_request();
+            let scan: Scan = test::call_and_read_body_json(&app, req).await;
+            created.push(scan);
+        }
+        assert_eq!(scan_db.lock().unwrap().len(), 1000);
+
+        // Lookups go straight to the entry for the id
+        for scan in [&created[0], &created[500], &created[999]] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/scans/{}", scan.id))
+                .to_request();
+            let fetched: Scan = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(fetched.id, scan.id);
+            assert_eq!(fetched.name, scan.name);
+            assert_eq!(fetched.status, "created");
+        }
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/scans/{}", created[500].id))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get()
+            .uri("/scans?limit=500&offset=499")
+            .to_request();
+        let listed: Page<Scan> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed.total, 999);
+        assert_eq!(listed.items.len(), 500);
+        assert_eq!(listed.items[0].name, "scan-0499");
+        assert_eq!(listed.items[499].name, "scan-0999");
+        assert!(listed.items.iter().all(|scan| scan.id != created[500].id));
+    }
+
+    fn event(event_type: &str, timestamp: u64) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            scan_id: Uuid::nil(),
+            event_type: event_type.to_string(),
+            data: String::new(),
+            timestamp,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_list_events_pagination_and_filters() {
+        let events: HashMap<Uuid, Event> = (0..10)
+            .map(|n| event(if n % 2 == 0 { "started" } else { "finished" }, n))
+            .map(|event| (event.id, event))
+            .collect();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(events)))
+                .service(list_events),
+        )
+        .await;
+
+        // Filtering applies before paging, and totals count matches only
+        let req = test::TestRequest::get()
+            .uri("/events?type=finished&limit=2&offset=1")
+            .to_request();
+        let page: Page<Event> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.total, 5);
+        let timestamps: Vec<u64> = page.items.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 5]);
+
+        // Offset past the end is an empty page, not an error
+        let req = test::TestRequest::get()
+            .uri("/events?offset=50")
+            .to_request();
+        let page: Page<Event> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.total, 10);
+        assert!(page.items.is_empty());
+
+        // Oversized limits are capped
+        let req = test::TestRequest::get()
+            .uri("/events?limit=100000")
+            .to_request();
+        let page: Page<Event> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.limit, MAX_PAGE_LIMIT);
+        assert_eq!(page.items.len(), 10);
+
+        for uri in ["/events?limit=0", "/events?limit=-1", "/events?offset=abc"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.status(),
+                actix_web::http::StatusCode::BAD_REQUEST,
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_list_scans_filters_by_status() {
+        let scans: HashMap<Uuid, Scan> = ["created", "running", "created"]
+            .iter()
+            .enumerate()
+            .map(|(n, status)| Scan {
+                id: Uuid::new_v4(),
+                name: format!("scan-{}", n),
+                target: "example.com".to_string(),
+                status: status.to_string(),
+            })
+            .map(|scan| (scan.id, scan))
+            .collect();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(scans)))
+                .service(list_scans),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/scans?status=created")
+            .to_request();
+        let page: Page<Scan> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.total, 2);
+        assert!(page.items.iter().all(|scan| scan.status == "created"));
+        assert_eq!(page.limit, DEFAULT_PAGE_LIMIT);
+    }
+}